@@ -25,6 +25,96 @@ impl Default for LogRecord {
     }
 }
 
+/// Longest process name kept by `RegisterLevels`
+pub const LEVELS_MAX_NAME_LEN: usize = 64;
+/// Longest module target in a `ModuleLevels`
+pub const LEVELS_MAX_MODULE_LEN: usize = 120;
+/// Most modules a process can register
+pub const LEVELS_MAX_MODULES: usize = 31;
+/// The `count` of a `SetLevel` that wasn't allowed to change the levels of another process
+pub const LEVELS_DENIED: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct ModuleLevel {
+    /// A `log::LevelFilter`, 0 = off through 5 = trace
+    pub level: u32,
+    pub module_length: u32,
+    pub module: [u8; LEVELS_MAX_MODULE_LEN],
+}
+
+/// The levels of the modules of one process, as exchanged by the level control opcodes
+#[repr(C, align(4096))]
+pub struct ModuleLevels {
+    pub pid: u32,
+    pub count: u32,
+    /// The token from `ClaimLevelControl`, which `SetLevel` needs to change the levels of another process
+    pub token: [u32; 4],
+    pub name_length: u32,
+    pub name: [u8; LEVELS_MAX_NAME_LEN],
+    pub modules: [ModuleLevel; LEVELS_MAX_MODULES],
+}
+
+impl Default for ModuleLevels {
+    fn default() -> Self {
+        ModuleLevels {
+            pid: 0,
+            count: 0,
+            token: [0u32; 4],
+            name_length: 0,
+            name: [0u8; LEVELS_MAX_NAME_LEN],
+            modules: [ModuleLevel { level: 0, module_length: 0, module: [0u8; LEVELS_MAX_MODULE_LEN] };
+                LEVELS_MAX_MODULES],
+        }
+    }
+}
+
+impl ModuleLevels {
+    /// The process name, cut off at `LEVELS_MAX_NAME_LEN` bytes
+    pub fn set_name(&mut self, name: &str) {
+        let name = truncate(name, LEVELS_MAX_NAME_LEN);
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.name_length = name.len() as u32;
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..(self.name_length as usize).min(LEVELS_MAX_NAME_LEN)])
+            .unwrap_or_default()
+    }
+
+    /// Adds a module, returning `false` if there is no room left. Names are cut off at
+    /// `LEVELS_MAX_MODULE_LEN` bytes.
+    pub fn push(&mut self, module: &str, level: u32) -> bool {
+        let entry = match self.modules.get_mut(self.count as usize) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let module = truncate(module, LEVELS_MAX_MODULE_LEN);
+        entry.module[..module.len()].copy_from_slice(module.as_bytes());
+        entry.module_length = module.len() as u32;
+        entry.level = level;
+        self.count += 1;
+        true
+    }
+
+    /// The registered modules and their levels
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.modules[..(self.count as usize).min(LEVELS_MAX_MODULES)].iter().map(|m| {
+            let len = (m.module_length as usize).min(LEVELS_MAX_MODULE_LEN);
+            (core::str::from_utf8(&m.module[..len]).unwrap_or_default(), m.level)
+        })
+    }
+}
+
+/// Cuts `s` off at `max` bytes without splitting a character
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
 #[derive(Debug, PartialEq, num_derive::FromPrimitive, num_derive::ToPrimitive)]
 pub enum Opcode {
     /// A `LogRecord` message, delivering structured log output
@@ -44,6 +134,41 @@ pub enum Opcode {
     TryHookUsbMirror = 4,
     UnhookUsbMirror = 5,
 
+    /// Sets which records the log store keeps. `arg1` is a PID, or 0 for the default that applies to
+    /// processes without a filter of their own; `arg2` is a `log::LevelFilter` (0 = off through 5 =
+    /// trace), or `STORE_FILTER_NONE` to remove the PID's own filter. Panics are always kept.
+    StoreSetFilter = 6,
+    /// Sets how many records the log store keeps to `arg1`, which is clamped to
+    /// `1..=STORE_MAX_CAPACITY`. Returns the capacity in effect.
+    StoreSetCapacity = 7,
+    /// Reads the log store into a mutably lent page. On the way in, `offset` is one more than the first
+    /// sequence number wanted (an `offset` can't be 0) and `valid` the number of new records to wait for,
+    /// or `None` to return right away. On the way back, `offset` is one more than the sequence number to
+    /// continue from and `valid` the length of the packed records; see `STORE_RECORD_HEADER_LEN` for the
+    /// layout.
+    StoreRead = 8,
+    /// Discards everything in the log store
+    StoreClear = 9,
+
+    /// Registers the module targets of the sending process for runtime level control, from a lent
+    /// `ModuleLevels` holding the process name and each module with its current level.
+    RegisterLevels = 10,
+    /// A mutably lent `ModuleLevels` from a registered process. It is held until the levels of the process
+    /// are changed, and then comes back with all of them.
+    ListenLevels = 11,
+    /// Changes levels, from a mutably lent `ModuleLevels` with the target `pid` (0 for the sender) and one
+    /// module. An empty module name sets every registered module of the process; a name that isn't
+    /// registered is added. `count` comes back as the number of modules changed, or `LEVELS_DENIED` if
+    /// the target is another process and `token` isn't the level control token.
+    SetLevel = 12,
+    /// Reads the levels of one registered process into a mutably lent `ModuleLevels`. `pid` goes in as
+    /// an index into the registered processes, and comes back as the PID found there, or 0 past the end.
+    GetLevels = 13,
+    /// Hands out the level control token, which lets `SetLevel` change the levels of other processes. It
+    /// is given out once per boot, to the shell; a blocking scalar that returns `Scalar5(1, token)`, or
+    /// `Scalar5(0, ..)` once the token has been claimed.
+    ClaimLevelControl = 14,
+
     /// A panic occurred, and a panic log is forthcoming
     PanicStarted = 1000,
 
@@ -88,3 +213,33 @@ pub enum Opcode {
     /// Enable receiving messages when the system is resumed from sleep.
     EnableRx = 2000,
 }
+
+/// `arg2` of `StoreSetFilter` that removes a process' own filter
+pub const STORE_FILTER_NONE: usize = usize::MAX;
+/// The level a panic is stored with; the other levels are `log::Level as u8`
+pub const STORE_LEVEL_PANIC: u8 = 0;
+/// Records kept by the log store until `StoreSetCapacity` says otherwise
+pub const STORE_DEFAULT_CAPACITY: usize = 256;
+pub const STORE_MAX_CAPACITY: usize = 4096;
+/// Stored text beyond this many bytes is cut off
+pub const STORE_MAX_TEXT_LEN: usize = 512;
+/// Records in a `StoreRead` page are packed back to back, each as a little-endian `u32` sequence
+/// number, the `u8` PID of the sender, the `u8` level, a little-endian `u16` text length, and then
+/// that many bytes of UTF-8 text.
+pub const STORE_RECORD_HEADER_LEN: usize = 8;
+
+/// Packs one record at the start of `buf` in the `StoreRead` layout. Returns the length used, or `None`
+/// if it doesn't fit.
+pub fn pack_stored_record(buf: &mut [u8], seq: u32, pid: u8, level: u8, text: &[u8]) -> Option<usize> {
+    let text = &text[..text.len().min(STORE_MAX_TEXT_LEN)];
+    let len = STORE_RECORD_HEADER_LEN + text.len();
+    if buf.len() < len {
+        return None;
+    }
+    buf[0..4].copy_from_slice(&seq.to_le_bytes());
+    buf[4] = pid;
+    buf[5] = level;
+    buf[6..8].copy_from_slice(&(text.len() as u16).to_le_bytes());
+    buf[STORE_RECORD_HEADER_LEN..len].copy_from_slice(text);
+    Some(len)
+}
@@ -0,0 +1,180 @@
+//! Runtime level control. A process registers the module targets it wants to be adjustable; from then on,
+//! the levels of those modules can be changed from anywhere through the log server, without a rebuild.
+//! Records are filtered here, before they are sent, by the registered module that is the longest prefix
+//! of their target; everything else stays at the level the process had when it registered.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use num_traits::ToPrimitive;
+
+use crate::api::*;
+use crate::store::conn;
+
+static REGISTERED: AtomicBool = AtomicBool::new(false);
+/// The level of targets that don't fall under a registered module
+static DEFAULT_LEVEL: AtomicUsize = AtomicUsize::new(log::LevelFilter::Info as usize);
+static MODULES: Mutex<Vec<(String, log::LevelFilter)>> = Mutex::new(Vec::new());
+/// The level control token, if this process claimed it
+static CONTROL_TOKEN: Mutex<Option<[u32; 4]>> = Mutex::new(None);
+
+/// The levels of the registered modules of one process
+#[derive(Debug, Clone)]
+pub struct ProcessLevels {
+    pub pid: u8,
+    pub name: String,
+    pub modules: Vec<(String, log::LevelFilter)>,
+}
+
+fn level_filter(level: u32) -> log::LevelFilter {
+    match level {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Error,
+        2 => log::LevelFilter::Warn,
+        3 => log::LevelFilter::Info,
+        4 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+fn lend_mut(cid: xous::CID, opcode: Opcode, levels: &mut ModuleLevels) -> Result<(), xous::Error> {
+    let buf = unsafe {
+        // safety: `ModuleLevels` is page-aligned and exactly one page long
+        xous::MemoryRange::new(levels as *mut ModuleLevels as usize, core::mem::size_of::<ModuleLevels>())
+            .unwrap()
+    };
+    xous::send_message(cid, xous::Message::new_lend_mut(opcode.to_usize().unwrap(), buf, None, None))
+        .map(|_| ())
+}
+
+/// Whether a record for `target` at `level` gets sent to the log server
+pub(crate) fn enabled(target: &str, level: log::Level) -> bool {
+    if !REGISTERED.load(Ordering::Relaxed) {
+        return true;
+    }
+    let modules = MODULES.lock().unwrap();
+    let filter = modules
+        .iter()
+        .filter(|(module, _)| {
+            target == module
+                || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::"))
+        })
+        .max_by_key(|(module, _)| module.len())
+        .map(|(_, filter)| *filter)
+        .unwrap_or_else(|| level_filter(DEFAULT_LEVEL.load(Ordering::Relaxed) as u32));
+    level <= filter
+}
+
+/// Replaces the local levels and lets through whatever the most verbose of them needs
+fn apply(levels: &ModuleLevels) {
+    let mut modules = MODULES.lock().unwrap();
+    *modules = levels.iter().map(|(module, level)| (module.to_string(), level_filter(level))).collect();
+    let max = modules
+        .iter()
+        .map(|(_, filter)| *filter)
+        .fold(level_filter(DEFAULT_LEVEL.load(Ordering::Relaxed) as u32), core::cmp::max);
+    log::set_max_level(max);
+}
+
+/// Registers `modules` (module paths such as `"gam"`, which also covers `"gam::layouts"`) with the log
+/// server, all at the current `log::max_level()`, and starts a thread that picks up level changes for
+/// this process. This can only be done once per process.
+pub fn register_levels(name: &str, modules: &[&str]) -> Result<(), xous::Error> {
+    if REGISTERED.swap(true, Ordering::Relaxed) {
+        return Err(xous::Error::ServerExists);
+    }
+    let current = log::max_level();
+    DEFAULT_LEVEL.store(current as usize, Ordering::Relaxed);
+    let mut levels = ModuleLevels::default();
+    levels.set_name(name);
+    for module in modules {
+        if !levels.push(module, current as u32) {
+            log::warn!("only the first {} modules can be registered for level control", LEVELS_MAX_MODULES);
+            break;
+        }
+    }
+    apply(&levels);
+
+    let cid = conn();
+    let buf = unsafe {
+        // safety: `ModuleLevels` is page-aligned and exactly one page long
+        xous::MemoryRange::new(&levels as *const ModuleLevels as usize, core::mem::size_of::<ModuleLevels>())
+            .unwrap()
+    };
+    xous::send_message(
+        cid,
+        xous::Message::new_lend(Opcode::RegisterLevels.to_usize().unwrap(), buf, None, None),
+    )?;
+    std::thread::spawn(move || {
+        loop {
+            let mut levels = ModuleLevels::default();
+            if let Err(e) = lend_mut(cid, Opcode::ListenLevels, &mut levels) {
+                log::error!("stopped listening for level changes: {:?}", e);
+                return;
+            }
+            // the log server doesn't know this process, which can only mean it restarted
+            if levels.pid == 0 {
+                return;
+            }
+            apply(&levels);
+        }
+    });
+    Ok(())
+}
+
+/// Claims the level control token, which lets `set_level()` change the levels of other processes. Only
+/// one process gets it per boot, and that should be the shell; returns `false` if it was already taken.
+pub fn claim_level_control() -> Result<bool, xous::Error> {
+    match xous::send_message(
+        conn(),
+        xous::Message::new_blocking_scalar(Opcode::ClaimLevelControl.to_usize().unwrap(), 0, 0, 0, 0),
+    )? {
+        xous::Result::Scalar5(1, t0, t1, t2, t3) => {
+            *CONTROL_TOKEN.lock().unwrap() = Some([t0 as u32, t1 as u32, t2 as u32, t3 as u32]);
+            Ok(true)
+        }
+        xous::Result::Scalar5(..) => Ok(false),
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Sets the level of `module` in process `pid`, or of all its registered modules if `module` is `None`.
+/// A `pid` of `None` means this process; any other process needs the token from
+/// `claim_level_control()`, or this fails with `AccessDenied`. Returns how many modules were changed,
+/// which is 0 if the process hasn't registered.
+pub fn set_level(
+    pid: Option<u8>,
+    module: Option<&str>,
+    level: log::LevelFilter,
+) -> Result<usize, xous::Error> {
+    let mut levels = ModuleLevels {
+        pid: pid.unwrap_or(0) as u32,
+        token: CONTROL_TOKEN.lock().unwrap().unwrap_or_default(),
+        ..Default::default()
+    };
+    levels.push(module.unwrap_or(""), level as u32);
+    lend_mut(conn(), Opcode::SetLevel, &mut levels)?;
+    match levels.count {
+        LEVELS_DENIED => Err(xous::Error::AccessDenied),
+        count => Ok(count as usize),
+    }
+}
+
+/// The levels of every process that registered, in the order they registered.
+pub fn registered_levels() -> Result<Vec<ProcessLevels>, xous::Error> {
+    let cid = conn();
+    let mut processes = Vec::new();
+    for index in 0.. {
+        let mut levels = ModuleLevels { pid: index, ..Default::default() };
+        lend_mut(cid, Opcode::GetLevels, &mut levels)?;
+        if levels.pid == 0 {
+            break;
+        }
+        processes.push(ProcessLevels {
+            pid: levels.pid as u8,
+            name: levels.name().to_string(),
+            modules: levels.iter().map(|(module, level)| (module.to_string(), level_filter(level))).collect(),
+        });
+    }
+    Ok(processes)
+}
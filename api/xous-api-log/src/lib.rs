@@ -6,6 +6,14 @@ use num_traits::ToPrimitive;
 
 pub mod api;
 mod cursor;
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+mod levels;
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+pub use levels::{ProcessLevels, claim_level_control, register_levels, registered_levels, set_level};
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+mod store;
+#[cfg(not(any(target_os = "none", feature = "nostd")))]
+pub use store::*;
 
 #[derive(Debug)]
 pub enum LogError {
@@ -70,9 +78,22 @@ impl XousLogger {
 }
 
 impl log::Log for XousLogger {
+    #[cfg(not(any(target_os = "none", feature = "nostd")))]
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        levels::enabled(metadata.target(), metadata.level())
+    }
+
+    #[cfg(any(target_os = "none", feature = "nostd"))]
     fn enabled(&self, _metadata: &log::Metadata) -> bool { true }
 
-    fn log(&self, record: &log::Record) { XOUS_LOGGER.log_impl(record); }
+    fn log(&self, record: &log::Record) {
+        // the log macros only check the global maximum, which is the most verbose registered module
+        #[cfg(not(any(target_os = "none", feature = "nostd")))]
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        XOUS_LOGGER.log_impl(record);
+    }
 
     fn flush(&self) {}
 }
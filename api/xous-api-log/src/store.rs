@@ -0,0 +1,172 @@
+//! Client side of the log store, which keeps recent records in the log server so they can be read back
+//! on the device, and saved across a crash by whoever drains it.
+
+use core::sync::atomic::Ordering;
+
+use num_traits::ToPrimitive;
+
+use crate::XOUS_LOGGER_CONNECTION;
+use crate::api::*;
+
+/// One record out of the log store
+#[derive(Debug, Clone)]
+pub struct StoredRecord {
+    /// Counts up from 0 at every boot of the log server
+    pub seq: u32,
+    pub pid: u8,
+    /// `None` for a panic
+    pub level: Option<log::Level>,
+    pub text: String,
+}
+
+impl core::fmt::Display for StoredRecord {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let level = match self.level {
+            None => "PANIC",
+            Some(log::Level::Error) => "ERR",
+            Some(log::Level::Warn) => "WARN",
+            Some(log::Level::Info) => "INFO",
+            Some(log::Level::Debug) => "DBG",
+            Some(log::Level::Trace) => "TRCE",
+        };
+        write!(f, "{} PID{} {}: {}", self.seq, self.pid, level, self.text)
+    }
+}
+
+/// A page of records packed by the log server, along with where the next read should pick up
+pub struct StorePage {
+    pub data: Vec<u8>,
+    pub next_seq: u32,
+}
+
+#[repr(C, align(4096))]
+struct RawPage([u8; 4096]);
+
+pub(crate) fn conn() -> xous::CID {
+    match XOUS_LOGGER_CONNECTION.load(Ordering::Relaxed) {
+        0 => xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap()).unwrap(),
+        cid => cid,
+    }
+}
+
+/// Keep records from `pid` (or from every process without a filter of its own, if `None`) at `level`
+/// and above. Panics are kept regardless.
+pub fn store_set_filter(pid: Option<u8>, level: log::LevelFilter) {
+    xous::send_message(
+        conn(),
+        xous::Message::new_blocking_scalar(
+            Opcode::StoreSetFilter.to_usize().unwrap(),
+            pid.unwrap_or(0) as usize,
+            level as usize,
+            0,
+            0,
+        ),
+    )
+    .ok();
+}
+
+/// Make `pid` follow the default filter again.
+pub fn store_clear_filter(pid: u8) {
+    xous::send_message(
+        conn(),
+        xous::Message::new_blocking_scalar(
+            Opcode::StoreSetFilter.to_usize().unwrap(),
+            pid as usize,
+            STORE_FILTER_NONE,
+            0,
+            0,
+        ),
+    )
+    .ok();
+}
+
+/// Sets how many records are kept, returning the capacity that took effect.
+pub fn store_set_capacity(records: usize) -> usize {
+    match xous::send_message(
+        conn(),
+        xous::Message::new_blocking_scalar(Opcode::StoreSetCapacity.to_usize().unwrap(), records, 0, 0, 0),
+    ) {
+        Ok(xous::Result::Scalar1(capacity)) => capacity,
+        _ => 0,
+    }
+}
+
+pub fn store_clear() {
+    xous::send_message(
+        conn(),
+        xous::Message::new_blocking_scalar(Opcode::StoreClear.to_usize().unwrap(), 0, 0, 0, 0),
+    )
+    .ok();
+}
+
+/// Reads packed records starting at sequence number `from`. With `wait_for` set, this blocks until that
+/// many records past `from` are in the store, or a panic is.
+pub fn store_read_raw(from: u32, wait_for: Option<usize>) -> Result<StorePage, xous::Error> {
+    let mut page = RawPage([0u8; 4096]);
+    let msg = xous::MemoryMessage {
+        id: Opcode::StoreRead.to_usize().unwrap(),
+        buf: unsafe {
+            // safety: `RawPage` is page-aligned and exactly one page long
+            xous::MemoryRange::new(&mut page as *mut RawPage as usize, core::mem::size_of::<RawPage>())
+                .unwrap()
+        },
+        offset: xous::MemoryAddress::new(from as usize + 1),
+        valid: wait_for.and_then(xous::MemorySize::new),
+    };
+    match xous::send_message(conn(), xous::Message::MutableBorrow(msg))? {
+        xous::Result::MemoryReturned(offset, valid) => {
+            let len = valid.map(|v| v.get()).unwrap_or(0).min(page.0.len());
+            let next_seq = offset.map(|o| o.get() - 1).unwrap_or(from as usize) as u32;
+            Ok(StorePage { data: page.0[..len].to_vec(), next_seq })
+        }
+        _ => Err(xous::Error::InternalError),
+    }
+}
+
+/// Reads the records starting at sequence number `from` that fit in a page; see `store_read_raw`.
+pub fn store_read(from: u32, wait_for: Option<usize>) -> Result<(Vec<StoredRecord>, u32), xous::Error> {
+    let page = store_read_raw(from, wait_for)?;
+    Ok((unpack_stored_records(&page.data), page.next_seq))
+}
+
+/// Every record in the store, oldest first.
+pub fn store_read_all() -> Result<Vec<StoredRecord>, xous::Error> {
+    let mut records = Vec::new();
+    let mut from = 0;
+    loop {
+        let (mut page, next) = store_read(from, None)?;
+        if page.is_empty() {
+            return Ok(records);
+        }
+        records.append(&mut page);
+        from = next;
+    }
+}
+
+/// Unpacks records in the `StoreRead` layout, stopping at the first one that is cut short.
+pub fn unpack_stored_records(mut data: &[u8]) -> Vec<StoredRecord> {
+    let mut records = Vec::new();
+    while data.len() >= STORE_RECORD_HEADER_LEN {
+        let text_len = u16::from_le_bytes([data[6], data[7]]) as usize;
+        if data.len() < STORE_RECORD_HEADER_LEN + text_len {
+            break;
+        }
+        let level = match data[5] {
+            1 => Some(log::Level::Error),
+            2 => Some(log::Level::Warn),
+            3 => Some(log::Level::Info),
+            4 => Some(log::Level::Debug),
+            5 => Some(log::Level::Trace),
+            _ => None,
+        };
+        records.push(StoredRecord {
+            seq: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            pid: data[4],
+            level,
+            text: String::from_utf8_lossy(&data[STORE_RECORD_HEADER_LEN..STORE_RECORD_HEADER_LEN + text_len])
+                .into_owned(),
+        });
+        data = &data[STORE_RECORD_HEADER_LEN + text_len..];
+    }
+    records
+}
@@ -81,6 +81,24 @@ pub enum Opcode {
     /// }
     /// ```
     TryConnect = 7,
+
+    /// Associate the PID of an app that was loaded at runtime with the app's name, so that the
+    /// capabilities declared for it in the app manifest can be enforced. Only accepted from the
+    /// app loader.
+    ///
+    /// # Message Types
+    ///
+    ///     * MutableLend
+    ///
+    /// # Arguments
+    ///
+    /// The memory being pointed to should be the app's name as a &str, and the length of the
+    /// string should be specified in the `valid` field. The PID of the app is passed in `offset`.
+    ///
+    /// # Return Values
+    ///
+    /// The first u32 of the memory is overwritten with 0 on success, or nonzero on failure.
+    RegisterAppProcess = 8,
 }
 
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
@@ -184,6 +184,34 @@ impl XousNames {
         }
     }
 
+    /// Tells xous-names that `pid` is running the app `name`, so that connections from it are limited
+    /// to the capabilities declared for that app. This is only honored when called by the app loader.
+    pub fn register_app_process(&self, pid: xous::PID, name: &str) -> Result<(), xous::Error> {
+        let mut cr: ConnectRequest = Default::default();
+        let name_bytes = name.as_bytes();
+        cr.len = cr.name.len().min(name_bytes.len()) as u32;
+        for (&src_byte, dest_byte) in name_bytes.iter().zip(&mut cr.name) {
+            *dest_byte = src_byte;
+        }
+        let msg = xous::MemoryMessage {
+            id: api::Opcode::RegisterAppProcess.to_usize().unwrap(),
+            buf: unsafe {
+                // safety: `cr` is #[repr(C, align(4096))], and should be exactly on page in size
+                xous::MemoryRange::new(
+                    &mut cr as *mut _ as *mut u8 as usize,
+                    core::mem::size_of::<ConnectRequest>(),
+                )?
+            },
+            offset: xous::MemorySize::new(pid.get() as usize),
+            valid: xous::MemorySize::new(cr.len as usize),
+        };
+        xous::send_message(self.conn, xous::Message::MutableBorrow(msg))?;
+
+        let response_ptr = &cr as *const ConnectRequest as *const u32;
+        // safety: because that's how it was packed on the server, a naked u32
+        if unsafe { response_ptr.read() } == 0 { Ok(()) } else { Err(xous::Error::AccessDenied) }
+    }
+
     /// Returns `true` if every server that specified a `max_conn` count has filled
     /// every slot available. Once all the limited slots are filled, the system has
     /// finished TOFU initialization and can begin regular operations.
@@ -71,10 +71,52 @@ pub enum Opcode {
     /// *arg1*: The integer that matches the Condition value
     FreeCondition = 11,
 
+    /// Get the elapsed time in microseconds. This is the `ElapsedMs` clock, so it also stops during
+    /// suspend, but the resolution depends on the hardware: timers that only count milliseconds give
+    /// whole milliseconds.
+    ElapsedUs = 12,
+
+    /// Set an alarm, from a lent `AlarmRequest`. The `id` of the request comes back as the handle of
+    /// the alarm, or 0 if the alarm couldn't be set.
+    SetAlarm = 13,
+
+    /// Cancel an alarm. Only the process that set an alarm can cancel it.
+    ///
+    /// # Arguments
+    ///
+    /// *arg1*: The ID of the alarm
+    ///
+    /// # Returns
+    ///
+    /// 1 if the alarm was still set, 0 otherwise
+    CancelAlarm = 14,
+
+    /// Used by the thread in the ticktimer that fires alarms, to wait for the next one. Only accepted from
+    /// the ticktimer itself.
+    AlarmWait = 15,
+
     /// Invalid call -- an error occurred decoding the opcode
     InvalidCall = u32::MAX as usize,
 }
 
+/// An alarm to set. When it goes off, the ticktimer sends a non-blocking scalar message with ID `opcode`
+/// to the server `sid`, with the alarm ID in *arg1*, `arg` in *arg2*, and in *arg3* the number of
+/// periods that passed since the last delivery (more than 1 if the server fell behind).
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Copy, Clone)]
+pub struct AlarmRequest {
+    pub sid: (u32, u32, u32, u32),
+    pub opcode: u32,
+    pub arg: u32,
+    /// Milliseconds from now on the `ElapsedMs` clock, or milliseconds since the UNIX epoch if
+    /// `wall_clock` is set
+    pub deadline_ms: u64,
+    pub wall_clock: bool,
+    /// Repeat every this many milliseconds, or 0 to go off once
+    pub period_ms: u64,
+    /// Filled in by the ticktimer
+    pub id: u32,
+}
+
 #[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct VersionString {
     pub version: String,
@@ -6,6 +6,25 @@ use num_traits::ToPrimitive;
 use xous::{CID, Error, send_message};
 use xous_semver::SemVer;
 
+/// When an alarm goes off
+#[derive(Debug, Copy, Clone)]
+pub enum AlarmTime {
+    /// This many milliseconds from now, on the `elapsed_ms()` clock. Like that clock, these alarms don't
+    /// advance during suspend.
+    AfterMs(u64),
+    /// At this wall-clock time, in milliseconds since the UNIX epoch. These go off right after a resume
+    /// if they came due during suspend. They only go off once the wall-clock time has been set.
+    AtUtcMs(u64),
+}
+
+/// The handle of an alarm, which can be passed to `Ticktimer::cancel_alarm()`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Alarm(u32);
+impl Alarm {
+    /// The ID the alarm messages carry in *arg1*
+    pub fn id(&self) -> u32 { self.0 }
+}
+
 #[derive(Debug)]
 pub struct Ticktimer {
     conn: CID,
@@ -42,6 +61,75 @@ impl Ticktimer {
         }
     }
 
+    /// Return the number of microseconds that have elapsed since boot. This is the same clock as
+    /// `elapsed_ms()`, but with whatever resolution the hardware timer has; hardware that only counts
+    /// milliseconds returns multiples of 1000.
+    pub fn elapsed_us(&self) -> u64 {
+        let response = send_message(
+            self.conn,
+            xous::Message::new_blocking_scalar(api::Opcode::ElapsedUs.to_usize().unwrap(), 0, 0, 0, 0),
+        )
+        .expect("Ticktimer: failure to send message to Ticktimer");
+        if let xous::Result::Scalar2(lower, upper) = response {
+            lower as u64 | ((upper as u64) << 32)
+        } else {
+            panic!("Ticktimer elapsed_us(): unexpected return value.");
+        }
+    }
+
+    /// Set an alarm that sends a scalar message with ID `opcode` to the server `sid` at `when`, and then
+    /// every `period_ms` milliseconds if that is given. The message carries the alarm ID in *arg1*,
+    /// `arg` in *arg2*, and the number of periods since the last delivery in *arg3*, which is more
+    /// than 1 if the server's queue was full or the server fell behind.
+    ///
+    /// Alarm messages are sent without blocking, so a server whose queue is full misses them. An alarm
+    /// whose server is gone is cancelled.
+    pub fn set_alarm(
+        &self,
+        sid: xous::SID,
+        opcode: u32,
+        arg: u32,
+        when: AlarmTime,
+        period_ms: Option<u64>,
+    ) -> Result<Alarm, Error> {
+        let (deadline_ms, wall_clock) = match when {
+            AlarmTime::AfterMs(ms) => (ms, false),
+            AlarmTime::AtUtcMs(ms) => (ms, true),
+        };
+        let request = api::AlarmRequest {
+            sid: sid.to_u32(),
+            opcode,
+            arg,
+            deadline_ms,
+            wall_clock,
+            period_ms: period_ms.unwrap_or(0),
+            id: 0,
+        };
+        let mut buf = xous_ipc::Buffer::into_buf(request).or(Err(Error::InternalError))?;
+        buf.lend_mut(self.conn, api::Opcode::SetAlarm.to_u32().unwrap())?;
+        match buf.to_original::<api::AlarmRequest, _>().or(Err(Error::InternalError))?.id {
+            0 => Err(Error::ServerNotFound),
+            id => Ok(Alarm(id)),
+        }
+    }
+
+    /// Cancel an alarm. Returns `false` if it was a one-time alarm that already went off, or an alarm
+    /// that was cancelled before.
+    pub fn cancel_alarm(&self, alarm: Alarm) -> bool {
+        send_message(
+            self.conn,
+            xous::Message::new_blocking_scalar(
+                api::Opcode::CancelAlarm.to_usize().unwrap(),
+                alarm.0 as usize,
+                0,
+                0,
+                0,
+            ),
+        )
+        .map(|r| r == xous::Result::Scalar1(1))
+        .expect("couldn't cancel alarm")
+    }
+
     /// Sleep for at least `ms` milliseconds. Blocks until the requested time has passed.
     ///
     /// # Arguments:
@@ -11,6 +11,7 @@ use num_traits::ToPrimitive;
 use crate::SERVER_NAME_APP_LOADER;
 
 pub(crate) struct AppLoader {
+    xns: xous_names::XousNames,
     gam: Gam,
     modals: Modals,
     auth: [u32; 4],
@@ -80,6 +81,7 @@ impl AppLoader {
                 .expect("Couldn't create menu");
 
         AppLoader {
+            xns: xous_names::XousNames::new().unwrap(),
             gam,
             modals,
             auth,
@@ -165,6 +167,17 @@ impl AppLoader {
             xous::send_message(spawn.cid, xous::Message::new_blocking_scalar(2, 1, 2, 3, 4)).unwrap();
         assert_eq!(xous::Result::Scalar1(2), result);
 
+        // bind the process to the app's capabilities before the app gets a chance to run. If that fails,
+        // the app is never loaded into the process.
+        if let Err(e) = self.xns.register_app_process(spawn.pid, &name) {
+            log::error!("xous-names refused to register `{}': {:?}; not loading it", name, e);
+            self.modals.finish_progress().expect("Couldn't close progressbar");
+            self.modals
+                .show_notification(t!("apploader.addapp.error", locales::LANG), None)
+                .expect("Couldn't show modal");
+            return;
+        }
+
         self.modals.update_progress(2).expect("Couldn't update progress");
 
         // load the app from the binary file
@@ -78,6 +78,9 @@ pub(crate) fn icontray_server(conn_to_main: xous::CID) {
             Some(Opcode::Unpick) => {
                 // ignore
             }
+            Some(Opcode::Forget) => {
+                // ignore
+            }
             Some(Opcode::GetPredictionTriggers) => {
                 xous::return_scalar(msg.sender, mytriggers.into())
                     .expect("couldn't return GetPredictionTriggers");
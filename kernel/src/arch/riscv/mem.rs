@@ -1072,6 +1072,39 @@ pub fn page_flags(virt: usize) -> Option<MemoryFlags> {
     if return_flags.is_empty() { None } else { Some(return_flags) }
 }
 
+/// Find the first run of contiguous, identically-flagged pages at or above `start` in the
+/// current process. Shared (lent) pages are skipped, as they do not belong to the process.
+///
+/// # Returns
+///
+/// * **None**: There are no more mapped pages in the user area above `start`
+/// * **Some((start, length, flags))**: The region that was found
+#[cfg(feature = "crash-dump")]
+pub fn next_mapped_region(start: usize) -> Option<(usize, usize, MemoryFlags)> {
+    let l1_pt = unsafe { &(*(PAGE_TABLE_ROOT_OFFSET as *const RootPageTable)) };
+    let mut region: Option<(usize, usize, MemoryFlags)> = None;
+    let mut addr = start & !(PAGE_SIZE - 1);
+    while addr < USER_AREA_END {
+        let vpn1 = (addr >> 22) & ((1 << 10) - 1);
+        if l1_pt.entries[vpn1] & MMUFlags::VALID.bits() == 0 {
+            if region.is_some() {
+                break;
+            }
+            // skip the whole superpage
+            addr = (vpn1 + 1) << 22;
+            continue;
+        }
+        match (page_flags(addr), region.as_mut()) {
+            (Some(flags), None) => region = Some((addr, PAGE_SIZE, flags)),
+            (Some(flags), Some(r)) if r.2 == flags => r.1 += PAGE_SIZE,
+            (_, Some(_)) => break,
+            (None, None) => {}
+        }
+        addr += PAGE_SIZE;
+    }
+    region
+}
+
 pub fn update_page_flags(virt: usize, flags: MemoryFlags) -> Result<(), xous_kernel::Error> {
     // The resulting flags must actually be valid
     if (flags & (MemoryFlags::R | MemoryFlags::W | MemoryFlags::X)).is_empty() {
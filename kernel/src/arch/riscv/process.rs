@@ -255,6 +255,21 @@ impl Process {
         &process.threads[tid]
     }
 
+    /// Returns the saved registers of a thread followed by its program counter, or `None` if the
+    /// thread does not exist. Used to build crash dumps.
+    #[cfg(feature = "crash-dump")]
+    pub fn thread_context(&self, tid: TID) -> Option<[usize; 32]> {
+        let process = unsafe { &*PROCESS };
+        let thread = process.threads.get(fixup_irq(tid))?;
+        if thread.sepc == 0 {
+            return None;
+        }
+        let mut context = [0usize; 32];
+        context[..31].copy_from_slice(&thread.registers);
+        context[31] = thread.sepc;
+        Some(context)
+    }
+
     #[cfg(feature = "gdb-stub")]
     pub fn for_each_thread_mut<F>(&self, mut op: F)
     where
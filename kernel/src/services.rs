@@ -2272,23 +2272,19 @@ impl SystemServices {
         Ok(())
     }
 
-    /* https://github.com/betrusted-io/xous-core/issues/90
     /// Sets the exception handler for the given process ID. If an exception handler
     /// exists, it will be silently overridden.
+    #[cfg(feature = "crash-dump")]
     pub fn set_exception_handler(
         &mut self,
         pid: PID,
         pc: usize,
         sp: usize,
     ) -> Result<(), xous_kernel::Error> {
-        self.get_process_mut(pid)?.exception_handler = if pc != 0 && sp != 0 {
-            Some(ExceptionHandler { pc, sp })
-        } else {
-            None
-        };
+        self.get_process_mut(pid)?.exception_handler =
+            if pc != 0 && sp != 0 { Some(ExceptionHandler { pc, sp }) } else { None };
         Ok(())
     }
-    */
 
     /// Causes the provided process to go into an exception state. This will fail
     /// if any of the following are true:
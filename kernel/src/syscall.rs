@@ -1118,12 +1118,29 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             ))
         }
 
-        /* https://github.com/betrusted-io/xous-core/issues/90
+        #[cfg(feature = "crash-dump")]
         SysCall::SetExceptionHandler(pc, sp) => SystemServices::with_mut(|ss| {
-            ss.set_exception_handler(pid, pc, sp)
-                .and(Ok(xous_kernel::Result::Ok))
+            ss.set_exception_handler(pid, pc, sp).and(Ok(xous_kernel::Result::Ok))
         }),
-        */
+        #[cfg(all(feature = "crash-dump", target_arch = "riscv32"))]
+        SysCall::GetThreadContext(tid, bank) => {
+            let context = ArchProcess::with_current(|process| process.thread_context(tid))
+                .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
+            let first = bank.checked_mul(5).ok_or(xous_kernel::Error::InvalidLimit)?;
+            if first >= context.len() {
+                return Err(xous_kernel::Error::InvalidLimit);
+            }
+            let mut regs = [0usize; 5];
+            for (dst, src) in regs.iter_mut().zip(context[first..].iter()) {
+                *dst = *src;
+            }
+            Ok(xous_kernel::Result::Scalar5(regs[0], regs[1], regs[2], regs[3], regs[4]))
+        }
+        #[cfg(all(feature = "crash-dump", target_arch = "riscv32"))]
+        SysCall::GetMemoryRegion(start) => match crate::arch::mem::next_mapped_region(start) {
+            Some((start, len, flags)) => Ok(xous_kernel::Result::Scalar5(start, len, flags.bits(), 0, 0)),
+            None => Err(xous_kernel::Error::BadAddress),
+        },
         _ => Err(xous_kernel::Error::UnhandledSyscall),
     }
 }
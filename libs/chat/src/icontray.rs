@@ -94,6 +94,9 @@ pub(crate) fn server(_cid: Option<CID>, icons: [&str; 4]) {
             Some(Opcode::Unpick) => {
                 // ignore
             }
+            Some(Opcode::Forget) => {
+                // ignore
+            }
             Some(Opcode::GetPredictionTriggers) => {
                 xous::return_scalar(msg.sender, mytriggers.into())
                     .expect("couldn't return GetPredictionTriggers");
@@ -0,0 +1,190 @@
+use std::io::{Read, Write};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::format::*;
+
+/// PDDB dictionary that crash dumps are stored in. Keys are named `<process>.<sequence>`.
+pub const CRASH_DICT: &str = "sys.crashdump";
+/// Dumps kept per process; the oldest one is deleted when a new one is stored.
+pub const MAX_DUMPS_PER_PROCESS: usize = 4;
+/// Highest thread ID that is probed for register state
+const MAX_TID: usize = 31;
+/// Thread ID the kernel runs exception handlers on
+const EXCEPTION_TID: usize = 1;
+
+static PROCESS_NAME: OnceLock<&'static str> = OnceLock::new();
+/// set while a dump is being taken, so a crash inside the crash handler doesn't recurse
+static IN_CRASH: AtomicBool = AtomicBool::new(false);
+
+/// Installs the crash handlers for this process. `name` identifies the process in the stored dumps, and
+/// should match the name of the ELF file so the host decoder can find its symbols.
+///
+/// Panics are recorded through a panic hook, which chains to the previously installed hook so the usual
+/// `PanicMessage` relay through `xous-log` still happens. CPU exceptions are recorded through the process'
+/// exception handler, after which the process is terminated.
+pub fn install(name: &'static str) -> Result<(), xous::Error> {
+    PROCESS_NAME.set(name).or(Err(xous::Error::MemoryInUse))?;
+    xous::set_exception_handler(exception_handler)?;
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if !IN_CRASH.swap(true, Ordering::SeqCst) {
+            let tid = xous::current_tid().unwrap_or(0) as u32;
+            let mut dump = CrashDump::new(CrashKind::Panic, xous::process::id(), tid, name);
+            dump.message = info.to_string();
+            // the address of a local is a close enough approximation of the panicking thread's stack pointer
+            let marker = 0u32;
+            capture_state(&mut dump, Some(&marker as *const u32 as u32));
+            store(&dump);
+            IN_CRASH.store(false, Ordering::SeqCst);
+        }
+        previous(info);
+    }));
+    Ok(())
+}
+
+fn exception_handler(ex: xous::Exception) -> isize {
+    if IN_CRASH.swap(true, Ordering::SeqCst) {
+        xous::terminate_process(1);
+    }
+    let name = PROCESS_NAME.get().copied().unwrap_or("unknown");
+    let mut dump = CrashDump::new(CrashKind::Exception, xous::process::id(), 0, name);
+    dump.code = exception_code(&ex);
+    dump.pc = ex.pc() as u32;
+    dump.address = ex.address().unwrap_or(0) as u32;
+    dump.message = format!("{:x?}", ex);
+    capture_state(&mut dump, None);
+    // the faulting thread is the one whose saved program counter points at the faulting instruction
+    if let Some(thread) = dump.threads.iter().find(|t| t.pc() == dump.pc) {
+        dump.tid = thread.tid;
+    }
+    store(&dump);
+    xous::terminate_process(1)
+}
+
+fn exception_code(ex: &xous::Exception) -> u32 {
+    use xous::{Exception, ExceptionType};
+    let code = match *ex {
+        Exception::InstructionAddressMisaligned(..) => ExceptionType::InstructionAddressMisaligned,
+        Exception::InstructionAccessFault(..) => ExceptionType::InstructionAccessFault,
+        Exception::IllegalInstruction(..) => ExceptionType::IllegalInstruction,
+        Exception::LoadAddressMisaligned(..) => ExceptionType::LoadAddressMisaligned,
+        Exception::LoadAccessFault(..) => ExceptionType::LoadAccessFault,
+        Exception::StoreAddressMisaligned(..) => ExceptionType::StoreAddressMisaligned,
+        Exception::StoreAccessFault(..) => ExceptionType::StoreAccessFault,
+        Exception::InstructionPageFault(..) => ExceptionType::InstructionPageFault,
+        Exception::LoadPageFault(..) => ExceptionType::LoadPageFault,
+        Exception::StorePageFault(..) => ExceptionType::StorePageFault,
+        Exception::Unknown(code, _, _) => return code as u32,
+    };
+    code as u32
+}
+
+/// Records the registers of all threads, the memory map and an excerpt of the crashed thread's stack.
+/// `sp` overrides the stack pointer used for the excerpt.
+fn capture_state(dump: &mut CrashDump, sp: Option<u32>) {
+    for tid in 0..=MAX_TID {
+        if tid == EXCEPTION_TID && dump.kind == CrashKind::Exception {
+            // that's us
+            continue;
+        }
+        if let Ok(context) = xous::thread_context(tid) {
+            let mut regs = [0u32; REGS_PER_THREAD];
+            for (dst, src) in regs.iter_mut().zip(context.iter()) {
+                *dst = *src as u32;
+            }
+            dump.threads.push(ThreadState { tid: tid as u32, regs });
+        }
+    }
+
+    let mut addr = 0;
+    while let Ok(Some((start, len, flags))) = xous::next_memory_region(addr) {
+        dump.regions.push(MemoryRegion { start: start as u32, len: len as u32, flags: flags.bits() as u32 });
+        addr = match start.checked_add(len) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    let sp = match sp {
+        Some(sp) => sp,
+        None => match dump.crashed_thread().or_else(|| dump.threads.iter().find(|t| t.pc() == dump.pc)) {
+            Some(thread) => thread.sp(),
+            None => return,
+        },
+    };
+    // only copy memory that is known to be mapped and readable
+    if let Some(region) = dump.regions.iter().find(|r| {
+        sp >= r.start && sp - r.start < r.len && r.flags & xous::MemoryFlags::R.bits() as u32 != 0
+    }) {
+        let len = ((region.start + region.len - sp) as usize).min(MAX_STACK_LEN);
+        let stack = unsafe { core::slice::from_raw_parts(sp as usize as *const u8, len) };
+        dump.stack_base = sp;
+        dump.stack = stack.to_vec();
+    }
+}
+
+/// Logs a summary of the dump and stores it in the PDDB. If the PDDB is not mounted, only the summary is
+/// available.
+fn store(dump: &CrashDump) {
+    log::error!(
+        "crash in {} (PID {} TID {}): pc {:08x} addr {:08x}: {}",
+        dump.name,
+        dump.pid,
+        dump.tid,
+        dump.pc,
+        dump.address,
+        dump.message
+    );
+    let pddb = pddb::Pddb::new();
+    if !pddb.is_mounted_nonblocking() {
+        log::warn!("PDDB is not mounted, crash dump was not stored");
+        return;
+    }
+    let mut seqs = dump_sequences(&pddb, &dump.name);
+    seqs.sort_unstable();
+    let next = seqs.last().map(|s| s + 1).unwrap_or(0);
+    while seqs.len() >= MAX_DUMPS_PER_PROCESS {
+        let oldest = seqs.remove(0);
+        pddb.delete_key(CRASH_DICT, &format!("{}.{}", dump.name, oldest), None).ok();
+    }
+    let encoded = dump.encode();
+    let key_name = format!("{}.{}", dump.name, next);
+    match pddb.get(CRASH_DICT, &key_name, None, true, true, Some(encoded.len()), None::<fn()>) {
+        Ok(mut key) => {
+            if key.write_all(&encoded).is_err() {
+                log::error!("couldn't write crash dump {}", key_name);
+            }
+        }
+        Err(e) => log::error!("couldn't create crash dump key {}: {:?}", key_name, e),
+    }
+    pddb.sync().ok();
+    log::info!("crash dump stored as {}:{}", CRASH_DICT, key_name);
+}
+
+fn dump_sequences(pddb: &pddb::Pddb, name: &str) -> Vec<u32> {
+    let prefix = format!("{}.", name);
+    pddb.list_keys(CRASH_DICT, None)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|k| k.strip_prefix(&prefix).and_then(|seq| seq.parse::<u32>().ok()))
+        .collect()
+}
+
+/// Lists the names of all stored crash dumps
+pub fn list_dumps(pddb: &pddb::Pddb) -> Vec<String> { pddb.list_keys(CRASH_DICT, None).unwrap_or_default() }
+
+/// Reads back the raw bytes of a stored crash dump
+pub fn read_dump(pddb: &pddb::Pddb, key_name: &str) -> std::io::Result<Vec<u8>> {
+    let mut key = pddb.get(CRASH_DICT, key_name, None, false, false, None, None::<fn()>)?;
+    let mut data = Vec::new();
+    key.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Deletes all stored crash dumps
+pub fn clear_dumps(pddb: &pddb::Pddb) -> std::io::Result<()> {
+    pddb.delete_dict(CRASH_DICT, None)?;
+    pddb.sync()
+}
@@ -0,0 +1,272 @@
+//! The on-disk crash dump format.
+//!
+//! Dumps are stored as a flat little-endian record so that they can be decoded on the host without
+//! having to match the serializer version of the device. All machine words are stored as `u32`, as
+//! every Xous hardware target is 32-bit.
+//!
+//! ```text
+//! "XCRD" | version: u16 | kind: u16 | pid: u32 | tid: u32
+//! exception code: u32 | pc: u32 | fault address: u32
+//! name:    len: u16, utf-8 bytes
+//! message: len: u16, utf-8 bytes
+//! threads: count: u16, { tid: u32, regs: [u32; REGS_PER_THREAD] }*
+//! regions: count: u16, { start: u32, len: u32, flags: u32 }*
+//! stack:   base: u32, len: u16, bytes
+//! ```
+
+pub const MAGIC: [u8; 4] = *b"XCRD";
+pub const VERSION: u16 = 1;
+/// general purpose registers (minus `zero`), followed by the program counter
+pub const REGS_PER_THREAD: usize = 32;
+/// Longest panic message that is kept, in bytes
+pub const MAX_MESSAGE_LEN: usize = 512;
+/// Largest stack excerpt that is kept, in bytes
+pub const MAX_STACK_LEN: usize = 2048;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CrashKind {
+    /// An unhandled CPU exception; `code` holds the `xous::ExceptionType`
+    Exception = 0,
+    Panic = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadState {
+    pub tid: u32,
+    pub regs: [u32; REGS_PER_THREAD],
+}
+impl ThreadState {
+    /// the program counter, stored after the general purpose registers
+    pub fn pc(&self) -> u32 { self.regs[REGS_PER_THREAD - 1] }
+
+    /// `x1`, the return address
+    pub fn ra(&self) -> u32 { self.regs[0] }
+
+    /// `x2`, the stack pointer
+    pub fn sp(&self) -> u32 { self.regs[1] }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub start: u32,
+    pub len: u32,
+    /// raw `xous::MemoryFlags` bits
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashDump {
+    pub kind: CrashKind,
+    pub pid: u32,
+    /// the thread that crashed
+    pub tid: u32,
+    pub code: u32,
+    pub pc: u32,
+    pub address: u32,
+    pub name: String,
+    pub message: String,
+    pub threads: Vec<ThreadState>,
+    pub regions: Vec<MemoryRegion>,
+    pub stack_base: u32,
+    pub stack: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    BadString,
+}
+
+impl CrashDump {
+    pub fn new(kind: CrashKind, pid: u32, tid: u32, name: &str) -> Self {
+        CrashDump {
+            kind,
+            pid,
+            tid,
+            code: 0,
+            pc: 0,
+            address: 0,
+            name: name.to_owned(),
+            message: String::new(),
+            threads: Vec::new(),
+            regions: Vec::new(),
+            stack_base: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    /// The state of the thread that crashed, if it was captured
+    pub fn crashed_thread(&self) -> Option<&ThreadState> { self.threads.iter().find(|t| t.tid == self.tid) }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256 + self.threads.len() * REGS_PER_THREAD * 4 + self.stack.len());
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.kind as u16).to_le_bytes());
+        for word in [self.pid, self.tid, self.code, self.pc, self.address] {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+        put_str(&mut out, &self.name, u16::MAX as usize);
+        put_str(&mut out, &self.message, MAX_MESSAGE_LEN);
+        out.extend_from_slice(&(self.threads.len() as u16).to_le_bytes());
+        for thread in self.threads.iter() {
+            out.extend_from_slice(&thread.tid.to_le_bytes());
+            for reg in thread.regs.iter() {
+                out.extend_from_slice(&reg.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&(self.regions.len() as u16).to_le_bytes());
+        for region in self.regions.iter() {
+            for word in [region.start, region.len, region.flags] {
+                out.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        let stack = &self.stack[..self.stack.len().min(MAX_STACK_LEN)];
+        out.extend_from_slice(&self.stack_base.to_le_bytes());
+        out.extend_from_slice(&(stack.len() as u16).to_le_bytes());
+        out.extend_from_slice(stack);
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let kind = if r.u16()? == CrashKind::Panic as u16 { CrashKind::Panic } else { CrashKind::Exception };
+        let pid = r.u32()?;
+        let tid = r.u32()?;
+        let code = r.u32()?;
+        let pc = r.u32()?;
+        let address = r.u32()?;
+        let name = r.string()?;
+        let message = r.string()?;
+        let mut threads = Vec::new();
+        for _ in 0..r.u16()? {
+            let tid = r.u32()?;
+            let mut regs = [0u32; REGS_PER_THREAD];
+            for reg in regs.iter_mut() {
+                *reg = r.u32()?;
+            }
+            threads.push(ThreadState { tid, regs });
+        }
+        let mut regions = Vec::new();
+        for _ in 0..r.u16()? {
+            regions.push(MemoryRegion { start: r.u32()?, len: r.u32()?, flags: r.u32()? });
+        }
+        let stack_base = r.u32()?;
+        let stack_len = r.u16()? as usize;
+        let stack = r.bytes(stack_len)?.to_vec();
+        Ok(CrashDump {
+            kind,
+            pid,
+            tid,
+            code,
+            pc,
+            address,
+            name,
+            message,
+            threads,
+            regions,
+            stack_base,
+            stack,
+        })
+    }
+
+    /// Iterates over the words of the stack excerpt, along with the address they were read from
+    pub fn stack_words(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.stack.chunks_exact(4).enumerate().map(move |(i, w)| {
+            (self.stack_base + i as u32 * 4, u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        })
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str, max: usize) {
+    let mut end = s.len().min(max);
+    // don't split a multi-byte character when truncating
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    out.extend_from_slice(&(end as u16).to_le_bytes());
+    out.extend_from_slice(&s.as_bytes()[..end]);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let slice = self.data.get(self.pos..self.pos + len).ok_or(DecodeError::Truncated)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).or(Err(DecodeError::BadString))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> CrashDump {
+        let mut dump = CrashDump::new(CrashKind::Exception, 7, 2, "shellchat");
+        dump.code = 8;
+        dump.pc = 0x2001_0040;
+        dump.address = 0xdead_beef;
+        let mut regs = [0u32; REGS_PER_THREAD];
+        regs[1] = 0x7fff_f000;
+        regs[REGS_PER_THREAD - 1] = 0x2001_0040;
+        dump.threads.push(ThreadState { tid: 2, regs });
+        dump.regions.push(MemoryRegion { start: 0x2000_0000, len: 0x1_0000, flags: 0b1010 });
+        dump.stack_base = 0x7fff_f000;
+        dump.stack = vec![0x40, 0x00, 0x01, 0x20, 1, 2, 3, 4];
+        dump
+    }
+
+    #[test]
+    fn roundtrip() {
+        let dump = sample();
+        let decoded = CrashDump::decode(&dump.encode()).unwrap();
+        assert_eq!(decoded, dump);
+        assert_eq!(decoded.crashed_thread().unwrap().sp(), 0x7fff_f000);
+        let words: Vec<(u32, u32)> = decoded.stack_words().collect();
+        assert_eq!(words, vec![(0x7fff_f000, 0x2001_0040), (0x7fff_f004, 0x0403_0201)]);
+    }
+
+    #[test]
+    fn long_messages_are_truncated_on_char_boundaries() {
+        let mut dump = sample();
+        dump.kind = CrashKind::Panic;
+        dump.message = "é".repeat(MAX_MESSAGE_LEN);
+        let decoded = CrashDump::decode(&dump.encode()).unwrap();
+        assert_eq!(decoded.message.len(), MAX_MESSAGE_LEN);
+        assert!(decoded.message.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(CrashDump::decode(b"nope"), Err(DecodeError::BadMagic));
+        let encoded = sample().encode();
+        assert_eq!(CrashDump::decode(&encoded[..encoded.len() - 1]), Err(DecodeError::Truncated));
+    }
+}
@@ -0,0 +1,17 @@
+//! Post-mortem crash dumps for Xous processes.
+//!
+//! A process opts in by calling `crash_dump::install("my-process")` early in `main()`. When the process
+//! then panics or takes an unhandled CPU exception, the registers of all of its threads, the faulting
+//! address, an excerpt of the crashed thread's stack and the process' memory map are stored in the
+//! `sys.crashdump` PDDB dictionary. The `crash-decode` tool turns a dump into a backtrace using the ELF
+//! symbols from the build.
+//!
+//! Capturing requires a kernel built with the `crash-dump` feature.
+
+pub mod format;
+pub use format::*;
+
+#[cfg(feature = "capture")]
+mod capture;
+#[cfg(feature = "capture")]
+pub use capture::*;
@@ -69,6 +69,149 @@ fn derive_ipc_inner(ast: DeriveInput) -> Result<proc_macro2::TokenStream, proc_m
     })
 }
 
+#[proc_macro_derive(IpcValidate)]
+pub fn derive_validate(ts: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(ts as syn::DeriveInput);
+    derive_validate_inner(ast).unwrap_or_else(|e| e).into()
+}
+
+fn derive_validate_inner(ast: DeriveInput) -> Result<proc_macro2::TokenStream, proc_macro2::TokenStream> {
+    let ident = &ast.ident;
+    let body = match &ast.data {
+        syn::Data::Struct(r#struct) => generate_validate_struct(&r#struct.fields),
+        syn::Data::Enum(r#enum) => generate_validate_enum(&ast, r#enum)?,
+        // Any bit pattern is a valid union. Reading a field is already `unsafe`.
+        syn::Data::Union(_) => quote! { Ok(()) },
+    };
+
+    let mut generics = ast.generics.clone();
+    let type_params: Vec<_> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in type_params {
+        where_clause.predicates.push(syn::parse_quote! { #param: flatipc::IpcValidate });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        unsafe impl #impl_generics flatipc::IpcValidate for #ident #ty_generics #where_clause {
+            fn validate(data: &[u8]) -> Result<(), flatipc::ValidationError> {
+                #body
+            }
+        }
+    })
+}
+
+fn generate_validate_struct(fields: &syn::Fields) -> proc_macro2::TokenStream {
+    let checks = fields.iter().enumerate().map(|(i, field)| {
+        let ty = &field.ty;
+        let member = match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let index = syn::Index::from(i);
+                quote! { #index }
+            }
+        };
+        quote! {
+            flatipc::validate::validate_at::<#ty>(data, core::mem::offset_of!(Self, #member))?;
+        }
+    });
+    quote! {
+        #(#checks)*
+        Ok(())
+    }
+}
+
+/// Enums are validated by reading the tag and then checking the fields of the matching variant. This
+/// relies on the layout guaranteed for enums with a primitive representation, where each variant is
+/// laid out as a `#[repr(C)]` struct that starts with the tag.
+fn generate_validate_enum(
+    ast: &DeriveInput,
+    enm: &syn::DataEnum,
+) -> Result<proc_macro2::TokenStream, proc_macro2::TokenStream> {
+    const PRIMITIVES: &[&str] = &["u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize"];
+    let mut primitive = None;
+    let mut repr_c = false;
+    for attr in ast.attrs.iter() {
+        if attr.path().is_ident("repr") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("C") {
+                    repr_c = true;
+                } else if let Some(ident) = meta.path.get_ident() {
+                    if PRIMITIVES.contains(&ident.to_string().as_str()) {
+                        primitive = Some(ident.clone());
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e| e.to_compile_error())?;
+        }
+    }
+    let Some(primitive) = primitive else {
+        return Err(syn::Error::new(
+            ast.span(),
+            "Enums must have a primitive representation such as repr(u8) to be validated",
+        )
+        .to_compile_error());
+    };
+    let has_fields = enm.variants.iter().any(|v| !v.fields.is_empty());
+    if has_fields && repr_c {
+        return Err(syn::Error::new(ast.span(), "Enums with fields must not be repr(C) to be validated")
+            .to_compile_error());
+    }
+    if has_fields && !ast.generics.params.is_empty() {
+        return Err(syn::Error::new(ast.generics.span(), "Generic enums with fields cannot be validated")
+            .to_compile_error());
+    }
+
+    let mut discriminants = vec![];
+    let mut checks = vec![];
+    let mut previous = None;
+    for (i, variant) in enm.variants.iter().enumerate() {
+        let discriminant = format_ident!("DISCRIMINANT_{}", i);
+        let value = match (&variant.discriminant, &previous) {
+            (Some((_, expr)), _) => quote! { (#expr) as #primitive },
+            (None, Some(previous)) => quote! { #previous + 1 },
+            (None, None) => quote! { 0 },
+        };
+        discriminants.push(quote! { const #discriminant: #primitive = #value; });
+        previous = Some(discriminant.clone());
+
+        let tys: Vec<_> = variant.fields.iter().map(|f| &f.ty).collect();
+        let field_checks = tys.iter().enumerate().map(|(i, ty)| {
+            let index = syn::Index::from(i + 1);
+            quote! {
+                flatipc::validate::validate_at::<#ty>(data, core::mem::offset_of!(Variant, #index))?;
+            }
+        });
+        let layout = if tys.is_empty() {
+            quote! {}
+        } else {
+            quote! {
+                #[repr(C)]
+                #[allow(dead_code)]
+                struct Variant(#primitive, #(#tys),*);
+            }
+        };
+        checks.push(quote! {
+            if tag == #discriminant {
+                #layout
+                #(#field_checks)*
+                return Ok(());
+            }
+        });
+    }
+
+    Ok(quote! {
+        #(#discriminants)*
+        let tag_size = core::mem::size_of::<#primitive>();
+        let tag = <#primitive>::from_ne_bytes(
+            data.get(..tag_size).ok_or(flatipc::ValidationError::TooShort)?.try_into().unwrap(),
+        );
+        #(#checks)*
+        Err(flatipc::ValidationError::Discriminant { offset: 0 })
+    })
+}
+
 fn ensure_valid_repr(ast: &DeriveInput) -> Result<(), proc_macro2::TokenStream> {
     let mut repr_c = false;
     for attr in ast.attrs.iter() {
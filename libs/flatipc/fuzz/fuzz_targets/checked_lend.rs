@@ -0,0 +1,84 @@
+#![no_main]
+//! Lend arbitrary bytes to a mock server that decodes them with the checked API. Anything that gets
+//! past validation must be safe to use, so the server touches every field.
+
+use std::sync::LazyLock;
+
+use flatipc::backend::mock::{CID, IPC_MACHINE, Server};
+use flatipc::{IntoIpc, Ipc};
+use libfuzzer_sys::fuzz_target;
+
+// Variants are only ever decoded, never constructed.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, flatipc::IpcSafe, flatipc::IpcValidate)]
+#[repr(u8)]
+pub enum Shape {
+    Empty,
+    Circle { x: i16, y: i16, radius: u16 },
+    Label(bool, char),
+    Filled(Fill),
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, flatipc::IpcSafe, flatipc::IpcValidate)]
+#[repr(u16)]
+pub enum Fill {
+    Dark = 1,
+    Light = 0x100,
+}
+
+#[derive(Debug, flatipc::Ipc, flatipc::IpcValidate)]
+#[repr(C)]
+pub struct Drawing {
+    shapes: flatipc::Vec<Shape, 8>,
+    title: flatipc::String<64>,
+    dirty: bool,
+    id: [u32; 4],
+}
+
+#[repr(C, align(4096))]
+struct Page([u8; 4096]);
+
+static SIGNATURE: LazyLock<usize> = LazyLock::new(|| {
+    Drawing { shapes: flatipc::Vec::new(), title: flatipc::String::new(), dirty: false, id: [0; 4] }
+        .into_ipc()
+        .signature()
+});
+
+static CONNECTION: LazyLock<CID> = LazyLock::new(|| {
+    IPC_MACHINE.lock().unwrap().add_server(Server::new(
+        Box::new(|_, signature, _, buffer| match IpcDrawing::from_slice_checked(buffer, signature) {
+            Ok(drawing) => {
+                let _ = format!("{:?} {}", drawing.shapes, drawing.title);
+                (1, 0)
+            }
+            Err(_) => (0, 0),
+        }),
+        Box::new(|_, signature, _, buffer| match IpcDrawing::from_slice_mut_checked(buffer, signature) {
+            Ok(drawing) => {
+                let _ = format!("{:?} {}", drawing.shapes, drawing.title);
+                drawing.dirty = !drawing.dirty;
+                drawing.shapes.clear();
+                (1, 0)
+            }
+            Err(_) => (0, 0),
+        }),
+    ))
+});
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, data)) = data.split_first() else { return };
+    let mut page = Box::new(Page([0; 4096]));
+    let len = data.len().min(page.0.len());
+    page.0[..len].copy_from_slice(&data[..len]);
+
+    // Most inputs should carry the right signature, or they never reach validation.
+    let signature = if mode & 1 == 0 { *SIGNATURE } else { mode as usize };
+    let connection = *CONNECTION;
+    let machine = IPC_MACHINE.lock().unwrap();
+    if mode & 2 == 0 {
+        machine.lend(connection, 0, signature, 0, &page.0);
+    } else {
+        machine.lend_mut(connection, 0, signature, 0, &mut page.0);
+    }
+});
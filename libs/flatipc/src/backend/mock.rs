@@ -21,7 +21,7 @@ pub struct IpcMachine {
     servers: Vec<Server>,
 }
 
-pub(crate) static IPC_MACHINE: LazyLock<Mutex<IpcMachine>> = LazyLock::new(|| Mutex::new(IpcMachine::new()));
+pub static IPC_MACHINE: LazyLock<Mutex<IpcMachine>> = LazyLock::new(|| Mutex::new(IpcMachine::new()));
 
 impl IpcMachine {
     fn new() -> Self { IpcMachine { servers: Vec::new() } }
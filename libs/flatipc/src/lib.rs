@@ -42,6 +42,12 @@
 //! Because `String` and `Vec` contain pointers, they are not `IpcSafe`. As such,
 //! replacements are made available in this crate that are `IpcSafe`.
 //!
+//! Servers that accept messages from untrusted clients can opt in to checking
+//! the contents of a message before using it. Deriving `IpcValidate` alongside
+//! `Ipc` enables `from_slice_checked()` and `from_memory_message_checked()`,
+//! which verify bools, chars, enum discriminants, and `String`/`Vec` lengths and
+//! encodings, and return a `ValidationError` rather than a malformed object.
+//!
 //! <br>
 //!
 //! # Example of a Derived Ipc Object
@@ -114,15 +120,16 @@ pub unsafe trait IpcSafe {}
 extern crate self as flatipc;
 
 // Allow doing `#[derive(flatipc::Ipc)]` instead of `#[derive(flatipc_derive::Ipc)]`
-pub use flatipc_derive::{Ipc, IpcSafe};
+pub use flatipc_derive::{Ipc, IpcSafe, IpcValidate};
 #[cfg(feature = "xous")]
 mod backend {
     pub use xous::CID;
     pub use xous::Error;
 }
 
+// The mock backend is public so that tests and fuzzers outside this crate can act as servers.
 #[cfg(not(feature = "xous"))]
-mod backend {
+pub mod backend {
     pub mod mock;
     pub use mock::CID;
 
@@ -140,6 +147,9 @@ pub use string::String;
 pub mod vec;
 pub use vec::Vec;
 
+pub mod validate;
+pub use validate::{IpcValidate, ValidationError};
+
 unsafe impl IpcSafe for i8 {}
 unsafe impl IpcSafe for i16 {}
 unsafe impl IpcSafe for i32 {}
@@ -230,6 +240,59 @@ pub unsafe trait Ipc {
     /// signature and returns `None` if there is no match. The returned object has a
     /// lifetime that's tied to the `MemoryMessage`.
     fn from_memory_message_mut<'a>(msg: &'a mut xous::MemoryMessage) -> Option<&'a mut Self>;
+
+    /// Like `from_slice()`, but check that `data` holds a valid `Original` before
+    /// creating a reference to it.
+    fn from_slice_checked<'a>(data: &'a [u8], signature: usize) -> Result<&'a Self, ValidationError>
+    where
+        Self: Sized,
+        Self::Original: IpcValidate,
+    {
+        validate::check::<Self>(data)?;
+        Self::from_slice(data, signature).ok_or(ValidationError::Signature)
+    }
+
+    /// Like `from_slice_mut()`, but check that `data` holds a valid `Original` before
+    /// creating a reference to it.
+    fn from_slice_mut_checked<'a>(
+        data: &'a mut [u8],
+        signature: usize,
+    ) -> Result<&'a mut Self, ValidationError>
+    where
+        Self: Sized,
+        Self::Original: IpcValidate,
+    {
+        validate::check::<Self>(data)?;
+        Self::from_slice_mut(data, signature).ok_or(ValidationError::Signature)
+    }
+
+    #[cfg(feature = "xous")]
+    /// Like `from_memory_message()`, but check that the message holds a valid `Original`.
+    /// Use this when the sender is not trusted.
+    fn from_memory_message_checked<'a>(msg: &'a xous::MemoryMessage) -> Result<&'a Self, ValidationError>
+    where
+        Self: Sized,
+        Self::Original: IpcValidate,
+    {
+        let data = unsafe { core::slice::from_raw_parts(msg.buf.as_ptr(), msg.buf.len()) };
+        validate::check::<Self>(data)?;
+        Self::from_memory_message(msg).ok_or(ValidationError::Signature)
+    }
+
+    #[cfg(feature = "xous")]
+    /// Like `from_memory_message_mut()`, but check that the message holds a valid `Original`.
+    /// Use this when the sender is not trusted.
+    fn from_memory_message_mut_checked<'a>(
+        msg: &'a mut xous::MemoryMessage,
+    ) -> Result<&'a mut Self, ValidationError>
+    where
+        Self: Sized,
+        Self::Original: IpcValidate,
+    {
+        let data = unsafe { core::slice::from_raw_parts(msg.buf.as_ptr(), msg.buf.len()) };
+        validate::check::<Self>(data)?;
+        Self::from_memory_message_mut(msg).ok_or(ValidationError::Signature)
+    }
 }
 
 /// Objects that have `IntoIpc` may be turned into an object that can be passed
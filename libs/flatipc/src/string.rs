@@ -1,11 +1,25 @@
 #[derive(Clone, Copy)]
 pub struct String<const N: usize> {
-    length: usize,
-    buffer: [u8; N],
+    pub(crate) length: usize,
+    pub(crate) buffer: [u8; N],
 }
 
 unsafe impl<const N: usize> crate::IpcSafe for String<N> {}
 
+unsafe impl<const N: usize> crate::IpcValidate for String<N> {
+    fn validate(data: &[u8]) -> Result<(), crate::ValidationError> {
+        let length_offset = core::mem::offset_of!(Self, length);
+        let length = crate::validate::read_usize(data, length_offset)?;
+        if length > N {
+            return Err(crate::ValidationError::Length { offset: length_offset });
+        }
+        let buffer = core::mem::offset_of!(Self, buffer);
+        core::str::from_utf8(&data[buffer..buffer + length])
+            .map_err(|_| crate::ValidationError::Utf8 { offset: buffer })?;
+        Ok(())
+    }
+}
+
 impl<const N: usize> String<N> {
     pub fn new() -> Self { String { buffer: [0; N], length: 0 } }
 
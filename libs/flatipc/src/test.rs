@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, flatipc::IpcSafe)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, flatipc::IpcSafe, flatipc::IpcValidate)]
 pub struct Point {
     pub x: i16,
     pub y: i16,
@@ -268,3 +268,129 @@ fn server_test() {
     let original_inc = lendable_inc.into_original();
     println!("Original value: {}", original_inc.value);
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, flatipc::IpcSafe, flatipc::IpcValidate)]
+#[repr(u8)]
+pub enum Shape {
+    Empty,
+    Circle(Point, u16),
+    Label { visible: bool, initial: char } = 7,
+    Filled(PixelFill),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, flatipc::IpcSafe, flatipc::IpcValidate)]
+#[repr(u8)]
+pub enum PixelFill {
+    Dark = 1,
+    Light = 2,
+}
+
+#[derive(Debug, flatipc::Ipc, flatipc::IpcValidate)]
+#[repr(C)]
+pub struct Drawing {
+    pub shapes: flatipc::Vec<Shape, 4>,
+    pub title: flatipc::String<16>,
+    pub dirty: bool,
+}
+
+#[repr(C, align(4096))]
+struct Page([u8; 4096]);
+
+/// Build a valid `IpcDrawing` in place in a zeroed page, so that no byte of it is uninitialized.
+fn drawing_page() -> (Box<Page>, usize) {
+    use flatipc::{IntoIpc, Ipc};
+
+    let signature = Drawing { shapes: flatipc::Vec::new(), title: flatipc::String::new(), dirty: false }
+        .into_ipc()
+        .signature();
+    let mut page = Box::new(Page([0; 4096]));
+    let drawing = IpcDrawing::from_slice_mut(&mut page.0, signature).unwrap();
+    drawing.shapes.push(Shape::Circle(Point { x: 1, y: 2 }, 3));
+    drawing.shapes.push(Shape::Label { visible: true, initial: 'x' });
+    drawing.shapes.push(Shape::Filled(PixelFill::Light));
+    core::fmt::Write::write_str(&mut drawing.title, "hello").unwrap();
+    (page, signature)
+}
+
+#[test]
+fn validation_rejects_malformed_objects() {
+    use flatipc::{Ipc, ValidationError};
+
+    let (page, signature) = drawing_page();
+    let drawing = IpcDrawing::from_slice_checked(&page.0, signature).map(|d| d.title.to_string());
+    assert_eq!(drawing, Ok("hello".to_string()));
+    assert_eq!(
+        IpcDrawing::from_slice_checked(&page.0, signature ^ 1).err(),
+        Some(ValidationError::Signature)
+    );
+    assert_eq!(
+        IpcDrawing::from_slice_checked(&page.0[..64], signature).err(),
+        Some(ValidationError::TooShort)
+    );
+
+    let corrupt = |offset: usize, value: &[u8]| {
+        let mut page = Page(page.0);
+        page.0[offset..offset + value.len()].copy_from_slice(value);
+        IpcDrawing::from_slice_checked(&page.0, signature).err()
+    };
+    let dirty = core::mem::offset_of!(Drawing, dirty);
+    assert_eq!(corrupt(dirty, &[2]), Some(ValidationError::Bool { offset: dirty }));
+
+    let title = core::mem::offset_of!(Drawing, title);
+    let length = title + core::mem::offset_of!(flatipc::String<16>, length);
+    let buffer = title + core::mem::offset_of!(flatipc::String<16>, buffer);
+    assert_eq!(corrupt(length, &17usize.to_ne_bytes()), Some(ValidationError::Length { offset: length }));
+    assert_eq!(corrupt(buffer, &[0xff]), Some(ValidationError::Utf8 { offset: buffer }));
+
+    let shapes =
+        core::mem::offset_of!(Drawing, shapes) + core::mem::offset_of!(flatipc::Vec<Shape, 4>, buffer);
+    let size = core::mem::size_of::<Shape>();
+    assert_eq!(corrupt(shapes, &[3]), Some(ValidationError::Discriminant { offset: shapes }));
+    assert_eq!(
+        corrupt(shapes + 2 * size + 1, &[0]),
+        Some(ValidationError::Discriminant { offset: shapes + 2 * size + 1 })
+    );
+    // The fourth slot is unused, so its contents don't matter.
+    assert_eq!(corrupt(shapes + 3 * size, &[0xff]), None);
+}
+
+/// Lend random mutations of a valid object to a mock server that only accepts checked objects. The
+/// server must either reject the buffer or get back something that is safe to use.
+#[test]
+fn validation_fuzz_smoke() {
+    use flatipc::Ipc;
+
+    let (page, signature) = drawing_page();
+    let server = flatipc::backend::mock::Server::new(
+        Box::new(|_, _, _, _| (0, 0)),
+        Box::new(|_, signature, _, buffer| match IpcDrawing::from_slice_mut_checked(buffer, signature) {
+            Ok(drawing) => {
+                let _ = format!("{:?}", drawing.shapes);
+                let _ = drawing.title.to_string();
+                drawing.dirty = true;
+                (1, 0)
+            }
+            Err(_) => (0, 0),
+        }),
+    );
+    let connection = flatipc::backend::mock::IPC_MACHINE.lock().unwrap().add_server(server);
+
+    let mut seed = 0x1234_5678_9abc_def0u64;
+    for _ in 0..2000 {
+        let mut buffer = Box::new(Page(page.0));
+        for _ in 0..4 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let offset = (seed as usize >> 8) % core::mem::size_of::<Drawing>();
+            buffer.0[offset] = seed as u8;
+        }
+        flatipc::backend::mock::IPC_MACHINE.lock().unwrap().lend_mut(
+            connection,
+            0,
+            signature,
+            0,
+            &mut buffer.0,
+        );
+    }
+}
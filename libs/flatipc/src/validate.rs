@@ -0,0 +1,138 @@
+//! Checked decoding for `Ipc` objects.
+//!
+//! By default the receiver of an `Ipc` object trusts the sender to have produced a valid
+//! representation of the type. That holds between services that are built together, but not for
+//! servers that may be reached by apps loaded at runtime: a bad `bool`, enum discriminant or string
+//! length is immediate undefined behaviour.
+//!
+//! Types that implement `IpcValidate` can have their raw bytes checked before a reference to them is
+//! created, which is what `Ipc::from_slice_checked()` and `Ipc::from_memory_message_checked()` do.
+//! `IpcValidate` may be derived for structs, for enums with a primitive representation such as
+//! `#[repr(u8)]`, and for unions.
+//!
+//! `Option<T>` and `Result<T, E>` have no defined layout and so cannot be validated. Types that must be
+//! checked should use an explicit `#[repr(u8)]` enum instead.
+
+/// A problem found while validating an `Ipc` object. Offsets are in bytes from the start of the object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The buffer is smaller than the object.
+    TooShort,
+    /// The buffer was tagged with the signature of a different type.
+    Signature,
+    /// A `bool` held something other than 0 or 1.
+    Bool { offset: usize },
+    /// A `char` held a value that is not a Unicode scalar value.
+    Char { offset: usize },
+    /// An enum held a discriminant that doesn't match any variant.
+    Discriminant { offset: usize },
+    /// A `String` or `Vec` claimed to be longer than its capacity.
+    Length { offset: usize },
+    /// A `String` did not contain UTF-8.
+    Utf8 { offset: usize },
+}
+
+impl ValidationError {
+    /// Shift the reported offset by `base`, used when validating a field of a larger object.
+    pub fn offset_by(self, base: usize) -> Self {
+        match self {
+            ValidationError::Bool { offset } => ValidationError::Bool { offset: offset + base },
+            ValidationError::Char { offset } => ValidationError::Char { offset: offset + base },
+            ValidationError::Discriminant { offset } => {
+                ValidationError::Discriminant { offset: offset + base }
+            }
+            ValidationError::Length { offset } => ValidationError::Length { offset: offset + base },
+            ValidationError::Utf8 { offset } => ValidationError::Utf8 { offset: offset + base },
+            other => other,
+        }
+    }
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValidationError::TooShort => write!(f, "buffer is too short"),
+            ValidationError::Signature => write!(f, "signature mismatch"),
+            ValidationError::Bool { offset } => write!(f, "invalid bool at offset {}", offset),
+            ValidationError::Char { offset } => write!(f, "invalid char at offset {}", offset),
+            ValidationError::Discriminant { offset } => {
+                write!(f, "invalid discriminant at offset {}", offset)
+            }
+            ValidationError::Length { offset } => write!(f, "length out of range at offset {}", offset),
+            ValidationError::Utf8 { offset } => write!(f, "invalid UTF-8 in string at offset {}", offset),
+        }
+    }
+}
+
+/// A type whose in-memory representation can be checked without first creating a reference to it.
+///
+/// # Safety
+///
+/// `validate()` must only return `Ok` if `data` holds a valid representation of `Self`. The checked
+/// decoders rely on this to hand out references to untrusted memory.
+pub unsafe trait IpcValidate: Sized {
+    /// Check that `data`, which is exactly `size_of::<Self>()` bytes long, holds a valid `Self`.
+    fn validate(data: &[u8]) -> Result<(), ValidationError>;
+}
+
+/// Validate the field of type `T` located at `offset` within `data`.
+pub fn validate_at<T: IpcValidate>(data: &[u8], offset: usize) -> Result<(), ValidationError> {
+    let end = offset + core::mem::size_of::<T>();
+    if end > data.len() {
+        return Err(ValidationError::TooShort);
+    }
+    T::validate(&data[offset..end]).map_err(|e| e.offset_by(offset))
+}
+
+/// Read a `usize` located at `offset` within `data`.
+pub fn read_usize(data: &[u8], offset: usize) -> Result<usize, ValidationError> {
+    let bytes = data.get(offset..offset + core::mem::size_of::<usize>()).ok_or(ValidationError::TooShort)?;
+    Ok(usize::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+macro_rules! always_valid {
+    ($($t:ty),*) => {
+        $(
+            unsafe impl IpcValidate for $t {
+                fn validate(_data: &[u8]) -> Result<(), ValidationError> { Ok(()) }
+            }
+        )*
+    };
+}
+
+always_valid!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64, usize, isize);
+
+unsafe impl IpcValidate for bool {
+    fn validate(data: &[u8]) -> Result<(), ValidationError> {
+        if data[0] > 1 { Err(ValidationError::Bool { offset: 0 }) } else { Ok(()) }
+    }
+}
+
+unsafe impl IpcValidate for char {
+    fn validate(data: &[u8]) -> Result<(), ValidationError> {
+        let value = u32::from_ne_bytes(data[..4].try_into().unwrap());
+        char::from_u32(value).map(|_| ()).ok_or(ValidationError::Char { offset: 0 })
+    }
+}
+
+unsafe impl<T: IpcValidate, const N: usize> IpcValidate for [T; N] {
+    fn validate(data: &[u8]) -> Result<(), ValidationError> {
+        let stride = core::mem::size_of::<T>();
+        for i in 0..N {
+            validate_at::<T>(data, i * stride)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `data` is large enough for the `Ipc` object `T` and holds a valid `T::Original`, which
+/// `Ipc` objects store at offset 0.
+pub(crate) fn check<T: crate::Ipc>(data: &[u8]) -> Result<(), ValidationError>
+where
+    T::Original: IpcValidate,
+{
+    if data.len() < core::mem::size_of::<T>() || data.len() < core::mem::size_of::<T::Original>() {
+        return Err(ValidationError::TooShort);
+    }
+    T::Original::validate(&data[..core::mem::size_of::<T::Original>()])
+}
@@ -1,12 +1,28 @@
 use std::mem::MaybeUninit;
 
 pub struct Vec<T, const N: usize> {
-    length: usize,
-    buffer: [MaybeUninit<T>; N],
+    pub(crate) length: usize,
+    pub(crate) buffer: [MaybeUninit<T>; N],
 }
 
 unsafe impl<T, const N: usize> crate::IpcSafe for Vec<T, N> {}
 
+unsafe impl<T: crate::IpcValidate, const N: usize> crate::IpcValidate for Vec<T, N> {
+    fn validate(data: &[u8]) -> Result<(), crate::ValidationError> {
+        let length_offset = core::mem::offset_of!(Self, length);
+        let length = crate::validate::read_usize(data, length_offset)?;
+        if length > N {
+            return Err(crate::ValidationError::Length { offset: length_offset });
+        }
+        // Only the initialized elements need to be valid.
+        let buffer = core::mem::offset_of!(Self, buffer);
+        for i in 0..length {
+            crate::validate::validate_at::<T>(data, buffer + i * core::mem::size_of::<T>())?;
+        }
+        Ok(())
+    }
+}
+
 impl<T, const N: usize> Vec<T, N> {
     pub fn new() -> Self {
         let buffer = [const { MaybeUninit::uninit() }; N];
@@ -0,0 +1,288 @@
+//! Keyboard layout descriptions for the Precursor keyboard.
+//!
+//! A layout is a text file that maps each key of the matrix to the characters it types. The built-in
+//! layouts are written in it (see `layouts/`), and user layouts are stored in the PDDB and loaded by the
+//! keyboard server at runtime. The `kbd-layout` tool checks a layout on the host before it is copied to
+//! a device.
+//!
+//! ```text
+//! # comments take up a whole line
+//! name Spanish
+//! # optional: the left shift key (orange) selects the alt column instead of the shift column
+//! left-shift alt
+//! #   row col  key shift hold alt
+//! key 1 2      e   E     €    ´
+//! # a dead key, followed by the pairs it composes: typing ´ and then a gives á
+//! dead ´  a á  e é  A Á  E É
+//! ```
+//!
+//! Each `key` line gives the characters typed by the key alone, after tapping a shift key, when it is
+//! held down, and after tapping the alt shift key. Trailing columns may be left out. A key with no
+//! `hold` character repeats while it is held. Characters are written as themselves, as `U+` followed by
+//! the hex code point, or with one of these names: `none`, `space`, `tab`, `bs` (backspace), `cr`
+//! (return), `si` (shift in, sent by the shift keys), `so` (shift out) and `f1` to `f4`.
+//!
+//! A character listed in a `dead` line doesn't type anything by itself: it waits for the next one, and
+//! types the composition for that pair. A space types the dead key itself, a backspace cancels it, and
+//! anything else types the dead key followed by the character.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Rows in the key matrix
+pub const ROWS: u8 = 9;
+/// Columns in the key matrix
+pub const COLS: u8 = 10;
+
+/// The built-in layouts, in the order of `keyboard::KeyMap`
+pub const QWERTY: &str = include_str!("../layouts/qwerty.txt");
+pub const AZERTY: &str = include_str!("../layouts/azerty.txt");
+pub const QWERTZ: &str = include_str!("../layouts/qwertz.txt");
+pub const DVORAK: &str = include_str!("../layouts/dvorak.txt");
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ScanCode {
+    /// base key value
+    pub key: Option<char>,
+    /// tap blue shift key, then key
+    pub shift: Option<char>,
+    /// hold blue shift key, then key
+    pub hold: Option<char>,
+    /// hold orange shift key, then key
+    pub alt: Option<char>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number of the offending line
+    pub line: usize,
+    pub reason: String,
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub name: String,
+    /// the left shift key selects the `alt` column, and the right one the `shift` column. Otherwise both
+    /// select the `shift` column.
+    pub left_shift_alt: bool,
+    keys: BTreeMap<(u8, u8), ScanCode>,
+    /// dead key -> (next character -> composed character)
+    dead: BTreeMap<char, BTreeMap<char, char>>,
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Result<Layout, ParseError> {
+        let mut name: Option<String> = None;
+        let mut left_shift_alt = false;
+        let mut keys = BTreeMap::new();
+        let mut dead: BTreeMap<char, BTreeMap<char, char>> = BTreeMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let err = |reason: String| ParseError { line: index + 1, reason };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut fields = rest.split_whitespace();
+            match directive {
+                "name" => {
+                    if rest.trim().is_empty() {
+                        return Err(err("the name is empty".to_string()));
+                    }
+                    name = Some(rest.trim().to_string());
+                }
+                "left-shift" => match rest.trim() {
+                    "shift" => left_shift_alt = false,
+                    "alt" => left_shift_alt = true,
+                    other => {
+                        return Err(err(format!("left-shift must be `shift` or `alt`, not `{}`", other)));
+                    }
+                },
+                "key" => {
+                    let r = parse_index(fields.next(), ROWS).map_err(|e| err(format!("row {}", e)))?;
+                    let c = parse_index(fields.next(), COLS).map_err(|e| err(format!("column {}", e)))?;
+                    let mut chars = [None; 4];
+                    for slot in chars.iter_mut() {
+                        if let Some(token) = fields.next() {
+                            *slot = parse_char(token).map_err(err)?;
+                        }
+                    }
+                    if fields.next().is_some() {
+                        return Err(err("a key has at most four characters: key shift hold alt".to_string()));
+                    }
+                    let [key, shift, hold, alt] = chars;
+                    if keys.insert((r, c), ScanCode { key, shift, hold, alt }).is_some() {
+                        return Err(err(format!("key {} {} is defined twice", r, c)));
+                    }
+                }
+                "dead" => {
+                    let key = match fields.next().map(parse_char) {
+                        Some(Ok(Some(key))) => key,
+                        Some(Err(e)) => return Err(err(e)),
+                        _ => return Err(err("the dead key is missing".to_string())),
+                    };
+                    let table = dead.entry(key).or_default();
+                    while let Some(base) = fields.next() {
+                        let Some(composed) = fields.next() else {
+                            return Err(err(format!("`{}` has nothing to compose to", base)));
+                        };
+                        match (parse_char(base).map_err(err)?, parse_char(composed).map_err(err)?) {
+                            (Some(base), Some(composed)) => {
+                                if table.insert(base, composed).is_some() {
+                                    return Err(err(format!("{} {} is composed twice", key, base)));
+                                }
+                            }
+                            _ => return Err(err("compositions can't use `none`".to_string())),
+                        }
+                    }
+                }
+                other => return Err(err(format!("unknown directive `{}`", other))),
+            }
+        }
+        let Some(name) = name else {
+            return Err(ParseError { line: 0, reason: "the layout has no name".to_string() });
+        };
+        Ok(Layout { name, left_shift_alt, keys, dead })
+    }
+
+    /// The characters typed by the key at `r`, `c`
+    pub fn scan_code(&self, r: u8, c: u8) -> ScanCode { self.keys.get(&(r, c)).copied().unwrap_or_default() }
+
+    pub fn is_dead(&self, c: char) -> bool { self.dead.contains_key(&c) }
+
+    /// Runs a typed character through the dead keys, pushing what it types to `out`. `pending` is the dead
+    /// key waiting for its second character, if any.
+    pub fn compose(&self, pending: &mut Option<char>, c: char, out: &mut Vec<char>) {
+        match pending.take() {
+            Some(dead) => {
+                if let Some(&composed) = self.dead.get(&dead).and_then(|table| table.get(&c)) {
+                    out.push(composed);
+                } else if c == ' ' {
+                    out.push(dead);
+                } else if c != '\u{8}' {
+                    out.push(dead);
+                    self.compose(pending, c, out);
+                }
+            }
+            None if self.is_dead(c) => *pending = Some(c),
+            None => out.push(c),
+        }
+    }
+
+    /// Things that are legal but probably mistakes, e.g. printable ASCII characters that can't be typed,
+    /// which would make some passwords impossible to enter
+    pub fn check(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let typed: Vec<char> = self
+            .keys
+            .values()
+            .flat_map(|code| [code.key, code.shift, code.hold, code.alt])
+            .flatten()
+            .collect();
+        let composed: Vec<char> = self
+            .dead
+            .iter()
+            .filter(|(dead, _)| typed.contains(dead))
+            .flat_map(|(_, table)| table.values().copied())
+            .collect();
+        for c in (' '..='~').chain(['\u{8}', '\r']) {
+            if !typed.contains(&c) && !composed.contains(&c) {
+                warnings.push(format!("no key types {:?}", c));
+            }
+        }
+        for dead in self.dead.keys() {
+            if !typed.contains(dead) {
+                warnings.push(format!("no key types the dead key {:?}", dead));
+            }
+        }
+        warnings
+    }
+}
+
+fn parse_index(token: Option<&str>, limit: u8) -> Result<u8, String> {
+    match token.map(|t| t.parse::<u8>()) {
+        Some(Ok(index)) if index < limit => Ok(index),
+        Some(Ok(index)) => Err(format!("{} is out of range (0-{})", index, limit - 1)),
+        Some(Err(_)) => Err(format!("`{}` is not a number", token.unwrap_or_default())),
+        None => Err("is missing".to_string()),
+    }
+}
+
+fn parse_char(token: &str) -> Result<Option<char>, String> {
+    let mut chars = token.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Some(c));
+    }
+    let c = match token {
+        "none" => return Ok(None),
+        "space" => ' ',
+        "tab" => '\t',
+        "bs" => '\u{8}',
+        "cr" => '\r',
+        "so" => '\u{e}',
+        "si" => '\u{f}',
+        "f1" => '\u{11}',
+        "f2" => '\u{12}',
+        "f3" => '\u{13}',
+        "f4" => '\u{14}',
+        _ => match token.strip_prefix("U+").map(|hex| u32::from_str_radix(hex, 16)) {
+            Some(Ok(code)) => char::from_u32(code).ok_or(format!("{} is not a character", token))?,
+            _ => return Err(format!("`{}` is not a character", token)),
+        },
+    };
+    Ok(Some(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_layouts_parse() {
+        let examples =
+            [include_str!("../layouts/examples/nordic.txt"), include_str!("../layouts/examples/spanish.txt")];
+        for text in [QWERTY, AZERTY, QWERTZ, DVORAK].into_iter().chain(examples) {
+            let layout = Layout::parse(text).unwrap();
+            assert_eq!(layout.check(), Vec::<String>::new(), "{}", layout.name);
+        }
+        let azerty = Layout::parse(AZERTY).unwrap();
+        assert!(azerty.left_shift_alt);
+        assert_eq!(
+            azerty.scan_code(3, 0),
+            ScanCode { key: Some('\u{8}'), shift: Some('\u{8}'), hold: None, alt: Some('\u{8}') }
+        );
+        assert_eq!(azerty.scan_code(0, 5), ScanCode::default());
+    }
+
+    #[test]
+    fn dead_keys() {
+        let layout = Layout::parse("name test\nkey 1 2 e E € ´\ndead ´ e é E É\ndead ¨ u ü").unwrap();
+        let mut pending = None;
+        let mut out = Vec::new();
+        for c in "´e´E´ ´x´¨u´\u{8}a".chars() {
+            layout.compose(&mut pending, c, &mut out);
+        }
+        assert_eq!(out.into_iter().collect::<String>(), "éÉ´´x´üa");
+        assert_eq!(layout.check().iter().filter(|w| w.contains("dead key")).count(), 1);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let err = Layout::parse("name test\n\nkey 9 0 a").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(Layout::parse("name test\nkey 0 0 a\nkey 0 0 b").is_err());
+        assert!(Layout::parse("name test\nkey 0 0 ab").is_err());
+        assert!(Layout::parse("name test\ndead ´ a").is_err());
+        assert!(Layout::parse("key 0 0 a").is_err());
+        assert_eq!(
+            Layout::parse("name t\nkey 0 0 U+e9 space none").unwrap().scan_code(0, 0).shift,
+            Some(' ')
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Saves the records kept by the log server to the PDDB, so the ones leading up to a crash or a panic can
+//! be read back after the reboot.
+//!
+//! `persist()` runs forever on a thread of its own. Once the PDDB is mounted, it drains the log server's
+//! store into the `sys.log` dictionary in chunks, oldest chunks being deleted once there are more than
+//! `MAX_SAVED_CHUNKS`. A chunk is written as soon as a panic comes in, so a panic is saved even if the
+//! system goes down right after.
+
+use std::io::{Read, Write};
+
+pub use log_server::StoredRecord;
+
+pub const LOG_DICT: &str = "sys.log";
+/// Each chunk holds up to a page of records
+pub const MAX_SAVED_CHUNKS: usize = 16;
+/// Records are saved once this many have come in. Every chunk is a write to flash, so this trades
+/// the records lost to a hard reset against flash wear.
+const BATCH_RECORDS: usize = 64;
+
+/// Saves records from the log server as they come in. Never returns unless the log server can't be read.
+pub fn persist() {
+    let pddb = pddb::Pddb::new();
+    pddb.is_mounted_blocking();
+    let mut chunks = chunk_numbers(&pddb);
+    let mut next_chunk = chunks.last().map(|c| c + 1).unwrap_or(0);
+    let mut from = 0;
+    loop {
+        let page = match log_server::store_read_raw(from, Some(BATCH_RECORDS)) {
+            Ok(page) => page,
+            Err(e) => {
+                log::error!("couldn't read the log store, records will not be saved: {:?}", e);
+                return;
+            }
+        };
+        from = page.next_seq;
+        if page.data.is_empty() {
+            continue;
+        }
+        while chunks.len() >= MAX_SAVED_CHUNKS {
+            let oldest = chunks.remove(0);
+            pddb.delete_key(LOG_DICT, &chunk_name(oldest), None).ok();
+        }
+        let key_name = chunk_name(next_chunk);
+        match pddb.get(LOG_DICT, &key_name, None, true, true, Some(page.data.len()), None::<fn()>) {
+            Ok(mut key) => {
+                if key.write_all(&page.data).is_err() {
+                    log::error!("couldn't write saved log {}", key_name);
+                }
+            }
+            Err(e) => log::error!("couldn't create saved log key {}: {:?}", key_name, e),
+        }
+        pddb.sync().ok();
+        chunks.push(next_chunk);
+        next_chunk += 1;
+    }
+}
+
+/// Chunk names sort the same as their numbers, so they list in order in the PDDB browser
+fn chunk_name(chunk: u32) -> String { format!("{:08}", chunk) }
+
+fn chunk_numbers(pddb: &pddb::Pddb) -> Vec<u32> {
+    let mut chunks: Vec<u32> = pddb
+        .list_keys(LOG_DICT, None)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|k| k.parse::<u32>().ok())
+        .collect();
+    chunks.sort_unstable();
+    chunks
+}
+
+/// Reads back the saved records, oldest first. Records from the current boot are included once they've
+/// been saved; sequence numbers start over at every boot, so a drop in `seq` marks a reboot.
+pub fn read_saved(pddb: &pddb::Pddb) -> Vec<StoredRecord> {
+    let mut records = Vec::new();
+    for chunk in chunk_numbers(pddb) {
+        let mut data = Vec::new();
+        match pddb.get(LOG_DICT, &chunk_name(chunk), None, false, false, None, None::<fn()>) {
+            Ok(mut key) => {
+                if key.read_to_end(&mut data).is_err() {
+                    continue;
+                }
+            }
+            Err(_) => continue,
+        }
+        records.append(&mut log_server::unpack_stored_records(&data));
+    }
+    records
+}
+
+/// Deletes all saved records
+pub fn clear_saved(pddb: &pddb::Pddb) -> std::io::Result<()> {
+    pddb.delete_dict(LOG_DICT, None)?;
+    pddb.sync()
+}
@@ -0,0 +1,130 @@
+//! ISO 7816-4 command and response APDUs.
+
+/// A status word, returned as the last two bytes of every response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Status(pub u16);
+
+impl Status {
+    pub const AUTH_BLOCKED: Status = Status(0x6983);
+    pub const CLA_NOT_SUPPORTED: Status = Status(0x6E00);
+    pub const CONDITIONS_NOT_SATISFIED: Status = Status(0x6985);
+    pub const FILE_NOT_FOUND: Status = Status(0x6A82);
+    pub const INCORRECT_DATA: Status = Status(0x6A80);
+    pub const INS_NOT_SUPPORTED: Status = Status(0x6D00);
+    pub const LAST_COMMAND_EXPECTED: Status = Status(0x6883);
+    pub const MEMORY_FAILURE: Status = Status(0x6581);
+    /// Pinpad entry was cancelled by the user (PC/SC part 10).
+    pub const PINPAD_CANCELLED: Status = Status(0x6401);
+    /// The new PIN and its confirmation didn't match (PC/SC part 10).
+    pub const PINPAD_MISMATCH: Status = Status(0x6402);
+    /// A PIN entered on the pinpad was too short or too long (PC/SC part 10).
+    pub const PINPAD_WRONG_LENGTH: Status = Status(0x6403);
+    pub const REFERENCED_DATA_NOT_FOUND: Status = Status(0x6A88);
+    pub const SECURITY_STATUS_NOT_SATISFIED: Status = Status(0x6982);
+    pub const SUCCESS: Status = Status(0x9000);
+    /// The application is in the termination state.
+    pub const TERMINATED: Status = Status(0x6285);
+    pub const WRONG_LENGTH: Status = Status(0x6700);
+    pub const WRONG_P1P2: Status = Status(0x6B00);
+
+    /// More response data can be fetched with GET RESPONSE.
+    pub fn bytes_remaining(remaining: usize) -> Status { Status(0x6100 | remaining.min(0xFF) as u16) }
+
+    /// A PIN did not verify; `tries` attempts are left.
+    pub fn verify_failed(tries: u8) -> Status { Status(0x63C0 | (tries & 0xF) as u16) }
+
+    pub fn to_bytes(self) -> [u8; 2] { self.0.to_be_bytes() }
+}
+
+/// A command APDU, in either short or extended form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+    /// Maximum length of the expected response, or `None` if no response data is expected.
+    pub le: Option<usize>,
+    /// The command was encoded with extended length fields.
+    pub extended: bool,
+}
+
+impl Command {
+    pub fn new(cla: u8, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Self {
+        Command { cla, ins, p1, p2, data: data.to_vec(), le: None, extended: false }
+    }
+
+    /// Decode the four ISO 7816-4 cases, in both their short and extended forms.
+    pub fn parse(apdu: &[u8]) -> Result<Command, Status> {
+        if apdu.len() < 4 {
+            return Err(Status::WRONG_LENGTH);
+        }
+        let mut cmd = Command::new(apdu[0], apdu[1], apdu[2], apdu[3], &[]);
+        let body = &apdu[4..];
+        match body.len() {
+            // case 1
+            0 => {}
+            // case 2, short
+            1 => cmd.le = Some(if body[0] == 0 { 256 } else { body[0] as usize }),
+            _ if body[0] != 0 => {
+                let lc = body[0] as usize;
+                match body.len() - 1 {
+                    // case 3, short
+                    n if n == lc => {}
+                    // case 4, short
+                    n if n == lc + 1 => {
+                        cmd.le = Some(if body[lc + 1] == 0 { 256 } else { body[lc + 1] as usize })
+                    }
+                    _ => return Err(Status::WRONG_LENGTH),
+                }
+                cmd.data = body[1..1 + lc].to_vec();
+            }
+            // case 2, extended
+            3 => {
+                let le = u16::from_be_bytes([body[1], body[2]]) as usize;
+                cmd.le = Some(if le == 0 { 65536 } else { le });
+                cmd.extended = true;
+            }
+            n if n > 3 => {
+                let lc = u16::from_be_bytes([body[1], body[2]]) as usize;
+                match n - 3 {
+                    // case 3, extended
+                    m if m == lc => {}
+                    // case 4, extended
+                    m if m == lc + 2 => {
+                        let le = u16::from_be_bytes([body[3 + lc], body[4 + lc]]) as usize;
+                        cmd.le = Some(if le == 0 { 65536 } else { le });
+                    }
+                    _ => return Err(Status::WRONG_LENGTH),
+                }
+                cmd.data = body[3..3 + lc].to_vec();
+                cmd.extended = true;
+            }
+            _ => return Err(Status::WRONG_LENGTH),
+        }
+        Ok(cmd)
+    }
+
+    /// The command is one link of a chain, and more data follows in the next command.
+    pub fn is_chained(&self) -> bool { self.cla & 0x10 != 0 }
+
+    /// Encode the command in its short form, which can carry up to 255 bytes of data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut apdu = vec![self.cla, self.ins, self.p1, self.p2];
+        if !self.data.is_empty() {
+            apdu.push(self.data.len() as u8);
+            apdu.extend_from_slice(&self.data);
+        }
+        if let Some(le) = self.le {
+            apdu.push(if le >= 256 { 0 } else { le as u8 });
+        }
+        apdu
+    }
+}
+
+/// Append the status word to `data`, producing a response APDU.
+pub fn response(mut data: Vec<u8>, status: Status) -> Vec<u8> {
+    data.extend_from_slice(&status.to_bytes());
+    data
+}
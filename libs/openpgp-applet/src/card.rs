@@ -0,0 +1,770 @@
+//! The OpenPGP card application, as described by version 3.4 of the functional specification.
+//!
+//! Only Curve25519 keys are supported: Ed25519 for the signature and authentication keys, and
+//! X25519 for the decryption key. Keys are generated on the card; key import is not supported.
+
+use ed25519_dalek::{Signer, SigningKey};
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::apdu::{Command, Status, response};
+use crate::tlv;
+
+/// Registered application provider ID and proprietary application identifier of OpenPGP.
+const RID_PIX: [u8; 6] = [0xD2, 0x76, 0x00, 0x01, 0x24, 0x01];
+const VERSION: [u8; 2] = [0x03, 0x04];
+/// Manufacturer IDs from FF00 upwards are reserved for test cards and unregistered implementations.
+const MANUFACTURER: [u8; 2] = [0xFF, 0xFE];
+/// Compact-TLV card capabilities: command chaining, no extended Lc/Le. Operational life cycle.
+pub const HISTORICAL_BYTES: [u8; 8] = [0x00, 0x73, 0x00, 0x00, 0x80, 0x05, 0x90, 0x00];
+/// GET CHALLENGE and a changeable PW status; 255 byte challenges and private DOs.
+const EXTENDED_CAPABILITIES: [u8; 10] = [0x50, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00];
+const ED25519_ATTRIBUTES: [u8; 10] = [0x16, 0x2B, 0x06, 0x01, 0x04, 0x01, 0xDA, 0x47, 0x0F, 0x01];
+const CV25519_ATTRIBUTES: [u8; 11] = [0x12, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01];
+
+const INS_SELECT: u8 = 0xA4;
+const INS_GET_DATA: u8 = 0xCA;
+const INS_PUT_DATA: u8 = 0xDA;
+const INS_VERIFY: u8 = 0x20;
+const INS_CHANGE_REFERENCE_DATA: u8 = 0x24;
+const INS_RESET_RETRY_COUNTER: u8 = 0x2C;
+const INS_GENERATE_ASYMMETRIC_KEY_PAIR: u8 = 0x47;
+const INS_PSO: u8 = 0x2A;
+const INS_INTERNAL_AUTHENTICATE: u8 = 0x88;
+const INS_GET_CHALLENGE: u8 = 0x84;
+const INS_GET_RESPONSE: u8 = 0xC0;
+const INS_TERMINATE_DF: u8 = 0xE6;
+const INS_ACTIVATE_FILE: u8 = 0x44;
+
+const MAX_PIN_LEN: usize = 127;
+const MAX_TRIES: u8 = 3;
+const MAX_CHALLENGE: usize = 255;
+/// Upper bound on the data accumulated over a chain of commands.
+const MAX_CHAIN: usize = 4096;
+const MAX_SIGNATURE_COUNT: u32 = 0xFF_FFFF;
+
+const PIN_TRIES: &str = "pin.tries";
+const PW1_MULTIPLE_SIGNATURES: &str = "pin.multisig";
+const SIGNATURE_COUNT: &str = "sigcount";
+const TERMINATED: &str = "terminated";
+
+/// Data objects that are written verbatim with PUT DATA, and their maximum lengths.
+const DATA_OBJECTS: &[(u16, usize)] = &[
+    (0x005B, 39),  // name
+    (0x005E, 254), // login data
+    (0x5F2D, 8),   // language preference
+    (0x5F35, 1),   // sex
+    (0x5F50, 255), // URL
+    (0x00C7, 20),  // signature key fingerprint
+    (0x00C8, 20),  // decryption key fingerprint
+    (0x00C9, 20),  // authentication key fingerprint
+    (0x00CA, 20),  // CA fingerprints
+    (0x00CB, 20),
+    (0x00CC, 20),
+    (0x00CE, 4), // key generation timestamps
+    (0x00CF, 4),
+    (0x00D0, 4),
+];
+
+/// Persistent storage for the card's keys, PINs and data objects.
+pub trait Store {
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+    fn set(&mut self, name: &str, value: &[u8]) -> Result<(), StoreError>;
+    /// Removing a name that isn't present is not an error.
+    fn remove(&mut self, name: &str) -> Result<(), StoreError>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StoreError;
+
+/// The PIN references used by VERIFY and CHANGE REFERENCE DATA.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PinRef {
+    /// PW1, verified for signing.
+    Pw1Sign = 0x81,
+    /// PW1, verified for decryption and authentication.
+    Pw1 = 0x82,
+    /// The admin PIN.
+    Pw3 = 0x83,
+}
+
+impl PinRef {
+    pub fn from_p2(p2: u8) -> Option<PinRef> {
+        match p2 {
+            0x81 => Some(PinRef::Pw1Sign),
+            0x82 => Some(PinRef::Pw1),
+            0x83 => Some(PinRef::Pw3),
+            _ => None,
+        }
+    }
+
+    fn pin(self) -> Pin {
+        match self {
+            PinRef::Pw1Sign | PinRef::Pw1 => Pin::User,
+            PinRef::Pw3 => Pin::Admin,
+        }
+    }
+
+    fn index(self) -> usize { self as usize - PinRef::Pw1Sign as usize }
+}
+
+/// What the user is being asked to enter on the device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PinPrompt {
+    Current(PinRef),
+    New(PinRef),
+    Confirm(PinRef),
+}
+
+/// Collects PINs on the device itself, so they never cross the USB link.
+pub trait PinEntry {
+    /// Returns `None` if the user cancelled.
+    fn request(&mut self, prompt: PinPrompt) -> Option<String>;
+}
+
+/// The secure PIN entry operations a reader may ask for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PinPadOp {
+    Verify,
+    Modify,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Pin {
+    User,
+    Reset,
+    Admin,
+}
+
+impl Pin {
+    fn name(self) -> &'static str {
+        match self {
+            Pin::User => "pin.user",
+            Pin::Reset => "pin.reset",
+            Pin::Admin => "pin.admin",
+        }
+    }
+
+    /// Position of the retry counter, both in storage and in the PW status bytes.
+    fn index(self) -> usize {
+        match self {
+            Pin::User => 0,
+            Pin::Reset => 1,
+            Pin::Admin => 2,
+        }
+    }
+
+    fn min_len(self) -> usize {
+        match self {
+            Pin::User => 6,
+            Pin::Reset | Pin::Admin => 8,
+        }
+    }
+
+    fn default(self) -> Option<&'static [u8]> {
+        match self {
+            Pin::User => Some(b"123456"),
+            Pin::Reset => None,
+            Pin::Admin => Some(b"12345678"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum KeyRef {
+    Sig,
+    Dec,
+    Aut,
+}
+
+impl KeyRef {
+    /// Decode the control reference template that GENERATE ASYMMETRIC KEY PAIR carries.
+    fn from_crt(data: &[u8]) -> Option<KeyRef> {
+        match tlv::next(data)?.0 {
+            0xB6 => Some(KeyRef::Sig),
+            0xB8 => Some(KeyRef::Dec),
+            0xA4 => Some(KeyRef::Aut),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            KeyRef::Sig => "key.sig",
+            KeyRef::Dec => "key.dec",
+            KeyRef::Aut => "key.aut",
+        }
+    }
+
+    fn attributes(self) -> &'static [u8] {
+        match self {
+            KeyRef::Sig | KeyRef::Aut => &ED25519_ATTRIBUTES,
+            KeyRef::Dec => &CV25519_ATTRIBUTES,
+        }
+    }
+
+    fn from_attributes_tag(tag: u16) -> Option<KeyRef> {
+        match tag {
+            0x00C1 => Some(KeyRef::Sig),
+            0x00C2 => Some(KeyRef::Dec),
+            0x00C3 => Some(KeyRef::Aut),
+            _ => None,
+        }
+    }
+}
+
+type CardResult = Result<Vec<u8>, Status>;
+
+pub struct OpenPgpCard<S, R> {
+    store: S,
+    rng: R,
+    serial: [u8; 4],
+    selected: bool,
+    /// Verification state, indexed by `PinRef::index()`.
+    verified: [bool; 3],
+    /// INS, P1, P2 and the data accumulated so far of an unfinished command chain.
+    chain: Option<(u8, u8, u8, Vec<u8>)>,
+    /// Response data not yet collected with GET RESPONSE.
+    pending: Vec<u8>,
+}
+
+impl<S: Store, R: RngCore + CryptoRng> OpenPgpCard<S, R> {
+    pub fn new(store: S, rng: R, serial: [u8; 4]) -> Self {
+        OpenPgpCard {
+            store,
+            rng,
+            serial,
+            selected: false,
+            verified: [false; 3],
+            chain: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// The full application identifier, which is what GnuPG derives the card serial number from.
+    pub fn aid(&self) -> [u8; 16] {
+        let mut aid = [0u8; 16];
+        aid[..6].copy_from_slice(&RID_PIX);
+        aid[6..8].copy_from_slice(&VERSION);
+        aid[8..10].copy_from_slice(&MANUFACTURER);
+        aid[10..14].copy_from_slice(&self.serial);
+        aid
+    }
+
+    pub fn store(&self) -> &S { &self.store }
+
+    /// Forget all session state, as happens when the reader powers the card down.
+    pub fn reset(&mut self) {
+        self.selected = false;
+        self.verified = [false; 3];
+        self.chain = None;
+        self.pending.clear();
+    }
+
+    /// Process one command APDU and return the response APDU.
+    pub fn process(&mut self, apdu: &[u8]) -> Vec<u8> {
+        let mut cmd = match Command::parse(apdu) {
+            Ok(cmd) => cmd,
+            Err(status) => return response(Vec::new(), status),
+        };
+        if cmd.ins == INS_GET_RESPONSE && self.chain.is_none() {
+            let pending = std::mem::take(&mut self.pending);
+            return self.respond(pending, cmd.le);
+        }
+        self.pending.clear();
+
+        if let Some((ins, p1, p2, mut data)) = self.chain.take() {
+            if (cmd.ins, cmd.p1, cmd.p2) != (ins, p1, p2) {
+                return response(Vec::new(), Status::LAST_COMMAND_EXPECTED);
+            }
+            data.extend_from_slice(&cmd.data);
+            cmd.data = data;
+        }
+        if cmd.data.len() > MAX_CHAIN {
+            return response(Vec::new(), Status::WRONG_LENGTH);
+        }
+        if cmd.is_chained() {
+            self.chain = Some((cmd.ins, cmd.p1, cmd.p2, cmd.data));
+            return response(Vec::new(), Status::SUCCESS);
+        }
+
+        match self.dispatch(&cmd) {
+            Ok(data) => self.respond(data, cmd.le),
+            Err(status) => response(Vec::new(), status),
+        }
+    }
+
+    /// Run a VERIFY or CHANGE REFERENCE DATA whose PINs are collected by `entry` rather than
+    /// sent by the host. `p2` is the PIN reference from the command the reader would have sent.
+    pub fn pinpad(&mut self, op: PinPadOp, p2: u8, entry: &mut impl PinEntry) -> Vec<u8> {
+        match self.pinpad_inner(op, p2, entry) {
+            Ok(data) => response(data, Status::SUCCESS),
+            Err(status) => response(Vec::new(), status),
+        }
+    }
+
+    fn pinpad_inner(&mut self, op: PinPadOp, p2: u8, entry: &mut impl PinEntry) -> CardResult {
+        if self.terminated() {
+            return Err(Status::TERMINATED);
+        }
+        if !self.selected {
+            return Err(Status::CONDITIONS_NOT_SATISFIED);
+        }
+        let pin_ref = PinRef::from_p2(p2).ok_or(Status::WRONG_P1P2)?;
+        let current = entry.request(PinPrompt::Current(pin_ref)).ok_or(Status::PINPAD_CANCELLED)?;
+        match op {
+            PinPadOp::Verify => {
+                self.verified[pin_ref.index()] = false;
+                self.check_pin(pin_ref.pin(), current.as_bytes())?;
+                self.verified[pin_ref.index()] = true;
+            }
+            PinPadOp::Modify => {
+                let new = entry.request(PinPrompt::New(pin_ref)).ok_or(Status::PINPAD_CANCELLED)?;
+                let confirm = entry.request(PinPrompt::Confirm(pin_ref)).ok_or(Status::PINPAD_CANCELLED)?;
+                if new != confirm {
+                    return Err(Status::PINPAD_MISMATCH);
+                }
+                if !(pin_ref.pin().min_len()..=MAX_PIN_LEN).contains(&new.len()) {
+                    return Err(Status::PINPAD_WRONG_LENGTH);
+                }
+                self.change_pin(pin_ref.pin(), current.as_bytes(), new.as_bytes())?;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    /// Send as much of `data` as the host asked for, and keep the rest for GET RESPONSE.
+    fn respond(&mut self, mut data: Vec<u8>, le: Option<usize>) -> Vec<u8> {
+        let max = le.unwrap_or(256);
+        if data.len() > max {
+            self.pending = data.split_off(max);
+            let remaining = self.pending.len();
+            return response(data, Status::bytes_remaining(remaining));
+        }
+        response(data, Status::SUCCESS)
+    }
+
+    fn dispatch(&mut self, cmd: &Command) -> CardResult {
+        if cmd.cla & !0x10 != 0 {
+            return Err(Status::CLA_NOT_SUPPORTED);
+        }
+        let tag = u16::from_be_bytes([cmd.p1, cmd.p2]);
+        match cmd.ins {
+            INS_SELECT => self.select(cmd),
+            INS_ACTIVATE_FILE => self.activate(),
+            _ if self.terminated() => Err(Status::TERMINATED),
+            _ if !self.selected => Err(Status::CONDITIONS_NOT_SATISFIED),
+            INS_GET_DATA => self.get_data(tag),
+            INS_PUT_DATA => self.put_data(tag, &cmd.data),
+            INS_VERIFY => self.verify(cmd),
+            INS_CHANGE_REFERENCE_DATA => self.change_reference_data(cmd),
+            INS_RESET_RETRY_COUNTER => self.reset_retry_counter(cmd),
+            INS_GENERATE_ASYMMETRIC_KEY_PAIR => self.generate(cmd),
+            INS_PSO => self.pso(cmd),
+            INS_INTERNAL_AUTHENTICATE => self.internal_authenticate(cmd),
+            INS_GET_CHALLENGE => self.get_challenge(cmd),
+            INS_TERMINATE_DF => self.terminate(cmd),
+            _ => Err(Status::INS_NOT_SUPPORTED),
+        }
+    }
+
+    fn select(&mut self, cmd: &Command) -> CardResult {
+        if cmd.p1 != 0x04 {
+            return Err(Status::WRONG_P1P2);
+        }
+        if !cmd.data.starts_with(&RID_PIX) {
+            return Err(Status::FILE_NOT_FOUND);
+        }
+        self.reset();
+        if self.terminated() {
+            return Err(Status::TERMINATED);
+        }
+        self.selected = true;
+        Ok(Vec::new())
+    }
+
+    /// ACTIVATE FILE only does something on a terminated card, which it returns to factory state.
+    fn activate(&mut self) -> CardResult {
+        if !self.terminated() {
+            return Ok(Vec::new());
+        }
+        let mut names = vec![PIN_TRIES, PW1_MULTIPLE_SIGNATURES, SIGNATURE_COUNT];
+        names.extend([Pin::User, Pin::Reset, Pin::Admin].iter().map(|pin| pin.name()));
+        names.extend([KeyRef::Sig, KeyRef::Dec, KeyRef::Aut].iter().map(|key| key.name()));
+        for name in names {
+            self.remove(name)?;
+        }
+        for &(tag, _) in DATA_OBJECTS {
+            self.remove(&do_name(tag))?;
+        }
+        // cleared last, so an interrupted reset can be retried
+        self.remove(TERMINATED)?;
+        self.reset();
+        log::info!("OpenPGP card returned to factory state");
+        Ok(Vec::new())
+    }
+
+    fn terminate(&mut self, cmd: &Command) -> CardResult {
+        if cmd.p1 != 0 || cmd.p2 != 0 {
+            return Err(Status::WRONG_P1P2);
+        }
+        // the admin PIN is not needed once it is blocked, so a card whose PINs are lost can be reset
+        if !self.verified[PinRef::Pw3.index()] && self.tries()[Pin::Admin.index()] != 0 {
+            return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+        }
+        self.put(TERMINATED, &[1])?;
+        self.reset();
+        Ok(Vec::new())
+    }
+
+    fn get_data(&self, tag: u16) -> CardResult {
+        let mut out = Vec::new();
+        match tag {
+            0x004F => out.extend_from_slice(&self.aid()),
+            0x0065 => {
+                for tag in [0x005B, 0x5F2D, 0x5F35] {
+                    tlv::write(&mut out, tag, &self.data_object(tag));
+                }
+            }
+            0x006E => {
+                tlv::write(&mut out, 0x004F, &self.aid());
+                tlv::write(&mut out, 0x5F52, &HISTORICAL_BYTES);
+                let mut discretionary = Vec::new();
+                for tag in 0x00C0..=0x00C6 {
+                    tlv::write(&mut discretionary, tag, &self.get_data(tag)?);
+                }
+                tlv::write(&mut discretionary, 0x00CD, &self.get_data(0x00CD)?);
+                tlv::write(&mut out, 0x0073, &discretionary);
+            }
+            0x007A => tlv::write(&mut out, 0x0093, &self.signature_count().to_be_bytes()[1..]),
+            0x5F52 => out.extend_from_slice(&HISTORICAL_BYTES),
+            0x00C0 => out.extend_from_slice(&EXTENDED_CAPABILITIES),
+            0x00C1..=0x00C3 => out.extend_from_slice(KeyRef::from_attributes_tag(tag).unwrap().attributes()),
+            0x00C4 => {
+                let multiple = self.store.get(PW1_MULTIPLE_SIGNATURES).map_or(0, |v| v[0]);
+                out.extend_from_slice(&[multiple, MAX_PIN_LEN as u8, MAX_PIN_LEN as u8, MAX_PIN_LEN as u8]);
+                out.extend_from_slice(&self.tries());
+            }
+            0x00C5 => out.extend(self.fixed_objects(0x00C7, 20)),
+            0x00C6 => out.extend(self.fixed_objects(0x00CA, 20)),
+            0x00CD => out.extend(self.fixed_objects(0x00CE, 4)),
+            tag if DATA_OBJECTS.iter().any(|&(t, _)| t == tag) => out = self.data_object(tag),
+            _ => return Err(Status::REFERENCED_DATA_NOT_FOUND),
+        }
+        Ok(out)
+    }
+
+    fn put_data(&mut self, tag: u16, data: &[u8]) -> CardResult {
+        if !self.verified[PinRef::Pw3.index()] {
+            return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+        }
+        match tag {
+            0x00C4 => match data.first() {
+                Some(&multiple) if multiple <= 1 => self.put(PW1_MULTIPLE_SIGNATURES, &[multiple])?,
+                _ => return Err(Status::INCORRECT_DATA),
+            },
+            // only the attributes already in use are accepted, optionally with the import format byte
+            0x00C1..=0x00C3 => {
+                let attributes = KeyRef::from_attributes_tag(tag).unwrap().attributes();
+                if !data.starts_with(attributes) || data.len() > attributes.len() + 1 {
+                    return Err(Status::INCORRECT_DATA);
+                }
+            }
+            0x00D3 => {
+                if data.is_empty() {
+                    self.remove(Pin::Reset.name())?;
+                    self.set_tries(Pin::Reset, 0)?;
+                } else {
+                    self.set_pin(Pin::Reset, data)?;
+                }
+            }
+            tag => {
+                let &(_, max_len) =
+                    DATA_OBJECTS.iter().find(|&&(t, _)| t == tag).ok_or(Status::REFERENCED_DATA_NOT_FOUND)?;
+                if data.len() > max_len {
+                    return Err(Status::WRONG_LENGTH);
+                }
+                if data.is_empty() {
+                    self.remove(&do_name(tag))?;
+                } else {
+                    self.put(&do_name(tag), data)?;
+                }
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn verify(&mut self, cmd: &Command) -> CardResult {
+        let pin_ref = PinRef::from_p2(cmd.p2).ok_or(Status::WRONG_P1P2)?;
+        match cmd.p1 {
+            0x00 => {}
+            0xFF if cmd.data.is_empty() => {
+                self.verified[pin_ref.index()] = false;
+                return Ok(Vec::new());
+            }
+            0xFF => return Err(Status::WRONG_LENGTH),
+            _ => return Err(Status::WRONG_P1P2),
+        }
+        if cmd.data.is_empty() {
+            // a status query, which doesn't cost a try
+            let tries = self.tries()[pin_ref.pin().index()];
+            return match (self.verified[pin_ref.index()], tries) {
+                (true, _) => Ok(Vec::new()),
+                (false, 0) => Err(Status::AUTH_BLOCKED),
+                (false, tries) => Err(Status::verify_failed(tries)),
+            };
+        }
+        self.verified[pin_ref.index()] = false;
+        self.check_pin(pin_ref.pin(), &cmd.data)?;
+        self.verified[pin_ref.index()] = true;
+        Ok(Vec::new())
+    }
+
+    fn change_reference_data(&mut self, cmd: &Command) -> CardResult {
+        if cmd.p1 != 0 {
+            return Err(Status::WRONG_P1P2);
+        }
+        let pin = match PinRef::from_p2(cmd.p2) {
+            Some(PinRef::Pw1Sign) => Pin::User,
+            Some(PinRef::Pw3) => Pin::Admin,
+            _ => return Err(Status::WRONG_P1P2),
+        };
+        let (old, new) = self.split_pins(pin, &cmd.data)?;
+        self.change_pin(pin, old, new)?;
+        Ok(Vec::new())
+    }
+
+    fn reset_retry_counter(&mut self, cmd: &Command) -> CardResult {
+        if cmd.p2 != PinRef::Pw1Sign as u8 {
+            return Err(Status::WRONG_P1P2);
+        }
+        match cmd.p1 {
+            0x00 => {
+                let (code, new) = self.split_pins(Pin::Reset, &cmd.data)?;
+                self.check_pin(Pin::Reset, code)?;
+                self.set_pin(Pin::User, new)?;
+            }
+            0x02 => {
+                if !self.verified[PinRef::Pw3.index()] {
+                    return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+                }
+                self.set_pin(Pin::User, &cmd.data)?;
+            }
+            _ => return Err(Status::WRONG_P1P2),
+        }
+        Ok(Vec::new())
+    }
+
+    fn generate(&mut self, cmd: &Command) -> CardResult {
+        let key = KeyRef::from_crt(&cmd.data).ok_or(Status::INCORRECT_DATA)?;
+        match (cmd.p1, cmd.p2) {
+            (0x80, 0x00) => {
+                if !self.verified[PinRef::Pw3.index()] {
+                    return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+                }
+                let mut secret = [0u8; 32];
+                self.rng.fill_bytes(&mut secret);
+                self.put(key.name(), &secret)?;
+                secret.fill(0);
+                if key == KeyRef::Sig {
+                    self.remove(SIGNATURE_COUNT)?;
+                }
+                log::info!("generated a new {:?} key", key);
+            }
+            (0x81, 0x00) => {}
+            _ => return Err(Status::WRONG_P1P2),
+        }
+        let secret = self.secret(key)?;
+        let public = match key {
+            KeyRef::Sig | KeyRef::Aut => SigningKey::from_bytes(&secret).verifying_key().to_bytes(),
+            KeyRef::Dec => PublicKey::from(&StaticSecret::from(secret)).to_bytes(),
+        };
+        let mut inner = Vec::new();
+        tlv::write(&mut inner, 0x0086, &public);
+        let mut out = Vec::new();
+        tlv::write(&mut out, 0x7F49, &inner);
+        Ok(out)
+    }
+
+    fn pso(&mut self, cmd: &Command) -> CardResult {
+        match (cmd.p1, cmd.p2) {
+            // COMPUTE DIGITAL SIGNATURE
+            (0x9E, 0x9A) => {
+                if !self.verified[PinRef::Pw1Sign.index()] {
+                    return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+                }
+                let signature = self.sign(KeyRef::Sig, &cmd.data)?;
+                let count = (self.signature_count() + 1).min(MAX_SIGNATURE_COUNT);
+                self.put(SIGNATURE_COUNT, &count.to_be_bytes()[1..])?;
+                if self.store.get(PW1_MULTIPLE_SIGNATURES).map_or(0, |v| v[0]) == 0 {
+                    self.verified[PinRef::Pw1Sign.index()] = false;
+                }
+                Ok(signature)
+            }
+            // DECIPHER: the data is the ephemeral public key, as A6 { 7F49 { 86 { point } } }
+            (0x80, 0x86) => {
+                if !self.verified[PinRef::Pw1.index()] {
+                    return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+                }
+                let point = tlv::find(&cmd.data, 0x00A6)
+                    .and_then(|v| tlv::find(v, 0x7F49))
+                    .and_then(|v| tlv::find(v, 0x0086))
+                    .ok_or(Status::INCORRECT_DATA)?;
+                // GnuPG may prefix the native point with 0x40
+                let point = match point {
+                    [0x40, rest @ ..] if rest.len() == 32 => rest,
+                    point => point,
+                };
+                let point: [u8; 32] = point.try_into().map_err(|_| Status::INCORRECT_DATA)?;
+                let secret = StaticSecret::from(self.secret(KeyRef::Dec)?);
+                Ok(secret.diffie_hellman(&PublicKey::from(point)).as_bytes().to_vec())
+            }
+            _ => Err(Status::WRONG_P1P2),
+        }
+    }
+
+    fn internal_authenticate(&mut self, cmd: &Command) -> CardResult {
+        if cmd.p1 != 0 || cmd.p2 != 0 {
+            return Err(Status::WRONG_P1P2);
+        }
+        if !self.verified[PinRef::Pw1.index()] {
+            return Err(Status::SECURITY_STATUS_NOT_SATISFIED);
+        }
+        self.sign(KeyRef::Aut, &cmd.data)
+    }
+
+    fn get_challenge(&mut self, cmd: &Command) -> CardResult {
+        let len = cmd.le.ok_or(Status::WRONG_LENGTH)?.min(MAX_CHALLENGE);
+        let mut challenge = vec![0u8; len];
+        self.rng.fill_bytes(&mut challenge);
+        Ok(challenge)
+    }
+
+    fn sign(&self, key: KeyRef, data: &[u8]) -> CardResult {
+        let signing_key = SigningKey::from_bytes(&self.secret(key)?);
+        Ok(signing_key.sign(data).to_bytes().to_vec())
+    }
+
+    fn secret(&self, key: KeyRef) -> Result<[u8; 32], Status> {
+        let secret = self.store.get(key.name()).ok_or(Status::REFERENCED_DATA_NOT_FOUND)?;
+        secret.as_slice().try_into().map_err(|_| Status::MEMORY_FAILURE)
+    }
+
+    fn terminated(&self) -> bool { self.store.get(TERMINATED).is_some() }
+
+    fn signature_count(&self) -> u32 {
+        match self.store.get(SIGNATURE_COUNT) {
+            Some(v) if v.len() == 3 => u32::from_be_bytes([0, v[0], v[1], v[2]]),
+            _ => 0,
+        }
+    }
+
+    fn data_object(&self, tag: u16) -> Vec<u8> { self.store.get(&do_name(tag)).unwrap_or_default() }
+
+    /// Three consecutive fixed-length data objects, zero-filled where unset.
+    fn fixed_objects(&self, first: u16, len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for tag in first..first + 3 {
+            let mut value = self.data_object(tag);
+            value.resize(len, 0);
+            out.extend_from_slice(&value);
+        }
+        out
+    }
+
+    fn tries(&self) -> [u8; 3] {
+        match self.store.get(PIN_TRIES) {
+            Some(v) if v.len() == 3 => [v[0], v[1], v[2]],
+            _ => [MAX_TRIES, 0, MAX_TRIES],
+        }
+    }
+
+    fn set_tries(&mut self, pin: Pin, count: u8) -> Result<(), Status> {
+        let mut tries = self.tries();
+        tries[pin.index()] = count;
+        self.put(PIN_TRIES, &tries)
+    }
+
+    /// PINs are stored as their length followed by a salted hash. The length is what lets
+    /// commands that concatenate an old and a new PIN be split apart.
+    fn pin_record(&self, pin: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.serial);
+        hasher.update(pin);
+        let mut record = vec![pin.len() as u8];
+        record.extend_from_slice(&hasher.finalize());
+        record
+    }
+
+    fn stored_pin(&self, pin: Pin) -> Option<Vec<u8>> {
+        self.store.get(pin.name()).or_else(|| pin.default().map(|default| self.pin_record(default)))
+    }
+
+    fn split_pins<'a>(&self, pin: Pin, data: &'a [u8]) -> Result<(&'a [u8], &'a [u8]), Status> {
+        if self.tries()[pin.index()] == 0 {
+            return Err(Status::AUTH_BLOCKED);
+        }
+        let len = self.stored_pin(pin).ok_or(Status::REFERENCED_DATA_NOT_FOUND)?[0] as usize;
+        if data.len() <= len {
+            return Err(Status::WRONG_LENGTH);
+        }
+        Ok(data.split_at(len))
+    }
+
+    /// The retry counter is decremented before comparing, so that cutting power in the middle of
+    /// a check can't be used to get unlimited guesses.
+    fn check_pin(&mut self, pin: Pin, candidate: &[u8]) -> Result<(), Status> {
+        let tries = self.tries()[pin.index()];
+        if tries == 0 {
+            return Err(Status::AUTH_BLOCKED);
+        }
+        let expected = self.stored_pin(pin).ok_or(Status::REFERENCED_DATA_NOT_FOUND)?;
+        self.set_tries(pin, tries - 1)?;
+        if bool::from(self.pin_record(candidate).ct_eq(&expected)) {
+            self.set_tries(pin, MAX_TRIES)
+        } else {
+            Err(Status::verify_failed(tries - 1))
+        }
+    }
+
+    fn change_pin(&mut self, pin: Pin, old: &[u8], new: &[u8]) -> Result<(), Status> {
+        self.check_pin(pin, old)?;
+        self.set_pin(pin, new)?;
+        match pin {
+            Pin::User => {
+                self.verified[PinRef::Pw1Sign.index()] = false;
+                self.verified[PinRef::Pw1.index()] = false;
+            }
+            _ => self.verified[PinRef::Pw3.index()] = false,
+        }
+        Ok(())
+    }
+
+    fn set_pin(&mut self, pin: Pin, new: &[u8]) -> Result<(), Status> {
+        if !(pin.min_len()..=MAX_PIN_LEN).contains(&new.len()) {
+            return Err(Status::WRONG_LENGTH);
+        }
+        let record = self.pin_record(new);
+        self.put(pin.name(), &record)?;
+        self.set_tries(pin, MAX_TRIES)
+    }
+
+    fn put(&mut self, name: &str, value: &[u8]) -> Result<(), Status> {
+        self.store.set(name, value).map_err(|_| {
+            log::error!("couldn't store {}", name);
+            Status::MEMORY_FAILURE
+        })
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), Status> {
+        self.store.remove(name).map_err(|_| {
+            log::error!("couldn't remove {}", name);
+            Status::MEMORY_FAILURE
+        })
+    }
+}
+
+fn do_name(tag: u16) -> String { format!("do.{:04X}", tag) }
@@ -0,0 +1,11 @@
+//! An OpenPGP card application that is independent of the transport carrying its APDUs, so it can
+//! be exercised on the host as well as behind the CCID interface of `usb-device-xous`.
+
+pub mod apdu;
+mod card;
+pub mod tlv;
+
+pub use card::{HISTORICAL_BYTES, OpenPgpCard, PinEntry, PinPadOp, PinPrompt, PinRef, Store, StoreError};
+
+#[cfg(test)]
+mod tests;
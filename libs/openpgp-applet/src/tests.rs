@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rand_chacha::ChaCha8Rng;
+use rand_chacha::rand_core::SeedableRng;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::apdu::{Command, Status};
+use crate::*;
+
+#[derive(Default)]
+struct MemStore(HashMap<String, Vec<u8>>);
+
+impl Store for MemStore {
+    fn get(&self, name: &str) -> Option<Vec<u8>> { self.0.get(name).cloned() }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Result<(), StoreError> {
+        self.0.insert(name.to_owned(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), StoreError> {
+        self.0.remove(name);
+        Ok(())
+    }
+}
+
+struct ScriptedEntry(Vec<Option<&'static str>>);
+
+impl PinEntry for ScriptedEntry {
+    fn request(&mut self, _prompt: PinPrompt) -> Option<String> { self.0.remove(0).map(String::from) }
+}
+
+type Card = OpenPgpCard<MemStore, ChaCha8Rng>;
+
+fn card() -> Card {
+    let mut card = OpenPgpCard::new(MemStore::default(), ChaCha8Rng::seed_from_u64(0), [1, 2, 3, 4]);
+    let (_, sw) = send(&mut card, 0xA4, 0x04, 0x00, &[0xD2, 0x76, 0x00, 0x01, 0x24, 0x01], None);
+    assert_eq!(sw, Status::SUCCESS);
+    card
+}
+
+fn send(card: &mut Card, ins: u8, p1: u8, p2: u8, data: &[u8], le: Option<usize>) -> (Vec<u8>, Status) {
+    let mut cmd = Command::new(0x00, ins, p1, p2, data);
+    cmd.le = le;
+    split(card.process(&cmd.to_bytes()))
+}
+
+fn split(mut response: Vec<u8>) -> (Vec<u8>, Status) {
+    let sw = response.split_off(response.len() - 2);
+    (response, Status(u16::from_be_bytes([sw[0], sw[1]])))
+}
+
+fn generate(card: &mut Card, crt: u8) -> Vec<u8> {
+    let (data, sw) = send(card, 0x47, 0x80, 0x00, &[crt, 0x00], Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    let inner = tlv::find(&data, 0x7F49).unwrap();
+    tlv::find(inner, 0x0086).unwrap().to_vec()
+}
+
+#[test]
+fn parse_apdu_cases() {
+    let case1 = Command::parse(&[0x00, 0xE6, 0x00, 0x00]).unwrap();
+    assert_eq!((case1.data.len(), case1.le), (0, None));
+    let case2 = Command::parse(&[0x00, 0xCA, 0x00, 0x6E, 0x00]).unwrap();
+    assert_eq!(case2.le, Some(256));
+    let case3 = Command::parse(&[0x00, 0x20, 0x00, 0x81, 0x02, 0x31, 0x32]).unwrap();
+    assert_eq!((case3.data.as_slice(), case3.le), (&b"12"[..], None));
+    let case4 = Command::parse(&[0x00, 0x2A, 0x9E, 0x9A, 0x01, 0xAA, 0x40]).unwrap();
+    assert_eq!((case4.data.as_slice(), case4.le), (&[0xAA][..], Some(0x40)));
+
+    let extended = Command::parse(&[0x00, 0x2A, 0x9E, 0x9A, 0x00, 0x00, 0x01, 0xAA, 0x00, 0x00]).unwrap();
+    assert!(extended.extended);
+    assert_eq!((extended.data.as_slice(), extended.le), (&[0xAA][..], Some(65536)));
+    let extended_le = Command::parse(&[0x00, 0xCA, 0x00, 0x6E, 0x00, 0x01, 0x00]).unwrap();
+    assert_eq!(extended_le.le, Some(256));
+
+    assert_eq!(Command::parse(&[0x00, 0xCA, 0x00]), Err(Status::WRONG_LENGTH));
+    assert_eq!(Command::parse(&[0x00, 0x20, 0x00, 0x81, 0x05, 0x31]), Err(Status::WRONG_LENGTH));
+}
+
+#[test]
+fn tlv_round_trip() {
+    let long = vec![0x55; 300];
+    let mut out = Vec::new();
+    tlv::write(&mut out, 0x5B, b"name");
+    tlv::write(&mut out, 0x7F49, &[0xAA; 200]);
+    tlv::write(&mut out, 0xC5, &long);
+    assert_eq!(&out[..2], &[0x5B, 0x04]);
+    assert_eq!(&out[6..10], &[0x7F, 0x49, 0x81, 200]);
+    assert_eq!(tlv::find(&out, 0x5B), Some(&b"name"[..]));
+    assert_eq!(tlv::find(&out, 0x7F49).map(|v| v.len()), Some(200));
+    assert_eq!(tlv::find(&out, 0xC5), Some(long.as_slice()));
+    assert_eq!(tlv::find(&out, 0xC6), None);
+    assert_eq!(tlv::next(&[0x5B, 0x05, 0x00]), None);
+}
+
+#[test]
+fn commands_require_select() {
+    let mut card = OpenPgpCard::new(MemStore::default(), ChaCha8Rng::seed_from_u64(0), [1, 2, 3, 4]);
+    let (_, sw) = send(&mut card, 0xCA, 0x00, 0x4F, &[], Some(256));
+    assert_eq!(sw, Status::CONDITIONS_NOT_SATISFIED);
+    let (_, sw) = send(&mut card, 0xA4, 0x04, 0x00, &[0xA0, 0x00, 0x00, 0x05, 0x27], None);
+    assert_eq!(sw, Status::FILE_NOT_FOUND);
+}
+
+#[test]
+fn application_related_data() {
+    let mut card = card();
+    let (aid, sw) = send(&mut card, 0xCA, 0x00, 0x4F, &[], Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    assert_eq!(aid, card.aid());
+    assert_eq!(&aid[10..14], &[1, 2, 3, 4]);
+
+    let (data, sw) = send(&mut card, 0xCA, 0x00, 0x6E, &[], Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    assert_eq!(tlv::find(&data, 0x4F), Some(&aid[..]));
+    let discretionary = tlv::find(&data, 0x73).unwrap();
+    assert_eq!(tlv::find(discretionary, 0xC1).unwrap()[0], 0x16);
+    assert_eq!(tlv::find(discretionary, 0xC2).unwrap()[0], 0x12);
+    assert_eq!(tlv::find(discretionary, 0xC4), Some(&[0, 127, 127, 127, 3, 0, 3][..]));
+    assert_eq!(tlv::find(discretionary, 0xC5), Some(&[0u8; 60][..]));
+
+    let (_, sw) = send(&mut card, 0xCA, 0x01, 0x23, &[], Some(256));
+    assert_eq!(sw, Status::REFERENCED_DATA_NOT_FOUND);
+}
+
+#[test]
+fn verify_counts_down_and_blocks() {
+    let mut card = card();
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, &[], None);
+    assert_eq!(sw, Status::verify_failed(3));
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"000000", None);
+    assert_eq!(sw, Status::verify_failed(2));
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"123456", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, &[], None);
+    assert_eq!(sw, Status::SUCCESS);
+
+    for tries in (0..3).rev() {
+        let (_, sw) = send(&mut card, 0x20, 0x00, 0x83, b"00000000", None);
+        assert_eq!(sw, Status::verify_failed(tries));
+    }
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    assert_eq!(sw, Status::AUTH_BLOCKED);
+    // the user PIN is unaffected
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x81, b"123456", None);
+    assert_eq!(sw, Status::SUCCESS);
+}
+
+#[test]
+fn change_and_reset_pins() {
+    let mut card = card();
+    let (_, sw) = send(&mut card, 0x24, 0x00, 0x81, b"123456654321", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"123456", None);
+    assert_eq!(sw, Status::verify_failed(2));
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"654321", None);
+    assert_eq!(sw, Status::SUCCESS);
+    // too short
+    let (_, sw) = send(&mut card, 0x24, 0x00, 0x81, b"65432112", None);
+    assert_eq!(sw, Status::WRONG_LENGTH);
+
+    // block PW1, then unblock it with a reset code set by the admin
+    for _ in 0..3 {
+        send(&mut card, 0x20, 0x00, 0x82, b"000000", None);
+    }
+    let (_, sw) = send(&mut card, 0x2C, 0x00, 0x81, b"resetcod111111", None);
+    assert_eq!(sw, Status::AUTH_BLOCKED);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0xDA, 0x00, 0xD3, b"resetcod", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x2C, 0x00, 0x81, b"resetcod111111", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"111111", None);
+    assert_eq!(sw, Status::SUCCESS);
+
+    let (_, sw) = send(&mut card, 0x2C, 0x02, 0x81, b"222222", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"222222", None);
+    assert_eq!(sw, Status::SUCCESS);
+}
+
+#[test]
+fn put_data_needs_admin() {
+    let mut card = card();
+    let (_, sw) = send(&mut card, 0xDA, 0x00, 0x5B, b"Doe<<John", None);
+    assert_eq!(sw, Status::SECURITY_STATUS_NOT_SATISFIED);
+    send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    let (_, sw) = send(&mut card, 0xDA, 0x00, 0x5B, b"Doe<<John", None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0xDA, 0x00, 0xC7, &[0x11; 21], None);
+    assert_eq!(sw, Status::WRONG_LENGTH);
+    let (_, sw) = send(&mut card, 0xDA, 0x00, 0xC1, &[0x01, 0x08, 0x00], None);
+    assert_eq!(sw, Status::INCORRECT_DATA);
+
+    let (data, _) = send(&mut card, 0xCA, 0x00, 0x65, &[], Some(256));
+    assert_eq!(tlv::find(&data, 0x5B), Some(&b"Doe<<John"[..]));
+    let (data, _) = send(&mut card, 0xCA, 0x00, 0x5B, &[], Some(256));
+    assert_eq!(data, b"Doe<<John");
+}
+
+#[test]
+fn sign_and_authenticate() {
+    let mut card = card();
+    let (_, sw) = send(&mut card, 0x47, 0x80, 0x00, &[0xB6, 0x00], Some(256));
+    assert_eq!(sw, Status::SECURITY_STATUS_NOT_SATISFIED);
+    send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    let public = VerifyingKey::from_bytes(&generate(&mut card, 0xB6).try_into().unwrap()).unwrap();
+    let (read_back, _) = send(&mut card, 0x47, 0x81, 0x00, &[0xB6, 0x00], Some(256));
+    assert_eq!(tlv::find(tlv::find(&read_back, 0x7F49).unwrap(), 0x86).unwrap(), public.as_bytes());
+
+    let digest = [0x5A; 32];
+    let (_, sw) = send(&mut card, 0x2A, 0x9E, 0x9A, &digest, Some(256));
+    assert_eq!(sw, Status::SECURITY_STATUS_NOT_SATISFIED);
+    send(&mut card, 0x20, 0x00, 0x81, b"123456", None);
+    let (signature, sw) = send(&mut card, 0x2A, 0x9E, 0x9A, &digest, Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    public.verify(&digest, &Signature::from_slice(&signature).unwrap()).unwrap();
+    // PW1 is only good for one signature by default
+    let (_, sw) = send(&mut card, 0x2A, 0x9E, 0x9A, &digest, Some(256));
+    assert_eq!(sw, Status::SECURITY_STATUS_NOT_SATISFIED);
+    let (data, _) = send(&mut card, 0xCA, 0x00, 0x7A, &[], Some(256));
+    assert_eq!(tlv::find(&data, 0x93), Some(&[0, 0, 1][..]));
+
+    let public = VerifyingKey::from_bytes(&generate(&mut card, 0xA4).try_into().unwrap()).unwrap();
+    send(&mut card, 0x20, 0x00, 0x82, b"123456", None);
+    let challenge = b"ssh session identifier";
+    let (signature, sw) = send(&mut card, 0x88, 0x00, 0x00, challenge, Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    public.verify(challenge, &Signature::from_slice(&signature).unwrap()).unwrap();
+}
+
+#[test]
+fn decipher() {
+    let mut card = card();
+    send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    let public: [u8; 32] = generate(&mut card, 0xB8).try_into().unwrap();
+    send(&mut card, 0x20, 0x00, 0x82, b"123456", None);
+
+    let ephemeral = StaticSecret::from([7u8; 32]);
+    let mut point = vec![0x40];
+    point.extend_from_slice(PublicKey::from(&ephemeral).as_bytes());
+    let (mut data, mut inner) = (Vec::new(), Vec::new());
+    tlv::write(&mut inner, 0x86, &point);
+    let mut key = Vec::new();
+    tlv::write(&mut key, 0x7F49, &inner);
+    tlv::write(&mut data, 0xA6, &key);
+
+    let (shared, sw) = send(&mut card, 0x2A, 0x80, 0x86, &data, Some(256));
+    assert_eq!(sw, Status::SUCCESS);
+    assert_eq!(shared, ephemeral.diffie_hellman(&PublicKey::from(public)).as_bytes());
+}
+
+#[test]
+fn chaining_and_get_response() {
+    let mut card = card();
+    send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    let url = [b'u'; 200];
+    let mut first = Command::new(0x10, 0xDA, 0x5F, 0x50, &url[..150]);
+    first.le = None;
+    let (_, sw) = split(card.process(&first.to_bytes()));
+    assert_eq!(sw, Status::SUCCESS);
+    // a different command breaks the chain
+    let (_, sw) = send(&mut card, 0xCA, 0x5F, 0x50, &[], Some(256));
+    assert_eq!(sw, Status::LAST_COMMAND_EXPECTED);
+
+    card.process(&first.to_bytes());
+    let (_, sw) = send(&mut card, 0xDA, 0x5F, 0x50, &url[150..], None);
+    assert_eq!(sw, Status::SUCCESS);
+
+    let (head, sw) = send(&mut card, 0xCA, 0x5F, 0x50, &[], Some(100));
+    assert_eq!(sw, Status::bytes_remaining(100));
+    let (tail, sw) = send(&mut card, 0xC0, 0x00, 0x00, &[], Some(100));
+    assert_eq!(sw, Status::SUCCESS);
+    assert_eq!([head, tail].concat(), url);
+}
+
+#[test]
+fn pinpad_entry() {
+    let mut card = card();
+    let response = card.pinpad(PinPadOp::Verify, 0x82, &mut ScriptedEntry(vec![None]));
+    assert_eq!(split(response).1, Status::PINPAD_CANCELLED);
+    let response = card.pinpad(PinPadOp::Verify, 0x82, &mut ScriptedEntry(vec![Some("123456")]));
+    assert_eq!(split(response).1, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, &[], None);
+    assert_eq!(sw, Status::SUCCESS);
+
+    let mut mismatch = ScriptedEntry(vec![Some("123456"), Some("24682468"), Some("13571357")]);
+    assert_eq!(split(card.pinpad(PinPadOp::Modify, 0x81, &mut mismatch)).1, Status::PINPAD_MISMATCH);
+    let mut short = ScriptedEntry(vec![Some("123456"), Some("2468"), Some("2468")]);
+    assert_eq!(split(card.pinpad(PinPadOp::Modify, 0x81, &mut short)).1, Status::PINPAD_WRONG_LENGTH);
+    let mut change = ScriptedEntry(vec![Some("123456"), Some("24682468"), Some("24682468")]);
+    assert_eq!(split(card.pinpad(PinPadOp::Modify, 0x81, &mut change)).1, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x82, b"24682468", None);
+    assert_eq!(sw, Status::SUCCESS);
+}
+
+#[test]
+fn terminate_and_activate() {
+    let mut card = card();
+    let (_, sw) = send(&mut card, 0xE6, 0x00, 0x00, &[], None);
+    assert_eq!(sw, Status::SECURITY_STATUS_NOT_SATISFIED);
+    send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    generate(&mut card, 0xB6);
+    send(&mut card, 0x24, 0x00, 0x83, b"12345678abcdefgh", None);
+
+    // a blocked admin PIN also allows termination
+    for _ in 0..3 {
+        send(&mut card, 0x20, 0x00, 0x83, b"00000000", None);
+    }
+    let (_, sw) = send(&mut card, 0xE6, 0x00, 0x00, &[], None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0xA4, 0x04, 0x00, &[0xD2, 0x76, 0x00, 0x01, 0x24, 0x01], None);
+    assert_eq!(sw, Status::TERMINATED);
+    let (_, sw) = send(&mut card, 0xCA, 0x00, 0x6E, &[], Some(256));
+    assert_eq!(sw, Status::TERMINATED);
+
+    let (_, sw) = send(&mut card, 0x44, 0x00, 0x00, &[], None);
+    assert_eq!(sw, Status::SUCCESS);
+    assert!(card.store().0.is_empty());
+    let (_, sw) = send(&mut card, 0xA4, 0x04, 0x00, &[0xD2, 0x76, 0x00, 0x01, 0x24, 0x01], None);
+    assert_eq!(sw, Status::SUCCESS);
+    let (_, sw) = send(&mut card, 0x20, 0x00, 0x83, b"12345678", None);
+    assert_eq!(sw, Status::SUCCESS);
+}
@@ -0,0 +1,64 @@
+//! The subset of BER-TLV used by the OpenPGP card specification. Tags are at most two bytes long.
+
+/// Append a TLV with the given `tag` and `value` to `out`.
+pub fn write(out: &mut Vec<u8>, tag: u16, value: &[u8]) {
+    if tag > 0xFF {
+        out.extend_from_slice(&tag.to_be_bytes());
+    } else {
+        out.push(tag as u8);
+    }
+    match value.len() {
+        len if len < 0x80 => out.push(len as u8),
+        len if len <= 0xFF => out.extend_from_slice(&[0x81, len as u8]),
+        len => {
+            out.push(0x82);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(value);
+}
+
+/// Split the first TLV off of `data`, returning its tag, its value, and whatever follows it.
+pub fn next(data: &[u8]) -> Option<(u16, &[u8], &[u8])> {
+    let (&first, mut rest) = data.split_first()?;
+    let mut tag = first as u16;
+    if first & 0x1F == 0x1F {
+        let (&second, r) = rest.split_first()?;
+        tag = (tag << 8) | second as u16;
+        rest = r;
+    }
+    let (&len_byte, r) = rest.split_first()?;
+    rest = r;
+    let len = match len_byte {
+        len if len < 0x80 => len as usize,
+        0x81 => {
+            let (&len, r) = rest.split_first()?;
+            rest = r;
+            len as usize
+        }
+        0x82 => {
+            if rest.len() < 2 {
+                return None;
+            }
+            let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            rest = &rest[2..];
+            len
+        }
+        _ => return None,
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// Find the value of the first top-level TLV in `data` with the given `tag`.
+pub fn find(mut data: &[u8], tag: u16) -> Option<&[u8]> {
+    while let Some((t, value, rest)) = next(data) {
+        if t == tag {
+            return Some(value);
+        }
+        data = rest;
+    }
+    None
+}
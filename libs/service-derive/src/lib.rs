@@ -0,0 +1,607 @@
+//! Generate the boilerplate for a Xous service from a trait.
+//!
+//! Annotating a trait with `#[service]` keeps the trait as-is and generates, next to it:
+//!
+//! * `FooOpcode`, an opcode enum numbered in declaration order, with `from_usize()` / `to_usize()`.
+//! * `FooClient`, a client stub wrapping a `xous::CID` with one method per trait method.
+//! * `FooServer`, with `handle()` to decode a `xous::Message` and call into the trait, and `dispatch()` to do
+//!   the same for a received `MessageEnvelope` and send back any reply.
+//! * `FooMock`, a host-only stand-in for `FooClient` that routes each call through `FooServer::handle()` on
+//!   an in-process implementation, so the encoding can be tested without a kernel.
+//! * `SERVER_NAME_FOO`, if a `name = "..."` argument is given.
+//!
+//! Every method takes `&mut self`, and either up to four scalar arguments or a single reference to a
+//! `flatipc::Ipc` type. Scalars are `bool` and integer types no wider than `usize`. Methods may return
+//! nothing, a scalar, or a pair of scalars. Methods that return nothing are sent as non-blocking scalars
+//! unless marked `#[blocking]`; everything else blocks. Opcodes may be pinned with `#[opcode(n)]`, in which
+//! case numbering continues from `n + 1`.
+
+use std::collections::HashMap;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    FnArg, Ident, ItemTrait, LitStr, Pat, ReturnType, TraitItem, Type, parse_macro_input, spanned::Spanned,
+};
+
+const SCALAR_TYPES: &[&str] = &["bool", "u8", "u16", "u32", "usize", "i8", "i16", "i32", "isize"];
+const MAX_SCALAR_ARGS: usize = 4;
+
+#[proc_macro_attribute]
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported service argument, expected `name = \"...\"`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemTrait);
+    service_inner(name, item).unwrap_or_else(|e| e).into()
+}
+
+fn error(span: proc_macro2::Span, msg: &str) -> TokenStream2 { syn::Error::new(span, msg).to_compile_error() }
+
+#[derive(Clone)]
+struct Scalar {
+    ty: Box<Type>,
+    is_bool: bool,
+}
+
+impl Scalar {
+    fn parse(ty: &Type) -> Option<Scalar> {
+        let Type::Path(path) = ty else { return None };
+        if path.qself.is_some() {
+            return None;
+        }
+        let ident = path.path.get_ident()?.to_string();
+        if !SCALAR_TYPES.contains(&ident.as_str()) {
+            return None;
+        }
+        Some(Scalar { ty: Box::new(ty.clone()), is_bool: ident == "bool" })
+    }
+
+    fn encode(&self, value: &TokenStream2) -> TokenStream2 {
+        quote! { (#value) as usize }
+    }
+
+    fn decode(&self, value: &TokenStream2) -> TokenStream2 {
+        if self.is_bool {
+            quote! { (#value) != 0 }
+        } else {
+            let ty = &self.ty;
+            quote! { (#value) as #ty }
+        }
+    }
+}
+
+enum Args {
+    Scalars(Vec<(Ident, Scalar)>),
+    Lend { ident: Ident, ty: Box<Type>, mutable: bool },
+}
+
+enum Ret {
+    Unit,
+    One(Scalar),
+    Two(Scalar, Scalar),
+}
+
+struct Method {
+    ident: Ident,
+    variant: Ident,
+    opcode: usize,
+    args: Args,
+    ret: Ret,
+    blocking: bool,
+}
+
+fn service_inner(name: Option<LitStr>, mut item: ItemTrait) -> Result<TokenStream2, TokenStream2> {
+    if !item.generics.params.is_empty() {
+        return Err(error(item.generics.span(), "service traits may not be generic"));
+    }
+
+    let mut methods = vec![];
+    let mut opcodes: HashMap<usize, Ident> = HashMap::new();
+    let mut next_opcode = 0;
+    for trait_item in item.items.iter_mut() {
+        let TraitItem::Fn(func) = trait_item else {
+            return Err(error(trait_item.span(), "service traits may only contain methods"));
+        };
+        let method = parse_method(func, next_opcode)?;
+        if let Some(previous) = opcodes.insert(method.opcode, method.ident.clone()) {
+            return Err(error(
+                method.ident.span(),
+                &format!("opcode {} is already used by `{}`", method.opcode, previous),
+            ));
+        }
+        next_opcode = method.opcode + 1;
+        methods.push(method);
+    }
+
+    let vis = &item.vis;
+    let trait_ident = &item.ident;
+    let opcode_ident = format_ident!("{}Opcode", trait_ident);
+    let client_ident = format_ident!("{}Client", trait_ident);
+    let server_ident = format_ident!("{}Server", trait_ident);
+    let mock_ident = format_ident!("{}Mock", trait_ident);
+
+    let server_name = name.map(|name| {
+        let const_ident = format_ident!("SERVER_NAME_{}", upper_snake_case(&trait_ident.to_string()));
+        quote! { #vis const #const_ident: &str = #name; }
+    });
+
+    let variants = methods.iter().map(|m| {
+        let variant = &m.variant;
+        let opcode = Literal::usize_unsuffixed(m.opcode);
+        quote! { #variant = #opcode }
+    });
+    let from_usize = methods.iter().map(|m| {
+        let variant = &m.variant;
+        let opcode = Literal::usize_unsuffixed(m.opcode);
+        quote! { #opcode => Some(#opcode_ident::#variant) }
+    });
+    let client_methods = methods.iter().map(|m| generate_call(m, &opcode_ident, false));
+    let mock_methods = methods.iter().map(|m| generate_call(m, &opcode_ident, true));
+    let handlers = methods.iter().map(|m| generate_handler(m, &opcode_ident));
+
+    Ok(quote! {
+        #item
+
+        #server_name
+
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        #vis enum #opcode_ident {
+            #(#variants,)*
+        }
+
+        impl #opcode_ident {
+            pub fn from_usize(value: usize) -> Option<Self> {
+                match value {
+                    #(#from_usize,)*
+                    _ => None,
+                }
+            }
+
+            pub fn to_usize(self) -> usize { self as usize }
+        }
+
+        #[derive(Debug, Copy, Clone)]
+        #vis struct #client_ident {
+            conn: xous::CID,
+        }
+
+        #[allow(dead_code)]
+        impl #client_ident {
+            pub fn new(conn: xous::CID) -> Self { #client_ident { conn } }
+
+            pub fn conn(&self) -> xous::CID { self.conn }
+
+            #(#client_methods)*
+        }
+
+        #vis struct #server_ident;
+
+        #[allow(dead_code)]
+        impl #server_ident {
+            /// Decode `msg` and call the matching method on `server`. Blocking messages produce a
+            /// `Scalar1` or `Scalar2` result that must be returned to the sender; everything else
+            /// produces `xous::Result::Ok`. Unknown opcodes yield `UnhandledSyscall`, and messages of
+            /// the wrong kind or with a mismatched buffer yield `InvalidSyscall`.
+            pub fn handle<S: #trait_ident + ?Sized>(
+                server: &mut S,
+                msg: &mut xous::Message,
+            ) -> Result<xous::Result, xous::Error> {
+                match #opcode_ident::from_usize(msg.id()) {
+                    #(#handlers)*
+                    None => Err(xous::Error::UnhandledSyscall),
+                }
+            }
+
+            /// Handle a received message and reply to the sender if it is blocked on a scalar.
+            /// Lent buffers are returned when `envelope` is dropped.
+            pub fn dispatch<S: #trait_ident + ?Sized>(
+                server: &mut S,
+                mut envelope: xous::MessageEnvelope,
+            ) -> Result<(), xous::Error> {
+                let sender = envelope.sender;
+                match #server_ident::handle(server, &mut envelope.body)? {
+                    xous::Result::Scalar1(a) => xous::return_scalar(sender, a),
+                    xous::Result::Scalar2(a, b) => xous::return_scalar2(sender, a, b),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        #[cfg(not(target_os = "xous"))]
+        #vis struct #mock_ident<S: #trait_ident> {
+            server: core::cell::RefCell<S>,
+        }
+
+        #[cfg(not(target_os = "xous"))]
+        #[allow(dead_code)]
+        impl<S: #trait_ident> #mock_ident<S> {
+            pub fn new(server: S) -> Self { #mock_ident { server: core::cell::RefCell::new(server) } }
+
+            pub fn into_inner(self) -> S { self.server.into_inner() }
+
+            pub fn with_server<R>(&self, f: impl FnOnce(&mut S) -> R) -> R { f(&mut self.server.borrow_mut()) }
+
+            fn transact(&self, msg: &mut xous::Message) -> Result<xous::Result, xous::Error> {
+                #server_ident::handle(&mut *self.server.borrow_mut(), msg)
+            }
+
+            #(#mock_methods)*
+        }
+    })
+}
+
+fn parse_method(func: &mut syn::TraitItemFn, next_opcode: usize) -> Result<Method, TokenStream2> {
+    let mut opcode = next_opcode;
+    let mut blocking = false;
+    let mut attr_error = None;
+    func.attrs.retain(|attr| {
+        if attr.path().is_ident("opcode") {
+            match attr.parse_args::<syn::LitInt>().and_then(|lit| lit.base10_parse::<usize>()) {
+                Ok(value) => opcode = value,
+                Err(e) => attr_error = Some(e.to_compile_error()),
+            }
+            false
+        } else if attr.path().is_ident("blocking") {
+            blocking = true;
+            false
+        } else {
+            true
+        }
+    });
+    if let Some(e) = attr_error {
+        return Err(e);
+    }
+
+    let sig = &func.sig;
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() || sig.variadic.is_some() {
+        return Err(error(sig.span(), "service methods may not be generic, async or variadic"));
+    }
+    match sig.inputs.first() {
+        Some(FnArg::Receiver(recv)) if recv.reference.is_some() && recv.mutability.is_some() => {}
+        _ => return Err(error(sig.span(), "service methods must take `&mut self`")),
+    }
+
+    let mut scalars = vec![];
+    let mut lend = None;
+    for input in sig.inputs.iter().skip(1) {
+        let FnArg::Typed(arg) = input else { unreachable!() };
+        let Pat::Ident(pat) = arg.pat.as_ref() else {
+            return Err(error(arg.pat.span(), "service method arguments must be plain identifiers"));
+        };
+        let ident = pat.ident.clone();
+        if let Type::Reference(reference) = arg.ty.as_ref() {
+            if lend.is_some() || !scalars.is_empty() {
+                return Err(error(arg.span(), "a lent buffer must be the only argument"));
+            }
+            lend = Some((ident, reference.elem.clone(), reference.mutability.is_some()));
+        } else if let Some(scalar) = Scalar::parse(&arg.ty) {
+            if lend.is_some() {
+                return Err(error(arg.span(), "a lent buffer must be the only argument"));
+            }
+            if scalars.len() == MAX_SCALAR_ARGS {
+                return Err(error(arg.span(), "scalar messages carry at most four arguments"));
+            }
+            scalars.push((ident, scalar));
+        } else {
+            return Err(error(
+                arg.ty.span(),
+                "unsupported argument type, expected a scalar or a reference to a flatipc `Ipc` type",
+            ));
+        }
+    }
+
+    let ret = match &sig.output {
+        ReturnType::Default => Ret::Unit,
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Tuple(tuple) if tuple.elems.is_empty() => Ret::Unit,
+            Type::Tuple(tuple) if tuple.elems.len() == 2 => {
+                match (Scalar::parse(&tuple.elems[0]), Scalar::parse(&tuple.elems[1])) {
+                    (Some(a), Some(b)) => Ret::Two(a, b),
+                    _ => return Err(error(ty.span(), "unsupported return type")),
+                }
+            }
+            ty => Ret::One(Scalar::parse(ty).ok_or_else(|| error(ty.span(), "unsupported return type"))?),
+        },
+    };
+
+    let args = match lend {
+        Some((ident, ty, mutable)) => {
+            if !matches!(ret, Ret::Unit) {
+                return Err(error(sig.output.span(), "methods that lend a buffer return data through it"));
+            }
+            if blocking {
+                return Err(error(sig.span(), "lent buffers always block, `#[blocking]` is redundant"));
+            }
+            Args::Lend { ident, ty, mutable }
+        }
+        None => {
+            if blocking && !matches!(ret, Ret::Unit) {
+                return Err(error(sig.span(), "methods that return a value always block"));
+            }
+            Args::Scalars(scalars)
+        }
+    };
+
+    Ok(Method {
+        ident: sig.ident.clone(),
+        variant: format_ident!("{}", upper_camel_case(&sig.ident.to_string())),
+        opcode,
+        args,
+        ret,
+        blocking,
+    })
+}
+
+fn return_type(ret: &Ret) -> TokenStream2 {
+    match ret {
+        Ret::Unit => quote! { () },
+        Ret::One(a) => {
+            let a = &a.ty;
+            quote! { #a }
+        }
+        Ret::Two(a, b) => {
+            let (a, b) = (&a.ty, &b.ty);
+            quote! { (#a, #b) }
+        }
+    }
+}
+
+/// Build a client-side method. The client and the mock encode and decode identically, and only differ in
+/// how the message reaches the server.
+fn generate_call(method: &Method, opcode_ident: &Ident, mock: bool) -> TokenStream2 {
+    let ident = &method.ident;
+    let variant = &method.variant;
+    let ret_ty = return_type(&method.ret);
+
+    match &method.args {
+        Args::Lend { ident: arg, ty, mutable } => {
+            let body = if mock {
+                let (ptr, kind) = if *mutable {
+                    (quote! { #arg as *mut #ty as usize }, quote! { MutableBorrow })
+                } else {
+                    (quote! { #arg as *const #ty as usize }, quote! { Borrow })
+                };
+                quote! {
+                    let buf = unsafe { xous::MemoryRange::new(#ptr, core::mem::size_of::<#ty>())? };
+                    let mut msg = xous::Message::#kind(xous::MemoryMessage {
+                        id: #opcode_ident::#variant as usize,
+                        buf,
+                        offset: xous::MemoryAddress::new(flatipc::Ipc::signature(#arg)),
+                        valid: None,
+                    });
+                    self.transact(&mut msg).map(|_| ())
+                }
+            } else if *mutable {
+                quote! { flatipc::Ipc::lend_mut(#arg, self.conn, #opcode_ident::#variant as usize) }
+            } else {
+                quote! { flatipc::Ipc::lend(#arg, self.conn, #opcode_ident::#variant as usize) }
+            };
+            let reference = if *mutable {
+                quote! { &mut #ty }
+            } else {
+                quote! { &#ty }
+            };
+            quote! {
+                pub fn #ident(&self, #arg: #reference) -> Result<(), xous::Error> {
+                    #body
+                }
+            }
+        }
+        Args::Scalars(scalars) => {
+            let params = scalars.iter().map(|(arg, scalar)| {
+                let ty = &scalar.ty;
+                quote! { #arg: #ty }
+            });
+            let mut words: Vec<TokenStream2> =
+                scalars.iter().map(|(arg, scalar)| scalar.encode(&quote! { #arg })).collect();
+            words.resize(MAX_SCALAR_ARGS, quote! { 0 });
+            let blocking = method.blocking || !matches!(method.ret, Ret::Unit);
+            let constructor = if blocking {
+                quote! { new_blocking_scalar }
+            } else {
+                quote! { new_scalar }
+            };
+            let build = quote! {
+                xous::Message::#constructor(#opcode_ident::#variant as usize, #(#words),*)
+            };
+            let send = if mock {
+                quote! {
+                    let mut msg = #build;
+                    let result = self.transact(&mut msg)?;
+                }
+            } else {
+                quote! { let result = xous::send_message(self.conn, #build)?; }
+            };
+            let decode = match &method.ret {
+                Ret::Unit if !blocking => quote! {
+                    let _ = result;
+                    Ok(())
+                },
+                Ret::Unit => quote! {
+                    match result {
+                        xous::Result::Scalar1(_) => Ok(()),
+                        _ => Err(xous::Error::InternalError),
+                    }
+                },
+                Ret::One(a) => {
+                    let a = a.decode(&quote! { r0 });
+                    quote! {
+                        match result {
+                            xous::Result::Scalar1(r0) => Ok(#a),
+                            _ => Err(xous::Error::InternalError),
+                        }
+                    }
+                }
+                Ret::Two(a, b) => {
+                    let (a, b) = (a.decode(&quote! { r0 }), b.decode(&quote! { r1 }));
+                    quote! {
+                        match result {
+                            xous::Result::Scalar2(r0, r1) => Ok((#a, #b)),
+                            _ => Err(xous::Error::InternalError),
+                        }
+                    }
+                }
+            };
+            quote! {
+                pub fn #ident(&self, #(#params),*) -> Result<#ret_ty, xous::Error> {
+                    #send
+                    #decode
+                }
+            }
+        }
+    }
+}
+
+fn generate_handler(method: &Method, opcode_ident: &Ident) -> TokenStream2 {
+    let ident = &method.ident;
+    let variant = &method.variant;
+
+    let body = match &method.args {
+        Args::Lend { ident: arg, ty, mutable: true } => quote! {
+            let xous::Message::MutableBorrow(mem) = msg else { return Err(xous::Error::InvalidSyscall) };
+            let #arg = <#ty as flatipc::Ipc>::from_memory_message_mut(mem).ok_or(xous::Error::InvalidSyscall)?;
+            server.#ident(#arg);
+            Ok(xous::Result::Ok)
+        },
+        Args::Lend { ident: arg, ty, mutable: false } => quote! {
+            let (xous::Message::Borrow(mem) | xous::Message::MutableBorrow(mem)) = msg else {
+                return Err(xous::Error::InvalidSyscall)
+            };
+            let #arg = <#ty as flatipc::Ipc>::from_memory_message(mem).ok_or(xous::Error::InvalidSyscall)?;
+            server.#ident(#arg);
+            Ok(xous::Result::Ok)
+        },
+        Args::Scalars(scalars) => {
+            let blocking = method.blocking || !matches!(method.ret, Ret::Unit);
+            let bind = if scalars.is_empty() {
+                quote! { _ }
+            } else {
+                quote! { scalar }
+            };
+            let kind = if blocking {
+                quote! { BlockingScalar }
+            } else {
+                quote! { Scalar }
+            };
+            let args = scalars.iter().enumerate().map(|(i, (_, scalar))| {
+                let field = format_ident!("arg{}", i + 1);
+                scalar.decode(&quote! { scalar.#field })
+            });
+            let call = quote! { server.#ident(#(#args),*) };
+            let reply = match &method.ret {
+                Ret::Unit if !blocking => quote! {
+                    #call;
+                    Ok(xous::Result::Ok)
+                },
+                Ret::Unit => quote! {
+                    #call;
+                    Ok(xous::Result::Scalar1(0))
+                },
+                Ret::One(a) => {
+                    let a = a.encode(&quote! { r0 });
+                    quote! {
+                        let r0 = #call;
+                        Ok(xous::Result::Scalar1(#a))
+                    }
+                }
+                Ret::Two(a, b) => {
+                    let (a, b) = (a.encode(&quote! { r0 }), b.encode(&quote! { r1 }));
+                    quote! {
+                        let (r0, r1) = #call;
+                        Ok(xous::Result::Scalar2(#a, #b))
+                    }
+                }
+            };
+            quote! {
+                let xous::Message::#kind(#bind) = msg else { return Err(xous::Error::InvalidSyscall) };
+                #reply
+            }
+        }
+    };
+
+    quote! {
+        Some(#opcode_ident::#variant) => {
+            #body
+        }
+    }
+}
+
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+fn upper_snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i != 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: TokenStream2) -> Result<TokenStream2, TokenStream2> {
+        service_inner(None, syn::parse2(src).unwrap())
+    }
+
+    #[test]
+    fn opcodes_continue_after_pinned_value() {
+        let mut item: ItemTrait = syn::parse2(quote! {
+            trait Foo {
+                fn a(&mut self);
+                #[opcode(10)]
+                fn b(&mut self);
+                fn c(&mut self);
+            }
+        })
+        .unwrap();
+        let mut next = 0;
+        let mut opcodes = vec![];
+        for trait_item in item.items.iter_mut() {
+            let TraitItem::Fn(func) = trait_item else { unreachable!() };
+            let method = parse_method(func, next).unwrap();
+            next = method.opcode + 1;
+            opcodes.push(method.opcode);
+            assert!(func.attrs.is_empty());
+        }
+        assert_eq!(opcodes, [0, 10, 11]);
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        assert!(parse(quote! { trait Foo { fn a(&mut self); #[opcode(0)] fn b(&mut self); } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&self); } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&mut self, v: u64); } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&mut self, a: u8, b: u8, c: u8, d: u8, e: u8); } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&mut self, buf: &mut IpcBuf, v: u32); } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&mut self, buf: &IpcBuf) -> u32; } }).is_err());
+        assert!(parse(quote! { trait Foo { #[blocking] fn a(&mut self) -> u32; } }).is_err());
+        assert!(parse(quote! { trait Foo { fn a(&mut self, a: u32, b: bool) -> (u32, bool); } }).is_ok());
+    }
+
+    #[test]
+    fn case_conversion() {
+        assert_eq!(upper_camel_case("get_utc_time"), "GetUtcTime");
+        assert_eq!(upper_snake_case("KeyStore"), "KEY_STORE");
+    }
+}
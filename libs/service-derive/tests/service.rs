@@ -0,0 +1,94 @@
+use flatipc::IntoIpc;
+use service_derive::service;
+
+#[derive(flatipc::Ipc, Debug)]
+#[repr(C)]
+pub struct Label {
+    len: u32,
+    data: [u8; 32],
+}
+
+#[service(name = "_Counter test_")]
+pub trait Counter {
+    fn add(&mut self, amount: u32) -> u32;
+    fn reset(&mut self);
+    #[blocking]
+    fn sync(&mut self);
+    #[opcode(10)]
+    fn split(&mut self, value: i32, negate: bool) -> (i32, bool);
+    fn rename(&mut self, label: &mut IpcLabel);
+    fn describe(&mut self, label: &IpcLabel);
+}
+
+#[derive(Default)]
+struct State {
+    total: u32,
+    resets: u32,
+    label: Vec<u8>,
+}
+
+impl Counter for State {
+    fn add(&mut self, amount: u32) -> u32 {
+        self.total += amount;
+        self.total
+    }
+
+    fn reset(&mut self) {
+        self.total = 0;
+        self.resets += 1;
+    }
+
+    fn sync(&mut self) {}
+
+    fn split(&mut self, value: i32, negate: bool) -> (i32, bool) {
+        if negate { (-value, value < 0) } else { (value, value < 0) }
+    }
+
+    fn rename(&mut self, label: &mut IpcLabel) {
+        label.data[..3].copy_from_slice(b"new");
+        label.len = 3;
+    }
+
+    fn describe(&mut self, label: &IpcLabel) { self.label = label.data[..label.len as usize].to_vec(); }
+}
+
+#[test]
+fn opcodes() {
+    assert_eq!(SERVER_NAME_COUNTER, "_Counter test_");
+    assert_eq!(CounterOpcode::Add.to_usize(), 0);
+    assert_eq!(CounterOpcode::Sync.to_usize(), 2);
+    assert_eq!(CounterOpcode::Describe.to_usize(), 12);
+    assert_eq!(CounterOpcode::from_usize(11), Some(CounterOpcode::Rename));
+    assert_eq!(CounterOpcode::from_usize(3), None);
+}
+
+#[test]
+fn mock_round_trip() {
+    let counter = CounterMock::new(State::default());
+    assert_eq!(counter.add(3).unwrap(), 3);
+    assert_eq!(counter.add(4).unwrap(), 7);
+    counter.reset().unwrap();
+    counter.sync().unwrap();
+    assert_eq!(counter.split(-5, true).unwrap(), (5, true));
+    assert_eq!(counter.split(9, false).unwrap(), (9, false));
+
+    let mut label = Label { len: 2, data: [0; 32] }.into_ipc();
+    label.data[..2].copy_from_slice(b"hi");
+    counter.describe(&label).unwrap();
+    counter.rename(&mut label).unwrap();
+    assert_eq!(&label.data[..label.len as usize], b"new");
+
+    let state = counter.into_inner();
+    assert_eq!((state.total, state.resets), (0, 1));
+    assert_eq!(state.label, b"hi");
+}
+
+#[test]
+fn rejects_malformed_messages() {
+    let mut state = State::default();
+    let mut wrong_kind = xous::Message::new_scalar(CounterOpcode::Add as usize, 1, 0, 0, 0);
+    assert_eq!(CounterServer::handle(&mut state, &mut wrong_kind), Err(xous::Error::InvalidSyscall));
+    let mut unknown = xous::Message::new_scalar(99, 0, 0, 0, 0);
+    assert_eq!(CounterServer::handle(&mut state, &mut unknown), Err(xous::Error::UnhandledSyscall));
+    assert_eq!(state.total, 0);
+}
@@ -50,6 +50,10 @@ pub struct UserPrefs {
     pub headset_volume: u32,
     pub autotype_rate: usize,
     pub lefty_mode: bool,
+    /// One of `locales::LANGUAGES`, or empty for the language the image was built for
+    pub language: String,
+    /// Name of the custom layout in `keyboard::LAYOUT_DICT`, or empty for a built-in one
+    pub keyboard_layout: String,
 }
 
 pub struct Manager {
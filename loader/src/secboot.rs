@@ -4,7 +4,7 @@ use sha2_loader::Sha512;
 use crate::SIGBLOCK_SIZE;
 use crate::println;
 
-const VERSION_STR: &'static str = "Xous OS Loader v0.9.6\n\r";
+const VERSION_STR: &'static str = "Xous OS Loader v0.9.7\n\r";
 // v0.9.0 -- initial version
 // v0.9.1 -- booting with hw acceleration, and "simplest signature" check on the entire xous.img blob
 // v0.9.2 -- add version and length check between header and signed area
@@ -12,12 +12,22 @@ const VERSION_STR: &'static str = "Xous OS Loader v0.9.6\n\r";
 // v0.9.4 -- monorepo conversion
 // v0.9.5 -- multiplatform conversion and phase 1 optimization
 // v0.9.6 -- convert signature check to pre-hash signature (see #472 https://github.com/betrusted-io/xous-core/issues/472)
+// v0.9.7 -- anti-rollback: v3 signature records carry a security version, checked against a counter in FLASH
 
 pub const STACK_LEN: u32 = 8192 - (7 * 4); // 7 words for backup kernel args
 pub const STACK_TOP: u32 = (crate::platform::RAM_BASE + crate::platform::RAM_SIZE) as u32 - STACK_LEN;
 
 use utralib::generated::*;
 
+/// Location of the anti-rollback counter page, as an offset from the start of FLASH. This is the last page of
+/// the early settings sector; it must match `ANTIROLLBACK_LOC` in the `spinor` crate.
+const ANTIROLLBACK_LOC: usize = 0x0097_F000;
+/// Words at the start of the anti-rollback page that hold the counter. The counter is a thermometer code:
+/// every cleared bit counts as one, so an erased page reads as 0, and the largest security version is 1024.
+const ANTIROLLBACK_WORDS: usize = 32;
+/// Signature record with the security version prepended to the version/length trailer
+const SIG_VERSION_SECVER: u32 = 3;
+
 #[repr(C)]
 struct SignatureInFlash {
     pub version: u32,
@@ -312,6 +322,9 @@ pub fn validate_xous_img(xous_img_offset: *const u32, fs_prehash: &mut [u8; 64])
     engine.wfo(utra::engine::WINDOW_WINDOW, 0);
     engine.wfo(utra::engine::MPSTART_MPSTART, 0);
 
+    // security version of the image; v2 records predate anti-rollback, and are treated as version 0
+    let mut security_version = 0;
+
     // select the public key
     let mut keyrom = Keyrom::new();
     let mut keyloc = KeyLoc::SelfSignPub; // start from the self-sign key first, then work your way to less secure options
@@ -384,7 +397,7 @@ pub fn validate_xous_img(xous_img_offset: *const u32, fs_prehash: &mut [u8; 64])
             u32::from_le_bytes(image[signed_len as usize - 8..signed_len as usize - 4].try_into().unwrap());
         let protected_len = u32::from_le_bytes(image[signed_len as usize - 4..].try_into().unwrap());
         // check that the signed versions match the version reported in the header
-        if sig.version != 2 || (sig.version != protected_version) {
+        if (sig.version != 2 && sig.version != SIG_VERSION_SECVER) || (sig.version != protected_version) {
             if sig.version == 1 {
                 gfx.msg(
                     "v1 signature found, but v2 required.\n\rIs your kernel up to date?\n\r",
@@ -415,6 +428,12 @@ pub fn validate_xous_img(xous_img_offset: *const u32, fs_prehash: &mut [u8; 64])
             }
             die();
         }
+        if sig.version == SIG_VERSION_SECVER {
+            // trailer is: security version, min semver, semver, version, length
+            security_version = u32::from_le_bytes(
+                image[signed_len as usize - 44..signed_len as usize - 40].try_into().unwrap(),
+            );
+        }
 
         let ed25519_signature = ed25519_dalek_loader::Signature::from(sig.signature);
         gfx.msg("Checking signature...\n\r", &mut cursor);
@@ -452,6 +471,21 @@ pub fn validate_xous_img(xous_img_offset: *const u32, fs_prehash: &mut [u8; 64])
         }
     }
 
+    // the security version can only be trusted once the signature has checked out
+    let rollback_limit = antirollback_counter();
+    println!("Security version {}, anti-rollback counter {}", security_version, rollback_limit);
+    if security_version < rollback_limit {
+        gfx.msg(
+            "Check fail: image is older than the anti-rollback\n\rcounter allows. Refusing to downgrade.\n\r",
+            &mut cursor,
+        );
+        println!(
+            "Check fail: security version {} is below the anti-rollback counter {}",
+            security_version, rollback_limit
+        );
+        die();
+    }
+
     // check the stack usage
     let stack: &[u32] = unsafe {
         core::slice::from_raw_parts(
@@ -484,6 +518,16 @@ pub fn validate_xous_img(xous_img_offset: *const u32, fs_prehash: &mut [u8; 64])
     true
 }
 
+/// Reads the anti-rollback counter out of FLASH
+fn antirollback_counter() -> u32 {
+    let counter = (HW_SPIFLASH_MEM + ANTIROLLBACK_LOC) as *const u32;
+    let mut count = 0;
+    for i in 0..ANTIROLLBACK_WORDS {
+        count += unsafe { counter.add(i).read_volatile() }.count_zeros();
+    }
+    count
+}
+
 fn die() {
     let ticktimer = CSR::new(utra::ticktimer::HW_TICKTIMER_BASE as *mut u32);
     let mut power = CSR::new(utra::power::HW_POWER_BASE as *mut u32);
@@ -84,33 +84,45 @@ fn generate_code(translations: Translations) -> proc_macro2::TokenStream {
         let mut langs = Vec::<TokenStream>::new();
         let mut needs_interpolation = false;
         let mut vars = Vec::new();
+        // languages without this key get the English string
+        let mut fallback = None;
         for (lang, tr) in trs {
             let lang_vars = extract_vars(&tr);
             needs_interpolation = lang_vars.len() > 0;
 
-            if needs_interpolation {
+            let expr = if needs_interpolation {
                 let idents = convert_vars_to_idents(&lang_vars);
                 vars.extend(lang_vars.clone());
-
-                langs.push(quote! {
-                    #lang => #tr#(.replace(#lang_vars, $#idents))*,
-                });
+                quote! { #tr#(.replace(#lang_vars, $#idents))* }
             } else {
-                langs.push(quote! {
-                    #lang => #tr,
-                });
+                quote! { #tr }
+            };
+            if lang == "en" {
+                fallback = Some(quote! { _ => #expr });
             }
+            langs.push(quote! {
+                #lang => #expr,
+            });
         }
 
         vars.sort();
         vars.dedup();
         let vars_ident = convert_vars_to_idents(&vars);
+        // or the key itself, if there is no English string either. The replacements don't change the key,
+        // but make it a `String` like the other arms.
+        let fallback = fallback.unwrap_or_else(|| {
+            if needs_interpolation {
+                quote! { _ => #key#(.replace(#vars, $#vars_ident))* }
+            } else {
+                quote! { _ => #key }
+            }
+        });
         if needs_interpolation {
             branches.push(quote! {
                 (#key, #(#vars_ident: $#vars_ident:expr, )*$lang:expr) => {
                     match $lang.as_ref() {
                         #(#langs)*
-                        e => panic!("Missing language: {}", e)
+                        #fallback
                     }
                 };
             });
@@ -124,7 +136,7 @@ fn generate_code(translations: Translations) -> proc_macro2::TokenStream {
                 (#key, $lang:expr) => {
                     match $lang.as_ref() {
                         #(#langs)*
-                        e => panic!("Missing language: {}", e)
+                        #fallback
                     }
                 };
             });
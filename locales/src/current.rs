@@ -0,0 +1,31 @@
+//! The language a process shows its strings in. It starts out as `LANG`, the language the image was
+//! built for, and can be changed to any of the `LANGUAGES` at runtime. Every process keeps its own, so
+//! a change has to be passed on to each server that renders strings: see `Gam::set_language()` and
+//! `Modals::set_language()`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Every language an image can be switched to, English first as it is what the others fall back to. A
+/// new language has to be added here, as well as to `locale.rs`, once its strings are in the
+/// `i18n.json` files.
+pub const LANGUAGES: &[&str] = &["en", "en-tts", "fr", "ja", "zh"];
+
+/// Index of the current language in `LANGUAGES`, or `usize::MAX` for `LANG`
+static CURRENT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// The language to pass to `t!`
+pub fn lang() -> &'static str {
+    LANGUAGES.get(CURRENT.load(Ordering::Relaxed)).copied().unwrap_or(crate::LANG)
+}
+
+/// Switches this process to `lang`. Returns `false`, and leaves the language as it was, if there are no
+/// strings for `lang`.
+pub fn set_lang(lang: &str) -> bool {
+    match LANGUAGES.iter().position(|l| *l == lang) {
+        Some(index) => {
+            CURRENT.store(index, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
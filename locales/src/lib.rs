@@ -4,3 +4,6 @@ pub mod locale;
 pub use locale::LANG;
 
 pub mod generated;
+
+mod current;
+pub use current::{LANGUAGES, lang, set_lang};
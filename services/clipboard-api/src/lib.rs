@@ -0,0 +1,168 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Moves text between apps: one app copies it to the clipboard server, another pastes it.
+//!
+//! The clipboard holds a single entry. Callers identify themselves with the token the GAM gave them when
+//! they registered their UX, and the clipboard checks that token with the GAM, so processes without a UX
+//! can't read it. An entry copied as `Sensitivity::Sensitive`, such as a password, is cleared after
+//! `SENSITIVE_TIMEOUT_MS`, and is only pasted into an app loaded through `app-loader` once the user has
+//! allowed it in a modal.
+//!
+//! ```ignore
+//! let clipboard = clipboard_api::Clipboard::new(&xns, gam_token).unwrap();
+//! clipboard.copy("correct horse battery staple", Sensitivity::Sensitive).unwrap();
+//! if let Some(text) = clipboard.paste().unwrap() {
+//!     log::info!("pasted {}", text);
+//! }
+//! ```
+
+use num_traits::ToPrimitive;
+use xous::{CID, Message, send_message};
+use xous_ipc::Buffer;
+
+pub const SERVER_NAME_CLIPBOARD: &str = "_Clipboard_"; // used internally by xous-names
+
+/// How long a sensitive entry stays on the clipboard
+pub const SENSITIVE_TIMEOUT_MS: u64 = 60_000;
+
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
+pub enum Opcode {
+    /// replace the entry (ClipboardRequest, lend_mut)
+    Copy = 0,
+    /// read the entry (ClipboardRequest, lend_mut); blocks while the user is asked to confirm
+    Paste,
+    /// remove the entry (blocking scalar with the token)
+    Clear,
+    /// the alarm for a sensitive entry has gone off (alarm message with the entry's serial number);
+    /// internal, and ignored until the entry is due
+    Expire,
+    /// the user has answered a confirmation (scalar with the request ID, and 1 if allowed); internal, and
+    /// ignored unless sent from within the clipboard server
+    Confirmed,
+    /// exit the server; internal, and ignored unless sent from within the clipboard server
+    Quit,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub enum Sensitivity {
+    Normal,
+    /// cleared after `SENSITIVE_TIMEOUT_MS`, and only pasted into apps loaded at runtime with the user's
+    /// confirmation
+    Sensitive,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub enum ClipboardResult {
+    Ok,
+    /// nothing was copied, or the entry was cleared
+    Empty,
+    /// the token isn't valid, or the user didn't allow a sensitive entry to be pasted
+    Denied,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct ClipboardRequest {
+    /// the GAM token of the calling app
+    pub token: [u32; 4],
+    /// the text copied, or set by the server to the text pasted
+    pub text: String,
+    /// that of the text copied or pasted; in a paste request, the most sensitive entry the caller takes
+    pub sensitivity: Sensitivity,
+    /// set by the server
+    pub result: ClipboardResult,
+}
+
+pub struct Clipboard {
+    conn: CID,
+    token: [u32; 4],
+}
+impl Clipboard {
+    /// Connects to the clipboard on behalf of the app that the GAM gave `token` to. Fails with
+    /// `ServerNotFound` in an image without a clipboard server.
+    pub fn new(xns: &xous_names::XousNames, token: [u32; 4]) -> Result<Self, xous::Error> {
+        let conn = xns.request_connection(SERVER_NAME_CLIPBOARD)?;
+        REFCOUNT.fetch_add(1, Ordering::Relaxed);
+        Ok(Clipboard { conn, token })
+    }
+
+    /// Puts `text` on the clipboard, in place of whatever was there
+    pub fn copy(&self, text: &str, sensitivity: Sensitivity) -> Result<(), xous::Error> {
+        match self.request(Opcode::Copy, text, sensitivity)?.result {
+            ClipboardResult::Denied => Err(xous::Error::AccessDenied),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the text on the clipboard, or `None` if it is empty. Fails with `AccessDenied` if the user
+    /// didn't allow a sensitive entry to be pasted into this app.
+    pub fn paste(&self) -> Result<Option<String>, xous::Error> { self.paste_at_most(Sensitivity::Sensitive) }
+
+    /// Like `paste()`, but a sensitive entry fails with `AccessDenied` instead of being pasted. This is for
+    /// services that paste on behalf of other apps, like the editor in `modals`, as they can't speak for
+    /// the app the text ends up in.
+    pub fn paste_normal(&self) -> Result<Option<String>, xous::Error> {
+        self.paste_at_most(Sensitivity::Normal)
+    }
+
+    fn paste_at_most(&self, sensitivity: Sensitivity) -> Result<Option<String>, xous::Error> {
+        let response = self.request(Opcode::Paste, "", sensitivity)?;
+        match response.result {
+            ClipboardResult::Ok => Ok(Some(response.text)),
+            ClipboardResult::Empty => Ok(None),
+            ClipboardResult::Denied => Err(xous::Error::AccessDenied),
+        }
+    }
+
+    /// Empties the clipboard
+    pub fn clear(&self) -> Result<(), xous::Error> {
+        let response = send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                Opcode::Clear.to_usize().unwrap(),
+                self.token[0] as usize,
+                self.token[1] as usize,
+                self.token[2] as usize,
+                self.token[3] as usize,
+            ),
+        )?;
+        match response {
+            xous::Result::Scalar1(1) => Ok(()),
+            xous::Result::Scalar1(_) => Err(xous::Error::AccessDenied),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
+    fn request(
+        &self,
+        op: Opcode,
+        text: &str,
+        sensitivity: Sensitivity,
+    ) -> Result<ClipboardRequest, xous::Error> {
+        let request = ClipboardRequest {
+            token: self.token,
+            text: String::from(text),
+            sensitivity,
+            result: ClipboardResult::Denied,
+        };
+        let mut buf = Buffer::into_buf(request).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, op.to_u32().unwrap())?;
+        buf.to_original::<ClipboardRequest, _>().or(Err(xous::Error::InternalError))
+    }
+}
+
+use core::sync::atomic::{AtomicU32, Ordering};
+static REFCOUNT: AtomicU32 = AtomicU32::new(0);
+impl Drop for Clipboard {
+    fn drop(&mut self) {
+        // the connection to the server side must be reference counted, so that multiple instances of this
+        // object within a single process do not end up de-allocating the CID on other threads before they
+        // go out of scope.
+        if REFCOUNT.fetch_sub(1, Ordering::Relaxed) == 1 {
+            unsafe {
+                xous::disconnect(self.conn).unwrap();
+            }
+        }
+    }
+}
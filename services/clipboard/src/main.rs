@@ -0,0 +1,298 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+//! Holds the text that apps copy and paste; see `clipboard-api` for how it is used.
+//!
+//! Every request carries the caller's GAM token, which the GAM identifies for us. Requests with a token
+//! the GAM doesn't know are turned away. When an app loaded through `app-loader` pastes a sensitive
+//! entry, the user is asked first: the paste is answered once they have, and the questions are asked one
+//! at a time by a thread of their own, so the clipboard stays available to everyone else in the meantime.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use clipboard_api::*;
+use locales::t;
+use num_traits::*;
+use xous::{Message, MessageEnvelope, msg_blocking_scalar_unpack, msg_scalar_unpack, send_message};
+use xous_ipc::Buffer;
+
+/// Most pastes that can wait on the user at once; more are turned away rather than queue up modals
+const MAX_PENDING_CONFIRMATIONS: usize = 4;
+
+struct Entry {
+    text: String,
+    sensitivity: Sensitivity,
+    /// tells entries apart, so that a timeout or a confirmation meant for an entry that has since been
+    /// replaced doesn't apply to the new one
+    serial: u32,
+    /// for a sensitive entry, the alarm that expires it and when it is due, on the `elapsed_ms()` clock
+    expiry: Option<(ticktimer_server::Alarm, u64)>,
+}
+
+impl Entry {
+    /// Overwrites the text with zeroes, so a sensitive entry doesn't linger in memory once it's gone
+    fn volatile_clear(&mut self) {
+        // safety: NULL bytes are valid UTF-8
+        let bytes = unsafe { self.text.as_bytes_mut() };
+        let ptr = bytes.as_mut_ptr();
+        for i in 0..bytes.len() {
+            // safety: the bounds were derived from a valid len; the pointer is aligned because it's derived
+            // from a slice. The "unsafe" version is used to force a zeroize of contents and avoid
+            // optimizations that could otherwise cause this operation to be skipped.
+            unsafe { ptr.add(i).write_volatile(0) };
+        }
+        // force all the writes to finish if they were re-ordered
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+fn clear(entry: &mut Option<Entry>, tt: &ticktimer_server::Ticktimer) {
+    if let Some(mut old) = entry.take() {
+        if let Some((alarm, _)) = old.expiry {
+            tt.cancel_alarm(alarm);
+        }
+        old.volatile_clear();
+    }
+}
+
+/// The `ClipboardRequest` of a `Copy` or `Paste`, which has to be lent mutably so that it can be
+/// answered. Anything else is turned away without being looked at; a request that is lent mutably but
+/// can't be read is answered with `Denied`.
+fn read_request(msg: &mut MessageEnvelope) -> Option<ClipboardRequest> {
+    if !matches!(msg.body, Message::MutableBorrow(_)) {
+        log::warn!("clipboard request from PID {:?} wasn't a lend_mut, ignoring", msg.sender.pid());
+        if let Message::BlockingScalar(_) = msg.body {
+            xous::return_scalar(msg.sender, 0).ok();
+        }
+        return None;
+    }
+    let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message()?) };
+    match buffer.to_original::<ClipboardRequest, _>() {
+        Ok(request) => Some(request),
+        Err(e) => {
+            log::warn!("malformed clipboard request from PID {:?}: {:?}", msg.sender.pid(), e);
+            respond(msg, "", Sensitivity::Normal, ClipboardResult::Denied);
+            None
+        }
+    }
+}
+
+/// Answers a `Copy` or `Paste` request, which `read_request()` has checked was lent mutably
+fn respond(msg: &mut MessageEnvelope, text: &str, sensitivity: Sensitivity, result: ClipboardResult) {
+    let Some(mem) = msg.body.memory_message_mut() else {
+        return;
+    };
+    let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+    let response = ClipboardRequest { token: [0; 4], text: String::from(text), sensitivity, result };
+    if buffer.replace(response).is_err() {
+        log::warn!("couldn't answer the clipboard request from PID {:?}", msg.sender.pid());
+    }
+}
+
+/// Asks the user whether `app` may paste the sensitive entry, then reports back to the server
+fn confirm_paste(modals: &modals::Modals, cid: xous::CID, request_id: u32, app: String) {
+    modals.add_list_item(t!("clipboard.allow", locales::lang())).expect("couldn't build radio item list");
+    modals.add_list_item(t!("clipboard.deny", locales::lang())).expect("couldn't build radio item list");
+    let allowed = match modals
+        .get_radiobutton(&t!("clipboard.confirm_paste", locales::lang()).replace("{app}", &app))
+    {
+        Ok(choice) => choice == t!("clipboard.allow", locales::lang()),
+        Err(e) => {
+            log::error!("couldn't ask about pasting: {:?}", e);
+            false
+        }
+    };
+    log::info!("{} {} to paste a sensitive entry", app, if allowed { "allowed" } else { "not allowed" });
+    send_message(
+        cid,
+        Message::new_scalar(
+            Opcode::Confirmed.to_usize().unwrap(),
+            request_id as usize,
+            allowed as usize,
+            0,
+            0,
+        ),
+    )
+    .expect("couldn't report confirmation");
+}
+
+fn main() -> ! {
+    log_server::init_wait().unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+    log::info!("my PID is {}", xous::process::id());
+
+    let xns = xous_names::XousNames::new().unwrap();
+    let clipboard_sid = xns.register_name(SERVER_NAME_CLIPBOARD, None).expect("can't register server");
+    log::trace!("registered with NS -- {:?}", clipboard_sid);
+    let self_cid = xous::connect(clipboard_sid).expect("couldn't connect to self");
+    let gam = gam::Gam::new(&xns).expect("can't connect to GAM");
+    let tt = ticktimer_server::Ticktimer::new().unwrap();
+
+    let (confirm_tx, confirm_rx) = mpsc::channel::<(u32, String)>();
+    std::thread::spawn(move || {
+        let xns = xous_names::XousNames::new().unwrap();
+        let modals = modals::Modals::new(&xns).expect("can't connect to modals");
+        for (request_id, app) in confirm_rx {
+            confirm_paste(&modals, self_cid, request_id, app);
+        }
+    });
+
+    let mut entry: Option<Entry> = None;
+    let mut serial = 0u32;
+    // pastes waiting on the user, with the serial of the entry they are about
+    let mut pending = HashMap::<u32, (MessageEnvelope, u32)>::new();
+    let mut next_request_id = 0u32;
+
+    loop {
+        let mut msg = xous::receive_message(clipboard_sid).unwrap();
+        match FromPrimitive::from_usize(msg.body.id()) {
+            Some(Opcode::Copy) => {
+                let Some(mut request) = read_request(&mut msg) else {
+                    continue;
+                };
+                let result = if gam.identify_token(request.token).unwrap_or(None).is_some() {
+                    clear(&mut entry, &tt);
+                    serial = serial.wrapping_add(1);
+                    let expiry = if request.sensitivity == Sensitivity::Sensitive {
+                        let due = tt.elapsed_ms() + SENSITIVE_TIMEOUT_MS;
+                        match tt.set_alarm(
+                            clipboard_sid,
+                            Opcode::Expire.to_u32().unwrap(),
+                            serial,
+                            ticktimer_server::AlarmTime::AfterMs(SENSITIVE_TIMEOUT_MS),
+                            None,
+                        ) {
+                            Ok(alarm) => Some((alarm, due)),
+                            Err(e) => {
+                                log::error!("couldn't set an alarm to expire the entry: {:?}", e);
+                                None
+                            }
+                        }
+                    } else {
+                        None
+                    };
+                    let mut copied = Entry {
+                        text: core::mem::take(&mut request.text),
+                        sensitivity: request.sensitivity,
+                        serial,
+                        expiry,
+                    };
+                    if copied.sensitivity == Sensitivity::Sensitive && copied.expiry.is_none() {
+                        // a sensitive entry that can't expire isn't kept at all
+                        copied.volatile_clear();
+                        ClipboardResult::Denied
+                    } else {
+                        entry = Some(copied);
+                        ClipboardResult::Ok
+                    }
+                } else {
+                    log::warn!("copy with an invalid token, ignoring");
+                    ClipboardResult::Denied
+                };
+                // the copied text isn't echoed back
+                respond(&mut msg, "", request.sensitivity, result);
+            }
+            Some(Opcode::Paste) => {
+                let Some(ClipboardRequest { token, sensitivity: accepts, .. }) = read_request(&mut msg)
+                else {
+                    continue;
+                };
+                let owner = gam.identify_token(token).unwrap_or(None);
+                match (owner, entry.as_ref()) {
+                    (None, _) => {
+                        log::warn!("paste with an invalid token, ignoring");
+                        respond(&mut msg, "", Sensitivity::Normal, ClipboardResult::Denied);
+                    }
+                    (Some(_), None) => respond(&mut msg, "", Sensitivity::Normal, ClipboardResult::Empty),
+                    (Some(_), Some(current)) if current.sensitivity > accepts => {
+                        respond(&mut msg, "", Sensitivity::Normal, ClipboardResult::Denied)
+                    }
+                    (Some(owner), Some(current))
+                        if owner.loaded && current.sensitivity == Sensitivity::Sensitive =>
+                    {
+                        if pending.len() >= MAX_PENDING_CONFIRMATIONS {
+                            log::warn!("too many pastes waiting on the user, turning away {}", owner.name);
+                            respond(&mut msg, "", Sensitivity::Normal, ClipboardResult::Denied);
+                        } else {
+                            // the caller stays blocked until the user has answered
+                            let request_id = next_request_id;
+                            next_request_id = next_request_id.wrapping_add(1);
+                            pending.insert(request_id, (msg, current.serial));
+                            confirm_tx.send((request_id, owner.name)).expect("confirmation thread is gone");
+                        }
+                    }
+                    (Some(_), Some(current)) => {
+                        respond(&mut msg, &current.text, current.sensitivity, ClipboardResult::Ok)
+                    }
+                }
+            }
+            Some(Opcode::Confirmed) => msg_scalar_unpack!(msg, request_id, allowed, _, _, {
+                if msg.sender.pid().map(|p| p.get()).unwrap_or_default() as u32 != xous::process::id() {
+                    log::warn!("confirmation from PID {:?}, ignoring", msg.sender.pid());
+                } else if let Some((mut origin, wanted)) = pending.remove(&(request_id as u32)) {
+                    match entry.as_ref() {
+                        _ if allowed == 0 => {
+                            respond(&mut origin, "", Sensitivity::Normal, ClipboardResult::Denied)
+                        }
+                        Some(current) if current.serial == wanted => {
+                            respond(&mut origin, &current.text, current.sensitivity, ClipboardResult::Ok)
+                        }
+                        // the entry was cleared or replaced while the user was deciding
+                        _ => respond(&mut origin, "", Sensitivity::Normal, ClipboardResult::Empty),
+                    }
+                    // dropping `origin` unblocks the caller
+                }
+            }),
+            Some(Opcode::Clear) => msg_blocking_scalar_unpack!(msg, t0, t1, t2, t3, {
+                let token = [t0 as u32, t1 as u32, t2 as u32, t3 as u32];
+                if gam.identify_token(token).unwrap_or(None).is_some() {
+                    clear(&mut entry, &tt);
+                    xous::return_scalar(msg.sender, 1).unwrap();
+                } else {
+                    log::warn!("clear with an invalid token, ignoring");
+                    xous::return_scalar(msg.sender, 0).unwrap();
+                }
+            }),
+            Some(Opcode::Expire) => msg_scalar_unpack!(msg, alarm_id, expired, _, _, {
+                // this comes from the ticktimer, so its sender can't vouch for it: it only clears the
+                // entry its alarm was set for, and not before that entry is due
+                let due = entry.as_ref().and_then(|current| match current.expiry {
+                    Some((alarm, due))
+                        if alarm.id() == alarm_id as u32 && current.serial == expired as u32 =>
+                    {
+                        Some(due)
+                    }
+                    _ => None,
+                });
+                match due {
+                    Some(due) if tt.elapsed_ms() >= due => {
+                        log::info!("sensitive entry timed out");
+                        clear(&mut entry, &tt);
+                    }
+                    _ => {
+                        log::warn!("expiry from PID {:?} doesn't match the entry, ignoring", msg.sender.pid())
+                    }
+                }
+            }),
+            Some(Opcode::Quit) => {
+                if msg.sender.pid().map(|p| p.get()).unwrap_or_default() as u32 != xous::process::id() {
+                    log::warn!("Quit from PID {:?}, ignoring", msg.sender.pid());
+                    continue;
+                }
+                log::warn!("Quit received, goodbye world!");
+                break;
+            }
+            None => {
+                log::error!("couldn't convert opcode: {:?}", msg);
+            }
+        }
+    }
+    // clean up our program
+    clear(&mut entry, &tt);
+    log::trace!("main loop exit, destroying servers");
+    xns.unregister_server(clipboard_sid).unwrap();
+    xous::destroy_server(clipboard_sid).unwrap();
+    log::trace!("quitting");
+    xous::terminate_process(0)
+}
@@ -51,6 +51,73 @@ pub(crate) enum Opcode {
 
     /// Suspend/resume callback
     SuspendResume,
+
+    /// Powers on the CODEC, sets up stereo streaming at the sample rate given in arg1 (in Hz); puts audio in
+    /// "paused" state
+    SetupStereo,
+
+    /// Opens a mixer stream with a given sample rate, priority and volume; returns the stream ID
+    OpenStream,
+
+    /// Closes a mixer stream, discarding any frames still queued in it
+    CloseStream,
+
+    /// Play frames into a mixer stream. Record frames are only returned through `SwapFrames`.
+    SwapStreamFrames,
+
+    /// set the software volume of a mixer stream, in percent
+    SetStreamVolume,
+
+    /// set the priority of a mixer stream
+    SetStreamPriority,
+
+    /// set the level, in percent of their volume, that lower-priority streams are ducked to
+    SetDuckLevel,
+}
+
+/// Sample rates supported by the CODEC. The discriminant is the rate in Hz.
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, Copy, Clone, PartialEq, Eq)]
+pub enum SampleRate {
+    Rate8k = 8_000,
+    Rate16k = 16_000,
+    Rate32k = 32_000,
+    Rate48k = 48_000,
+}
+impl SampleRate {
+    pub fn hz(&self) -> u32 { *self as u32 }
+}
+
+/// Mixer stream priorities. When a stream is playing, all streams of a lower priority are ducked.
+#[derive(
+    Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, Copy, Clone, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum StreamPriority {
+    /// background music, ambient sounds
+    Low = 0,
+    Normal = 1,
+    /// notification tones
+    High = 2,
+    /// speech and alerts that should be heard over everything else
+    Critical = 3,
+}
+
+/// The stream that frames submitted with `SwapFrames` are played through. It always exists, runs at the
+/// hardware sample rate and has `Normal` priority.
+pub const LEGACY_STREAM: u32 = 0;
+/// Maximum number of concurrently open mixer streams, including the legacy stream
+pub const MAX_STREAMS: usize = 8;
+/// Default level that lower-priority streams are ducked to, in percent of their set volume
+pub const DEFAULT_DUCK_PCT: u8 = 25;
+
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
+pub enum StreamError {
+    NoError = 0,
+    /// all mixer streams are in use
+    NoStreamsAvailable = 1,
+    /// the stream ID does not refer to an open stream
+    InvalidStream = 2,
+    /// the requested sample rate is not supported
+    InvalidRate = 3,
 }
 
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
@@ -80,6 +147,8 @@ pub(crate) enum EventCallback {
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Copy, Clone)]
 pub(crate) struct ScalarHook {
     pub sid: (u32, u32, u32, u32),
+    /// mixer stream whose free frame count is reported in the callback
+    pub stream: u32,
     pub id: u32, /* ID of the scalar message to send through (e.g. the discriminant of the Enum on the
                   * caller's side API) */
     pub cid: xous::CID, /* caller-side connection ID for the scalar message to route to. Created by the
@@ -213,3 +282,10 @@ impl FrameRing {
         }
     }
 }
+
+/// A `FrameRing` destined for a particular mixer stream
+#[derive(rkyv::Serialize, rkyv::Deserialize, Debug, rkyv::Archive, Copy, Clone)]
+pub struct StreamFrameRing {
+    pub stream: u32,
+    pub frames: FrameRing,
+}
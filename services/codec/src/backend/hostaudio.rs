@@ -2,10 +2,16 @@
 
 use codec::FIFO_DEPTH;
 
-pub struct Codec {}
+use crate::api::SampleRate;
+
+pub struct Codec {
+    sample_rate: SampleRate,
+}
 
 impl Codec {
-    pub fn new(_conn: xous::CID, _xns: &xous_names::XousNames) -> Codec { Codec {} }
+    pub fn new(_conn: xous::CID, _xns: &xous_names::XousNames) -> Codec {
+        Codec { sample_rate: SampleRate::Rate8k }
+    }
 
     pub fn suspend(&self) {}
 
@@ -33,6 +39,10 @@ impl Codec {
 
     pub fn is_live(&self) -> bool { false }
 
+    pub fn set_sample_rate(&mut self, rate: SampleRate) { self.sample_rate = rate; }
+
+    pub fn sample_rate(&self) -> SampleRate { self.sample_rate }
+
     pub fn get_headset_code(&mut self) -> u8 { 0 }
 
     pub fn get_dacflag_code(&mut self) -> u8 { 0 }
@@ -37,6 +37,7 @@ pub struct Codec {
     speaker_gain: f32,
     headphone_left_gain: f32,
     headphone_right_gain: f32,
+    sample_rate: SampleRate,
 }
 
 static SILENCE: [u32; FIFO_DEPTH] = [ZERO_PCM as u32 | (ZERO_PCM as u32) << 16; FIFO_DEPTH];
@@ -157,6 +158,7 @@ impl Codec {
             speaker_gain: -6.0,
             headphone_left_gain: -15.0,
             headphone_right_gain: -15.0,
+            sample_rate: SampleRate::Rate8k,
         }
     }
 
@@ -259,6 +261,11 @@ impl Codec {
 
     pub fn is_live(&self) -> bool { self.live }
 
+    /// Sets the rate used by the next `init()`. The clock tree is only reprogrammed on `init()`.
+    pub fn set_sample_rate(&mut self, rate: SampleRate) { self.sample_rate = rate; }
+
+    pub fn sample_rate(&self) -> SampleRate { self.sample_rate }
+
     pub fn set_speaker_gain_db(&mut self, gain_db: f32) {
         self.i2c.i2c_mutex_acquire();
         self.speaker_gain = gain_db;
@@ -376,19 +383,16 @@ impl Codec {
         code
     }
 
-    /// audio_clocks() sets up the default clocks for 8kHz sampling rate, assuming a 12MHz MCLK input
+    /// audio_clocks() sets up the clocks for the configured sampling rate, assuming a 12MHz MCLK input
     ///
     /// fIN = 12 MHz
-    /// M = 2.5
-    /// N = 32   (PLL freq = 153.6MHz)
-    /// N_MOD = 0
-    /// P = 12.5
-    /// fOUT = 12_288_000 Hz
+    /// P = 1, R = 1, J = 7, D = 1680 (PLL freq = 86.016MHz)
     ///
-    /// sample rate = 8_000
-    /// oversampling rate (OSR) = 128
-    /// local divider = 12
-    /// 8_000 * 128 * 12 = 12_288_000 Hz
+    /// The DAC and ADC dividers are then picked so that the oversampling rate stays at 128:
+    /// 86_016_000 / (NDAC * MDAC * 128) = fs
+    /// 86_016_000 / (NADC * MADC * 128) = fs
+    ///
+    /// e.g. for 8kHz, NDAC * MDAC = 84 = 12 * 7, and NADC * MADC = 84 = 42 * 2
     fn audio_clocks(&mut self) {
         self.i2c.i2c_mutex_acquire();
         self.w(0, &[0]); // select page 0
@@ -400,11 +404,16 @@ impl Codec {
         // select PLL_CLKIN = MCLK; CODEC_CLKIN = PLL_CLK
         self.w(4, &[0b0000_0011]);
 
-        // fs = 8kHz
         // PLL_CLKIN = 12MHz
-        // PLLP = 1, PLLR = 1, PLLJ = 7, PLLD = 1680, NDAC = *12*, MDAC = 7, DOSR = 128, MADC = 2 , NADC =
-        // *42* ^^ from page 68 of datasheet, fs=48kHz/12MHz clkin line, with *bold* items multiplied
-        // by 6 to get to 8kHz
+        // PLLP = 1, PLLR = 1, PLLJ = 7, PLLD = 1680, NDAC = 2, MDAC = 7, DOSR = 128, MADC = 2 , NADC = 7
+        // ^^ from page 68 of datasheet, fs=48kHz/12MHz clkin line. NDAC and NADC (and MADC for 32kHz) are
+        // scaled up to get to the lower rates.
+        let (ndac, mdac, nadc, madc) = match self.sample_rate {
+            SampleRate::Rate8k => (12, 7, 42, 2),
+            SampleRate::Rate16k => (6, 7, 21, 2),
+            SampleRate::Rate32k => (3, 7, 7, 3),
+            SampleRate::Rate48k => (2, 7, 7, 2),
+        };
         self.w(5, &[
             0b1001_0001,                // P, R = 1, 1 and pll powered up
             7,                          // PLLJ = 7
@@ -413,16 +422,16 @@ impl Codec {
         ]);
 
         self.w(11, &[
-            0x80 | 12, // NADC = 12 (set to 2 for 48kHz)
-            0x80 | 7,  // MDAC = 7
-            0,         // DOSR = MSB of 128
-            128,       // DOSR = LSB of 128
+            0x80 | ndac, // NDAC
+            0x80 | mdac, // MDAC
+            0,           // DOSR = MSB of 128
+            128,         // DOSR = LSB of 128
         ]);
 
         self.w(18, &[
-            0x80 | 42, // NADC = 42 (set to 7 for 48kHz)
-            0x80 | 2,  // MADC = 2
-            128,       // AOSR = 128
+            0x80 | nadc, // NADC
+            0x80 | madc, // MADC
+            128,         // AOSR = 128
         ]);
         self.i2c.i2c_mutex_release();
     }
@@ -435,8 +444,8 @@ impl Codec {
         self.i2c.i2c_mutex_acquire();
         self.w(0, &[0]); // select page 0
 
-        // 32 bits/word * 2 channels * 8000 samples/s = 512_000 = BCLK
-        // pick off of DAC_MOD_CLK = 1.024MHz
+        // 32 bits/word * 2 channels * fs = BCLK, e.g. 512_000 at 8kHz
+        // pick off of DAC_MOD_CLK = fs * DOSR = fs * 128, divided by 2
         self.w(27, &[
             0b00_00_1_1_0_1, /* I2S standard, 16 bits per sample, BCLK output, WCLK output, DOUT is
                               * Hi-Z when unused */
@@ -21,7 +21,14 @@ impl Codec {
         Ok(Codec { conn, frame_sid: None })
     }
 
+    /// Hooks a callback that fires whenever the legacy stream (fed by `swap_frames`) can take more frames.
     pub fn hook_frame_callback(&mut self, id: u32, cid: CID) -> Result<(), xous::Error> {
+        self.hook_stream_callback(LEGACY_STREAM, id, cid)
+    }
+
+    /// Hooks a callback that fires whenever `stream` can take more frames. The `free_play` argument of the
+    /// callback reports the free frames in that stream. Only one callback can be hooked per `Codec` object.
+    pub fn hook_stream_callback(&mut self, stream: u32, id: u32, cid: CID) -> Result<(), xous::Error> {
         if self.frame_sid.is_none() {
             let sid = xous::create_server().unwrap();
             self.frame_sid = Some(sid);
@@ -34,7 +41,7 @@ impl Codec {
                 sid_tuple.3 as usize,
             )
             .unwrap();
-            let hookdata = ScalarHook { sid: sid_tuple, id, cid };
+            let hookdata = ScalarHook { sid: sid_tuple, stream, id, cid };
             let buf = Buffer::into_buf(hookdata).or(Err(xous::Error::InternalError))?;
             buf.lend(self.conn, Opcode::AudioStreamSubscribe.to_u32().unwrap()).map(|_| ())
         } else {
@@ -47,6 +54,105 @@ impl Codec {
             .map(|_| ())
     }
 
+    /// Powers on the CODEC and sets up stereo streaming at `rate`. Mixer streams opened at other rates are
+    /// resampled to this rate.
+    pub fn setup_stream(&mut self, rate: SampleRate) -> Result<(), xous::Error> {
+        send_message(
+            self.conn,
+            Message::new_scalar(Opcode::SetupStereo.to_usize().unwrap(), rate.to_usize().unwrap(), 0, 0, 0),
+        )
+        .map(|_| ())
+    }
+
+    /// Opens a mixer stream. `volume` is in percent. Returns the ID of the stream, to be used with
+    /// `swap_stream_frames()`.
+    pub fn open_stream(
+        &mut self,
+        rate: SampleRate,
+        priority: StreamPriority,
+        volume: u8,
+    ) -> Result<u32, xous::Error> {
+        let response = send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                Opcode::OpenStream.to_usize().unwrap(),
+                rate.to_usize().unwrap(),
+                priority.to_usize().unwrap(),
+                volume as usize,
+                0,
+            ),
+        )?;
+        if let xous::Result::Scalar2(code, id) = response {
+            match FromPrimitive::from_usize(code) {
+                Some(StreamError::NoError) => Ok(id as u32),
+                Some(StreamError::NoStreamsAvailable) => Err(xous::Error::OutOfMemory),
+                _ => Err(xous::Error::InvalidLimit),
+            }
+        } else {
+            log::error!("unexpected return value: {:#?}", response);
+            Err(xous::Error::InternalError)
+        }
+    }
+
+    pub fn close_stream(&mut self, stream: u32) -> Result<(), xous::Error> {
+        send_message(
+            self.conn,
+            Message::new_scalar(Opcode::CloseStream.to_usize().unwrap(), stream as usize, 0, 0, 0),
+        )
+        .map(|_| ())
+    }
+
+    /// Queues the frames in `frames` into a mixer stream. Unlike `swap_frames`, no record frames are
+    /// returned.
+    pub fn swap_stream_frames(&mut self, stream: u32, frames: &mut FrameRing) -> Result<(), xous::Error> {
+        let mut buf = Buffer::into_buf(StreamFrameRing { stream, frames: *frames })
+            .or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::SwapStreamFrames.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+
+        *frames = buf.to_original::<StreamFrameRing, _>().unwrap().frames;
+        Ok(())
+    }
+
+    /// Sets the software volume of a mixer stream, in percent
+    pub fn set_stream_volume(&self, stream: u32, volume: u8) -> Result<(), xous::Error> {
+        send_message(
+            self.conn,
+            Message::new_scalar(
+                Opcode::SetStreamVolume.to_usize().unwrap(),
+                stream as usize,
+                volume as usize,
+                0,
+                0,
+            ),
+        )
+        .map(|_| ())
+    }
+
+    pub fn set_stream_priority(&self, stream: u32, priority: StreamPriority) -> Result<(), xous::Error> {
+        send_message(
+            self.conn,
+            Message::new_scalar(
+                Opcode::SetStreamPriority.to_usize().unwrap(),
+                stream as usize,
+                priority.to_usize().unwrap(),
+                0,
+                0,
+            ),
+        )
+        .map(|_| ())
+    }
+
+    /// Sets the level that streams are ducked to while a higher-priority stream plays, in percent of their
+    /// volume
+    pub fn set_duck_level(&self, pct: u8) -> Result<(), xous::Error> {
+        send_message(
+            self.conn,
+            Message::new_scalar(Opcode::SetDuckLevel.to_usize().unwrap(), pct as usize, 0, 0, 0),
+        )
+        .map(|_| ())
+    }
+
     pub fn power_off(&mut self) -> Result<(), xous::Error> {
         send_message(self.conn, Message::new_scalar(Opcode::PowerOff.to_usize().unwrap(), 0, 0, 0, 0))
             .map(|_| ())
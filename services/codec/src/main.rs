@@ -3,9 +3,11 @@
 
 mod api;
 mod backend;
+mod mixer;
 use api::*;
 use backend::Codec;
 use log::info;
+use mixer::Mixer;
 use num_traits::{FromPrimitive, ToPrimitive};
 use xous::{CID, msg_scalar_unpack};
 use xous_ipc::Buffer;
@@ -15,6 +17,7 @@ struct ScalarCallback {
     server_to_cb_cid: CID,
     cb_to_client_cid: CID,
     cb_to_client_id: u32,
+    stream: u32,
 }
 
 fn main() -> ! {
@@ -58,6 +61,7 @@ fn wrapped_main() -> ! {
     let mut speaker_analog_gain_db: f32 = -6.0;
     let mut headphone_analog_gain_db: f32 = -15.0;
     let mut audio_cb_conns: [Option<ScalarCallback>; 32] = [None; 32];
+    let mut mixer = Mixer::new(SampleRate::Rate8k);
     loop {
         let mut msg = xous::receive_message(codec_sid).unwrap();
         let op: Option<api::Opcode> = FromPrimitive::from_usize(msg.body.id());
@@ -72,12 +76,13 @@ fn wrapped_main() -> ! {
                 codec.power(false);
             }),
             Some(api::Opcode::Setup8kStereo) => xous::msg_scalar_unpack!(msg, _, _, _, _, {
-                log::trace!("turning on codec power");
-                codec.power(true);
-                log::trace!("waiting for power up");
-                ticktimer.sleep_ms(2).unwrap();
-                log::trace!("initializing codec");
-                codec.init();
+                setup_stream(&mut codec, &mut mixer, &ticktimer, SampleRate::Rate8k);
+            }),
+            Some(api::Opcode::SetupStereo) => xous::msg_scalar_unpack!(msg, rate, _, _, _, {
+                match FromPrimitive::from_usize(rate) {
+                    Some(rate) => setup_stream(&mut codec, &mut mixer, &ticktimer, rate),
+                    None => log::error!("unsupported sample rate {}, ignoring!", rate),
+                }
             }),
             Some(api::Opcode::ResumeStream) => xous::msg_scalar_unpack!(msg, _, _, _, _, {
                 if codec.is_on() && codec.is_init() {
@@ -118,31 +123,7 @@ fn wrapped_main() -> ! {
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
                 let mut framering = buffer.to_original::<codec::api::FrameRing, _>().unwrap();
 
-                loop {
-                    if let Some(frame) = framering.dq_frame() {
-                        let mut printed = false;
-                        while codec.free_play_frames() == 0 {
-                            if !printed {
-                                log::debug!("swap overrun");
-                                printed = true;
-                            }
-                            xous::yield_slice();
-                            if !codec.is_live() {
-                                // handle the case that play stopped while we're trying to run the swap
-                                break;
-                            }
-                        }
-                        if codec.free_play_frames() > 0 {
-                            codec.nq_play_frame(frame).unwrap(); // throw away the result because we know this must succeed
-                        } else {
-                            // TODO: need to define a behavior when we have a play overrun. Do we:
-                            // - wait until we can play the frame?
-                            // - throw away the frame?
-                        }
-                    } else {
-                        break;
-                    }
-                }
+                play_frames(&mut codec, &mut mixer, LEGACY_STREAM, &mut framering);
 
                 framering.reset_ptrs();
                 loop {
@@ -159,6 +140,53 @@ fn wrapped_main() -> ! {
 
                 buffer.replace(framering).unwrap();
             }
+            Some(api::Opcode::SwapStreamFrames) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut stream_ring = buffer.to_original::<codec::api::StreamFrameRing, _>().unwrap();
+                play_frames(&mut codec, &mut mixer, stream_ring.stream, &mut stream_ring.frames);
+                stream_ring.frames.reset_ptrs();
+                buffer.replace(stream_ring).unwrap();
+            }
+            Some(api::Opcode::OpenStream) => {
+                xous::msg_blocking_scalar_unpack!(msg, rate, priority, volume, _, {
+                    let result = match (FromPrimitive::from_usize(rate), FromPrimitive::from_usize(priority))
+                    {
+                        (Some(rate), Some(priority)) => mixer.open(rate, priority, volume.min(100) as u8),
+                        _ => Err(StreamError::InvalidRate),
+                    };
+                    match result {
+                        Ok(id) => {
+                            xous::return_scalar2(msg.sender, StreamError::NoError as usize, id as usize)
+                        }
+                        Err(e) => xous::return_scalar2(msg.sender, e as usize, 0),
+                    }
+                    .expect("couldn't return OpenStream");
+                })
+            }
+            Some(api::Opcode::CloseStream) => xous::msg_scalar_unpack!(msg, id, _, _, _, {
+                if mixer.close(id as u32).is_err() {
+                    log::warn!("attempted to close stream {} which is not open", id);
+                }
+            }),
+            Some(api::Opcode::SetStreamVolume) => xous::msg_scalar_unpack!(msg, id, volume, _, _, {
+                if mixer.set_volume(id as u32, volume.min(100) as u8).is_err() {
+                    log::warn!("attempted to set volume on stream {} which is not open", id);
+                }
+            }),
+            Some(api::Opcode::SetStreamPriority) => xous::msg_scalar_unpack!(msg, id, priority, _, _, {
+                match FromPrimitive::from_usize(priority) {
+                    Some(priority) => {
+                        if mixer.set_priority(id as u32, priority).is_err() {
+                            log::warn!("attempted to set priority on stream {} which is not open", id);
+                        }
+                    }
+                    None => log::error!("got stream priority that we don't recognize, ignoring!"),
+                }
+            }),
+            Some(api::Opcode::SetDuckLevel) => xous::msg_scalar_unpack!(msg, pct, _, _, _, {
+                mixer.set_duck_level(pct.min(100) as u8);
+            }),
             Some(api::Opcode::AudioStreamSubscribe) => {
                 let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
                 let hookdata = buffer.to_original::<ScalarHook, _>().unwrap();
@@ -168,7 +196,8 @@ fn wrapped_main() -> ! {
             }
             Some(api::Opcode::AnotherFrame) => xous::msg_scalar_unpack!(msg, _rdcount, _wrcount, _, _, {
                 //log::trace!("A rd {} wr {}", rdcount, wrcount);
-                send_event(&audio_cb_conns, codec.free_play_frames(), codec.available_rec_frames());
+                pump_mixer(&mut codec, &mut mixer);
+                send_event(&audio_cb_conns, &mixer, codec.available_rec_frames());
             }),
             Some(api::Opcode::SetSpeakerVolume) => xous::msg_scalar_unpack!(msg, op, gain_code, _, _, {
                 match FromPrimitive::from_usize(op) {
@@ -263,6 +292,62 @@ fn wrapped_main() -> ! {
     xous::terminate_process(0)
 }
 
+fn setup_stream(
+    codec: &mut Codec,
+    mixer: &mut Mixer,
+    ticktimer: &ticktimer_server::Ticktimer,
+    rate: SampleRate,
+) {
+    log::trace!("turning on codec power");
+    codec.power(true);
+    log::trace!("waiting for power up");
+    ticktimer.sleep_ms(2).unwrap();
+    log::trace!("initializing codec at {}Hz", rate.hz());
+    codec.set_sample_rate(rate);
+    codec.init();
+    mixer.set_out_rate(rate);
+}
+
+/// Moves mixed frames into the hardware play buffer until either runs out
+fn pump_mixer(codec: &mut Codec, mixer: &mut Mixer) {
+    while codec.free_play_frames() > 0 {
+        if let Some(frame) = mixer.mix_frame() {
+            codec.nq_play_frame(frame).unwrap(); // always succeeds because we checked for free frames first
+        } else {
+            break;
+        }
+    }
+}
+
+/// Queues the frames in `framering` into a mixer stream, waiting for room if the stream is full
+fn play_frames(codec: &mut Codec, mixer: &mut Mixer, stream: u32, framering: &mut codec::FrameRing) {
+    while let Some(frame) = framering.dq_frame() {
+        let mut printed = false;
+        let mut pending = frame;
+        loop {
+            match mixer.push_frame(stream, pending) {
+                Ok(()) => break,
+                Err(frame) => {
+                    if mixer.has_stream(stream) && codec.is_live() {
+                        if !printed {
+                            log::debug!("swap overrun");
+                            printed = true;
+                        }
+                        pending = frame;
+                        pump_mixer(codec, mixer);
+                        xous::yield_slice();
+                    } else {
+                        // the stream was closed, or play stopped while we're trying to run the swap: drop the
+                        // frame
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    pump_mixer(codec, mixer);
+}
+
 fn do_hook(hookdata: ScalarHook, cb_conns: &mut [Option<ScalarCallback>; 32]) {
     let (s0, s1, s2, s3) = hookdata.sid;
     let sid = xous::SID::from_u32(s0, s1, s2, s3);
@@ -271,6 +356,7 @@ fn do_hook(hookdata: ScalarHook, cb_conns: &mut [Option<ScalarCallback>; 32]) {
         server_to_cb_cid,
         cb_to_client_cid: hookdata.cid,
         cb_to_client_id: hookdata.id,
+        stream: hookdata.stream,
     });
     let mut found = false;
     for entry in cb_conns.iter_mut() {
@@ -299,9 +385,11 @@ fn unhook(cb_conns: &mut [Option<ScalarCallback>; 32]) {
         *entry = None;
     }
 }
-fn send_event(cb_conns: &[Option<ScalarCallback>; 32], free_play: usize, avail_rec: usize) {
+fn send_event(cb_conns: &[Option<ScalarCallback>; 32], mixer: &Mixer, avail_rec: usize) {
     for entry in cb_conns.iter() {
         if let Some(scb) = entry {
+            // each subscriber is told how much room is left in the mixer stream it is feeding
+            let free_play = mixer.free_frames(scb.stream);
             // note that the "which" argument is only used for GPIO events, to indicate which pin had the
             // event
             xous::send_message(
@@ -0,0 +1,321 @@
+//! Software mixer that lets several clients share the CODEC output.
+//!
+//! Each client opens a stream with its own sample rate, volume and priority. Streams are resampled to the
+//! hardware rate with a linear interpolator and summed with saturation. Whenever a stream is playing, every
+//! stream with a lower priority is ducked to `duck_pct` percent of its volume.
+//!
+//! Samples use the same packing as the hardware FIFO: `|31 right 16|15 left 0|`, signed 16-bit.
+
+use std::collections::VecDeque;
+
+use crate::api::*;
+
+/// how many frames a stream may have queued before it stops accepting more
+pub const MAX_QUEUED_FRAMES: usize = 16;
+/// fixed point "one" for the resampler phase accumulator
+const PHASE_ONE: u32 = 1 << 16;
+const SILENCE: u32 = ZERO_PCM as u32 | (ZERO_PCM as u32) << 16;
+
+fn left(s: u32) -> i32 { (s & 0xFFFF) as u16 as i16 as i32 }
+
+fn right(s: u32) -> i32 { (s >> 16) as u16 as i16 as i32 }
+
+fn pack(l: i32, r: i32) -> u32 {
+    let l = l.clamp(i16::MIN as i32, i16::MAX as i32) as i16 as u16 as u32;
+    let r = r.clamp(i16::MIN as i32, i16::MAX as i32) as i16 as u16 as u32;
+    l | r << 16
+}
+
+/// The point `frac` (16.16 fixed point, below one) of the way from `a` to `b`. The difference of two samples
+/// times `frac` doesn't fit an `i32`, so this is done in `i64`.
+fn lerp(a: i32, b: i32, frac: u32) -> i32 { a + (((b - a) as i64 * frac as i64) >> 16) as i32 }
+
+/// Linear interpolating sample rate converter, operating on one stereo stream.
+pub struct Resampler {
+    /// input samples advanced per output sample, in 16.16 fixed point
+    step: u32,
+    /// position between `prev` (0) and the next queued sample (`PHASE_ONE`)
+    phase: u32,
+    prev: u32,
+}
+impl Resampler {
+    pub fn new(in_rate: u32, out_rate: u32) -> Self {
+        Resampler { step: Self::step_for(in_rate, out_rate), phase: PHASE_ONE, prev: SILENCE }
+    }
+
+    fn step_for(in_rate: u32, out_rate: u32) -> u32 {
+        ((in_rate as u64 * PHASE_ONE as u64) / out_rate as u64) as u32
+    }
+
+    pub fn set_rates(&mut self, in_rate: u32, out_rate: u32) {
+        self.step = Self::step_for(in_rate, out_rate);
+    }
+
+    /// Produces the next output sample, consuming input samples from `input` as needed. Returns `None` if
+    /// more input is required; the resampler state is preserved so it can pick up where it left off.
+    pub fn next_sample(&mut self, input: &mut VecDeque<u32>) -> Option<u32> {
+        if self.step == PHASE_ONE {
+            // rates match, no conversion necessary
+            return input.pop_front();
+        }
+        while self.phase >= PHASE_ONE {
+            self.prev = input.pop_front()?;
+            self.phase -= PHASE_ONE;
+        }
+        let next = *input.front()?;
+        let l = lerp(left(self.prev), left(next), self.phase);
+        let r = lerp(right(self.prev), right(next), self.phase);
+        self.phase += self.step;
+        Some(pack(l, r))
+    }
+}
+
+struct Stream {
+    id: u32,
+    rate: u32,
+    priority: StreamPriority,
+    /// volume in percent
+    volume: u8,
+    queue: VecDeque<u32>,
+    resampler: Resampler,
+}
+impl Stream {
+    fn is_active(&self) -> bool { !self.queue.is_empty() }
+}
+
+pub struct Mixer {
+    out_rate: u32,
+    streams: Vec<Stream>,
+    next_id: u32,
+    duck_pct: u8,
+}
+impl Mixer {
+    /// Creates a mixer with the legacy stream already open at the output rate
+    pub fn new(out_rate: SampleRate) -> Self {
+        let mut mixer = Mixer {
+            out_rate: out_rate.hz(),
+            streams: Vec::new(),
+            next_id: LEGACY_STREAM + 1,
+            duck_pct: DEFAULT_DUCK_PCT,
+        };
+        mixer.streams.push(Stream {
+            id: LEGACY_STREAM,
+            rate: out_rate.hz(),
+            priority: StreamPriority::Normal,
+            volume: 100,
+            queue: VecDeque::with_capacity(MAX_QUEUED_FRAMES * FIFO_DEPTH),
+            resampler: Resampler::new(out_rate.hz(), out_rate.hz()),
+        });
+        mixer
+    }
+
+    /// Changes the hardware output rate. The legacy stream follows the output rate.
+    pub fn set_out_rate(&mut self, out_rate: SampleRate) {
+        self.out_rate = out_rate.hz();
+        for stream in self.streams.iter_mut() {
+            if stream.id == LEGACY_STREAM {
+                stream.rate = out_rate.hz();
+            }
+            stream.resampler.set_rates(stream.rate, out_rate.hz());
+        }
+    }
+
+    pub fn open(
+        &mut self,
+        rate: SampleRate,
+        priority: StreamPriority,
+        volume: u8,
+    ) -> Result<u32, StreamError> {
+        if self.streams.len() >= MAX_STREAMS {
+            return Err(StreamError::NoStreamsAvailable);
+        }
+        let id = self.next_id;
+        // IDs are never re-used, so a stale handle can't play into somebody else's stream
+        self.next_id = self.next_id.wrapping_add(1).max(LEGACY_STREAM + 1);
+        self.streams.push(Stream {
+            id,
+            rate: rate.hz(),
+            priority,
+            volume: volume.min(100),
+            queue: VecDeque::with_capacity(MAX_QUEUED_FRAMES * FIFO_DEPTH),
+            resampler: Resampler::new(rate.hz(), self.out_rate),
+        });
+        Ok(id)
+    }
+
+    pub fn close(&mut self, id: u32) -> Result<(), StreamError> {
+        if id == LEGACY_STREAM {
+            // the legacy stream can't be closed, but closing it flushes it
+            self.stream_mut(id)?.queue.clear();
+            return Ok(());
+        }
+        let index = self.streams.iter().position(|s| s.id == id).ok_or(StreamError::InvalidStream)?;
+        self.streams.remove(index);
+        Ok(())
+    }
+
+    pub fn has_stream(&self, id: u32) -> bool { self.streams.iter().any(|s| s.id == id) }
+
+    fn stream_mut(&mut self, id: u32) -> Result<&mut Stream, StreamError> {
+        self.streams.iter_mut().find(|s| s.id == id).ok_or(StreamError::InvalidStream)
+    }
+
+    pub fn set_volume(&mut self, id: u32, volume: u8) -> Result<(), StreamError> {
+        self.stream_mut(id)?.volume = volume.min(100);
+        Ok(())
+    }
+
+    pub fn set_priority(&mut self, id: u32, priority: StreamPriority) -> Result<(), StreamError> {
+        self.stream_mut(id)?.priority = priority;
+        Ok(())
+    }
+
+    pub fn set_duck_level(&mut self, pct: u8) { self.duck_pct = pct.min(100); }
+
+    /// Number of whole frames that can still be queued into a stream
+    pub fn free_frames(&self, id: u32) -> usize {
+        match self.streams.iter().find(|s| s.id == id) {
+            Some(s) => MAX_QUEUED_FRAMES.saturating_sub(s.queue.len().div_ceil(FIFO_DEPTH)),
+            None => 0,
+        }
+    }
+
+    /// Queues a frame of samples, at the stream's own rate. Hands the frame back if the stream is full.
+    pub fn push_frame(&mut self, id: u32, frame: [u32; FIFO_DEPTH]) -> Result<(), [u32; FIFO_DEPTH]> {
+        if self.free_frames(id) == 0 {
+            return Err(frame);
+        }
+        match self.stream_mut(id) {
+            Ok(stream) => {
+                stream.queue.extend(frame.iter());
+                Ok(())
+            }
+            Err(_) => Err(frame),
+        }
+    }
+
+    pub fn is_active(&self) -> bool { self.streams.iter().any(|s| s.is_active()) }
+
+    /// Mixes one frame at the output rate. Returns `None` if no stream has anything to play.
+    pub fn mix_frame(&mut self) -> Option<[u32; FIFO_DEPTH]> {
+        let top_priority = self.streams.iter().filter(|s| s.is_active()).map(|s| s.priority).max()?;
+        let mut left_acc = [0i32; FIFO_DEPTH];
+        let mut right_acc = [0i32; FIFO_DEPTH];
+        for stream in self.streams.iter_mut().filter(|s| s.is_active()) {
+            // gain in percent, with ducking applied for streams that are outranked
+            let gain = if stream.priority < top_priority {
+                stream.volume as i32 * self.duck_pct as i32 / 100
+            } else {
+                stream.volume as i32
+            };
+            for (l, r) in left_acc.iter_mut().zip(right_acc.iter_mut()) {
+                // a stream that runs dry mid-frame simply contributes silence for the rest of the frame
+                let Some(sample) = stream.resampler.next_sample(&mut stream.queue) else { break };
+                *l += left(sample) * gain / 100;
+                *r += right(sample) * gain / 100;
+            }
+        }
+        let mut frame = [SILENCE; FIFO_DEPTH];
+        for ((dst, &l), &r) in frame.iter_mut().zip(left_acc.iter()).zip(right_acc.iter()) {
+            *dst = pack(l, r);
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant_frame(l: i16, r: i16) -> [u32; FIFO_DEPTH] { [pack(l as i32, r as i32); FIFO_DEPTH] }
+
+    #[test]
+    fn legacy_stream_passes_through() {
+        let mut mixer = Mixer::new(SampleRate::Rate8k);
+        mixer.push_frame(LEGACY_STREAM, constant_frame(1000, -1000)).unwrap();
+        let out = mixer.mix_frame().unwrap();
+        assert!(out.iter().all(|&s| left(s) == 1000 && right(s) == -1000));
+        assert!(mixer.mix_frame().is_none());
+    }
+
+    #[test]
+    fn mixing_saturates() {
+        let mut mixer = Mixer::new(SampleRate::Rate8k);
+        let id = mixer.open(SampleRate::Rate8k, StreamPriority::Normal, 100).unwrap();
+        mixer.push_frame(LEGACY_STREAM, constant_frame(30000, -30000)).unwrap();
+        mixer.push_frame(id, constant_frame(30000, -30000)).unwrap();
+        let out = mixer.mix_frame().unwrap();
+        assert!(out.iter().all(|&s| left(s) == i16::MAX as i32 && right(s) == i16::MIN as i32));
+    }
+
+    #[test]
+    fn lower_priority_is_ducked() {
+        let mut mixer = Mixer::new(SampleRate::Rate8k);
+        mixer.set_duck_level(50);
+        let low = mixer.open(SampleRate::Rate8k, StreamPriority::Low, 100).unwrap();
+        let high = mixer.open(SampleRate::Rate8k, StreamPriority::Critical, 100).unwrap();
+        mixer.push_frame(low, constant_frame(1000, 1000)).unwrap();
+        mixer.push_frame(high, constant_frame(100, 100)).unwrap();
+        let out = mixer.mix_frame().unwrap();
+        assert_eq!(left(out[0]), 600);
+        // once the high priority stream is done, the low priority stream returns to full volume
+        mixer.push_frame(low, constant_frame(1000, 1000)).unwrap();
+        let out = mixer.mix_frame().unwrap();
+        assert_eq!(left(out[0]), 1000);
+    }
+
+    #[test]
+    fn upsampling_produces_more_frames() {
+        let mut mixer = Mixer::new(SampleRate::Rate16k);
+        let id = mixer.open(SampleRate::Rate8k, StreamPriority::Normal, 100).unwrap();
+        for _ in 0..4 {
+            mixer.push_frame(id, constant_frame(500, 500)).unwrap();
+        }
+        let mut frames = 0;
+        while let Some(frame) = mixer.mix_frame() {
+            if frames > 0 {
+                assert!(frame[..FIFO_DEPTH / 2].iter().all(|&s| left(s) == 500));
+            }
+            frames += 1;
+        }
+        // 4 frames at 8kHz become ~8 frames at 16kHz, less the interpolator's one-sample lookahead
+        assert!(frames >= 7 && frames <= 8);
+    }
+
+    #[test]
+    fn resampling_full_scale_step() {
+        let mut resampler = Resampler::new(44_100, 48_000);
+        let mut input: VecDeque<u32> =
+            [pack(-32768, 32767), pack(32767, -32768), pack(32767, -32768)].into_iter().collect();
+        let (mut last_left, mut last_right) = (i16::MIN as i32, i16::MAX as i32);
+        while let Some(s) = resampler.next_sample(&mut input) {
+            // the step is interpolated without wrapping around
+            assert!(left(s) >= last_left && right(s) <= last_right);
+            (last_left, last_right) = (left(s), right(s));
+        }
+        assert!(last_left > 0 && last_right < 0);
+    }
+
+    #[test]
+    fn streams_are_limited() {
+        let mut mixer = Mixer::new(SampleRate::Rate48k);
+        for _ in 1..MAX_STREAMS {
+            mixer.open(SampleRate::Rate48k, StreamPriority::Normal, 100).unwrap();
+        }
+        assert!(matches!(
+            mixer.open(SampleRate::Rate48k, StreamPriority::Normal, 100),
+            Err(StreamError::NoStreamsAvailable)
+        ));
+        assert!(matches!(mixer.close(1234), Err(StreamError::InvalidStream)));
+    }
+
+    #[test]
+    fn full_stream_rejects_frames() {
+        let mut mixer = Mixer::new(SampleRate::Rate8k);
+        for _ in 0..MAX_QUEUED_FRAMES {
+            mixer.push_frame(LEGACY_STREAM, constant_frame(0, 0)).unwrap();
+        }
+        assert_eq!(mixer.free_frames(LEGACY_STREAM), 0);
+        assert!(mixer.push_frame(LEGACY_STREAM, constant_frame(0, 0)).is_err());
+    }
+}
@@ -16,7 +16,8 @@ pub(crate) enum Opcode {
     Lookup = 0,
     Flush = 1,
 
-    /// used internally to update the TTL field, and eventually expire the cache (unless cache is frozen)
+    /// sent by a periodic ticktimer alarm to update the TTL field, and eventually expire the cache (unless
+    /// cache is frozen)
     UpdateTtl = 2,
 
     /// issuing this opcode causes all future attempts to change the DNS server configs to be ignored. This
@@ -7,7 +7,6 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io::ErrorKind;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
-use std::thread;
 use std::time::Duration;
 
 use api::*;
@@ -475,28 +474,18 @@ fn main() -> ! {
     // the `u32` value is the TTL of the IpAddr
     let mut dns_cache = HashMap::<std::string::String, HashMap<IpAddr, u32>>::new();
 
-    // build a thread that pings the UpdateTtl function once every few minutes to expire the DNS cache
-    thread::spawn({
-        let local_cid = xous::connect(dns_sid).unwrap();
-        move || {
-            const TTL_INTERVAL_SECS: usize = 300; // every 5 minutes update the map
-            let tt = ticktimer_server::Ticktimer::new().unwrap();
-            loop {
-                tt.sleep_ms(TTL_INTERVAL_SECS * 1000).unwrap();
-                xous::send_message(
-                    local_cid,
-                    xous::Message::new_scalar(
-                        Opcode::UpdateTtl.to_usize().unwrap(),
-                        TTL_INTERVAL_SECS,
-                        0,
-                        0,
-                        0,
-                    ),
-                )
-                .expect("couldn't increment DNS cache");
-            }
-        }
-    });
+    // have the ticktimer ping the UpdateTtl function once every few minutes to expire the DNS cache
+    const TTL_INTERVAL_SECS: u32 = 300; // every 5 minutes update the map
+    let tt = ticktimer_server::Ticktimer::new().unwrap();
+    let _ttl_alarm = tt
+        .set_alarm(
+            dns_sid,
+            Opcode::UpdateTtl.to_u32().unwrap(),
+            TTL_INTERVAL_SECS,
+            ticktimer_server::AlarmTime::AfterMs(TTL_INTERVAL_SECS as u64 * 1000),
+            Some(TTL_INTERVAL_SECS as u64 * 1000),
+        )
+        .expect("couldn't set the DNS cache alarm");
 
     log::trace!("ready to accept requests");
     loop {
@@ -594,7 +583,9 @@ fn main() -> ! {
                     }
                 }
             }
-            Some(Opcode::UpdateTtl) => msg_scalar_unpack!(msg, incr_secs, _, _, _, {
+            Some(Opcode::UpdateTtl) => msg_scalar_unpack!(msg, _alarm, incr_secs, periods, _, {
+                // `periods` is more than 1 if alarms were missed, e.g. while suspended
+                let incr_secs = incr_secs.saturating_mul(periods.max(1));
                 let increment = if incr_secs < u32::MAX as usize { incr_secs as u32 } else { u32::MAX };
                 if !resolver.get_freeze() {
                     let mut expired_names = Vec::<std::string::String>::new();
@@ -15,6 +15,9 @@ Thanks!
 
 Keymap: offset 0, size 4
 Early sleep: offset 4, size 4
+
+The last page of the sector (0xF000) is reserved for the anti-rollback counter, see
+`spinor::ANTIROLLBACK_LOC`. It is read by the loader and written only by root-keys.
 */
 
 const KEYMAP: Slot = Slot { offset: 0, size: 4 };
@@ -227,6 +227,65 @@ pub(crate) enum Opcode {
     /// Register a name that can acquire a token. This is only intended to be used with pre-registered apps
     #[cfg(feature = "unsafe-app-loading")]
     RegisterName = 34,
+
+    /// Switch the language the screen is drawn in; the argument is an index into `locales::LANGUAGES`
+    SetLanguage = 35,
+
+    /// Attach an accessible label to a canvas, or to a bitmap drawn on it (AccessibleLabel)
+    AccessibleLabel = 36,
+
+    /// Turn the screen reader on or off (ScreenReaderSwitch)
+    SetScreenReader = 37,
+
+    /// Look up the app a token was issued to (TokenIdentity)
+    IdentifyToken = 38,
+
+    /// Mark the text input of an app as sensitive, so the IME predictor doesn't learn from it
+    /// (SensitiveInput)
+    SetInputSensitive = 39,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Clone)]
+pub(crate) struct AccessibleLabel {
+    pub(crate) canvas: Gid,
+    pub(crate) label: String,
+    /// the token of the context the canvas belongs to, for the label of the canvas itself, which is read
+    /// out when it gets focus; `None` for the label of a bitmap that was just drawn on it, which is read out
+    /// like text drawn there
+    pub(crate) owner: Option<[u32; 4]>,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct ScreenReaderSwitch {
+    /// the caller's GAM token; only apps built into the image may switch the screen reader
+    pub(crate) token: [u32; 4],
+    pub(crate) enable: bool,
+    /// set by the GAM to whether the screen reader is now on, or `None` if the token wasn't accepted
+    pub(crate) on: Option<bool>,
+}
+
+/// The app a GAM token was issued to. Other services use this to extend the GAM's trust model to their
+/// own APIs, by having callers pass in their token.
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Clone, PartialEq, Eq)]
+pub struct TokenOwner {
+    /// the name the token was claimed with
+    pub name: String,
+    /// true for apps loaded through `app-loader` after boot, which are less trusted than the ones built
+    /// into the image
+    pub loaded: bool,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct SensitiveInput {
+    pub(crate) token: [u32; 4],
+    pub(crate) sensitive: bool,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub(crate) struct TokenIdentity {
+    pub(crate) token: [u32; 4],
+    /// set by the GAM; `None` if the token isn't valid
+    pub(crate) owner: Option<TokenOwner>,
 }
 
 // small wart -- we have to reset the size of a modal to max size for resize computations
@@ -41,6 +41,7 @@ pub(crate) enum MenuMgrOp {
     InsertItem(usize),
     DeleteItem,
     SetIndex(usize),
+    Clear,
     Quit,
     // response must be one of these
     Ok,
@@ -39,6 +39,8 @@ pub struct Bitmap {
     pub bound: Rectangle,
     tile_bits: usize,
     mosaic: Vec<Tile>,
+    /// what the bitmap shows, read out by the screen reader when it is drawn
+    pub label: Option<String>,
 }
 
 impl Bitmap {
@@ -59,7 +61,13 @@ impl Bitmap {
             tl = Point::new(0, br.y + 1);
             br = Point::new(size.x, tl.y);
         }
-        Self { width: size.x as usize + 1, bound: Rectangle::new(Point::new(0, 0), size), tile_bits, mosaic }
+        Self {
+            width: size.x as usize + 1,
+            bound: Rectangle::new(Point::new(0, 0), size),
+            tile_bits,
+            mosaic,
+            label: None,
+        }
     }
 
     pub fn from_img(img: &Img, fit: Option<Point>) -> Self {
@@ -144,6 +152,7 @@ impl Bitmap {
             bound,
             tile_bits: tile_bits.try_into().unwrap(),
             mosaic,
+            label: None,
         };
 
         if rotate { bm.rotate90() } else { bm }
@@ -282,6 +291,7 @@ impl Bitmap {
             x = x + bits_per_word;
             r90_y = r90_y + bits_per_word;
         }
+        r90.label = self.label.clone();
         r90
     }
 }
@@ -311,6 +321,7 @@ impl From<[Option<Tile>; 6]> for Bitmap {
             bound: Self::hull(&mosaic),
             tile_bits: (tile_size.x + 1) as usize * (tile_size.y + 1) as usize,
             mosaic,
+            label: None,
         }
     }
 }
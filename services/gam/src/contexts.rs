@@ -10,6 +10,7 @@ use log::info;
 use xous_ipc::Buffer;
 
 use crate::api::Opcode;
+use crate::screenreader::ScreenReader;
 use crate::*;
 
 // todo:
@@ -96,6 +97,8 @@ pub(crate) struct UxContext {
     pub focuschange_id: Option<u32>,
     /// sets the behavior of the IMEF
     pub imef_menu_mode: bool,
+    /// keeps the IMEF from feeding what is typed back to the predictor
+    pub imef_sensitive: bool,
 }
 pub(crate) const BOOT_CONTEXT_TRUSTLEVEL: u8 = 254;
 
@@ -121,6 +124,7 @@ pub(crate) struct ContextManager {
     tt: ticktimer_server::Ticktimer,
     /// used to suppress the main menu from activating until the boot PIN has been requested
     allow_mainmenu: bool,
+    reader: ScreenReader,
 }
 impl ContextManager {
     pub fn new(xns: &xous_names::XousNames) -> Self {
@@ -144,6 +148,7 @@ impl ContextManager {
             trng: trng::Trng::new(&xns).expect("couldn't connect to trng"),
             tt: ticktimer_server::Ticktimer::new().unwrap(),
             allow_mainmenu: false,
+            reader: ScreenReader::new(),
         }
     }
 
@@ -156,6 +161,11 @@ impl ContextManager {
 
     pub(crate) fn allow_untrusted_code(&self) -> bool { self.tm.allow_untrusted_code() }
 
+    pub(crate) fn identify_token(&self, token: &[u32; 4]) -> Option<TokenOwner> {
+        let name = self.tm.lookup_name(token)?;
+        Some(TokenOwner { loaded: self.tm.is_loaded_app(&name), name })
+    }
+
     pub(crate) fn is_token_valid(&self, token: [u32; 4]) -> bool { self.tm.is_token_valid(token) }
 
     pub(crate) fn register(
@@ -192,6 +202,7 @@ impl ContextManager {
                         rawkeys_id: registration.rawkeys_id,
                         vibe: false,
                         imef_menu_mode: false,
+                        imef_sensitive: false,
                         // this gets initialized on the first attempt to change predictors, not here
                         pred_token: None,
                     };
@@ -221,6 +232,7 @@ impl ContextManager {
                         rawkeys_id: registration.rawkeys_id,
                         vibe: false,
                         imef_menu_mode: false,
+                        imef_sensitive: false,
                         pred_token: None,
                     };
 
@@ -258,6 +270,7 @@ impl ContextManager {
                         rawkeys_id: registration.rawkeys_id,
                         vibe: false,
                         imef_menu_mode: false,
+                        imef_sensitive: false,
                         pred_token: None,
                     };
                     self.contexts.insert(token, ux_context);
@@ -293,6 +306,7 @@ impl ContextManager {
                         rawkeys_id: registration.rawkeys_id,
                         vibe: false,
                         imef_menu_mode: false,
+                        imef_sensitive: false,
                         pred_token: None,
                     };
                     self.contexts.insert(token, ux_context);
@@ -542,6 +556,7 @@ impl ContextManager {
             let maybe_new_focus = self.get_context_by_token(token);
             if let Some(context) = maybe_new_focus {
                 self.imef.set_menu_mode(context.imef_menu_mode).expect("couldn't set menu mode");
+                self.imef.set_sensitive(context.imef_sensitive).expect("couldn't set sensitive input");
                 if clear {
                     context.layout.clear(gfx, canvases).expect("can't clear on context activation");
                 }
@@ -559,6 +574,7 @@ impl ContextManager {
                 self.last_context = self.focused_context;
                 self.focused_context = Some(last_token);
             }
+            self.announce_focus(token);
             log::trace!("context stack: {:x?}", self.context_stack);
             if self.context_stack.len() > 1 {
                 // we've now got a stack of contexts, start stashing copies
@@ -586,6 +602,75 @@ impl ContextManager {
         Ok(())
     }
 
+    /// Has the screen reader read out that `token` has focus
+    fn announce_focus(&mut self, token: [u32; 4]) {
+        if !self.reader.is_enabled() {
+            return;
+        }
+        if let Some(context) = self.contexts.get(&token) {
+            // modals are introduced by their own text
+            let name = match context.layout {
+                UxLayout::ModalLayout(_) => None,
+                _ => self.tm.lookup_name(&token),
+            };
+            let gids: Vec<Gid> = context.layout.get_gids().iter().map(|gr| gr.gid).collect();
+            let alert = context.layout.behavior() == LayoutBehavior::Alert;
+            self.reader.focus(name.as_deref(), alert, &gids);
+        }
+    }
+
+    /// The type of the canvas `gid`, if it belongs to the focused context
+    fn focused_canvas_type(&self, gid: Gid) -> Option<CanvasType> {
+        self.focused_context()?.layout.get_gids().iter().find(|gr| gr.gid == gid).map(|gr| gr.canvas_type)
+    }
+
+    /// Hands a `TextView` that was just drawn to the screen reader, if the focused context drew it
+    pub(crate) fn read_textview(&mut self, tv: &TextView) {
+        if !self.reader.is_enabled() {
+            return;
+        }
+        if let Some(canvas_type) = self.focused_canvas_type(tv.get_canvas_gid()) {
+            self.reader.text(tv, canvas_type);
+        }
+    }
+
+    pub(crate) fn accessible_label(&mut self, label: AccessibleLabel) {
+        if let Some(token) = label.owner {
+            let owns_canvas = self
+                .contexts
+                .get(&token)
+                .is_some_and(|context| context.layout.get_gids().iter().any(|gr| gr.gid == label.canvas));
+            if !owns_canvas {
+                log::warn!("refusing to label canvas {:?}: the token doesn't own it", label.canvas);
+                return;
+            }
+            self.reader.set_label(label.canvas, label.label.as_str());
+            // drop the labels of canvases that no longer belong to any context
+            let live: Vec<Gid> = self
+                .contexts
+                .values()
+                .flat_map(|context| context.layout.get_gids())
+                .map(|gr| gr.gid)
+                .collect();
+            self.reader.retain_labels(|gid| live.contains(gid));
+        } else if self.reader.is_enabled() && self.focused_canvas_type(label.canvas).is_some() {
+            self.reader.label(label.label.as_str());
+        }
+    }
+
+    /// Turns the screen reader on or off, returning whether it is on
+    pub(crate) fn set_screen_reader(&mut self, enable: bool) -> bool {
+        match self.reader.set_enabled(enable) {
+            Ok(_) => {
+                if let Some(token) = self.focused_context {
+                    self.announce_focus(token);
+                }
+            }
+            Err(e) => log::warn!("couldn't turn the screen reader on: {:?}", e),
+        }
+        self.reader.is_enabled()
+    }
+
     pub(crate) fn set_pred_api_token(&mut self, at: ApiToken) {
         for context in self.contexts.values_mut() {
             if context.gam_token == at.gam_token {
@@ -811,6 +896,17 @@ impl ContextManager {
         }
     }
 
+    pub(crate) fn set_input_sensitive(&mut self, token: [u32; 4], sensitive: bool) {
+        if let Some(context) = self.contexts.get_mut(&token) {
+            context.imef_sensitive = sensitive;
+            log::debug!("sensitive input for token {:?} is now {}", token, sensitive);
+            // menu mode only changes on the next activation, but this has to hold from the next keystroke
+            if self.focused_context == Some(token) {
+                self.imef.set_sensitive(sensitive).expect("couldn't set sensitive input");
+            }
+        }
+    }
+
     pub(crate) fn raise_menu(
         &mut self,
         name: &str,
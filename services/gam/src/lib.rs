@@ -231,6 +231,11 @@ impl Gam {
             let buf = Buffer::into_buf(gt).or(Err(xous::Error::InternalError))?;
             buf.lend(self.conn, Opcode::RenderTile.to_u32().unwrap()).map(|_| ())?;
         }
+        if let Some(label) = bm.label.as_ref() {
+            let label = AccessibleLabel { canvas: gid, label: label.clone(), owner: None };
+            let buf = Buffer::into_buf(label).or(Err(xous::Error::InternalError))?;
+            buf.lend(self.conn, Opcode::AccessibleLabel.to_u32().unwrap()).map(|_| ())?;
+        }
         Ok(())
     }
 
@@ -355,6 +360,16 @@ impl Gam {
             .map(|_| ())
     }
 
+    /// Marks the text input of the app with `token` as sensitive, or not. While it is, nothing typed into it
+    /// is fed back to the IME predictor, so a secret typed there can't turn up among its predictions.
+    pub fn set_input_sensitive(&self, token: [u32; 4], sensitive: bool) -> Result<(), xous::Error> {
+        let buf =
+            Buffer::into_buf(SensitiveInput { token, sensitive }).or(Err(xous::Error::InternalError))?;
+        buf.send(self.conn, Opcode::SetInputSensitive.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))
+            .map(|_| ())
+    }
+
     pub fn trusted_init_done(&self) -> Result<bool, xous::Error> {
         let response = send_message(
             self.conn,
@@ -488,6 +503,54 @@ impl Gam {
             .map(|_| ())
     }
 
+    /// Switches the fonts and the focused UX to `lang`, and redraws it. Strings are looked up by each
+    /// process that draws them, so the processes that show text have to be switched as well, with
+    /// `locales::set_lang()` or through their own servers (e.g. `Modals::set_language()`).
+    pub fn set_language(&self, lang: &str) -> Result<(), xous::Error> {
+        let index = locales::LANGUAGES.iter().position(|l| *l == lang).ok_or(xous::Error::InvalidString)?;
+        send_message(self.conn, Message::new_scalar(Opcode::SetLanguage.to_usize().unwrap(), index, 0, 0, 0))
+            .map(|_| ())
+    }
+
+    /// Gives the canvas `gid` an accessible label, which the screen reader reads out whenever the canvas
+    /// gets focus. Use this for canvases whose content isn't text, such as the framebuffer of a game. An
+    /// empty label removes it. `token` is the caller's GAM token: only the owner of a canvas can label it.
+    pub fn set_accessible_label(&self, token: [u32; 4], gid: Gid, label: &str) -> Result<(), xous::Error> {
+        let label = AccessibleLabel { canvas: gid, label: String::from(label), owner: Some(token) };
+        let buf = Buffer::into_buf(label).or(Err(xous::Error::InternalError))?;
+        buf.lend(self.conn, Opcode::AccessibleLabel.to_u32().unwrap()).map(|_| ())
+    }
+
+    /// Turns the screen reader on or off. While it is on, the GAM reads out changes of focus, the selected
+    /// items of menus and modals, and new text drawn by the focused context, with the `tts` server. Turning
+    /// it on fails with `ServerNotFound` in an image built without the `tts` server.
+    ///
+    /// `token` is the caller's GAM token. Apps loaded after boot can't switch the screen reader, and get
+    /// `AccessDenied`.
+    pub fn set_screen_reader(&self, token: [u32; 4], enable: bool) -> Result<(), xous::Error> {
+        let switch = ScreenReaderSwitch { token, enable, on: None };
+        let mut buf = Buffer::into_buf(switch).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::SetScreenReader.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let switch = buf.to_original::<ScreenReaderSwitch, _>().or(Err(xous::Error::InternalError))?;
+        match switch.on {
+            Some(on) if on == enable => Ok(()),
+            Some(_) => Err(xous::Error::ServerNotFound),
+            None => Err(xous::Error::AccessDenied),
+        }
+    }
+
+    /// Looks up the app that `token` was issued to, or `None` if it isn't a valid token. This lets a service
+    /// take a caller's token as proof of who it is, and treat apps loaded at runtime with more caution.
+    pub fn identify_token(&self, token: [u32; 4]) -> Result<Option<TokenOwner>, xous::Error> {
+        let identity = TokenIdentity { token, owner: None };
+        let mut buf = Buffer::into_buf(identity).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::IdentifyToken.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let identity = buf.to_original::<TokenIdentity, _>().or(Err(xous::Error::InternalError))?;
+        Ok(identity.owner)
+    }
+
     pub fn selftest(&self, duration_ms: usize) {
         send_message(
             self.conn,
@@ -10,6 +10,7 @@ use layouts::*;
 mod contexts;
 use contexts::*;
 mod bip39;
+mod screenreader;
 
 use core::sync::atomic::{AtomicU32, Ordering};
 use std::collections::HashMap;
@@ -47,6 +48,7 @@ fn main() -> ! {
 fn wrapped_main() -> ! {
     log_server::init_wait().unwrap();
     log::set_max_level(log::LevelFilter::Info);
+    log_server::register_levels("gam", &["gam"]).expect("couldn't register for log level control");
     info!("my PID is {}", xous::process::id());
 
     let xns = xous_names::XousNames::new().unwrap();
@@ -224,12 +226,13 @@ fn wrapped_main() -> ! {
                 })
             }
             Some(Opcode::SetDebugLevel) => msg_blocking_scalar_unpack!(msg, level, _, _, _, {
-                match level {
-                    0 => log::set_max_level(log::LevelFilter::Info),
-                    1 => log::set_max_level(log::LevelFilter::Debug),
-                    2 => log::set_max_level(log::LevelFilter::Trace),
-                    _ => log::set_max_level(log::LevelFilter::Info),
-                }
+                let filter = match level {
+                    1 => log::LevelFilter::Debug,
+                    2 => log::LevelFilter::Trace,
+                    _ => log::LevelFilter::Info,
+                };
+                // goes through the log server, so the change shows up with everyone else's levels
+                log_server::set_level(None, None, filter).ok();
                 xous::return_scalar(msg.sender, level).unwrap();
             }),
             Some(Opcode::RenderTextView) => {
@@ -283,12 +286,13 @@ fn wrapped_main() -> ! {
                                 tv.bounds_computed = tv_clone.bounds_computed;
                                 tv.overflow = tv_clone.overflow;
                                 tv.busy_animation_state = tv_clone.busy_animation_state;
-
-                                let ret = api::Return::RenderReturn(tv);
-                                buffer.replace(ret).unwrap();
                                 if canvas.is_onscreen() {
                                     canvas.do_drawn().expect("couldn't set canvas to drawn");
+                                    context_mgr.read_textview(&tv);
                                 }
+
+                                let ret = api::Return::RenderReturn(tv);
+                                buffer.replace(ret).unwrap();
                             } else {
                                 log::debug!(
                                     "attempt to draw TextView on non-drawable canvas. Not fatal, but request ignored. {:?}",
@@ -540,6 +544,11 @@ fn wrapped_main() -> ! {
                 let at = buf.to_original::<ApiToken, _>().unwrap();
                 context_mgr.set_pred_api_token(at);
             }
+            Some(Opcode::SetInputSensitive) => {
+                let buf = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let request = buf.to_original::<SensitiveInput, _>().unwrap();
+                context_mgr.set_input_sensitive(request.token, request.sensitive);
+            }
             Some(Opcode::TrustedInitDone) => xous::msg_blocking_scalar_unpack!(msg, _, _, _, _, {
                 if context_mgr.allow_untrusted_code() {
                     xous::return_scalar(msg.sender, 1).unwrap();
@@ -714,6 +723,39 @@ fn wrapped_main() -> ! {
                     gfx.set_devboot(false).expect("couldn't send devboot message");
                 }
             }),
+            Some(Opcode::SetLanguage) => msg_scalar_unpack!(msg, index, _, _, _, {
+                if let Some(lang) = locales::LANGUAGES.get(index) {
+                    locales::set_lang(lang);
+                    gfx.set_language(lang).expect("couldn't set the language of the graphics server");
+                    context_mgr.redraw().ok();
+                    context_mgr.redraw_imef().ok();
+                }
+            }),
+            Some(Opcode::AccessibleLabel) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let label = buffer.to_original::<AccessibleLabel, _>().unwrap();
+                context_mgr.accessible_label(label);
+            }
+            Some(Opcode::SetScreenReader) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut switch = buffer.to_original::<ScreenReaderSwitch, _>().unwrap();
+                switch.on = match context_mgr.identify_token(&switch.token) {
+                    Some(owner) if !owner.loaded => Some(context_mgr.set_screen_reader(switch.enable)),
+                    _ => {
+                        log::warn!("screen reader switch refused: the token isn't that of a built-in app");
+                        None
+                    }
+                };
+                buffer.replace(switch).unwrap();
+            }
+            Some(Opcode::IdentifyToken) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut identity = buffer.to_original::<TokenIdentity, _>().unwrap();
+                identity.owner = context_mgr.identify_token(&identity.token);
+                buffer.replace(identity).unwrap();
+            }
             Some(Opcode::TestPattern) => msg_blocking_scalar_unpack!(msg, duration_ms, _, _, _, {
                 if !did_test {
                     did_test = true;
@@ -180,6 +180,13 @@ impl<'a> Menu<'a> {
         }
     }
 
+    /// Removes every item, e.g. to add them back in a different language. The canvas is resized by the
+    /// next `add_item()`.
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.index = 0;
+    }
+
     // note: this routine has yet to be tested. (remove this comment once it has been actually used by
     // something)
     pub fn delete_item(&mut self, item: &str) -> bool {
@@ -464,6 +471,26 @@ impl MenuMatic {
         if ret.op == MenuMgrOp::Ok { true } else { false }
     }
 
+    /// Replaces all the items of the menu with `items`
+    pub fn set_items(&self, items: Vec<MenuItem>) {
+        let mm = MenuManagement {
+            item: MenuItem {
+                // dummy record
+                name: String::new(),
+                action_conn: None,
+                action_opcode: 0,
+                action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
+                close_on_select: false,
+            },
+            op: MenuMgrOp::Clear,
+        };
+        let mut buf = Buffer::into_buf(mm).expect("Couldn't convert to memory structure");
+        buf.lend_mut(self.cid, 0).expect("Couldn't issue management opcode");
+        for item in items {
+            self.add_item(item);
+        }
+    }
+
     pub fn set_index(&self, index: usize) {
         let op = MenuManagement {
             item: MenuItem {
@@ -590,6 +617,11 @@ pub fn menu_matic(
                             mgmt.op = MenuMgrOp::Ok;
                             buffer.replace(mgmt).unwrap();
                         }
+                        MenuMgrOp::Clear => {
+                            menu.lock().unwrap().clear();
+                            mgmt.op = MenuMgrOp::Ok;
+                            buffer.replace(mgmt).unwrap();
+                        }
                         MenuMgrOp::Quit => {
                             mgmt.op = MenuMgrOp::Ok;
                             buffer.replace(mgmt).unwrap();
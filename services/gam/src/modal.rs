@@ -2,6 +2,8 @@
 
 mod textentry;
 pub use textentry::*;
+mod texteditor;
+pub use texteditor::*;
 mod radiobuttons;
 pub use radiobuttons::*;
 mod checkboxes;
@@ -37,6 +39,7 @@ pub const MAX_ITEMS: usize = 8;
 #[enum_dispatch(ActionApi)]
 pub enum ActionType {
     TextEntry,
+    TextEditor,
     Bip39Entry,
     RadioButtons,
     CheckBoxes,
@@ -368,7 +371,7 @@ impl<'a> Modal<'a> {
         log::debug!("requesting content canvas for modal");
         let canvas =
             gam.request_content_canvas(authtoken.unwrap()).expect("couldn't get my content canvas from GAM");
-        let line_height = if locales::LANG == "zh" {
+        let line_height = if locales::lang() == "zh" {
             // zh has no "small" style
             gam.glyph_height_hint(GlyphStyle::Regular).expect("couldn't get glyph height hint") as i16
         } else {
@@ -208,13 +208,13 @@ impl ActionApi for Bip39Entry {
             // put in some error/guidance text instead of a recco list
             let mut guidance = String::new();
             if self.user_input.len() > 0 {
-                guidance.push_str(t!("bip39.invalid_word", locales::LANG));
+                guidance.push_str(t!("bip39.invalid_word", locales::lang()));
             } else {
                 // no input yet, give some options
                 if self.payload.is_some() {
-                    guidance.push_str(t!("bip39.enter_to_complete", locales::LANG));
+                    guidance.push_str(t!("bip39.enter_to_complete", locales::lang()));
                 } else {
-                    guidance.push_str(t!("bip39.start_typing", locales::LANG));
+                    guidance.push_str(t!("bip39.start_typing", locales::lang()));
                 }
             }
             let mut tv = TextView::new(
@@ -291,16 +291,16 @@ impl ActionApi for Bip39Entry {
         // ------- status --------
         let mut status = String::new();
         if let Some(p) = &self.payload {
-            status.push_str(t!("bip39.valid_phrase", locales::LANG));
+            status.push_str(t!("bip39.valid_phrase", locales::lang()));
             status.push_str(" ");
             status.push_str(&hex::encode(&p));
         } else {
             if self.user_input.len() == 0 && self.accepted_words.len() == 0 {
-                status.push_str(t!("bip39.waiting", locales::LANG));
+                status.push_str(t!("bip39.waiting", locales::lang()));
             } else {
-                status.push_str(t!("bip39.invalid_phrase", locales::LANG));
+                status.push_str(t!("bip39.invalid_phrase", locales::lang()));
                 status.push_str("\n");
-                status.push_str(t!("bip39.abort_help", locales::LANG));
+                status.push_str(t!("bip39.abort_help", locales::lang()));
             }
         }
         let mut tv = TextView::new(
@@ -392,7 +392,7 @@ impl ActionApi for Bip39Entry {
                 {
                     let xns = xous_names::XousNames::new().unwrap();
                     let tts = tts_frontend::TtsFrontend::new(&xns).unwrap();
-                    tts.tts_blocking(locales::t!("input.delete-tts", locales::LANG)).unwrap();
+                    tts.tts_blocking(locales::t!("input.delete-tts", locales::lang())).unwrap();
                 }
                 if self.user_input.len() > 0 {
                     // don't backspace if we have no string.
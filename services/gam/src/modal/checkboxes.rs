@@ -129,7 +129,7 @@ impl ActionApi for CheckBoxes {
             modal.gam.post_textview(&mut tv).expect("couldn't post tv");
             #[cfg(feature = "tts")]
             {
-                self.tts.tts_blocking(t!("checkbox.select_and_close_tts", locales::LANG)).unwrap();
+                self.tts.tts_blocking(t!("checkbox.select_and_close_tts", locales::lang())).unwrap();
                 for item in self.action_payload.clone().payload().iter() {
                     if let Some(name) = item {
                         self.tts.tts_blocking(name.as_str()).unwrap();
@@ -144,7 +144,7 @@ impl ActionApi for CheckBoxes {
             Point::new(text_x, cur_y),
             Point::new(modal.canvas_width - modal.margin, cur_y + modal.line_height),
         ));
-        write!(tv, "{}", t!("radio.select_and_close", locales::LANG)).unwrap();
+        write!(tv, "{}", t!("radio.select_and_close", locales::lang())).unwrap();
         modal.gam.post_textview(&mut tv).expect("couldn't post tv");
 
         // divider lines
@@ -185,7 +185,7 @@ impl ActionApi for CheckBoxes {
                         self.action_payload.remove(item_name);
                         #[cfg(feature = "tts")]
                         {
-                            self.tts.tts_blocking(t!("checkbox.uncheck", locales::LANG)).unwrap();
+                            self.tts.tts_blocking(t!("checkbox.uncheck", locales::lang())).unwrap();
                             self.tts.tts_blocking(item_name).unwrap();
                         }
                     } else {
@@ -198,7 +198,7 @@ impl ActionApi for CheckBoxes {
                         } else {
                             #[cfg(feature = "tts")]
                             {
-                                self.tts.tts_blocking(t!("checkbox.check", locales::LANG)).unwrap();
+                                self.tts.tts_blocking(t!("checkbox.check", locales::lang())).unwrap();
                                 self.tts.tts_blocking(item_name).unwrap();
                             }
                         }
@@ -46,7 +46,7 @@ impl Notification {
             Some(setting) => {
                 let qrcode = match QrCode::new(setting) {
                     Ok(code) => code,
-                    Err(_e) => QrCode::new(t!("notification.qrcode.error", locales::LANG)).unwrap(),
+                    Err(_e) => QrCode::new(t!("notification.qrcode.error", locales::lang())).unwrap(),
                 };
                 self.qrwidth = qrcode.width();
                 log::info!("qrcode {}x{} : {} bytes ", self.qrwidth, self.qrwidth, setting.len());
@@ -80,7 +80,7 @@ impl Notification {
             Point::new(modal.margin, at_height + modal.margin * 2),
             (modal.canvas_width - modal.margin * 2) as u16,
         );
-        write!(tv, "{}", t!("notification.dismiss", locales::LANG)).unwrap();
+        write!(tv, "{}", t!("notification.dismiss", locales::lang())).unwrap();
         modal.gam.bounds_compute_textview(&mut tv).expect("couldn't simulate text size");
         let textwidth = if let Some(bounds) = tv.bounds_computed {
             bounds.br.x - bounds.tl.x
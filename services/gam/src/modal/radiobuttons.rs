@@ -134,7 +134,7 @@ impl ActionApi for RadioButtons {
             modal.gam.post_textview(&mut tv).expect("couldn't post tv");
             #[cfg(feature = "tts")]
             {
-                self.tts.tts_blocking(t!("radio.select_and_close_tts", locales::LANG)).unwrap();
+                self.tts.tts_blocking(t!("radio.select_and_close_tts", locales::lang())).unwrap();
                 self.tts.tts_blocking(self.action_payload.as_str()).unwrap();
             }
         }
@@ -145,7 +145,7 @@ impl ActionApi for RadioButtons {
             Point::new(text_x, cur_y),
             Point::new(modal.canvas_width - modal.margin, cur_y + modal.line_height),
         ));
-        write!(tv, "{}", t!("radio.select_and_close", locales::LANG)).unwrap();
+        write!(tv, "{}", t!("radio.select_and_close", locales::lang())).unwrap();
         modal.gam.post_textview(&mut tv).expect("couldn't post tv");
 
         // divider lines
@@ -185,7 +185,7 @@ impl ActionApi for RadioButtons {
                         RadioButtonPayload::new(self.items[self.select_index as usize].as_str());
                     #[cfg(feature = "tts")]
                     {
-                        self.tts.tts_blocking(t!("radio.selection_tts", locales::LANG)).unwrap();
+                        self.tts.tts_blocking(t!("radio.selection_tts", locales::lang())).unwrap();
                         self.tts.tts_simple(self.items[self.select_index as usize].as_str()).unwrap();
                     }
                 } else {
@@ -0,0 +1,626 @@
+use core::cell::Cell;
+use core::fmt::Write;
+
+use clipboard_api::{Clipboard, Sensitivity};
+use graphics_server::api::GlyphStyle;
+use graphics_server::api::*;
+use locales::t;
+use xous_ipc::Buffer;
+
+use crate::*;
+
+/// Lines of text shown at once; longer texts scroll
+const ROWS: usize = 10;
+/// Extra space between lines of text, in pixels
+const LINE_SPACING: i16 = 2;
+/// Room left on the right for the scroll bar, in pixels
+const SCROLLBAR_WIDTH: i16 = 6;
+/// Used if the width of a monospace character can't be measured
+const DEFAULT_CHAR_WIDTH: i16 = 8;
+/// Text measured to find the width of a monospace character
+const MEASURE_TEXT: &str = "0000000000";
+/// Characters allowed if the caller doesn't set a limit
+pub const DEFAULT_EDITOR_LIMIT: usize = 4096;
+/// The most characters an editor can be asked to hold; its buffer is allocated up front
+pub const MAX_EDITOR_LIMIT: usize = 16384;
+
+/// A multi-line text editor. Text is drawn in a monospace font so it can be wrapped into lines here, which is
+/// what lets the cursor move up and down by line and the view scroll to follow it.
+///
+/// - arrow keys move the cursor
+/// - enter starts a new line
+/// - F1 starts a selection at the cursor, or drops it; moving the cursor then selects text
+/// - F2 copies the selection to the clipboard, F3 cuts it, F4 pastes
+/// - select (∴) closes the editor and returns the text
+///
+/// Typing beyond the character limit is ignored, and pastes are cut short to fit. The clipboard is used
+/// with the GAM token of the editor's owner. For the `modals` server that token doesn't say which app the
+/// text is being edited for, so sensitive entries aren't pasted.
+pub struct TextEditor {
+    pub action_conn: xous::CID,
+    pub action_opcode: u32,
+    /// GAM token the clipboard is used with
+    token: [u32; 4],
+    /// connected to on first use
+    clipboard: Option<Clipboard>,
+    buffer: EditBuffer,
+    /// shown until a key is hit in an empty editor, and returned if nothing was typed
+    placeholder: Option<String>,
+    keys_hit: bool,
+    /// width of a monospace character, measured before the first redraw
+    char_width: Cell<i16>,
+    /// how many characters fit on a line
+    columns: Cell<usize>,
+    /// the first line shown
+    top_line: Cell<usize>,
+}
+
+impl TextEditor {
+    /// Creates an editor holding at most `max_chars` characters. A `placeholder` that persists is the
+    /// initial text to be edited; otherwise it is only shown while the editor is empty. `token` is the GAM
+    /// token of the editor's owner, which the clipboard is used with.
+    pub fn new(
+        action_conn: xous::CID,
+        action_opcode: u32,
+        max_chars: usize,
+        placeholder: Option<(String, bool)>,
+        token: [u32; 4],
+    ) -> Self {
+        let (initial, placeholder) = match placeholder {
+            Some((text, true)) => (text, None),
+            Some((text, false)) => (String::new(), Some(text)),
+            None => (String::new(), None),
+        };
+        Self {
+            action_conn,
+            action_opcode,
+            token,
+            clipboard: None,
+            buffer: EditBuffer::new(&initial, max_chars),
+            placeholder,
+            keys_hit: false,
+            char_width: Cell::new(0),
+            columns: Cell::new(1),
+            top_line: Cell::new(0),
+        }
+    }
+
+    fn line_height() -> i16 { glyph_to_height_hint(GlyphStyle::Monospace) as i16 + LINE_SPACING }
+
+    fn showing_placeholder(&self) -> bool {
+        self.placeholder.is_some() && self.buffer.text.is_empty() && !self.keys_hit
+    }
+}
+
+impl ActionApi for TextEditor {
+    fn set_action_opcode(&mut self, op: u32) { self.action_opcode = op }
+
+    fn height(&self, glyph_height: i16, margin: i16, modal: &Modal) -> i16 {
+        /*
+            | text text text text text text  |
+            | text text text_                #
+            |                                #
+                 ... ROWS lines in all ...
+            ----------------------------------
+            123/4096  F1 select, F2 copy, ...
+        */
+        if self.char_width.get() == 0 {
+            let mut tv = TextView::new(
+                modal.canvas,
+                TextBounds::GrowableFromTl(Point::new(0, 0), modal.canvas_width as u16),
+            );
+            tv.style = GlyphStyle::Monospace;
+            tv.margin = Point::new(0, 0);
+            tv.draw_border = false;
+            tv.ellipsis = false;
+            write!(tv.text, "{}", MEASURE_TEXT).unwrap();
+            let width = match modal.gam.bounds_compute_textview(&mut tv) {
+                Ok(_) => tv
+                    .bounds_computed
+                    .map(|bounds| bounds.width() as i16 / MEASURE_TEXT.len() as i16)
+                    .filter(|&width| width > 0),
+                Err(_) => None,
+            };
+            self.char_width.set(width.unwrap_or(DEFAULT_CHAR_WIDTH));
+            let text_width = modal.canvas_width - 2 * modal.margin - SCROLLBAR_WIDTH;
+            self.columns.set((text_width / self.char_width.get()).max(1) as usize);
+        }
+        Self::line_height() * ROWS as i16 + glyph_height + 2 * margin
+    }
+
+    fn redraw(&self, at_height: i16, modal: &Modal) {
+        let line_height = Self::line_height();
+        let left = modal.margin;
+        let right = modal.canvas_width - modal.margin - SCROLLBAR_WIDTH;
+        let char_width = self.char_width.get();
+
+        let lines = wrap(&self.buffer.text, self.columns.get());
+        let cursor_line = line_of(&lines, self.buffer.cursor);
+        // scroll just enough to keep the cursor in view
+        let mut top = self.top_line.get().min(lines.len().saturating_sub(ROWS));
+        if cursor_line < top {
+            top = cursor_line;
+        } else if cursor_line >= top + ROWS {
+            top = cursor_line + 1 - ROWS;
+        }
+        self.top_line.set(top);
+
+        let selection = self.buffer.selection();
+        for (row, &(start, end)) in lines.iter().enumerate().skip(top).take(ROWS) {
+            let y = at_height + (row - top) as i16 * line_height;
+            let mut tv = TextView::new(
+                modal.canvas,
+                TextBounds::BoundingBox(Rectangle::new(
+                    Point::new(left, y),
+                    Point::new(right, y + line_height),
+                )),
+            );
+            tv.margin = Point::new(0, 0);
+            tv.draw_border = false;
+            tv.ellipsis = false;
+            if self.showing_placeholder() {
+                tv.style = GlyphStyle::Small;
+                tv.ellipsis = true;
+                write!(tv.text, "{}", self.placeholder.as_ref().unwrap()).unwrap();
+            } else {
+                tv.style = GlyphStyle::Monospace;
+                tv.text.extend(self.buffer.text[start..end].iter());
+            }
+            if row == cursor_line {
+                tv.insertion = Some((self.buffer.cursor - start) as i32);
+            }
+            modal.gam.post_textview(&mut tv).expect("couldn't post textview");
+
+            // underline the selected part of the line
+            if let Some((sel_start, sel_end)) = selection {
+                let (from, to) = (sel_start.max(start), sel_end.min(end));
+                if from < to {
+                    let underline = y + line_height - 1;
+                    modal
+                        .gam
+                        .draw_line(
+                            modal.canvas,
+                            Line::new_with_style(
+                                Point::new(left + (from - start) as i16 * char_width, underline),
+                                Point::new(left + (to - start) as i16 * char_width, underline),
+                                DrawStyle::new(PixelColor::Dark, PixelColor::Dark, 1),
+                            ),
+                        )
+                        .expect("couldn't draw selection");
+                }
+            }
+        }
+
+        let text_bottom = at_height + line_height * ROWS as i16;
+        if lines.len() > ROWS {
+            // scroll bar, sized and placed in proportion to the part of the text in view
+            let x = modal.canvas_width - modal.margin - SCROLLBAR_WIDTH / 2;
+            let span = (line_height * ROWS as i16) as usize;
+            modal
+                .gam
+                .draw_line(
+                    modal.canvas,
+                    Line::new_with_style(
+                        Point::new(x, at_height + (span * top / lines.len()) as i16),
+                        Point::new(x, at_height + (span * (top + ROWS) / lines.len()).min(span) as i16),
+                        DrawStyle::new(PixelColor::Dark, PixelColor::Dark, 2),
+                    ),
+                )
+                .expect("couldn't draw scroll bar");
+        }
+        modal
+            .gam
+            .draw_line(
+                modal.canvas,
+                Line::new_with_style(
+                    Point::new(left, text_bottom + 1),
+                    Point::new(modal.canvas_width - modal.margin, text_bottom + 1),
+                    DrawStyle::new(PixelColor::Dark, PixelColor::Dark, 1),
+                ),
+            )
+            .expect("couldn't draw editor outline");
+
+        // ------- status --------
+        let mut tv = TextView::new(
+            modal.canvas,
+            TextBounds::BoundingBox(Rectangle::new(
+                Point::new(left, text_bottom + modal.margin),
+                Point::new(modal.canvas_width - modal.margin, text_bottom + modal.margin + modal.line_height),
+            )),
+        );
+        tv.style = GlyphStyle::Small;
+        tv.margin = Point::new(0, 0);
+        tv.draw_border = false;
+        tv.ellipsis = true;
+        write!(
+            tv.text,
+            "{}/{}  {}",
+            self.buffer.text.len(),
+            self.buffer.max_chars,
+            t!("editor.help", locales::lang())
+        )
+        .unwrap();
+        modal.gam.post_textview(&mut tv).expect("couldn't post textview");
+    }
+
+    fn close(&mut self) { self.buffer.volatile_clear(); }
+
+    fn key_action(&mut self, k: char) -> Option<ValidatorErr> {
+        log::trace!("key_action: {}", k);
+        match k {
+            '←' => self.buffer.left(),
+            '→' => self.buffer.right(),
+            '↑' => self.buffer.up(&wrap(&self.buffer.text, self.columns.get())),
+            '↓' => self.buffer.down(&wrap(&self.buffer.text, self.columns.get())),
+            '∴' => {
+                if self.buffer.text.is_empty() && !self.keys_hit {
+                    if let Some(placeholder) = self.placeholder.as_ref() {
+                        self.buffer.insert(placeholder);
+                    }
+                }
+                // relinquish focus before returning the result
+                let gam = crate::Gam::new(&xous_names::XousNames::new().unwrap()).unwrap();
+                gam.relinquish_focus().unwrap();
+                xous::yield_slice();
+
+                let payload = TextEntryPayload::new_with_fields(self.buffer.contents(), None);
+                let buf = Buffer::into_buf(TextEntryPayloads::single(payload))
+                    .expect("couldn't convert message to payload");
+                buf.send(self.action_conn, self.action_opcode)
+                    .map(|_| ())
+                    .expect("couldn't send action message");
+
+                self.buffer.volatile_clear();
+                self.keys_hit = false;
+            }
+            '\u{11}' => self.buffer.toggle_mark(),
+            '\u{12}' => self.copy(),
+            '\u{13}' => {
+                if self.copy() {
+                    self.buffer.delete_selection();
+                    self.keys_hit = true;
+                }
+            }
+            '\u{14}' => {
+                let pasted = self.clipboard().map(|clipboard| clipboard.paste_normal());
+                match pasted {
+                    Some(Ok(Some(mut text))) => {
+                        self.buffer.insert(&text);
+                        self.keys_hit = true;
+                        volatile_clear_str(&mut text);
+                    }
+                    Some(Err(e)) => log::warn!("couldn't paste: {:?}", e),
+                    _ => {}
+                }
+            }
+            '\u{0}' | '\u{f700}' | '\u{f701}' => {
+                // ignore null messages and the keys that aren't text
+            }
+            '\u{8}' => {
+                self.keys_hit = true;
+                #[cfg(feature = "tts")]
+                {
+                    let xns = xous_names::XousNames::new().unwrap();
+                    let tts = tts_frontend::TtsFrontend::new(&xns).unwrap();
+                    tts.tts_blocking(locales::t!("input.delete-tts", locales::lang())).unwrap();
+                }
+                self.buffer.backspace();
+            }
+            _ => {
+                let k = if k == '\u{d}' { '\n' } else { k };
+                if k != '\n' && k.is_control() {
+                    return None;
+                }
+                self.keys_hit = true;
+                #[cfg(feature = "tts")]
+                {
+                    let xns = xous_names::XousNames::new().unwrap();
+                    let tts = tts_frontend::TtsFrontend::new(&xns).unwrap();
+                    tts.tts_blocking(&k.to_string()).unwrap();
+                }
+                let mut s = [0u8; 4];
+                self.buffer.insert(k.encode_utf8(&mut s));
+            }
+        }
+        None
+    }
+}
+
+impl TextEditor {
+    fn clipboard(&mut self) -> Option<&Clipboard> {
+        if self.clipboard.is_none() {
+            let xns = xous_names::XousNames::new().unwrap();
+            match Clipboard::new(&xns, self.token) {
+                Ok(clipboard) => self.clipboard = Some(clipboard),
+                Err(e) => log::warn!("no clipboard: {:?}", e),
+            }
+        }
+        self.clipboard.as_ref()
+    }
+
+    /// Copies the selection to the clipboard, returning false if there was nothing to copy or it failed
+    fn copy(&mut self) -> bool {
+        let mut selected = match self.buffer.selected() {
+            Some(selected) => selected,
+            None => return false,
+        };
+        let copied = match self.clipboard().map(|clipboard| clipboard.copy(&selected, Sensitivity::Normal)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                log::warn!("couldn't copy: {:?}", e);
+                false
+            }
+            None => false,
+        };
+        volatile_clear_str(&mut selected);
+        copied
+    }
+}
+
+/// The text being edited. It is kept as characters so that the cursor moves by character; the storage is
+/// allocated up front for the character limit, so that it never moves and leaves copies of the text behind.
+struct EditBuffer {
+    text: Vec<char>,
+    /// where text is inserted, as an index into `text`
+    cursor: usize,
+    /// the other end of the selection, if one is being made
+    mark: Option<usize>,
+    /// the column that moving up and down tries to stay in
+    goal: Option<usize>,
+    max_chars: usize,
+}
+
+impl EditBuffer {
+    fn new(initial: &str, max_chars: usize) -> Self {
+        let mut text = Vec::with_capacity(max_chars);
+        text.extend(initial.chars().take(max_chars));
+        let cursor = text.len();
+        EditBuffer { text, cursor, mark: None, goal: None, max_chars }
+    }
+
+    fn contents(&self) -> String { self.text.iter().collect() }
+
+    /// The selected range, if there is one
+    fn selection(&self) -> Option<(usize, usize)> {
+        self.mark
+            .filter(|&mark| mark != self.cursor)
+            .map(|mark| (mark.min(self.cursor), mark.max(self.cursor)))
+    }
+
+    fn selected(&self) -> Option<String> {
+        self.selection().map(|(start, end)| self.text[start..end].iter().collect())
+    }
+
+    fn toggle_mark(&mut self) { self.mark = if self.mark.is_some() { None } else { Some(self.cursor) }; }
+
+    /// Removes the selected text, returning false if nothing was selected
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.mark = None;
+        self.goal = None;
+        if let Some((start, end)) = selection {
+            self.text.drain(start..end);
+            self.cursor = start;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Inserts `s` at the cursor in place of the selection, leaving out whatever goes beyond the limit
+    fn insert(&mut self, s: &str) {
+        self.delete_selection();
+        let room = self.max_chars.saturating_sub(self.text.len());
+        let before = self.text.len();
+        self.text.splice(self.cursor..self.cursor, s.chars().take(room));
+        self.cursor += self.text.len() - before;
+    }
+
+    fn backspace(&mut self) {
+        if !self.delete_selection() && self.cursor > 0 {
+            self.cursor -= 1;
+            self.text.remove(self.cursor);
+        }
+    }
+
+    fn left(&mut self) {
+        self.goal = None;
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.goal = None;
+        self.cursor = (self.cursor + 1).min(self.text.len());
+    }
+
+    fn up(&mut self, lines: &[(usize, usize)]) {
+        let line = line_of(lines, self.cursor);
+        if line == 0 {
+            self.goal = None;
+            self.cursor = 0;
+        } else {
+            self.move_to_line(lines, line, line - 1);
+        }
+    }
+
+    fn down(&mut self, lines: &[(usize, usize)]) {
+        let line = line_of(lines, self.cursor);
+        if line + 1 >= lines.len() {
+            self.goal = None;
+            self.cursor = self.text.len();
+        } else {
+            self.move_to_line(lines, line, line + 1);
+        }
+    }
+
+    /// Moves the cursor from line `from` to line `to`, keeping to the same column where the line allows
+    fn move_to_line(&mut self, lines: &[(usize, usize)], from: usize, to: usize) {
+        let goal = *self.goal.get_or_insert(self.cursor - lines[from].0);
+        self.cursor = (lines[to].0 + goal).min(last_position(lines, to));
+    }
+
+    /// Overwrites the text, including what's left in the storage past its end, with zeroes
+    fn volatile_clear(&mut self) {
+        let ptr = self.text.as_mut_ptr();
+        for i in 0..self.text.capacity() {
+            // safety: the pointer is aligned and the storage is allocated for `capacity()` characters. The
+            // "unsafe" version of this is used to force a zeroize of contents and avoid optimizations that
+            // could otherwise cause this operation to be skipped.
+            unsafe { ptr.add(i).write_volatile('\0') };
+        }
+        // force all the writes to finish if they were re-ordered
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+        self.text.clear();
+        self.cursor = 0;
+        self.mark = None;
+        self.goal = None;
+    }
+}
+
+fn volatile_clear_str(s: &mut String) {
+    // safety: NULL bytes are valid UTF-8
+    let bytes = unsafe { s.as_bytes_mut() };
+    let ptr = bytes.as_mut_ptr();
+    for i in 0..bytes.len() {
+        // safety: the bounds were derived from a valid len; the pointer is aligned because it's derived from
+        // a slice.
+        unsafe { ptr.add(i).write_volatile(0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Breaks `text` into lines of at most `columns` characters, returned as `(start, end)` ranges of `text`.
+/// Lines break after a newline, which isn't part of either line, or else after the last space that fits;
+/// words too long for a line are split.
+fn wrap(text: &[char], columns: usize) -> Vec<(usize, usize)> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+    let mut start = 0;
+    loop {
+        let paragraph_end = text[start..].iter().position(|&c| c == '\n').map_or(text.len(), |i| start + i);
+        while paragraph_end - start > columns {
+            let end = match text[start + 1..start + columns].iter().rposition(|&c| c == ' ') {
+                Some(space) => start + space + 2,
+                None => start + columns,
+            };
+            lines.push((start, end));
+            start = end;
+        }
+        lines.push((start, paragraph_end));
+        if paragraph_end == text.len() {
+            return lines;
+        }
+        start = paragraph_end + 1;
+    }
+}
+
+/// The line that the cursor at `position` is on
+fn line_of(lines: &[(usize, usize)], position: usize) -> usize {
+    lines.iter().rposition(|&(start, _)| start <= position).unwrap_or(0)
+}
+
+/// The last cursor position on line `line`: where a line wraps on to the next one, the position at its end
+/// belongs to the next line
+fn last_position(lines: &[(usize, usize)], line: usize) -> usize {
+    let (_, end) = lines[line];
+    match lines.get(line + 1) {
+        Some(&(next_start, _)) if next_start == end => end - 1,
+        _ => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> Vec<char> { s.chars().collect() }
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(wrap(&[], 10), vec![(0, 0)]);
+        // breaks after the last space that fits
+        assert_eq!(wrap(&chars("the quick brown fox"), 8), vec![(0, 4), (4, 10), (10, 16), (16, 19)]);
+        // splits a word that is too long, and starts a line after each newline
+        assert_eq!(
+            wrap(&chars("abcdefghijkl\n\nab\n"), 5),
+            vec![(0, 5), (5, 10), (10, 12), (13, 13), (14, 16), (17, 17)]
+        );
+    }
+
+    #[test]
+    fn cursor_lines() {
+        let text = chars("the quick brown fox\nab");
+        let lines = wrap(&text, 8);
+        assert_eq!(lines, vec![(0, 4), (4, 10), (10, 16), (16, 19), (20, 22)]);
+        // the end of a wrapped line is the start of the next one
+        assert_eq!(line_of(&lines, 10), 2);
+        assert_eq!(last_position(&lines, 0), 3);
+        // the end of a paragraph stays on its line
+        assert_eq!(line_of(&lines, 19), 3);
+        assert_eq!(last_position(&lines, 3), 19);
+    }
+
+    #[test]
+    fn up_and_down_keep_the_column() {
+        let mut buffer = EditBuffer::new("the quick brown fox\nab", 100);
+        let lines = wrap(&buffer.text, 8);
+        buffer.cursor = 7;
+        buffer.down(&lines);
+        assert_eq!(buffer.cursor, 13);
+        buffer.down(&lines);
+        assert_eq!(buffer.cursor, 19);
+        buffer.down(&lines);
+        assert_eq!(buffer.cursor, 22);
+        for _ in 0..3 {
+            buffer.up(&lines);
+        }
+        assert_eq!(buffer.cursor, 7);
+        buffer.up(&lines);
+        assert_eq!(buffer.cursor, 3);
+        buffer.up(&lines);
+        assert_eq!(buffer.cursor, 0);
+        for _ in 0..5 {
+            buffer.down(&lines);
+        }
+        assert_eq!(buffer.cursor, 22);
+    }
+
+    #[test]
+    fn edit_within_limit() {
+        let mut buffer = EditBuffer::new("hello", 8);
+        buffer.cursor = 0;
+        buffer.insert(">");
+        assert_eq!(buffer.contents(), ">hello");
+        assert_eq!(buffer.cursor, 1);
+        // only what fits is pasted
+        buffer.insert("1234");
+        assert_eq!(buffer.contents(), ">12hello");
+        buffer.insert("x");
+        assert_eq!(buffer.contents(), ">12hello");
+        buffer.backspace();
+        assert_eq!(buffer.contents(), ">1hello");
+        let capacity = buffer.text.capacity();
+        buffer.insert("abc");
+        assert_eq!(buffer.text.capacity(), capacity);
+    }
+
+    #[test]
+    fn selection() {
+        let mut buffer = EditBuffer::new("hello world", 100);
+        buffer.toggle_mark();
+        assert_eq!(buffer.selected(), None);
+        for _ in 0..5 {
+            buffer.left();
+        }
+        assert_eq!(buffer.selected(), Some("world".to_string()));
+        // typing replaces the selection
+        buffer.insert("there");
+        assert_eq!(buffer.contents(), "hello there");
+        assert_eq!(buffer.mark, None);
+        buffer.toggle_mark();
+        buffer.cursor = 0;
+        buffer.backspace();
+        assert_eq!(buffer.contents(), "");
+        assert_eq!(buffer.cursor, 0);
+    }
+}
@@ -20,6 +20,13 @@ pub type Payloads = [TextEntryPayload; MAX_FIELDS as usize];
 pub struct TextEntryPayloads(Payloads, usize);
 
 impl TextEntryPayloads {
+    pub(crate) fn single(payload: TextEntryPayload) -> Self {
+        let mut payloads = TextEntryPayloads::default();
+        payloads.0[0] = payload;
+        payloads.1 = 1;
+        payloads
+    }
+
     pub fn first(&self) -> TextEntryPayload { self.0[0].clone() }
 
     pub fn content(&self) -> Vec<TextEntryPayload> { self.0[..self.1].to_vec() }
@@ -684,7 +691,7 @@ impl ActionApi for TextEntry {
                 {
                     let xns = xous_names::XousNames::new().unwrap();
                     let tts = tts_frontend::TtsFrontend::new(&xns).unwrap();
-                    tts.tts_blocking(locales::t!("input.delete-tts", locales::LANG)).unwrap();
+                    tts.tts_blocking(locales::t!("input.delete-tts", locales::lang())).unwrap();
                 }
                 if payload.placeholder_persist && payload.placeholder.is_some() && payload.content.len() == 0
                 {
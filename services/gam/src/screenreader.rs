@@ -0,0 +1,187 @@
+//! Reads out what the focused context draws, for people who can't see the screen.
+//!
+//! Nothing here knows about particular apps: everything is derived from the `TextView`s that come through
+//! the GAM to be drawn, plus the labels apps attach to canvases and bitmaps that aren't text. Menus and
+//! modals mark the selected item with a `▶`, either in front of the item's text or drawn on its own just
+//! before it on the same line, so that is what a selection is recognized by.
+
+use std::collections::{HashMap, VecDeque};
+
+use graphics_server::api::{Gid, TextView};
+use tts_frontend::{Priority, SpeechMode, TtsFrontend};
+
+use crate::api::CanvasType;
+
+/// The cursor that menus and modals draw next to the selected item
+const SELECTION_MARKER: char = '\u{25B6}';
+/// How many texts of the focused context are remembered, so that redraws don't read them out again
+const SPOKEN_HISTORY: usize = 64;
+
+pub(crate) struct ScreenReader {
+    /// `Some` while the screen reader is on
+    tts: Option<TtsFrontend>,
+    /// accessible labels of canvases, read out when their context gets focus
+    labels: HashMap<Gid, String>,
+    /// texts of the focused context that have been read out
+    spoken: VecDeque<String>,
+    /// the line of a selection marker that was drawn on its own; the next text drawn on that line is the
+    /// selected item
+    marker: Option<(Gid, i16, i16)>,
+    /// the selected item that was read out last, and its utterance, which is cut short when the
+    /// selection moves on
+    selection: Option<(String, u32)>,
+    /// an alert such as a modal or a notification has focus. It cuts short whatever the app behind it is
+    /// saying, which carries on once the alert has been read out.
+    alert: bool,
+}
+
+impl ScreenReader {
+    pub(crate) fn new() -> Self {
+        ScreenReader {
+            tts: None,
+            labels: HashMap::new(),
+            spoken: VecDeque::new(),
+            marker: None,
+            selection: None,
+            alert: false,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool { self.tts.is_some() }
+
+    /// Turns the screen reader on or off. Turning it on fails if there is no TTS server in this image.
+    pub(crate) fn set_enabled(&mut self, enable: bool) -> Result<(), xous::Error> {
+        if enable && self.tts.is_none() {
+            let xns = xous_names::XousNames::new().unwrap();
+            self.tts = Some(TtsFrontend::try_new(&xns)?);
+            log::info!("screen reader on");
+        } else if !enable {
+            if let Some(tts) = self.tts.take() {
+                tts.stop().ok();
+                log::info!("screen reader off");
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the accessible label of the canvas `gid`; an empty label removes it
+    pub(crate) fn set_label(&mut self, gid: Gid, label: &str) {
+        if label.is_empty() {
+            self.labels.remove(&gid);
+        } else {
+            self.labels.insert(gid, label.to_string());
+        }
+    }
+
+    /// Drops the labels of the canvases for which `keep` returns false
+    pub(crate) fn retain_labels(&mut self, keep: impl Fn(&Gid) -> bool) {
+        self.labels.retain(|gid, _| keep(gid));
+    }
+
+    /// Reads out a change of focus to the context called `name`, whose canvases are `gids`
+    pub(crate) fn focus(&mut self, name: Option<&str>, alert: bool, gids: &[Gid]) {
+        self.spoken.clear();
+        self.marker = None;
+        self.selection = None;
+        self.alert = alert;
+        let phrase: Vec<&str> = name
+            .into_iter()
+            .chain(gids.iter().filter_map(|gid| self.labels.get(gid).map(|label| label.as_str())))
+            .collect();
+        if !phrase.is_empty() {
+            self.speak(&phrase.join(", "), SpeechMode::Replace);
+        }
+    }
+
+    /// Reads out `tv`, just drawn on a canvas of the focused context, unless it has been read out already
+    pub(crate) fn text(&mut self, tv: &TextView, canvas_type: CanvasType) {
+        if self.tts.is_none() {
+            return;
+        }
+        match canvas_type {
+            CanvasType::Menu | CanvasType::Modal | CanvasType::ChatContent | CanvasType::Framebuffer => {}
+            // the status bar changes all the time, and the input area is voiced by the IME
+            CanvasType::Status | CanvasType::ChatInput | CanvasType::ChatPreditive => return,
+        }
+        // fields being typed into are redrawn on every key, which the IME and the text entry voice already
+        if tv.insertion.is_some() {
+            return;
+        }
+        let gid = tv.get_canvas_gid();
+        let line = tv.bounds_computed.map(|r| (r.tl.y, r.br.y));
+        let text = tv.text.as_str().trim();
+        if let Some(item) = text.strip_prefix(SELECTION_MARKER) {
+            let item = item.trim();
+            if item.is_empty() {
+                self.marker = line.map(|(top, bottom)| (gid, top, bottom));
+            } else {
+                self.select(item);
+            }
+            return;
+        }
+        if text.is_empty() {
+            return;
+        }
+        if let (Some((marker_gid, top, bottom)), Some((text_top, text_bottom))) = (self.marker, line) {
+            if marker_gid == gid && text_top <= bottom && top <= text_bottom {
+                self.marker = None;
+                self.select(text);
+                return;
+            }
+        }
+        // a menu is only read out as its selection moves, not all at once
+        if canvas_type != CanvasType::Menu {
+            self.read(text);
+        }
+    }
+
+    /// Reads out the label of something that isn't text, such as a bitmap, drawn on the focused context
+    pub(crate) fn label(&mut self, label: &str) {
+        if self.tts.is_some() {
+            self.read(label);
+        }
+    }
+
+    fn read(&mut self, text: &str) {
+        if self.remember(text) {
+            self.speak(text, SpeechMode::Queue);
+        }
+    }
+
+    fn select(&mut self, item: &str) {
+        if self.selection.as_ref().map(|(selected, _)| selected.as_str()) == Some(item) {
+            return;
+        }
+        if let (Some((_, id)), Some(tts)) = (self.selection.take(), self.tts.as_ref()) {
+            tts.cancel(id).ok();
+        }
+        self.remember(item);
+        if let Some(id) = self.speak(item, SpeechMode::Queue) {
+            self.selection = Some((item.to_string(), id));
+        }
+    }
+
+    /// Adds `text` to what has been read out, returning false if it was there already
+    fn remember(&mut self, text: &str) -> bool {
+        if self.spoken.iter().any(|spoken| spoken == text) {
+            return false;
+        }
+        if self.spoken.len() >= SPOKEN_HISTORY {
+            self.spoken.pop_front();
+        }
+        self.spoken.push_back(text.to_string());
+        true
+    }
+
+    fn speak(&self, text: &str, mode: SpeechMode) -> Option<u32> {
+        let (priority, mode) =
+            if self.alert { (Priority::High, SpeechMode::Interrupt) } else { (Priority::Normal, mode) };
+        match self.tts.as_ref()?.speak(text, priority, mode) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                log::warn!("couldn't read out {}: {:?}", text, e);
+                None
+            }
+        }
+    }
+}
@@ -138,6 +138,15 @@ impl<'a> TokenManager {
         None
     }
 
+    /// True if `name` was registered by an app loaded after boot, rather than one built into the image
+    #[cfg(feature = "unsafe-app-loading")]
+    pub(crate) fn is_loaded_app(&self, name: &str) -> bool {
+        self.extra_names.iter().any(|extra_name| extra_name == name)
+    }
+
+    #[cfg(not(feature = "unsafe-app-loading"))]
+    pub(crate) fn is_loaded_app(&self, _name: &str) -> bool { false }
+
     /// Register a new name that can then claim a token. Note that only pre-registered applications are
     /// allowed to do this.
     #[cfg(feature = "unsafe-app-loading")]
@@ -120,6 +120,9 @@ pub(crate) enum Opcode {
     /// draw the boot logo (for continuity as apps initialize)
     DrawBootLogo,
 
+    /// switch the language glyphs are picked for; the argument is an index into `locales::LANGUAGES`
+    SetLanguage,
+
     Quit,
 }
 
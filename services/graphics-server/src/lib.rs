@@ -525,6 +525,22 @@ impl Gfx {
             .map(|_| ())
     }
 
+    /// Switches the language whose glyphs are preferred when text is laid out. Languages that
+    /// aren't in `locales::LANGUAGES` are ignored. This is normally only called by the GAM, as part of
+    /// `Gam::set_language()`.
+    ///
+    /// # Example
+    /// ```
+    /// use graphics_server::Gfx;
+    /// let gfx = Gfx::new(&xous_names::XousNames::new().unwrap()).unwrap();
+    /// gfx.set_language("zh").unwrap();
+    /// ```
+    pub fn set_language(&self, lang: &str) -> Result<(), xous::Error> {
+        let index = locales::LANGUAGES.iter().position(|l| *l == lang).ok_or(xous::Error::InvalidString)?;
+        send_message(self.conn, Message::new_scalar(Opcode::SetLanguage.to_usize().unwrap(), index, 0, 0, 0))
+            .map(|_| ())
+    }
+
     /// Reads the font map in bulk from the graphics server.
     ///
     /// Instead of implementing the read in the library, we hand the raw opcode to the caller.
@@ -190,6 +190,11 @@ fn wrapped_main(main_thread_token: backend::MainThreadToken) -> ! {
     }
 
     let xns = xous_names::XousNames::new().unwrap();
+    // paging in the frame buffer or the drawing code makes the UI stutter, so ask to stay resident
+    #[cfg(feature = "swap")]
+    xous_swapper::Swapper::new()
+        .and_then(|swapper| swapper.set_residency_hint(xous_swapper::policy::ResidencyHint::KeepResident))
+        .expect("couldn't set residency hint with the swapper");
     // these connections should be established:
     // - GAM
     // - keyrom (for verifying font maps)
@@ -582,6 +587,11 @@ fn wrapped_main(main_thread_token: backend::MainThreadToken) -> ! {
                     display.blit_screen(&poweron::LOGO_MAP);
                     display.redraw();
                 }),
+                Some(Opcode::SetLanguage) => msg_scalar_unpack!(msg, index, _, _, _, {
+                    if let Some(lang) = locales::LANGUAGES.get(index) {
+                        locales::set_lang(lang);
+                    }
+                }),
                 Some(Opcode::Devboot) => msg_scalar_unpack!(msg, ena, _, _, _, {
                     if ena != 0 {
                         display.set_devboot(true);
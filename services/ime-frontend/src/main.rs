@@ -18,7 +18,7 @@ use xous::{CID, msg_scalar_unpack};
 use xous_ipc::Buffer;
 
 /// max number of prediction options to track/render
-const MAX_PREDICTION_OPTIONS: usize = 4;
+const MAX_PREDICTION_OPTIONS: usize = ime_plugin_api::MAX_PREDICTIONS;
 
 struct InputTracker {
     /// connection for handling graphical update requests
@@ -61,6 +61,8 @@ struct InputTracker {
 
     /// if set to true, the F1-F4 keys work as menu selects, and not as predictive inputs
     menu_mode: bool,
+    /// if set to true, nothing typed or picked is fed back to the predictor, so it can't learn from it
+    sensitive: bool,
 
     /// render the predictions. Slightly awkward because this code comes from before we had libstd
     pred_options: [Option<String>; MAX_PREDICTION_OPTIONS],
@@ -88,6 +90,7 @@ impl InputTracker {
             was_grown: false,
             pred_options: Default::default(),
             menu_mode: false,
+            sensitive: false,
             #[cfg(feature = "tts")]
             tts: TtsFrontend::new(xns).unwrap(),
         }
@@ -135,6 +138,23 @@ impl InputTracker {
 
     pub fn set_menu_mode(&mut self, mode: bool) { self.menu_mode = mode; }
 
+    pub fn set_sensitive(&mut self, sensitive: bool) {
+        self.sensitive = sensitive;
+        self.can_unpick = false;
+    }
+
+    /// Tells the predictor what was typed or picked, unless the input is sensitive. Returns true if it
+    /// was told, and so can be asked to take it back.
+    fn feedback_picked(&self, picked: String) -> bool {
+        match self.predictor {
+            Some(predictor) if !self.sensitive => {
+                predictor.feedback_picked(picked).expect("couldn't send feedback to predictor");
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn clear_area(&mut self) -> Result<(), xous::Error> {
         if let Some(pc) = self.pred_canvas {
             let pc_bounds: Point =
@@ -191,18 +211,45 @@ impl InputTracker {
         Ok(())
     }
 
-    fn insert_prediction(&mut self, index: usize) {
+    /// Returns `true` if the prediction replaced the phrase being typed, in which case the predictor has
+    /// been handed an empty input and its options should be fetched again.
+    fn insert_prediction(&mut self, index: usize) -> bool {
         let debug1 = false;
         if debug1 {
             info!("IMEF|insert_prediction index {}", index);
         }
         let pred_str = match &self.pred_options[index] {
             Some(s) => s,
-            _ => return, // if the index doesn't exist for some reason, do nothing without throwing an error
+            // if the index doesn't exist for some reason, do nothing without throwing an error
+            _ => return false,
         };
         if debug1 {
             info!("IMEF|insert_prediction string {}, last_trigger {:?}", pred_str, self.last_trigger_char);
         }
+        // predictors that convert what is typed (e.g. pinyin into hanzi) offer candidates for the whole
+        // phrase typed since the last trigger, so picking one replaces that phrase
+        if !self.pred_phrase.is_empty()
+            && self.insertion == self.characters
+            && self.line.ends_with(self.pred_phrase.as_str())
+        {
+            let keep = self.line.len() - self.pred_phrase.len();
+            self.line.truncate(keep);
+            self.line.push_str(pred_str);
+            self.characters = self.line.chars().count();
+            self.insertion = self.characters;
+            self.last_trigger_char = Some(self.characters);
+            self.pred_phrase.clear();
+            self.can_unpick = false;
+            if let Some(predictor) = self.predictor {
+                // word-level predictors learn from what is picked; the phrase that would normally be fed
+                // back at the next trigger is gone, so report the pick here instead
+                if self.pred_triggers.map_or(false, |t| t.whitespace || t.punctuation) {
+                    self.feedback_picked(String::from(pred_str));
+                }
+                predictor.set_input(String::new()).expect("couldn't clear predictor input");
+            }
+            return true;
+        }
         if let Some(offset) = self.last_trigger_char {
             if offset < self.characters {
                 // copy the bytes in the original string, up to the offset; and then copy the bytes in the
@@ -261,6 +308,7 @@ impl InputTracker {
                 self.insertion = self.characters;
             }
         }
+        false
     }
 
     pub fn update(
@@ -349,7 +397,7 @@ impl InputTracker {
                     '\u{0011}' => {
                         // F1
                         if !self.menu_mode {
-                            self.insert_prediction(0);
+                            update_predictor |= self.insert_prediction(0);
                             do_redraw = true;
                         } else {
                             retstring = Some(String::from("\u{0011}"));
@@ -359,7 +407,7 @@ impl InputTracker {
                     '\u{0012}' => {
                         // F2
                         if !self.menu_mode {
-                            self.insert_prediction(1);
+                            update_predictor |= self.insert_prediction(1);
                             do_redraw = true;
                         } else {
                             retstring = Some(String::from("\u{0012}"));
@@ -369,7 +417,7 @@ impl InputTracker {
                     '\u{0013}' => {
                         // F3
                         if !self.menu_mode {
-                            self.insert_prediction(2);
+                            update_predictor |= self.insert_prediction(2);
                             do_redraw = true;
                         } else {
                             retstring = Some(String::from("\u{0013}"));
@@ -379,7 +427,7 @@ impl InputTracker {
                     '\u{0014}' => {
                         // F4
                         if !self.menu_mode {
-                            self.insert_prediction(3);
+                            update_predictor |= self.insert_prediction(3);
                             do_redraw = true;
                         } else {
                             retstring = Some(String::from("\u{0014}"));
@@ -469,15 +517,9 @@ impl InputTracker {
 
                         if let Some(trigger) = self.pred_triggers {
                             if trigger.newline {
-                                self.predictor
-                                    .unwrap()
-                                    .feedback_picked(String::from(&self.line))
-                                    .expect("couldn't send feedback to predictor");
+                                self.feedback_picked(String::from(&self.line));
                             } else if trigger.punctuation {
-                                self.predictor
-                                    .unwrap()
-                                    .feedback_picked(String::from(&self.pred_phrase))
-                                    .expect("couldn't send feedback to predictor");
+                                self.feedback_picked(String::from(&self.pred_phrase));
                             }
                         }
                         self.can_unpick = false;
@@ -518,23 +560,15 @@ impl InputTracker {
                         if let Some(trigger) = self.pred_triggers {
                             if trigger.whitespace && k.is_ascii_whitespace() {
                                 if self.pred_phrase.len() > 0 {
-                                    self.predictor
-                                        .unwrap()
-                                        .feedback_picked(String::from(&self.pred_phrase))
-                                        .expect("couldn't send feedback to predictor");
+                                    self.can_unpick = self.feedback_picked(String::from(&self.pred_phrase));
                                     self.pred_phrase.clear();
-                                    self.can_unpick = true;
                                     update_predictor = true;
                                 }
                                 self.last_trigger_char = Some(self.insertion);
                             } else if trigger.punctuation && k.is_ascii_punctuation() {
                                 if self.pred_phrase.len() > 0 {
-                                    self.predictor
-                                        .unwrap()
-                                        .feedback_picked(String::from(&self.pred_phrase))
-                                        .expect("couldn't send feedback to predictor");
+                                    self.can_unpick = self.feedback_picked(String::from(&self.pred_phrase));
                                     self.pred_phrase.clear();
-                                    self.can_unpick = true;
                                     update_predictor = true;
                                 }
                                 self.last_trigger_char = Some(self.insertion);
@@ -960,6 +994,9 @@ fn main() -> ! {
                     tracker.set_menu_mode(false);
                 }
             }),
+            Some(ImefOpcode::SetSensitive) => msg_scalar_unpack!(msg, arg, _, _, _, {
+                tracker.set_sensitive(arg == 1);
+            }),
             Some(ImefOpcode::Quit) => {
                 log::error!("recevied quit, goodbye!");
                 break;
@@ -0,0 +1,103 @@
+//! The server side of the plugins that convert what is typed into another script (pinyin into hanzi,
+//! romaji into kana and kanji). They only differ in how candidates are made, so the predictor protocol
+//! lives here and each plugin supplies a [`Converter`].
+
+use num_traits::FromPrimitive;
+use xous::msg_scalar_unpack;
+use xous_ipc::Buffer;
+
+use crate::{AcquirePredictor, Opcode, Prediction, PredictionTriggers, Return};
+
+pub trait Converter {
+    /// Called whenever the predictor is acquired. The PDDB is usually mounted well after boot, so this is
+    /// where a plugin picks up its user dictionary once it can, without blocking on it.
+    fn acquired(&mut self) {}
+
+    /// Candidates for `input`, best first, at most `MAX_PREDICTIONS` of them
+    fn candidates(&self, input: &str) -> Vec<String>;
+}
+
+/// Answers predictor requests on `sid` with the candidates from `converter`, until told to quit. Nothing
+/// is learned from what was typed or picked.
+pub fn serve_converter(sid: xous::SID, converter: &mut dyn Converter) {
+    let triggers = PredictionTriggers { newline: true, punctuation: true, whitespace: true };
+
+    let mut api_token: Option<[u32; 4]> = None;
+    let mut candidates: Vec<String> = Vec::new();
+    loop {
+        let mut msg = xous::receive_message(sid).unwrap();
+        log::trace!("received message {:?}", msg);
+        match FromPrimitive::from_usize(msg.body.id()) {
+            Some(Opcode::Acquire) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ret = buffer.to_original::<AcquirePredictor, _>().unwrap();
+                if api_token.is_none() {
+                    if let Some(token) = ret.token {
+                        api_token = Some(token);
+                    } else {
+                        let new_token = xous::create_server_id().unwrap().to_array();
+                        ret.token = Some(new_token);
+                        api_token = Some(new_token);
+                    }
+                } else {
+                    ret.token = None;
+                    log::warn!("attempt to acquire lock on a predictor that was already locked");
+                }
+                buffer.replace(ret).unwrap();
+                converter.acquired();
+            }
+            Some(Opcode::Release) => msg_scalar_unpack!(msg, t0, t1, t2, t3, {
+                let token = [t0 as u32, t1 as u32, t2 as u32, t3 as u32];
+                if let Some(t) = api_token {
+                    if t == token {
+                        api_token.take();
+                        candidates.clear();
+                    } else {
+                        log::warn!("Release called with an invalid token");
+                    }
+                } else {
+                    log::warn!("Release called on a predictor that was in a released state");
+                }
+            }),
+            Some(Opcode::Input) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let s = buffer.as_flat::<String, _>().unwrap();
+                candidates = converter.candidates(s.as_str());
+                log::trace!("input {} has {} candidates", s.as_str(), candidates.len());
+            }
+            Some(Opcode::Prediction) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut prediction: Prediction = buffer.to_original::<Prediction, _>().unwrap();
+                if api_token == Some(prediction.api_token) {
+                    if let Some(c) = candidates.get(prediction.index as usize) {
+                        prediction.string.clear();
+                        prediction.string.push_str(c);
+                        prediction.valid = true;
+                    } else {
+                        prediction.valid = false;
+                    }
+                } else {
+                    prediction.valid = false;
+                    log::warn!("api token mismatch, ignoring");
+                }
+                buffer.replace(Return::Prediction(prediction)).expect("couldn't return Prediction");
+            }
+            Some(Opcode::Picked) | Some(Opcode::Unpick) | Some(Opcode::Forget) => {}
+            Some(Opcode::GetPredictionTriggers) => {
+                xous::return_scalar(msg.sender, triggers.into())
+                    .expect("couldn't return GetPredictionTriggers");
+            }
+            Some(Opcode::Quit) => {
+                if api_token.is_some() {
+                    log::error!("received quit, goodbye!");
+                    break;
+                }
+            }
+            None => {
+                log::error!("unknown Opcode");
+            }
+        }
+    }
+}
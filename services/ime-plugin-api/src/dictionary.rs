@@ -0,0 +1,123 @@
+//! A compact candidate dictionary, shared by the plugins that convert a reading into another script
+//! (pinyin into hanzi, kana into kanji).
+//!
+//! The text format has one entry per line: a reading, then its candidates separated by whitespace, most
+//! likely first. Blank lines and lines starting with `#` are skipped. A reading may appear on more than
+//! one line, in which case the candidates are appended in order.
+//!
+//! ```text
+//! # reading  candidates
+//! ni         你 呢 尼 泥
+//! nihao      你好
+//! ```
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl Dictionary {
+    pub fn parse(text: &str) -> Self {
+        let mut dict = Dictionary::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            if let Some(reading) = fields.next() {
+                let candidates = dict.entries.entry(reading.to_string()).or_default();
+                for c in fields {
+                    if !candidates.iter().any(|existing| existing == c) {
+                        candidates.push(c.to_string());
+                    }
+                }
+            }
+        }
+        dict
+    }
+
+    /// Merges `other` into this dictionary. Candidates from `other` are offered first, so a user
+    /// dictionary merged over the built-in one can reorder it as well as extend it.
+    pub fn merge_over(&mut self, other: Dictionary) {
+        for (reading, mut candidates) in other.entries {
+            let existing = self.entries.entry(reading).or_default();
+            existing.retain(|c| !candidates.contains(c));
+            candidates.append(existing);
+            *existing = candidates;
+        }
+    }
+
+    pub fn lookup(&self, reading: &str) -> Option<&[String]> {
+        self.entries.get(reading).map(|c| c.as_slice())
+    }
+
+    /// Entries whose reading extends `prefix`, in reading order. The entry for `prefix` itself, if any, is
+    /// not included.
+    pub fn completions<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a [String])> + 'a {
+        self.entries
+            .range::<str, _>((std::ops::Bound::Excluded(prefix), std::ops::Bound::Unbounded))
+            .take_while(move |(reading, _)| reading.starts_with(prefix))
+            .map(|(reading, candidates)| (reading.as_str(), candidates.as_slice()))
+    }
+
+    /// The length in bytes of the longest reading that starts `input`, if any
+    pub fn longest_reading(&self, input: &str) -> Option<usize> {
+        input
+            .char_indices()
+            .rev()
+            .map(|(i, c)| i + c.len_utf8())
+            .find(|&end| self.entries.contains_key(&input[..end]))
+    }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    pub fn len(&self) -> usize { self.entries.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let dict = Dictionary::parse("# comment\n\nni 你 呢\n  nihao 你好  \nni 尼 你\nlonely\n");
+        assert_eq!(dict.len(), 3);
+        // repeated readings append, without repeating candidates
+        assert_eq!(dict.lookup("ni").unwrap(), ["你", "呢", "尼"]);
+        assert_eq!(dict.lookup("nihao").unwrap(), ["你好"]);
+        assert!(dict.lookup("lonely").unwrap().is_empty());
+        assert!(dict.lookup("#").is_none());
+        assert!(Dictionary::parse("# nothing\n").is_empty());
+    }
+
+    #[test]
+    fn test_merge_over() {
+        let mut dict = Dictionary::parse("ni 你 呢 尼\nhao 好");
+        dict.merge_over(Dictionary::parse("ni 尼 泥\nma 吗"));
+        assert_eq!(dict.lookup("ni").unwrap(), ["尼", "泥", "你", "呢"]);
+        assert_eq!(dict.lookup("hao").unwrap(), ["好"]);
+        assert_eq!(dict.lookup("ma").unwrap(), ["吗"]);
+    }
+
+    #[test]
+    fn test_completions() {
+        let dict = Dictionary::parse("ni 你\nnihao 你好\nnimen 你们\nnin 您\nno 不\nn 嗯");
+        let readings: Vec<&str> = dict.completions("ni").map(|(reading, _)| reading).collect();
+        assert_eq!(readings, ["nihao", "nimen", "nin"]);
+        assert_eq!(dict.completions("nin").count(), 0);
+        assert_eq!(dict.completions("x").count(), 0);
+    }
+
+    #[test]
+    fn test_longest_reading() {
+        let dict = Dictionary::parse("にほん 日本\nにほんご 日本語\nに 二");
+        assert_eq!(dict.longest_reading("にほんごです"), Some("にほんご".len()));
+        assert_eq!(dict.longest_reading("にほんです"), Some("にほん".len()));
+        assert_eq!(dict.longest_reading("にわ"), Some("に".len()));
+        assert_eq!(dict.longest_reading("です"), None);
+        assert_eq!(dict.longest_reading(""), None);
+    }
+}
@@ -1,11 +1,18 @@
 #![cfg_attr(target_os = "none", no_std)]
 
+pub mod converter;
+pub mod dictionary;
 mod rkyv_enum;
+pub use converter::{Converter, serve_converter};
+pub use dictionary::Dictionary;
 use num_traits::{FromPrimitive, ToPrimitive};
 pub use rkyv_enum::*;
 use xous::{CID, Message, send_message};
 use xous_ipc::Buffer;
 
+/// How many predictions the IME front end shows at once; indices past this are never asked for
+pub const MAX_PREDICTIONS: usize = 4;
+
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct Prediction {
     pub index: u32,
@@ -82,6 +89,10 @@ pub enum Opcode {
     Acquire,
     Release,
 
+    /// Discard anything the plugin has learned from what was typed, including any copy it saved. Plugins
+    /// that don't learn ignore this.
+    Forget,
+
     Quit,
 }
 
@@ -95,6 +106,8 @@ pub trait PredictionApi {
     fn acquire(&self, api_token: Option<[u32; 4]>) -> Result<[u32; 4], xous::Error>;
     /// releases the lock. Also clears any sensitive data that may be in the predictor.
     fn release(&self, api_token: [u32; 4]);
+    /// discards everything the predictor has learned from the user
+    fn forget(&self) -> Result<(), xous::Error>;
 }
 
 // provide a convenience version of the API for generic/standard calls
@@ -137,6 +150,16 @@ impl PredictionApi for PredictionPlugin {
         }
     }
 
+    fn forget(&self) -> Result<(), xous::Error> {
+        match self.connection {
+            Some(cid) => {
+                send_message(cid, Message::new_scalar(Opcode::Forget.to_usize().unwrap(), 0, 0, 0, 0))?;
+                Ok(())
+            }
+            _ => Err(xous::Error::UseBeforeInit),
+        }
+    }
+
     fn set_input(&self, s: String) -> Result<(), xous::Error> {
         match self.connection {
             Some(cid) => {
@@ -254,6 +277,9 @@ pub enum ImefOpcode {
     Redraw,
 
     Quit,
+
+    /// mark the input as sensitive -- while set, nothing typed is fed back to the predictor to learn from
+    SetSensitive,
 }
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
 pub enum ImefCallback {
@@ -278,6 +304,7 @@ pub trait ImeFrontEndApi {
     fn conn(&self) -> xous::CID;
     fn getop_process_keys(&self) -> u32;
     fn set_menu_mode(&self, mode: bool) -> Result<(), xous::Error>;
+    fn set_sensitive(&self, sensitive: bool) -> Result<(), xous::Error>;
 }
 
 pub const SERVER_NAME_IME_FRONT: &str = "_IME front end_";
@@ -363,6 +390,20 @@ impl ImeFrontEndApi for ImeFrontEnd {
         .map(|_| ())
     }
 
+    fn set_sensitive(&self, sensitive: bool) -> Result<(), xous::Error> {
+        xous::send_message(
+            self.cid,
+            xous::Message::new_scalar(
+                ImefOpcode::SetSensitive.to_usize().unwrap(),
+                if sensitive { 1 } else { 0 },
+                0,
+                0,
+                0,
+            ),
+        )
+        .map(|_| ())
+    }
+
     fn hook_listener_callback(&mut self, cb: fn(String)) -> Result<(), xous::Error> {
         if unsafe { INPUT_CB }.is_some() {
             return Err(xous::Error::MemoryInUse); // can't hook it twice
@@ -0,0 +1,130 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Converts romaji into kana and kanji. Candidates for the phrase being typed are offered in the
+//! prediction bar, and picking one replaces the romaji.
+//!
+//! To use it, register a UX with `predictor: Some(SERVER_NAME_IME_PLUGIN_KANA)` and add the service to
+//! the image, e.g. `cargo xtask app-image --service ime-plugin-kana --feature ime-kana` to have shellchat
+//! use it.
+
+mod romaji;
+pub use romaji::{to_hiragana, to_katakana};
+
+pub const SERVER_NAME_IME_PLUGIN_KANA: &str = "_IME kana plugin_";
+
+/// PDDB dictionary and key holding the user dictionary, in the same format as the built-in one, with
+/// readings written in hiragana. It is read when the plugin is first acquired after the PDDB is mounted.
+pub const KANA_DICT: &str = "ime.dict";
+pub const KANA_DICT_KEY: &str = "kana";
+
+/// Most candidates offered for one phrase, one for each slot in the prediction bar
+pub const MAX_CANDIDATES: usize = ime_plugin_api::MAX_PREDICTIONS;
+
+// just inherit all the default from the ime_plugin_api
+pub use ime_plugin_api::*;
+
+/// Candidates for `input`, best first: the hiragana, the dictionary entries for the whole reading, the
+/// reading converted word by word, the katakana, and then entries that start with the reading.
+///
+/// Until the romaji spells out whole kana, only the partly converted hiragana is offered.
+pub fn candidates(dict: &Dictionary, input: &str) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    if input.is_empty() {
+        return ret;
+    }
+    let hiragana = to_hiragana(input);
+    offer(&mut ret, &hiragana);
+    if hiragana.chars().any(|c| c.is_ascii()) {
+        return ret;
+    }
+    if let Some(exact) = dict.lookup(&hiragana) {
+        for c in exact {
+            offer(&mut ret, c);
+        }
+    }
+    offer(&mut ret, &compose(dict, &hiragana));
+    offer(&mut ret, &to_katakana(&hiragana));
+    for (_reading, completions) in dict.completions(&hiragana) {
+        if let Some(c) = completions.first() {
+            offer(&mut ret, c);
+        }
+        if ret.len() == MAX_CANDIDATES {
+            break;
+        }
+    }
+    ret
+}
+
+fn offer(candidates: &mut Vec<String>, c: &str) {
+    if candidates.len() < MAX_CANDIDATES && !candidates.iter().any(|existing| existing == c) {
+        candidates.push(c.to_string());
+    }
+}
+
+/// Replaces the longest readings the dictionary knows with their first candidates, leaving the kana in
+/// between (particles, okurigana) as they are. Single kana readings are left alone too, otherwise every
+/// き and て would turn into a kanji.
+fn compose(dict: &Dictionary, reading: &str) -> String {
+    let mut composed = String::new();
+    let mut rest = reading;
+    while let Some(c) = rest.chars().next() {
+        let word = dict.longest_reading(rest).filter(|&len| len > c.len_utf8());
+        match word.and_then(|len| Some((len, dict.lookup(&rest[..len])?.first()?))) {
+            Some((len, kanji)) => {
+                composed.push_str(kanji);
+                rest = &rest[len..];
+            }
+            None => {
+                composed.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    composed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict() -> Dictionary {
+        Dictionary::parse("にほん 日本 二本\nにほんご 日本語\nご 語 五\nわたし 私\nにわ 庭\nき 木")
+    }
+
+    #[test]
+    fn test_candidates() {
+        let dict = dict();
+        assert_eq!(candidates(&dict, "nihon"), ["にほん", "日本", "二本", "ニホン"]);
+        assert_eq!(candidates(&dict, "watashiha"), ["わたしは", "私は", "ワタシハ"]);
+        assert!(candidates(&dict, "").is_empty());
+    }
+
+    #[test]
+    fn test_partial_romaji() {
+        // until the romaji spells out whole kana, only the partly converted hiragana is offered
+        assert_eq!(candidates(&dict(), "nihonk"), ["にほんk"]);
+    }
+
+    #[test]
+    fn test_completions() {
+        // entries that start with the reading come last, after the katakana
+        assert_eq!(candidates(&dict(), "ni"), ["に", "ニ", "日本", "日本語"]);
+    }
+
+    #[test]
+    fn test_limit() {
+        let dict = Dictionary::parse("き 木 気 来 機 記 期");
+        let offered = candidates(&dict, "ki");
+        assert_eq!(offered.len(), MAX_CANDIDATES);
+        assert_eq!(offered, ["き", "木", "気", "来"]);
+    }
+
+    #[test]
+    fn test_compose() {
+        let dict = dict();
+        // the longest known reading wins, and single kana are left alone
+        assert_eq!(compose(&dict, "にほんごのき"), "日本語のき");
+        assert_eq!(compose(&dict, "にわにわ"), "庭庭");
+        assert_eq!(compose(&dict, "です"), "です");
+    }
+}
@@ -0,0 +1,164 @@
+//! Romaji to kana. Both Hepburn and Kunrei spellings are accepted, along with the `x`/`l` prefixes for
+//! small kana that most desktop IMEs use.
+
+/// Longest romaji spelling in `ROMAJI`
+const MAX_SPELLING: usize = 4;
+
+#[rustfmt::skip]
+const ROMAJI: &[(&str, &str)] = &[
+    ("a", "あ"), ("i", "い"), ("u", "う"), ("e", "え"), ("o", "お"),
+    ("ka", "か"), ("ki", "き"), ("ku", "く"), ("ke", "け"), ("ko", "こ"),
+    ("ga", "が"), ("gi", "ぎ"), ("gu", "ぐ"), ("ge", "げ"), ("go", "ご"),
+    ("sa", "さ"), ("shi", "し"), ("si", "し"), ("su", "す"), ("se", "せ"), ("so", "そ"),
+    ("za", "ざ"), ("ji", "じ"), ("zi", "じ"), ("zu", "ず"), ("ze", "ぜ"), ("zo", "ぞ"),
+    ("ta", "た"), ("chi", "ち"), ("ti", "ち"), ("tsu", "つ"), ("tu", "つ"), ("te", "て"), ("to", "と"),
+    ("da", "だ"), ("di", "ぢ"), ("du", "づ"), ("de", "で"), ("do", "ど"),
+    ("na", "な"), ("ni", "に"), ("nu", "ぬ"), ("ne", "ね"), ("no", "の"),
+    ("ha", "は"), ("hi", "ひ"), ("fu", "ふ"), ("hu", "ふ"), ("he", "へ"), ("ho", "ほ"),
+    ("ba", "ば"), ("bi", "び"), ("bu", "ぶ"), ("be", "べ"), ("bo", "ぼ"),
+    ("pa", "ぱ"), ("pi", "ぴ"), ("pu", "ぷ"), ("pe", "ぺ"), ("po", "ぽ"),
+    ("ma", "ま"), ("mi", "み"), ("mu", "む"), ("me", "め"), ("mo", "も"),
+    ("ya", "や"), ("yu", "ゆ"), ("yo", "よ"),
+    ("ra", "ら"), ("ri", "り"), ("ru", "る"), ("re", "れ"), ("ro", "ろ"),
+    ("wa", "わ"), ("wo", "を"), ("vu", "ゔ"),
+    ("kya", "きゃ"), ("kyu", "きゅ"), ("kyo", "きょ"),
+    ("gya", "ぎゃ"), ("gyu", "ぎゅ"), ("gyo", "ぎょ"),
+    ("sha", "しゃ"), ("shu", "しゅ"), ("sho", "しょ"), ("she", "しぇ"),
+    ("sya", "しゃ"), ("syu", "しゅ"), ("syo", "しょ"),
+    ("ja", "じゃ"), ("ju", "じゅ"), ("jo", "じょ"), ("je", "じぇ"),
+    ("jya", "じゃ"), ("jyu", "じゅ"), ("jyo", "じょ"),
+    ("zya", "じゃ"), ("zyu", "じゅ"), ("zyo", "じょ"),
+    ("cha", "ちゃ"), ("chu", "ちゅ"), ("cho", "ちょ"), ("che", "ちぇ"),
+    ("tya", "ちゃ"), ("tyu", "ちゅ"), ("tyo", "ちょ"),
+    ("nya", "にゃ"), ("nyu", "にゅ"), ("nyo", "にょ"),
+    ("hya", "ひゃ"), ("hyu", "ひゅ"), ("hyo", "ひょ"),
+    ("bya", "びゃ"), ("byu", "びゅ"), ("byo", "びょ"),
+    ("pya", "ぴゃ"), ("pyu", "ぴゅ"), ("pyo", "ぴょ"),
+    ("mya", "みゃ"), ("myu", "みゅ"), ("myo", "みょ"),
+    ("rya", "りゃ"), ("ryu", "りゅ"), ("ryo", "りょ"),
+    ("fa", "ふぁ"), ("fi", "ふぃ"), ("fe", "ふぇ"), ("fo", "ふぉ"),
+    ("xa", "ぁ"), ("xi", "ぃ"), ("xu", "ぅ"), ("xe", "ぇ"), ("xo", "ぉ"),
+    ("la", "ぁ"), ("li", "ぃ"), ("lu", "ぅ"), ("le", "ぇ"), ("lo", "ぉ"),
+    ("xya", "ゃ"), ("xyu", "ゅ"), ("xyo", "ょ"), ("lya", "ゃ"), ("lyu", "ゅ"), ("lyo", "ょ"),
+    ("xtu", "っ"), ("ltu", "っ"), ("xtsu", "っ"), ("ltsu", "っ"), ("xwa", "ゎ"), ("lwa", "ゎ"),
+];
+
+/// Converts `romaji` to hiragana. Letters that don't spell a kana yet (e.g. the `k` at the end of
+/// `nihonk`) are passed through, so the result shows how far the conversion got.
+pub fn to_hiragana(romaji: &str) -> String {
+    let input: Vec<char> = romaji.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut kana = String::new();
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        let next = input.get(i + 1).copied();
+        if c == 'n' {
+            match next {
+                // `nn` is an explicit ん, unless the second `n` starts the next kana, as in `konnichiha`
+                Some('n') => {
+                    kana.push('ん');
+                    i += if input.get(i + 2).is_some_and(|&c| is_vowel(c) || c == 'y') { 1 } else { 2 };
+                    continue;
+                }
+                // ん before a consonant, or at the end of the input
+                None => {
+                    kana.push('ん');
+                    i += 1;
+                    continue;
+                }
+                Some(n) if !is_vowel(n) && n != 'y' => {
+                    kana.push('ん');
+                    i += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        // a doubled consonant is a small っ, as in `kitte`
+        if c.is_ascii_alphabetic() && !is_vowel(c) && next == Some(c) {
+            kana.push('っ');
+            i += 1;
+            continue;
+        }
+        let matched = (1..=MAX_SPELLING.min(input.len() - i)).rev().find_map(|len| {
+            let spelling: String = input[i..i + len].iter().collect();
+            ROMAJI.iter().find(|(r, _)| *r == spelling).map(|(_, k)| (len, *k))
+        });
+        match matched {
+            Some((len, k)) => {
+                kana.push_str(k);
+                i += len;
+            }
+            None => {
+                kana.push(c);
+                i += 1;
+            }
+        }
+    }
+    kana
+}
+
+/// Converts the hiragana in `hiragana` to katakana, leaving everything else as it is
+pub fn to_katakana(hiragana: &str) -> String {
+    hiragana
+        .chars()
+        .map(|c| match c {
+            'ぁ'..='ゖ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect()
+}
+
+fn is_vowel(c: char) -> bool { matches!(c, 'a' | 'i' | 'u' | 'e' | 'o') }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hiragana() {
+        assert_eq!(to_hiragana("nihongo"), "にほんご");
+        // Hepburn and Kunrei spellings, and upper case
+        assert_eq!(to_hiragana("shitsumon"), "しつもん");
+        assert_eq!(to_hiragana("situmon"), "しつもん");
+        assert_eq!(to_hiragana("FUJI"), "ふじ");
+        assert_eq!(to_hiragana("kyouto"), "きょうと");
+        assert_eq!(to_hiragana("xtsu"), "っ");
+        assert_eq!(to_hiragana("lya"), "ゃ");
+    }
+
+    #[test]
+    fn test_n() {
+        assert_eq!(to_hiragana("konnichiha"), "こんにちは");
+        assert_eq!(to_hiragana("kanji"), "かんじ");
+        assert_eq!(to_hiragana("hon"), "ほん");
+        assert_eq!(to_hiragana("honn"), "ほん");
+        assert_eq!(to_hiragana("konya"), "こにゃ");
+        assert_eq!(to_hiragana("sanpo"), "さんぽ");
+        assert_eq!(to_hiragana("kinnen"), "きんねん");
+    }
+
+    #[test]
+    fn test_sokuon() {
+        assert_eq!(to_hiragana("kitte"), "きって");
+        assert_eq!(to_hiragana("zasshi"), "ざっし");
+        assert_eq!(to_hiragana("matcha"), "まtちゃ");
+    }
+
+    #[test]
+    fn test_partial() {
+        // letters that don't spell a kana yet are passed through
+        assert_eq!(to_hiragana("nihonk"), "にほんk");
+        assert_eq!(to_hiragana("ky"), "ky");
+        assert_eq!(to_hiragana("kk"), "っk");
+        assert_eq!(to_hiragana("a1"), "あ1");
+    }
+
+    #[test]
+    fn test_katakana() {
+        assert_eq!(to_katakana("こーひー"), "コーヒー");
+        assert_eq!(to_katakana("きゃっと"), "キャット");
+        assert_eq!(to_katakana("ゔぁ"), "ヴァ");
+        assert_eq!(to_katakana("abc漢字"), "abc漢字");
+    }
+}
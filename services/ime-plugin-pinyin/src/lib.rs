@@ -0,0 +1,128 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Converts toneless pinyin into hanzi. Candidates for the phrase being typed are offered in the
+//! prediction bar, and picking one replaces the pinyin.
+//!
+//! To use it, register a UX with `predictor: Some(SERVER_NAME_IME_PLUGIN_PINYIN)` and add the service to
+//! the image, e.g. `cargo xtask app-image --service ime-plugin-pinyin --feature ime-pinyin` to have
+//! shellchat use it.
+
+pub const SERVER_NAME_IME_PLUGIN_PINYIN: &str = "_IME pinyin plugin_";
+
+/// PDDB dictionary and key holding the user dictionary, in the same format as the built-in one. It is
+/// read when the plugin is first acquired after the PDDB is mounted.
+pub const PINYIN_DICT: &str = "ime.dict";
+pub const PINYIN_DICT_KEY: &str = "pinyin";
+
+/// Most candidates offered for one phrase, one for each slot in the prediction bar
+pub const MAX_CANDIDATES: usize = ime_plugin_api::MAX_PREDICTIONS;
+
+// just inherit all the default from the ime_plugin_api
+pub use ime_plugin_api::*;
+
+/// Candidates for `input`, best first: the dictionary entries for the whole reading, then the reading
+/// spelled out syllable by syllable, then entries that start with the reading.
+pub fn candidates(dict: &Dictionary, input: &str) -> Vec<String> {
+    let reading = normalize(input);
+    let mut ret: Vec<String> = Vec::new();
+    if reading.is_empty() {
+        return ret;
+    }
+    if let Some(exact) = dict.lookup(&reading) {
+        for c in exact {
+            offer(&mut ret, c);
+        }
+    }
+    if let Some(composed) = compose(dict, &reading) {
+        offer(&mut ret, &composed);
+    }
+    for (_reading, completions) in dict.completions(&reading) {
+        if let Some(c) = completions.first() {
+            offer(&mut ret, c);
+        }
+        if ret.len() == MAX_CANDIDATES {
+            break;
+        }
+    }
+    ret
+}
+
+fn offer(candidates: &mut Vec<String>, c: &str) {
+    if candidates.len() < MAX_CANDIDATES && !candidates.iter().any(|existing| existing == c) {
+        candidates.push(c.to_string());
+    }
+}
+
+/// Lower case, `ü` written as `v`, and tone numbers dropped
+fn normalize(input: &str) -> String {
+    input
+        .chars()
+        .filter_map(|c| match c {
+            'ü' | 'Ü' => Some('v'),
+            c if c.is_ascii_alphabetic() => Some(c.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Splits the reading into the longest readings the dictionary knows, and joins their first candidates
+fn compose(dict: &Dictionary, reading: &str) -> Option<String> {
+    let mut composed = String::new();
+    let mut rest = reading;
+    while !rest.is_empty() {
+        let len = dict.longest_reading(rest)?;
+        composed.push_str(dict.lookup(&rest[..len])?.first()?);
+        rest = &rest[len..];
+    }
+    Some(composed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict() -> Dictionary {
+        Dictionary::parse("ni 你 呢\nhao 好 号\nnihao 你好\nnimen 你们\nnv 女\nai 爱")
+    }
+
+    #[test]
+    fn test_candidates() {
+        let dict = dict();
+        assert_eq!(candidates(&dict, "nihao"), ["你好"]);
+        assert_eq!(candidates(&dict, "haoai"), ["好爱"]);
+        assert_eq!(candidates(&dict, "ni"), ["你", "呢", "你好", "你们"]);
+        assert!(candidates(&dict, "").is_empty());
+        assert!(candidates(&dict, "xyz").is_empty());
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("Ni3Hao3"), "nihao");
+        assert_eq!(normalize("nü"), "nv");
+        assert_eq!(candidates(&dict(), "NÜ"), ["女"]);
+    }
+
+    #[test]
+    fn test_compose() {
+        let dict = dict();
+        // the longest known reading wins
+        assert_eq!(compose(&dict, "nihaohao").as_deref(), Some("你好好"));
+        assert_eq!(compose(&dict, "nihaoai").as_deref(), Some("你好爱"));
+        // every syllable has to be known
+        assert_eq!(compose(&dict, "nixhao"), None);
+    }
+
+    #[test]
+    fn test_limit() {
+        let dict = Dictionary::parse("shi 是 事 十 时 市 使");
+        assert_eq!(candidates(&dict, "shi"), ["是", "事", "十", "时"]);
+        assert_eq!(candidates(&dict, "shi").len(), MAX_CANDIDATES);
+    }
+
+    #[test]
+    fn test_builtin() {
+        let dict = Dictionary::parse(include_str!("../data/pinyin.txt"));
+        assert!(!dict.lookup("ai").unwrap().iter().any(|c| c == "在"));
+        assert_eq!(dict.lookup("zai").unwrap().first().map(|c| c.as_str()), Some("在"));
+    }
+}
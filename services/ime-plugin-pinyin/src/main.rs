@@ -0,0 +1,63 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use std::io::Read;
+
+use ime_plugin_pinyin::*;
+use log::info;
+
+struct Pinyin {
+    dict: Dictionary,
+    pddb: pddb::Pddb,
+    user_dict_loaded: bool,
+}
+
+impl Converter for Pinyin {
+    fn acquired(&mut self) {
+        // the user dictionary can only be read once the PDDB is up, which is usually well after boot; don't
+        // block on it, just try again on the next acquire
+        if !self.user_dict_loaded && self.pddb.is_mounted_nonblocking() {
+            self.user_dict_loaded = true;
+            if let Some(user_dict) = load_user_dictionary(&self.pddb) {
+                log::info!("merging {} readings from the user dictionary", user_dict.len());
+                self.dict.merge_over(user_dict);
+            }
+        }
+    }
+
+    fn candidates(&self, input: &str) -> Vec<String> { ime_plugin_pinyin::candidates(&self.dict, input) }
+}
+
+fn main() -> ! {
+    log_server::init_wait().unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+    info!("my PID is {}", xous::process::id());
+
+    let xns = xous_names::XousNames::new().unwrap();
+    // one connection only, should be the GAM
+    let ime_py_sid = xns
+        .register_name(ime_plugin_pinyin::SERVER_NAME_IME_PLUGIN_PINYIN, None)
+        .expect("can't register server");
+    log::trace!("registered with NS -- {:?}", ime_py_sid);
+
+    let dict = Dictionary::parse(include_str!("../data/pinyin.txt"));
+    log::info!("built-in dictionary has {} readings", dict.len());
+    let mut pinyin = Pinyin { dict, pddb: pddb::Pddb::new(), user_dict_loaded: false };
+    serve_converter(ime_py_sid, &mut pinyin);
+
+    log::trace!("main loop exit, destroying servers");
+    xns.unregister_server(ime_py_sid).unwrap();
+    xous::destroy_server(ime_py_sid).unwrap();
+    log::trace!("quitting");
+    xous::terminate_process(0)
+}
+
+fn load_user_dictionary(pddb: &pddb::Pddb) -> Option<Dictionary> {
+    let mut key = pddb.get(PINYIN_DICT, PINYIN_DICT_KEY, None, false, false, None, None::<fn()>).ok()?;
+    let mut text = String::new();
+    if let Err(e) = key.read_to_string(&mut text) {
+        log::warn!("couldn't read the user dictionary: {:?}", e);
+        return None;
+    }
+    Some(Dictionary::parse(&text))
+}
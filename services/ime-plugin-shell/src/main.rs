@@ -163,6 +163,12 @@ fn main() -> ! {
                     log::warn!("predictor not acquired, ignoring");
                 }
             }
+            Some(Opcode::Forget) => {
+                history_store.clear();
+                if let Some((_token, history)) = &mut active_history {
+                    history.clear();
+                }
+            }
             Some(Opcode::GetPredictionTriggers) => {
                 xous::return_scalar(msg.sender, mytriggers.into())
                     .expect("couldn't return GetPredictionTriggers");
@@ -71,6 +71,7 @@ fn main() -> ! {
                 buffer.replace(Return::Prediction(prediction)).expect("couldn't return Prediction");
             }
             Some(Opcode::Unpick) => {}
+            Some(Opcode::Forget) => {}
             Some(Opcode::GetPredictionTriggers) => {
                 xous::return_scalar(msg.sender, mytriggers.into())
                     .expect("couldn't return GetPredictionTriggers");
@@ -0,0 +1,47 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Completes words from a bundled word list, ranking first the words the user has picked before. What
+//! is learned is kept in the PDDB, and can be wiped with `forget_learned()` (the "Forget learned words"
+//! item in the preferences menu). Nothing is learned while an app has marked its input sensitive with
+//! `Gam::set_input_sensitive()`, nor from anything that looks more like part of a secret than a word.
+//!
+//! To use it, register a UX with `predictor: Some(SERVER_NAME_IME_PLUGIN_WORDS)` and add the service to
+//! the image, e.g. `cargo xtask app-image --service ime-plugin-words --feature ime-words` to have
+//! shellchat use it.
+//!
+//! Word lists live in `data/<language>.txt`, one word per line, most frequent first. Adding a language
+//! takes a list and an entry in `BUNDLED`.
+
+pub mod model;
+
+pub const SERVER_NAME_IME_PLUGIN_WORDS: &str = "_IME word completion plugin_";
+
+/// PDDB dictionary holding the learned words, with one key per language
+pub const WORDS_DICT: &str = "ime.words";
+
+// just inherit all the default from the ime_plugin_api
+pub use ime_plugin_api::*;
+
+/// Bundled word lists by language; the first one is used for languages that don't have one
+const BUNDLED: &[(&str, &str)] = &[("en", include_str!("../data/en.txt"))];
+
+/// The bundled word list for `lang`, and the language it is actually for
+pub fn bundled_words(lang: &str) -> (&'static str, &'static str) {
+    BUNDLED.iter().find(|(l, _)| lang.starts_with(l)).copied().unwrap_or(BUNDLED[0])
+}
+
+/// Wipes the words learned from the user, in the plugin and in the PDDB. If the plugin isn't running,
+/// the saved words are deleted directly.
+pub fn forget_learned(xns: &xous_names::XousNames) -> Result<(), xous::Error> {
+    match xns.request_connection(SERVER_NAME_IME_PLUGIN_WORDS) {
+        Ok(cid) => PredictionPlugin { connection: Some(cid) }.forget(),
+        Err(_) => {
+            let pddb = pddb::Pddb::new();
+            match pddb.delete_dict(WORDS_DICT, None) {
+                Ok(_) => pddb.sync().or(Err(xous::Error::InternalError)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(_) => Err(xous::Error::InternalError),
+            }
+        }
+    }
+}
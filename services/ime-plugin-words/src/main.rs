@@ -0,0 +1,186 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use std::io::{Read, Write};
+
+use ime_plugin_words::model::WordModel;
+use ime_plugin_words::*;
+use log::{error, info};
+use num_traits::FromPrimitive;
+use xous::msg_scalar_unpack;
+use xous_ipc::Buffer;
+
+/// Learned words are saved when the predictor is released, or after this many picks, whichever is first
+const SAVE_EVERY_PICKS: usize = 32;
+
+fn main() -> ! {
+    log_server::init_wait().unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+    info!("my PID is {}", xous::process::id());
+
+    let xns = xous_names::XousNames::new().unwrap();
+    let ime_words_sid = xns
+        .register_name(ime_plugin_words::SERVER_NAME_IME_PLUGIN_WORDS, None)
+        .expect("can't register server");
+    log::trace!("registered with NS -- {:?}", ime_words_sid);
+    let pddb = pddb::Pddb::new();
+
+    let (lang, words) = bundled_words(locales::LANG);
+    let mut model = WordModel::new(words);
+    // the learned words can only be read once the PDDB is up, which is usually well after boot. Until
+    // then, words are learned in memory and merged with the saved ones once they are read.
+    let mut learned_loaded = false;
+    // a forget that came in before the saved words could be read
+    let mut wipe_pending = false;
+    let mut picks_since_save = 0;
+
+    let mytriggers = PredictionTriggers { newline: false, punctuation: true, whitespace: true };
+
+    let mut api_token: Option<[u32; 4]> = None;
+    let mut completions: Vec<String> = Vec::new();
+    loop {
+        let mut msg = xous::receive_message(ime_words_sid).unwrap();
+        log::trace!("received message {:?}", msg);
+        match FromPrimitive::from_usize(msg.body.id()) {
+            Some(Opcode::Acquire) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ret = buffer.to_original::<AcquirePredictor, _>().unwrap();
+                if api_token.is_none() {
+                    if let Some(token) = ret.token {
+                        api_token = Some(token);
+                    } else {
+                        let new_token = xous::create_server_id().unwrap().to_array();
+                        ret.token = Some(new_token);
+                        api_token = Some(new_token);
+                    }
+                } else {
+                    ret.token = None;
+                    log::warn!("attempt to acquire lock on a predictor that was already locked");
+                }
+                buffer.replace(ret).unwrap();
+                if !learned_loaded && pddb.is_mounted_nonblocking() {
+                    learned_loaded = true;
+                    if wipe_pending {
+                        wipe_saved(&pddb);
+                        wipe_pending = false;
+                    } else if let Some(saved) = read_saved(&pddb, lang) {
+                        model.merge_saved(&saved);
+                        log::info!("{} learned words", model.learned_len());
+                    }
+                }
+            }
+            Some(Opcode::Release) => msg_scalar_unpack!(msg, t0, t1, t2, t3, {
+                let token = [t0 as u32, t1 as u32, t2 as u32, t3 as u32];
+                if let Some(t) = api_token {
+                    if t == token {
+                        api_token.take();
+                        completions.clear();
+                        if learned_loaded && model.is_dirty() {
+                            save(&pddb, lang, &mut model);
+                            picks_since_save = 0;
+                        }
+                    } else {
+                        log::warn!("Release called with an invalid token");
+                    }
+                } else {
+                    log::warn!("Release called on a predictor that was in a released state");
+                }
+            }),
+            Some(Opcode::Input) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let s = buffer.as_flat::<String, _>().unwrap();
+                completions = model.completions(s.as_str());
+            }
+            Some(Opcode::Picked) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let s = buffer.as_flat::<String, _>().unwrap();
+                model.learn(s.as_str());
+                picks_since_save += 1;
+                if learned_loaded && picks_since_save >= SAVE_EVERY_PICKS && model.is_dirty() {
+                    save(&pddb, lang, &mut model);
+                    picks_since_save = 0;
+                }
+            }
+            Some(Opcode::Prediction) => {
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut prediction: Prediction = buffer.to_original::<Prediction, _>().unwrap();
+                if api_token == Some(prediction.api_token) {
+                    if let Some(c) = completions.get(prediction.index as usize) {
+                        prediction.string.clear();
+                        prediction.string.push_str(c);
+                        prediction.valid = true;
+                    } else {
+                        prediction.valid = false;
+                    }
+                } else {
+                    prediction.valid = false;
+                    log::warn!("api token mismatch, ignoring");
+                }
+                buffer.replace(Return::Prediction(prediction)).expect("couldn't return Prediction");
+            }
+            Some(Opcode::Unpick) => model.unlearn_last(),
+            Some(Opcode::Forget) => {
+                model.forget();
+                completions.clear();
+                picks_since_save = 0;
+                if pddb.is_mounted_nonblocking() {
+                    wipe_saved(&pddb);
+                    learned_loaded = true;
+                } else {
+                    wipe_pending = true;
+                }
+                log::info!("learned words forgotten");
+            }
+            Some(Opcode::GetPredictionTriggers) => {
+                xous::return_scalar(msg.sender, mytriggers.into())
+                    .expect("couldn't return GetPredictionTriggers");
+            }
+            Some(Opcode::Quit) => {
+                if api_token.is_some() {
+                    error!("received quit, goodbye!");
+                    break;
+                }
+            }
+            None => {
+                error!("unknown Opcode");
+            }
+        }
+    }
+    log::trace!("main loop exit, destroying servers");
+    xns.unregister_server(ime_words_sid).unwrap();
+    xous::destroy_server(ime_words_sid).unwrap();
+    log::trace!("quitting");
+    xous::terminate_process(0)
+}
+
+fn read_saved(pddb: &pddb::Pddb, lang: &str) -> Option<String> {
+    let mut key = pddb.get(WORDS_DICT, lang, None, false, false, None, None::<fn()>).ok()?;
+    let mut saved = String::new();
+    if let Err(e) = key.read_to_string(&mut saved) {
+        log::warn!("couldn't read the learned words: {:?}", e);
+        return None;
+    }
+    Some(saved)
+}
+
+fn save(pddb: &pddb::Pddb, lang: &str, model: &mut WordModel) {
+    let saved = model.save();
+    // delete first, so a shorter list doesn't leave the tail of the previous one behind
+    pddb.delete_key(WORDS_DICT, lang, None).ok();
+    match pddb.get(WORDS_DICT, lang, None, true, true, Some(saved.len()), None::<fn()>) {
+        Ok(mut key) => {
+            if let Err(e) = key.write_all(saved.as_bytes()) {
+                log::warn!("couldn't save the learned words: {:?}", e);
+            }
+        }
+        Err(e) => log::warn!("couldn't create the learned words key: {:?}", e),
+    }
+    pddb.sync().ok();
+}
+
+fn wipe_saved(pddb: &pddb::Pddb) {
+    pddb.delete_dict(WORDS_DICT, None).ok();
+    pddb.sync().ok();
+}
@@ -0,0 +1,259 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Most completions offered for one word
+pub const MAX_COMPLETIONS: usize = 4;
+/// Most words remembered; past this, the least picked word is dropped to make room
+pub const MAX_LEARNED: usize = 2048;
+/// Words outside this length are not learned. Long ones are more likely to be pasted secrets than
+/// vocabulary.
+const LEARNED_LEN: core::ops::RangeInclusive<usize> = 2..=32;
+
+/// Ranks completions for a word being typed. Words the user has picked come first, most picked first,
+/// then words from the bundled list in the order they appear in it.
+pub struct WordModel {
+    /// bundled words, mapped to their position in the list
+    bundled: BTreeMap<&'static str, usize>,
+    /// lower case words the user has picked, and how many times
+    learned: BTreeMap<String, u32>,
+    /// the words from the most recent pick, so they can be unlearned
+    last_learned: Vec<String>,
+    /// set when `learned` has changed since it was last saved
+    dirty: bool,
+}
+
+impl WordModel {
+    /// `bundled` has one word per line, most frequent first. Blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn new(bundled: &'static str) -> Self {
+        let mut words = BTreeMap::new();
+        for word in bundled.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let rank = words.len();
+            words.entry(word).or_insert(rank);
+        }
+        WordModel { bundled: words, learned: BTreeMap::new(), last_learned: Vec::new(), dirty: false }
+    }
+
+    pub fn completions(&self, input: &str) -> Vec<String> {
+        let prefix = input.to_lowercase();
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+        // (times picked, position in the bundled list)
+        let mut found: BTreeMap<&str, (u32, usize)> = BTreeMap::new();
+        for (word, &count) in
+            self.learned.range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        {
+            if !word.starts_with(&prefix) {
+                break;
+            }
+            found.insert(word, (count, usize::MAX));
+        }
+        for (&word, &rank) in
+            self.bundled.range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        {
+            if !word.starts_with(&prefix) {
+                break;
+            }
+            found.entry(word).or_insert((0, usize::MAX)).1 = rank;
+        }
+        found.remove(prefix.as_str());
+        let mut ranked: Vec<(&str, (u32, usize))> = found.into_iter().collect();
+        ranked.sort_by_key(|&(_, (count, rank))| (Reverse(count), rank));
+        ranked.into_iter().take(MAX_COMPLETIONS).map(|(word, _)| match_case(input, word)).collect()
+    }
+
+    /// Counts the words in `phrase` as picked. Anything that looks more like part of a secret than a word
+    /// is skipped: digits or symbols mixed in, capitals past the first letter of a word that isn't all
+    /// capitals, and lengths outside `LEARNED_LEN`.
+    pub fn learn(&mut self, phrase: &str) {
+        self.last_learned.clear();
+        for word in phrase.split(|c: char| c.is_whitespace() || SEPARATORS.contains(&c)) {
+            if !learnable(word) {
+                continue;
+            }
+            let word = word.to_lowercase();
+            *self.learned.entry(word.clone()).or_insert(0) += 1;
+            self.last_learned.push(word);
+            self.dirty = true;
+        }
+        while self.learned.len() > MAX_LEARNED {
+            let least = self
+                .learned
+                .iter()
+                .filter(|(word, _)| !self.last_learned.contains(word))
+                .min_by_key(|(_, &count)| count)
+                .map(|(word, _)| word.clone());
+            match least {
+                Some(word) => self.learned.remove(&word),
+                None => break,
+            };
+        }
+    }
+
+    /// Takes back the most recent `learn()`
+    pub fn unlearn_last(&mut self) {
+        for word in self.last_learned.drain(..) {
+            if let Some(count) = self.learned.get_mut(&word) {
+                *count -= 1;
+                if *count == 0 {
+                    self.learned.remove(&word);
+                }
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn forget(&mut self) {
+        self.learned.clear();
+        self.last_learned.clear();
+        self.dirty = false;
+    }
+
+    pub fn is_dirty(&self) -> bool { self.dirty }
+
+    /// The learned words as saved in the PDDB: one `word count` pair per line. Clears the dirty flag.
+    pub fn save(&mut self) -> String {
+        self.dirty = false;
+        self.learned.iter().map(|(word, count)| format!("{} {}\n", word, count)).collect()
+    }
+
+    /// Adds counts saved by `save()` to the ones learned so far
+    pub fn merge_saved(&mut self, saved: &str) {
+        for line in saved.lines() {
+            let mut fields = line.split_whitespace();
+            if let (Some(word), Some(Ok(count))) = (fields.next(), fields.next().map(|c| c.parse::<u32>())) {
+                *self.learned.entry(word.to_string()).or_insert(0) += count;
+            }
+        }
+    }
+
+    pub fn learned_len(&self) -> usize { self.learned.len() }
+}
+
+/// Punctuation that ends a word; any other symbol is taken as part of it
+const SEPARATORS: &[char] = &['.', ',', ';', ':', '!', '?', '"', '(', ')'];
+
+fn learnable(word: &str) -> bool {
+    let mut rest = word.chars().skip(1);
+    LEARNED_LEN.contains(&word.chars().count())
+        && word.chars().all(|c| c.is_alphabetic())
+        && (rest.all(|c| !c.is_uppercase()) || word.chars().all(|c| !c.is_lowercase()))
+}
+
+/// Follows the case of what was typed: `He` completes to `Hello`, and `HE` to `HELLO`
+fn match_case(input: &str, word: &str) -> String {
+    let mut typed = input.chars().filter(|c| c.is_alphabetic());
+    match (typed.next(), typed.next()) {
+        (Some(first), Some(second)) if first.is_uppercase() && second.is_uppercase() => word.to_uppercase(),
+        (Some(first), _) if first.is_uppercase() => {
+            let mut chars = word.chars();
+            chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+        }
+        _ => word.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLED: &str = "# most frequent first\nthe\nthere\nthen\nthey\nthem\nhello\nhelp\n";
+
+    #[test]
+    fn test_completions() {
+        let model = WordModel::new(BUNDLED);
+        assert_eq!(model.completions("the"), ["there", "then", "they", "them"]);
+        assert_eq!(model.completions("He"), ["Hello", "Help"]);
+        assert_eq!(model.completions("HE"), ["HELLO", "HELP"]);
+        assert!(model.completions("").is_empty());
+        assert!(model.completions("xyz").is_empty());
+    }
+
+    #[test]
+    fn test_learn() {
+        let mut model = WordModel::new(BUNDLED);
+        assert!(!model.is_dirty());
+        model.learn("Theme");
+        model.learn("theory, themes.");
+        model.learn("themes");
+        assert!(model.is_dirty());
+        assert_eq!(model.learned_len(), 3);
+        // picked words come first, most picked first, then the bundled ones
+        assert_eq!(model.completions("the"), ["themes", "theme", "theory", "there"]);
+        assert_eq!(model.completions("hel"), ["hello", "help"]);
+    }
+
+    #[test]
+    fn test_learn_skips_secrets() {
+        let mut model = WordModel::new(BUNDLED);
+        model.learn("hunter2 Tr0ub4dor&3 p@ssword CorrectHorse xKcd a");
+        model.learn("supercalifragilisticexpialidociousness");
+        assert_eq!(model.learned_len(), 0);
+        assert!(!model.is_dirty());
+        model.learn("NASA Paris");
+        assert_eq!(model.completions("na"), ["nasa"]);
+        assert_eq!(model.completions("pa"), ["paris"]);
+    }
+
+    #[test]
+    fn test_unlearn() {
+        let mut model = WordModel::new(BUNDLED);
+        model.learn("theme");
+        model.learn("theme theory");
+        model.unlearn_last();
+        // only the most recent pick is taken back, and only once
+        model.unlearn_last();
+        assert_eq!(model.learned_len(), 1);
+        assert_eq!(model.completions("theo"), Vec::<String>::new());
+        assert_eq!(model.completions("them"), ["theme"]);
+    }
+
+    #[test]
+    fn test_eviction() {
+        // distinct all-letter words, since digits aren't learned
+        let word =
+            |i: usize| -> String { format!("{:04}", i).bytes().map(|b| (b - b'0' + b'a') as char).collect() };
+        let mut model = WordModel::new(BUNDLED);
+        model.learn("favourite");
+        model.learn("favourite");
+        for i in 0..MAX_LEARNED {
+            model.learn(&word(i));
+        }
+        assert_eq!(model.learned_len(), MAX_LEARNED);
+        // a least picked word made room; the most picked one and the one just picked stay
+        let saved = model.save();
+        assert!(saved.contains("favourite 2\n"));
+        assert!(saved.contains(&format!("{} 1\n", word(MAX_LEARNED - 1))));
+        assert_eq!(saved.lines().filter(|l| l.ends_with(" 1")).count(), MAX_LEARNED - 1);
+    }
+
+    #[test]
+    fn test_save_and_merge() {
+        let mut model = WordModel::new(BUNDLED);
+        model.learn("theme theme theory");
+        let saved = model.save();
+        assert!(!model.is_dirty());
+        assert_eq!(saved, "theme 2\ntheory 1\n");
+
+        // words learned before the saved ones could be read are added to them
+        let mut restored = WordModel::new(BUNDLED);
+        restored.learn("theory");
+        restored.merge_saved(&saved);
+        restored.merge_saved("garbage\nnot-a-count x\n");
+        assert_eq!(restored.learned_len(), 2);
+        assert_eq!(restored.save(), "theme 2\ntheory 2\n");
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut model = WordModel::new(BUNDLED);
+        model.learn("theme");
+        model.forget();
+        assert_eq!(model.learned_len(), 0);
+        assert!(!model.is_dirty());
+        model.unlearn_last();
+        assert_eq!(model.completions("the"), ["there", "then", "they", "them"]);
+    }
+}
@@ -2,18 +2,11 @@ use std::fmt::Display;
 
 pub const SERVER_NAME_KBD: &str = "_Matrix keyboard driver_";
 
-#[allow(dead_code)]
-#[derive(Debug, Default, Copy, Clone)]
-pub struct ScanCode {
-    /// base key value
-    pub key: Option<char>,
-    /// tap blue shift key, then key
-    pub shift: Option<char>,
-    /// hold blue shift key, then key
-    pub hold: Option<char>,
-    /// hold orange shift key, then key
-    pub alt: Option<char>,
-}
+pub use keyboard_layout::{Layout, ParseError, ScanCode};
+
+/// PDDB dictionary holding the user's keyboard layouts, one key per layout, in the format described in the
+/// `keyboard-layout` crate. The preferences menu offers them next to the built-in ones.
+pub const LAYOUT_DICT: &str = "kbd.layouts";
 
 /// Maintainer note: there is a "BackupKeyboardLayout" serializer inside
 /// root-keys/api.rs that needs to be updated when this changes.
@@ -24,6 +17,8 @@ pub enum KeyMap {
     Qwertz,
     Dvorak,
     Braille,
+    /// a layout loaded at runtime with `Keyboard::set_layout()`
+    Custom,
     Undefined,
 }
 impl From<usize> for KeyMap {
@@ -34,6 +29,7 @@ impl From<usize> for KeyMap {
             2 => KeyMap::Qwertz,
             3 => KeyMap::Dvorak,
             4 => KeyMap::Braille,
+            5 => KeyMap::Custom,
             _ => KeyMap::Qwerty,
         }
     }
@@ -47,6 +43,7 @@ impl From<KeyMap> for usize {
             KeyMap::Qwertz => 2,
             KeyMap::Dvorak => 3,
             KeyMap::Braille => 4,
+            KeyMap::Custom => 5,
             KeyMap::Undefined => 255,
         }
     }
@@ -60,6 +57,7 @@ impl Display for KeyMap {
             Self::Qwertz => write!(f, "QWERTZ"),
             Self::Dvorak => write!(f, "Dvorak"),
             Self::Braille => write!(f, "Braille"),
+            Self::Custom => write!(f, "Custom"),
             Self::Undefined => write!(f, "Undefined"),
         }
     }
@@ -102,6 +100,12 @@ pub(crate) enum Opcode {
 
     /// Suspend/resume callback
     SuspendResume = 10,
+
+    /// load a layout description and select it as `KeyMap::Custom`
+    SetLayout = 13, //(String)
+
+    /// the built-in mapping typed until a custom layout is loaded, e.g. when entering the PDDB PIN
+    GetBaseKeyMap = 14,
 }
 
 // this structure is used to register a keyboard listener. Currently, we only accept
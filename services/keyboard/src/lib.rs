@@ -56,6 +56,16 @@ impl Keyboard {
         .map(|_| ())
     }
 
+    /// Loads a layout description, in the format of the `keyboard-layout` crate, and switches to it. The
+    /// layout isn't remembered across reboots: until it is loaded again, which can't happen before the
+    /// PDDB is mounted, the keyboard types the built-in mapping from `get_base_keymap()`. That includes
+    /// the PIN that unlocks the PDDB. Call `Layout::parse()` first to find out why a layout is rejected.
+    pub fn set_layout(&self, layout: &str) -> Result<(), xous::Error> {
+        Layout::parse(layout).or(Err(xous::Error::InvalidString))?;
+        let buf = Buffer::into_buf(String::from(layout)).or(Err(xous::Error::InternalError))?;
+        buf.lend(self.conn, Opcode::SetLayout.to_u32().unwrap()).map(|_| ())
+    }
+
     pub fn get_keymap(&self) -> Result<KeyMap, xous::Error> {
         match send_message(
             self.conn,
@@ -66,6 +76,18 @@ impl Keyboard {
         }
     }
 
+    /// The built-in mapping that was last selected, which is what the keyboard types while a custom layout
+    /// isn't loaded. Never `KeyMap::Custom`.
+    pub fn get_base_keymap(&self) -> Result<KeyMap, xous::Error> {
+        match send_message(
+            self.conn,
+            Message::new_blocking_scalar(Opcode::GetBaseKeyMap.to_usize().unwrap(), 0, 0, 0, 0),
+        ) {
+            Ok(xous::Result::Scalar1(code)) => Ok(code.into()),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
     /// Blocks until a key is hit. Does not block the keyboard server, just the caller.
     /// Returns a `Vec::<char>`, as the user can press more than one key at a time.
     /// The specific order of a simultaneous key hit event is not defined.
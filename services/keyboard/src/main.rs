@@ -43,6 +43,12 @@ mod implementation {
         ticktimer: Ticktimer,
         /// mapping for ScanCode translation
         map: KeyMap,
+        /// the layout typed with `map`
+        layout: Layout,
+        /// the layout last loaded with `set_layout()`, typed with `KeyMap::Custom`
+        custom_layout: Option<Layout>,
+        /// a dead key waiting for the character it modifies
+        dead_key: Option<char>,
         /// delay in ms before a key is considered to be repeating
         delay: u32,
         /// rate in ms for repeating a key
@@ -168,8 +174,7 @@ mod implementation {
                 // the right language before typing in the password to unlock the PDDB, which
                 // is *actually* where all the user settings should be located.
                 let kb_raw = ea.get_keymap().expect("cannot fetch keymap from early settings");
-                let map = KeyMap::from(kb_raw);
-                map
+                base_map(kb_raw)
             };
 
             Keyboard {
@@ -180,6 +185,9 @@ mod implementation {
                 last_state: HashSet::with_capacity(16),
                 ticktimer,
                 map: default_map,
+                layout: layout_for(default_map, None),
+                custom_layout: None,
+                dead_key: None,
                 delay: 500,
                 rate: 50, // ubuntu default rate is 90, windows is 30
                 shift_down: false,
@@ -233,6 +241,7 @@ mod implementation {
             self.shift_up = false;
             self.alt_down = false;
             self.alt_up = false;
+            self.dead_key = None;
             self.repeating_key = None;
             self.chord_captured = false;
             self.chord_active = 0;
@@ -249,16 +258,45 @@ mod implementation {
         }
 
         pub(crate) fn set_map(&mut self, map: KeyMap) {
+            if let KeyMap::Custom = map {
+                // only `set_layout()` can select a custom layout, since it has to come with one
+                log::warn!("ignoring a request to select the custom layout without loading one");
+                return;
+            }
             self.early_settings.set_keymap(map.into()).expect("cannot set early keymap");
-            self.map = map;
+            self.select_map(map);
         }
 
         pub(crate) fn get_map(&mut self) -> KeyMap {
-            let kb_raw = self.early_settings.get_keymap().expect("cannot fetch early keymap");
-            self.map = KeyMap::from(kb_raw);
+            // a custom layout only lives in RAM; otherwise the early settings are authoritative
+            if let KeyMap::Custom = self.map {
+                return self.map;
+            }
+            let map = self.get_base_map();
+            if usize::from(map) != usize::from(self.map) {
+                self.select_map(map);
+            }
             self.map
         }
 
+        pub(crate) fn get_base_map(&mut self) -> KeyMap {
+            base_map(self.early_settings.get_keymap().expect("cannot fetch early keymap"))
+        }
+
+        pub(crate) fn set_layout(&mut self, layout: Layout) {
+            log::info!("switching to the {} layout", layout.name);
+            // the early settings keep the built-in mapping, since the custom layout can't be loaded before
+            // the PDDB is mounted and the PIN has to be typed with something that exists at boot
+            self.custom_layout = Some(layout);
+            self.select_map(KeyMap::Custom);
+        }
+
+        fn select_map(&mut self, map: KeyMap) {
+            self.map = map;
+            self.layout = layout_for(map, self.custom_layout.as_ref());
+            self.dead_key = None;
+        }
+
         pub(crate) fn set_repeat(&mut self, rate: u32, delay: u32) {
             self.rate = rate;
             self.delay = delay;
@@ -501,74 +539,68 @@ mod implementation {
 
             // first check for shift and alt keys
             for rc in krs.keydowns.iter() {
-                match self.map {
-                    KeyMap::Azerty => {
-                        if (rc.r == 8) && (rc.c == 5) {
-                            // left shift (orange)
-                            if self.alt_up == false {
-                                self.alt_down = true;
-                            } else {
-                                self.alt_up = false;
-                            }
-                        } else if (rc.r == 8) && (rc.c == 9) {
-                            // right shift (yellow)
-                            if self.shift_up == false {
-                                self.shift_down = true;
-                            } else {
-                                self.shift_up = false;
-                            }
+                if self.layout.left_shift_alt {
+                    if (rc.r == 8) && (rc.c == 5) {
+                        // left shift (orange)
+                        if self.alt_up == false {
+                            self.alt_down = true;
+                        } else {
+                            self.alt_up = false;
+                        }
+                    } else if (rc.r == 8) && (rc.c == 9) {
+                        // right shift (yellow)
+                        if self.shift_up == false {
+                            self.shift_down = true;
+                        } else {
+                            self.shift_up = false;
                         }
                     }
-                    _ => {
-                        // the rest just have one color of shift
-                        if ((rc.r == 8) && (rc.c == 5)) || ((rc.r == 8) && (rc.c == 9)) {
-                            // if the shift key was tapped twice, remove the shift modifier
-                            if self.shift_up == false {
-                                //info!("shift down true");
-                                self.shift_down = true;
-                            } else {
-                                //info!("shift up false");
-                                self.shift_up = false;
-                            }
+                } else {
+                    // the rest just have one color of shift
+                    if ((rc.r == 8) && (rc.c == 5)) || ((rc.r == 8) && (rc.c == 9)) {
+                        // if the shift key was tapped twice, remove the shift modifier
+                        if self.shift_up == false {
+                            //info!("shift down true");
+                            self.shift_down = true;
+                        } else {
+                            //info!("shift up false");
+                            self.shift_up = false;
                         }
                     }
                 }
             }
             let mut keyups_noshift: Vec<RowCol> = Vec::new();
             for &rc in krs.keyups.iter() {
-                match self.map {
-                    KeyMap::Azerty => {
-                        if (rc.r == 8) && (rc.c == 5) {
-                            // left shift (orange)
-                            if self.alt_down {
-                                self.alt_up = true;
-                            }
-                            self.alt_down = false;
-                        } else if (rc.r == 8) && (rc.c == 9) {
-                            // right shift (yellow)
-                            if self.shift_down {
-                                self.shift_up = true;
-                            }
-                            self.shift_down = false;
-                        } else {
-                            keyups_noshift.push(RowCol { r: rc.r as _, c: rc.c as _ });
+                if self.layout.left_shift_alt {
+                    if (rc.r == 8) && (rc.c == 5) {
+                        // left shift (orange)
+                        if self.alt_down {
+                            self.alt_up = true;
                         }
+                        self.alt_down = false;
+                    } else if (rc.r == 8) && (rc.c == 9) {
+                        // right shift (yellow)
+                        if self.shift_down {
+                            self.shift_up = true;
+                        }
+                        self.shift_down = false;
+                    } else {
+                        keyups_noshift.push(RowCol { r: rc.r as _, c: rc.c as _ });
                     }
-                    _ => {
-                        // the rest just have one color of shift
-                        if ((rc.r == 8) && (rc.c == 5)) || ((rc.r == 8) && (rc.c == 9)) {
-                            // only set the shift-up if we didn't previously clear it with a double-tap of
-                            // shift
-                            if self.shift_down {
-                                //info!("shift up true");
-                                self.shift_up = true;
-                            }
-                            //info!("shift down false");
-                            self.shift_down = false;
-                        } else {
-                            //info!("adding non-shift entry {:?}", rc);
-                            keyups_noshift.push(RowCol { r: rc.r as _, c: rc.c as _ });
+                } else {
+                    // the rest just have one color of shift
+                    if ((rc.r == 8) && (rc.c == 5)) || ((rc.r == 8) && (rc.c == 9)) {
+                        // only set the shift-up if we didn't previously clear it with a double-tap of
+                        // shift
+                        if self.shift_down {
+                            //info!("shift up true");
+                            self.shift_up = true;
                         }
+                        //info!("shift down false");
+                        self.shift_down = false;
+                    } else {
+                        //info!("adding non-shift entry {:?}", rc);
+                        keyups_noshift.push(RowCol { r: rc.r as _, c: rc.c as _ });
                     }
                 }
             }
@@ -578,13 +610,7 @@ mod implementation {
                 self.chord_timestamp = self.ticktimer.elapsed_ms();
             }
             for &rc in krs.keydowns.iter() {
-                let code = match self.map {
-                    KeyMap::Qwerty => map_qwerty(rc),
-                    KeyMap::Dvorak => map_dvorak(rc),
-                    KeyMap::Azerty => map_azerty(rc),
-                    KeyMap::Qwertz => map_qwertz(rc),
-                    _ => ScanCode { key: None, shift: None, hold: None, alt: None },
-                };
+                let code = self.layout.scan_code(rc.r, rc.c);
                 if code.hold == None && !((rc.r == 5) && (rc.c == 2))
                 // scan code for the menu key
                 {
@@ -607,15 +633,10 @@ mod implementation {
                 hold = false;
             }
 
+            let mut typed: Vec<char> = Vec::new();
             for &rc in keyups_noshift.iter() {
                 // info!("interpreting keyups_noshift entry {:?}", rc);
-                let code = match self.map {
-                    KeyMap::Qwerty => map_qwerty(rc),
-                    KeyMap::Dvorak => map_dvorak(rc),
-                    KeyMap::Azerty => map_azerty(rc),
-                    KeyMap::Qwertz => map_qwertz(rc),
-                    _ => ScanCode { key: None, shift: None, hold: None, alt: None },
-                };
+                let code = self.layout.scan_code(rc.r, rc.c);
                 // delete the key repeat if there is one
                 if code.hold == None {
                     if let Some(key) = code.key {
@@ -627,60 +648,60 @@ mod implementation {
                     }
                 }
 
-                match self.map {
-                    KeyMap::Azerty => {
-                        if self.shift_down || self.shift_up {
-                            if let Some(shiftcode) = code.shift {
-                                ks.push(shiftcode);
-                            } else if let Some(keycode) = code.key {
-                                ks.push(keycode);
-                            }
-                            self.shift_down = false;
-                            self.shift_up = false;
-                        } else if self.alt_down || self.alt_up {
-                            if let Some(altcode) = code.alt {
-                                ks.push(altcode);
-                            } else if let Some(shiftcode) = code.shift {
-                                ks.push(shiftcode);
-                            } else if let Some(keycode) = code.key {
-                                ks.push(keycode);
-                            }
-                            self.alt_down = false;
-                            self.alt_up = false;
-                        } else if hold {
-                            if let Some(holdcode) = code.hold {
-                                ks.push(holdcode);
-                            }
-                        } else {
-                            if let Some(keycode) = code.key {
-                                ks.push(keycode);
-                            }
+                if self.layout.left_shift_alt {
+                    if self.shift_down || self.shift_up {
+                        if let Some(shiftcode) = code.shift {
+                            typed.push(shiftcode);
+                        } else if let Some(keycode) = code.key {
+                            typed.push(keycode);
+                        }
+                        self.shift_down = false;
+                        self.shift_up = false;
+                    } else if self.alt_down || self.alt_up {
+                        if let Some(altcode) = code.alt {
+                            typed.push(altcode);
+                        } else if let Some(shiftcode) = code.shift {
+                            typed.push(shiftcode);
+                        } else if let Some(keycode) = code.key {
+                            typed.push(keycode);
+                        }
+                        self.alt_down = false;
+                        self.alt_up = false;
+                    } else if hold {
+                        if let Some(holdcode) = code.hold {
+                            typed.push(holdcode);
+                        }
+                    } else {
+                        if let Some(keycode) = code.key {
+                            typed.push(keycode);
                         }
                     }
-                    _ => {
-                        if self.shift_down || self.alt_down || self.shift_up || self.alt_up {
-                            if let Some(shiftcode) = code.shift {
-                                ks.push(shiftcode);
-                            } else if let Some(keycode) = code.key {
-                                ks.push(keycode);
-                            }
-                            self.shift_down = false;
-                            self.alt_down = false;
-                            self.shift_up = false;
-                            self.alt_up = false;
-                        } else if hold {
-                            if let Some(holdcode) = code.hold {
-                                ks.push(holdcode);
-                            }
-                        } else {
-                            if let Some(keycode) = code.key {
-                                // info!("appeding normal key '{}'", keycode);
-                                ks.push(keycode);
-                            }
+                } else {
+                    if self.shift_down || self.alt_down || self.shift_up || self.alt_up {
+                        if let Some(shiftcode) = code.shift {
+                            typed.push(shiftcode);
+                        } else if let Some(keycode) = code.key {
+                            typed.push(keycode);
+                        }
+                        self.shift_down = false;
+                        self.alt_down = false;
+                        self.shift_up = false;
+                        self.alt_up = false;
+                    } else if hold {
+                        if let Some(holdcode) = code.hold {
+                            typed.push(holdcode);
+                        }
+                    } else {
+                        if let Some(keycode) = code.key {
+                            // info!("appeding normal key '{}'", keycode);
+                            typed.push(keycode);
                         }
                     }
                 }
             }
+            for c in typed {
+                self.layout.compose(&mut self.dead_key, c, &mut ks);
+            }
 
             // if we're in a key hold state, we've passed the rate timestamp point, and there's a repeating
             // key defined
@@ -707,6 +728,7 @@ mod implementation {
     pub(crate) struct Keyboard {
         cid: xous::CID,
         map: KeyMap,
+        base_map: KeyMap,
         rate: u32,
         delay: u32,
         chord_interval: u32,
@@ -718,6 +740,7 @@ mod implementation {
             Keyboard {
                 cid: xous::connect(sid).unwrap(),
                 map: KeyMap::Qwerty,
+                base_map: KeyMap::Qwerty,
                 rate: 20,
                 delay: 200,
                 chord_interval: 50,
@@ -731,10 +754,19 @@ mod implementation {
 
         pub fn resume(&self) {}
 
-        pub fn set_map(&mut self, map: KeyMap) { self.map = map; }
+        pub fn set_map(&mut self, map: KeyMap) {
+            if !matches!(map, KeyMap::Custom) {
+                self.map = map;
+                self.base_map = map;
+            }
+        }
+
+        pub fn set_layout(&mut self, _layout: Layout) { self.map = KeyMap::Custom; }
 
         pub fn get_map(&self) -> KeyMap { self.map }
 
+        pub fn get_base_map(&self) -> KeyMap { self.base_map }
+
         pub fn update(&self) -> KeyRawStates { KeyRawStates::new() }
 
         pub fn track_chord(&mut self, _krs: &KeyRawStates) -> Vec<char> { Vec::new() }
@@ -897,6 +929,17 @@ fn main() -> ! {
             Some(Opcode::GetKeyMap) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
                 xous::return_scalar(msg.sender, kbd.get_map().into()).expect("can't retrieve keymap");
             }),
+            Some(Opcode::GetBaseKeyMap) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
+                xous::return_scalar(msg.sender, kbd.get_base_map().into()).expect("can't retrieve keymap");
+            }),
+            Some(Opcode::SetLayout) => {
+                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                let text = buffer.as_flat::<String, _>().unwrap();
+                match Layout::parse(text.as_str()) {
+                    Ok(layout) => kbd.set_layout(layout),
+                    Err(e) => log::error!("keyboard layout rejected: {}", e),
+                }
+            }
             Some(Opcode::SetRepeat) => msg_scalar_unpack!(msg, rate, delay, _, _, {
                 kbd.set_repeat(rate as u32, delay as u32);
             }),
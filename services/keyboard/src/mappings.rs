@@ -1,14 +1,26 @@
 #![allow(dead_code)] // because hosted mode doesn't use mappings
-#![allow(unused_imports)]
-#[rustfmt::skip] // this file is a lookup table. Allow wide columns.
-mod qwerty;
-pub(crate) use qwerty::*;
-#[rustfmt::skip] // this file is a lookup table. Allow wide columns.
-mod qwertz;
-pub(crate) use qwertz::*;
-#[rustfmt::skip] // this file is a lookup table. Allow wide columns.
-mod azerty;
-pub(crate) use azerty::*;
-#[rustfmt::skip] // this file is a lookup table. Allow wide columns.
-mod dvorak;
-pub(crate) use dvorak::*;
+use crate::{KeyMap, Layout};
+
+/// The layout typed with `map`. Braille is chorded and doesn't use one. `KeyMap::Custom` falls back to
+/// QWERTY if no layout has been loaded.
+pub(crate) fn layout_for(map: KeyMap, custom: Option<&Layout>) -> Layout {
+    if let (KeyMap::Custom, Some(layout)) = (map, custom) {
+        return layout.clone();
+    }
+    let text = match map {
+        KeyMap::Azerty => keyboard_layout::AZERTY,
+        KeyMap::Qwertz => keyboard_layout::QWERTZ,
+        KeyMap::Dvorak => keyboard_layout::DVORAK,
+        _ => keyboard_layout::QWERTY,
+    };
+    Layout::parse(text).expect("built-in layouts are checked by the keyboard-layout tests")
+}
+
+/// The built-in mapping kept in the early settings. Custom layouts are never kept there, as they can't
+/// be loaded before the PDDB is mounted; should one be found anyway, QWERTY is typed in its place.
+pub(crate) fn base_map(kb_raw: usize) -> KeyMap {
+    match KeyMap::from(kb_raw) {
+        KeyMap::Custom => KeyMap::Qwerty,
+        map => map,
+    }
+}
@@ -34,6 +34,10 @@ pub struct ManagedPromptWithTextResponse {
     /// placeholders
     pub placeholders: Option<[Option<(String, bool)>; 10]>,
     pub growable: bool,
+    /// `Some` to edit the one field in a multi-line editor, holding at most this many characters
+    pub multiline: Option<u32>,
+    /// obscure the entered text, as for a password
+    pub password: bool,
 }
 
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Clone)]
@@ -167,4 +171,7 @@ pub(crate) enum Opcode {
     Gutter = 29,
 
     Quit = 30,
+
+    /// switch the language modals are shown in; the argument is an index into `locales::LANGUAGES`
+    SetLanguage = 36,
 }
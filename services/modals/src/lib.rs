@@ -24,6 +24,8 @@ pub struct AlertModalBuilder<'a> {
     validators: Vec<Option<TextValidationFn>>,
     placeholders: Vec<Option<(String, bool)>>,
     growable: bool,
+    multiline: Option<usize>,
+    password: bool,
     modals: &'a Modals,
 }
 
@@ -65,6 +67,22 @@ impl<'a> AlertModalBuilder<'a> {
         self
     }
 
+    /// Edits the field in a multi-line editor, with word wrap, scrolling and cut/copy/paste, instead of a
+    /// one-line entry. Only a single field can be edited this way. Text is limited to `max_chars` characters,
+    /// or `gam::modal::DEFAULT_EDITOR_LIMIT` if `None`, and never more than `gam::modal::MAX_EDITOR_LIMIT`.
+    /// Enter starts a new line; the select key finishes.
+    pub fn multiline(&'a mut self, max_chars: Option<usize>) -> &'a mut Self {
+        self.multiline = Some(max_chars.unwrap_or(DEFAULT_EDITOR_LIMIT).min(MAX_EDITOR_LIMIT));
+        self
+    }
+
+    /// Obscures the fields as they are typed, showing only the last character, for passwords and PINs.
+    /// Can't be combined with `multiline()`.
+    pub fn password(&'a mut self) -> &'a mut Self {
+        self.password = true;
+        self
+    }
+
     pub fn build(&self) -> Result<TextEntryPayloads, xous::Error> {
         self.modals.lock();
         let mut final_placeholders: Option<[Option<(String, bool)>; 10]> = None;
@@ -75,6 +93,16 @@ impl<'a> AlertModalBuilder<'a> {
             self.modals.unlock();
             return Err(xous::Error::UnknownError);
         }
+        if self.multiline.is_some() && fields_amt != 1 {
+            log::error!("a multi-line editor can only edit one field");
+            self.modals.unlock();
+            return Err(xous::Error::UnknownError);
+        }
+        if self.multiline.is_some() && self.password {
+            log::error!("a multi-line editor can't be used for a password");
+            self.modals.unlock();
+            return Err(xous::Error::UnknownError);
+        }
 
         match self.placeholders.len() {
             1.. => {
@@ -110,6 +138,8 @@ impl<'a> AlertModalBuilder<'a> {
                 fields: fields_amt as u32,
                 placeholders: final_placeholders.clone(),
                 growable: self.growable,
+                multiline: self.multiline.map(|max_chars| max_chars as u32),
+                password: self.password,
             };
             let mut buf = Buffer::into_buf(spec).or(Err(xous::Error::InternalError))?;
             buf.lend_mut(self.modals.conn, Opcode::PromptWithTextResponse.to_u32().unwrap())
@@ -196,6 +226,8 @@ impl Modals {
             placeholders: vec![],
             modals: self,
             growable: false,
+            multiline: None,
+            password: false,
         }
     }
 
@@ -867,6 +899,14 @@ impl Modals {
         Ok(())
     }
 
+    /// Switches the language the modals server shows its modals in. This doesn't take the lock, so it
+    /// also applies to a modal that is already up, from its next redraw.
+    pub fn set_language(&self, lang: &str) -> Result<(), xous::Error> {
+        let index = locales::LANGUAGES.iter().position(|l| *l == lang).ok_or(xous::Error::InvalidString)?;
+        send_message(self.conn, Message::new_scalar(Opcode::SetLanguage.to_usize().unwrap(), index, 0, 0, 0))
+            .map(|_| ())
+    }
+
     /// Blocks until we have a lock on the modals server
     fn lock(&self) {
         if !self.have_lock.get() {
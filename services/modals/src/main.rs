@@ -422,7 +422,7 @@ fn wrapped_main() -> ! {
                     #[cfg(feature = "tts")]
                     {
                         if tt.elapsed_ms() - last_tick > TICK_INTERVAL {
-                            tts.tts_blocking(t!("progress.increment", locales::LANG)).unwrap();
+                            tts.tts_blocking(t!("progress.increment", locales::lang())).unwrap();
                             last_tick = tt.elapsed_ms();
                         }
                     }
@@ -457,13 +457,29 @@ fn wrapped_main() -> ! {
                         tts.tts_simple(config.prompt.as_str()).unwrap();
                         log::info!("setting growable to: {:?}", config.growable);
                         renderer_modal.set_growable(config.growable);
-                        renderer_modal.modify(
-                            Some(ActionType::TextEntry({
+                        let action = if let Some(max_chars) = config.multiline {
+                            ActionType::TextEditor(TextEditor::new(
+                                renderer_cid,
+                                Opcode::TextEntryReturn.to_u32().unwrap(),
+                                // the limit comes from the caller, so it can't size our allocation
+                                (max_chars as usize).min(MAX_EDITOR_LIMIT),
+                                config.placeholders.as_ref().and_then(|p| p[0].clone()),
+                                renderer_modal.authtoken,
+                            ))
+                        } else {
+                            ActionType::TextEntry({
                                 let mut ta = text_action.clone();
                                 ta.reset_action_payloads(config.fields, config.placeholders.clone());
+                                if config.password {
+                                    ta.is_password = true;
+                                    ta.visibility = TextEntryVisibility::LastChars;
+                                }
 
                                 ta
-                            })),
+                            })
+                        };
+                        renderer_modal.modify(
+                            Some(action),
                             Some(config.prompt.as_str()),
                             false,
                             None,
@@ -514,7 +530,7 @@ fn wrapped_main() -> ! {
                         let phrase = renderer_modal
                             .gam
                             .bytes_to_bip39(&config.bip39_data[..config.bip39_len as usize].to_vec())
-                            .unwrap_or(vec![t!("bip39.invalid_bytes", locales::LANG).to_string()]);
+                            .unwrap_or(vec![t!("bip39.invalid_bytes", locales::lang()).to_string()]);
                         #[cfg(feature = "hazardous-debug")]
                         log::info!("BIP-39 phrase: {:?}", phrase);
 
@@ -616,7 +632,7 @@ fn wrapped_main() -> ! {
                         fixed_items.clear();
                         #[cfg(feature = "tts")]
                         {
-                            tts.tts_blocking(t!("modals.radiobutton", locales::LANG)).unwrap();
+                            tts.tts_blocking(t!("modals.radiobutton", locales::lang())).unwrap();
                             tts.tts_blocking(config.prompt.as_str()).unwrap();
                         }
                         renderer_modal.modify(
@@ -643,7 +659,7 @@ fn wrapped_main() -> ! {
                         fixed_items.clear();
                         #[cfg(feature = "tts")]
                         {
-                            tts.tts_blocking(t!("modals.checkbox", locales::LANG)).unwrap();
+                            tts.tts_blocking(t!("modals.checkbox", locales::lang())).unwrap();
                             tts.tts_blocking(config.prompt.as_str()).unwrap();
                         }
                         renderer_modal.modify(
@@ -900,6 +916,11 @@ fn wrapped_main() -> ! {
                     }
                 }
             }
+            Some(Opcode::SetLanguage) => msg_scalar_unpack!(msg, index, _, _, _, {
+                if let Some(lang) = locales::LANGUAGES.get(index) {
+                    locales::set_lang(lang);
+                }
+            }),
             Some(Opcode::Gutter) => {
                 log::info!("gutter op, doing nothing");
             }
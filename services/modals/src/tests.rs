@@ -134,6 +134,21 @@ pub fn spawn_test() {
             }
             log::info!("text input test done");
 
+            log::info!("test multi-line editor");
+            match modals
+                .alert_builder("Edit this note. Press select to close.")
+                .field_placeholder_persist(
+                    Some("A note\nthat spans a few lines, long enough to wrap.".to_string()),
+                    None,
+                )
+                .multiline(Some(256))
+                .build()
+            {
+                Ok(text) => log::info!("Note: {}", text.first().content),
+                _ => log::error!("multi-line editor failed"),
+            }
+            log::info!("multi-line editor test done");
+
             // 3. test notificatons
             log::info!("testing notification");
             modals.show_notification("这是一个测验!", Some("这是一个测验!")).expect("notification failed");
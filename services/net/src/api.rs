@@ -361,6 +361,11 @@ pub(crate) enum Opcode {
     StdTcpStreamShutdown = 46,
 
     LoopbackRx = 47,
+
+    /// A frame from the USB network link is waiting (internal)
+    UsbUplinkRx = 48,
+    /// The USB network link went down (internal)
+    UsbUplinkHangup = 49,
     // do not use any numbers higher than 0x8000 as that is reserved for the nonblocking flag
 }
 #[allow(dead_code)]
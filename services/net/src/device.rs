@@ -8,12 +8,15 @@ use com::api::NET_MTU;
 use num_traits::*;
 use smoltcp::phy::{self, ChecksumCapabilities, DeviceCapabilities, Medium};
 use smoltcp::wire::{
-    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol, Ipv4Address,
-    Ipv4Packet, Ipv4Repr, /* IpProtocol, TcpPacket, TcpRepr, IpAddress, UdpPacket, UdpRepr */
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol, IpProtocol,
+    Ipv4Address, Ipv4Packet, Ipv4Repr, UdpPacket, /* TcpPacket, TcpRepr, IpAddress, UdpRepr */
 };
 
+use crate::usb_uplink::UsbUplink;
 use crate::{IPV4_ADDRESS, MAC_ADDRESS_LSB, MAC_ADDRESS_MSB};
 
+const DHCP_CLIENT_PORT: u16 = 68;
+
 pub struct NetPhy {
     rx_buffer: [u8; NET_MTU],
     tx_buffer: [u8; NET_MTU],
@@ -22,10 +25,11 @@ pub struct NetPhy {
     loopback_conn: xous::CID,
     // tracks the length (and count) of the loopback packets pending
     loopback_pending: Arc<Mutex<VecDeque<u16>>>,
+    usb: UsbUplink,
 }
 
 impl<'a> NetPhy {
-    pub fn new(xns: &xous_names::XousNames, loopback_conn: xous::CID) -> NetPhy {
+    pub fn new(xns: &xous_names::XousNames, loopback_conn: xous::CID, usb: UsbUplink) -> NetPhy {
         NetPhy {
             rx_buffer: [0; NET_MTU],
             tx_buffer: [0; NET_MTU],
@@ -33,6 +37,7 @@ impl<'a> NetPhy {
             rx_avail: None,
             loopback_conn,
             loopback_pending: Arc::new(Mutex::new(VecDeque::new())),
+            usb,
         }
     }
 
@@ -70,6 +75,7 @@ impl phy::Device for NetPhy {
                 loopback_conn: self.loopback_conn,
                 loopback_count: self.loopback_pending.clone(),
                 caps: csum_copy,
+                usb: &self.usb,
             }))
         } else {
             if let Some(rx_len) = self.rx_avail.take() {
@@ -84,6 +90,20 @@ impl phy::Device for NetPhy {
                     loopback_conn: self.loopback_conn,
                     loopback_count: self.loopback_pending.clone(),
                     caps: csum_copy,
+                    usb: &self.usb,
+                }))
+            } else if let Some(frame) = self.usb.take_frame() {
+                let rx_len = frame.len().min(NET_MTU);
+                log::debug!("usb rx of {} bytes", rx_len);
+                self.rx_buffer[..rx_len].copy_from_slice(&frame[..rx_len]);
+
+                Some((NetPhyRxToken { buf: &mut self.rx_buffer[..rx_len] }, NetPhyTxToken {
+                    buf: &mut self.tx_buffer[..],
+                    com: &self.com,
+                    loopback_conn: self.loopback_conn,
+                    loopback_count: self.loopback_pending.clone(),
+                    caps: csum_copy,
+                    usb: &self.usb,
                 }))
             } else {
                 log::trace!("nothing to rx");
@@ -101,6 +121,7 @@ impl phy::Device for NetPhy {
             loopback_conn: self.loopback_conn,
             loopback_count: self.loopback_pending.clone(),
             caps: csum_copy,
+            usb: &self.usb,
         })
     }
 
@@ -134,6 +155,7 @@ pub struct NetPhyTxToken<'a> {
     loopback_conn: xous::CID,
     loopback_count: Arc<Mutex<VecDeque<u16>>>,
     caps: ChecksumCapabilities,
+    usb: &'a UsbUplink,
 }
 impl<'a> NetPhyTxToken<'a> {
     /// Initiates the Rx side of things to read out the loopback packet that was queued
@@ -390,9 +412,38 @@ impl<'a> phy::TxToken for NetPhyTxToken<'a> {
             }
         }
         // forward the packet on if it's not a loopback (loopback will call return early and exit before
-        // getting to this line)
-        self.com.wlan_send_packet(&self.buf[..len]).expect("driver error sending WLAN packet");
+        // getting to this line). Stations heard from over USB are reached there, and everything else
+        // unicast through WLAN; broadcasts go out on both links, except for DHCP requests, which are
+        // only for the USB link because the EC does DHCP for WLAN.
+        let frame = &self.buf[..len];
+        match EthernetFrame::new_checked(frame) {
+            Ok(eth_frame) if eth_frame.dst_addr().is_unicast() => {
+                if self.usb.is_peer(&eth_frame.dst_addr()) {
+                    self.usb.send(frame);
+                } else {
+                    self.com.wlan_send_packet(frame).expect("driver error sending WLAN packet");
+                }
+            }
+            Ok(eth_frame) if is_dhcp_request(&eth_frame) => self.usb.send(frame),
+            Ok(_) => {
+                self.usb.send(frame);
+                self.com.wlan_send_packet(frame).expect("driver error sending WLAN packet");
+            }
+            Err(_) => self.com.wlan_send_packet(frame).expect("driver error sending WLAN packet"),
+        }
 
         result
     }
 }
+
+fn is_dhcp_request(eth_frame: &EthernetFrame<&[u8]>) -> bool {
+    if eth_frame.ethertype() != EthernetProtocol::Ipv4 {
+        return false;
+    }
+    match Ipv4Packet::new_checked(eth_frame.payload()) {
+        Ok(packet) if packet.next_header() == IpProtocol::Udp => UdpPacket::new_checked(packet.payload())
+            .map(|udp| udp.src_port() == DHCP_CLIENT_PORT)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
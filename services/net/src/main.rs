@@ -16,6 +16,7 @@ use std_tcplistener::*;
 
 mod connection_manager;
 mod device;
+mod usb_uplink;
 
 #[cfg(test)]
 mod tests;
@@ -33,15 +34,17 @@ use byteorder::{ByteOrder, NetworkEndian};
 use smoltcp::iface::SocketHandle;
 use smoltcp::iface::{Config, Interface, SocketSet};
 use smoltcp::phy::{Device, Tracer};
-use smoltcp::socket::{icmp, tcp, udp};
+use smoltcp::socket::{dhcpv4, icmp, tcp, udp};
 use smoltcp::time::{Duration, Instant};
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr};
 use smoltcp::wire::{Icmpv4Packet, Icmpv4Repr, Icmpv6Packet, Icmpv6Repr};
 use xous::{CID, Message, SID, msg_blocking_scalar_unpack, msg_scalar_unpack, try_send_message};
 use xous_ipc::Buffer;
 
 // 0 indicates no address is currently assigned
 pub static IPV4_ADDRESS: AtomicU32 = AtomicU32::new(0);
+// the address on the USB network link, if it's up; 0 otherwise
+pub static USB_IPV4_ADDRESS: AtomicU32 = AtomicU32::new(0);
 // stash the MAC address for inserstion as a loopback target. Coded as big-end bytes.
 pub static MAC_ADDRESS_LSB: AtomicU32 = AtomicU32::new(0);
 pub static MAC_ADDRESS_MSB: AtomicU16 = AtomicU16::new(0);
@@ -211,7 +214,8 @@ fn main() -> ! {
     };
     config.random_seed = trng.get_u64().unwrap();
 
-    let device = device::NetPhy::new(&xns, net_cid);
+    let usb_uplink = usb_uplink::UsbUplink::new();
+    let device = device::NetPhy::new(&xns, net_cid, usb_uplink.clone());
     let mut device = Tracer::new(device, |_timestamp, _printer| {
         log::trace!("{}", _printer);
     });
@@ -230,6 +234,9 @@ fn main() -> ! {
         let icmp_socket = sockets.get_mut::<icmp::Socket>(icmp_handle);
         icmp_socket.bind(icmp::Endpoint::Ident(PING_IDENT)).expect("couldn't bind to icmp socket");
     }
+    // the address on the USB link is assigned by the host
+    let dhcp_handle = sockets.add(dhcpv4::Socket::new());
+    let mut usb_config: Option<UsbIpv4Conf> = None;
 
     // ------------- libstd variant -----------
     // Each process keeps track of its own sockets. These are kept in a Vec. When a handle
@@ -311,6 +318,16 @@ fn main() -> ! {
         }
     });
 
+    // frames from the USB network link are collected by their own thread
+    #[cfg(not(feature = "renode-minimal"))]
+    thread::spawn({
+        let usb_uplink = usb_uplink.clone();
+        let net_conn = net_conn.clone();
+        move || {
+            usb_uplink.receive_thread(net_conn);
+        }
+    });
+
     let mut cid_to_disconnect: Option<CID> = None;
 
     let (core_tx, core_rx) = channel();
@@ -1109,38 +1126,15 @@ fn main() -> ! {
 
                                     if config.addr != [127, 0, 0, 1] {
                                         // note: ARP cache is stale. Maybe that's ok?
-                                        iface.update_ip_addrs(|ip_addrs| {
-                                            ip_addrs.clear();
-                                            ip_addrs
-                                                .push(IpCidr::new(
-                                                    IpAddress::v4(
-                                                        config.addr[0],
-                                                        config.addr[1],
-                                                        config.addr[2],
-                                                        config.addr[3],
-                                                    ),
-                                                    24,
-                                                ))
-                                                .unwrap();
-                                            // ...and the loopback interface
-                                            ip_addrs
-                                                .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
-                                                .unwrap();
-                                        });
+                                        // this also resets the default route, in case it has changed
+                                        update_ip_config(
+                                            &mut iface,
+                                            net_config.as_ref(),
+                                            usb_config.as_ref(),
+                                        );
                                     } else {
                                         log::warn!("Attempt to update the loopback interface! Ignoring.");
                                     }
-                                    // reset the default route, in case it has changed
-                                    iface.routes_mut().remove_default_ipv4_route();
-                                    iface
-                                        .routes_mut()
-                                        .add_default_ipv4_route(Ipv4Address::new(
-                                            config.gtwy[0],
-                                            config.gtwy[1],
-                                            config.gtwy[2],
-                                            config.gtwy[3],
-                                        ))
-                                        .unwrap();
 
                                     dns_allclear_hook.notify();
                                     dns_ipv4_hook.notify_custom_args([
@@ -1257,11 +1251,64 @@ fn main() -> ! {
                     }
                 }
             }),
+            Some(Opcode::UsbUplinkRx) => msg_scalar_unpack!(msg, _, _, _, _, {
+                match xous::try_send_message(
+                    net_conn,
+                    Message::new_scalar(Opcode::NetPump.to_usize().unwrap(), 0, 0, 0, 0),
+                ) {
+                    Ok(_) => {}
+                    Err(xous::Error::ServerQueueFull) => {
+                        log::warn!("Our net queue runneth over, packets will be dropped.");
+                    }
+                    Err(e) => {
+                        log::error!("Unhandled error sending NetPump to self: {:?}", e);
+                    }
+                }
+            }),
+            Some(Opcode::UsbUplinkHangup) => msg_scalar_unpack!(msg, _, _, _, _, {
+                // start over with DHCP when the link comes back, which may well be to another host
+                sockets.get_mut::<dhcpv4::Socket>(dhcp_handle).reset();
+                if usb_config.take().is_some() {
+                    log::info!("USB network config dropped");
+                    update_ip_config(&mut iface, net_config.as_ref(), None);
+                    if net_config.is_none() {
+                        dns_allclear_hook.notify();
+                    }
+                }
+            }),
             Some(Opcode::NetPump) => msg_scalar_unpack!(msg, _, _, _, _, {
                 log::trace!("NetPump");
                 let now = timer.elapsed_ms();
                 let timestamp = Instant::from_millis(now as i64);
-                if !iface.poll(timestamp, &mut device, &mut sockets) {
+                let readiness_changed = iface.poll(timestamp, &mut device, &mut sockets);
+                // the USB link is configured by DHCP, and the results come out of the poll
+                match sockets.get_mut::<dhcpv4::Socket>(dhcp_handle).poll() {
+                    Some(dhcpv4::Event::Configured(config)) => {
+                        log::info!("USB network config acquired: {:?}", config);
+                        usb_config = Some(UsbIpv4Conf { address: config.address, router: config.router });
+                        update_ip_config(&mut iface, net_config.as_ref(), usb_config.as_ref());
+                        // WLAN's name servers take precedence, as its default route does
+                        if net_config.is_none() {
+                            dns_allclear_hook.notify();
+                            for dns in config.dns_servers.iter() {
+                                dns_ipv4_hook.notify_custom_args([
+                                    Some(u32::from_be_bytes(dns.0)),
+                                    None,
+                                    None,
+                                    None,
+                                ]);
+                            }
+                        }
+                    }
+                    Some(dhcpv4::Event::Deconfigured) => {
+                        if usb_config.take().is_some() {
+                            log::info!("USB network config lost");
+                            update_ip_config(&mut iface, net_config.as_ref(), None);
+                        }
+                    }
+                    None => {}
+                }
+                if !readiness_changed {
                     // nothing to do, continue on.
                     log::debug!("No change to socket readiness");
                     continue;
@@ -1939,6 +1986,10 @@ fn main() -> ! {
                 // note: ARP cache isn't reset
                 iface.routes_mut().remove_default_ipv4_route();
                 dns_allclear_hook.notify();
+                // the USB link doesn't go through the EC, so it carries on
+                if let Some(router) = usb_config.and_then(|c| c.router) {
+                    iface.routes_mut().add_default_ipv4_route(router).unwrap();
+                }
 
                 match try_send_message(
                     cm_cid,
@@ -2004,3 +2055,44 @@ fn main() -> ! {
     log::trace!("quitting");
     xous::terminate_process(0)
 }
+
+/// The address the host's DHCP server gave us on the USB network link.
+#[derive(Debug, Copy, Clone)]
+struct UsbIpv4Conf {
+    address: Ipv4Cidr,
+    router: Option<Ipv4Address>,
+}
+
+/// Sets the interface addresses and the default route from the configurations of the links that are
+/// up. WLAN comes first, so that it's preferred as the source address and for the default route
+/// when both links are up: the USB link is typically a host sharing its own connection.
+fn update_ip_config(iface: &mut Interface, wlan: Option<&Ipv4Conf>, usb: Option<&UsbIpv4Conf>) {
+    iface.update_ip_addrs(|ip_addrs| {
+        ip_addrs.clear();
+        if let Some(config) = wlan {
+            ip_addrs
+                .push(IpCidr::new(
+                    IpAddress::v4(config.addr[0], config.addr[1], config.addr[2], config.addr[3]),
+                    24,
+                ))
+                .unwrap();
+        }
+        if let Some(config) = usb {
+            ip_addrs.push(IpCidr::Ipv4(config.address)).unwrap();
+        }
+        // ...and the loopback interface
+        ip_addrs.push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8)).unwrap();
+    });
+    USB_IPV4_ADDRESS
+        .store(usb.map(|c| u32::from_be_bytes(c.address.address().0)).unwrap_or(0), Ordering::SeqCst);
+
+    iface.routes_mut().remove_default_ipv4_route();
+    let gateway = match (wlan, usb) {
+        (Some(config), _) => Some(Ipv4Address::from_bytes(&config.gtwy)),
+        (None, Some(config)) => config.router,
+        (None, None) => None,
+    };
+    if let Some(gateway) = gateway {
+        iface.routes_mut().add_default_ipv4_route(gateway).unwrap();
+    }
+}
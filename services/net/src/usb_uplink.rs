@@ -0,0 +1,103 @@
+//! The USB network link. When `usb-device-xous` presents its CDC-NCM core, the host gets an
+//! Ethernet interface to us, which is joined to the same smoltcp interface as WLAN: frames from the
+//! host are queued here, and `NetPhy` sends a frame to the host when its destination is a station
+//! that was last heard from over USB. The address on the USB link comes from the host's DHCP
+//! server, since unlike WLAN there is no EC to run DHCP for us.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use num_traits::*;
+use smoltcp::wire::EthernetAddress;
+use usb_device_xous::UsbHid;
+use xous::{CID, Message};
+
+use crate::api::Opcode;
+
+/// Frames from the host that smoltcp hasn't picked up yet. Beyond this, new frames are dropped.
+const MAX_PENDING_FRAMES: usize = 32;
+
+#[derive(Clone)]
+pub struct UsbUplink {
+    rx: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    /// Shared with the receive thread, which makes the connection once the USB server is up.
+    usb: Arc<Mutex<Option<UsbHid>>>,
+    /// Stations we have heard from on the USB link; everything else is reached through WLAN.
+    peers: Arc<Mutex<HashSet<EthernetAddress>>>,
+}
+
+impl UsbUplink {
+    pub fn new() -> Self {
+        UsbUplink {
+            rx: Arc::new(Mutex::new(VecDeque::new())),
+            usb: Arc::new(Mutex::new(None)),
+            peers: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Waits for frames from the host, forever. This claims the network interface of the USB
+    /// server for this process, so it has to be running before anyone else tries to.
+    pub fn receive_thread(&self, net_conn: CID) {
+        // this blocks until the USB server is up, which is why it isn't done in `new()`. Sends get
+        // their own connection, so they don't queue up behind the blocking wait below.
+        let usb = UsbHid::new();
+        *self.usb.lock().unwrap() = Some(UsbHid::new());
+        loop {
+            let op = match usb.ncm_wait_frame() {
+                Ok(frame) => {
+                    let mut rx = self.rx.lock().unwrap();
+                    if rx.len() >= MAX_PENDING_FRAMES {
+                        log::warn!("USB uplink rx queue is full, dropping a frame");
+                        continue;
+                    }
+                    rx.push_back(frame);
+                    Opcode::UsbUplinkRx
+                }
+                Err(xous::Error::ProcessTerminated) => {
+                    log::info!("USB uplink is down");
+                    self.peers.lock().unwrap().clear();
+                    self.rx.lock().unwrap().clear();
+                    Opcode::UsbUplinkHangup
+                }
+                Err(e) => {
+                    log::error!("USB uplink disabled, couldn't wait for frames: {:?}", e);
+                    break;
+                }
+            };
+            match xous::try_send_message(net_conn, Message::new_scalar(op.to_usize().unwrap(), 0, 0, 0, 0)) {
+                Ok(_) => {}
+                Err(xous::Error::ServerQueueFull) => {
+                    log::warn!("Our net queue runneth over, packets will be dropped.");
+                }
+                Err(e) => {
+                    log::error!("Unhandled error sending {:?} to self: {:?}", op, e);
+                }
+            }
+        }
+    }
+
+    /// The oldest frame from the host, if any. Its sender is remembered as reachable over USB.
+    pub fn take_frame(&self) -> Option<Vec<u8>> {
+        let frame = self.rx.lock().unwrap().pop_front()?;
+        if frame.len() >= 12 {
+            let src = EthernetAddress::from_bytes(&frame[6..12]);
+            if src.is_unicast() {
+                self.peers.lock().unwrap().insert(src);
+            }
+        }
+        Some(frame)
+    }
+
+    pub fn is_peer(&self, addr: &EthernetAddress) -> bool { self.peers.lock().unwrap().contains(addr) }
+
+    /// Sends a frame to the host. Frames are silently dropped while the link is down.
+    pub fn send(&self, frame: &[u8]) {
+        if let Some(usb) = self.usb.lock().unwrap().as_ref() {
+            match usb.ncm_send_frame(frame) {
+                Ok(_) | Err(xous::Error::ProcessTerminated) => {}
+                Err(xous::Error::ServerQueueFull) => log::debug!("USB uplink is backed up, dropped a frame"),
+                Err(e) => log::warn!("couldn't send a frame on the USB uplink: {:?}", e),
+            }
+        }
+    }
+}
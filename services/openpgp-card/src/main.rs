@@ -0,0 +1,136 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+use locales::t;
+use openpgp_applet::{OpenPgpCard, PinEntry, PinPadOp, PinPrompt, PinRef, Store, StoreError};
+use usb_device_xous::{CcidRequest, UsbHid};
+
+/// Everything the card knows lives in this dictionary, in whichever bases are open.
+const OPENPGP_DICT: &str = "openpgp.card";
+/// The card serial number, generated the first time the card is used.
+const SERIAL_KEY: &str = "serial";
+
+/// Offset of P2 within the APDU template of a PIN verify or modify request.
+const TEMPLATE_P2: usize = 3;
+
+struct PddbStore {
+    pddb: pddb::Pddb,
+}
+
+impl Store for PddbStore {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        let mut key = self.pddb.get(OPENPGP_DICT, name, None, false, false, None, None::<fn()>).ok()?;
+        let mut value = Vec::new();
+        key.read_to_end(&mut value).ok()?;
+        Some(value)
+    }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Result<(), StoreError> {
+        // replace rather than overwrite, so a shorter value doesn't leave the tail of a longer one
+        self.pddb.delete_key(OPENPGP_DICT, name, None).ok();
+        let mut key = self
+            .pddb
+            .get(OPENPGP_DICT, name, None, true, true, Some(value.len()), None::<fn()>)
+            .or(Err(StoreError))?;
+        key.write_all(value).or(Err(StoreError))?;
+        self.pddb.sync().or(Err(StoreError))
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), StoreError> {
+        match self.pddb.delete_key(OPENPGP_DICT, name, None) {
+            Ok(_) => self.pddb.sync().or(Err(StoreError)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(StoreError),
+        }
+    }
+}
+
+/// Asks for PINs with a modal, so they are typed on the device's own keyboard.
+struct ModalPinEntry {
+    modals: modals::Modals,
+}
+
+impl PinEntry for ModalPinEntry {
+    fn request(&mut self, prompt: PinPrompt) -> Option<String> {
+        let text = match prompt {
+            PinPrompt::Current(PinRef::Pw3) => t!("openpgp.pin_admin", locales::LANG),
+            PinPrompt::Current(_) => t!("openpgp.pin_user", locales::LANG),
+            PinPrompt::New(PinRef::Pw3) => t!("openpgp.new_pin_admin", locales::LANG),
+            PinPrompt::New(_) => t!("openpgp.new_pin_user", locales::LANG),
+            PinPrompt::Confirm(_) => t!("openpgp.confirm_pin", locales::LANG),
+        };
+        let mut payload = self.modals.alert_builder(text).field(None, None).password().build().ok()?.first();
+        let pin = payload.as_str().to_string();
+        payload.volatile_clear();
+        // the alert modal can't be dismissed, so an empty entry is how the user backs out
+        if pin.is_empty() { None } else { Some(pin) }
+    }
+}
+
+fn card_serial(store: &mut PddbStore, trng: &trng::Trng) -> [u8; 4] {
+    if let Some(serial) = store.get(SERIAL_KEY).and_then(|s| s.try_into().ok()) {
+        return serial;
+    }
+    // 0 and 0xFFFF_FFFF are reserved by the specification
+    let mut serial = 0;
+    while serial == 0 || serial == u32::MAX {
+        serial = trng.get_u32().expect("couldn't get random number");
+    }
+    let serial = serial.to_be_bytes();
+    store.set(SERIAL_KEY, &serial).expect("couldn't store card serial number");
+    serial
+}
+
+fn main() -> ! {
+    log_server::init_wait().unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+    log::info!("my PID is {}", xous::process::id());
+
+    let xns = xous_names::XousNames::new().unwrap();
+    let modals = modals::Modals::new(&xns).unwrap();
+    let trng = trng::Trng::new(&xns).unwrap();
+    let usb = UsbHid::new();
+
+    let mut store = PddbStore { pddb: pddb::Pddb::new() };
+    store.pddb.is_mounted_blocking();
+    let serial = card_serial(&mut store, &trng);
+    let mut card = OpenPgpCard::new(store, trng, serial);
+    let mut pin_entry = ModalPinEntry { modals };
+    log::info!("OpenPGP card {:02x?} ready", card.aid());
+
+    loop {
+        let response = match usb.ccid_wait_incoming() {
+            Ok(CcidRequest::Apdu(apdu)) => card.process(&apdu),
+            Ok(CcidRequest::PinVerify(template)) if template.len() > TEMPLATE_P2 => {
+                card.pinpad(PinPadOp::Verify, template[TEMPLATE_P2], &mut pin_entry)
+            }
+            Ok(CcidRequest::PinModify(template)) if template.len() > TEMPLATE_P2 => {
+                card.pinpad(PinPadOp::Modify, template[TEMPLATE_P2], &mut pin_entry)
+            }
+            Ok(CcidRequest::PinVerify(_)) | Ok(CcidRequest::PinModify(_)) => {
+                openpgp_applet::apdu::response(vec![], openpgp_applet::apdu::Status::WRONG_LENGTH)
+            }
+            Ok(CcidRequest::Reset) | Err(xous::Error::ProcessTerminated) => {
+                // a power cycle or a disconnect forgets every verified PIN
+                card.reset();
+                continue;
+            }
+            Err(xous::Error::AccessDenied) => {
+                log::error!("another process already owns the CCID interface, quitting");
+                break;
+            }
+            Err(e) => {
+                log::error!("error waiting for a CCID request: {:?}", e);
+                continue;
+            }
+        };
+        if let Err(e) = usb.ccid_send(&response) {
+            log::warn!("couldn't send CCID response: {:?}", e);
+        }
+    }
+
+    xous::terminate_process(0)
+}
@@ -9,6 +9,8 @@ pub(crate) const SERVER_NAME_KEYS: &str = "_Root key server and update manager_"
 pub(crate) const SIG_LOADER_VERSION: u32 = 1; // standard ed25519 signature
 #[allow(dead_code)]
 pub(crate) const SIG_KERNEL_VERSION: u32 = 2; // ed25519ph signature
+#[allow(dead_code)]
+pub(crate) const SIG_KERNEL_SECVER_VERSION: u32 = 3; // ed25519ph signature + anti-rollback security version
 
 #[allow(dead_code)]
 #[derive(num_derive::FromPrimitive, num_derive::ToPrimitive, Debug)]
@@ -116,6 +118,12 @@ pub(crate) enum Opcode {
     EfuseRun = 49,
     #[cfg(feature = "efuse")]
     EfusePasswordReturn = 50,
+
+    /// Returns the security version of the booted kernel, and the anti-rollback counter
+    AntirollbackStatus = 51,
+    /// Asks the user to confirm, then advances the anti-rollback counter to the booted kernel's security
+    /// version
+    UxAdvanceAntirollback = 52,
 }
 
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, PartialEq, Eq)]
@@ -318,6 +326,9 @@ impl From<KeyMap> for BackupKeyboardLayout {
             KeyMap::Dvorak => BackupKeyboardLayout::Dvorak,
             KeyMap::Qwertz => BackupKeyboardLayout::Qwertz,
             KeyMap::Braille => BackupKeyboardLayout::Braille,
+            // the backup password is typed before the PDDB that keeps a custom layout is restored, so
+            // backups record the built-in mapping (`Keyboard::get_base_keymap()`) instead
+            KeyMap::Custom => BackupKeyboardLayout::Qwerty,
             KeyMap::Undefined => BackupKeyboardLayout::Qwerty,
         }
     }
@@ -44,6 +44,10 @@ const MAX_ROLLBACK_LIMIT: u8 = 255;
 /// Size of the total area allocated for signatures. It is equal to the size of one FLASH sector, which is the
 /// smallest increment that can be erased.
 const SIGBLOCK_SIZE: u32 = 0x1000;
+/// Words of the anti-rollback page that hold the counter, as a thermometer code: every cleared bit counts
+/// as one security version. This must match `ANTIROLLBACK_WORDS` in the loader.
+const ANTIROLLBACK_WORDS: usize = 32;
+const MAX_SECURITY_VERSION: u32 = ANTIROLLBACK_WORDS as u32 * 32;
 /// location of the csr.csv that's appended on gateware images, used for USB updates.
 const METADATA_OFFSET: usize = 0x27_6000;
 #[allow(dead_code)]
@@ -157,6 +161,11 @@ pub(crate) struct RootKeys {
     kernel_end_mr: xous::MemoryRange,
     kernel_end_base_offset: u32,
     kernel_base: u32,
+    /// Window to the anti-rollback counter page
+    antirollback_mr: xous::MemoryRange,
+    /// Security version of the kernel that was booted. Read once on boot, because the kernel in FLASH can
+    /// be replaced by a staged update before the next reboot.
+    booted_security_version: u32,
     /// regions of RAM that holds all plaintext passwords, keys, and temp data. stuck in two well-defined
     /// page so we can zero-ize it upon demand, without guessing about stack frames and/or Rust
     /// optimizers removing writes
@@ -259,6 +268,14 @@ impl<'a> RootKeys {
         )
         .expect("couldn't map in the kernel end region");
 
+        let antirollback = xous::syscall::map_memory(
+            Some(NonZeroUsize::new((spinor::ANTIROLLBACK_LOC + xous::FLASH_PHYS_BASE) as usize).unwrap()),
+            None,
+            spinor::ANTIROLLBACK_LEN as usize,
+            xous::MemoryFlags::R,
+        )
+        .expect("couldn't map in the anti-rollback counter");
+
         let mut sensitive_data =
             xous::syscall::map_memory(None, None, 0x1000, xous::MemoryFlags::R | xous::MemoryFlags::W)
                 .expect("couldn't map sensitive data page");
@@ -286,7 +303,7 @@ impl<'a> RootKeys {
             k.clone_from_slice(&trng.get_u64().unwrap().to_be_bytes());
         }
 
-        let keys = RootKeys {
+        let mut keys = RootKeys {
             keyrom: CSR::new(keyrom.as_mut_ptr() as *mut u32),
             gateware_mr: gateware,
             gateware_base: xous::SOC_MAIN_GW_LOC,
@@ -299,6 +316,8 @@ impl<'a> RootKeys {
             kernel_end_mr,
             kernel_end_base_offset: kernel_end_base_offset as u32,
             kernel_base: xous::KERNEL_LOC,
+            antirollback_mr: antirollback,
+            booted_security_version: 0,
             sensitive_data: RefCell::new(sensitive_data),
             pass_cache,
             update_password_policy: PasswordRetentionPolicy::AlwaysPurge,
@@ -322,6 +341,12 @@ impl<'a> RootKeys {
             keys.keyrom.wfo(utra::keyrom::ADDRESS_ADDRESS, i);
             log::info!("this.data[0x{:x}] = 0x{:x};", i, keys.keyrom.rf(utra::keyrom::DATA_DATA));
         } */
+        keys.booted_security_version = keys.kernel_security_version();
+        log::info!(
+            "kernel security version {}, anti-rollback counter {}",
+            keys.booted_security_version,
+            keys.antirollback_counter()
+        );
 
         keys
     }
@@ -370,6 +395,77 @@ impl<'a> RootKeys {
 
     pub fn kernel_end_base(&self) -> u32 { self.kernel_base + self.kernel_end_base_offset }
 
+    pub fn antirollback(&self) -> &[u8] { unsafe { self.antirollback_mr.as_slice::<u8>() } }
+
+    /// Reads a word out of the signed trailer at the end of the kernel image. `offset` counts back from the
+    /// end of the signed region.
+    fn kernel_trailer_word(&self, offset: usize) -> u32 {
+        let kernel_end_region = self.kernel_end();
+        let end_mask = kernel_end_region.len() - 1;
+        let sig_region = &self.kernel_sig()[..core::mem::size_of::<SignatureInFlash>()];
+        let sig_rec: &SignatureInFlash =
+            unsafe { (sig_region.as_ptr() as *const SignatureInFlash).as_ref().unwrap() };
+        let end = SIGBLOCK_SIZE as usize + sig_rec.signed_len as usize;
+        let mut word = [0u8; 4];
+        for (i, b) in word.iter_mut().enumerate() {
+            *b = kernel_end_region[(end - offset + i) & end_mask];
+        }
+        u32::from_le_bytes(word)
+    }
+
+    /// The signature record version of the kernel in FLASH. Re-signing must preserve it, because the v3
+    /// records carry a security version in the signed trailer.
+    fn kernel_sig_version(&self) -> u32 {
+        if self.kernel_trailer_word(8) == SIG_KERNEL_SECVER_VERSION {
+            SIG_KERNEL_SECVER_VERSION
+        } else {
+            SIG_KERNEL_VERSION
+        }
+    }
+
+    /// Security version of the kernel in FLASH. Images signed before anti-rollback existed are version 0.
+    pub fn kernel_security_version(&self) -> u32 {
+        if self.kernel_sig_version() == SIG_KERNEL_SECVER_VERSION {
+            // trailer is: security version, min semver, semver, version, length
+            self.kernel_trailer_word(8 + 16 + 16 + 4)
+        } else {
+            0
+        }
+    }
+
+    pub fn booted_security_version(&self) -> u32 { self.booted_security_version }
+
+    /// The lowest security version the loader will boot
+    pub fn antirollback_counter(&self) -> u32 {
+        let counter = unsafe { self.antirollback_mr.as_slice::<u32>() };
+        counter[..ANTIROLLBACK_WORDS].iter().map(|w| w.count_zeros()).sum()
+    }
+
+    /// Advances the anti-rollback counter to the security version of the booted kernel. The counter
+    /// never moves backwards, and never past the booted kernel, so a successfully updated system can't lock
+    /// itself out. Returns the new value of the counter.
+    pub fn advance_antirollback(&mut self) -> Result<u32, RootkeyResult> {
+        let current = self.antirollback_counter();
+        let target = self.booted_security_version.min(MAX_SECURITY_VERSION);
+        if target <= current {
+            return Ok(current);
+        }
+        let mut counter = [0xFFu8; ANTIROLLBACK_WORDS * 4];
+        for version in 0..target as usize {
+            counter[version / 8] &= !(1 << (version % 8));
+        }
+        self.spinor
+            .patch(self.antirollback(), spinor::ANTIROLLBACK_LOC, &counter, 0)
+            .map_err(|_| RootkeyResult::FlashError)?;
+        let updated = self.antirollback_counter();
+        if updated != target {
+            log::error!("anti-rollback counter readback mismatch: wrote {}, read {}", target, updated);
+            return Err(RootkeyResult::FlashError);
+        }
+        log::info!("anti-rollback counter advanced from {} to {}", current, updated);
+        Ok(updated)
+    }
+
     /// takes a root key and computes the current rollback state of the key by hashing it
     /// MAX_ROLLBACK_LIMIT - GLOBAL_ROLLBACK times.
     fn compute_key_rollback(&mut self, key: &mut [u8]) {
@@ -2826,7 +2922,7 @@ impl<'a> RootKeys {
 
         // force the records to match our measured values
         let mut len_data = [0u8; 8];
-        for (&src, dst) in SIG_KERNEL_VERSION.to_le_bytes().iter().zip(len_data[..4].iter_mut()) {
+        for (&src, dst) in self.kernel_sig_version().to_le_bytes().iter().zip(len_data[..4].iter_mut()) {
             *dst = src;
         }
         for (&src, dst) in (kernel_len as u32 - 4).to_le_bytes().iter().zip(len_data[4..].iter_mut()) {
@@ -3039,7 +3135,7 @@ impl<'a> RootKeys {
                 log::info!("loader sig area after: {:x?}", &(self.loader_code()[..0x80]));
             }
             SignatureType::Kernel => {
-                signature.version = SIG_KERNEL_VERSION;
+                signature.version = self.kernel_sig_version();
                 log::info!("kernel sig area before: {:x?}", &(self.kernel_sig()[..0x80]));
                 self.spinor
                     .patch(self.kernel_sig(), self.kernel_sig_base(), &sig_region, 0)
@@ -112,6 +112,34 @@ impl RootKeys {
         }
     }
 
+    /// Returns the security version of the booted kernel, and the anti-rollback counter, which is the lowest
+    /// security version the loader will boot.
+    pub fn antirollback_status(&self) -> Result<(u32, u32), xous::Error> {
+        match send_message(
+            self.conn,
+            Message::new_blocking_scalar(Opcode::AntirollbackStatus.to_usize().unwrap(), 0, 0, 0, 0),
+        ) {
+            Ok(xous::Result::Scalar2(booted, counter)) => Ok((booted as u32, counter as u32)),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
+    /// Advances the anti-rollback counter to the security version of the booted kernel, after asking the
+    /// user to confirm. Call this once an update has booted and proven itself: from then on, images with a
+    /// lower security version will not boot. Returns the new counter, or `None` if the user declined or the
+    /// counter could not be written.
+    pub fn advance_antirollback(&self) -> Result<Option<u32>, xous::Error> {
+        match send_message(
+            self.conn,
+            Message::new_blocking_scalar(Opcode::UxAdvanceAntirollback.to_usize().unwrap(), 0, 0, 0, 0),
+        ) {
+            Ok(xous::Result::Scalar2(ok, counter)) => {
+                if ok != 0 { Ok(Some(counter as u32)) } else { Ok(None) }
+            }
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
     pub fn do_update_gw_ux_flow(&self) {
         send_message(
             self.conn,
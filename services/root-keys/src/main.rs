@@ -567,6 +567,12 @@ mod implementation {
         pub fn set_dont_ask_init(&self) {}
 
         pub fn reset_dont_ask_init(&mut self) {}
+
+        pub fn booted_security_version(&self) -> u32 { 0 }
+
+        pub fn antirollback_counter(&self) -> u32 { 0 }
+
+        pub fn advance_antirollback(&mut self) -> Result<u32, RootkeyResult> { Ok(0) }
     }
 }
 
@@ -2227,6 +2233,54 @@ fn main() -> ! {
                 keys.reset_dont_ask_init();
                 xous::return_scalar(msg.sender, 1).ok();
             }),
+            Some(Opcode::AntirollbackStatus) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
+                xous::return_scalar2(
+                    msg.sender,
+                    keys.booted_security_version() as usize,
+                    keys.antirollback_counter() as usize,
+                )
+                .ok();
+            }),
+            Some(Opcode::UxAdvanceAntirollback) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
+                let target = keys.booted_security_version();
+                if target <= keys.antirollback_counter() {
+                    // nothing to do, don't bother the user
+                    xous::return_scalar2(msg.sender, 1, keys.antirollback_counter() as usize).ok();
+                    continue;
+                }
+                modals.add_list_item(t!("rootkeys.gwup.yes", locales::LANG)).expect("modals error");
+                modals.add_list_item(t!("rootkeys.gwup.no", locales::LANG)).expect("modals error");
+                let confirmed = match modals.get_radiobutton(
+                    &t!("rootkeys.antirollback.confirm", locales::LANG)
+                        .replace("{version}", &target.to_string()),
+                ) {
+                    Ok(response) => response == t!("rootkeys.gwup.yes", locales::LANG),
+                    _ => {
+                        log::error!("modals error, aborting");
+                        false
+                    }
+                };
+                if !confirmed {
+                    xous::return_scalar2(msg.sender, 0, keys.antirollback_counter() as usize).ok();
+                    continue;
+                }
+                match keys.advance_antirollback() {
+                    Ok(counter) => {
+                        modals
+                            .show_notification(
+                                &t!("rootkeys.antirollback.finished", locales::LANG)
+                                    .replace("{version}", &counter.to_string()),
+                                None,
+                            )
+                            .expect("modals error");
+                        xous::return_scalar2(msg.sender, 1, counter as usize).ok();
+                    }
+                    Err(e) => {
+                        log::error!("couldn't advance the anti-rollback counter: {:?}", e);
+                        xous::return_scalar2(msg.sender, 0, keys.antirollback_counter() as usize).ok();
+                    }
+                }
+            }),
             #[cfg(feature = "efuse")]
             Some(Opcode::BurnEfuse) => {
                 // Flow:
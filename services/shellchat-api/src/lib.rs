@@ -0,0 +1,143 @@
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Lets any process add a verb to shellchat, so apps loaded after boot can expose their own diagnostics.
+//!
+//! The process registers the verb along with a server of its own. When the verb is typed, shellchat
+//! sends an `Invocation` to that server, and the process answers with any number of `output()` calls
+//! followed by `finish()`; each piece of output shows up in the chat as it arrives. Because output
+//! arrives after the command line has finished running, it can't be piped into another command.
+//!
+//! A verb goes away with `unregister()`, or once the process that registered it has exited.
+//!
+//! ```ignore
+//! let sid = xous::create_server().unwrap();
+//! let shell = shellchat_api::ShellVerbs::new(&xns).unwrap();
+//! shell.register("mydiag", "mydiag [status] [reset]", sid, MyOpcode::Shell as usize).unwrap();
+//! loop {
+//!     let msg = xous::receive_message(sid).unwrap();
+//!     if msg.body.id() == MyOpcode::Shell as usize {
+//!         let invocation = shellchat_api::Invocation::from_message(&msg).unwrap();
+//!         shell.output(invocation.id, &format!("got {:?}", invocation.args)).ok();
+//!         shell.finish(invocation.id).ok();
+//!     }
+//! }
+//! ```
+
+use num_traits::ToPrimitive;
+use xous::{CID, MessageEnvelope, SID};
+use xous_ipc::Buffer;
+
+pub const SERVER_NAME_SHELLCHAT: &str = "_Shell chat application_"; // used internally by xous-names
+
+#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
+pub enum Opcode {
+    /// a line of text has arrived
+    Line = 0, // make sure we occupy opcodes with discriminants < 1000, as the rest are used for callbacks
+    /// redraw our UI
+    Redraw,
+    /// change focus
+    ChangeFocus,
+    /// exit the application
+    Quit,
+    /// add a verb implemented by another process (VerbRegistration, lend_mut)
+    RegisterVerb,
+    /// remove a verb registered by the calling process (String, lend)
+    UnregisterVerb,
+    /// output from a verb implemented by another process (VerbOutput, send)
+    VerbOutput,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct VerbRegistration {
+    pub verb: String,
+    /// how to use the verb, shown in the list of commands
+    pub help: String,
+    /// the server `Invocation`s are sent to. Note that this discloses the SID to shellchat.
+    pub listener: [u32; 4],
+    /// the message ID of the `Invocation`s
+    pub invoke_id: u32,
+    /// set by shellchat when the verb is registered
+    pub accepted: bool,
+}
+
+/// Sent to the listener when its verb is typed
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Invocation {
+    /// identifies this invocation in `output()` and `finish()`
+    pub id: u32,
+    pub verb: String,
+    /// the words after the verb, one per argument
+    pub args: Vec<String>,
+}
+impl Invocation {
+    /// Reads an invocation out of a message sent to the listener with the registered `invoke_id`
+    pub fn from_message(msg: &MessageEnvelope) -> Option<Invocation> {
+        let mem = msg.body.memory_message()?;
+        let buffer = unsafe { Buffer::from_memory_message(mem) };
+        buffer.to_original::<Invocation, _>().ok()
+    }
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct VerbOutput {
+    pub id: u32,
+    pub text: String,
+    /// no more output follows for this invocation
+    pub done: bool,
+}
+
+pub struct ShellVerbs {
+    conn: CID,
+}
+impl ShellVerbs {
+    pub fn new(xns: &xous_names::XousNames) -> Result<Self, xous::Error> {
+        let conn = xns.request_connection_blocking(SERVER_NAME_SHELLCHAT)?;
+        Ok(ShellVerbs { conn })
+    }
+
+    /// Adds `verb` to shellchat. Fails with `ServerExists` if the verb is taken, either by a command built
+    /// into shellchat or by another process.
+    pub fn register(
+        &self,
+        verb: &str,
+        help: &str,
+        listener: SID,
+        invoke_id: usize,
+    ) -> Result<(), xous::Error> {
+        if verb.is_empty() || verb.contains(char::is_whitespace) {
+            return Err(xous::Error::InvalidString);
+        }
+        let registration = VerbRegistration {
+            verb: verb.to_string(),
+            help: help.to_string(),
+            listener: listener.to_array(),
+            invoke_id: invoke_id as u32,
+            accepted: false,
+        };
+        let mut buf = Buffer::into_buf(registration).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::RegisterVerb.to_u32().unwrap())?;
+        let registration = buf.to_original::<VerbRegistration, _>()?;
+        if registration.accepted { Ok(()) } else { Err(xous::Error::ServerExists) }
+    }
+
+    /// Removes a verb registered by this process
+    pub fn unregister(&self, verb: &str) -> Result<(), xous::Error> {
+        let buf = Buffer::into_buf(String::from(verb)).or(Err(xous::Error::InternalError))?;
+        buf.lend(self.conn, Opcode::UnregisterVerb.to_u32().unwrap()).map(|_| ())
+    }
+
+    /// Shows `text` in the chat, as output of the invocation `id`
+    pub fn output(&self, id: u32, text: &str) -> Result<(), xous::Error> {
+        self.send(VerbOutput { id, text: text.to_string(), done: false })
+    }
+
+    /// Ends the invocation `id`; any further output for it is dropped
+    pub fn finish(&self, id: u32) -> Result<(), xous::Error> {
+        self.send(VerbOutput { id, text: String::new(), done: true })
+    }
+
+    fn send(&self, output: VerbOutput) -> Result<(), xous::Error> {
+        let buf = Buffer::into_buf(output).or(Err(xous::Error::InternalError))?;
+        buf.send(self.conn, Opcode::VerbOutput.to_u32().unwrap()).map(|_| ())
+    }
+}
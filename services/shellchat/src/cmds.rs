@@ -2,14 +2,18 @@ use core::fmt::Write;
 use std::collections::HashMap;
 
 use String;
+use shellchat_api::{VerbOutput, VerbRegistration};
 #[cfg(feature = "shellperf")]
 use utralib::generated::*;
 use xous::MessageEnvelope;
+
+use crate::external::ExternalVerbs;
+use crate::script;
 /////////////////////////// Common items to all commands
 pub trait ShellCmdApi<'a> {
     // user implemented:
-    // called to process the command with the remainder of the string attached
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error>;
+    // called to process the command with its arguments, one per word
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error>;
     // called to process incoming messages that may have been origniated by the most recently issued command
     fn callback(
         &mut self,
@@ -75,7 +79,7 @@ impl CommonEnv {
         1. mod/use the new command
         2. create an entry for the command's storage in the CmdEnv structure
         3. initialize the persistant storage here
-        4. add it to the "commands" array in the dispatch() routine below
+        4. add it to the "commands" array in the commands! macro below
 
     Side note: if your command doesn't require persistent storage, you could,
     technically, generate the command dynamically every time it's called. Echo
@@ -125,6 +129,17 @@ mod pddb_cmd;
 use pddb_cmd::*;
 mod usb;
 use usb::*;
+mod crashdump;
+use crashdump::*;
+mod logs;
+use logs::*;
+mod loglevel;
+use loglevel::*;
+mod script_cmd;
+use script_cmd::*;
+pub use script_cmd::{BOOT_SCRIPT, read_script};
+mod clip;
+use clip::*;
 
 #[cfg(not(feature = "no-codec"))]
 mod test;
@@ -154,6 +169,9 @@ use aes_cmd::*;
 pub struct CmdEnv {
     common_env: CommonEnv,
     lastverb: String,
+    script: script::Engine,
+    pddb: pddb::Pddb,
+    external: ExternalVerbs,
     ///// 2. declare storage for your command here.
     sleep_cmd: Sleep,
     sensors_cmd: Sensors,
@@ -172,6 +190,11 @@ pub struct CmdEnv {
     pddb_cmd: PddbCmd,
     wlan_cmd: Wlan,
     usb_cmd: Usb,
+    crashdump_cmd: CrashDumpCmd,
+    logs_cmd: Logs,
+    loglevel_cmd: LogLevelCmd,
+    script_cmd: ScriptCmd,
+    clip_cmd: Clip,
 
     #[cfg(not(feature = "no-codec"))]
     test_cmd: Test,
@@ -187,8 +210,62 @@ pub struct CmdEnv {
     engine_cmd: Engine,
     //fcc_cmd: Fcc,
 }
+
+/// Binds `$commands` to all of the commands. This is a macro rather than a method, so the commands can
+/// be borrowed alongside the rest of the `CmdEnv`.
+macro_rules! commands {
+    ($env:ident, $commands:ident) => {
+        let mut echo_cmd = Echo {}; // this command has no persistent storage, so we can "create" it every time we need the commands (but it's a zero-cost absraction so this doesn't actually create any instructions)
+        let mut ver_cmd = Ver {};
+        let mut backlight_cmd = Backlight {};
+        let mut accel_cmd = Accel {};
+        let mut console_cmd = Console {};
+        let $commands: &mut [&mut dyn ShellCmdApi] = &mut [
+            ///// 4. add your command to this array, so that it can be looked up and dispatched
+            &mut echo_cmd,
+            &mut $env.sleep_cmd,
+            &mut $env.sensors_cmd,
+            //&mut $env.callback_cmd,
+            &mut $env.rtc_cmd,
+            &mut $env.vibe_cmd,
+            &mut $env.ssid_cmd,
+            &mut ver_cmd,
+            //&mut $env.audio_cmd,
+            &mut backlight_cmd,
+            &mut accel_cmd,
+            #[cfg(feature = "dbg-ecupdate")]
+            &mut $env.ecup_cmd,
+            &mut $env.trng_cmd,
+            &mut console_cmd,
+            // &mut $env.memtest_cmd,
+            &mut $env.keys_cmd,
+            &mut $env.wlan_cmd,
+            &mut $env.jtag_cmd,
+            &mut $env.net_cmd,
+            &mut $env.pddb_cmd,
+            &mut $env.usb_cmd,
+            &mut $env.crashdump_cmd,
+            &mut $env.logs_cmd,
+            &mut $env.loglevel_cmd,
+            &mut $env.script_cmd,
+            &mut $env.clip_cmd,
+            #[cfg(not(feature = "no-codec"))]
+            &mut $env.test_cmd,
+            #[cfg(feature = "tts")]
+            &mut $env.tts_cmd,
+            #[cfg(feature = "hashtest")]
+            &mut $env.sha_cmd,
+            #[cfg(feature = "aestests")]
+            &mut $env.aes_cmd,
+            #[cfg(feature = "benchmarks")]
+            &mut $env.engine_cmd,
+            //&mut $env.fcc_cmd,
+        ];
+    };
+}
+
 impl CmdEnv {
-    pub fn new(xns: &xous_names::XousNames) -> CmdEnv {
+    pub fn new(xns: &xous_names::XousNames, token: [u32; 4]) -> CmdEnv {
         let ticktimer = ticktimer_server::Ticktimer::new().expect("Couldn't connect to Ticktimer");
         #[cfg(feature = "shellperf")]
         let perf_csr = xous::syscall::map_memory(
@@ -253,6 +330,9 @@ impl CmdEnv {
         CmdEnv {
             common_env: _common,
             lastverb: String::new(),
+            script: script::Engine::default(),
+            pddb: pddb::Pddb::new(),
+            external: ExternalVerbs::default(),
             ///// 3. initialize your storage, by calling new()
             sleep_cmd: {
                 log::debug!("sleep");
@@ -307,6 +387,26 @@ impl CmdEnv {
                 log::debug!("usb");
                 Usb::new()
             },
+            crashdump_cmd: {
+                log::debug!("crashdump");
+                CrashDumpCmd::new()
+            },
+            logs_cmd: {
+                log::debug!("logs");
+                Logs::new()
+            },
+            loglevel_cmd: {
+                log::debug!("loglevel");
+                LogLevelCmd::new()
+            },
+            script_cmd: {
+                log::debug!("script");
+                ScriptCmd::new()
+            },
+            clip_cmd: {
+                log::debug!("clip");
+                Clip::new(token)
+            },
 
             #[cfg(not(feature = "no-codec"))]
             test_cmd: {
@@ -315,7 +415,7 @@ impl CmdEnv {
             },
 
             #[cfg(feature = "tts")]
-            tts_cmd: Tts::new(&xns),
+            tts_cmd: Tts::new(&xns, token),
 
             #[cfg(feature = "hashtest")]
             sha_cmd: sha,
@@ -332,87 +432,17 @@ impl CmdEnv {
         maybe_cmdline: Option<&mut String>,
         maybe_callback: Option<&MessageEnvelope>,
     ) -> Result<Option<String>, xous::Error> {
-        let mut ret = String::new();
-
-        let mut echo_cmd = Echo {}; // this command has no persistent storage, so we can "create" it every time we call dispatch (but it's a zero-cost absraction so this doesn't actually create any instructions)
-        let mut ver_cmd = Ver {};
-        let mut backlight_cmd = Backlight {};
-        let mut accel_cmd = Accel {};
-        let mut console_cmd = Console {};
-        let commands: &mut [&mut dyn ShellCmdApi] = &mut [
-            ///// 4. add your command to this array, so that it can be looked up and dispatched
-            &mut echo_cmd,
-            &mut self.sleep_cmd,
-            &mut self.sensors_cmd,
-            //&mut self.callback_cmd,
-            &mut self.rtc_cmd,
-            &mut self.vibe_cmd,
-            &mut self.ssid_cmd,
-            &mut ver_cmd,
-            //&mut self.audio_cmd,
-            &mut backlight_cmd,
-            &mut accel_cmd,
-            #[cfg(feature = "dbg-ecupdate")]
-            &mut self.ecup_cmd,
-            &mut self.trng_cmd,
-            &mut console_cmd,
-            // &mut self.memtest_cmd,
-            &mut self.keys_cmd,
-            &mut self.wlan_cmd,
-            &mut self.jtag_cmd,
-            &mut self.net_cmd,
-            &mut self.pddb_cmd,
-            &mut self.usb_cmd,
-            #[cfg(not(feature = "no-codec"))]
-            &mut self.test_cmd,
-            #[cfg(feature = "tts")]
-            &mut self.tts_cmd,
-            #[cfg(feature = "hashtest")]
-            &mut self.sha_cmd,
-            #[cfg(feature = "aestests")]
-            &mut self.aes_cmd,
-            #[cfg(feature = "benchmarks")]
-            &mut self.engine_cmd,
-            //&mut self.fcc_cmd,
-        ];
+        commands!(self, commands);
 
         if let Some(cmdline) = maybe_cmdline {
-            let maybe_verb = tokenize(cmdline);
-
-            let mut cmd_ret: Result<Option<String>, xous::Error> = Ok(None);
-            if let Some(verb_string) = maybe_verb {
-                let verb = &verb_string;
-
-                // search through the list of commands linearly until one matches,
-                // then run it.
-                let mut match_found = false;
-                for cmd in commands.iter_mut() {
-                    if cmd.matches(verb) {
-                        match_found = true;
-                        cmd_ret = cmd.process(cmdline.to_string(), &mut self.common_env);
-                        self.lastverb.clear();
-                        write!(self.lastverb, "{}", verb).expect("SHCH: couldn't record last verb");
-                    };
-                }
-
-                // if none match, create a list of available commands
-                if !match_found {
-                    let mut first = true;
-                    write!(ret, "Commands: ").unwrap();
-                    for cmd in commands.iter() {
-                        if !first {
-                            ret.push_str(", ");
-                        }
-                        ret.push_str(cmd.verb());
-                        first = false;
-                    }
-                    Ok(Some(ret))
-                } else {
-                    cmd_ret
-                }
-            } else {
-                Ok(None)
-            }
+            let mut verbs = Verbs {
+                commands,
+                env: &mut self.common_env,
+                lastverb: &mut self.lastverb,
+                pddb: &self.pddb,
+                external: &mut self.external,
+            };
+            self.script.run_line(&mut verbs, cmdline)
         } else if let Some(callback) = maybe_callback {
             let mut cmd_ret: Result<Option<String>, xous::Error> = Ok(None);
             // first check and see if we have a callback registration; if not, just map to the last verb
@@ -434,34 +464,74 @@ impl CmdEnv {
             Ok(None)
         }
     }
+
+    /// Adds a verb implemented by another process, unless the verb is taken
+    pub fn register_verb(&mut self, registration: &VerbRegistration, pid: Option<xous::PID>) -> bool {
+        commands!(self, commands);
+        if commands.iter().any(|cmd| cmd.matches(&registration.verb))
+            || script::BUILTINS.contains(&registration.verb.as_str())
+        {
+            return false;
+        }
+        self.external.register(registration, pid)
+    }
+
+    pub fn unregister_verb(&mut self, verb: &str, pid: Option<xous::PID>) {
+        self.external.unregister(verb, pid)
+    }
+
+    /// The text to show for output from a verb implemented by another process
+    pub fn verb_output(&mut self, output: VerbOutput, pid: Option<xous::PID>) -> Option<String> {
+        self.external.output(output, pid)
+    }
 }
 
-/// extract the first token, as delimited by spaces
-/// modifies the incoming line by removing the token and returning the remainder
-/// returns the found token
-/// note: we don't have split() because of nostd
-pub fn tokenize(line: &mut String) -> Option<String> {
-    let mut token = String::new();
-    let mut retline = String::new();
+/// Runs the commands of a parsed line for the script engine
+struct Verbs<'a, 'b> {
+    commands: &'a mut [&'b mut dyn ShellCmdApi<'b>],
+    env: &'a mut CommonEnv,
+    lastverb: &'a mut String,
+    pddb: &'a pddb::Pddb,
+    external: &'a mut ExternalVerbs,
+}
+impl script::Shell for Verbs<'_, '_> {
+    type Error = xous::Error;
 
-    let lineiter = line.as_str().chars();
-    let mut foundspace = false;
-    let mut foundrest = false;
-    for ch in lineiter {
-        if ch != ' ' && !foundspace {
-            token.push(ch);
-        } else if foundspace && foundrest {
-            retline.push(ch);
-        } else if foundspace && ch != ' ' {
-            // handle case of multiple spaces in a row
-            foundrest = true;
-            retline.push(ch);
-        } else {
-            foundspace = true;
-            // consume the space
+    fn command(&mut self, verb: &str, args: Vec<String>) -> Result<Option<String>, xous::Error> {
+        // search through the list of commands linearly until one matches,
+        // then run it.
+        for cmd in self.commands.iter_mut() {
+            if cmd.matches(verb) {
+                self.lastverb.clear();
+                write!(self.lastverb, "{}", verb).expect("SHCH: couldn't record last verb");
+                return cmd.process(args, self.env);
+            };
+        }
+        if self.external.contains(verb) {
+            return Ok(self.external.invoke(verb, args));
+        }
+
+        // if none match, create a list of available commands
+        let mut ret = String::new();
+        write!(ret, "Commands: ").unwrap();
+        let external = self.external.list();
+        let verbs = self
+            .commands
+            .iter()
+            .map(|cmd| cmd.verb())
+            .chain(script::BUILTINS)
+            .chain(external.iter().map(|(verb, _)| *verb));
+        for (i, verb) in verbs.enumerate() {
+            if i != 0 {
+                ret.push_str(", ");
+            }
+            ret.push_str(verb);
         }
+        for (verb, help) in external {
+            write!(ret, "\n{}: {}", verb, help).unwrap();
+        }
+        Ok(Some(ret))
     }
-    line.clear();
-    write!(line, "{}", retline.as_str()).unwrap();
-    if token.len() > 0 { Some(token) } else { None }
+
+    fn script(&mut self, name: &str) -> Option<String> { read_script(self.pddb, name) }
 }
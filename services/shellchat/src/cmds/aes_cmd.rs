@@ -298,11 +298,11 @@ impl<'a> ShellCmdApi<'a> for Aes {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         let mut ret = String::new();
         let helpstring = "Aes [check128] [check128sw] [check256] [check256sw] [hwbench] [swbench] [susres]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
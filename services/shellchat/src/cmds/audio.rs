@@ -71,13 +71,13 @@ impl Audio {
 impl<'a> ShellCmdApi<'a> for Audio {
     cmd_api!(audio);
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
 
         let mut ret = String::new();
         let helpstring = "audio [play] [info]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
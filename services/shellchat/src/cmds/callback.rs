@@ -41,7 +41,7 @@ impl CallBack {
 impl<'a> ShellCmdApi<'a> for CallBack {
     cmd_api!(cb);
 
-    fn process(&mut self, _args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, _args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
 
         self.state += 1;
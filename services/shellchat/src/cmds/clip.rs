@@ -0,0 +1,76 @@
+use core::fmt::Write;
+
+use String;
+use clipboard_api::{Clipboard, Sensitivity};
+
+use crate::{CommonEnv, ShellCmdApi};
+
+pub struct Clip {
+    token: [u32; 4],
+    /// connected on first use, as the clipboard server may not be up yet when the shell starts
+    clipboard: Option<Clipboard>,
+}
+impl Clip {
+    pub fn new(token: [u32; 4]) -> Clip { Clip { token, clipboard: None } }
+}
+
+impl<'a> ShellCmdApi<'a> for Clip {
+    cmd_api!(clip);
+
+    // inserts boilerplate for command API
+
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        let mut ret = String::new();
+        let helpstring = "clip [show] [copy <text>] [secret <text>] [clear]";
+
+        if self.clipboard.is_none() {
+            self.clipboard = Clipboard::new(&env.xns, self.token).ok();
+        }
+        let clipboard = match self.clipboard.as_ref() {
+            Some(clipboard) => clipboard,
+            None => {
+                write!(ret, "No clipboard in this image").unwrap();
+                return Ok(Some(ret));
+            }
+        };
+        let mut tokens = args.iter().map(String::as_str);
+
+        if let Some(sub_cmd) = tokens.next() {
+            match sub_cmd {
+                "show" => match clipboard.paste() {
+                    Ok(Some(text)) => write!(ret, "{}", text).unwrap(),
+                    Ok(None) => write!(ret, "Clipboard is empty").unwrap(),
+                    Err(e) => write!(ret, "Couldn't paste: {:?}", e).unwrap(),
+                },
+                "copy" | "secret" => {
+                    let mut text = String::new();
+                    join_tokens(&mut text, &mut tokens);
+                    let sensitivity =
+                        if sub_cmd == "secret" { Sensitivity::Sensitive } else { Sensitivity::Normal };
+                    match clipboard.copy(&text, sensitivity) {
+                        Ok(()) => write!(ret, "Copied {} chars", text.chars().count()).unwrap(),
+                        Err(e) => write!(ret, "Couldn't copy: {:?}", e).unwrap(),
+                    }
+                }
+                "clear" => match clipboard.clear() {
+                    Ok(()) => write!(ret, "Clipboard cleared").unwrap(),
+                    Err(e) => write!(ret, "Couldn't clear: {:?}", e).unwrap(),
+                },
+                _ => write!(ret, "{}", helpstring).unwrap(),
+            }
+        } else {
+            write!(ret, "{}", helpstring).unwrap();
+        }
+        Ok(Some(ret))
+    }
+}
+
+fn join_tokens<'a>(buf: &mut String, tokens: impl Iterator<Item = &'a str>) {
+    for (i, tok) in tokens.enumerate() {
+        if i == 0 {
+            write!(buf, "{}", tok).unwrap();
+        } else {
+            write!(buf, " {}", tok).unwrap();
+        }
+    }
+}
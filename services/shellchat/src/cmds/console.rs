@@ -10,12 +10,12 @@ impl<'a> ShellCmdApi<'a> for Console {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
         let mut ret = String::new();
         let helpstring = "Serial console options: kernel, log, app";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -0,0 +1,77 @@
+use core::fmt::Write;
+
+use String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+/// hex characters per `CRD:` line in the log
+const HEX_LINE_LEN: usize = 64;
+
+pub struct CrashDumpCmd {
+    pddb: pddb::Pddb,
+}
+impl CrashDumpCmd {
+    pub fn new() -> Self { CrashDumpCmd { pddb: pddb::Pddb::new() } }
+}
+
+impl<'a> ShellCmdApi<'a> for CrashDumpCmd {
+    cmd_api!(crashdump);
+
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        let mut ret = String::new();
+        let helpstring = "crashdump [list] [show <name>] [clear]";
+
+        let mut tokens = args.iter().map(String::as_str);
+        match tokens.next() {
+            Some("list") => {
+                let dumps = crash_dump::list_dumps(&self.pddb);
+                if dumps.is_empty() {
+                    write!(ret, "No crash dumps").unwrap();
+                }
+                for name in dumps {
+                    write!(ret, "{}\n", name).unwrap();
+                }
+            }
+            Some("show") => {
+                let name = match tokens.next() {
+                    Some(name) => name,
+                    None => return Ok(Some(helpstring.to_string())),
+                };
+                match crash_dump::read_dump(&self.pddb, name) {
+                    Ok(data) => match crash_dump::CrashDump::decode(&data) {
+                        Ok(dump) => {
+                            // the raw dump goes to the log, where it can be captured and fed to
+                            // `crash-decode`
+                            let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+                            for line in hex.as_bytes().chunks(HEX_LINE_LEN) {
+                                log::info!("CRD:{}", std::str::from_utf8(line).unwrap());
+                            }
+                            write!(
+                                ret,
+                                "{} {:?} PID {} TID {}\npc {:08x} addr {:08x}\n{}\n{} threads, {} regions. Raw dump sent to the log.",
+                                dump.name,
+                                dump.kind,
+                                dump.pid,
+                                dump.tid,
+                                dump.pc,
+                                dump.address,
+                                dump.message,
+                                dump.threads.len(),
+                                dump.regions.len()
+                            )
+                            .unwrap();
+                        }
+                        Err(e) => write!(ret, "Couldn't decode {}: {:?}", name, e).unwrap(),
+                    },
+                    Err(e) => write!(ret, "Couldn't read {}: {:?}", name, e).unwrap(),
+                }
+            }
+            Some("clear") => match crash_dump::clear_dumps(&self.pddb) {
+                Ok(_) => write!(ret, "Crash dumps cleared").unwrap(),
+                Err(e) => write!(ret, "Couldn't clear crash dumps: {:?}", e).unwrap(),
+            },
+            _ => write!(ret, "{}", helpstring).unwrap(),
+        }
+        Ok(Some(ret))
+    }
+}
@@ -10,7 +10,7 @@ impl<'a> ShellCmdApi<'a> for Echo {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
-        Ok(Some(args))
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        Ok(Some(args.join(" ")))
     }
 }
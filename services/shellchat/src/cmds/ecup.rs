@@ -16,12 +16,12 @@ impl<'a> ShellCmdApi<'a> for EcUpdate {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         let mut ret = String::new();
         let helpstring = "ecup [gw] [fw] [wf200] [auto]";
 
-        log::debug!("ecup handling {}", &args);
-        let mut tokens = args.split(' ');
+        log::debug!("ecup handling {:?}", &args);
+        let mut tokens = args.iter().map(String::as_str);
         let ecup_conn = env.xns.request_connection_blocking("__ECUP server__").unwrap();
 
         if let Some(sub_cmd) = tokens.next() {
@@ -79,6 +79,8 @@ impl<'a> ShellCmdApi<'a> for EcUpdate {
                     write!(ret, "{}", helpstring).unwrap();
                 }
             }
+        } else {
+            write!(ret, "{}", helpstring).unwrap();
         }
         Ok(Some(ret))
     }
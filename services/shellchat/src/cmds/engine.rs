@@ -306,7 +306,7 @@ impl<'a> ShellCmdApi<'a> for Engine {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
         let mut ret = String::new();
         #[cfg(feature = "engine-ll")]
@@ -314,7 +314,7 @@ impl<'a> ShellCmdApi<'a> for Engine {
         #[cfg(not(feature = "engine-ll"))]
         let helpstring = "engine [susres] [dh] [ed] [wycheproof]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
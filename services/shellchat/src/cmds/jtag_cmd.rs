@@ -17,12 +17,12 @@ impl<'a> ShellCmdApi<'a> for JtagCmd {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
         let mut ret = String::new();
         let helpstring = "jtag [id] [dna] [efuse] [cntl] [reset] [burn0]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -63,12 +63,12 @@ impl<'a> ShellCmdApi<'a> for Keys {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
         let mut ret = String::new();
-        let helpstring = "keys [usblock] [usbunlock] [pddbrecycle]";
+        let helpstring = "keys [usblock] [usbunlock] [pddbrecycle] [rollback [advance]]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -108,6 +108,24 @@ impl<'a> ShellCmdApi<'a> for Keys {
                         write!(ret, "aes test failed").unwrap();
                     }
                 }
+                "rollback" => match tokens.next() {
+                    Some("advance") => match self.rootkeys.advance_antirollback() {
+                        Ok(Some(counter)) => {
+                            write!(ret, "Anti-rollback counter is at security version {}", counter).unwrap()
+                        }
+                        Ok(None) => write!(ret, "Anti-rollback counter not advanced").unwrap(),
+                        Err(e) => write!(ret, "Error advancing anti-rollback counter: {:?}", e).unwrap(),
+                    },
+                    _ => match self.rootkeys.antirollback_status() {
+                        Ok((booted, counter)) => write!(
+                            ret,
+                            "Booted security version {}, anti-rollback counter {}",
+                            booted, counter
+                        )
+                        .unwrap(),
+                        Err(e) => write!(ret, "Error reading anti-rollback status: {:?}", e).unwrap(),
+                    },
+                },
                 "pddbrecycle" => {
                     // erase the page table, which should effectively trigger a reformat on the next boot
                     self.spinor.bulk_erase(xous::PDDB_LOC, 1024 * 1024).expect("couldn't erase page table");
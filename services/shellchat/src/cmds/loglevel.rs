@@ -0,0 +1,81 @@
+use core::fmt::Write;
+
+use String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+#[derive(Debug)]
+pub struct LogLevelCmd {}
+impl LogLevelCmd {
+    pub fn new() -> Self {
+        // the shell is the one process that may change the levels of others
+        match log_server::claim_level_control() {
+            Ok(true) => {}
+            Ok(false) => {
+                log::warn!("log level control was already claimed; loglevel set only works on the shell")
+            }
+            Err(e) => log::warn!("couldn't claim log level control: {:?}", e),
+        }
+        LogLevelCmd {}
+    }
+}
+
+fn parse_level(level: &str) -> Option<log::LevelFilter> {
+    match level {
+        "off" => Some(log::LevelFilter::Off),
+        "error" => Some(log::LevelFilter::Error),
+        "warn" => Some(log::LevelFilter::Warn),
+        "info" => Some(log::LevelFilter::Info),
+        "debug" => Some(log::LevelFilter::Debug),
+        "trace" => Some(log::LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+impl<'a> ShellCmdApi<'a> for LogLevelCmd {
+    cmd_api!(loglevel);
+
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        let mut ret = String::new();
+        let helpstring = "loglevel [list] [set <pid> <off|error|warn|info|debug|trace> [module]]";
+
+        let mut tokens = args.iter().map(String::as_str);
+        match tokens.next() {
+            Some("list") => match log_server::registered_levels() {
+                Ok(processes) => {
+                    if processes.is_empty() {
+                        write!(ret, "No processes registered for level control").unwrap();
+                    }
+                    for process in processes {
+                        write!(ret, "PID{} {}\n", process.pid, process.name).unwrap();
+                        for (module, level) in process.modules {
+                            write!(ret, "  {}: {}\n", module, level).unwrap();
+                        }
+                    }
+                }
+                Err(e) => write!(ret, "Couldn't read log levels: {:?}", e).unwrap(),
+            },
+            Some("set") => {
+                let pid = tokens.next().and_then(|pid| pid.parse::<u8>().ok());
+                let level = tokens.next().and_then(parse_level);
+                let (pid, level) = match (pid, level) {
+                    (Some(pid), Some(level)) if pid != 0 => (pid, level),
+                    _ => return Ok(Some(helpstring.to_string())),
+                };
+                let module = tokens.next();
+                match log_server::set_level(Some(pid), module, level) {
+                    Ok(0) => write!(ret, "PID {} isn't registered for level control", pid).unwrap(),
+                    Ok(changed) => {
+                        write!(ret, "Set {} module(s) of PID {} to {}", changed, pid, level).unwrap()
+                    }
+                    Err(xous::Error::AccessDenied) => {
+                        write!(ret, "Not allowed to set the log levels of PID {}", pid).unwrap()
+                    }
+                    Err(e) => write!(ret, "Couldn't set the log level: {:?}", e).unwrap(),
+                }
+            }
+            _ => write!(ret, "{}", helpstring).unwrap(),
+        }
+        Ok(Some(ret))
+    }
+}
@@ -0,0 +1,118 @@
+use core::fmt::Write;
+
+use String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+/// records shown when no count is given
+const DEFAULT_SHOWN: usize = 20;
+
+pub struct Logs {
+    pddb: pddb::Pddb,
+}
+impl Logs {
+    pub fn new() -> Self { Logs { pddb: pddb::Pddb::new() } }
+}
+
+fn parse_level(level: &str) -> Option<log::LevelFilter> {
+    match level {
+        "off" => Some(log::LevelFilter::Off),
+        "error" => Some(log::LevelFilter::Error),
+        "warn" => Some(log::LevelFilter::Warn),
+        "info" => Some(log::LevelFilter::Info),
+        "debug" => Some(log::LevelFilter::Debug),
+        "trace" => Some(log::LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+/// Writes the last `count` of `records`, marking where the system was rebooted.
+fn show(ret: &mut String, records: &[log_store::StoredRecord], count: usize) {
+    if records.is_empty() {
+        write!(ret, "No records").unwrap();
+        return;
+    }
+    let start = records.len().saturating_sub(count);
+    let mut last_seq = None;
+    for record in &records[start..] {
+        if last_seq.map(|seq| record.seq <= seq).unwrap_or(false) {
+            write!(ret, "--- reboot ---\n").unwrap();
+        }
+        write!(ret, "{}\n", record).unwrap();
+        last_seq = Some(record.seq);
+    }
+}
+
+impl<'a> ShellCmdApi<'a> for Logs {
+    cmd_api!(logs);
+
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        let mut ret = String::new();
+        let helpstring = "logs [recent [n]] [saved [n]] [panics] [filter <off|error|warn|info|debug|trace> [pid]] [unfilter <pid>] [size <n>] [clear [saved]]";
+
+        let mut tokens = args.iter().map(String::as_str);
+        match tokens.next() {
+            Some("recent") => {
+                let count = tokens.next().and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_SHOWN);
+                match log_server::store_read_all() {
+                    Ok(records) => show(&mut ret, &records, count),
+                    Err(e) => write!(ret, "Couldn't read the log store: {:?}", e).unwrap(),
+                }
+            }
+            Some("saved") => {
+                let count = tokens.next().and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_SHOWN);
+                show(&mut ret, &log_store::read_saved(&self.pddb), count);
+            }
+            Some("panics") => {
+                let panics: Vec<log_store::StoredRecord> =
+                    log_store::read_saved(&self.pddb).into_iter().filter(|r| r.level.is_none()).collect();
+                if panics.is_empty() {
+                    write!(ret, "No saved panics").unwrap();
+                }
+                for panic in panics {
+                    write!(ret, "PID{}: {}\n", panic.pid, panic.text).unwrap();
+                }
+            }
+            Some("filter") => {
+                let level = match tokens.next().and_then(parse_level) {
+                    Some(level) => level,
+                    None => return Ok(Some(helpstring.to_string())),
+                };
+                match tokens.next().map(|pid| pid.parse::<u8>()) {
+                    Some(Ok(pid)) => {
+                        log_server::store_set_filter(Some(pid), level);
+                        write!(ret, "Keeping {} and above from PID {}", level, pid).unwrap();
+                    }
+                    Some(Err(_)) => write!(ret, "{}", helpstring).unwrap(),
+                    None => {
+                        log_server::store_set_filter(None, level);
+                        write!(ret, "Keeping {} and above by default", level).unwrap();
+                    }
+                }
+            }
+            Some("unfilter") => match tokens.next().and_then(|pid| pid.parse::<u8>().ok()) {
+                Some(pid) => {
+                    log_server::store_clear_filter(pid);
+                    write!(ret, "PID {} follows the default filter", pid).unwrap();
+                }
+                None => write!(ret, "{}", helpstring).unwrap(),
+            },
+            Some("size") => match tokens.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => write!(ret, "Keeping {} records", log_server::store_set_capacity(n)).unwrap(),
+                None => write!(ret, "{}", helpstring).unwrap(),
+            },
+            Some("clear") => match tokens.next() {
+                Some("saved") => match log_store::clear_saved(&self.pddb) {
+                    Ok(_) => write!(ret, "Saved logs cleared").unwrap(),
+                    Err(e) => write!(ret, "Couldn't clear saved logs: {:?}", e).unwrap(),
+                },
+                _ => {
+                    log_server::store_clear();
+                    write!(ret, "Log store cleared").unwrap();
+                }
+            },
+            _ => write!(ret, "{}", helpstring).unwrap(),
+        }
+        Ok(Some(ret))
+    }
+}
@@ -72,7 +72,7 @@ impl<'a> ShellCmdApi<'a> for NetCmd {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         if self.callback_id.is_none() {
             let cb_id = env.register_handler(String::from(self.verb()));
             log::trace!("hooking net callback with ID {}", cb_id);
@@ -87,7 +87,7 @@ impl<'a> ShellCmdApi<'a> for NetCmd {
         #[cfg(not(target_os = "xous"))]
         let helpstring = "net [udp [port]] [count]] [tcpget host/path]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -0,0 +1,86 @@
+use core::fmt::Write as FmtWrite;
+use std::io::{Read, Write};
+
+use String;
+
+use crate::{CommonEnv, ShellCmdApi};
+
+/// PDDB dictionary holding the stored scripts, one key per script
+pub const SCRIPT_DICT: &str = "shellchat.scripts";
+/// The script run once the PDDB is mounted
+pub const BOOT_SCRIPT: &str = "boot";
+
+pub struct ScriptCmd {
+    pddb: pddb::Pddb,
+}
+impl ScriptCmd {
+    pub fn new() -> Self { ScriptCmd { pddb: pddb::Pddb::new() } }
+}
+
+impl<'a> ShellCmdApi<'a> for ScriptCmd {
+    cmd_api!(script);
+
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+        let mut ret = String::new();
+        let helpstring = "script [list] [show <name>] [add <name> <line>] [delete <name>]";
+
+        let mut args = args.into_iter();
+        let subcommand = args.next().unwrap_or_default();
+        let name = args.next().unwrap_or_default();
+        let name = name.as_str();
+        // the line is parsed again when the script runs, so quote it to keep its own quoting
+        let line = args.collect::<Vec<String>>().join(" ");
+        match subcommand.as_str() {
+            "list" => {
+                let names = self.pddb.list_keys(SCRIPT_DICT, None).unwrap_or_default();
+                if names.is_empty() {
+                    write!(ret, "No scripts").unwrap();
+                }
+                for name in names {
+                    write!(ret, "{}\n", name).unwrap();
+                }
+            }
+            "show" if !name.is_empty() => match read_script(&self.pddb, name) {
+                Some(text) => write!(ret, "{}", text).unwrap(),
+                None => write!(ret, "No script named {}", name).unwrap(),
+            },
+            "add" if !name.is_empty() && !line.is_empty() => {
+                let mut text = read_script(&self.pddb, name).unwrap_or_default();
+                text.push_str(&line);
+                text.push('\n');
+                // delete first, so the key is sized for the new text
+                self.pddb.delete_key(SCRIPT_DICT, name, None).ok();
+                match self.pddb.get(SCRIPT_DICT, name, None, true, true, Some(text.len()), None::<fn()>) {
+                    Ok(mut key) => match key.write_all(text.as_bytes()) {
+                        Ok(_) => write!(ret, "{}: {} lines", name, text.lines().count()).unwrap(),
+                        Err(e) => write!(ret, "Couldn't write {}: {:?}", name, e).unwrap(),
+                    },
+                    Err(e) => write!(ret, "Couldn't create {}: {:?}", name, e).unwrap(),
+                }
+                self.pddb.sync().ok();
+            }
+            "delete" if !name.is_empty() => match self.pddb.delete_key(SCRIPT_DICT, name, None) {
+                Ok(_) => {
+                    self.pddb.sync().ok();
+                    write!(ret, "Deleted {}", name).unwrap();
+                }
+                Err(_) => write!(ret, "No script named {}", name).unwrap(),
+            },
+            _ => write!(ret, "{}", helpstring).unwrap(),
+        }
+        Ok(Some(ret))
+    }
+}
+
+/// The text of the stored script `name`
+pub fn read_script(pddb: &pddb::Pddb, name: &str) -> Option<String> {
+    let mut key = pddb.get(SCRIPT_DICT, name, None, false, false, None, None::<fn()>).ok()?;
+    let mut text = String::new();
+    match key.read_to_string(&mut text) {
+        Ok(_) => Some(text),
+        Err(e) => {
+            log::warn!("couldn't read script {}: {:?}", name, e);
+            None
+        }
+    }
+}
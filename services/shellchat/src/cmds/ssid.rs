@@ -15,11 +15,11 @@ impl Ssid {
 impl<'a> ShellCmdApi<'a> for Ssid {
     cmd_api!(ssid);
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         let mut ret = String::new();
         let helpstring = "ssid [scan]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
         if self.cb_id.is_none() {
             self.cb_id = Some(env.register_handler(String::from(self.verb())));
         }
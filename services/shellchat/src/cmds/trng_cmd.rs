@@ -11,12 +11,12 @@ impl TrngCmd {
 impl<'a> ShellCmdApi<'a> for TrngCmd {
     cmd_api!(trng);
 
-    fn process(&mut self, args: String, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         use core::fmt::Write;
         let mut ret = String::new();
         let helpstring = "trng [avnist] [ronist] [runs] [excur] [errs] [pump]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -8,9 +8,13 @@ use crate::{CommonEnv, ShellCmdApi};
 #[derive(Debug)]
 pub struct Tts {
     pub fe: TtsFrontend,
+    /// the shell's GAM token, which switching the screen reader requires
+    token: [u32; 4],
 }
 impl Tts {
-    pub fn new(xns: &xous_names::XousNames) -> Tts { Tts { fe: TtsFrontend::new(xns).unwrap() } }
+    pub fn new(xns: &xous_names::XousNames, token: [u32; 4]) -> Tts {
+        Tts { fe: TtsFrontend::new(xns).unwrap(), token }
+    }
 }
 
 impl<'a> ShellCmdApi<'a> for Tts {
@@ -18,11 +22,11 @@ impl<'a> ShellCmdApi<'a> for Tts {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         let mut ret = String::new();
-        let helpstring = "tts options: speak";
+        let helpstring = "tts options: speak, queue, alert, cancel <id>, stop, reader [on|off]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -31,6 +35,35 @@ impl<'a> ShellCmdApi<'a> for Tts {
                     join_tokens(&mut text, &mut tokens);
                     self.fe.tts_simple(text.as_str()).unwrap();
                 }
+                "queue" | "alert" => {
+                    let mut text = String::new();
+                    join_tokens(&mut text, &mut tokens);
+                    let (priority, mode) = if sub_cmd == "alert" {
+                        (Priority::High, SpeechMode::Interrupt)
+                    } else {
+                        (Priority::Normal, SpeechMode::Queue)
+                    };
+                    let id = self.fe.speak(text.as_str(), priority, mode)?;
+                    write!(ret, "utterance {}", id).unwrap();
+                }
+                "cancel" => match tokens.next().and_then(|id| id.parse::<u32>().ok()) {
+                    Some(id) => self.fe.cancel(id)?,
+                    None => write!(ret, "tts cancel <id>").unwrap(),
+                },
+                "stop" => {
+                    self.fe.stop()?;
+                }
+                "reader" => match tokens.next() {
+                    Some("on") => match env.gam.set_screen_reader(self.token, true) {
+                        Ok(_) => write!(ret, "Screen reader on").unwrap(),
+                        Err(e) => write!(ret, "Couldn't turn the screen reader on: {:?}", e).unwrap(),
+                    },
+                    Some("off") => {
+                        env.gam.set_screen_reader(self.token, false)?;
+                        write!(ret, "Screen reader off").unwrap();
+                    }
+                    _ => write!(ret, "tts reader [on|off]").unwrap(),
+                },
                 _ => {
                     write!(ret, "{}", helpstring).unwrap();
                 }
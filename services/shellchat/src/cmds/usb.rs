@@ -1,6 +1,6 @@
 use std::fmt::Write;
 
-use usb_device_xous::{UsbDeviceState, UsbDeviceType, UsbHid};
+use usb_device_xous::{CompositeFunctions, UsbDeviceState, UsbDeviceType, UsbHid};
 
 use crate::{CommonEnv, ShellCmdApi};
 
@@ -17,14 +17,14 @@ impl<'a> ShellCmdApi<'a> for Usb {
 
     // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         let mut ret = String::new();
         #[cfg(not(feature = "mass-storage"))]
-        let helpstring = "usb [hid] [fido] [debug] [send <string>] [status] [leds] [lock] [unlock] [kbdtest]";
+        let helpstring = "usb [hid] [fido] [ccid] [ncm] [composite [fido] [serial]] [debug] [send <string>] [status] [leds] [lock] [unlock] [kbdtest]";
         #[cfg(feature = "mass-storage")]
-        let helpstring = "usb [hid] [fido] [ms] [debug] [send <string>] [status] [leds] [lock] [unlock] [kbdtest] [console] [noconsole]";
+        let helpstring = "usb [hid] [fido] [ccid] [ncm] [ms] [composite [fido] [serial] [ms]] [debug] [send <string>] [status] [leds] [lock] [unlock] [kbdtest] [console] [noconsole]";
 
-        let mut tokens = args.split(' ');
+        let mut tokens = args.iter().map(String::as_str);
 
         if let Some(sub_cmd) = tokens.next() {
             match sub_cmd {
@@ -41,6 +41,39 @@ impl<'a> ShellCmdApi<'a> for Usb {
                     self.usb_dev.ensure_core(usb_device_xous::UsbDeviceType::Fido).unwrap();
                     write!(ret, "USB connected to FIDO-only core").unwrap();
                 }
+                "ccid" => {
+                    self.usb_dev.ensure_core(usb_device_xous::UsbDeviceType::Ccid).unwrap();
+                    write!(ret, "USB connected to smart card (CCID) core").unwrap();
+                }
+                "ncm" => {
+                    self.usb_dev.ensure_core(usb_device_xous::UsbDeviceType::Ncm).unwrap();
+                    write!(ret, "USB connected to network (CDC-NCM) core").unwrap();
+                }
+                "composite" => {
+                    // with no functions named, the composite core presents all of them
+                    let mut functions: Option<CompositeFunctions> = None;
+                    let mut valid = true;
+                    for name in tokens.by_ref() {
+                        let function = match name {
+                            "fido" => CompositeFunctions::FIDO,
+                            "serial" => CompositeFunctions::SERIAL,
+                            #[cfg(feature = "mass-storage")]
+                            "ms" => CompositeFunctions::MASS_STORAGE,
+                            _ => {
+                                valid = false;
+                                break;
+                            }
+                        };
+                        functions = Some(functions.map_or(function, |f| f | function));
+                    }
+                    if valid {
+                        let functions = functions.unwrap_or(CompositeFunctions::all());
+                        self.usb_dev.ensure_composite(functions).unwrap();
+                        write!(ret, "USB connected to composite core with {:?}", functions).unwrap();
+                    } else {
+                        write!(ret, "{}", helpstring).unwrap();
+                    }
+                }
                 "debug" => {
                     self.usb_dev.switch_to_core(usb_device_xous::UsbDeviceType::Debug).unwrap();
                     self.usb_dev.debug_usb(Some(false)).unwrap();
@@ -83,7 +116,7 @@ impl<'a> ShellCmdApi<'a> for Usb {
                     write!(ret, "USB TRNG serial sending should be stopped.").ok();
                 }
                 "send" => match self.usb_dev.get_current_core() {
-                    Ok(UsbDeviceType::FidoKbd) | Ok(UsbDeviceType::Serial) => {
+                    Ok(UsbDeviceType::FidoKbd) | Ok(UsbDeviceType::Serial) | Ok(UsbDeviceType::Composite) => {
                         let mut val = String::new();
                         join_tokens(&mut val, &mut tokens);
                         match self.usb_dev.send_str(&val) {
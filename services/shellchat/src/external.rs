@@ -0,0 +1,137 @@
+//! Verbs implemented by other processes, registered through `shellchat_api`
+
+use std::collections::{BTreeMap, VecDeque};
+
+use shellchat_api::{Invocation, VerbOutput, VerbRegistration};
+use xous_ipc::Buffer;
+
+/// Most invocations that can still be sending output; past this, the oldest one is forgotten
+const MAX_PENDING: usize = 16;
+
+struct ExternalVerb {
+    help: String,
+    /// the process that registered the verb
+    pid: Option<xous::PID>,
+    listener: xous::SID,
+    conn: xous::CID,
+    invoke_id: u32,
+}
+
+#[derive(Default)]
+pub struct ExternalVerbs {
+    verbs: BTreeMap<String, ExternalVerb>,
+    /// invocations that can still send output, oldest first, with the verb they are for
+    pending: VecDeque<(u32, String)>,
+    next_id: u32,
+}
+
+impl ExternalVerbs {
+    /// Adds a verb, unless another process has it
+    pub fn register(&mut self, registration: &VerbRegistration, pid: Option<xous::PID>) -> bool {
+        self.prune();
+        if self.verbs.contains_key(&registration.verb) {
+            return false;
+        }
+        let listener = xous::SID::from_array(registration.listener);
+        let conn = match xous::connect(listener) {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::warn!("couldn't connect to the listener of {}: {:?}", registration.verb, e);
+                return false;
+            }
+        };
+        log::info!("{} registered by PID {:?}", registration.verb, pid);
+        self.verbs.insert(
+            registration.verb.clone(),
+            ExternalVerb {
+                help: registration.help.clone(),
+                pid,
+                listener,
+                conn,
+                invoke_id: registration.invoke_id,
+            },
+        );
+        true
+    }
+
+    /// Removes a verb, if `pid` is the process that registered it
+    pub fn unregister(&mut self, verb: &str, pid: Option<xous::PID>) {
+        if self.verbs.get(verb).map(|v| v.pid) == Some(pid) {
+            log::info!("{} unregistered", verb);
+            self.remove(verb);
+        }
+    }
+
+    pub fn contains(&self, verb: &str) -> bool { self.verbs.contains_key(verb) }
+
+    /// Sends `args` to the process behind `verb`. Its output arrives later, through `output()`; what is
+    /// returned here is only for when the process can't be reached.
+    pub fn invoke(&mut self, verb: &str, args: Vec<String>) -> Option<String> {
+        let (conn, invoke_id) = match self.verbs.get(verb) {
+            Some(v) => (v.conn, v.invoke_id),
+            None => return None,
+        };
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let invocation = Invocation { id, verb: verb.to_string(), args };
+        let buf = Buffer::into_buf(invocation).expect("couldn't serialize an invocation");
+        match buf.send(conn, invoke_id) {
+            Ok(_) => {
+                if self.pending.len() >= MAX_PENDING {
+                    self.pending.pop_front();
+                }
+                self.pending.push_back((id, verb.to_string()));
+                None
+            }
+            Err(e) => {
+                log::info!("{} is gone: {:?}", verb, e);
+                self.remove(verb);
+                Some(format!("{} is no longer available", verb))
+            }
+        }
+    }
+
+    /// The text to show for `output`, if it belongs to an invocation of a verb registered by `pid`
+    pub fn output(&mut self, output: VerbOutput, pid: Option<xous::PID>) -> Option<String> {
+        let index = self.pending.iter().position(|(id, _)| *id == output.id)?;
+        if self.verbs.get(&self.pending[index].1).map(|v| v.pid) != Some(pid) {
+            log::warn!("dropping output for invocation {} sent by PID {:?}", output.id, pid);
+            return None;
+        }
+        if output.done {
+            self.pending.remove(index);
+        }
+        if output.text.is_empty() { None } else { Some(output.text) }
+    }
+
+    /// `(verb, help)` for each verb whose process is still running
+    pub fn list(&mut self) -> Vec<(&str, &str)> {
+        self.prune();
+        self.verbs.iter().map(|(verb, v)| (verb.as_str(), v.help.as_str())).collect()
+    }
+
+    /// Removes the verbs of processes that have exited. Their servers go away with them, so there is
+    /// nothing left to connect to.
+    fn prune(&mut self) {
+        let gone: Vec<String> = self
+            .verbs
+            .iter()
+            .filter(|(_, v)| xous::try_connect(v.listener).is_err())
+            .map(|(verb, _)| verb.clone())
+            .collect();
+        for verb in gone {
+            log::info!("{} went away with its process", verb);
+            self.remove(&verb);
+        }
+    }
+
+    fn remove(&mut self, verb: &str) {
+        if let Some(removed) = self.verbs.remove(verb) {
+            self.pending.retain(|(_, v)| v != verb);
+            // a process can register several verbs on the same server
+            if !self.verbs.values().any(|v| v.conn == removed.conn) {
+                unsafe { xous::disconnect(removed.conn).ok() };
+            }
+        }
+    }
+}
@@ -18,7 +18,7 @@ pub struct Echo {
 impl<'a> ShellCmdApi<'a> for Echo {
     cmd_api!(echo); // inserts boilerplate for command API
 
-    fn process(&mut self, args: String, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
+    fn process(&mut self, args: Vec<String>, _env: &mut CommonEnv) -> Result<Option<String>, xous::Error> {
         Ok(Some(rest))
     }
 }
@@ -43,6 +43,8 @@ use xous_ipc::Buffer;
 #[doc = include_str!("../README.md")]
 mod cmds;
 use cmds::*;
+mod external;
+mod script;
 
 #[cfg(not(feature = "no-codec"))]
 mod oqc_test;
@@ -156,10 +158,24 @@ impl Repl {
             .register_ux(UxRegistration {
                 app_name: String::from(gam::APP_NAME_SHELLCHAT),
                 ux_type: gam::UxType::Chat,
-                #[cfg(not(feature = "tts"))]
+                #[cfg(not(any(
+                    feature = "tts",
+                    feature = "ime-pinyin",
+                    feature = "ime-kana",
+                    feature = "ime-words"
+                )))]
                 predictor: Some(String::from(ime_plugin_shell::SERVER_NAME_IME_PLUGIN_SHELL)),
                 #[cfg(feature = "tts")]
                 predictor: Some(String::from(ime_plugin_tts::SERVER_NAME_IME_PLUGIN_TTS)),
+                #[cfg(all(feature = "ime-pinyin", not(feature = "tts")))]
+                predictor: Some(String::from(ime_plugin_pinyin::SERVER_NAME_IME_PLUGIN_PINYIN)),
+                #[cfg(all(feature = "ime-kana", not(any(feature = "tts", feature = "ime-pinyin"))))]
+                predictor: Some(String::from(ime_plugin_kana::SERVER_NAME_IME_PLUGIN_KANA)),
+                #[cfg(all(
+                    feature = "ime-words",
+                    not(any(feature = "tts", feature = "ime-pinyin", feature = "ime-kana"))
+                ))]
+                predictor: Some(String::from(ime_plugin_words::SERVER_NAME_IME_PLUGIN_WORDS)),
                 listener: sid.to_array(), /* note disclosure of our SID to the GAM -- the secret is now
                                            * shared with the GAM! */
                 redraw_id: ShellOpcode::Redraw.to_u32().unwrap(),
@@ -187,7 +203,7 @@ impl Repl {
             bubble_margin: Point::new(4, 4),
             bubble_radius: 4,
             bubble_space: 4,
-            env: CmdEnv::new(xns),
+            env: CmdEnv::new(xns, token.unwrap()),
             token: token.unwrap(),
             #[cfg(feature = "tts")]
             tts: TtsFrontend::new(xns).unwrap(),
@@ -203,6 +219,18 @@ impl Repl {
 
     fn msg(&mut self, message: MessageEnvelope) { self.msg = Some(message); }
 
+    /// show output that arrived on its own, from a verb implemented by another process
+    fn output(&mut self, text: String, init_done: bool) -> Result<(), xous::Error> {
+        #[cfg(feature = "tts")]
+        {
+            let mut output = t!("shellchat.output-tts", locales::LANG).to_string();
+            output.push_str(text.as_str());
+            self.tts.tts_simple(&output).unwrap();
+        }
+        self.circular_push(History { text, is_input: false });
+        self.redraw(init_done)
+    }
+
     fn circular_push(&mut self, item: History) {
         if self.history.len() >= self.history_len {
             self.history.remove(0);
@@ -368,22 +396,11 @@ impl Repl {
 
 ////////////////// local message passing from Ux Callback
 use num_traits::{FromPrimitive, ToPrimitive};
-
-#[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
-enum ShellOpcode {
-    /// a line of text has arrived
-    Line = 0, // make sure we occupy opcodes with discriminants < 1000, as the rest are used for callbacks
-    /// redraw our UI
-    Redraw,
-    /// change focus
-    ChangeFocus,
-    /// exit the application
-    Quit,
-}
+// the opcodes are shared with other processes, so they can register verbs
+use shellchat_api::Opcode as ShellOpcode;
+pub(crate) use shellchat_api::SERVER_NAME_SHELLCHAT;
+use shellchat_api::{VerbOutput, VerbRegistration};
 //////////////////
-
-// nothing prevents the two from being the same, other than naming conventions
-pub(crate) const SERVER_NAME_SHELLCHAT: &str = "_Shell chat application_"; // used internally by xous-names
 fn main() -> ! {
     #[cfg(not(any(feature = "ditherpunk", feature = "locktests")))]
     wrapped_main();
@@ -425,6 +442,13 @@ fn wrapped_main() -> ! {
                 xous::Message::new_scalar(ShellOpcode::Redraw.to_usize().unwrap(), 0, 0, 0, 0),
             )
             .ok();
+            // the boot script is typed in for the user, so its output shows up like any other command's
+            if pddb.is_mounted_nonblocking() && read_script(&pddb, BOOT_SCRIPT).is_some() {
+                let cmd = format!("run {}", BOOT_SCRIPT);
+                let buf = Buffer::into_buf(cmd).unwrap();
+                buf.send(main_conn, ShellOpcode::Line.to_u32().unwrap())
+                    .expect("couldn't run the boot script");
+            }
         }
     });
 
@@ -435,7 +459,7 @@ fn wrapped_main() -> ! {
         autobasis_launcher(shch_sid);
     }
     loop {
-        let msg = xous::receive_message(shch_sid).unwrap();
+        let mut msg = xous::receive_message(shch_sid).unwrap();
         let shell_op: Option<ShellOpcode> = FromPrimitive::from_usize(msg.body.id());
         log::debug!("Shellchat got message {:?}", msg);
         match shell_op {
@@ -473,6 +497,60 @@ fn wrapped_main() -> ! {
                 log::error!("got Quit");
                 break;
             }
+            Some(ShellOpcode::RegisterVerb) => {
+                let pid = msg.sender.pid();
+                let Some(mem) = msg.body.memory_message_mut() else {
+                    log::warn!("RegisterVerb from {:?} was not a mutable lend", pid);
+                    continue;
+                };
+                let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+                let registration = match buffer.to_original::<VerbRegistration, _>() {
+                    Ok(mut registration) => {
+                        registration.accepted = repl.env.register_verb(&registration, pid);
+                        registration
+                    }
+                    Err(e) => {
+                        log::warn!("malformed RegisterVerb from {:?}: {:?}", pid, e);
+                        VerbRegistration {
+                            verb: String::new(),
+                            help: String::new(),
+                            listener: [0; 4],
+                            invoke_id: 0,
+                            accepted: false,
+                        }
+                    }
+                };
+                if buffer.replace(registration).is_err() {
+                    log::warn!("couldn't return the VerbRegistration to {:?}", pid);
+                }
+            }
+            Some(ShellOpcode::UnregisterVerb) => {
+                let Some(mem) = msg.body.memory_message() else {
+                    log::warn!("UnregisterVerb from {:?} was not a memory message", msg.sender.pid());
+                    continue;
+                };
+                let buffer = unsafe { Buffer::from_memory_message(mem) };
+                let Ok(verb) = buffer.as_flat::<String, _>() else {
+                    log::warn!("malformed UnregisterVerb from {:?}, ignoring", msg.sender.pid());
+                    continue;
+                };
+                repl.env.unregister_verb(verb.as_str(), msg.sender.pid());
+            }
+            Some(ShellOpcode::VerbOutput) => {
+                let Some(mem) = msg.body.memory_message() else {
+                    log::warn!("VerbOutput from {:?} was not a memory message", msg.sender.pid());
+                    continue;
+                };
+                let buffer = unsafe { Buffer::from_memory_message(mem) };
+                let Ok(output) = buffer.to_original::<VerbOutput, _>() else {
+                    log::warn!("malformed VerbOutput from {:?}, ignoring", msg.sender.pid());
+                    continue;
+                };
+                if let Some(text) = repl.env.verb_output(output, msg.sender.pid()) {
+                    repl.output(text, pddb_init_done.load(Ordering::SeqCst))
+                        .expect("REPL couldn't show output");
+                }
+            }
             _ => {
                 log::trace!("got unknown message, treating as callback");
                 repl.msg(msg);
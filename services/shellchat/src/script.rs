@@ -0,0 +1,444 @@
+//! Command line parsing and script execution.
+//!
+//! A line is split into words on spaces. Single quotes keep everything up to the next single quote
+//! as-is; double quotes keep spaces, `;`, `|` and `#`, but still expand variables; a backslash outside of
+//! single quotes keeps the next character as-is. `;` separates commands, which run one after the other.
+//! `|` passes the output of a command to the next one, as its last argument. A word starting with `#`
+//! starts a comment that runs to the end of the line.
+//!
+//! `$name` or `${name}` expands to a variable set with `set name value`. In a script, `$1` to `$9` are
+//! its arguments, `$0` is its name and `$*` is all of the arguments.
+//!
+//! This module only deals with text: the commands themselves, and the stored scripts, are provided by a
+//! `Shell`.
+
+use std::collections::BTreeMap;
+
+/// Most scripts that can be running inside one another, so a script that runs itself doesn't take the
+/// shell down with it
+pub const MAX_DEPTH: usize = 8;
+
+/// Verbs handled here rather than by the `Shell`
+pub const BUILTINS: [&str; 2] = ["set", "run"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Var(String),
+}
+
+/// A word, before its variables are expanded
+type Word = Vec<Part>;
+/// The verb and its arguments
+type Command = Vec<Word>;
+/// Commands joined by `|`
+type Pipeline = Vec<Command>;
+
+/// Runs commands for the `Engine`
+pub trait Shell {
+    type Error;
+    /// Runs `verb`. `args` are the command's words after the verb, one per argument.
+    fn command(&mut self, verb: &str, args: Vec<String>) -> Result<Option<String>, Self::Error>;
+    /// The text of the stored script `name`, if there is one
+    fn script(&mut self, name: &str) -> Option<String>;
+}
+
+#[derive(Default)]
+pub struct Engine {
+    vars: BTreeMap<String, String>,
+    /// the name and arguments of each script being run, innermost last
+    args: Vec<Vec<String>>,
+}
+
+impl Engine {
+    /// Runs one line typed by the user. Syntax errors are reported as the line's output; errors only come
+    /// from the `Shell`, and stop the line where they happen.
+    pub fn run_line<S: Shell>(&mut self, shell: &mut S, line: &str) -> Result<Option<String>, S::Error> {
+        match parse(line) {
+            Ok(pipelines) => self.run(shell, &pipelines),
+            Err(e) => Ok(Some(e)),
+        }
+    }
+
+    /// Runs the stored script named by `args[0]`, with the rest as its arguments. A syntax error stops the
+    /// script, and is reported with the line it is on.
+    pub fn run_script<S: Shell>(
+        &mut self,
+        shell: &mut S,
+        args: Vec<String>,
+    ) -> Result<Option<String>, S::Error> {
+        let name = match args.first() {
+            Some(name) => name.clone(),
+            None => return Ok(Some("run <script> [args...]".to_string())),
+        };
+        if self.args.len() >= MAX_DEPTH {
+            return Ok(Some(format!("{}: scripts nested too deeply", name)));
+        }
+        let text = match shell.script(&name) {
+            Some(text) => text,
+            None => return Ok(Some(format!("no script named {}", name))),
+        };
+        self.args.push(args);
+        let mut out = None;
+        let mut result = Ok(());
+        for (index, line) in text.lines().enumerate() {
+            match parse(line) {
+                Ok(pipelines) => match self.run(shell, &pipelines) {
+                    Ok(output) => append(&mut out, output),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                },
+                Err(e) => {
+                    append(&mut out, Some(format!("{}:{}: {}", name, index + 1, e)));
+                    break;
+                }
+            }
+        }
+        self.args.pop();
+        result.map(|_| out)
+    }
+
+    fn run<S: Shell>(&mut self, shell: &mut S, pipelines: &[Pipeline]) -> Result<Option<String>, S::Error> {
+        let mut out = None;
+        for pipeline in pipelines {
+            let mut piped: Option<String> = None;
+            for command in pipeline {
+                let mut words: Vec<String> = command.iter().map(|word| self.expand(word)).collect();
+                words.extend(piped.take());
+                piped = self.command(shell, words)?;
+            }
+            append(&mut out, piped);
+        }
+        Ok(out)
+    }
+
+    fn command<S: Shell>(
+        &mut self,
+        shell: &mut S,
+        mut words: Vec<String>,
+    ) -> Result<Option<String>, S::Error> {
+        let verb = words.remove(0);
+        match verb.as_str() {
+            "set" => Ok(self.set(words)),
+            "run" => self.run_script(shell, words),
+            _ => shell.command(&verb, words),
+        }
+    }
+
+    /// `set` lists the variables, `set name` clears one, and `set name value...` sets it
+    fn set(&mut self, words: Vec<String>) -> Option<String> {
+        let mut words = words.into_iter();
+        let name = match words.next() {
+            Some(name) => name,
+            None => {
+                let list: Vec<String> =
+                    self.vars.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                return if list.is_empty() { None } else { Some(list.join("\n")) };
+            }
+        };
+        if !is_name(&name) {
+            return Some(format!("`{}` is not a variable name", name));
+        }
+        let value: Vec<String> = words.collect();
+        if value.is_empty() {
+            self.vars.remove(&name);
+        } else {
+            self.vars.insert(name, value.join(" "));
+        }
+        None
+    }
+
+    fn expand(&self, word: &Word) -> String {
+        let mut expanded = String::new();
+        for part in word {
+            match part {
+                Part::Text(text) => expanded.push_str(text),
+                Part::Var(name) => expanded.push_str(&self.var(name)),
+            }
+        }
+        expanded
+    }
+
+    fn var(&self, name: &str) -> String {
+        let args: &[String] = self.args.last().map(|args| args.as_slice()).unwrap_or(&[]);
+        if name == "*" {
+            args.get(1..).unwrap_or(&[]).join(" ")
+        } else if let Ok(index) = name.parse::<usize>() {
+            args.get(index).cloned().unwrap_or_default()
+        } else {
+            self.vars.get(name).cloned().unwrap_or_default()
+        }
+    }
+}
+
+fn append(out: &mut Option<String>, output: Option<String>) {
+    match (out.as_mut(), output) {
+        (Some(out), Some(output)) => {
+            out.push('\n');
+            out.push_str(&output);
+        }
+        (None, Some(output)) => *out = Some(output),
+        (_, None) => {}
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Collects the words of a line as it is parsed
+#[derive(Default)]
+struct Parser {
+    pipelines: Vec<Pipeline>,
+    pipeline: Pipeline,
+    command: Command,
+    word: Word,
+    /// set once the current word has started, so `""` is an empty word rather than no word at all
+    in_word: bool,
+}
+
+impl Parser {
+    fn push_char(&mut self, c: char) {
+        self.in_word = true;
+        match self.word.last_mut() {
+            Some(Part::Text(text)) => text.push(c),
+            _ => self.word.push(Part::Text(c.to_string())),
+        }
+    }
+
+    fn push_var(&mut self, name: String) {
+        self.in_word = true;
+        self.word.push(Part::Var(name));
+    }
+
+    fn end_word(&mut self) {
+        if self.in_word {
+            self.command.push(std::mem::take(&mut self.word));
+            self.in_word = false;
+        }
+    }
+
+    fn end_command(&mut self) -> Result<(), String> {
+        self.end_word();
+        if self.command.is_empty() {
+            return Err("missing command around `|`".to_string());
+        }
+        self.pipeline.push(std::mem::take(&mut self.command));
+        Ok(())
+    }
+
+    fn end_pipeline(&mut self) -> Result<(), String> {
+        self.end_word();
+        if self.command.is_empty() && self.pipeline.is_empty() {
+            // an empty statement, e.g. a blank line or `a;;b`
+            return Ok(());
+        }
+        self.end_command()?;
+        self.pipelines.push(std::mem::take(&mut self.pipeline));
+        Ok(())
+    }
+}
+
+fn parse(line: &str) -> Result<Vec<Pipeline>, String> {
+    let mut parser = Parser::default();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => parser.end_word(),
+            ';' => parser.end_pipeline()?,
+            '|' => parser.end_command()?,
+            '#' if !parser.in_word => break,
+            '\\' => match chars.next() {
+                Some(c) => parser.push_char(c),
+                None => return Err("nothing to escape after `\\`".to_string()),
+            },
+            '\'' => {
+                parser.in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => parser.push_char(c),
+                        None => return Err("missing closing `'`".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                parser.in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.peek(), Some('"' | '\\' | '$')) => {
+                            parser.push_char(chars.next().unwrap())
+                        }
+                        Some('$') => parse_var(&mut chars, &mut parser)?,
+                        Some(c) => parser.push_char(c),
+                        None => return Err("missing closing `\"`".to_string()),
+                    }
+                }
+            }
+            '$' => parse_var(&mut chars, &mut parser)?,
+            c => parser.push_char(c),
+        }
+    }
+    parser.end_pipeline()?;
+    Ok(parser.pipelines)
+}
+
+/// Parses what follows a `$`. A `$` that isn't followed by a variable name is kept as-is.
+fn parse_var(chars: &mut std::iter::Peekable<std::str::Chars>, parser: &mut Parser) -> Result<(), String> {
+    match chars.peek().copied() {
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err("missing closing `}`".to_string()),
+                }
+            }
+            if !is_name(&name) && name != "*" && name.parse::<usize>().is_err() {
+                return Err(format!("`{}` is not a variable name", name));
+            }
+            parser.push_var(name);
+        }
+        Some(c) if c.is_ascii_digit() || c == '*' => {
+            chars.next();
+            parser.push_var(c.to_string());
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            parser.push_var(name);
+        }
+        _ => parser.push_char('$'),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the commands it is asked to run, with their arguments separated by commas. `echo` returns
+    /// its arguments, and `fail` is an error.
+    #[derive(Default)]
+    struct TestShell {
+        ran: Vec<String>,
+        scripts: BTreeMap<String, String>,
+    }
+
+    impl Shell for TestShell {
+        type Error = String;
+
+        fn command(&mut self, verb: &str, args: Vec<String>) -> Result<Option<String>, String> {
+            self.ran.push(format!("{}({})", verb, args.join(",")));
+            match verb {
+                "echo" => Ok(Some(args.join(" "))),
+                "fail" => Err(args.join(" ")),
+                _ => Ok(None),
+            }
+        }
+
+        fn script(&mut self, name: &str) -> Option<String> { self.scripts.get(name).cloned() }
+    }
+
+    fn run(engine: &mut Engine, shell: &mut TestShell, line: &str) -> Option<String> {
+        engine.run_line(shell, line).unwrap()
+    }
+
+    #[test]
+    fn quoting() {
+        let mut engine = Engine::default();
+        let mut shell = TestShell::default();
+        assert_eq!(run(&mut engine, &mut shell, "echo  a   b"), Some("a b".to_string()));
+        assert_eq!(run(&mut engine, &mut shell, "echo \"a  ;b\" 'c | d'"), Some("a  ;b c | d".to_string()));
+        assert_eq!(
+            run(&mut engine, &mut shell, r#"echo a\ \;b "\"\$" '\n'"#),
+            Some(r#"a ;b "$ \n"#.to_string())
+        );
+        assert_eq!(run(&mut engine, &mut shell, "echo a#b # the rest is ignored"), Some("a#b".to_string()));
+        assert_eq!(run(&mut engine, &mut shell, "echo \"\" x"), Some(" x".to_string()));
+        assert_eq!(run(&mut engine, &mut shell, "  "), None);
+        assert_eq!(
+            shell.ran,
+            ["echo(a,b)", "echo(a  ;b,c | d)", r#"echo(a ;b,"$,\n)"#, "echo(a#b)", "echo(,x)"]
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let mut engine = Engine::default();
+        let mut shell = TestShell::default();
+        for line in ["echo 'a", "echo \"a", "echo a\\", "echo | | echo", "echo |", "echo ${a", "echo ${a b}"]
+        {
+            assert!(run(&mut engine, &mut shell, line).is_some(), "{}", line);
+        }
+        assert!(shell.ran.is_empty());
+    }
+
+    #[test]
+    fn sequences_and_pipes() {
+        let mut engine = Engine::default();
+        let mut shell = TestShell::default();
+        assert_eq!(
+            run(&mut engine, &mut shell, "echo a; quiet; echo b;; echo c"),
+            Some("a\nb\nc".to_string())
+        );
+        assert_eq!(run(&mut engine, &mut shell, "echo a | echo b | quiet x | echo c"), Some("c".to_string()));
+        assert_eq!(shell.ran[4..], ["echo(a)", "echo(b,a)", "quiet(x,b a)", "echo(c)"]);
+        assert_eq!(engine.run_line(&mut shell, "echo a; fail oops; echo b"), Err("oops".to_string()));
+        assert_eq!(shell.ran.last().unwrap(), "fail(oops)");
+    }
+
+    #[test]
+    fn variables() {
+        let mut engine = Engine::default();
+        let mut shell = TestShell::default();
+        assert_eq!(run(&mut engine, &mut shell, "set host  example.com  80; set x"), None);
+        assert_eq!(
+            run(&mut engine, &mut shell, "echo $host:${host}x '$host' \"$host\" $nothing $ 5$"),
+            Some("example.com 80:example.com 80x $host example.com 80  $ 5$".to_string())
+        );
+        assert_eq!(run(&mut engine, &mut shell, "set"), Some("host=example.com 80".to_string()));
+        assert_eq!(run(&mut engine, &mut shell, "set host; set"), None);
+        assert!(run(&mut engine, &mut shell, "set 1x y").unwrap().contains("not a variable"));
+        // variables are expanded when their command runs, not when the line is read
+        assert_eq!(run(&mut engine, &mut shell, "set a 1; echo $a"), Some("1".to_string()));
+    }
+
+    #[test]
+    fn scripts() {
+        let mut engine = Engine::default();
+        let mut shell = TestShell::default();
+        shell.scripts.insert("greet".to_string(), "# says hello\necho hello $1\n\necho $0: $*".to_string());
+        shell.scripts.insert("outer".to_string(), "set depth outer\nrun greet \"$1 $2\" two".to_string());
+        shell.scripts.insert("broken".to_string(), "echo ok\necho 'oops\necho never".to_string());
+        shell.scripts.insert("forever".to_string(), "run forever".to_string());
+        assert_eq!(
+            run(&mut engine, &mut shell, "run greet world"),
+            Some("hello world\ngreet: world".to_string())
+        );
+        assert_eq!(
+            run(&mut engine, &mut shell, "run outer a b; echo $depth [$1]"),
+            Some("hello a b\ngreet: a b two\nouter []".to_string())
+        );
+        assert_eq!(
+            run(&mut engine, &mut shell, "run broken"),
+            Some("ok\nbroken:2: missing closing `'`".to_string())
+        );
+        assert!(run(&mut engine, &mut shell, "run forever").unwrap().contains("nested too deeply"));
+        assert_eq!(run(&mut engine, &mut shell, "run missing"), Some("no script named missing".to_string()));
+        assert!(engine.args.is_empty());
+    }
+}
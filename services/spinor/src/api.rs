@@ -7,6 +7,10 @@ pub const SPINOR_ERASE_SIZE: u32 = 0x1000; // this is the smallest sector size.
 // note: logical lengths of regions are in xous::definitions
 #[allow(dead_code)]
 pub const SPINOR_BULK_ERASE_SIZE: u32 = 0x1_0000; // this is the bulk erase size.
+/// Anti-rollback counter page, checked by the loader on boot. It is the last page of the early settings
+/// sector; only the SoC token holder (`root-keys`) may write to it.
+pub const ANTIROLLBACK_LOC: u32 = 0x0097_F000;
+pub const ANTIROLLBACK_LEN: u32 = SPINOR_ERASE_SIZE;
 
 #[derive(num_derive::FromPrimitive, num_derive::ToPrimitive, Debug)]
 pub(crate) enum Opcode {
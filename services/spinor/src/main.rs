@@ -945,6 +945,15 @@ fn main() -> ! {
                             authorized = false;
                         }
                     }
+                    // the anti-rollback counter can only be advanced by the SoC token holder, otherwise
+                    // anyone could lock out the running firmware, or re-enable a downgrade
+                    if (wr.start < ANTIROLLBACK_LOC + ANTIROLLBACK_LEN)
+                        && (wr.start + wr.len > ANTIROLLBACK_LOC)
+                        && st != wr.id
+                    {
+                        wr.result = Some(SpinorError::AccessDenied);
+                        authorized = false;
+                    }
                 } else {
                     // the soc token MUST be initialized early on, if not, something bad has happened.
                     wr.result = Some(SpinorError::AccessDenied);
@@ -4,11 +4,16 @@ use num_traits::*;
 
 use crate::{StatusOpcode, app_autogen};
 
-pub fn create_app_menu(status_conn: xous::CID) {
+pub fn create_app_menu(menu_management_sid: xous::SID, status_conn: xous::CID) -> MenuMatic {
+    menu_matic(app_menu_items(status_conn), gam::APP_MENU_NAME, Some(menu_management_sid)).unwrap()
+}
+
+/// The items of the app menu, in the current language
+pub fn app_menu_items(status_conn: xous::CID) -> Vec<MenuItem> {
     let mut menu_items = Vec::<MenuItem>::new();
 
     menu_items.push(MenuItem {
-        name: String::from(t!("appmenu.shellchat", locales::LANG)),
+        name: String::from(t!("appmenu.shellchat", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::SwitchToShellchat.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -19,11 +24,11 @@ pub fn create_app_menu(status_conn: xous::CID) {
     app_autogen::app_menu_items(&mut menu_items, status_conn);
 
     menu_items.push(MenuItem {
-        name: String::from(t!("mainmenu.closemenu", locales::LANG)),
+        name: String::from(t!("mainmenu.closemenu", locales::lang())),
         action_conn: None,
         action_opcode: 0,
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
         close_on_select: true,
     });
-    menu_matic(menu_items, gam::APP_MENU_NAME, None);
+    menu_items
 }
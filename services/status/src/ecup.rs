@@ -115,7 +115,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                 if !validate_package(package, PackageType::Ec) {
                     log::error!("firmware package did not pass validation");
                     modals
-                        .show_notification(&format!("{} gateware", t!("ecup.invalid", locales::LANG)), None)
+                        .show_notification(&format!("{} gateware", t!("ecup.invalid", locales::lang())), None)
                         .unwrap();
                 } else {
                     log::info!("updating GW");
@@ -150,7 +150,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                 if !validate_package(package, PackageType::Ec) {
                     log::error!("firmware package did not pass validation");
                     modals
-                        .show_notification(&format!("{} firmware", t!("ecup.invalid", locales::LANG)), None)
+                        .show_notification(&format!("{} firmware", t!("ecup.invalid", locales::lang())), None)
                         .unwrap();
                 } else {
                     let length = u32::from_le_bytes(package[0x28..0x2c].try_into().unwrap());
@@ -211,7 +211,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                 } else {
                     log::error!("wf200 package did not pass validation");
                     modals
-                        .show_notification(&format!("{} WF200", t!("ecup.invalid", locales::LANG)), None)
+                        .show_notification(&format!("{} WF200", t!("ecup.invalid", locales::lang())), None)
                         .unwrap();
                     xous::return_scalar(msg.sender, UpdateResult::PackageInvalid.to_usize().unwrap())
                         .unwrap();
@@ -224,10 +224,10 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                 let ec_rev = com.get_ec_sw_tag().unwrap(); // fetch the purported rev from the EC. We take it at face value.
                 if ec_rev < net::MIN_EC_REV {
                     log::warn!("EC firmware is too old to interoperate with the connection manager.");
-                    let mut note = String::from(t!("net.ec_rev_old", locales::LANG));
+                    let mut note = String::from(t!("net.ec_rev_old", locales::lang()));
                     note.push_str(&format!(
                         "\n\n{}{}",
-                        t!("net.ec_current_rev", locales::LANG),
+                        t!("net.ec_current_rev", locales::lang()),
                         ec_rev.to_string()
                     ));
                     modals.show_notification(&note, None).unwrap();
@@ -298,7 +298,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                     if force {
                         modals
                             .show_notification(
-                                &format!("{} gateware", t!("ecup.invalid", locales::LANG)),
+                                &format!("{} gateware", t!("ecup.invalid", locales::lang())),
                                 None,
                             )
                             .unwrap();
@@ -392,7 +392,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                         log::error!("firmware package did not pass validation");
                         modals
                             .show_notification(
-                                &format!("{} gateware", t!("ecup.invalid", locales::LANG)),
+                                &format!("{} gateware", t!("ecup.invalid", locales::lang())),
                                 None,
                             )
                             .unwrap();
@@ -474,7 +474,10 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                     } else {
                         log::error!("wf200 package did not pass validation");
                         modals
-                            .show_notification(&format!("{} WF200", t!("ecup.invalid", locales::LANG)), None)
+                            .show_notification(
+                                &format!("{} WF200", t!("ecup.invalid", locales::lang())),
+                                None,
+                            )
                             .unwrap();
                         xous::return_scalar(msg.sender, UpdateResult::PackageInvalid.to_usize().unwrap())
                             .unwrap();
@@ -483,7 +486,7 @@ pub(crate) fn ecupdate_thread(sid: xous::SID) {
                 }
 
                 if did_something {
-                    modals.dynamic_notification(Some(t!("ecup.resetting", locales::LANG)), None).unwrap();
+                    modals.dynamic_notification(Some(t!("ecup.resetting", locales::lang())), None).unwrap();
                     log::info!("EC firmware had an update");
                     ticktimer.sleep_ms(500).unwrap(); // paranoia wait
                     llio.ec_reset().unwrap(); // firmware should reload
@@ -529,7 +532,7 @@ fn do_update(
     // grab an uptime measurement from the EC
     let ut = com.get_ec_uptime().unwrap();
     // pop up a dialog box to warn users, in case they are in the process of resetting the device
-    modals.dynamic_notification(Some(&format!("{}", t!("ecup.preparing", locales::LANG))), None).unwrap();
+    modals.dynamic_notification(Some(&format!("{}", t!("ecup.preparing", locales::lang()))), None).unwrap();
     tt.sleep_ms(3000).ok();
     // check the uptime again as a very basic link-up check
     // (usually the link is either stuck at 0, 0xffff, or 0xdddd if the EC is misbehaving)
@@ -546,7 +549,7 @@ fn do_update(
     // erase
     modals
         .dynamic_notification_update(
-            Some(&format!("{}\n({})", t!("ecup.erasing", locales::LANG), name)),
+            Some(&format!("{}\n({})", t!("ecup.erasing", locales::lang()), name)),
             None,
         )
         .unwrap();
@@ -555,7 +558,9 @@ fn do_update(
         modals.dynamic_notification_close().unwrap();
     } else {
         modals.dynamic_notification_close().unwrap();
-        modals.show_notification(&format!("{}\n({})", t!("ecup.abort", locales::LANG), name), None).unwrap();
+        modals
+            .show_notification(&format!("{}\n({})", t!("ecup.abort", locales::lang()), name), None)
+            .unwrap();
         return false;
     }
     xous::yield_slice();
@@ -564,7 +569,7 @@ fn do_update(
     log::info!("init progress: {:x}->{:x}", pkg_offset, pkg_offset + image_len);
     modals
         .start_progress(
-            &format!("{} {}...", t!("ecup.writing", locales::LANG), name),
+            &format!("{} {}...", t!("ecup.writing", locales::lang()), name),
             flash_start,
             flash_start + image_len,
             flash_start,
@@ -590,7 +595,7 @@ fn do_update(
         if com.flash_program(prog_addr, pages).unwrap() == false {
             modals.finish_progress().unwrap();
             modals
-                .show_notification(&format!("{} {}...", t!("ecup.abort", locales::LANG), name), None)
+                .show_notification(&format!("{} {}...", t!("ecup.abort", locales::lang()), name), None)
                 .unwrap();
             return false;
         }
@@ -638,7 +643,7 @@ fn do_update(
         if com.flash_program(prog_addr, pages).unwrap() == false {
             modals.finish_progress().unwrap();
             modals
-                .show_notification(&format!("{}\n({})", t!("ecup.abort", locales::LANG), name), None)
+                .show_notification(&format!("{}\n({})", t!("ecup.abort", locales::lang()), name), None)
                 .unwrap();
             return false;
         }
@@ -8,13 +8,19 @@ use root_keys::RootKeys;
 
 use crate::StatusOpcode;
 
-#[allow(unused_variables)] // quiets a warning about unused com that is emitted in tts config. Would be nice to make this more targeted...
 pub fn create_main_menu(
     keys: Arc<Mutex<RootKeys>>,
     menu_management_sid: xous::SID,
     status_conn: xous::CID,
     com: &com::Com,
 ) -> MenuMatic {
+    let menuitems = main_menu_items(&keys, status_conn, com);
+    menu_matic(menuitems, MAIN_MENU_NAME, Some(menu_management_sid)).unwrap()
+}
+
+/// The items of the main menu, in the current language
+#[allow(unused_variables)] // quiets a warning about unused com that is emitted in tts config. Would be nice to make this more targeted...
+pub fn main_menu_items(keys: &Arc<Mutex<RootKeys>>, status_conn: xous::CID, com: &com::Com) -> Vec<MenuItem> {
     let key_conn = keys.lock().unwrap().conn();
 
     let mut menuitems = Vec::<MenuItem>::new();
@@ -23,7 +29,7 @@ pub fn create_main_menu(
     /*
     #[cfg(not(feature="tts"))]
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.backlighton", locales::LANG)),
+        name: String::from(t!("mainmenu.backlighton", locales::lang())),
         action_conn: Some(com.conn()),
         action_opcode: com.getop_backlight(),
         action_payload: MenuPayload::Scalar([191 >> 3, 191 >> 3, 0, 0]),
@@ -32,7 +38,7 @@ pub fn create_main_menu(
 
     #[cfg(not(feature="tts"))]
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.backlightoff", locales::LANG)),
+        name: String::from(t!("mainmenu.backlightoff", locales::lang())),
         action_conn: Some(com.conn()),
         action_opcode: com.getop_backlight(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -40,7 +46,7 @@ pub fn create_main_menu(
     }); */
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.sleep", locales::LANG)),
+        name: String::from(t!("mainmenu.sleep", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::TrySuspend.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -48,7 +54,7 @@ pub fn create_main_menu(
     });
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.app", locales::LANG)),
+        name: String::from(t!("mainmenu.app", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::SubmenuApp.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -56,7 +62,7 @@ pub fn create_main_menu(
     });
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.preferences", locales::LANG)),
+        name: String::from(t!("mainmenu.preferences", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::Preferences.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -66,14 +72,14 @@ pub fn create_main_menu(
     let key_init = keys.lock().unwrap().is_initialized().unwrap();
     if !key_init {
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.init_keys", locales::LANG)),
+            name: String::from(t!("mainmenu.init_keys", locales::lang())),
             action_conn: Some(key_conn),
             action_opcode: keys.lock().unwrap().get_try_init_keys_op(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
             close_on_select: true,
         });
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.provision_gateware", locales::LANG)),
+            name: String::from(t!("mainmenu.provision_gateware", locales::lang())),
             action_conn: Some(key_conn),
             // note this is using the blind copy opcode -- makes a copy without installing keys
             action_opcode: keys.lock().unwrap().get_blind_copy_gateware_op(),
@@ -82,7 +88,7 @@ pub fn create_main_menu(
         });
 
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.force_ecup", locales::LANG)),
+            name: String::from(t!("mainmenu.force_ecup", locales::lang())),
             action_conn: Some(status_conn),
             action_opcode: StatusOpcode::ForceEcUpdate.to_u32().unwrap(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -90,7 +96,7 @@ pub fn create_main_menu(
         });
     } else {
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.provision_gateware", locales::LANG)),
+            name: String::from(t!("mainmenu.provision_gateware", locales::lang())),
             action_conn: Some(key_conn),
             // note this is using the update opcode -- makes a copy while installing keys
             action_opcode: keys.lock().unwrap().get_update_gateware_op(),
@@ -99,7 +105,7 @@ pub fn create_main_menu(
         });
 
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.force_ecup", locales::LANG)),
+            name: String::from(t!("mainmenu.force_ecup", locales::lang())),
             action_conn: Some(status_conn),
             action_opcode: StatusOpcode::ForceEcUpdate.to_u32().unwrap(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -107,7 +113,7 @@ pub fn create_main_menu(
         });
 
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.selfsign", locales::LANG)),
+            name: String::from(t!("mainmenu.selfsign", locales::lang())),
             action_conn: Some(key_conn),
             action_opcode: keys.lock().unwrap().get_try_selfsign_op(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -116,7 +122,7 @@ pub fn create_main_menu(
     }
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.pddb", locales::LANG)),
+        name: String::from(t!("mainmenu.pddb", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::SubmenuPddb.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -126,7 +132,7 @@ pub fn create_main_menu(
     #[cfg(feature = "efuse")]
     if keys.lock().unwrap().is_zero_key().unwrap() == Some(true) {
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.backup_key", locales::LANG)),
+            name: String::from(t!("mainmenu.backup_key", locales::lang())),
             action_conn: Some(status_conn),
             action_opcode: StatusOpcode::BurnBackupKey.to_u32().unwrap(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -136,7 +142,7 @@ pub fn create_main_menu(
 
     if key_init {
         menuitems.push(MenuItem {
-            name: String::from(t!("mainmenu.prep_backup", locales::LANG)),
+            name: String::from(t!("mainmenu.prep_backup", locales::lang())),
             action_conn: Some(status_conn),
             action_opcode: StatusOpcode::PrepareBackup.to_u32().unwrap(),
             action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -145,7 +151,7 @@ pub fn create_main_menu(
     }
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.lockdevice", locales::LANG)),
+        name: String::from(t!("mainmenu.lockdevice", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::Reboot.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
@@ -153,19 +159,40 @@ pub fn create_main_menu(
     });
 
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.battery_disconnect", locales::LANG)),
+        name: String::from(t!("mainmenu.battery_disconnect", locales::lang())),
         action_conn: Some(status_conn),
         action_opcode: StatusOpcode::BatteryDisconnect.to_u32().unwrap(),
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
         close_on_select: true,
     });
     menuitems.push(MenuItem {
-        name: String::from(t!("mainmenu.closemenu", locales::LANG)),
+        name: String::from(t!("mainmenu.closemenu", locales::lang())),
         action_conn: None,
         action_opcode: 0,
         action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
         close_on_select: true,
     });
 
-    menu_matic(menuitems, MAIN_MENU_NAME, Some(menu_management_sid)).unwrap()
+    menuitems
+}
+
+/// The items that control the backlight by hand, shown at the top of the main menu when the automatic
+/// backlight is off
+pub fn backlight_items(com: &com::Com) -> [MenuItem; 2] {
+    [
+        MenuItem {
+            name: String::from(t!("mainmenu.backlighton", locales::lang())),
+            action_conn: Some(com.conn()),
+            action_opcode: com.getop_backlight(),
+            action_payload: MenuPayload::Scalar([191 >> 3, 191 >> 3, 0, 0]),
+            close_on_select: true,
+        },
+        MenuItem {
+            name: String::from(t!("mainmenu.backlightoff", locales::lang())),
+            action_conn: Some(com.conn()),
+            action_opcode: com.getop_backlight(),
+            action_payload: MenuPayload::Scalar([0, 0, 0, 0]),
+            close_on_select: true,
+        },
+    ]
 }
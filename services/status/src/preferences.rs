@@ -1,4 +1,5 @@
 use std::fmt::Display;
+use std::io::Read;
 
 use locales::t;
 use num_traits::*;
@@ -11,6 +12,9 @@ pub trait PrefHandler {
     fn handle(&mut self, op: usize) -> bool;
 
     fn claim_menumatic_menu(&mut self, cid: xous::CID);
+
+    /// Rebuilds the menu claimed by `claim_menumatic_menu()` in the current language
+    fn relabel_menu(&mut self);
 }
 
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, PartialEq, PartialOrd)]
@@ -30,10 +34,13 @@ enum DevicePrefsOp {
     AudioOff,
     HeadsetVolume,
     EarpieceVolume,
+    Language,
+    ForgetLearnedWords,
 
     // Those are reserved for internal use
     UpdateMenuAudioEnabled = 399,
     UpdateMenuAudioDisabled,
+    UpdateMenuLanguage,
 }
 
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive, PartialEq, PartialOrd)]
@@ -41,25 +48,33 @@ pub enum PrefsMenuUpdateOp {
     // Those are reserved for internal use
     UpdateMenuAudioEnabled = 399,
     UpdateMenuAudioDisabled,
+    /// Sent by the status thread once it has switched languages; handled for all menus at once
+    UpdateMenuLanguage,
 }
 
 impl Display for DevicePrefsOp {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            Self::AutobacklightOnBoot => write!(f, "{}", t!("prefs.autobacklight_enable", locales::LANG)),
-            Self::AutobacklightTimeout => write!(f, "{}", t!("prefs.autobacklight_duration", locales::LANG)),
-            Self::AutoSleepTimeout => write!(f, "{}", t!("prefs.autosleep_duration", locales::LANG)),
-            Self::RebootOnAutoSleep => write!(f, "{}", t!("prefs.autosleep_reboot", locales::LANG)),
-            Self::ConnectKnownNetworksOnBoot => write!(f, "{}", t!("prefs.wifi_connect_auto", locales::LANG)),
-            Self::WifiKill => write!(f, "{}", t!("prefs.wifi_kill", locales::LANG)),
-            Self::KeyboardLayout => write!(f, "{}", t!("prefs.keyboard_layout", locales::LANG)),
-            Self::WLANMenu => write!(f, "{}", t!("prefs.wifi_setting", locales::LANG)),
-            Self::SetTime => write!(f, "{}", t!("mainmenu.set_rtc", locales::LANG)),
-            Self::SetTimezone => write!(f, "{}", t!("mainmenu.set_tz", locales::LANG)),
-            Self::AudioOn => write!(f, "{}", t!("prefs.enable_audio", locales::LANG)),
-            Self::AudioOff => write!(f, "{}", t!("prefs.disable_audio", locales::LANG)),
-            Self::HeadsetVolume => write!(f, "{}", t!("prefs.headphone_volume", locales::LANG)),
-            Self::EarpieceVolume => write!(f, "{}", t!("prefs.speaker_volume", locales::LANG)),
+            Self::AutobacklightOnBoot => write!(f, "{}", t!("prefs.autobacklight_enable", locales::lang())),
+            Self::AutobacklightTimeout => {
+                write!(f, "{}", t!("prefs.autobacklight_duration", locales::lang()))
+            }
+            Self::AutoSleepTimeout => write!(f, "{}", t!("prefs.autosleep_duration", locales::lang())),
+            Self::RebootOnAutoSleep => write!(f, "{}", t!("prefs.autosleep_reboot", locales::lang())),
+            Self::ConnectKnownNetworksOnBoot => {
+                write!(f, "{}", t!("prefs.wifi_connect_auto", locales::lang()))
+            }
+            Self::WifiKill => write!(f, "{}", t!("prefs.wifi_kill", locales::lang())),
+            Self::KeyboardLayout => write!(f, "{}", t!("prefs.keyboard_layout", locales::lang())),
+            Self::WLANMenu => write!(f, "{}", t!("prefs.wifi_setting", locales::lang())),
+            Self::SetTime => write!(f, "{}", t!("mainmenu.set_rtc", locales::lang())),
+            Self::SetTimezone => write!(f, "{}", t!("mainmenu.set_tz", locales::lang())),
+            Self::AudioOn => write!(f, "{}", t!("prefs.enable_audio", locales::lang())),
+            Self::AudioOff => write!(f, "{}", t!("prefs.disable_audio", locales::lang())),
+            Self::HeadsetVolume => write!(f, "{}", t!("prefs.headphone_volume", locales::lang())),
+            Self::EarpieceVolume => write!(f, "{}", t!("prefs.speaker_volume", locales::lang())),
+            Self::Language => write!(f, "{}", t!("prefs.language", locales::lang())),
+            Self::ForgetLearnedWords => write!(f, "{}", t!("prefs.forget_words", locales::lang())),
 
             _ => unimplemented!("should not end up here!"),
         }
@@ -115,6 +130,7 @@ impl PrefHandler for DevicePrefs {
                 match other {
                     PrefsMenuUpdateOp::UpdateMenuAudioEnabled => self.alter_menu_audio_off(),
                     PrefsMenuUpdateOp::UpdateMenuAudioDisabled => self.alter_menu_audio_on(),
+                    PrefsMenuUpdateOp::UpdateMenuLanguage => self.relabel_menu(),
                 }
                 true
             }
@@ -150,28 +166,16 @@ impl PrefHandler for DevicePrefs {
 
     fn claim_menumatic_menu(&mut self, cid: xous::CID) {
         // TODO(gsora): we have to specify and handle a manager here, because of the audio on/off thing.
-        let mut menus = self
-            .actions()
-            .iter()
-            .map(|action| gam::MenuItem {
-                name: String::from(&action.to_string()),
-                action_conn: Some(cid),
-                action_opcode: action.to_u32().unwrap(),
-                action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
-                close_on_select: true,
-            })
-            .collect::<Vec<gam::MenuItem>>();
-
-        menus.push(gam::MenuItem {
-            name: String::from(t!("mainmenu.closemenu", locales::LANG)),
-            action_conn: None,
-            action_opcode: 0,
-            action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
-            close_on_select: true,
-        });
-
+        let menus = self.menu_items(cid);
         self.menu = gam::menu_matic(menus, gam::PREFERENCES_MENU_NAME, Some(self.menu_manager_sid));
     }
+
+    fn relabel_menu(&mut self) {
+        let menus = self.menu_items(self.menu_global_conn);
+        if let Some(menu) = self.menu.as_ref() {
+            menu.set_items(menus);
+        }
+    }
 }
 
 impl DevicePrefs {
@@ -199,6 +203,29 @@ impl DevicePrefs {
         }
     }
 
+    fn menu_items(&mut self, cid: xous::CID) -> Vec<gam::MenuItem> {
+        let mut menus = self
+            .actions()
+            .iter()
+            .map(|action| gam::MenuItem {
+                name: String::from(&action.to_string()),
+                action_conn: Some(cid),
+                action_opcode: action.to_u32().unwrap(),
+                action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
+                close_on_select: true,
+            })
+            .collect::<Vec<gam::MenuItem>>();
+
+        menus.push(gam::MenuItem {
+            name: String::from(t!("mainmenu.closemenu", locales::lang())),
+            action_conn: None,
+            action_opcode: 0,
+            action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
+            close_on_select: true,
+        });
+        menus
+    }
+
     fn actions(&mut self) -> Vec<DevicePrefsOp> {
         use DevicePrefsOp::*;
 
@@ -211,6 +238,8 @@ impl DevicePrefs {
             AutoSleepTimeout,
             RebootOnAutoSleep,
             KeyboardLayout,
+            Language,
+            ForgetLearnedWords,
             // Note: this vec sets the order of items in the preferences menu
             // The CI system assumes that the time setting items are always at
             // the bottom of the preferences menu, in this particular order.
@@ -249,6 +278,8 @@ impl DevicePrefs {
             AutoSleepTimeout => self.autosleep_timeout(),
             RebootOnAutoSleep => self.reboot_on_autosleep(),
             KeyboardLayout => self.keyboard_layout(),
+            Language => self.language(),
+            ForgetLearnedWords => self.forget_learned_words(),
             WLANMenu => self.wlan_menu(),
             SetTime => self.set_time_menu(),
             SetTimezone => self.set_timezone_menu(),
@@ -269,7 +300,7 @@ impl DevicePrefs {
 
     fn show_error_modal(&self, e: DevicePrefsError) {
         self.modals
-            .show_notification(format!("{}: {}", t!("wlan.error", locales::LANG), e).as_str(), None)
+            .show_notification(format!("{}: {}", t!("wlan.error", locales::lang()), e).as_str(), None)
             .unwrap()
     }
 }
@@ -278,13 +309,15 @@ impl DevicePrefs {
     fn autobacklight_on_boot(&mut self) -> Result<(), DevicePrefsError> {
         let cv = self.up.autobacklight_on_boot_or_value(true)?;
 
-        self.modals.add_list(vec![t!("prefs.yes", locales::LANG), t!("prefs.no", locales::LANG)]).unwrap();
+        self.modals
+            .add_list(vec![t!("prefs.yes", locales::lang()), t!("prefs.no", locales::lang())])
+            .unwrap();
 
         let new_result = yes_no_to_bool(
             self.modals
                 .get_radiobutton(&format!(
                     "{} {}",
-                    t!("prefs.current_setting", locales::LANG),
+                    t!("prefs.current_setting", locales::lang()),
                     bool_to_yes_no(cv)
                 ))
                 .unwrap()
@@ -341,12 +374,12 @@ impl DevicePrefs {
 
         let raw_timeout = self
             .modals
-            .alert_builder(t!("prefs.autobacklight_duration_in_secs", locales::LANG))
+            .alert_builder(t!("prefs.autobacklight_duration_in_secs", locales::lang()))
             .field(
                 Some(cv.to_string()),
                 Some(|tf| match tf.as_str().parse::<u64>() {
                     Ok(_) => None,
-                    Err(_) => Some(String::from(t!("prefs.autobacklight_err", locales::LANG))),
+                    Err(_) => Some(String::from(t!("prefs.autobacklight_err", locales::lang()))),
                 }),
             )
             .build()
@@ -362,12 +395,12 @@ impl DevicePrefs {
 
         let raw_timeout = self
             .modals
-            .alert_builder(t!("prefs.autosleep_duration_in_mins", locales::LANG))
+            .alert_builder(t!("prefs.autosleep_duration_in_mins", locales::lang()))
             .field(
                 Some(cv.to_string()),
                 Some(|tf| match tf.as_str().parse::<u64>() {
                     Ok(_) => None,
-                    Err(_) => Some(String::from(t!("prefs.autobacklight_err", locales::LANG))),
+                    Err(_) => Some(String::from(t!("prefs.autobacklight_err", locales::lang()))),
                 }),
             )
             .build()
@@ -381,12 +414,14 @@ impl DevicePrefs {
     fn reboot_on_autosleep(&self) -> Result<(), DevicePrefsError> {
         let cv = self.up.reboot_on_autosleep_or_default()?;
 
-        self.modals.add_list(vec![t!("prefs.yes", locales::LANG), t!("prefs.no", locales::LANG)]).unwrap();
+        self.modals
+            .add_list(vec![t!("prefs.yes", locales::lang()), t!("prefs.no", locales::lang())])
+            .unwrap();
         let new_result = yes_no_to_bool(
             self.modals
                 .get_radiobutton(&format!(
                     "{} {}",
-                    t!("prefs.current_setting", locales::LANG),
+                    t!("prefs.current_setting", locales::lang()),
                     bool_to_yes_no(cv)
                 ))
                 .unwrap()
@@ -399,13 +434,15 @@ impl DevicePrefs {
     fn wifi_kill(&mut self) -> Result<(), DevicePrefsError> {
         let cv = self.up.wifi_kill_or_default()?;
 
-        self.modals.add_list(vec![t!("prefs.yes", locales::LANG), t!("prefs.no", locales::LANG)]).unwrap();
+        self.modals
+            .add_list(vec![t!("prefs.yes", locales::lang()), t!("prefs.no", locales::lang())])
+            .unwrap();
 
         let new_result = yes_no_to_bool(
             self.modals
                 .get_radiobutton(&format!(
                     "{} {}",
-                    t!("prefs.current_setting", locales::LANG),
+                    t!("prefs.current_setting", locales::lang()),
                     bool_to_yes_no(cv)
                 ))
                 .unwrap()
@@ -427,13 +464,15 @@ impl DevicePrefs {
     fn connect_known_networks_on_boot(&mut self) -> Result<(), DevicePrefsError> {
         let cv = self.up.connect_known_networks_on_boot_or_value(true)?;
 
-        self.modals.add_list(vec![t!("prefs.yes", locales::LANG), t!("prefs.no", locales::LANG)]).unwrap();
+        self.modals
+            .add_list(vec![t!("prefs.yes", locales::lang()), t!("prefs.no", locales::lang())])
+            .unwrap();
 
         let new_result = yes_no_to_bool(
             self.modals
                 .get_radiobutton(&format!(
                     "{} {}",
-                    t!("prefs.current_setting", locales::LANG),
+                    t!("prefs.current_setting", locales::lang()),
                     bool_to_yes_no(cv)
                 ))
                 .unwrap()
@@ -480,27 +519,107 @@ impl DevicePrefs {
     }
 
     fn keyboard_layout(&mut self) -> Result<(), DevicePrefsError> {
-        let kl: usize = self.kbd.get_keymap().unwrap().into();
+        let map = self.kbd.get_keymap().unwrap();
+        let current = match map {
+            keyboard::KeyMap::Custom => self.up.keyboard_layout_or_default()?,
+            _ => map.to_string(),
+        };
 
-        let mappings = vec!["QWERTY", "AZERTY", "QWERTZ", "Dvorak"];
+        let mut mappings =
+            vec!["QWERTY".to_string(), "AZERTY".to_string(), "QWERTZ".to_string(), "Dvorak".to_string()];
+        let builtin = mappings.len();
+        let pddb = pddb::Pddb::new();
+        for name in custom_layouts(&pddb) {
+            if !mappings.contains(&name) {
+                mappings.push(name);
+            }
+        }
 
-        self.modals.add_list(mappings.clone()).unwrap();
+        self.modals.add_list(mappings.iter().map(|name| name.as_str()).collect()).unwrap();
 
         let new_result = self
             .modals
-            .get_radiobutton(&format!(
-                "{} {}",
-                t!("prefs.current_setting", locales::LANG),
-                keyboard::KeyMap::from(kl)
-            ))
+            .get_radiobutton(&format!("{} {}", t!("prefs.current_setting", locales::lang()), current))
             .unwrap();
 
-        let new_result = match mappings.iter().position(|&elem| elem == new_result.as_str()) {
+        let new_result = match mappings.iter().position(|elem| *elem == new_result) {
             Some(val) => val,
             None => 0,
         };
 
-        self.kbd.set_keymap(keyboard::KeyMap::from(new_result)).unwrap();
+        if new_result < builtin {
+            self.kbd.set_keymap(keyboard::KeyMap::from(new_result)).unwrap();
+            self.up.set_keyboard_layout(String::new())?;
+            return Ok(());
+        }
+        let name = &mappings[new_result];
+        let text = read_layout(&pddb, name).unwrap_or_default();
+        match keyboard::Layout::parse(&text) {
+            Ok(_) => {
+                self.kbd.set_layout(&text)?;
+                self.up.set_keyboard_layout(name.to_string())?;
+                let base = self.kbd.get_base_keymap().unwrap();
+                self.modals
+                    .show_notification(
+                        &format!("{} {}", t!("prefs.keyboard_layout_premount", locales::lang()), base),
+                        None,
+                    )
+                    .unwrap();
+            }
+            Err(e) => {
+                self.modals
+                    .show_notification(
+                        &format!("{}\n{}", t!("prefs.keyboard_layout_invalid", locales::lang()), e),
+                        None,
+                    )
+                    .unwrap();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn language(&mut self) -> Result<(), DevicePrefsError> {
+        let names: Vec<&str> = locales::LANGUAGES.iter().map(|lang| language_name(lang)).collect();
+        self.modals.add_list(names.clone()).unwrap();
+
+        let new_result = self
+            .modals
+            .get_radiobutton(&format!(
+                "{} {}",
+                t!("prefs.current_setting", locales::lang()),
+                language_name(locales::lang())
+            ))
+            .unwrap();
+
+        if let Some(index) = names.iter().position(|&name| name == new_result.as_str()) {
+            self.up.set_language(locales::LANGUAGES[index].to_string())?;
+            // the status thread switches itself, the GAM and the modals, and then has the menus rebuilt
+            xous::send_message(
+                self.status_cid,
+                xous::Message::new_scalar(
+                    crate::StatusOpcode::SetLanguage.to_usize().unwrap(),
+                    index,
+                    0,
+                    0,
+                    0,
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn forget_learned_words(&mut self) -> Result<(), DevicePrefsError> {
+        self.modals
+            .add_list(vec![t!("prefs.yes", locales::lang()), t!("prefs.no", locales::lang())])
+            .unwrap();
+        let confirm = self.modals.get_radiobutton(t!("prefs.forget_words_confirm", locales::lang())).unwrap();
+        if yes_no_to_bool(&confirm) {
+            let xns = xous_names::XousNames::new().unwrap();
+            ime_plugin_words::forget_learned(&xns)?;
+            self.modals.show_notification(t!("prefs.forget_words_done", locales::lang()), None).unwrap();
+        }
 
         Ok(())
     }
@@ -519,7 +638,7 @@ impl DevicePrefs {
     fn alter_menu_audio_on(&mut self) {
         let menu = self.menu.as_ref().unwrap();
 
-        menu.delete_item(t!("mainmenu.closemenu", locales::LANG));
+        menu.delete_item(t!("mainmenu.closemenu", locales::lang()));
         menu.delete_item(&DevicePrefsOp::AudioOn.to_string());
         menu.add_item(gam::MenuItem {
             name: String::from(&DevicePrefsOp::AudioOff.to_string()),
@@ -544,7 +663,7 @@ impl DevicePrefs {
         });
 
         menu.add_item(gam::MenuItem {
-            name: String::from(t!("mainmenu.closemenu", locales::LANG)),
+            name: String::from(t!("mainmenu.closemenu", locales::lang())),
             action_conn: None,
             action_opcode: 0,
             action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
@@ -566,7 +685,7 @@ impl DevicePrefs {
     fn alter_menu_audio_off(&mut self) {
         let menu = self.menu.as_ref().unwrap();
         // hide volume toggles
-        menu.delete_item(t!("mainmenu.closemenu", locales::LANG));
+        menu.delete_item(t!("mainmenu.closemenu", locales::lang()));
         menu.delete_item(&DevicePrefsOp::AudioOff.to_string());
         menu.delete_item(&DevicePrefsOp::EarpieceVolume.to_string());
         menu.delete_item(&DevicePrefsOp::HeadsetVolume.to_string());
@@ -578,7 +697,7 @@ impl DevicePrefs {
             close_on_select: true,
         });
         menu.add_item(gam::MenuItem {
-            name: String::from(t!("mainmenu.closemenu", locales::LANG)),
+            name: String::from(t!("mainmenu.closemenu", locales::lang())),
             action_conn: None,
             action_opcode: 0,
             action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
@@ -615,7 +734,7 @@ impl DevicePrefs {
     #[cfg(not(feature = "no-codec"))]
     fn headset_volume(&mut self) -> Result<(), DevicePrefsError> {
         // headset -> headphone
-        let (db_val, slider_val) = self.volume_slider(t!("prefs.headphone_volume", locales::LANG), true)?;
+        let (db_val, slider_val) = self.volume_slider(t!("prefs.headphone_volume", locales::lang()), true)?;
         self.codec.set_headphone_volume(codec::VolumeOps::Set, Some(db_val as f32))?;
         self.up.set_headset_volume(slider_val)?;
 
@@ -625,7 +744,7 @@ impl DevicePrefs {
     #[cfg(not(feature = "no-codec"))]
     fn earpiece_volume(&mut self) -> Result<(), DevicePrefsError> {
         // earpiece -> speaker
-        let (db_val, slider_val) = self.volume_slider(t!("prefs.speaker_volume", locales::LANG), false)?;
+        let (db_val, slider_val) = self.volume_slider(t!("prefs.speaker_volume", locales::lang()), false)?;
         self.codec.set_speaker_volume(codec::VolumeOps::Set, Some(db_val as f32))?;
         self.up.set_earpiece_volume(slider_val)?;
         Ok(())
@@ -638,10 +757,37 @@ pub fn percentage_to_db(value: u32) -> i32 {
     (negated_val as i32 * -80) / 100
 }
 
+/// What a language is called in the language selection, which is in the language itself so it can be
+/// found no matter which one is current
+fn language_name(lang: &str) -> &str {
+    match lang {
+        "en" => "English",
+        "en-tts" => "English (text to speech)",
+        "fr" => "Français",
+        "ja" => "日本語",
+        "zh" => "中文",
+        other => other,
+    }
+}
+
+/// The user's keyboard layouts, by the name of their key in `keyboard::LAYOUT_DICT`
+fn custom_layouts(pddb: &pddb::Pddb) -> Vec<String> {
+    let mut names = pddb.list_keys(keyboard::LAYOUT_DICT, None).unwrap_or_default();
+    names.sort();
+    names
+}
+
+pub(crate) fn read_layout(pddb: &pddb::Pddb, name: &str) -> Option<String> {
+    let mut key = pddb.get(keyboard::LAYOUT_DICT, name, None, false, false, None, None::<fn()>).ok()?;
+    let mut text = String::new();
+    key.read_to_string(&mut text).ok()?;
+    Some(text)
+}
+
 fn yes_no_to_bool(val: &str) -> bool {
-    if val == t!("prefs.yes", locales::LANG) {
+    if val == t!("prefs.yes", locales::lang()) {
         true
-    } else if val == t!("prefs.no", locales::LANG) {
+    } else if val == t!("prefs.no", locales::lang()) {
         false
     } else {
         unreachable!("cannot go here!");
@@ -650,8 +796,8 @@ fn yes_no_to_bool(val: &str) -> bool {
 
 fn bool_to_yes_no(val: bool) -> String {
     match val {
-        true => t!("prefs.yes", locales::LANG).to_owned(),
-        false => t!("prefs.no", locales::LANG).to_owned(),
+        true => t!("prefs.yes", locales::lang()).to_owned(),
+        false => t!("prefs.no", locales::lang()).to_owned(),
     }
 }
 
@@ -694,6 +840,14 @@ fn run_menu_thread(sid: xous::SID, status_cid: xous::CID) {
 
         let op = msg.body.id();
 
+        if op == PrefsMenuUpdateOp::UpdateMenuLanguage.to_usize().unwrap() {
+            // every handler has a menu to rebuild
+            for handler in handlers.iter_mut() {
+                handler.relabel_menu();
+            }
+            continue;
+        }
+
         for handler in handlers.iter_mut() {
             if handler.handle(op) {
                 log::debug!("handler found!");
@@ -19,11 +19,11 @@ pub enum WlanManOp {
 impl Display for WlanManOp {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            Self::AddNetworkManually => write!(f, "{}", t!("wlan.manual_add", locales::LANG)),
-            Self::ScanForNetworks => write!(f, "{}", t!("wlan.scan", locales::LANG)),
-            Self::Status => write!(f, "{}", t!("wlan.status", locales::LANG)),
-            Self::DeleteNetwork => write!(f, "{}", t!("wlan.delete", locales::LANG)),
-            Self::KnownNetworks => write!(f, "{}", t!("wlan.list_known", locales::LANG)),
+            Self::AddNetworkManually => write!(f, "{}", t!("wlan.manual_add", locales::lang())),
+            Self::ScanForNetworks => write!(f, "{}", t!("wlan.scan", locales::lang())),
+            Self::Status => write!(f, "{}", t!("wlan.status", locales::lang())),
+            Self::DeleteNetwork => write!(f, "{}", t!("wlan.delete", locales::lang())),
+            Self::KnownNetworks => write!(f, "{}", t!("wlan.list_known", locales::lang())),
         }
     }
 }
@@ -64,6 +64,8 @@ pub struct WLANMan {
     netmgr: net::NetManager,
     modals: modals::Modals,
     pddb: pddb::Pddb,
+    menu: Option<gam::MenuMatic>,
+    menu_conn: Option<xous::CID>,
 }
 
 impl PrefHandler for WLANMan {
@@ -82,6 +84,31 @@ impl PrefHandler for WLANMan {
     }
 
     fn claim_menumatic_menu(&mut self, cid: xous::CID) {
+        self.menu_conn = Some(cid);
+        self.menu =
+            gam::menu_matic(self.menu_items(cid), gam::WIFI_MENU_NAME, Some(xous::create_server().unwrap()));
+    }
+
+    fn relabel_menu(&mut self) {
+        if let (Some(menu), Some(cid)) = (self.menu.as_ref(), self.menu_conn) {
+            menu.set_items(self.menu_items(cid));
+        }
+    }
+}
+
+impl WLANMan {
+    pub fn new(xns: &xous_names::XousNames) -> Self {
+        Self {
+            com: com::Com::new(&xns).unwrap(),
+            netmgr: net::NetManager::new(),
+            modals: modals::Modals::new(&xns).unwrap(),
+            pddb: pddb::Pddb::new(),
+            menu: None,
+            menu_conn: None,
+        }
+    }
+
+    fn menu_items(&self, cid: xous::CID) -> Vec<gam::MenuItem> {
         let mut menus = self
             .actions()
             .iter()
@@ -95,25 +122,13 @@ impl PrefHandler for WLANMan {
             .collect::<Vec<gam::MenuItem>>();
 
         menus.push(gam::MenuItem {
-            name: String::from(t!("mainmenu.closemenu", locales::LANG)),
+            name: String::from(t!("mainmenu.closemenu", locales::lang())),
             action_conn: None,
             action_opcode: 0,
             action_payload: gam::MenuPayload::Scalar([0, 0, 0, 0]),
             close_on_select: true,
         });
-
-        gam::menu_matic(menus, gam::WIFI_MENU_NAME, None);
-    }
-}
-
-impl WLANMan {
-    pub fn new(xns: &xous_names::XousNames) -> Self {
-        Self {
-            com: com::Com::new(&xns).unwrap(),
-            netmgr: net::NetManager::new(),
-            modals: modals::Modals::new(&xns).unwrap(),
-            pddb: pddb::Pddb::new(),
-        }
+        menus
     }
 
     pub fn actions(&self) -> Vec<WlanManOp> {
@@ -141,7 +156,7 @@ impl WLANMan {
     fn add_new_ssid(&mut self) -> Result<(), WLANError> {
         let connection_data = self
             .modals
-            .alert_builder(t!("wlan.ssid_entry", locales::LANG))
+            .alert_builder(t!("wlan.ssid_entry", locales::lang()))
             .field(
                 Some("SSID".to_string()),
                 Some(|text| {
@@ -152,7 +167,7 @@ impl WLANMan {
                     None
                 }),
             )
-            .field(Some(t!("wlan.password", locales::LANG).to_string()), None)
+            .field(Some(t!("wlan.password", locales::lang()).to_string()), None)
             .build()
             .unwrap();
 
@@ -211,7 +226,7 @@ impl WLANMan {
             // accumulate the results.
             match state {
                 ScanState::Updating => {
-                    let mut progress = t!("wlan.ssid_scanning", locales::LANG).to_string();
+                    let mut progress = t!("wlan.ssid_scanning", locales::lang()).to_string();
                     let networks: Vec<&str> = networks.iter().map(|s| s.as_str()).collect();
                     progress.push_str("\n\n");
                     for network in networks {
@@ -231,7 +246,7 @@ impl WLANMan {
                     if showing_wait {
                         self.modals.dynamic_notification_close().unwrap();
                     }
-                    self.modals.show_notification(t!("wlan.ssid_off_error", locales::LANG), None).unwrap();
+                    self.modals.show_notification(t!("wlan.ssid_off_error", locales::lang()), None).unwrap();
                     return Ok(());
                 }
             }
@@ -251,17 +266,17 @@ impl WLANMan {
         networks.truncate(max_entries);
 
         if networks.is_empty() {
-            self.modals.show_notification(t!("wlan.no_networks", locales::LANG), None).unwrap();
+            self.modals.show_notification(t!("wlan.no_networks", locales::lang()), None).unwrap();
             return Ok(());
         }
 
-        networks.push(t!("wlan.cancel", locales::LANG));
+        networks.push(t!("wlan.cancel", locales::lang()));
 
         self.modals.add_list(networks).unwrap();
 
-        let ssid = self.modals.get_radiobutton(t!("wlan.ssid_choose", locales::LANG)).unwrap();
+        let ssid = self.modals.get_radiobutton(t!("wlan.ssid_choose", locales::lang())).unwrap();
 
-        if ssid == t!("wlan.cancel", locales::LANG) {
+        if ssid == t!("wlan.cancel", locales::lang()) {
             return Ok(());
         }
 
@@ -271,8 +286,8 @@ impl WLANMan {
     fn fill_password_for_ssid(&mut self, ssid: &str) -> Result<(), WLANError> {
         let connection_data = self
             .modals
-            .alert_builder(&t!("wlan.ssid_password", locales::LANG).replace("{ssid}", ssid))
-            .field(Some(t!("wlan.password", locales::LANG).to_string()), None)
+            .alert_builder(&t!("wlan.ssid_password", locales::lang()).replace("{ssid}", ssid))
+            .field(Some(t!("wlan.password", locales::lang()).to_string()), None)
             .build()
             .unwrap();
 
@@ -285,7 +300,7 @@ impl WLANMan {
         let status = self.com.wlan_status()?;
         let ssid = match status.ssid {
             Some(s) => s.name.to_string(),
-            None => t!("stats.disconnected", locales::LANG).to_string(),
+            None => t!("stats.disconnected", locales::lang()).to_string(),
         };
 
         let ls = status.link_state;
@@ -316,14 +331,14 @@ impl WLANMan {
             Err(_) => Vec::new(),
         };
 
-        let mut networks_string = String::from(t!("wlan.no_known_networks", locales::LANG));
+        let mut networks_string = String::from(t!("wlan.no_known_networks", locales::lang()));
 
         if networks.is_empty() {
             self.modals.show_notification(&networks_string, None).unwrap();
             return Ok(());
         }
 
-        networks_string = String::from(t!("wlan.known_networks", locales::LANG));
+        networks_string = String::from(t!("wlan.known_networks", locales::lang()));
 
         networks_string += &networks.iter().map(|s| format!(" ▪ {}", s)).collect::<Vec<String>>().join("\n");
 
@@ -339,16 +354,16 @@ impl WLANMan {
         };
 
         if networks.is_empty() {
-            self.modals.show_notification(t!("wlan.no_known_networks", locales::LANG), None).unwrap();
+            self.modals.show_notification(t!("wlan.no_known_networks", locales::lang()), None).unwrap();
             return Ok(());
         }
 
-        let cancel_item = t!("wlan.cancel", locales::LANG);
+        let cancel_item = t!("wlan.cancel", locales::lang());
         self.modals.add_list(networks.iter().map(|s| s.as_str()).collect()).unwrap();
         self.modals.add_list_item(cancel_item).unwrap();
 
         let ssid_to_be_deleted =
-            self.modals.get_radiobutton(t!("wlan.choose_delete", locales::LANG)).unwrap();
+            self.modals.get_radiobutton(t!("wlan.choose_delete", locales::lang())).unwrap();
 
         if ssid_to_be_deleted.eq(cancel_item) {
             return Ok(());
@@ -375,7 +390,7 @@ impl WLANMan {
 
     fn show_error_modal(&self, e: WLANError) {
         self.modals
-            .show_notification(format!("{}: {}", t!("wlan.error", locales::LANG), e).as_str(), None)
+            .show_notification(format!("{}: {}", t!("wlan.error", locales::lang()), e).as_str(), None)
             .unwrap()
     }
 }
@@ -6,15 +6,73 @@ pub(crate) enum Opcode {
     TextToSpeech,
     /// Non-interruptable conversion of a string to audible speech. Blocks until the phrase is finished.
     TextToSpeechBlocking,
-    /// Stops audio playback immediately. Does not stop wave generation.
+    /// Stops audio playback immediately and cancels everything queued. Does not stop wave generation.
     CodecStop,
     /// Set words per minute
     SetWordsPerMinute,
     /// Exits the server
     Quit,
+    /// Queues an utterance (Speech, lend_mut), returning its ID
+    Speak,
+    /// Cancels an utterance by ID, whether it is being spoken or still waiting. Ignored unless sent by the
+    /// process that queued the utterance.
+    Cancel,
+    /// The playback thread finished speaking an utterance (internal)
+    PlaybackDone,
 }
 
 #[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct TtsFrontendMsg {
     pub text: String,
 }
+
+/// Utterances are spoken highest priority first, and in the order they came in within a priority
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    num_derive::FromPrimitive,
+    num_derive::ToPrimitive,
+)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// What a new utterance does to the ones already queued
+#[derive(Debug, Copy, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub enum SpeechMode {
+    /// waits for its turn
+    Queue,
+    /// cuts short an utterance of lower priority that is being spoken; the interrupted utterance is
+    /// spoken again from its start once its turn comes back
+    Interrupt,
+    /// cancels every utterance of the same or lower priority, whether it is being spoken or waiting
+    Replace,
+}
+
+#[derive(Debug, Clone, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Speech {
+    pub text: String,
+    pub priority: Priority,
+    pub mode: SpeechMode,
+    /// server and message ID that are sent a scalar of `(utterance ID, SpeechOutcome)` once the utterance
+    /// is done with
+    pub callback: Option<([u32; 4], u32)>,
+    /// filled in by the server
+    pub id: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_derive::FromPrimitive, num_derive::ToPrimitive)]
+pub enum SpeechOutcome {
+    Finished,
+    Cancelled,
+}
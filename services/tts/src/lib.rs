@@ -19,21 +19,75 @@ impl TtsFrontend {
         Ok(TtsFrontend { conn })
     }
 
+    /// Like `new()`, but fails instead of waiting if the TTS server isn't running, e.g. because this image
+    /// was built without it
+    pub fn try_new(xns: &xous_names::XousNames) -> Result<Self, xous::Error> {
+        let conn = xns.request_connection(api::SERVER_NAME_TTS)?;
+        REFCOUNT.fetch_add(1, Ordering::Relaxed);
+        Ok(TtsFrontend { conn })
+    }
+
     /// A fully synchronous text to speech call. The text is turned into speech and played immediately.
-    /// If there is speech currently playing, it is cut short and the new text takes its place.
+    /// If there is speech currently playing, it is cut short and the new text takes its place. This is
+    /// `speak(text, Priority::Normal, SpeechMode::Replace)`, so it doesn't cut short `Priority::High` speech.
     pub fn tts_simple(&self, text: &str) -> Result<(), xous::Error> {
         let msg = TtsFrontendMsg { text: String::from(text) };
         let buf = Buffer::into_buf(msg).or(Err(xous::Error::InternalError))?;
         buf.lend(self.conn, Opcode::TextToSpeech.to_u32().unwrap()).map(|_| ())
     }
 
-    /// This blocks until the text is finished rendering
+    /// This blocks until the text is finished rendering, or until it is cancelled or replaced
     pub fn tts_blocking(&self, text: &str) -> Result<(), xous::Error> {
         let msg = TtsFrontendMsg { text: String::from(text) };
         let buf = Buffer::into_buf(msg).or(Err(xous::Error::InternalError))?;
         buf.lend(self.conn, Opcode::TextToSpeechBlocking.to_u32().unwrap()).map(|_| ())
     }
 
+    /// Queues `text` to be spoken according to `priority` and `mode`, returning an ID for `cancel()`
+    pub fn speak(&self, text: &str, priority: Priority, mode: SpeechMode) -> Result<u32, xous::Error> {
+        self.queue_speech(Speech { text: String::from(text), priority, mode, callback: None, id: 0 })
+    }
+
+    /// Like `speak()`, and once the utterance is spoken or cancelled, a scalar message `callback_id` is
+    /// sent to the server `sid` with the utterance ID in arg1 and its `SpeechOutcome` in arg2.
+    pub fn speak_with_callback(
+        &self,
+        text: &str,
+        priority: Priority,
+        mode: SpeechMode,
+        sid: xous::SID,
+        callback_id: u32,
+    ) -> Result<u32, xous::Error> {
+        self.queue_speech(Speech {
+            text: String::from(text),
+            priority,
+            mode,
+            callback: Some((sid.to_array(), callback_id)),
+            id: 0,
+        })
+    }
+
+    /// Cancels the utterance `id`, whether it is being spoken or still waiting its turn. Only utterances
+    /// queued by this process can be cancelled.
+    pub fn cancel(&self, id: u32) -> Result<(), xous::Error> {
+        send_message(self.conn, Message::new_scalar(Opcode::Cancel.to_usize().unwrap(), id as usize, 0, 0, 0))
+            .map(|_| ())
+    }
+
+    /// Cancels everything that is queued and stops speaking immediately
+    pub fn stop(&self) -> Result<(), xous::Error> {
+        send_message(self.conn, Message::new_scalar(Opcode::CodecStop.to_usize().unwrap(), 0, 0, 0, 0))
+            .map(|_| ())
+    }
+
+    fn queue_speech(&self, speech: Speech) -> Result<u32, xous::Error> {
+        let mut buf = Buffer::into_buf(speech).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::Speak.to_u32().unwrap())?;
+        let speech = buf.to_original::<Speech, _>()?;
+        // the server leaves the ID at 0 if it couldn't make sense of the request
+        if speech.id == 0 { Err(xous::Error::InternalError) } else { Ok(speech.id) }
+    }
+
     pub fn set_words_per_minute(&self, wpm: u32) -> Result<(), xous::Error> {
         send_message(
             self.conn,
@@ -2,19 +2,20 @@
 #![cfg_attr(target_os = "none", no_main)]
 
 mod api;
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, Ordering};
+mod queue;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
 use api::*;
 use codec::{FrameRing, VolumeOps, ZERO_PCM};
 use num_traits::*;
-use xous::{Message, msg_scalar_unpack, send_message};
+use queue::{SpeechQueue, Utterance};
+use xous::{Message, MessageEnvelope, msg_scalar_unpack, send_message};
 use xous_ipc::Buffer;
 use xous_tts_backend::*;
 
 const DEFAULT_WPM: u32 = 350;
-const WAIT_INTERVAL: usize = 50; // milliseconds to wait before polling if a phrase is finished.
 const MAX_BUF_DEPTH: usize = (8000 * core::mem::size_of::<u16>()) * 3; // 8000 samples/s * num seconds to buffer
 const DRAIN_INTERVAL: usize = 100; // milliseconds to wait before checking if buffer has drained
 
@@ -29,6 +30,68 @@ pub(crate) enum CallbackOp {
     Quit,
 }
 
+/// How to tell the requester of an utterance that it is done with
+struct Waiter {
+    /// the process that queued the utterance, and so may cancel it
+    owner: Option<xous::PID>,
+    /// connection and message ID for the `SpeechOutcome`
+    callback: Option<(xous::CID, u32)>,
+    /// a blocking request, answered by dropping it
+    blocked: Option<MessageEnvelope>,
+}
+
+fn notify(utterance: Utterance<Waiter>, outcome: SpeechOutcome) {
+    log::debug!("utterance {} {:?}", utterance.id, outcome);
+    if let Some((cid, id)) = utterance.waiter.callback {
+        xous::try_send_message(
+            cid,
+            Message::new_scalar(id as usize, utterance.id as usize, outcome.to_usize().unwrap(), 0, 0),
+        )
+        .ok();
+    }
+    // a blocking caller returns once its message is dropped
+    drop(utterance.waiter.blocked);
+}
+
+/// The state shared between the main loop and the threads that fill and drain the wave buffer
+struct Player {
+    wavbuf: Arc<Mutex<VecDeque<u16>>>,
+    synth_done: Arc<AtomicBool>,
+    just_initiated: Arc<AtomicBool>,
+    /// ID of the utterance being played, or 0. It is reported back with `PlaybackDone` once played out.
+    playing: Arc<AtomicU32>,
+    /// set to drop what the synthesizer still sends for an utterance that was cancelled
+    discard: Arc<AtomicBool>,
+}
+impl Player {
+    /// Starts whatever the queue says should now be playing, or stops if there is nothing left to say
+    fn play_next(&self, queue: &mut SpeechQueue<Waiter>, tts_be: &TtsBackend, codec: &mut codec::Codec) {
+        if let Some((id, text)) = queue.start() {
+            log::debug!("speaking utterance {}", id);
+            // a synthesis still running belongs to the utterance that was interrupted or cancelled, so
+            // drop the rest of it, up to its end, rather than play it as part of this one
+            let preempted =
+                self.playing.load(Ordering::SeqCst) != 0 && !self.synth_done.load(Ordering::SeqCst);
+            self.discard.store(preempted, Ordering::SeqCst);
+            self.synth_done.store(false, Ordering::SeqCst);
+            self.wavbuf.lock().unwrap().clear(); // this will truncate any buffered audio that is playing
+            // set last, so the playback thread can't take the empty buffer as the end of this utterance
+            self.playing.store(id, Ordering::SeqCst);
+            tts_be.tts_simple(text).unwrap();
+            self.just_initiated.store(true, Ordering::SeqCst);
+            log::trace!("resuming codec");
+            codec.resume().unwrap();
+        } else if !queue.is_speaking() && self.playing.swap(0, Ordering::SeqCst) != 0 {
+            log::debug!("nothing left to say, stopping");
+            if !self.synth_done.load(Ordering::SeqCst) {
+                self.discard.store(true, Ordering::SeqCst);
+            }
+            self.wavbuf.lock().unwrap().clear();
+            codec.pause().unwrap();
+        }
+    }
+}
+
 fn main() -> ! {
     log_server::init_wait().unwrap();
     log::set_max_level(log::LevelFilter::Info);
@@ -36,7 +99,7 @@ fn main() -> ! {
 
     let xns = xous_names::XousNames::new().unwrap();
     let tts_sid = xns.register_name(api::SERVER_NAME_TTS, None).expect("can't register server");
-    // let tts_cid = xous::connect(tts_sid).unwrap();
+    let tts_cid = xous::connect(tts_sid).unwrap();
     log::trace!("registered with NS -- {:?}", tts_sid);
 
     let tts_be = TtsBackend::new(&xns).unwrap();
@@ -47,11 +110,12 @@ fn main() -> ! {
     let wav_cid = xous::connect(wav_sid).unwrap();
     let wavbuf = Arc::new(Mutex::new(VecDeque::<u16>::new()));
     let synth_done = Arc::new(AtomicBool::new(false));
+    let discard = Arc::new(AtomicBool::new(false));
     std::thread::spawn({
         let wav_sid = wav_sid.clone();
         let wavbuf = wavbuf.clone();
-        // let tts_cid = tts_cid.clone();
         let synth_done = synth_done.clone();
+        let discard = discard.clone();
         move || {
             let tt = ticktimer_server::Ticktimer::new().unwrap();
             loop {
@@ -78,10 +142,18 @@ fn main() -> ! {
                             unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
                         let wavdat = buffer.to_original::<TtsBackendData, _>().unwrap();
                         let mut buf = wavbuf.lock().unwrap();
-                        for &d in wavdat.data[..wavdat.len as usize].iter() {
-                            buf.push_back(d);
+                        let discarding = discard.load(Ordering::SeqCst);
+                        if !discarding {
+                            for &d in wavdat.data[..wavdat.len as usize].iter() {
+                                buf.push_back(d);
+                            }
                         }
                         match wavdat.control {
+                            Some(TtsBeControl::End) | Some(TtsBeControl::Abort) if discarding => {
+                                // the end of a synthesis that was cut short. It says nothing about the
+                                // utterance playing now, whose samples follow.
+                                discard.store(false, Ordering::SeqCst);
+                            }
                             Some(TtsBeControl::End) => {
                                 // the buffer can still be quite full at this point, we have to wait until it
                                 // drains naturally
@@ -98,7 +170,9 @@ fn main() -> ! {
                                 // more data can arrive after done is set true if a new synthesis was
                                 // kicked off that aborts the current run. reflect that in the synth_done
                                 // state.
-                                synth_done.store(false, Ordering::SeqCst);
+                                if !discarding {
+                                    synth_done.store(false, Ordering::SeqCst);
+                                }
                             }
                         }
                     }
@@ -116,10 +190,12 @@ fn main() -> ! {
     let cb_sid = xous::create_server().unwrap();
     let cb_cid = xous::connect(cb_sid).unwrap();
     let just_initiated = Arc::new(AtomicBool::new(false));
+    let playing = Arc::new(AtomicU32::new(0));
     std::thread::spawn({
         let wavbuf = wavbuf.clone();
         let synth_done = synth_done.clone();
         let just_initiated = just_initiated.clone();
+        let playing = playing.clone();
         move || {
             let mut codec = codec::Codec::new(&xous_names::XousNames::new().unwrap()).unwrap();
             let mut frame_count = 0;
@@ -175,6 +251,20 @@ fn main() -> ! {
                                 // finished
                                 if (locked_buf.len() == 0) && synth_done.load(Ordering::SeqCst) {
                                     codec.pause().unwrap();
+                                    let id = playing.swap(0, Ordering::SeqCst);
+                                    if id != 0 {
+                                        xous::try_send_message(
+                                            tts_cid,
+                                            Message::new_scalar(
+                                                Opcode::PlaybackDone.to_usize().unwrap(),
+                                                id as usize,
+                                                0,
+                                                0,
+                                                0,
+                                            ),
+                                        )
+                                        .ok();
+                                    }
                                 }
                             }
                         })
@@ -198,43 +288,125 @@ fn main() -> ! {
     codec.set_headphone_volume(VolumeOps::RestoreDefault, None).unwrap();
     codec.hook_frame_callback(CallbackOp::Callback.to_u32().unwrap(), cb_cid).unwrap();
 
+    let player = Player { wavbuf, synth_done, just_initiated, playing, discard };
+    let mut queue = SpeechQueue::<Waiter>::new();
+    // connections to the servers that asked for completion callbacks
+    let mut callback_conns: HashMap<[u32; 4], xous::CID> = HashMap::new();
+
     let mut wpm = DEFAULT_WPM;
     tts_be.tts_config(wav_sid.to_array(), WaveOp::Return.to_u32().unwrap(), None, Some(wpm)).unwrap();
     loop {
         let msg = xous::receive_message(tts_sid).unwrap();
         match FromPrimitive::from_usize(msg.body.id()) {
             Some(Opcode::TextToSpeech) => {
+                let owner = msg.sender.pid();
                 let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
                 let msg = buffer.to_original::<TtsFrontendMsg, _>().unwrap();
                 log::debug!("tts front end got string {}", msg.text.as_str());
-                wavbuf.lock().unwrap().clear(); // this will truncate any buffered audio that is playing
-                synth_done.store(false, Ordering::SeqCst);
-                tts_be.tts_simple(msg.text.as_str()).unwrap();
-                just_initiated.store(true, Ordering::SeqCst);
-                log::trace!("resuming codec");
-                codec.resume().unwrap();
+                let waiter = Waiter { owner, callback: None, blocked: None };
+                let (_, ended) = queue.push(msg.text, Priority::Normal, SpeechMode::Replace, waiter);
+                for (utterance, outcome) in ended {
+                    notify(utterance, outcome);
+                }
+                player.play_next(&mut queue, &tts_be, &mut codec);
             }
             Some(Opcode::TextToSpeechBlocking) => {
-                let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
-                let msg = buffer.to_original::<TtsFrontendMsg, _>().unwrap();
-                log::debug!("tts blocking front end got string {}", msg.text.as_str());
-                wavbuf.lock().unwrap().clear(); // this will truncate any buffered audio that is playing
-                synth_done.store(false, Ordering::SeqCst);
-                tts_be.tts_simple(msg.text.as_str()).unwrap();
-                just_initiated.store(true, Ordering::SeqCst);
-                log::trace!("resuming codec (blocking)");
-                codec.resume().unwrap();
-                while !synth_done.load(Ordering::SeqCst) {
-                    // this is done fairly "fast" because the synth buf fills quickly
-                    tt.sleep_ms(WAIT_INTERVAL).unwrap();
+                let text = {
+                    let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                    buffer.to_original::<TtsFrontendMsg, _>().unwrap().text
+                };
+                log::debug!("tts blocking front end got string {}", text.as_str());
+                // the message is held until the utterance is done with, which is what unblocks the caller
+                let waiter = Waiter { owner: msg.sender.pid(), callback: None, blocked: Some(msg) };
+                let (_, ended) = queue.push(text, Priority::Normal, SpeechMode::Replace, waiter);
+                for (utterance, outcome) in ended {
+                    notify(utterance, outcome);
                 }
-                while codec.is_running().unwrap() {
-                    // this actually waits until the playing is fully done
-                    tt.sleep_ms(WAIT_INTERVAL).unwrap();
+                player.play_next(&mut queue, &tts_be, &mut codec);
+            }
+            Some(Opcode::Speak) => {
+                let owner = msg.sender.pid();
+                let mut msg = msg;
+                let Some(mem) = msg.body.memory_message_mut() else {
+                    log::warn!("Speak from {:?} was not a mutable lend", owner);
+                    if msg.body.is_blocking() {
+                        xous::return_scalar(msg.sender, 0).ok();
+                    }
+                    continue;
+                };
+                let mut buffer = unsafe { Buffer::from_memory_message_mut(mem) };
+                let Ok(mut speech) = buffer.to_original::<Speech, _>() else {
+                    log::warn!("malformed Speak from {:?}", owner);
+                    // 0 is never an utterance ID, and tells the caller nothing was queued
+                    let rejected = Speech {
+                        text: String::new(),
+                        priority: Priority::Low,
+                        mode: SpeechMode::Queue,
+                        callback: None,
+                        id: 0,
+                    };
+                    buffer.replace(rejected).ok();
+                    continue;
+                };
+                let callback = speech.callback.and_then(|(sid, id)| {
+                    let cid = match callback_conns.get(&sid) {
+                        Some(&cid) => cid,
+                        None => {
+                            let cid = xous::try_connect(xous::SID::from_array(sid)).ok()?;
+                            callback_conns.insert(sid, cid);
+                            cid
+                        }
+                    };
+                    Some((cid, id))
+                });
+                let (id, ended) = queue.push(
+                    speech.text.clone(),
+                    speech.priority,
+                    speech.mode,
+                    Waiter { owner, callback, blocked: None },
+                );
+                for (utterance, outcome) in ended {
+                    notify(utterance, outcome);
                 }
+                speech.id = id;
+                buffer.replace(speech).expect("couldn't return Speech");
+                player.play_next(&mut queue, &tts_be, &mut codec);
             }
+            Some(Opcode::Cancel) => msg_scalar_unpack!(msg, id, _, _, _, {
+                match queue.get(id as u32) {
+                    Some(utterance) if utterance.waiter.owner != msg.sender.pid() => {
+                        log::warn!(
+                            "PID {:?} can't cancel utterance {} of another process",
+                            msg.sender.pid(),
+                            id
+                        );
+                    }
+                    Some(_) => {
+                        if let Some(utterance) = queue.cancel(id as u32) {
+                            notify(utterance, SpeechOutcome::Cancelled);
+                        }
+                    }
+                    None => {}
+                }
+                player.play_next(&mut queue, &tts_be, &mut codec);
+            }),
+            Some(Opcode::PlaybackDone) => msg_scalar_unpack!(msg, id, _, _, _, {
+                // only the playback thread in this process knows when an utterance has been played out
+                if msg.sender.pid().map(|p| p.get()).unwrap_or_default() as u32 != xous::process::id() {
+                    log::warn!("PlaybackDone from {:?} ignored", msg.sender.pid());
+                    continue;
+                }
+                if let Some(utterance) = queue.finished(id as u32) {
+                    notify(utterance, SpeechOutcome::Finished);
+                }
+                player.play_next(&mut queue, &tts_be, &mut codec);
+            }),
             Some(Opcode::CodecStop) => {
                 log::info!("stop called. Immediate stop and loss of audio data.");
+                for utterance in queue.clear() {
+                    notify(utterance, SpeechOutcome::Cancelled);
+                }
+                player.play_next(&mut queue, &tts_be, &mut codec);
                 codec.abort().unwrap();
             }
             Some(Opcode::SetWordsPerMinute) => msg_scalar_unpack!(msg, wpm_arg, _, _, _, {
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+
+use crate::api::{Priority, SpeechMode, SpeechOutcome};
+
+pub struct Utterance<T> {
+    pub id: u32,
+    pub priority: Priority,
+    pub text: String,
+    /// whatever the server needs to tell the requester that the utterance is done
+    pub waiter: T,
+}
+
+/// Orders the utterances waiting to be spoken. It only keeps track of what should be playing; the server
+/// does the playing, starting whatever `start()` hands it and reporting back with `finished()`.
+pub struct SpeechQueue<T> {
+    /// the utterance being spoken, and whether it has been handed to the synthesizer yet
+    current: Option<(Utterance<T>, bool)>,
+    /// highest priority first, and first come first within a priority
+    waiting: VecDeque<Utterance<T>>,
+    next_id: u32,
+}
+
+impl<T> SpeechQueue<T> {
+    pub fn new() -> Self { SpeechQueue { current: None, waiting: VecDeque::new(), next_id: 1 } }
+
+    /// Queues `text`, returning its ID along with the utterances it cancelled
+    pub fn push(
+        &mut self,
+        text: String,
+        priority: Priority,
+        mode: SpeechMode,
+        waiter: T,
+    ) -> (u32, Vec<(Utterance<T>, SpeechOutcome)>) {
+        let id = self.next_id;
+        // 0 is never an ID, so it can stand for "nothing playing"
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        let mut ended = Vec::new();
+        match mode {
+            SpeechMode::Queue => {}
+            SpeechMode::Interrupt => {
+                if matches!(&self.current, Some((current, _)) if current.priority < priority) {
+                    let (current, _) = self.current.take().unwrap();
+                    self.insert(current, true);
+                }
+            }
+            SpeechMode::Replace => {
+                if matches!(&self.current, Some((current, _)) if current.priority <= priority) {
+                    let (current, _) = self.current.take().unwrap();
+                    ended.push((current, SpeechOutcome::Cancelled));
+                }
+                let (cancelled, kept) = self.waiting.drain(..).partition(|u| u.priority <= priority);
+                self.waiting = kept;
+                ended.extend(cancelled.into_iter().map(|u: Utterance<T>| (u, SpeechOutcome::Cancelled)));
+            }
+        }
+        self.insert(Utterance { id, priority, text, waiter }, false);
+        self.advance();
+        (id, ended)
+    }
+
+    /// Removes the utterance `id`, wherever it is
+    pub fn cancel(&mut self, id: u32) -> Option<Utterance<T>> {
+        if matches!(&self.current, Some((current, _)) if current.id == id) {
+            let (current, _) = self.current.take().unwrap();
+            self.advance();
+            return Some(current);
+        }
+        let index = self.waiting.iter().position(|u| u.id == id)?;
+        self.waiting.remove(index)
+    }
+
+    /// The utterance `id`, wherever it is
+    pub fn get(&self, id: u32) -> Option<&Utterance<T>> {
+        self.current.iter().map(|(current, _)| current).chain(self.waiting.iter()).find(|u| u.id == id)
+    }
+
+    /// The utterance `id` has been spoken to its end
+    pub fn finished(&mut self, id: u32) -> Option<Utterance<T>> {
+        if !matches!(&self.current, Some((current, true)) if current.id == id) {
+            // playback of something that has since been interrupted or cancelled
+            return None;
+        }
+        let (current, _) = self.current.take().unwrap();
+        self.advance();
+        Some(current)
+    }
+
+    /// The ID and text of the utterance that should now be playing, if it hasn't been started yet
+    pub fn start(&mut self) -> Option<(u32, &str)> {
+        match &mut self.current {
+            Some((current, started)) if !*started => {
+                *started = true;
+                Some((current.id, current.text.as_str()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_speaking(&self) -> bool { self.current.is_some() }
+
+    /// Removes every utterance
+    pub fn clear(&mut self) -> Vec<Utterance<T>> {
+        self.current.take().map(|(current, _)| current).into_iter().chain(self.waiting.drain(..)).collect()
+    }
+
+    /// Puts `utterance` after the ones of the same or higher priority, or before the ones of the same
+    /// priority if it goes `first`
+    fn insert(&mut self, utterance: Utterance<T>, first: bool) {
+        let index = self
+            .waiting
+            .iter()
+            .position(
+                |u| if first { u.priority <= utterance.priority } else { u.priority < utterance.priority },
+            )
+            .unwrap_or(self.waiting.len());
+        self.waiting.insert(index, utterance);
+    }
+
+    fn advance(&mut self) {
+        if self.current.is_none() {
+            self.current = self.waiting.pop_front().map(|u| (u, false));
+        }
+    }
+}
+
+impl<T> Default for SpeechQueue<T> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(queue: &mut SpeechQueue<()>, text: &str, priority: Priority, mode: SpeechMode) -> u32 {
+        queue.push(text.to_string(), priority, mode, ()).0
+    }
+
+    /// Plays everything queued to the end, returning the texts in the order they were spoken
+    fn drain(queue: &mut SpeechQueue<()>) -> Vec<String> {
+        let mut spoken = Vec::new();
+        while let Some((id, text)) = queue.start() {
+            spoken.push(text.to_string());
+            assert!(queue.finished(id).is_some());
+        }
+        assert!(!queue.is_speaking());
+        spoken
+    }
+
+    #[test]
+    fn priorities() {
+        let mut queue = SpeechQueue::new();
+        push(&mut queue, "a", Priority::Normal, SpeechMode::Queue);
+        assert_eq!(queue.start().map(|(_, text)| text), Some("a"));
+        assert_eq!(queue.start(), None);
+        push(&mut queue, "b", Priority::Low, SpeechMode::Queue);
+        push(&mut queue, "c", Priority::Normal, SpeechMode::Queue);
+        push(&mut queue, "d", Priority::High, SpeechMode::Queue);
+        push(&mut queue, "e", Priority::Normal, SpeechMode::Queue);
+        assert_eq!(queue.start(), None);
+        assert_eq!(queue.finished(1).map(|u| u.text), Some("a".to_string()));
+        assert_eq!(drain(&mut queue), ["d", "c", "e", "b"]);
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut queue = SpeechQueue::new();
+        let long = push(&mut queue, "long text", Priority::Normal, SpeechMode::Queue);
+        queue.start();
+        push(&mut queue, "next", Priority::Normal, SpeechMode::Queue);
+        // the same priority doesn't interrupt
+        push(&mut queue, "same", Priority::Normal, SpeechMode::Interrupt);
+        assert_eq!(queue.start(), None);
+        let (_, ended) = queue.push("alert".to_string(), Priority::High, SpeechMode::Interrupt, ());
+        assert!(ended.is_empty());
+        let (alert, _) = queue.start().unwrap();
+        // playback of the interrupted utterance ending doesn't count
+        assert!(queue.finished(long).is_none());
+        assert!(queue.finished(alert).is_some());
+        assert_eq!(drain(&mut queue), ["long text", "next", "same"]);
+    }
+
+    #[test]
+    fn replace_and_cancel() {
+        let mut queue = SpeechQueue::new();
+        push(&mut queue, "a", Priority::Normal, SpeechMode::Queue);
+        queue.start();
+        let b = push(&mut queue, "b", Priority::Low, SpeechMode::Queue);
+        push(&mut queue, "c", Priority::High, SpeechMode::Queue);
+        let (_, ended) = queue.push("d".to_string(), Priority::Normal, SpeechMode::Replace, ());
+        let ended: Vec<(&str, SpeechOutcome)> = ended.iter().map(|(u, o)| (u.text.as_str(), *o)).collect();
+        assert_eq!(ended, [("a", SpeechOutcome::Cancelled), ("b", SpeechOutcome::Cancelled)]);
+        assert!(queue.cancel(b).is_none());
+        let e = push(&mut queue, "e", Priority::Normal, SpeechMode::Queue);
+        assert_eq!(queue.get(e).map(|u| u.text.as_str()), Some("e"));
+        assert!(queue.get(b).is_none());
+        assert_eq!(queue.cancel(e).map(|u| u.text), Some("e".to_string()));
+        assert_eq!(drain(&mut queue), ["c", "d"]);
+
+        let a = push(&mut queue, "a", Priority::Normal, SpeechMode::Queue);
+        queue.start();
+        push(&mut queue, "b", Priority::Normal, SpeechMode::Queue);
+        assert_eq!(queue.cancel(a).map(|u| u.text), Some("a".to_string()));
+        assert_eq!(drain(&mut queue), ["b"]);
+
+        push(&mut queue, "a", Priority::Normal, SpeechMode::Queue);
+        queue.start();
+        push(&mut queue, "b", Priority::Low, SpeechMode::Queue);
+        assert_eq!(queue.clear().len(), 2);
+        assert!(!queue.is_speaking());
+    }
+}
@@ -119,7 +119,7 @@ pub fn char_to_hid_code_us101(key: char) -> Vec<UsbKeyCode> {
     code
 }
 
-/// auto-generated using tools/kbd_layout.py + `usb kbdtest` on device for dvorak layout on US101
+/// auto-generated using `kbd-layout kbdtest --generate` + `usb kbdtest` on device for dvorak layout on US101
 #[cfg(any(feature = "precursor", feature = "renode", feature = "cramium-soc"))]
 #[rustfmt::skip]
 pub fn char_to_hid_code_dvorak(key: char) -> Vec<UsbKeyCode> {
@@ -13,9 +13,11 @@ pub(crate) enum Opcode {
     SendString = 2,
     /// Get the current LED state
     GetLedState = 3,
-    /// Switch to a specified device core
+    /// Switch to a specified device core. For the composite core, `arg2` carries its
+    /// `CompositeFunctions`.
     SwitchCores = 4,
-    /// Makes sure a given core is selected
+    /// Makes sure a given core is selected; a composite core with a different set of functions counts
+    /// as a different core
     EnsureCore = 5,
     /// Check which core is connected
     WhichCore = 6,
@@ -77,6 +79,16 @@ pub(crate) enum Opcode {
     /// Unset HID descriptor and reset HIDv2 state
     HIDUnsetDescriptor = 1030,
 
+    /// Blocks the caller, waiting for something for the smart card to do
+    CcidRxDeferred = 1536,
+    /// Answer the smart card command that was last handed out
+    CcidTx = 1537,
+
+    /// Blocks the caller until an Ethernet frame arrives from the host on the network interface
+    NcmRxDeferred = 1792,
+    /// Send an Ethernet frame to the host on the network interface
+    NcmTx = 1793,
+
     /// Handle the USB interrupt
     UsbIrqHandler = 2048,
     /// Suspend/resume callback
@@ -127,6 +139,10 @@ pub enum UsbDeviceType {
     MassStorage = 3,
     Serial = 4,
     HIDv2 = 5,
+    Ccid = 6,
+    Ncm = 7,
+    /// Several functions on one device; which ones is chosen with `CompositeFunctions`
+    Composite = 8,
 }
 use std::convert::TryFrom;
 
@@ -142,11 +158,27 @@ impl TryFrom<usize> for UsbDeviceType {
             3 => Ok(UsbDeviceType::MassStorage),
             4 => Ok(UsbDeviceType::Serial),
             5 => Ok(UsbDeviceType::HIDv2),
+            6 => Ok(UsbDeviceType::Ccid),
+            7 => Ok(UsbDeviceType::Ncm),
+            8 => Ok(UsbDeviceType::Composite),
             _ => Err("Invalid UsbDeviceType specifier"),
         }
     }
 }
 
+bitflags::bitflags! {
+    /// The functions presented at once by the `Composite` core. Combine them with `|`.
+    pub struct CompositeFunctions: usize {
+        /// FIDO/CTAP, the same raw HID interface as the `Fido` core
+        const FIDO = 1;
+        /// The serial port, with the same hooks (console, listeners, TRNG sender) as the `Serial` core
+        const SERIAL = 2;
+        /// A mass storage LUN served by the application registered with `set_block_device`
+        #[cfg(feature = "mass-storage")]
+        const MASS_STORAGE = 4;
+    }
+}
+
 pub const SERIAL_BINARY_BUFLEN: usize = 128;
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Clone)]
 pub struct UsbSerialAscii {
@@ -181,6 +213,76 @@ pub struct HIDReportMessage {
     pub data: Option<HIDReport>,
 }
 
+/// Big enough for a short APDU and its CCID framing.
+pub const CCID_MAX_MSG_LEN: usize = 512;
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Copy, Clone)]
+pub struct CcidMsgIpc {
+    pub data: [u8; CCID_MAX_MSG_LEN],
+    pub len: usize,
+    /// Encodes the state of the message, and what `data` holds
+    pub code: CcidCode,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Copy, Clone, Eq, PartialEq)]
+pub enum CcidCode {
+    RxWait,
+    /// `data` is a command APDU
+    Apdu,
+    /// `data` is the APDU template of a PIN verification to be done on the device
+    PinVerify,
+    /// `data` is the APDU template of a PIN change to be done on the device
+    PinModify,
+    /// The host powered the card on or off
+    Reset,
+    /// `data` is a response APDU
+    Tx,
+    TxAck,
+    Hangup,
+    Denied,
+}
+
+/// Something the host asked of the smart card.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CcidRequest {
+    /// Process a command APDU, and answer with `ccid_send()`.
+    Apdu(Vec<u8>),
+    /// Collect a PIN on the device and verify it, as the command in the APDU template would.
+    /// Answer with `ccid_send()`.
+    PinVerify(Vec<u8>),
+    /// Collect the current and a new PIN on the device and change it. Answer with `ccid_send()`.
+    PinModify(Vec<u8>),
+    /// The card was powered on or off, so any verified PINs are forgotten. Needs no answer.
+    Reset,
+}
+
+/// An Ethernet frame without its FCS, and without VLAN tags.
+pub const NCM_MAX_FRAME_LEN: usize = 1514;
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Copy, Clone)]
+pub struct NcmMsgIpc {
+    pub data: [u8; NCM_MAX_FRAME_LEN],
+    pub len: usize,
+    /// Encodes the state of the message
+    pub code: NcmCode,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Copy, Clone, Eq, PartialEq)]
+pub enum NcmCode {
+    RxWait,
+    /// `data` is a frame from the host
+    Frame,
+    /// `data` is a frame for the host
+    Tx,
+    TxAck,
+    /// The frame was dropped because the host isn't keeping up
+    Busy,
+    /// The host isn't listening, either because the cable is out or because it hasn't brought its
+    /// end of the link up
+    Hangup,
+    Denied,
+}
+
 /// this structure is used to register a USB listener.
 #[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Clone)]
 pub(crate) struct UsbListenerRegistration {
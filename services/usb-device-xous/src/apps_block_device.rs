@@ -25,6 +25,8 @@ struct RwOp {
     max_lba_id: usize,
 }
 
+/// Clones share the application registration, so every mass storage LUN serves the same app.
+#[derive(Clone)]
 pub struct AppsBlockDevice {
     app_cid: Arc<Mutex<Option<xous::CID>>>,
     rw_ids: Arc<Mutex<RwOp>>,
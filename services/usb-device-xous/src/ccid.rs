@@ -0,0 +1,324 @@
+//! A single-slot CCID (USB smart card reader) class, with the card itself living in another
+//! server. Commands that need the card are queued as `CcidRequest`s for the main loop to hand
+//! over, and the card's answer comes back through `respond()`.
+//!
+//! Reference: "Specification for Integrated Circuit(s) Cards Interface Devices", revision 1.1.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+use usb_device::Result;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+
+use crate::api::{CCID_MAX_MSG_LEN, CcidCode, CcidMsgIpc, CcidRequest};
+
+const USB_CLASS_CCID: u8 = 0x0B;
+const CCID_FUNCTIONAL_DESCRIPTOR: u8 = 0x21;
+const PACKET_SIZE: usize = 64;
+const HEADER_LEN: usize = 10;
+/// A short APDU of 261 bytes plus the CCID header; this is what we promise the host.
+const MAX_MESSAGE_LEN: usize = 271;
+
+// PC_to_RDR messages
+const ICC_POWER_ON: u8 = 0x62;
+const ICC_POWER_OFF: u8 = 0x63;
+const GET_SLOT_STATUS: u8 = 0x65;
+const XFR_BLOCK: u8 = 0x6F;
+const GET_PARAMETERS: u8 = 0x6C;
+const RESET_PARAMETERS: u8 = 0x6D;
+const SET_PARAMETERS: u8 = 0x61;
+const SECURE: u8 = 0x69;
+
+// RDR_to_PC messages
+const DATA_BLOCK: u8 = 0x80;
+const SLOT_STATUS: u8 = 0x81;
+const PARAMETERS: u8 = 0x82;
+
+// bStatus fields
+const ICC_ACTIVE: u8 = 0x00;
+const ICC_INACTIVE: u8 = 0x01;
+const COMMAND_FAILED: u8 = 0x40;
+
+// bError values
+const CMD_NOT_SUPPORTED: u8 = 0x00;
+const BAD_LENGTH: u8 = 0x01;
+const BAD_SLOT: u8 = 0x05;
+const CMD_SLOT_BUSY: u8 = 0xE0;
+const ICC_MUTE: u8 = 0xFE;
+
+/// Class-specific control request to abort a command in progress.
+const REQUEST_ABORT: u8 = 0x01;
+
+/// The ATR historical bytes are those of the OpenPGP card application: command chaining, no
+/// extended lengths, operational life cycle. The ATR itself selects T=1.
+const HISTORICAL_BYTES: [u8; 8] = [0x00, 0x73, 0x00, 0x00, 0x80, 0x05, 0x90, 0x00];
+
+/// Offsets of the APDU template within the PIN verification and modification structures of a
+/// PC_to_RDR_Secure message, counting the leading bPINOperation byte.
+const PIN_VERIFY_APDU_OFFSET: usize = 15;
+const PIN_MODIFY_APDU_OFFSET: usize = 20;
+
+/// T=1 protocol data returned for the parameter commands: Fi/Di 372/1, LRC, BWI 4, CWI 5, IFSC 254.
+const T1_PARAMETERS: [u8; 7] = [0x11, 0x10, 0x00, 0x45, 0x00, 0xFE, 0x00];
+
+pub struct CcidClass<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    bulk_out: EndpointOut<'a, B>,
+    bulk_in: EndpointIn<'a, B>,
+    /// Reassembles a PC_to_RDR message that spans several packets.
+    rx: Vec<u8>,
+    /// Bytes of RDR_to_PC messages that haven't made it into a packet yet.
+    tx: VecDeque<u8>,
+    /// The last packet sent was full, so the transfer has to be terminated with a zero-length packet.
+    tx_zlp: bool,
+    /// Sequence number of the command that is waiting on the card.
+    busy_seq: Option<u8>,
+    requests: VecDeque<CcidRequest>,
+    powered: bool,
+}
+
+impl<B: UsbBus> CcidClass<'_, B> {
+    pub fn new(alloc: &UsbBusAllocator<B>) -> CcidClass<'_, B> {
+        CcidClass {
+            interface: alloc.interface(),
+            bulk_out: alloc.bulk(PACKET_SIZE as u16),
+            bulk_in: alloc.bulk(PACKET_SIZE as u16),
+            rx: Vec::new(),
+            tx: VecDeque::new(),
+            tx_zlp: false,
+            busy_seq: None,
+            requests: VecDeque::new(),
+            powered: false,
+        }
+    }
+
+    /// The next thing the card needs to act on, if any.
+    pub fn take_request(&mut self) -> Option<CcidRequest> { self.requests.pop_front() }
+
+    /// Answer the command that is waiting on the card with `response`, a response APDU.
+    pub fn respond(&mut self, response: &[u8]) {
+        if let Some(seq) = self.busy_seq.take() {
+            self.send(DATA_BLOCK, seq, [self.icc_status(), 0, 0], response);
+        } else {
+            log::warn!("dropping a smart card response that no command is waiting for");
+        }
+    }
+
+    /// Forget all state, as if the reader had been unplugged.
+    pub fn hangup(&mut self) {
+        self.rx.clear();
+        self.tx.clear();
+        self.tx_zlp = false;
+        self.busy_seq = None;
+        self.requests.clear();
+        self.powered = false;
+    }
+
+    fn icc_status(&self) -> u8 { if self.powered { ICC_ACTIVE } else { ICC_INACTIVE } }
+
+    fn send(&mut self, message_type: u8, seq: u8, specific: [u8; 3], data: &[u8]) {
+        let idle = self.tx.is_empty() && !self.tx_zlp;
+        self.tx.push_back(message_type);
+        self.tx.extend((data.len() as u32).to_le_bytes());
+        self.tx.push_back(0); // slot
+        self.tx.push_back(seq);
+        self.tx.extend(specific);
+        self.tx.extend(data.iter().copied());
+        // otherwise the completion of the packet in flight picks this up
+        if idle {
+            self.flush();
+        }
+    }
+
+    fn fail(&mut self, seq: u8, error: u8) {
+        self.send(SLOT_STATUS, seq, [COMMAND_FAILED | self.icc_status(), error, 0], &[]);
+    }
+
+    fn flush(&mut self) {
+        if self.tx_zlp {
+            if self.bulk_in.write(&[]).is_ok() {
+                self.tx_zlp = false;
+            }
+            return;
+        }
+        if self.tx.is_empty() {
+            return;
+        }
+        let len = self.tx.len().min(PACKET_SIZE);
+        let packet: Vec<u8> = self.tx.iter().take(len).copied().collect();
+        match self.bulk_in.write(&packet) {
+            Ok(written) => {
+                self.tx.drain(..written);
+                self.tx_zlp = self.tx.is_empty() && written == PACKET_SIZE;
+            }
+            Err(UsbError::WouldBlock) => {}
+            Err(e) => log::warn!("CCID bulk in error: {:?}", e),
+        }
+    }
+
+    fn atr() -> Vec<u8> {
+        let mut atr = vec![0x3B, 0x80 | HISTORICAL_BYTES.len() as u8, 0x80, 0x01];
+        atr.extend_from_slice(&HISTORICAL_BYTES);
+        // TCK covers everything after TS
+        let tck = atr[1..].iter().fold(0, |acc, b| acc ^ b);
+        atr.push(tck);
+        atr
+    }
+
+    fn handle(&mut self, message: Vec<u8>) {
+        let (message_type, slot, seq) = (message[0], message[5], message[6]);
+        let data = &message[HEADER_LEN..];
+        if slot != 0 {
+            self.fail(seq, BAD_SLOT);
+            return;
+        }
+        if self.busy_seq.is_some() {
+            self.fail(seq, CMD_SLOT_BUSY);
+            return;
+        }
+        log::debug!("CCID message {:x} seq {} len {}", message_type, seq, data.len());
+        match message_type {
+            ICC_POWER_ON => {
+                self.powered = true;
+                self.requests.push_back(CcidRequest::Reset);
+                self.send(DATA_BLOCK, seq, [ICC_ACTIVE, 0, 0], &Self::atr());
+            }
+            ICC_POWER_OFF => {
+                self.powered = false;
+                self.requests.push_back(CcidRequest::Reset);
+                self.send(SLOT_STATUS, seq, [ICC_INACTIVE, 0, 0], &[]);
+            }
+            GET_SLOT_STATUS => self.send(SLOT_STATUS, seq, [self.icc_status(), 0, 0], &[]),
+            GET_PARAMETERS | RESET_PARAMETERS | SET_PARAMETERS => {
+                self.send(PARAMETERS, seq, [self.icc_status(), 0, 0x01], &T1_PARAMETERS)
+            }
+            XFR_BLOCK | SECURE if !self.powered => self.fail(seq, ICC_MUTE),
+            XFR_BLOCK => {
+                self.busy_seq = Some(seq);
+                self.requests.push_back(CcidRequest::Apdu(data.to_vec()));
+            }
+            SECURE => {
+                let request = match data.first() {
+                    Some(0x00) if data.len() > PIN_VERIFY_APDU_OFFSET + 4 => {
+                        CcidRequest::PinVerify(data[PIN_VERIFY_APDU_OFFSET..].to_vec())
+                    }
+                    Some(0x01) if data.len() > PIN_MODIFY_APDU_OFFSET + 4 => {
+                        CcidRequest::PinModify(data[PIN_MODIFY_APDU_OFFSET..].to_vec())
+                    }
+                    _ => {
+                        // the error offset points at the first field we couldn't make sense of
+                        self.fail(seq, HEADER_LEN as u8);
+                        return;
+                    }
+                };
+                self.busy_seq = Some(seq);
+                self.requests.push_back(request);
+            }
+            _ => self.fail(seq, CMD_NOT_SUPPORTED),
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for CcidClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(self.interface, USB_CLASS_CCID, 0x00, 0x00)?;
+        let max_message = (MAX_MESSAGE_LEN as u32).to_le_bytes();
+        #[rustfmt::skip]
+        writer.write(CCID_FUNCTIONAL_DESCRIPTOR, &[
+            0x10, 0x01,             // bcdCCID 1.10
+            0x00,                   // bMaxSlotIndex
+            0x07,                   // bVoltageSupport: 5V, 3V, 1.8V
+            0x02, 0x00, 0x00, 0x00, // dwProtocols: T=1
+            0xA0, 0x0F, 0x00, 0x00, // dwDefaultClock: 4 MHz
+            0xA0, 0x0F, 0x00, 0x00, // dwMaximumClock
+            0x00,                   // bNumClockSupported
+            0x00, 0x2A, 0x00, 0x00, // dwDataRate: 10752 bps
+            0x00, 0x2A, 0x00, 0x00, // dwMaxDataRate
+            0x00,                   // bNumDataRatesSupported
+            0xFE, 0x00, 0x00, 0x00, // dwMaxIFSD
+            0x00, 0x00, 0x00, 0x00, // dwSynchProtocols
+            0x00, 0x00, 0x00, 0x00, // dwMechanical
+            0xBE, 0x00, 0x02, 0x00, // dwFeatures: automatic everything, short APDU level exchange
+            max_message[0], max_message[1], max_message[2], max_message[3], // dwMaxCCIDMessageLength
+            0xFF,                   // bClassGetResponse: echo the APDU class
+            0xFF,                   // bClassEnvelope
+            0x00, 0x00,             // wLcdLayout: none, the PIN prompt is on our own screen
+            0x03,                   // bPINSupport: verification and modification
+            0x01,                   // bMaxCCIDBusySlots
+        ])?;
+        writer.endpoint(&self.bulk_out)?;
+        writer.endpoint(&self.bulk_in)
+    }
+
+    fn reset(&mut self) { self.hangup(); }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+        if req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u8::from(self.interface) as u16
+            && req.request == REQUEST_ABORT
+        {
+            // commands complete as soon as the card answers, so there is nothing to abort
+            xfer.accept().ok();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr != self.bulk_out.address() {
+            return;
+        }
+        let mut packet = [0u8; PACKET_SIZE];
+        let len = match self.bulk_out.read(&mut packet) {
+            Ok(len) => len,
+            Err(UsbError::WouldBlock) => return,
+            Err(e) => {
+                log::warn!("CCID bulk out error: {:?}", e);
+                return;
+            }
+        };
+        self.rx.extend_from_slice(&packet[..len]);
+        if self.rx.len() < HEADER_LEN {
+            return;
+        }
+        let expected = HEADER_LEN + u32::from_le_bytes(self.rx[1..5].try_into().unwrap()) as usize;
+        if expected > MAX_MESSAGE_LEN {
+            let seq = self.rx[6];
+            self.rx.clear();
+            self.fail(seq, BAD_LENGTH);
+        } else if self.rx.len() >= expected {
+            let mut message = std::mem::take(&mut self.rx);
+            message.truncate(expected);
+            self.handle(message);
+        } else if len < PACKET_SIZE {
+            log::warn!("CCID transfer ended {} bytes short; dropping it", expected - self.rx.len());
+            self.rx.clear();
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.bulk_in.address() {
+            self.flush();
+        }
+    }
+}
+
+/// Hand `request` to the server waiting on `listener`.
+pub(crate) fn deliver(listener: &mut xous::MessageEnvelope, request: CcidRequest) {
+    let mut response =
+        unsafe { xous_ipc::Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap()) };
+    let mut buf = response.to_original::<CcidMsgIpc, _>().unwrap();
+    assert_eq!(buf.code, CcidCode::RxWait, "Expected CcidCode::RxWait in wrapper");
+    let (code, data) = match request {
+        CcidRequest::Apdu(data) => (CcidCode::Apdu, data),
+        CcidRequest::PinVerify(data) => (CcidCode::PinVerify, data),
+        CcidRequest::PinModify(data) => (CcidCode::PinModify, data),
+        CcidRequest::Reset => (CcidCode::Reset, Vec::new()),
+    };
+    let len = data.len().min(CCID_MAX_MSG_LEN);
+    buf.data[..len].copy_from_slice(&data[..len]);
+    buf.len = len;
+    buf.code = code;
+    response.replace(buf).unwrap();
+}
@@ -1,49 +1,699 @@
-#![allow(dead_code)]
+//! Hosted mode has no USB controller, so the device is exported over USB/IP on localhost instead. A
+//! Linux host attaches it with `usbip attach -r 127.0.0.1 -b 1-1`, and then talks to the same class
+//! drivers that run on hardware. The host needs the `vhci-hcd` module loaded, and `usbip list -r
+//! 127.0.0.1` shows whichever view is currently connected.
+//!
+//! Each view gets its own `UsbIpBus`, just as each view gets its own `SpinalUsbDevice` on hardware, and
+//! `UsbIpServer` decides which of them is plugged in. URBs from the host are turned into the packets and
+//! events a device controller would produce, and `poll()` reports them to `usb-device` when the main
+//! loop gets an `UsbIrqHandler` message.
 
-pub struct SpinalUdcDescriptor {}
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
 
-pub struct UdcEpStatus {}
+use num_traits::*;
+use usb_device::bus::PollResult;
+use usb_device::{Result, UsbDirection, class_prelude::*};
 
-pub struct SpinalUsbMgmt {}
-impl SpinalUsbMgmt {
-    pub fn print_regs(&self) {}
+use crate::usbip::{self, DeviceInfo, OpRequest, UrbCommand};
+use crate::*;
 
-    pub fn connect_device_core(&mut self, _state: bool) {}
+const NUM_ENDPOINTS: usize = 16;
+/// The port `usbipd` listens on. Can be overridden with the `XOUS_USBIP_PORT` environment variable.
+const USBIP_PORT: u16 = 3240;
+const USBIP_BUSNUM: u32 = 1;
+const USBIP_DEVNUM: u32 = 1;
+/// How long enumeration waits for the main loop to answer each request made on behalf of the host.
+const ENUMERATION_TIMEOUT: Duration = Duration::from_secs(5);
 
-    pub fn is_device_connected(&self) -> bool { false }
+struct Urb {
+    seqnum: u32,
+    dir_in: bool,
+    length: usize,
+    /// What has been gathered so far for an IN transfer.
+    data: Vec<u8>,
+    /// Issued by `UsbIpBus::enumerate()` rather than by the host.
+    internal: bool,
+}
+
+enum Packet {
+    Setup([u8; 8]),
+    /// `seqnum` is set on the last packet of an OUT transfer, which completes once it has been read.
+    Data {
+        data: Vec<u8>,
+        seqnum: Option<u32>,
+    },
+}
+
+#[derive(Copy, Clone)]
+struct EpConfig {
+    max_packet_size: usize,
+}
+
+struct BusState {
+    in_eps: [Option<EpConfig>; NUM_ENDPOINTS],
+    out_eps: [Option<EpConfig>; NUM_ENDPOINTS],
+    /// Bit n is OUT endpoint n, bit n + 16 is IN endpoint n.
+    stalled: u32,
+    out_packets: Vec<VecDeque<Packet>>,
+    out_urbs: Vec<VecDeque<Urb>>,
+    in_urbs: Vec<VecDeque<Urb>>,
+    /// A packet written to an IN endpoint before the host asked for it.
+    in_pending: Vec<Option<Vec<u8>>>,
+    /// The control transfer in progress on ep0, and the ones queued behind it with their setup packet
+    /// and OUT data.
+    control: Option<Urb>,
+    control_queue: VecDeque<(Urb, [u8; 8], Vec<u8>)>,
+    ep_out: u16,
+    ep_in_complete: u16,
+    reset: bool,
+    suspend: bool,
+    /// The connection of the host that imported the device.
+    link: Option<TcpStream>,
+    internal_result: Option<std::result::Result<Vec<u8>, i32>>,
+    info: Option<DeviceInfo>,
+}
+
+impl BusState {
+    fn new() -> Self {
+        BusState {
+            in_eps: [None; NUM_ENDPOINTS],
+            out_eps: [None; NUM_ENDPOINTS],
+            stalled: 0,
+            out_packets: (0..NUM_ENDPOINTS).map(|_| VecDeque::new()).collect(),
+            out_urbs: (0..NUM_ENDPOINTS).map(|_| VecDeque::new()).collect(),
+            in_urbs: (0..NUM_ENDPOINTS).map(|_| VecDeque::new()).collect(),
+            in_pending: vec![None; NUM_ENDPOINTS],
+            control: None,
+            control_queue: VecDeque::new(),
+            ep_out: 0,
+            ep_in_complete: 0,
+            reset: false,
+            suspend: false,
+            link: None,
+            internal_result: None,
+            info: None,
+        }
+    }
+
+    fn max_packet_size(&self, index: usize, dir_in: bool) -> usize {
+        let ep = if dir_in { self.in_eps[index] } else { self.out_eps[index] };
+        ep.map(|ep| ep.max_packet_size).unwrap_or(8)
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        if let Some(link) = self.link.as_mut() {
+            if let Err(e) = link.write_all(message) {
+                log::warn!("USB/IP link lost: {:?}", e);
+                link.shutdown(Shutdown::Both).ok();
+                self.link = None;
+            }
+        }
+    }
+
+    fn finish(&mut self, urb: Urb, status: i32) {
+        if urb.internal {
+            self.internal_result = Some(if status == 0 { Ok(urb.data) } else { Err(status) });
+            return;
+        }
+        let actual_length = match (urb.dir_in, status) {
+            (true, _) => urb.data.len(),
+            (false, 0) => urb.length,
+            (false, _) => 0,
+        };
+        let ret =
+            usbip::ret_submit(urb.seqnum, status, actual_length, if urb.dir_in { &urb.data } else { &[] });
+        self.send(&ret);
+    }
+
+    fn finish_control(&mut self, status: i32) {
+        if let Some(urb) = self.control.take() {
+            self.finish(urb, status);
+        }
+    }
+
+    /// Moves the next queued control request onto ep0. Only `poll()` does this, so a request can't
+    /// start while the device is still stalling or answering the one before it.
+    fn start_control(&mut self) {
+        if self.control.is_some() {
+            return;
+        }
+        if let Some((urb, setup, data)) = self.control_queue.pop_front() {
+            // a SETUP always clears a stalled ep0
+            self.stalled &= !(1 | 1 << 16);
+            self.out_packets[0].push_back(Packet::Setup(setup));
+            let max_packet_size = self.max_packet_size(0, false);
+            for chunk in data.chunks(max_packet_size) {
+                self.out_packets[0].push_back(Packet::Data { data: chunk.to_vec(), seqnum: None });
+            }
+            self.control = Some(urb);
+        }
+    }
+
+    /// Fails everything the host is waiting for, which is what a disconnect looks like to the host.
+    fn clear(&mut self) {
+        let queued: Vec<Urb> = self.control_queue.drain(..).map(|(urb, _, _)| urb).collect();
+        for urb in self.control.take().into_iter().chain(queued) {
+            self.finish(urb, usbip::ECONNRESET);
+        }
+        for index in 0..NUM_ENDPOINTS {
+            while let Some(urb) = self.in_urbs[index].pop_front() {
+                self.finish(urb, usbip::ECONNRESET);
+            }
+            while let Some(urb) = self.out_urbs[index].pop_front() {
+                self.finish(urb, usbip::ECONNRESET);
+            }
+            self.out_packets[index].clear();
+            self.in_pending[index] = None;
+        }
+        self.ep_out = 0;
+        self.ep_in_complete = 0;
+    }
+
+    fn submit(&mut self, urb: Urb, ep: usize, setup: [u8; 8], data: Vec<u8>) {
+        if ep == 0 {
+            self.control_queue.push_back((urb, setup, data));
+            return;
+        }
+        let stall_bit = if urb.dir_in { 1 << (ep + 16) } else { 1 << ep };
+        let allocated = if urb.dir_in { self.in_eps[ep].is_some() } else { self.out_eps[ep].is_some() };
+        if !allocated || self.stalled & stall_bit != 0 {
+            self.finish(urb, usbip::EPIPE);
+            return;
+        }
+        let max_packet_size = self.max_packet_size(ep, urb.dir_in);
+        if urb.dir_in {
+            self.in_urbs[ep].push_back(urb);
+            if let Some(packet) = self.in_pending[ep].take() {
+                self.fill_in_urb(ep, &packet, max_packet_size);
+            }
+        } else {
+            let seqnum = urb.seqnum;
+            let mut chunks: Vec<&[u8]> = data.chunks(max_packet_size).collect();
+            if chunks.is_empty() {
+                chunks.push(&[]);
+            }
+            let last = chunks.len() - 1;
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let seqnum = if i == last { Some(seqnum) } else { None };
+                self.out_packets[ep].push_back(Packet::Data { data: chunk.to_vec(), seqnum });
+            }
+            self.out_urbs[ep].push_back(urb);
+            self.ep_out |= 1 << ep;
+        }
+    }
+
+    /// Hands a packet written by the device to the oldest IN transfer on `ep`, completing it if the
+    /// packet is short or fills the transfer.
+    fn fill_in_urb(&mut self, ep: usize, packet: &[u8], max_packet_size: usize) {
+        if let Some(mut urb) = self.in_urbs[ep].pop_front() {
+            let room = urb.length - urb.data.len();
+            urb.data.extend_from_slice(&packet[..packet.len().min(room)]);
+            if packet.len() < max_packet_size || urb.data.len() >= urb.length {
+                self.finish(urb, 0);
+            } else {
+                self.in_urbs[ep].push_front(urb);
+            }
+            self.ep_in_complete |= 1 << ep;
+        }
+    }
+
+    fn unlink(&mut self, victim: u32) -> bool {
+        if self.control.as_ref().map(|urb| urb.seqnum) == Some(victim) {
+            self.control = None;
+            // drop what's left of the cancelled request; the requests queued behind it aren't on ep0 yet
+            self.out_packets[0].clear();
+            return true;
+        }
+        if let Some(position) = self.control_queue.iter().position(|(urb, _, _)| urb.seqnum == victim) {
+            self.control_queue.remove(position);
+            return true;
+        }
+        for ep in 1..NUM_ENDPOINTS {
+            if let Some(position) = self.in_urbs[ep].iter().position(|urb| urb.seqnum == victim) {
+                self.in_urbs[ep].remove(position);
+                return true;
+            }
+            if let Some(position) = self.out_urbs[ep].iter().position(|urb| urb.seqnum == victim) {
+                self.out_urbs[ep].remove(position);
+                // the URB owns every packet up to and including its last one
+                let mut owned = true;
+                self.out_packets[ep].retain(|packet| {
+                    let keep = !owned;
+                    if let Packet::Data { seqnum: Some(seqnum), .. } = packet {
+                        if *seqnum == victim {
+                            owned = false;
+                        }
+                    }
+                    keep
+                });
+                return true;
+            }
+        }
+        false
+    }
+}
+
+struct Shared {
+    state: Mutex<BusState>,
+    /// Signalled whenever an internal request completes or a reset is taken.
+    cv: Condvar,
+    conn: xous::CID,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, BusState> { self.state.lock().unwrap() }
+
+    /// The equivalent of the controller raising its interrupt.
+    fn kick(&self) {
+        xous::try_send_message(
+            self.conn,
+            xous::Message::new_scalar(Opcode::UsbIrqHandler.to_usize().unwrap(), 0, 0, 0, 0),
+        )
+        .ok();
+    }
 
-    pub fn disable_debug(&mut self, _disable: bool) {}
+    fn control_request(&self, setup: [u8; 8]) -> std::result::Result<Vec<u8>, i32> {
+        let length = u16::from_le_bytes([setup[6], setup[7]]) as usize;
+        let urb = Urb { seqnum: 0, dir_in: setup[0] & 0x80 != 0, length, data: Vec::new(), internal: true };
+        let mut state = self.lock();
+        state.internal_result = None;
+        state.submit(urb, 0, setup, Vec::new());
+        drop(state);
+        self.kick();
+        let state = self.lock();
+        let (mut state, _) = self
+            .cv
+            .wait_timeout_while(state, ENUMERATION_TIMEOUT, |state| state.internal_result.is_none())
+            .unwrap();
+        state.internal_result.take().unwrap_or(Err(usbip::ETIMEDOUT))
+    }
 
-    pub fn get_disable_debug(&self) -> bool { false }
+    /// Does what a hub and the host's enumeration would have done before handing the device over:
+    /// reset it, give it an address and read its descriptors.
+    fn enumerate(&self) -> Option<DeviceInfo> {
+        let mut state = self.lock();
+        state.clear();
+        state.reset = true;
+        drop(state);
+        self.kick();
+        let state = self.lock();
+        let (state, _) = self.cv.wait_timeout_while(state, ENUMERATION_TIMEOUT, |state| state.reset).unwrap();
+        if state.reset {
+            log::warn!("USB/IP: the device didn't take the reset");
+            return None;
+        }
+        drop(state);
+        self.control_request([0x00, 0x05, USBIP_DEVNUM as u8, 0, 0, 0, 0, 0]).ok()?;
+        let device = self.control_request([0x80, 0x06, 0x00, 0x01, 0, 0, 18, 0]).ok()?;
+        let header = self.control_request([0x80, 0x06, 0x00, 0x02, 0, 0, 9, 0]).ok()?;
+        if header.len() < 4 {
+            return None;
+        }
+        let config = self.control_request([0x80, 0x06, 0x00, 0x02, 0, 0, header[2], header[3]]).ok()?;
+        let info = DeviceInfo::from_descriptors(USBIP_BUSNUM, USBIP_DEVNUM, &device, &config);
+        self.lock().info = info.clone();
+        info
+    }
 
-    pub fn xous_suspend(&mut self) {}
+    fn detach(&self) {
+        let mut state = self.lock();
+        if let Some(link) = state.link.take() {
+            link.shutdown(Shutdown::Both).ok();
+        }
+        state.clear();
+        state.suspend = true;
+        drop(state);
+        self.kick();
+    }
+}
 
-    pub fn xous_resume1(&mut self) {}
+/// A `UsbBus` whose wire is a USB/IP connection.
+pub struct UsbIpBus {
+    shared: Arc<Shared>,
+}
 
-    pub fn xous_resume2(&mut self) {}
+impl UsbBus for UsbIpBus {
+    fn alloc_ep(
+        &mut self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        _interval: u8,
+    ) -> Result<EndpointAddress> {
+        if ep_type == EndpointType::Isochronous {
+            return Err(UsbError::Unsupported);
+        }
+        let mut state = self.shared.lock();
+        let eps = match ep_dir {
+            UsbDirection::In => &mut state.in_eps,
+            UsbDirection::Out => &mut state.out_eps,
+        };
+        for index in ep_addr.map(|a| a.index()..a.index() + 1).unwrap_or(1..NUM_ENDPOINTS) {
+            if index < NUM_ENDPOINTS && eps[index].is_none() {
+                eps[index] = Some(EpConfig { max_packet_size: max_packet_size as usize });
+                log::debug!("alloc ep{} type {:?} dir {:?} ({})", index, ep_type, ep_dir, max_packet_size);
+                return Ok(EndpointAddress::from_parts(index, ep_dir));
+            }
+        }
+        Err(match ep_addr {
+            Some(_) => UsbError::InvalidEndpoint,
+            None => UsbError::EndpointOverflow,
+        })
+    }
+
+    fn enable(&mut self) {}
+
+    fn reset(&self) {
+        log::info!("USB reset");
+        let mut state = self.shared.lock();
+        state.stalled = 0;
+        for pending in state.in_pending.iter_mut() {
+            *pending = None;
+        }
+    }
+
+    fn set_device_address(&self, addr: u8) {
+        log::debug!("USB address {}", addr);
+    }
+
+    fn write(&self, ep_addr: EndpointAddress, buf: &[u8]) -> Result<usize> {
+        let index = ep_addr.index();
+        let mut state = self.shared.lock();
+        if index >= NUM_ENDPOINTS || state.in_eps[index].is_none() {
+            return Err(UsbError::InvalidEndpoint);
+        }
+        let max_packet_size = state.max_packet_size(index, true);
+        if index == 0 {
+            match state.control.take() {
+                Some(mut urb) if urb.dir_in && urb.length > 0 => {
+                    let room = urb.length - urb.data.len();
+                    urb.data.extend_from_slice(&buf[..buf.len().min(room)]);
+                    let done = buf.len() < max_packet_size || urb.data.len() >= urb.length;
+                    state.control = Some(urb);
+                    if done {
+                        // the host acknowledges an IN data stage with a zero length OUT
+                        state.out_packets[0].push_back(Packet::Data { data: Vec::new(), seqnum: None });
+                        state.finish_control(0);
+                    }
+                }
+                // the status stage of a request without an IN data stage
+                Some(urb) => {
+                    state.control = Some(urb);
+                    state.finish_control(0);
+                }
+                // cancelled by the host
+                None => {}
+            }
+            state.ep_in_complete |= 1;
+        } else if !state.in_urbs[index].is_empty() {
+            state.fill_in_urb(index, buf, max_packet_size);
+        } else if state.in_pending[index].is_none() {
+            state.in_pending[index] = Some(buf.to_vec());
+            return Ok(buf.len());
+        } else {
+            return Err(UsbError::WouldBlock);
+        }
+        drop(state);
+        self.shared.cv.notify_all();
+        self.shared.kick();
+        Ok(buf.len())
+    }
+
+    fn read(&self, ep_addr: EndpointAddress, buf: &mut [u8]) -> Result<usize> {
+        let index = ep_addr.index();
+        let mut state = self.shared.lock();
+        if index >= NUM_ENDPOINTS {
+            return Err(UsbError::InvalidEndpoint);
+        }
+        let len = match state.out_packets[index].front() {
+            Some(Packet::Setup(setup)) if buf.len() >= setup.len() => {
+                buf[..setup.len()].copy_from_slice(setup);
+                setup.len()
+            }
+            Some(Packet::Data { data, .. }) if buf.len() >= data.len() => {
+                buf[..data.len()].copy_from_slice(data);
+                data.len()
+            }
+            Some(_) => return Err(UsbError::BufferOverflow),
+            None => return Err(UsbError::WouldBlock),
+        };
+        if let Some(Packet::Data { seqnum: Some(seqnum), .. }) = state.out_packets[index].pop_front() {
+            if let Some(position) = state.out_urbs[index].iter().position(|urb| urb.seqnum == seqnum) {
+                let urb = state.out_urbs[index].remove(position).unwrap();
+                state.finish(urb, 0);
+            }
+        }
+        let more = !state.out_packets[index].is_empty();
+        if more && index != 0 {
+            state.ep_out |= 1 << index;
+        }
+        drop(state);
+        if more {
+            self.shared.kick();
+        }
+        Ok(len)
+    }
+
+    fn set_stalled(&self, ep_addr: EndpointAddress, stalled: bool) {
+        let index = ep_addr.index();
+        let dir_in = ep_addr.direction() == UsbDirection::In;
+        let bit = if dir_in { 1 << (index + 16) } else { 1 << index };
+        let mut state = self.shared.lock();
+        if !stalled {
+            state.stalled &= !bit;
+            return;
+        }
+        state.stalled |= bit;
+        if index == 0 {
+            // the request was refused: skip the rest of it, but not the setup of the next one
+            while let Some(Packet::Data { .. }) = state.out_packets[0].front() {
+                state.out_packets[0].pop_front();
+            }
+            if state.control.is_some() {
+                state.finish_control(usbip::EPIPE);
+            }
+            if !state.control_queue.is_empty() {
+                drop(state);
+                self.shared.cv.notify_all();
+                self.shared.kick();
+                return;
+            }
+        } else if dir_in {
+            while let Some(urb) = state.in_urbs[index].pop_front() {
+                state.finish(urb, usbip::EPIPE);
+            }
+        } else {
+            while let Some(urb) = state.out_urbs[index].pop_front() {
+                state.finish(urb, usbip::EPIPE);
+            }
+            state.out_packets[index].clear();
+        }
+        drop(state);
+        self.shared.cv.notify_all();
+    }
 
-    pub fn descriptor_from_status(&self, _ep_status: &UdcEpStatus) -> SpinalUdcDescriptor {
-        SpinalUdcDescriptor {}
+    fn is_stalled(&self, ep_addr: EndpointAddress) -> bool {
+        let bit = match ep_addr.direction() {
+            UsbDirection::In => 1 << (ep_addr.index() + 16),
+            UsbDirection::Out => 1 << ep_addr.index(),
+        };
+        self.shared.lock().stalled & bit != 0
     }
 
-    pub fn status_from_index(&self, _index: usize) -> UdcEpStatus { UdcEpStatus {} }
+    fn suspend(&self) {
+        log::info!("USB/IP host detached");
+    }
+
+    fn resume(&self) {
+        log::info!("USB/IP host attached");
+    }
+
+    fn poll(&self) -> PollResult {
+        let mut state = self.shared.lock();
+        let result = if state.reset {
+            state.reset = false;
+            self.shared.cv.notify_all();
+            PollResult::Reset
+        } else if state.suspend {
+            state.suspend = false;
+            PollResult::Suspend
+        } else {
+            state.start_control();
+            // ep0 is always read when it's reported, so it's reported for as long as it has packets
+            let (ep_setup, ep0_out) = match state.out_packets[0].front() {
+                Some(Packet::Setup(_)) => (1, 0),
+                Some(Packet::Data { .. }) => (0, 1),
+                None => (0, 0),
+            };
+            let ep_out = state.ep_out | ep0_out;
+            let ep_in_complete = state.ep_in_complete;
+            state.ep_out = 0;
+            state.ep_in_complete = 0;
+            if ep_out | ep_in_complete | ep_setup == 0 {
+                PollResult::None
+            } else {
+                PollResult::Data { ep_out, ep_in_complete, ep_setup }
+            }
+        };
+        let more = match result {
+            PollResult::Reset | PollResult::Suspend => {
+                state.suspend
+                    || state.ep_out != 0
+                    || state.ep_in_complete != 0
+                    || !state.out_packets[0].is_empty()
+            }
+            _ => false,
+        };
+        drop(state);
+        if more {
+            self.shared.kick();
+        }
+        result
+    }
+
+    fn force_reset(&self) -> Result<()> {
+        // the host sees a disconnect, and has to attach again
+        self.shared.detach();
+        Ok(())
+    }
+}
+
+struct ServerState {
+    buses: BTreeMap<usize, Arc<Shared>>,
+    exported: Option<usize>,
+}
+
+/// Listens for USB/IP hosts, and offers them whichever view is connected. Its methods mirror those of
+/// `SpinalUsbMgmt` so the hosted main loop reads like the hardware one.
+pub struct UsbIpServer {
+    state: Arc<Mutex<ServerState>>,
+    conn: xous::CID,
+    debug_disabled: bool,
 }
-pub struct SpinalUsbDevice {}
 
-impl SpinalUsbDevice {
-    pub fn new(_sid: xous::SID, _view: std::sync::Arc<std::sync::atomic::AtomicUsize>) -> SpinalUsbDevice {
-        SpinalUsbDevice {}
+impl UsbIpServer {
+    /// Starts listening; `conn` is where the buses send their `UsbIrqHandler` messages.
+    pub fn new(conn: xous::CID) -> UsbIpServer {
+        let state = Arc::new(Mutex::new(ServerState { buses: BTreeMap::new(), exported: None }));
+        let port = std::env::var("XOUS_USBIP_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(USBIP_PORT);
+        match TcpListener::bind(("127.0.0.1", port)) {
+            Ok(listener) => {
+                log::info!("USB/IP server listening on 127.0.0.1:{}", port);
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    for stream in listener.incoming() {
+                        match stream {
+                            Ok(stream) => {
+                                let state = state.clone();
+                                std::thread::spawn(move || serve(state, stream));
+                            }
+                            Err(e) => log::warn!("USB/IP accept failed: {:?}", e),
+                        }
+                    }
+                });
+            }
+            // hosted mode is still useful without USB, so this isn't fatal
+            Err(e) => log::warn!("couldn't listen for USB/IP on port {}: {:?}", port, e),
+        }
+        UsbIpServer { state, conn, debug_disabled: false }
+    }
+
+    /// Creates the bus for a view. Only one of them is plugged in at a time, see `connect_view()`.
+    pub fn bus(&self, view: usize) -> UsbIpBus {
+        let shared =
+            Arc::new(Shared { state: Mutex::new(BusState::new()), cv: Condvar::new(), conn: self.conn });
+        self.state.lock().unwrap().buses.insert(view, shared.clone());
+        UsbIpBus { shared }
     }
 
-    pub fn get_iface(&self) -> SpinalUsbMgmt { SpinalUsbMgmt {} }
+    /// Plugs in the bus of `view`, or unplugs the device if `None`. A host that had imported a view
+    /// that is no longer plugged in sees it disconnect.
+    pub fn connect_view(&mut self, view: Option<usize>) {
+        let mut state = self.state.lock().unwrap();
+        if state.exported != view {
+            if let Some(bus) = state.exported.and_then(|v| state.buses.get(&v)) {
+                bus.detach();
+                bus.lock().info = None;
+            }
+            state.exported = view;
+        }
+    }
 
-    pub fn print_ep_stats(&self) {}
+    pub fn is_device_connected(&self) -> bool { self.state.lock().unwrap().exported.is_some() }
 
-    pub fn print_regs(&self) {}
+    pub fn disable_debug(&mut self, disable: bool) { self.debug_disabled = disable; }
 
-    /// simple but easy to understand allocator for buffers inside the descriptor memory space
-    pub fn alloc_region(&mut self, _requested: usize) -> Option<u32> { None }
+    pub fn get_disable_debug(&self) -> bool { self.debug_disabled }
+}
 
-    /// returns `true` if the region was available to be deallocated
-    pub fn dealloc_region(&mut self, _offset: usize) -> bool { false }
+fn serve(state: Arc<Mutex<ServerState>>, mut stream: TcpStream) {
+    stream.set_nodelay(true).ok();
+    let request = match usbip::read_op_request(&mut stream) {
+        Ok(request) => request,
+        Err(e) => {
+            log::warn!("bad USB/IP request: {:?}", e);
+            return;
+        }
+    };
+    let exported = {
+        let state = state.lock().unwrap();
+        state.exported.and_then(|view| state.buses.get(&view).cloned())
+    };
+    match request {
+        OpRequest::DevList => {
+            let info = exported.and_then(|bus| {
+                let cached = bus.lock().info.clone();
+                let attached = bus.lock().link.is_some();
+                if cached.is_some() || attached { cached } else { bus.enumerate() }
+            });
+            stream
+                .write_all(&usbip::devlist_reply(info.as_ref().map(std::slice::from_ref).unwrap_or(&[])))
+                .ok();
+        }
+        OpRequest::Import { busid } => {
+            let bus = exported
+                .filter(|_| busid == format!("{}-{}", USBIP_BUSNUM, USBIP_DEVNUM))
+                .filter(|bus| bus.lock().link.is_none());
+            let info = bus.as_ref().and_then(|bus| bus.enumerate());
+            if stream.write_all(&usbip::import_reply(info.as_ref())).is_err() || info.is_none() {
+                log::info!("USB/IP import of {} refused", busid);
+                return;
+            }
+            let bus = bus.unwrap();
+            bus.lock().link = stream.try_clone().ok();
+            log::info!("USB/IP host imported {}", busid);
+            loop {
+                match usbip::read_urb_command(&mut stream) {
+                    Ok(UrbCommand::Submit { seqnum, ep, dir_in, length, setup, data, iso_packets }) => {
+                        let urb = Urb { seqnum, dir_in, length, data: Vec::new(), internal: false };
+                        let mut state = bus.lock();
+                        if iso_packets > 0 {
+                            state.finish(urb, usbip::EPIPE);
+                        } else {
+                            state.submit(urb, ep as usize, setup, data);
+                        }
+                        drop(state);
+                        bus.kick();
+                    }
+                    Ok(UrbCommand::Unlink { seqnum, victim }) => {
+                        let mut state = bus.lock();
+                        let status = if state.unlink(victim) { usbip::ECONNRESET } else { 0 };
+                        state.send(&usbip::ret_unlink(seqnum, status));
+                        drop(state);
+                        bus.kick();
+                    }
+                    Err(e) => {
+                        log::info!("USB/IP host detached: {:?}", e);
+                        break;
+                    }
+                }
+            }
+            bus.detach();
+        }
+    }
 }
@@ -0,0 +1,66 @@
+//! Interface association descriptors. A function that spans several interfaces, like CDC-ACM with its
+//! communication and data interfaces, has to be grouped with an IAD when it shares a device with other
+//! functions; otherwise Windows binds a driver to the first interface only. `usbd-serial` predates IADs,
+//! so this wraps a class and writes the IAD in front of its descriptors.
+
+use core::ops::{Deref, DerefMut};
+
+use usb_device::Result;
+use usb_device::class_prelude::*;
+
+/// bDescriptorType for an interface association
+const INTERFACE_ASSOCIATION: u8 = 0x0b;
+
+pub(crate) struct Associated<C> {
+    class: C,
+    first_interface: u8,
+    interface_count: u8,
+    function: (u8, u8, u8),
+}
+
+impl<C> Associated<C> {
+    /// `first_interface` has to be the number the allocator gave the first interface of `class`.
+    /// `function` is the (class, subclass, protocol) triple the host matches drivers against.
+    pub(crate) fn new(class: C, first_interface: u8, interface_count: u8, function: (u8, u8, u8)) -> Self {
+        Associated { class, first_interface, interface_count, function }
+    }
+}
+
+impl<C> Deref for Associated<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C { &self.class }
+}
+
+impl<C> DerefMut for Associated<C> {
+    fn deref_mut(&mut self) -> &mut C { &mut self.class }
+}
+
+impl<B: UsbBus, C: UsbClass<B>> UsbClass<B> for Associated<C> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        let (class, sub_class, protocol) = self.function;
+        writer.write(
+            INTERFACE_ASSOCIATION,
+            &[self.first_interface, self.interface_count, class, sub_class, protocol, 0],
+        )?;
+        self.class.get_configuration_descriptors(writer)
+    }
+
+    fn get_string(&self, index: StringIndex, lang_id: u16) -> Option<&str> {
+        self.class.get_string(index, lang_id)
+    }
+
+    fn reset(&mut self) { self.class.reset() }
+
+    fn poll(&mut self) { self.class.poll() }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) { self.class.control_out(xfer) }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) { self.class.control_in(xfer) }
+
+    fn endpoint_setup(&mut self, addr: EndpointAddress) { self.class.endpoint_setup(addr) }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) { self.class.endpoint_out(addr) }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) { self.class.endpoint_in_complete(addr) }
+}
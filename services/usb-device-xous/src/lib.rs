@@ -109,6 +109,53 @@ impl UsbHid {
         }
     }
 
+    /// Switches to the composite core, presenting `functions` together. Always triggers a reset, like
+    /// `switch_to_core`.
+    pub fn switch_to_composite(&self, functions: CompositeFunctions) -> Result<(), xous::Error> {
+        if functions.is_empty() {
+            return Err(xous::Error::InvalidLimit);
+        }
+        match send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                Opcode::SwitchCores.to_usize().unwrap(),
+                UsbDeviceType::Composite as usize,
+                functions.bits(),
+                0,
+                0,
+            ),
+        ) {
+            Ok(xous::Result::Scalar1(code)) => match code {
+                0 => Ok(()),
+                _ => Err(xous::Error::InternalError),
+            },
+            _ => panic!("Internal error: illegal return type"),
+        }
+    }
+
+    /// this will not trigger a reset if the composite core is already up with exactly `functions`
+    pub fn ensure_composite(&self, functions: CompositeFunctions) -> Result<(), xous::Error> {
+        if functions.is_empty() {
+            return Err(xous::Error::InvalidLimit);
+        }
+        match send_message(
+            self.conn,
+            Message::new_blocking_scalar(
+                Opcode::EnsureCore.to_usize().unwrap(),
+                UsbDeviceType::Composite as usize,
+                functions.bits(),
+                0,
+                0,
+            ),
+        ) {
+            Ok(xous::Result::Scalar1(code)) => match code {
+                0 => Ok(()),
+                _ => Err(xous::Error::InternalError),
+            },
+            _ => panic!("Internal error: illegal return type"),
+        }
+    }
+
     pub fn get_current_core(&self) -> Result<UsbDeviceType, xous::Error> {
         match send_message(
             self.conn,
@@ -121,6 +168,10 @@ impl UsbHid {
                 #[cfg(feature = "mass-storage")]
                 3 => Ok(UsbDeviceType::MassStorage),
                 4 => Ok(UsbDeviceType::Serial),
+                5 => Ok(UsbDeviceType::HIDv2),
+                6 => Ok(UsbDeviceType::Ccid),
+                7 => Ok(UsbDeviceType::Ncm),
+                8 => Ok(UsbDeviceType::Composite),
                 _ => Err(xous::Error::InternalError),
             },
             _ => panic!("Internal error: illegal return type"),
@@ -307,6 +358,80 @@ impl UsbHid {
         }
     }
 
+    /// Blocks until the host has something for the smart card to do. The first caller of this or
+    /// `ccid_send()` becomes the card; calls from any other process are denied.
+    pub fn ccid_wait_incoming(&self) -> Result<CcidRequest, xous::Error> {
+        let req = CcidMsgIpc { data: [0; CCID_MAX_MSG_LEN], len: 0, code: CcidCode::RxWait };
+        let mut buf = Buffer::into_buf(req).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::CcidRxDeferred.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let ack = buf.to_original::<CcidMsgIpc, _>().unwrap();
+        let data = ack.data[..ack.len].to_vec();
+        match ack.code {
+            CcidCode::Apdu => Ok(CcidRequest::Apdu(data)),
+            CcidCode::PinVerify => Ok(CcidRequest::PinVerify(data)),
+            CcidCode::PinModify => Ok(CcidRequest::PinModify(data)),
+            CcidCode::Reset => Ok(CcidRequest::Reset),
+            CcidCode::Hangup => Err(xous::Error::ProcessTerminated),
+            CcidCode::Denied => Err(xous::Error::AccessDenied),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
+    /// Answer the request last returned by `ccid_wait_incoming()` with a response APDU.
+    pub fn ccid_send(&self, response: &[u8]) -> Result<(), xous::Error> {
+        if response.len() > CCID_MAX_MSG_LEN {
+            return Err(xous::Error::OutOfMemory);
+        }
+        let mut req = CcidMsgIpc { data: [0; CCID_MAX_MSG_LEN], len: response.len(), code: CcidCode::Tx };
+        req.data[..response.len()].copy_from_slice(response);
+        let mut buf = Buffer::into_buf(req).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::CcidTx.to_u32().unwrap()).or(Err(xous::Error::InternalError))?;
+        let ack = buf.to_original::<CcidMsgIpc, _>().unwrap();
+        match ack.code {
+            CcidCode::TxAck => Ok(()),
+            CcidCode::Denied => Err(xous::Error::AccessDenied),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
+    /// Blocks until the host sends an Ethernet frame on the network interface. The first caller of
+    /// this or `ncm_send_frame()` owns the interface; calls from any other process are denied.
+    /// Returns `ProcessTerminated` when the link goes down.
+    pub fn ncm_wait_frame(&self) -> Result<Vec<u8>, xous::Error> {
+        let req = NcmMsgIpc { data: [0; NCM_MAX_FRAME_LEN], len: 0, code: NcmCode::RxWait };
+        let mut buf = Buffer::into_buf(req).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::NcmRxDeferred.to_u32().unwrap())
+            .or(Err(xous::Error::InternalError))?;
+        let ack = buf.to_original::<NcmMsgIpc, _>().unwrap();
+        match ack.code {
+            NcmCode::Frame => Ok(ack.data[..ack.len].to_vec()),
+            NcmCode::Hangup => Err(xous::Error::ProcessTerminated),
+            NcmCode::Denied => Err(xous::Error::AccessDenied),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
+    /// Sends an Ethernet frame to the host. Frames are dropped with `ProcessTerminated` while the
+    /// link is down, and with `ServerQueueFull` when the host isn't keeping up.
+    pub fn ncm_send_frame(&self, frame: &[u8]) -> Result<(), xous::Error> {
+        if frame.len() > NCM_MAX_FRAME_LEN {
+            return Err(xous::Error::OutOfMemory);
+        }
+        let mut req = NcmMsgIpc { data: [0; NCM_MAX_FRAME_LEN], len: frame.len(), code: NcmCode::Tx };
+        req.data[..frame.len()].copy_from_slice(frame);
+        let mut buf = Buffer::into_buf(req).or(Err(xous::Error::InternalError))?;
+        buf.lend_mut(self.conn, Opcode::NcmTx.to_u32().unwrap()).or(Err(xous::Error::InternalError))?;
+        let ack = buf.to_original::<NcmMsgIpc, _>().unwrap();
+        match ack.code {
+            NcmCode::TxAck => Ok(()),
+            NcmCode::Hangup => Err(xous::Error::ProcessTerminated),
+            NcmCode::Busy => Err(xous::Error::ServerQueueFull),
+            NcmCode::Denied => Err(xous::Error::AccessDenied),
+            _ => Err(xous::Error::InternalError),
+        }
+    }
+
     /// Blocks until an ASCII string terminated by `delimiter` is received on serial; if `None`, it
     /// will return as soon as a character (or series of characters) have been received (thus the return
     /// `String` will be piecemeal)
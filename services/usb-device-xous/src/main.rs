@@ -21,13 +21,24 @@ mod spinal_udc;
 use packed_struct::PackedStructSlice;
 #[cfg(any(feature = "precursor", feature = "renode"))]
 use spinal_udc::*;
-#[cfg(all(any(feature = "precursor", feature = "renode"), feature = "mass-storage"))]
+#[cfg(all(
+    any(feature = "precursor", feature = "renode", not(target_os = "xous")),
+    feature = "mass-storage"
+))]
 mod apps_block_device;
 
+#[cfg(any(feature = "precursor", feature = "renode", not(target_os = "xous")))]
+mod ccid;
 #[cfg(any(feature = "precursor", feature = "renode"))]
 mod hid;
 #[cfg(not(target_os = "xous"))]
 mod hosted;
+#[cfg(any(feature = "precursor", feature = "renode"))]
+mod iad;
+#[cfg(any(feature = "precursor", feature = "renode", not(target_os = "xous")))]
+mod ncm;
+#[cfg(not(target_os = "xous"))]
+mod usbip;
 use std::collections::BTreeMap;
 
 #[cfg(not(target_os = "xous"))]
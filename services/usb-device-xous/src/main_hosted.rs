@@ -1,16 +1,50 @@
 use core::num::NonZeroU8;
-use core::sync::atomic::AtomicUsize;
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::convert::TryInto;
 
+use keyboard::KeyMap;
 use num_traits::*;
+use packed_struct::PackedStructSlice;
+use usb_device::class_prelude::*;
+use usb_device::prelude::*;
+use usb_device_xous::KeyboardLedsReport;
+use usb_device_xous::UsbDeviceType;
+use usbd_serial::SerialPort;
 use xous::{msg_blocking_scalar_unpack, msg_scalar_unpack};
 use xous_ipc::Buffer;
-use xous_semver::SemVer;
+use xous_usb_hid::device::DeviceClass;
+use xous_usb_hid::device::fido::RawFido;
+use xous_usb_hid::device::fido::RawFidoConfig;
 use xous_usb_hid::device::fido::RawFidoReport;
+use xous_usb_hid::device::keyboard::{NKROBootKeyboard, NKROBootKeyboardConfig};
+use xous_usb_hid::page::Keyboard;
+use xous_usb_hid::prelude::*;
 
 use crate::*;
 
+/// The views that can be exported over USB/IP. The numbering matches `main_hw`; HIDv2 and the composite
+/// device aren't offered in hosted mode.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+#[repr(usize)]
+enum Views {
+    FidoWithKbd = 0,
+    FidoOnly = 1,
+    #[cfg(feature = "mass-storage")]
+    MassStorage = 2,
+    Serial = 3,
+    Ccid = 5,
+    Ncm = 6,
+}
+
+/// Same meanings as in `main_hw`.
+#[derive(Debug)]
+enum SerialListenMode {
+    NoListener,
+    AsciiListener(Option<char>),
+    BinaryListener,
+    ConsoleListener,
+}
+
 pub(crate) fn main_hosted() -> ! {
     log_server::init_wait().unwrap();
     log::set_max_level(log::LevelFilter::Info);
@@ -22,68 +56,107 @@ pub(crate) fn main_hosted() -> ! {
     let llio = llio::Llio::new(&xns);
     let tt = ticktimer_server::Ticktimer::new().unwrap();
 
-    let minimum_ver = SemVer { maj: 0, min: 9, rev: 8, extra: 20, commit: None };
-    let soc_ver = llio.soc_gitrev().unwrap();
-    if soc_ver < minimum_ver {
-        if soc_ver.min != 0 {
-            // don't show during hosted mode, which reports 0.0.0+0
-            tt.sleep_ms(1500).ok(); // wait for some system boot to happen before popping up the modal
-            let modals = modals::Modals::new(&xns).unwrap();
-            modals.show_notification(
-                &format!("SoC version >= 0.9.8+20 required for USB HID. Detected rev: {}. Refusing to start USB driver.",
-                soc_ver.to_string()
-            ),
-                None
-            ).unwrap();
-        }
-        let mut fido_listener: Option<xous::MessageEnvelope> = None;
-        loop {
-            let msg = xous::receive_message(usbdev_sid).unwrap();
-            match FromPrimitive::from_usize(msg.body.id()) {
-                Some(Opcode::DebugUsbOp) => {
-                    msg_blocking_scalar_unpack!(msg, _update_req, _new_state, _, _, {
-                        xous::return_scalar2(msg.sender, 0, 1).expect("couldn't return status");
-                    })
-                }
-                Some(Opcode::U2fRxDeferred) => {
-                    // block any rx requests forever
-                    fido_listener = Some(msg);
-                }
-                Some(Opcode::IsSocCompatible) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
-                    xous::return_scalar(msg.sender, 0).expect("couldn't return compatibility status")
-                }),
-                Some(Opcode::Quit) => {
-                    break;
-                }
-                _ => {
-                    log::warn!("SoC not compatible with HID, ignoring USB message: {:?}", msg);
-                    // make it so blocking scalars don't block
-                    if let xous::Message::BlockingScalar(xous::ScalarMessage {
-                        id: _,
-                        arg1: _,
-                        arg2: _,
-                        arg3: _,
-                        arg4: _,
-                    }) = msg.body
-                    {
-                        log::warn!("Returning bogus result");
-                        xous::return_scalar(msg.sender, 0).unwrap();
-                    }
-                }
-            }
-        }
-        log::info!("consuming listener: {:?}", fido_listener);
-    }
-
-    let view = Arc::new(AtomicUsize::new(0));
-    let usbdev = SpinalUsbDevice::new(usbdev_sid, view.clone());
-    let mut usbmgmt = usbdev.get_iface();
+    // there is no SoC to check the revision of, so unlike `main_hw` this goes straight to the device
+    let cid = xous::connect(usbdev_sid).expect("couldn't create suspend callback connection");
+    let native_kbd = keyboard::Keyboard::new(&xns).unwrap();
+    let serial_number = format!("{:x}", llio.soc_dna().unwrap_or(0));
+    let mut usbip = UsbIpServer::new(cid);
 
     // register a suspend/resume listener
-    let cid = xous::connect(usbdev_sid).expect("couldn't create suspend callback connection");
     let mut susres = susres::Susres::new(None, &xns, api::Opcode::SuspendResume as u32, cid)
         .expect("couldn't create suspend/resume object");
 
+    // FIDO + keyboard
+    let usb_alloc = UsbBusAllocator::new(usbip.bus(Views::FidoWithKbd as usize));
+
+    let mut composite = UsbHidClassBuilder::new()
+        .add_device(NKROBootKeyboardConfig::default())
+        .add_device(RawFidoConfig::default())
+        .build(&usb_alloc);
+
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+
+    // FIDO only
+    let fido_alloc = UsbBusAllocator::new(usbip.bus(Views::FidoOnly as usize));
+    let mut fido_class = UsbHidClassBuilder::new().add_device(RawFidoConfig::default()).build(&fido_alloc);
+
+    let mut fido_dev = UsbDeviceBuilder::new(&fido_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+
+    // Mass storage
+    #[cfg(feature = "mass-storage")]
+    let ums_alloc = UsbBusAllocator::new(usbip.bus(Views::MassStorage as usize));
+    #[cfg(feature = "mass-storage")]
+    let abd = apps_block_device::AppsBlockDevice::new();
+    #[cfg(feature = "mass-storage")]
+    let abdcid = abd.conn();
+    #[cfg(feature = "mass-storage")]
+    let mut ums = usbd_scsi::Scsi::new(
+        &ums_alloc,
+        64,
+        abd.clone(),
+        "Kosagi".as_bytes(),
+        "Kosagi Precursor".as_bytes(),
+        "1".as_bytes(),
+    );
+    #[cfg(feature = "mass-storage")]
+    let mut ums_device = UsbDeviceBuilder::new(&ums_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .self_powered(false)
+        .max_power(500)
+        .build();
+
+    // Serial
+    const SERIAL_BUF_LEN: usize = 1024;
+    let serial_alloc = UsbBusAllocator::new(usbip.bus(Views::Serial as usize));
+    let mut serial_port = SerialPort::new(&serial_alloc);
+    let mut serial_device = UsbDeviceBuilder::new(&serial_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .self_powered(false)
+        .max_power(500)
+        .build();
+    let mut serial_listener: Option<xous::MessageEnvelope> = None;
+    let mut serial_listen_mode: SerialListenMode = SerialListenMode::NoListener;
+    let mut serial_buf = Vec::<u8>::new();
+    let mut serial_rx_trigger = false; // when true, the condition was met to pass data to the listener (but the listener was not yet installed)
+
+    // CCID smart card reader
+    let ccid_alloc = UsbBusAllocator::new(usbip.bus(Views::Ccid as usize));
+    let mut ccid = ccid::CcidClass::new(&ccid_alloc);
+    let mut ccid_device = UsbDeviceBuilder::new(&ccid_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+    let mut ccid_listener: Option<xous::MessageEnvelope> = None;
+    let mut ccid_listener_pid: Option<NonZeroU8> = None;
+
+    // CDC-NCM network interface
+    let ncm_alloc = UsbBusAllocator::new(usbip.bus(Views::Ncm as usize));
+    let mut ncm = ncm::NcmClass::new(&ncm_alloc, ncm::host_mac(&serial_number));
+    let mut ncm_device = UsbDeviceBuilder::new(&ncm_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+    let mut ncm_listener: Option<xous::MessageEnvelope> = None;
+    let mut ncm_listener_pid: Option<NonZeroU8> = None;
+
+    // like the hardware, the device core is not connected until someone asks for it
+    let mut view = Views::FidoWithKbd;
+
+    let mut led_state: KeyboardLedsReport = KeyboardLedsReport::default();
     let mut fido_listener: Option<xous::MessageEnvelope> = None;
     // under the theory that PIDs are unforgeable. TODO: check that PIDs are unforgeable.
     // also if someone commandeers a process, all bets are off within that process (this is a general
@@ -92,6 +165,8 @@ pub(crate) fn main_hosted() -> ! {
     let mut fido_rx_queue = VecDeque::<[u8; 64]>::new();
 
     let mut lockstatus_force_update = true; // some state to track if we've been through a susupend/resume, to help out the status thread with its UX update after a restart-from-cold
+    let mut was_suspend = true;
+    let mut autotype_delay_ms = 30;
 
     loop {
         let mut msg = xous::receive_message(usbdev_sid).unwrap();
@@ -100,23 +175,66 @@ pub(crate) fn main_hosted() -> ! {
         match opcode {
             #[cfg(feature = "mass-storage")]
             Some(Opcode::SetBlockDevice) => {
-                log::info!("ignoring SetBlockDevice in hosted mode");
+                msg_blocking_scalar_unpack!(msg, read_id, write_id, max_lba_id, _, {
+                    xous::send_message(
+                        abdcid,
+                        xous::Message::new_blocking_scalar(
+                            apps_block_device::BlockDeviceMgmtOp::SetOps.to_usize().unwrap(),
+                            read_id,
+                            write_id,
+                            max_lba_id,
+                            0,
+                        ),
+                    )
+                    .unwrap();
+                    xous::return_scalar(msg.sender, 0).unwrap();
+                })
             }
             #[cfg(feature = "mass-storage")]
-            Some(Opcode::SetBlockDeviceSID) => {
-                log::info!("ignoring SetBlockDeviceSID in hosted mode");
-            }
+            Some(Opcode::SetBlockDeviceSID) => msg_blocking_scalar_unpack!(msg, sid1, sid2, sid3, sid4, {
+                xous::send_message(
+                    abdcid,
+                    xous::Message::new_blocking_scalar(
+                        apps_block_device::BlockDeviceMgmtOp::SetSID.to_usize().unwrap(),
+                        sid1,
+                        sid2,
+                        sid3,
+                        sid4,
+                    ),
+                )
+                .unwrap();
+                xous::return_scalar(msg.sender, 0).unwrap();
+            }),
             #[cfg(feature = "mass-storage")]
-            Some(Opcode::ResetBlockDevice) => {
-                log::info!("ignoring ResetBlockDevice in hosted mode");
-            }
+            Some(Opcode::ResetBlockDevice) => msg_blocking_scalar_unpack!(msg, 0, 0, 0, 0, {
+                xous::send_message(
+                    abdcid,
+                    xous::Message::new_blocking_scalar(
+                        apps_block_device::BlockDeviceMgmtOp::Reset.to_usize().unwrap(),
+                        0,
+                        0,
+                        0,
+                        0,
+                    ),
+                )
+                .unwrap();
+                xous::return_scalar(msg.sender, 0).unwrap();
+            }),
             Some(Opcode::SuspendResume) => msg_scalar_unpack!(msg, token, _, _, _, {
-                usbmgmt.xous_suspend();
                 susres.suspend_until_resume(token).expect("couldn't execute suspend/resume");
-                // resume1 + reset brings us to an initialized state
-                usbmgmt.xous_resume1();
-                // resume2 brings us to our last application state
-                usbmgmt.xous_resume2();
+                // a resume looks like a replug to the USB/IP host, as it does on hardware
+                let reset = match view {
+                    Views::FidoWithKbd => usb_dev.force_reset(),
+                    Views::FidoOnly => fido_dev.force_reset(),
+                    #[cfg(feature = "mass-storage")]
+                    Views::MassStorage => ums_device.force_reset(),
+                    Views::Serial => serial_device.force_reset(),
+                    Views::Ccid => ccid_device.force_reset(),
+                    Views::Ncm => ncm_device.force_reset(),
+                };
+                if let Err(e) = reset {
+                    log::warn!("USB reset on resume failed: {:?}", e);
+                }
                 lockstatus_force_update = true; // notify the status bar that yes, it does need to redraw the lock status, even if the value hasn't changed since the last read
             }),
             Some(Opcode::IsSocCompatible) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
@@ -190,54 +308,384 @@ pub(crate) fn main_hosted() -> ! {
                     let mut u2f_msg = RawFidoReport::default();
                     assert_eq!(u2f_ipc.code, U2fCode::Tx, "Expected U2fCode::Tx in wrapper");
                     u2f_msg.packet.copy_from_slice(&u2f_ipc.data);
-                    log::debug!("sent U2F packet {:x?}", u2f_ipc.data);
+                    let u2f = match view {
+                        Views::FidoWithKbd => Some(composite.device::<RawFido<'_, _>, _>()),
+                        Views::FidoOnly => Some(fido_class.device::<RawFido<'_, _>, _>()),
+                        _ => None,
+                    };
+                    match u2f {
+                        Some(u2f) => {
+                            u2f.write_report(&u2f_msg).ok();
+                            log::debug!("sent U2F packet {:x?}", u2f_ipc.data);
+                        }
+                        None => log::warn!("dropping U2F packet sent while in {:?} mode", view),
+                    }
                     u2f_ipc.code = U2fCode::TxAck;
                 } else {
                     u2f_ipc.code = U2fCode::Denied;
                 }
                 buffer.replace(u2f_ipc).unwrap();
             }
-            Some(Opcode::UsbIrqHandler) => {}
-            Some(Opcode::SwitchCores) => msg_blocking_scalar_unpack!(msg, core, _, _, _, {
-                if core == 1 {
-                    log::info!("Connecting USB device core; disconnecting debug USB core");
-                    usbmgmt.connect_device_core(true);
+            Some(Opcode::CcidRxDeferred) => {
+                if ccid_listener_pid.is_none() {
+                    ccid_listener_pid = msg.sender.pid();
+                }
+                if ccid_listener_pid != msg.sender.pid() {
+                    log::warn!(
+                        "CCID interface capability is locked on first use; additional servers are ignored: {:?}",
+                        msg.sender
+                    );
+                    let mut buffer =
+                        unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                    let mut ccid_ipc = buffer.to_original::<CcidMsgIpc, _>().unwrap();
+                    ccid_ipc.code = CcidCode::Denied;
+                    buffer.replace(ccid_ipc).unwrap();
+                } else if let Some(request) = ccid.take_request() {
+                    ccid::deliver(&mut msg, request);
                 } else {
-                    log::info!("Connecting debug core; disconnecting USB device core");
-                    usbmgmt.connect_device_core(false);
+                    if ccid_listener.is_some() {
+                        log::error!(
+                            "Double CCID listener request detected; the previous one is returned empty."
+                        );
+                    }
+                    ccid_listener = Some(msg);
+                }
+            }
+            Some(Opcode::CcidTx) => {
+                if ccid_listener_pid.is_none() {
+                    ccid_listener_pid = msg.sender.pid();
+                }
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ccid_ipc = buffer.to_original::<CcidMsgIpc, _>().unwrap();
+                if ccid_listener_pid == msg.sender.pid() && ccid_ipc.code == CcidCode::Tx {
+                    ccid.respond(&ccid_ipc.data[..ccid_ipc.len.min(CCID_MAX_MSG_LEN)]);
+                    ccid_ipc.code = CcidCode::TxAck;
+                } else {
+                    if ccid_ipc.code != CcidCode::Tx {
+                        log::warn!("CcidTx with unexpected code {:?}, ignoring", ccid_ipc.code);
+                    }
+                    ccid_ipc.code = CcidCode::Denied;
+                }
+                buffer.replace(ccid_ipc).unwrap();
+            }
+            Some(Opcode::NcmRxDeferred) => {
+                if ncm_listener_pid.is_none() {
+                    ncm_listener_pid = msg.sender.pid();
+                }
+                if ncm_listener_pid != msg.sender.pid() {
+                    log::warn!(
+                        "NCM interface capability is locked on first use; additional servers are ignored: {:?}",
+                        msg.sender
+                    );
+                    let mut buffer =
+                        unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                    let mut ncm_ipc = buffer.to_original::<NcmMsgIpc, _>().unwrap();
+                    ncm_ipc.code = NcmCode::Denied;
+                    buffer.replace(ncm_ipc).unwrap();
+                } else if let Some(frame) = ncm.take_frame() {
+                    ncm::deliver(&mut msg, frame);
+                } else {
+                    if ncm_listener.is_some() {
+                        log::error!(
+                            "Double NCM listener request detected; the previous one is returned empty."
+                        );
+                    }
+                    ncm_listener = Some(msg);
+                }
+            }
+            Some(Opcode::NcmTx) => {
+                if ncm_listener_pid.is_none() {
+                    ncm_listener_pid = msg.sender.pid();
+                }
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ncm_ipc = buffer.to_original::<NcmMsgIpc, _>().unwrap();
+                ncm_ipc.code = if ncm_listener_pid != msg.sender.pid() {
+                    NcmCode::Denied
+                } else if view != Views::Ncm || !ncm.is_active() {
+                    NcmCode::Hangup
+                } else if ncm.send(&ncm_ipc.data[..ncm_ipc.len.min(NCM_MAX_FRAME_LEN)]) {
+                    NcmCode::TxAck
+                } else {
+                    NcmCode::Busy
+                };
+                buffer.replace(ncm_ipc).unwrap();
+            }
+            Some(Opcode::UsbIrqHandler) => {
+                let mut serial_rx = false;
+                let maybe_u2f = match view {
+                    Views::FidoWithKbd => {
+                        if usb_dev.poll(&mut [&mut composite]) {
+                            match composite.device::<NKROBootKeyboard<_>, _>().read_report() {
+                                Ok(l) => {
+                                    log::info!("keyboard LEDs: {:?}", l);
+                                    led_state = l;
+                                }
+                                Err(e) => log::trace!("KEYB ERR: {:?}", e),
+                            }
+                            Some(composite.device::<RawFido<'_, _>, _>())
+                        } else {
+                            None
+                        }
+                    }
+                    Views::FidoOnly => {
+                        if fido_dev.poll(&mut [&mut fido_class]) {
+                            Some(fido_class.device::<RawFido<'_, _>, _>())
+                        } else {
+                            None
+                        }
+                    }
+                    #[cfg(feature = "mass-storage")]
+                    Views::MassStorage => {
+                        if ums_device.poll(&mut [&mut ums]) {
+                            log::debug!("ums device had something to do!")
+                        }
+                        None
+                    }
+                    Views::Serial => {
+                        serial_rx = serial_device.poll(&mut [&mut serial_port]);
+                        None
+                    }
+                    Views::Ccid => {
+                        ccid_device.poll(&mut [&mut ccid]);
+                        if ccid_listener.is_some() {
+                            if let Some(request) = ccid.take_request() {
+                                let mut listener = ccid_listener.take().unwrap();
+                                ccid::deliver(&mut listener, request);
+                            }
+                        }
+                        None
+                    }
+                    Views::Ncm => {
+                        let was_active = ncm.is_active();
+                        ncm_device.poll(&mut [&mut ncm]);
+                        if ncm_listener.is_some() {
+                            if let Some(frame) = ncm.take_frame() {
+                                let mut listener = ncm_listener.take().unwrap();
+                                ncm::deliver(&mut listener, frame);
+                            } else if was_active && !ncm.is_active() {
+                                // the host took its end of the link down
+                                hangup_ncm_listener(&mut ncm_listener);
+                            }
+                        }
+                        None
+                    }
+                };
+                if let Some(u2f) = maybe_u2f {
+                    match u2f.read_report() {
+                        Ok(u2f_report) => {
+                            if let Some(mut listener) = fido_listener.take() {
+                                let mut response = unsafe {
+                                    Buffer::from_memory_message_mut(
+                                        listener.body.memory_message_mut().unwrap(),
+                                    )
+                                };
+                                let mut buf = response.to_original::<U2fMsgIpc, _>().unwrap();
+                                assert_eq!(buf.code, U2fCode::RxWait, "Expected U2fcode::RxWait in wrapper");
+                                buf.data.copy_from_slice(&u2f_report.packet);
+                                log::trace!("ret deferred data {:x?}", &u2f_report.packet[..8]);
+                                buf.code = U2fCode::RxAck;
+                                response.replace(buf).unwrap();
+                            } else {
+                                log::debug!("Got U2F packet, but no server to respond...queuing.");
+                                fido_rx_queue.push_back(u2f_report.packet);
+                            }
+                        }
+                        Err(e) => log::trace!("U2F ERR: {:?}", e),
+                    }
+                }
+                if serial_rx {
+                    let mut data = [0u8; SERIAL_BUF_LEN];
+                    let readlen = serial_port.read(&mut data).unwrap_or(0);
+                    let data = &data[..readlen];
+                    match serial_listen_mode {
+                        SerialListenMode::NoListener => match std::str::from_utf8(data) {
+                            Ok(s) => log::debug!("No listener ascii: {}", s),
+                            Err(_) => log::debug!("No listener binary: {:x?}", data),
+                        },
+                        SerialListenMode::ConsoleListener => match std::str::from_utf8(data) {
+                            Ok(s) => {
+                                for c in s.chars() {
+                                    native_kbd.inject_key(c);
+                                }
+                            }
+                            Err(_) => log::info!("Non UTF-8 received on console: {:x?}", data),
+                        },
+                        SerialListenMode::AsciiListener(maybe_delimiter) if readlen > 0 => {
+                            // once true, the trigger sticks as true until the listener is answered
+                            serial_rx_trigger |= match maybe_delimiter {
+                                Some(delimiter) => data.contains(&(delimiter as u8)),
+                                None => true,
+                            };
+                            serial_buf.extend_from_slice(data);
+                            if serial_rx_trigger {
+                                if let Some(mut rx_msg) = serial_listener.take() {
+                                    let mut response = unsafe {
+                                        Buffer::from_memory_message_mut(
+                                            rx_msg.body.memory_message_mut().unwrap(),
+                                        )
+                                    };
+                                    let mut buf = response.to_original::<UsbSerialAscii, _>().unwrap();
+                                    use std::fmt::Write;
+                                    write!(buf.s, "{}", std::string::String::from_utf8_lossy(&serial_buf))
+                                        .ok();
+                                    response.replace(buf).unwrap();
+                                    // the rx_msg will drop and respond to the listener
+                                    serial_buf.clear();
+                                    serial_rx_trigger = false;
+                                }
+                            }
+                        }
+                        SerialListenMode::BinaryListener if readlen > 0 => {
+                            serial_buf.extend_from_slice(data);
+                            if serial_buf.len() >= SERIAL_BINARY_BUFLEN {
+                                if let Some(mut rx_msg) = serial_listener.take() {
+                                    let mut response = unsafe {
+                                        Buffer::from_memory_message_mut(
+                                            rx_msg.body.memory_message_mut().unwrap(),
+                                        )
+                                    };
+                                    let mut buf = response.to_original::<UsbSerialBinary, _>().unwrap();
+                                    buf.d
+                                        .copy_from_slice(serial_buf.drain(..SERIAL_BINARY_BUFLEN).as_slice());
+                                    buf.len = SERIAL_BINARY_BUFLEN;
+                                    response.replace(buf).unwrap();
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                let is_suspend = match view {
+                    Views::FidoWithKbd => usb_dev.state() == UsbDeviceState::Suspend,
+                    Views::FidoOnly => fido_dev.state() == UsbDeviceState::Suspend,
+                    #[cfg(feature = "mass-storage")]
+                    Views::MassStorage => ums_device.state() == UsbDeviceState::Suspend,
+                    Views::Serial => serial_device.state() == UsbDeviceState::Suspend,
+                    Views::Ccid => ccid_device.state() == UsbDeviceState::Suspend,
+                    Views::Ncm => ncm_device.state() == UsbDeviceState::Suspend,
+                };
+                // a USB/IP host detaching shows up as a suspend
+                if is_suspend {
+                    log::info!("suspend detected");
+                    if !was_suspend {
+                        // FIDO listener needs to know when USB was unplugged, so that it can reset state per
+                        // FIDO2 spec
+                        if let Some(mut listener) = fido_listener.take() {
+                            let mut response = unsafe {
+                                Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap())
+                            };
+                            let mut buf = response.to_original::<U2fMsgIpc, _>().unwrap();
+                            assert_eq!(buf.code, U2fCode::RxWait, "Expected U2fcode::RxWait in wrapper");
+                            buf.code = U2fCode::Hangup;
+                            response.replace(buf).unwrap();
+                        }
+                        // the card loses power along with the reader
+                        ccid.hangup();
+                        if let Some(mut listener) = ccid_listener.take() {
+                            let mut response = unsafe {
+                                Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap())
+                            };
+                            let mut buf = response.to_original::<CcidMsgIpc, _>().unwrap();
+                            buf.code = CcidCode::Hangup;
+                            response.replace(buf).unwrap();
+                        }
+                        // and the network link goes down with the cable
+                        ncm.hangup();
+                        hangup_ncm_listener(&mut ncm_listener);
+                    }
+                    was_suspend = true;
+                } else {
+                    was_suspend = false;
+                }
+            }
+            // always triggers a reset when called
+            Some(Opcode::SwitchCores) => msg_blocking_scalar_unpack!(msg, core, _, _, _, {
+                let devtype: UsbDeviceType = core.try_into().unwrap();
+                // the network link goes down across a reset
+                ncm.hangup();
+                hangup_ncm_listener(&mut ncm_listener);
+                match hosted_view(devtype) {
+                    Ok(Some(new_view)) => {
+                        log::info!("Connecting {:?} over USB/IP", new_view);
+                        // unplug first, so the host sees a fresh device even if the view didn't change
+                        usbip.connect_view(None);
+                        view = new_view;
+                        usbip.connect_view(Some(view as usize));
+                        xous::return_scalar(msg.sender, 0).unwrap();
+                    }
+                    Ok(None) => {
+                        log::info!("Disconnecting USB/IP device");
+                        usbip.connect_view(None);
+                        xous::return_scalar(msg.sender, 0).unwrap();
+                    }
+                    Err(_) => {
+                        // callers expect core switches to succeed, so this is only reported in the log
+                        log::warn!("USB device type {} is not available in hosted mode", devtype as usize);
+                        xous::return_scalar(msg.sender, 0).unwrap();
+                    }
                 }
-                xous::return_scalar(msg.sender, 0).unwrap();
             }),
             Some(Opcode::EnsureCore) => msg_blocking_scalar_unpack!(msg, core, _, _, _, {
-                if core == 1 {
-                    if !usbmgmt.is_device_connected() {
-                        log::info!("Connecting USB device core; disconnecting debug USB core");
-                        usbmgmt.connect_device_core(true);
+                let devtype: UsbDeviceType = core.try_into().unwrap();
+                match hosted_view(devtype) {
+                    Ok(Some(new_view)) => {
+                        if !usbip.is_device_connected() || view != new_view {
+                            if view == Views::Ncm && new_view != Views::Ncm {
+                                ncm.hangup();
+                                hangup_ncm_listener(&mut ncm_listener);
+                            }
+                            log::info!("Ensuring {:?} over USB/IP", new_view);
+                            view = new_view;
+                            usbip.connect_view(Some(view as usize));
+                        }
+                        if view == Views::FidoWithKbd {
+                            let keyboard = composite.device::<NKROBootKeyboard<'_, _>, _>();
+                            keyboard.write_report([Keyboard::NoEventIndicated]).ok(); // queues an "all key-up" for the interface
+                            keyboard.tick().ok();
+                        }
+                        xous::return_scalar(msg.sender, 0).unwrap();
                     }
-                } else {
-                    if usbmgmt.is_device_connected() {
-                        log::info!("Connecting debug core; disconnecting USB device core");
-                        usbmgmt.connect_device_core(false);
+                    Ok(None) => {
+                        if usbip.is_device_connected() {
+                            log::info!("Disconnecting USB/IP device");
+                            usbip.connect_view(None);
+                        }
+                        xous::return_scalar(msg.sender, 0).unwrap();
+                    }
+                    Err(_) => {
+                        log::warn!("USB device type {} is not available in hosted mode", devtype as usize);
+                        xous::return_scalar(msg.sender, 0).unwrap();
                     }
                 }
-                xous::return_scalar(msg.sender, 0).unwrap();
             }),
             Some(Opcode::WhichCore) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
-                if usbmgmt.is_device_connected() {
-                    xous::return_scalar(msg.sender, 1).unwrap();
+                let devtype = if usbip.is_device_connected() {
+                    match view {
+                        Views::FidoWithKbd => UsbDeviceType::FidoKbd,
+                        Views::FidoOnly => UsbDeviceType::Fido,
+                        #[cfg(feature = "mass-storage")]
+                        Views::MassStorage => UsbDeviceType::MassStorage,
+                        Views::Serial => UsbDeviceType::Serial,
+                        Views::Ccid => UsbDeviceType::Ccid,
+                        Views::Ncm => UsbDeviceType::Ncm,
+                    }
                 } else {
-                    xous::return_scalar(msg.sender, 0).unwrap();
-                }
+                    UsbDeviceType::Debug
+                };
+                xous::return_scalar(msg.sender, devtype as usize).unwrap();
             }),
             Some(Opcode::RestrictDebugAccess) => msg_scalar_unpack!(msg, restrict, _, _, _, {
                 if restrict == 0 {
-                    usbmgmt.disable_debug(false);
+                    usbip.disable_debug(false);
                 } else {
-                    usbmgmt.disable_debug(true);
+                    usbip.disable_debug(true);
                 }
             }),
             Some(Opcode::IsRestricted) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
-                if usbmgmt.get_disable_debug() {
+                if usbip.get_disable_debug() {
                     xous::return_scalar(msg.sender, 1).unwrap();
                 } else {
                     xous::return_scalar(msg.sender, 0).unwrap();
@@ -248,15 +696,13 @@ pub(crate) fn main_hosted() -> ! {
                     // if new_state is true (not 0), then try to lock the USB port
                     // if false, try to unlock the USB port
                     if new_state != 0 {
-                        usbmgmt.disable_debug(true);
+                        usbip.disable_debug(true);
                     } else {
-                        usbmgmt.disable_debug(false);
+                        usbip.disable_debug(false);
                     }
                 }
-                // at this point, *read back* the new state -- don't assume it "took". The readback is always
-                // based on a real hardware value and not the requested value. for now, always
-                // false.
-                let is_locked = if usbmgmt.get_disable_debug() { 1 } else { 0 };
+                // at this point, *read back* the new state -- don't assume it "took".
+                let is_locked = if usbip.get_disable_debug() { 1 } else { 0 };
 
                 // this is a performance optimization. we could always redraw the status, but, instead we only
                 // redraw when the status has changed. However, there is an edge case: on a
@@ -268,19 +714,184 @@ pub(crate) fn main_hosted() -> ! {
                 lockstatus_force_update = false;
             }),
             Some(Opcode::LinkStatus) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
-                xous::return_scalar(msg.sender, 0).unwrap();
+                let state = match view {
+                    Views::FidoWithKbd => usb_dev.state(),
+                    Views::FidoOnly => fido_dev.state(),
+                    #[cfg(feature = "mass-storage")]
+                    Views::MassStorage => ums_device.state(),
+                    Views::Serial => serial_device.state(),
+                    Views::Ccid => ccid_device.state(),
+                    Views::Ncm => ncm_device.state(),
+                };
+                xous::return_scalar(msg.sender, state as usize).unwrap();
+            }),
+            Some(Opcode::SendKeyCode) => msg_blocking_scalar_unpack!(msg, code0, code1, code2, autoup, {
+                if view == Views::FidoWithKbd && usb_dev.state() == UsbDeviceState::Configured {
+                    let native_map = native_kbd.get_keymap().unwrap();
+                    let codes: Vec<Keyboard> = [code0, code1, code2]
+                        .iter()
+                        .filter(|&&code| code != 0)
+                        .map(|&code| match native_map {
+                            KeyMap::Dvorak => mappings::char_to_hid_code_dvorak(code as u8 as char)[0],
+                            _ => mappings::char_to_hid_code_us101(code as u8 as char)[0],
+                        })
+                        .collect();
+                    let keyboard = composite.device::<NKROBootKeyboard<'_, _>, _>();
+                    keyboard.write_report(codes).ok();
+                    keyboard.tick().ok();
+                    tt.sleep_ms(autotype_delay_ms).ok();
+                    if autoup == 1 {
+                        keyboard.write_report([Keyboard::NoEventIndicated]).ok(); // this is the key-up
+                        keyboard.tick().ok();
+                        tt.sleep_ms(autotype_delay_ms).ok();
+                    }
+                    xous::return_scalar(msg.sender, 0).unwrap();
+                } else {
+                    xous::return_scalar(msg.sender, 1).unwrap();
+                }
+            }),
+            Some(Opcode::SetAutotypeRate) => msg_scalar_unpack!(msg, rate, _, _, _, {
+                let checked_rate = if rate > 500 { 500 } else { rate };
+                autotype_delay_ms = checked_rate;
             }),
-            Some(Opcode::SendKeyCode) => {
-                xous::return_scalar(msg.sender, 1).unwrap();
-            }
             Some(Opcode::SendString) => {
                 let mut buffer =
                     unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
-                let usb_send = buffer.to_original::<api::UsbString, _>().unwrap(); // suppress mut warning on hosted mode
+                let mut usb_send = buffer.to_original::<api::UsbString, _>().unwrap();
+                let mut sent = 0;
+                if view == Views::FidoWithKbd {
+                    let native_map = native_kbd.get_keymap().unwrap();
+                    for ch in usb_send.s.as_str().chars() {
+                        let codes = match native_map {
+                            KeyMap::Dvorak => mappings::char_to_hid_code_dvorak(ch),
+                            _ => mappings::char_to_hid_code_us101(ch),
+                        };
+                        let keyboard = composite.device::<NKROBootKeyboard<'_, _>, _>();
+                        keyboard.write_report(codes).ok();
+                        keyboard.tick().ok();
+                        tt.sleep_ms(autotype_delay_ms).ok();
+                        keyboard.write_report([Keyboard::NoEventIndicated]).ok(); // this is the key-up
+                        keyboard.tick().ok();
+                        tt.sleep_ms(autotype_delay_ms).ok();
+                        sent += 1;
+                    }
+                } else if view == Views::Serial {
+                    let send_data = usb_send.s.as_bytes();
+                    while sent < send_data.len() {
+                        match serial_port.write(&send_data[sent..]) {
+                            Ok(written) => sent += written,
+                            Err(_) => {
+                                log::warn!("Serial send is blocking. Delaying and trying again.");
+                                tt.sleep_ms(100).ok();
+                            }
+                        }
+                        serial_port.flush().ok();
+                    }
+                }
+                usb_send.sent = Some(sent as _);
                 buffer.replace(usb_send).unwrap();
             }
-            Some(Opcode::GetLedState) => {
-                xous::return_scalar(msg.sender, 0).unwrap();
+            Some(Opcode::GetLedState) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
+                let mut code = [0u8; 1];
+                led_state.pack_to_slice(&mut code).unwrap();
+                xous::return_scalar(msg.sender, code[0] as usize).unwrap();
+            }),
+            Some(Opcode::LogString) => {
+                // best effort, as on hardware: characters are dropped rather than retried
+                if view == Views::Serial {
+                    let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                    let usb_send = buffer.to_original::<api::UsbString, _>().unwrap();
+                    serial_port.write(usb_send.s.as_bytes()).ok();
+                    serial_port.flush().ok();
+                }
+            }
+            Some(Opcode::SerialHookAscii) => {
+                let maybe_delimiter = {
+                    let buffer = unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
+                    let data = buffer.to_original::<UsbSerialAscii, _>().unwrap();
+                    data.delimiter
+                };
+                serial_listen_mode = SerialListenMode::AsciiListener(maybe_delimiter);
+                serial_listener = Some(msg);
+            }
+            Some(Opcode::SerialHookBinary) => {
+                serial_listen_mode = SerialListenMode::BinaryListener;
+                serial_listener = Some(msg);
+            }
+            Some(Opcode::SerialHookConsole) => msg_scalar_unpack!(msg, _, _, _, _, {
+                let log_conn = xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap()).unwrap();
+                match xous::send_message(
+                    log_conn,
+                    xous::Message::new_blocking_scalar(
+                        log_server::api::Opcode::TryHookUsbMirror.to_usize().unwrap(),
+                        0,
+                        0,
+                        0,
+                        0,
+                    ),
+                ) {
+                    Ok(xous::Result::Scalar1(1)) => {
+                        serial_listen_mode = SerialListenMode::ConsoleListener;
+                        // unhook any previous pending listener
+                        serial_listener.take();
+                    }
+                    _ => log::error!("Could not connect USB console"),
+                }
+            }),
+            Some(Opcode::SerialClearHooks) => {
+                let log_conn = xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap()).unwrap();
+                // it is never harmful to double-unhook this
+                xous::send_message(
+                    log_conn,
+                    xous::Message::new_blocking_scalar(
+                        log_server::api::Opcode::UnhookUsbMirror.to_usize().unwrap(),
+                        0,
+                        0,
+                        0,
+                        0,
+                    ),
+                )
+                .ok();
+                serial_listen_mode = SerialListenMode::NoListener;
+                serial_listener.take();
+            }
+            Some(Opcode::SerialFlush) => msg_scalar_unpack!(msg, _, _, _, _, {
+                if view == Views::Serial {
+                    serial_port.flush().ok();
+                }
+                // return whatever is pending in the main loop's buffer
+                match serial_listen_mode {
+                    SerialListenMode::BinaryListener => {
+                        if let Some(mut rx_msg) = serial_listener.take() {
+                            let mut response = unsafe {
+                                Buffer::from_memory_message_mut(rx_msg.body.memory_message_mut().unwrap())
+                            };
+                            let mut buf = response.to_original::<UsbSerialBinary, _>().unwrap();
+                            let chars_avail = serial_buf.len().min(SERIAL_BINARY_BUFLEN);
+                            buf.len = chars_avail;
+                            buf.d[..chars_avail].copy_from_slice(serial_buf.drain(..chars_avail).as_slice());
+                            response.replace(buf).unwrap();
+                        }
+                    }
+                    SerialListenMode::AsciiListener(_) => {
+                        if let Some(mut rx_msg) = serial_listener.take() {
+                            let mut response = unsafe {
+                                Buffer::from_memory_message_mut(rx_msg.body.memory_message_mut().unwrap())
+                            };
+                            let mut buf = response.to_original::<UsbSerialAscii, _>().unwrap();
+                            use std::fmt::Write;
+                            write!(buf.s, "{}", std::string::String::from_utf8_lossy(&serial_buf)).ok();
+                            response.replace(buf).unwrap();
+                            serial_buf.clear();
+                            serial_rx_trigger = false;
+                        }
+                    }
+                    _ => {}
+                }
+            }),
+            Some(Opcode::SerialHookTrngSender) => {
+                // there is no TRNG hardware behind a hosted build, so there is nothing worth streaming
+                log::warn!("The serial TRNG sender is not available in hosted mode");
             }
             Some(Opcode::Quit) => {
                 log::warn!("Quit received, goodbye world!");
@@ -291,8 +902,39 @@ pub(crate) fn main_hosted() -> ! {
     }
     // clean up our program
     log::trace!("main loop exit, destroying servers");
+    usbip.connect_view(None);
+    ccid_listener.take();
+    ncm_listener.take();
+    serial_listener.take();
     xns.unregister_server(usbdev_sid).unwrap();
     xous::destroy_server(usbdev_sid).unwrap();
     log::trace!("quitting");
     xous::terminate_process(0)
 }
+
+/// Maps a device type onto the view that provides it: `Ok(None)` is the debug core, which in hosted mode
+/// just means nothing is plugged in, and `Err` is a type that can't be exported over USB/IP yet.
+fn hosted_view(devtype: UsbDeviceType) -> Result<Option<Views>, UsbDeviceType> {
+    match devtype {
+        UsbDeviceType::Debug => Ok(None),
+        UsbDeviceType::FidoKbd => Ok(Some(Views::FidoWithKbd)),
+        UsbDeviceType::Fido => Ok(Some(Views::FidoOnly)),
+        #[cfg(feature = "mass-storage")]
+        UsbDeviceType::MassStorage => Ok(Some(Views::MassStorage)),
+        UsbDeviceType::Serial => Ok(Some(Views::Serial)),
+        UsbDeviceType::Ccid => Ok(Some(Views::Ccid)),
+        UsbDeviceType::Ncm => Ok(Some(Views::Ncm)),
+        other => Err(other),
+    }
+}
+
+/// Tell the network server waiting for a frame, if any, that the link is down.
+fn hangup_ncm_listener(listener: &mut Option<xous::MessageEnvelope>) {
+    if let Some(mut listener) = listener.take() {
+        let mut response =
+            unsafe { Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap()) };
+        let mut buf = response.to_original::<NcmMsgIpc, _>().unwrap();
+        buf.code = NcmCode::Hangup;
+        response.replace(buf).unwrap();
+    }
+}
@@ -5,6 +5,7 @@ use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::sync::Arc;
 
+use frunk_core::hlist::{HCons, HNil};
 #[cfg(all(not(feature = "minimal"), any(feature = "renode", feature = "precursor")))]
 use keyboard::KeyMap;
 use num_traits::*;
@@ -26,6 +27,7 @@ use xous_usb_hid::device::fido::RawFidoReport;
 use xous_usb_hid::device::keyboard::{NKROBootKeyboard, NKROBootKeyboardConfig};
 use xous_usb_hid::page::Keyboard;
 use xous_usb_hid::prelude::*;
+use xous_usb_hid::usb_class::UsbHidClass;
 
 use crate::hid::AppHIDConfig;
 use crate::*;
@@ -42,6 +44,9 @@ enum Views {
     MassStorage = 2,
     Serial = 3,
     HIDv2 = 4,
+    Ccid = 5,
+    Ncm = 6,
+    Composite = 7,
 }
 
 #[derive(num_derive::FromPrimitive, num_derive::ToPrimitive, Debug)]
@@ -77,6 +82,8 @@ enum SerialListenMode {
 pub(crate) fn main_hw() -> ! {
     log_server::init_wait().unwrap();
     log::set_max_level(log::LevelFilter::Info);
+    log_server::register_levels("usb-device-xous", &["usb_device_xous", "usb_device"])
+        .expect("couldn't register for log level control");
     log::info!("my PID is {}", xous::process::id());
 
     let xns = xous_names::XousNames::new().unwrap();
@@ -110,6 +117,8 @@ pub(crate) fn main_hw() -> ! {
                 ).unwrap();
             }
             let mut fido_listener: Option<xous::MessageEnvelope> = None;
+            let mut ccid_listener: Option<xous::MessageEnvelope> = None;
+            let mut ncm_listener: Option<xous::MessageEnvelope> = None;
             loop {
                 let msg = xous::receive_message(usbdev_sid).unwrap();
                 match FromPrimitive::from_usize(msg.body.id()) {
@@ -122,6 +131,12 @@ pub(crate) fn main_hw() -> ! {
                         // block any rx requests forever
                         fido_listener = Some(msg);
                     }
+                    Some(Opcode::CcidRxDeferred) => {
+                        ccid_listener = Some(msg);
+                    }
+                    Some(Opcode::NcmRxDeferred) => {
+                        ncm_listener = Some(msg);
+                    }
                     Some(Opcode::IsSocCompatible) => msg_blocking_scalar_unpack!(msg, _, _, _, _, {
                         xous::return_scalar(msg.sender, 0).expect("couldn't return compatibility status")
                     }),
@@ -145,7 +160,7 @@ pub(crate) fn main_hw() -> ! {
                     }
                 }
             }
-            log::info!("consuming listener: {:?}", fido_listener);
+            log::info!("consuming listeners: {:?} {:?} {:?}", fido_listener, ccid_listener, ncm_listener);
         }
     }
     #[cfg(feature = "minimal")]
@@ -205,6 +220,24 @@ pub(crate) fn main_hw() -> ! {
     let serial_dev = SpinalUsbDevice::new(usbdev_sid, usb.clone(), csr.clone());
     #[cfg(any(feature = "renode", feature = "precursor"))]
     serial_dev.init();
+    #[cfg(any(feature = "renode", feature = "precursor"))]
+    let ccid_dev = SpinalUsbDevice::new(usbdev_sid, usb.clone(), csr.clone());
+    #[cfg(any(feature = "renode", feature = "precursor"))]
+    ccid_dev.init();
+    #[cfg(any(feature = "renode", feature = "precursor"))]
+    let ncm_dev = SpinalUsbDevice::new(usbdev_sid, usb.clone(), csr.clone());
+    #[cfg(any(feature = "renode", feature = "precursor"))]
+    ncm_dev.init();
+    // one view for every selection of composite functions
+    #[cfg(any(feature = "renode", feature = "precursor"))]
+    let multi_devs: Vec<(CompositeFunctions, SpinalUsbDevice)> = (1..=CompositeFunctions::all().bits())
+        .filter_map(CompositeFunctions::from_bits)
+        .map(|functions| {
+            let dev = SpinalUsbDevice::new(usbdev_sid, usb.clone(), csr.clone());
+            dev.init();
+            (functions, dev)
+        })
+        .collect();
 
     // register a suspend/resume listener
     #[cfg(any(feature = "renode", feature = "precursor", feature = "hosted"))]
@@ -246,7 +279,7 @@ pub(crate) fn main_hw() -> ! {
     let mut ums = usbd_scsi::Scsi::new(
         &ums_alloc,
         64,
-        abd,
+        abd.clone(),
         "Kosagi".as_bytes(),
         "Kosagi Precursor".as_bytes(),
         "1".as_bytes(),
@@ -299,6 +332,47 @@ pub(crate) fn main_hw() -> ! {
         AppHIDConfig::default(),
         100, // 100 * 64 bytes = 6.4kb, quite the backlog
     );
+
+    // CCID smart card reader
+    let ccid_alloc = UsbBusAllocator::new(ccid_dev);
+    let mut ccid = ccid::CcidClass::new(&ccid_alloc);
+    let mut ccid_device = UsbDeviceBuilder::new(&ccid_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+    // the card is whichever process first asks for CCID traffic; same trust model as the FIDO listener
+    let mut ccid_listener: Option<xous::MessageEnvelope> = None;
+    let mut ccid_listener_pid: Option<NonZeroU8> = None;
+
+    // CDC-NCM network interface
+    let ncm_alloc = UsbBusAllocator::new(ncm_dev);
+    let mut ncm = ncm::NcmClass::new(&ncm_alloc, ncm::host_mac(&serial_number));
+    let mut ncm_device = UsbDeviceBuilder::new(&ncm_alloc, UsbVidPid(0x1209, 0x3613))
+        .manufacturer("Kosagi")
+        .product("Precursor")
+        .serial_number(&serial_number)
+        .build();
+    // the network interface belongs to whichever process first uses it, which is normally `net`
+    let mut ncm_listener: Option<xous::MessageEnvelope> = None;
+    let mut ncm_listener_pid: Option<NonZeroU8> = None;
+
+    // Composite: FIDO, serial and mass storage on one device. Each selection of functions has its own
+    // view, which allocates just those functions, so the interfaces are numbered without gaps and
+    // bNumInterfaces matches what the host is shown.
+    let multi_allocs: Vec<(CompositeFunctions, UsbBusAllocator<SpinalUsbDevice>)> =
+        multi_devs.into_iter().map(|(functions, dev)| (functions, UsbBusAllocator::new(dev))).collect();
+    let mut multi_devices: Vec<CompositeDevice> = multi_allocs
+        .iter()
+        .map(|(functions, alloc)| {
+            #[cfg(feature = "mass-storage")]
+            let multi = CompositeDevice::new(alloc, *functions, &serial_number, &abd);
+            #[cfg(not(feature = "mass-storage"))]
+            let multi = CompositeDevice::new(alloc, *functions, &serial_number);
+            multi
+        })
+        .collect();
+    let mut multi_functions = CompositeFunctions::all();
     // track which view is visible on the device core
     #[cfg(all(not(feature = "minimal")))]
     let mut view = Views::FidoWithKbd;
@@ -557,6 +631,20 @@ pub(crate) fn main_hw() -> ! {
                         Err(e) => log::warn!("USB reset on resume failed: {:?}", e),
                         _ => (),
                     },
+                    Views::Ccid => match ccid_device.force_reset() {
+                        Err(e) => log::warn!("USB reset on resume failed: {:?}", e),
+                        _ => (),
+                    },
+                    Views::Ncm => match ncm_device.force_reset() {
+                        Err(e) => log::warn!("USB reset on resume failed: {:?}", e),
+                        _ => (),
+                    },
+                    Views::Composite => {
+                        match composite_for(&mut multi_devices, multi_functions).device.force_reset() {
+                            Err(e) => log::warn!("USB reset on resume failed: {:?}", e),
+                            _ => (),
+                        }
+                    }
                 }
                 // resume2 brings us to our last application state
                 usbmgmt.xous_resume2();
@@ -675,6 +763,16 @@ pub(crate) fn main_hw() -> ! {
                         Views::MassStorage => panic!("did not expect u2f tx when in mass storage mode!"),
                         Views::Serial => panic!("did not expect u2f tx while in serial mode!"),
                         Views::HIDv2 => panic!("did not expect u2f tx while in hidv2 mode!"),
+                        Views::Ccid => panic!("did not expect u2f tx while in ccid mode!"),
+                        Views::Ncm => panic!("did not expect u2f tx while in ncm mode!"),
+                        Views::Composite => {
+                            match composite_for(&mut multi_devices, multi_functions).fido.as_mut() {
+                                Some(fido) => fido.device::<RawFido<'_, _>, _>(),
+                                None => panic!(
+                                    "did not expect u2f tx while the composite device has no FIDO function!"
+                                ),
+                            }
+                        }
                     };
                     u2f.write_report(&u2f_msg).ok();
                     log::debug!("sent U2F packet {:x?}", u2f_ipc.data);
@@ -684,7 +782,95 @@ pub(crate) fn main_hw() -> ! {
                 }
                 buffer.replace(u2f_ipc).unwrap();
             }
+            Some(Opcode::CcidRxDeferred) => {
+                if ccid_listener_pid.is_none() {
+                    ccid_listener_pid = msg.sender.pid();
+                }
+                if ccid_listener_pid != msg.sender.pid() {
+                    log::warn!(
+                        "CCID interface capability is locked on first use; additional servers are ignored: {:?}",
+                        msg.sender
+                    );
+                    let mut buffer =
+                        unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                    let mut ccid_ipc = buffer.to_original::<CcidMsgIpc, _>().unwrap();
+                    ccid_ipc.code = CcidCode::Denied;
+                    buffer.replace(ccid_ipc).unwrap();
+                } else if let Some(request) = ccid.take_request() {
+                    ccid::deliver(&mut msg, request);
+                } else {
+                    if ccid_listener.is_some() {
+                        log::error!(
+                            "Double CCID listener request detected; the previous one is returned empty."
+                        );
+                    }
+                    ccid_listener = Some(msg);
+                }
+            }
+            Some(Opcode::CcidTx) => {
+                if ccid_listener_pid.is_none() {
+                    ccid_listener_pid = msg.sender.pid();
+                }
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ccid_ipc = buffer.to_original::<CcidMsgIpc, _>().unwrap();
+                if ccid_listener_pid == msg.sender.pid() && ccid_ipc.code == CcidCode::Tx {
+                    ccid.respond(&ccid_ipc.data[..ccid_ipc.len.min(CCID_MAX_MSG_LEN)]);
+                    ccid_ipc.code = CcidCode::TxAck;
+                } else {
+                    if ccid_ipc.code != CcidCode::Tx {
+                        log::warn!("CcidTx with unexpected code {:?}, ignoring", ccid_ipc.code);
+                    }
+                    ccid_ipc.code = CcidCode::Denied;
+                }
+                buffer.replace(ccid_ipc).unwrap();
+            }
+            Some(Opcode::NcmRxDeferred) => {
+                if ncm_listener_pid.is_none() {
+                    ncm_listener_pid = msg.sender.pid();
+                }
+                if ncm_listener_pid != msg.sender.pid() {
+                    log::warn!(
+                        "NCM interface capability is locked on first use; additional servers are ignored: {:?}",
+                        msg.sender
+                    );
+                    let mut buffer =
+                        unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                    let mut ncm_ipc = buffer.to_original::<NcmMsgIpc, _>().unwrap();
+                    ncm_ipc.code = NcmCode::Denied;
+                    buffer.replace(ncm_ipc).unwrap();
+                } else if let Some(frame) = ncm.take_frame() {
+                    ncm::deliver(&mut msg, frame);
+                } else {
+                    if ncm_listener.is_some() {
+                        log::error!(
+                            "Double NCM listener request detected; the previous one is returned empty."
+                        );
+                    }
+                    ncm_listener = Some(msg);
+                }
+            }
+            Some(Opcode::NcmTx) => {
+                if ncm_listener_pid.is_none() {
+                    ncm_listener_pid = msg.sender.pid();
+                }
+                let mut buffer =
+                    unsafe { Buffer::from_memory_message_mut(msg.body.memory_message_mut().unwrap()) };
+                let mut ncm_ipc = buffer.to_original::<NcmMsgIpc, _>().unwrap();
+                ncm_ipc.code = if ncm_listener_pid != msg.sender.pid() {
+                    NcmCode::Denied
+                } else if view != Views::Ncm || !ncm.is_active() {
+                    NcmCode::Hangup
+                } else if ncm.send(&ncm_ipc.data[..ncm_ipc.len.min(NCM_MAX_FRAME_LEN)]) {
+                    NcmCode::TxAck
+                } else {
+                    NcmCode::Busy
+                };
+                buffer.replace(ncm_ipc).unwrap();
+            }
             Some(Opcode::UsbIrqHandler) => {
+                // set when the serial port of the current view has seen traffic
+                let mut serial_rx = false;
                 let maybe_u2f = match view {
                     Views::FidoWithKbd => {
                         if usb_dev.poll(&mut [&mut composite]) {
@@ -715,117 +901,7 @@ pub(crate) fn main_hw() -> ! {
                         None
                     }
                     Views::Serial => {
-                        if serial_device.poll(&mut [&mut serial_port]) {
-                            let mut data: [u8; SERIAL_BUF_LEN] = [0u8; SERIAL_BUF_LEN];
-                            match serial_listen_mode {
-                                SerialListenMode::NoListener => match serial_port.read(&mut data) {
-                                    Ok(len) => match std::str::from_utf8(&data[..len]) {
-                                        Ok(s) => log::debug!("No listener ascii: {}", s),
-                                        Err(_) => {
-                                            log::debug!("No listener binary: {:x?}", &data[..len]);
-                                        }
-                                    },
-                                    Err(e) => {
-                                        log::debug!("No listener: {:?}", e);
-                                    }
-                                },
-                                SerialListenMode::ConsoleListener => match serial_port.read(&mut data) {
-                                    Ok(len) => match std::str::from_utf8(&data[..len]) {
-                                        Ok(s) => {
-                                            for c in s.chars() {
-                                                native_kbd.inject_key(c);
-                                            }
-                                        }
-                                        Err(_) => {
-                                            log::info!("Non UTF-8 received on console: {:x?}", &data[..len]);
-                                        }
-                                    },
-                                    Err(e) => {
-                                        log::info!("Serial read error: {:?}", e);
-                                    }
-                                },
-                                SerialListenMode::AsciiListener(maybe_delimiter) => {
-                                    let readlen = serial_port.read(&mut data).unwrap_or(0);
-                                    if readlen == 0 {
-                                        continue;
-                                    }
-                                    if let Some(delimiter) = maybe_delimiter {
-                                        if !delimiter.is_ascii() {
-                                            log::warn!(
-                                                "Chosen ASCII delimiter {} is not ASCII. Serial receive will not function properly.",
-                                                delimiter
-                                            );
-                                        }
-                                        if !serial_rx_trigger {
-                                            // once true, sticks as true
-                                            serial_rx_trigger = data[..readlen]
-                                                .iter()
-                                                .find(|&&c| c == (delimiter as u8))
-                                                .is_some();
-                                        }
-                                    } else {
-                                        serial_rx_trigger = true;
-                                    }
-                                    // append the incoming data to the main buffer
-                                    for &d in &data[..readlen] {
-                                        serial_buf.push(d);
-                                    }
-                                    // now see if we should pass it back to the listener (if it is hooked)
-                                    if serial_rx_trigger && serial_listener.is_some() {
-                                        let mut rx_msg = serial_listener.take().unwrap();
-                                        let mut response = unsafe {
-                                            Buffer::from_memory_message_mut(
-                                                rx_msg.body.memory_message_mut().unwrap(),
-                                            )
-                                        };
-                                        let mut buf = response.to_original::<UsbSerialAscii, _>().unwrap();
-                                        use std::fmt::Write; // is this really the best way to do it? probably not.
-                                        write!(
-                                            buf.s,
-                                            "{}",
-                                            std::string::String::from_utf8_lossy(&serial_buf)
-                                        )
-                                        .ok();
-
-                                        response.replace(buf).unwrap();
-                                        // the rx_msg will drop and respond to the listener
-                                        serial_rx_trigger = false;
-                                    }
-                                }
-                                SerialListenMode::BinaryListener => {
-                                    let readlen = serial_port.read(&mut data).unwrap_or(0);
-                                    if readlen == 0 {
-                                        continue;
-                                    }
-                                    // append the incoming data to the main buffer
-                                    for &d in &data[..readlen] {
-                                        serial_buf.push(d);
-                                    }
-                                    if serial_buf.len() >= SERIAL_BINARY_BUFLEN {
-                                        match serial_listener.take() {
-                                            Some(mut rx_msg) => {
-                                                let mut response = unsafe {
-                                                    Buffer::from_memory_message_mut(
-                                                        rx_msg.body.memory_message_mut().unwrap(),
-                                                    )
-                                                };
-                                                let mut buf =
-                                                    response.to_original::<UsbSerialBinary, _>().unwrap();
-                                                buf.d.copy_from_slice(
-                                                    serial_buf.drain(..SERIAL_BINARY_BUFLEN).as_slice(),
-                                                );
-                                                buf.len = SERIAL_BINARY_BUFLEN;
-                                                response.replace(buf).unwrap();
-                                                // the rx_msg will drop and respond to the listener
-                                            }
-                                            None => {
-                                                // do nothing, keep queuing data...
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                        serial_rx = serial_device.poll(&mut [&mut serial_port]);
                         None
                     }
                     Views::HIDv2 => {
@@ -836,6 +912,39 @@ pub(crate) fn main_hw() -> ! {
 
                         None
                     }
+                    Views::Ccid => {
+                        ccid_device.poll(&mut [&mut ccid]);
+                        if ccid_listener.is_some() {
+                            if let Some(request) = ccid.take_request() {
+                                let mut listener = ccid_listener.take().unwrap();
+                                ccid::deliver(&mut listener, request);
+                            }
+                        }
+                        None
+                    }
+                    Views::Ncm => {
+                        let was_active = ncm.is_active();
+                        ncm_device.poll(&mut [&mut ncm]);
+                        if ncm_listener.is_some() {
+                            if let Some(frame) = ncm.take_frame() {
+                                let mut listener = ncm_listener.take().unwrap();
+                                ncm::deliver(&mut listener, frame);
+                            } else if was_active && !ncm.is_active() {
+                                // the host took its end of the link down
+                                hangup_ncm_listener(&mut ncm_listener);
+                            }
+                        }
+                        None
+                    }
+                    Views::Composite => {
+                        let multi = composite_for(&mut multi_devices, multi_functions);
+                        if multi.poll() {
+                            serial_rx = multi.serial.is_some();
+                            multi.fido.as_mut().map(|fido| fido.device::<RawFido<'_, _>, _>())
+                        } else {
+                            None
+                        }
+                    }
                 };
                 if let Some(u2f) = maybe_u2f {
                     match u2f.read_report() {
@@ -861,6 +970,112 @@ pub(crate) fn main_hw() -> ! {
                         Err(e) => log::trace!("U2F ERR: {:?}", e),
                     }
                 }
+                let maybe_serial = if serial_rx {
+                    serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices)
+                } else {
+                    None
+                };
+                if let Some(serial_port) = maybe_serial {
+                    let mut data: [u8; SERIAL_BUF_LEN] = [0u8; SERIAL_BUF_LEN];
+                    match serial_listen_mode {
+                        SerialListenMode::NoListener => match serial_port.read(&mut data) {
+                            Ok(len) => match std::str::from_utf8(&data[..len]) {
+                                Ok(s) => log::debug!("No listener ascii: {}", s),
+                                Err(_) => {
+                                    log::debug!("No listener binary: {:x?}", &data[..len]);
+                                }
+                            },
+                            Err(e) => {
+                                log::debug!("No listener: {:?}", e);
+                            }
+                        },
+                        SerialListenMode::ConsoleListener => match serial_port.read(&mut data) {
+                            Ok(len) => match std::str::from_utf8(&data[..len]) {
+                                Ok(s) => {
+                                    for c in s.chars() {
+                                        native_kbd.inject_key(c);
+                                    }
+                                }
+                                Err(_) => {
+                                    log::info!("Non UTF-8 received on console: {:x?}", &data[..len]);
+                                }
+                            },
+                            Err(e) => {
+                                log::info!("Serial read error: {:?}", e);
+                            }
+                        },
+                        SerialListenMode::AsciiListener(maybe_delimiter) => {
+                            let readlen = serial_port.read(&mut data).unwrap_or(0);
+                            if readlen == 0 {
+                                continue;
+                            }
+                            if let Some(delimiter) = maybe_delimiter {
+                                if !delimiter.is_ascii() {
+                                    log::warn!(
+                                        "Chosen ASCII delimiter {} is not ASCII. Serial receive will not function properly.",
+                                        delimiter
+                                    );
+                                }
+                                if !serial_rx_trigger {
+                                    // once true, sticks as true
+                                    serial_rx_trigger =
+                                        data[..readlen].iter().find(|&&c| c == (delimiter as u8)).is_some();
+                                }
+                            } else {
+                                serial_rx_trigger = true;
+                            }
+                            // append the incoming data to the main buffer
+                            for &d in &data[..readlen] {
+                                serial_buf.push(d);
+                            }
+                            // now see if we should pass it back to the listener (if it is hooked)
+                            if serial_rx_trigger && serial_listener.is_some() {
+                                let mut rx_msg = serial_listener.take().unwrap();
+                                let mut response = unsafe {
+                                    Buffer::from_memory_message_mut(rx_msg.body.memory_message_mut().unwrap())
+                                };
+                                let mut buf = response.to_original::<UsbSerialAscii, _>().unwrap();
+                                use std::fmt::Write; // is this really the best way to do it? probably not.
+                                write!(buf.s, "{}", std::string::String::from_utf8_lossy(&serial_buf)).ok();
+
+                                response.replace(buf).unwrap();
+                                // the rx_msg will drop and respond to the listener
+                                serial_rx_trigger = false;
+                            }
+                        }
+                        SerialListenMode::BinaryListener => {
+                            let readlen = serial_port.read(&mut data).unwrap_or(0);
+                            if readlen == 0 {
+                                continue;
+                            }
+                            // append the incoming data to the main buffer
+                            for &d in &data[..readlen] {
+                                serial_buf.push(d);
+                            }
+                            if serial_buf.len() >= SERIAL_BINARY_BUFLEN {
+                                match serial_listener.take() {
+                                    Some(mut rx_msg) => {
+                                        let mut response = unsafe {
+                                            Buffer::from_memory_message_mut(
+                                                rx_msg.body.memory_message_mut().unwrap(),
+                                            )
+                                        };
+                                        let mut buf = response.to_original::<UsbSerialBinary, _>().unwrap();
+                                        buf.d.copy_from_slice(
+                                            serial_buf.drain(..SERIAL_BINARY_BUFLEN).as_slice(),
+                                        );
+                                        buf.len = SERIAL_BINARY_BUFLEN;
+                                        response.replace(buf).unwrap();
+                                        // the rx_msg will drop and respond to the listener
+                                    }
+                                    None => {
+                                        // do nothing, keep queuing data...
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
 
                 let is_suspend = match view {
                     Views::FidoWithKbd => usb_dev.state() == UsbDeviceState::Suspend,
@@ -869,6 +1084,12 @@ pub(crate) fn main_hw() -> ! {
                     Views::MassStorage => ums_device.state() == UsbDeviceState::Suspend,
                     Views::Serial => serial_device.state() == UsbDeviceState::Suspend,
                     Views::HIDv2 => hidv2.state() == UsbDeviceState::Suspend,
+                    Views::Ccid => ccid_device.state() == UsbDeviceState::Suspend,
+                    Views::Ncm => ncm_device.state() == UsbDeviceState::Suspend,
+                    Views::Composite => {
+                        composite_for(&mut multi_devices, multi_functions).device.state()
+                            == UsbDeviceState::Suspend
+                    }
                 };
                 if is_suspend {
                     log::info!("suspend detected");
@@ -885,6 +1106,19 @@ pub(crate) fn main_hw() -> ! {
                             buf.code = U2fCode::Hangup;
                             response.replace(buf).unwrap();
                         }
+                        // the card loses power along with the reader
+                        ccid.hangup();
+                        if let Some(mut listener) = ccid_listener.take() {
+                            let mut response = unsafe {
+                                Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap())
+                            };
+                            let mut buf = response.to_original::<CcidMsgIpc, _>().unwrap();
+                            buf.code = CcidCode::Hangup;
+                            response.replace(buf).unwrap();
+                        }
+                        // and the network link goes down with the cable
+                        ncm.hangup();
+                        hangup_ncm_listener(&mut ncm_listener);
                     }
                     was_suspend = true;
                 } else {
@@ -892,7 +1126,7 @@ pub(crate) fn main_hw() -> ! {
                 }
             }
             // always triggers a reset when called
-            Some(Opcode::SwitchCores) => msg_blocking_scalar_unpack!(msg, core, _, _, _, {
+            Some(Opcode::SwitchCores) => msg_blocking_scalar_unpack!(msg, core, functions, _, _, {
                 // ensure unhook the logger if it's connected to serial
                 let log_conn = xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap()).unwrap();
                 // it is never harmful to double-unhook this
@@ -920,6 +1154,9 @@ pub(crate) fn main_hw() -> ! {
                     .ok();
                     trng.set_test_mode(trng::api::TrngTestMode::None);
                 }
+                // the network link goes down across a reset
+                ncm.hangup();
+                hangup_ncm_listener(&mut ncm_listener);
 
                 let devtype: UsbDeviceType = core.try_into().unwrap();
                 match devtype {
@@ -1001,14 +1238,68 @@ pub(crate) fn main_hw() -> ! {
                             }
                         }
                     }
+                    UsbDeviceType::Ccid => {
+                        log::info!("Connecting CCID device");
+                        match view {
+                            Views::Ccid => usbmgmt.connect_device_core(true),
+                            _ => {
+                                view = Views::Ccid;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
+                    UsbDeviceType::Ncm => {
+                        log::info!("Connecting NCM device");
+                        match view {
+                            Views::Ncm => usbmgmt.connect_device_core(true),
+                            _ => {
+                                view = Views::Ncm;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
+                    UsbDeviceType::Composite => {
+                        let functions = requested_functions(functions);
+                        log::info!("Connecting composite device with {:?}", functions);
+                        match view {
+                            Views::Composite if functions == multi_functions => {
+                                usbmgmt.connect_device_core(true)
+                            }
+                            _ => {
+                                view = Views::Composite;
+                                multi_functions = functions;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
                 }
                 xous::return_scalar(msg.sender, 0).unwrap();
             }),
             // does not trigger a reset if we're already on the core
-            Some(Opcode::EnsureCore) => msg_blocking_scalar_unpack!(msg, core, _, _, _, {
+            Some(Opcode::EnsureCore) => msg_blocking_scalar_unpack!(msg, core, functions, _, _, {
                 let devtype: UsbDeviceType = core.try_into().unwrap();
+                let functions = requested_functions(functions);
+                let has_serial = view == Views::Serial
+                    || (view == Views::Composite && multi_functions.contains(CompositeFunctions::SERIAL));
+                let keeps_serial = match devtype {
+                    UsbDeviceType::Serial => view == Views::Serial,
+                    UsbDeviceType::Composite => view == Views::Composite && functions == multi_functions,
+                    _ => false,
+                };
                 // if we are switching away from serial, unhook any possible listeners, and the logger
-                if view == Views::Serial && devtype != UsbDeviceType::Serial {
+                if has_serial && !keeps_serial {
                     let log_conn =
                         xous::connect(xous::SID::from_bytes(b"xous-log-server ").unwrap()).unwrap();
                     // it is never harmful to double-unhook this
@@ -1037,6 +1328,10 @@ pub(crate) fn main_hw() -> ! {
                         trng.set_test_mode(trng::api::TrngTestMode::None);
                     }
                 }
+                if view == Views::Ncm && devtype != UsbDeviceType::Ncm {
+                    ncm.hangup();
+                    hangup_ncm_listener(&mut ncm_listener);
+                }
 
                 match devtype {
                     UsbDeviceType::Debug => {
@@ -1135,6 +1430,56 @@ pub(crate) fn main_hw() -> ! {
                             }
                         }
                     }
+                    UsbDeviceType::Ccid => {
+                        log::info!("Ensuring CCID device");
+                        if !usbmgmt.is_device_connected() {
+                            view = Views::Ccid;
+                            usbmgmt.connect_device_core(true);
+                        } else {
+                            if view != Views::Ccid {
+                                view = Views::Ccid;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
+                    UsbDeviceType::Ncm => {
+                        log::info!("Ensuring NCM device");
+                        if !usbmgmt.is_device_connected() {
+                            view = Views::Ncm;
+                            usbmgmt.connect_device_core(true);
+                        } else {
+                            if view != Views::Ncm {
+                                view = Views::Ncm;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
+                    UsbDeviceType::Composite => {
+                        log::info!("Ensuring composite device with {:?}", functions);
+                        if !usbmgmt.is_device_connected() {
+                            view = Views::Composite;
+                            multi_functions = functions;
+                            usbmgmt.connect_device_core(true);
+                        } else {
+                            if view != Views::Composite || multi_functions != functions {
+                                view = Views::Composite;
+                                multi_functions = functions;
+                                usbmgmt.ll_reset(true);
+                                tt.sleep_ms(1000).ok();
+                                usbmgmt.ll_connect_device_core(true);
+                                tt.sleep_ms(EXTENDED_CORE_RESET_MS).ok();
+                                usbmgmt.ll_reset(false);
+                            }
+                        }
+                    }
                 }
                 xous::return_scalar(msg.sender, 0).unwrap();
             }),
@@ -1157,6 +1502,11 @@ pub(crate) fn main_hw() -> ! {
                         Views::HIDv2 => {
                             xous::return_scalar(msg.sender, UsbDeviceType::HIDv2 as usize).unwrap()
                         }
+                        Views::Ccid => xous::return_scalar(msg.sender, UsbDeviceType::Ccid as usize).unwrap(),
+                        Views::Ncm => xous::return_scalar(msg.sender, UsbDeviceType::Ncm as usize).unwrap(),
+                        Views::Composite => {
+                            xous::return_scalar(msg.sender, UsbDeviceType::Composite as usize).unwrap()
+                        }
                     }
                 } else {
                     xous::return_scalar(msg.sender, UsbDeviceType::Debug as usize).unwrap();
@@ -1210,6 +1560,12 @@ pub(crate) fn main_hw() -> ! {
                     }
                     Views::Serial => xous::return_scalar(msg.sender, serial_device.state() as usize).unwrap(),
                     Views::HIDv2 => xous::return_scalar(msg.sender, hidv2.state() as usize).unwrap(),
+                    Views::Ccid => xous::return_scalar(msg.sender, ccid_device.state() as usize).unwrap(),
+                    Views::Ncm => xous::return_scalar(msg.sender, ncm_device.state() as usize).unwrap(),
+                    Views::Composite => {
+                        let state = composite_for(&mut multi_devices, multi_functions).device.state();
+                        xous::return_scalar(msg.sender, state as usize).unwrap()
+                    }
                 }
             }),
             Some(Opcode::SendKeyCode) => msg_blocking_scalar_unpack!(msg, code0, code1, code2, autoup, {
@@ -1266,8 +1622,8 @@ pub(crate) fn main_hw() -> ! {
                 // the logger API is "best effort" only. Because retries and response codes can cause problems
                 // in the logger API, if anything goes wrong, we prefer to discard characters rather than get
                 // the whole subsystem stuck in some awful recursive error handling hell.
-                match view {
-                    Views::Serial => {
+                match serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices) {
+                    Some(serial_port) => {
                         let buffer =
                             unsafe { Buffer::from_memory_message(msg.body.memory_message().unwrap()) };
                         let usb_send = buffer.to_original::<api::UsbString, _>().unwrap();
@@ -1293,7 +1649,7 @@ pub(crate) fn main_hw() -> ! {
                             }
                         }
                     }
-                    _ => {} // do nothing; don't fail, don't report any error.
+                    None => {} // do nothing; don't fail, don't report any error.
                 }
             }
             Some(Opcode::SetAutotypeRate) => msg_scalar_unpack!(msg, rate, _, _, _, {
@@ -1333,28 +1689,32 @@ pub(crate) fn main_hw() -> ! {
                             sent += 1;
                         }
                     }
-                    Views::Serial => {
-                        // this is implemented as a "blocking write": the routine will block until the data
-                        // has all been written.
-                        let send_data = usb_send.s.as_bytes();
-                        let to_send = usb_send.s.len();
-                        // log::debug!("serial RTS: {:?}", serial_port.rts());
-                        // log::debug!("serial DTR: {:?}", serial_port.dtr());
-                        while sent < to_send {
-                            match serial_port.write(&send_data[sent..to_send]) {
-                                Ok(written) => {
-                                    sent += written;
-                                }
-                                Err(_) => {
-                                    log::warn!("Serial send is blocking. Delaying and trying again.");
-                                    tt.sleep_ms(100).ok();
+                    Views::Serial | Views::Composite => {
+                        if let Some(serial_port) =
+                            serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices)
+                        {
+                            // this is implemented as a "blocking write": the routine will block until the
+                            // data has all been written.
+                            let send_data = usb_send.s.as_bytes();
+                            let to_send = usb_send.s.len();
+                            // log::debug!("serial RTS: {:?}", serial_port.rts());
+                            // log::debug!("serial DTR: {:?}", serial_port.dtr());
+                            while sent < to_send {
+                                match serial_port.write(&send_data[sent..to_send]) {
+                                    Ok(written) => {
+                                        sent += written;
+                                    }
+                                    Err(_) => {
+                                        log::warn!("Serial send is blocking. Delaying and trying again.");
+                                        tt.sleep_ms(100).ok();
+                                    }
                                 }
-                            }
-                            match serial_port.flush() {
-                                Ok(_) => {}
-                                Err(_) => {
-                                    log::warn!("Serial port reported WouldBlock on flush");
-                                    tt.sleep_ms(100).ok();
+                                match serial_port.flush() {
+                                    Ok(_) => {}
+                                    Err(_) => {
+                                        log::warn!("Serial port reported WouldBlock on flush");
+                                        tt.sleep_ms(100).ok();
+                                    }
                                 }
                             }
                         }
@@ -1438,7 +1798,11 @@ pub(crate) fn main_hw() -> ! {
             }
             Some(Opcode::SerialFlush) => msg_scalar_unpack!(msg, _, _, _, _, {
                 // this will hardware flush any pending items in usb_serial driver
-                serial_port.flush().ok();
+                if let Some(serial_port) =
+                    serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices)
+                {
+                    serial_port.flush().ok();
+                }
                 // this tries to return any data that's pending within the main loop's buffers
                 match serial_listen_mode {
                     SerialListenMode::BinaryListener => {
@@ -1480,7 +1844,7 @@ pub(crate) fn main_hw() -> ! {
                 }
             }),
             Some(Opcode::SerialHookTrngSender) => msg_scalar_unpack!(msg, trng_mode_code, _, _, _, {
-                if view != Views::Serial {
+                if serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices).is_none() {
                     log::error!("USB is not in serial mode. Ignoring request to hook TRNG sender");
                     continue;
                 }
@@ -1625,6 +1989,11 @@ pub(crate) fn main_hw() -> ! {
                     // stale request from previously configured TRNG system
                     continue;
                 }
+                let serial_port =
+                    match serial_for_view(&view, multi_functions, &mut serial_port, &mut multi_devices) {
+                        Some(port) => port,
+                        None => continue,
+                    };
                 let mut sent = false;
                 if serial_port.dtr() {
                     if serial_trng_buf.len() < TRNG_PKT_SIZE {
@@ -1735,13 +2104,15 @@ pub(crate) fn main_hw() -> ! {
             }
             Some(Opcode::SetLogLevel) => msg_scalar_unpack!(msg, level_code, _, _, _, {
                 let level = LogLevel::try_from(level_code).unwrap_or(LogLevel::Info);
-                match level {
-                    LogLevel::Trace => log::set_max_level(log::LevelFilter::Trace),
-                    LogLevel::Info => log::set_max_level(log::LevelFilter::Info),
-                    LogLevel::Debug => log::set_max_level(log::LevelFilter::Debug),
-                    LogLevel::Warn => log::set_max_level(log::LevelFilter::Warn),
-                    LogLevel::Err => log::set_max_level(log::LevelFilter::Error),
-                }
+                let filter = match level {
+                    LogLevel::Trace => log::LevelFilter::Trace,
+                    LogLevel::Info => log::LevelFilter::Info,
+                    LogLevel::Debug => log::LevelFilter::Debug,
+                    LogLevel::Warn => log::LevelFilter::Warn,
+                    LogLevel::Err => log::LevelFilter::Error,
+                };
+                // goes through the log server, so the change shows up with everyone else's levels
+                log_server::set_level(None, None, filter).ok();
             }),
             Some(Opcode::Quit) => {
                 log::warn!("Quit received, goodbye world!");
@@ -1759,3 +2130,127 @@ pub(crate) fn main_hw() -> ! {
     log::trace!("quitting");
     xous::terminate_process(0)
 }
+
+/// Tell the network server waiting for a frame, if any, that the link is down.
+fn hangup_ncm_listener(listener: &mut Option<xous::MessageEnvelope>) {
+    if let Some(mut listener) = listener.take() {
+        let mut response =
+            unsafe { Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap()) };
+        let mut buf = response.to_original::<NcmMsgIpc, _>().unwrap();
+        buf.code = NcmCode::Hangup;
+        response.replace(buf).unwrap();
+    }
+}
+
+/// A composite device that carries only the functions it was built with.
+struct CompositeDevice<'a> {
+    functions: CompositeFunctions,
+    device: UsbDevice<'a, SpinalUsbDevice>,
+    serial: Option<iad::Associated<SerialPort<'a, SpinalUsbDevice>>>,
+    fido: Option<UsbHidClass<'a, SpinalUsbDevice, HCons<RawFido<'a, SpinalUsbDevice>, HNil>>>,
+    #[cfg(feature = "mass-storage")]
+    ums: Option<usbd_scsi::Scsi<'a, SpinalUsbDevice, apps_block_device::AppsBlockDevice>>,
+}
+
+impl<'a> CompositeDevice<'a> {
+    fn new(
+        alloc: &'a UsbBusAllocator<SpinalUsbDevice>,
+        functions: CompositeFunctions,
+        serial_number: &'a str,
+        #[cfg(feature = "mass-storage")] abd: &apps_block_device::AppsBlockDevice,
+    ) -> CompositeDevice<'a> {
+        // allocated first, so the serial port's communication and data interfaces are 0 and 1
+        let serial = if functions.contains(CompositeFunctions::SERIAL) {
+            Some(iad::Associated::new(SerialPort::new(alloc), 0, 2, (0x02, 0x02, 0x01)))
+        } else {
+            None
+        };
+        let fido = if functions.contains(CompositeFunctions::FIDO) {
+            Some(UsbHidClassBuilder::new().add_device(RawFidoConfig::default()).build(alloc))
+        } else {
+            None
+        };
+        // shares the application block device with the mass storage core
+        #[cfg(feature = "mass-storage")]
+        let ums = if functions.contains(CompositeFunctions::MASS_STORAGE) {
+            Some(usbd_scsi::Scsi::new(
+                alloc,
+                64,
+                abd.clone(),
+                "Kosagi".as_bytes(),
+                "Kosagi Precursor".as_bytes(),
+                "1".as_bytes(),
+            ))
+        } else {
+            None
+        };
+        let device = UsbDeviceBuilder::new(alloc, UsbVidPid(0x1209, 0x3613))
+            .manufacturer("Kosagi")
+            .product("Precursor")
+            .serial_number(serial_number)
+            // "miscellaneous" device class: the functions are described by their interfaces and IADs
+            .device_class(0xef)
+            .device_sub_class(0x02)
+            .device_protocol(0x01)
+            .self_powered(false)
+            .max_power(500)
+            .build();
+        CompositeDevice {
+            functions,
+            device,
+            serial,
+            fido,
+            #[cfg(feature = "mass-storage")]
+            ums,
+        }
+    }
+
+    fn poll(&mut self) -> bool {
+        let mut classes: Vec<&mut dyn UsbClass<SpinalUsbDevice>> = Vec::new();
+        if let Some(serial) = self.serial.as_mut() {
+            classes.push(serial);
+        }
+        if let Some(fido) = self.fido.as_mut() {
+            classes.push(fido);
+        }
+        #[cfg(feature = "mass-storage")]
+        {
+            if let Some(ums) = self.ums.as_mut() {
+                classes.push(ums);
+            }
+        }
+        self.device.poll(&mut classes)
+    }
+}
+
+/// The composite device built with exactly `functions`.
+fn composite_for<'p, 'a>(
+    devices: &'p mut [CompositeDevice<'a>],
+    functions: CompositeFunctions,
+) -> &'p mut CompositeDevice<'a> {
+    devices
+        .iter_mut()
+        .find(|multi| multi.functions == functions)
+        .expect("no view for these composite functions")
+}
+
+/// The serial port of the current view, if it has one.
+fn serial_for_view<'p, 'a>(
+    view: &Views,
+    functions: CompositeFunctions,
+    serial: &'p mut SerialPort<'a, SpinalUsbDevice>,
+    composites: &'p mut [CompositeDevice<'a>],
+) -> Option<&'p mut SerialPort<'a, SpinalUsbDevice>> {
+    match view {
+        Views::Serial => Some(serial),
+        Views::Composite => composite_for(composites, functions).serial.as_deref_mut(),
+        _ => None,
+    }
+}
+
+/// The functions asked for in a `SwitchCores`/`EnsureCore` message. A bare `switch_to_core()` to the
+/// composite core doesn't name any, and gets all of them.
+fn requested_functions(bits: usize) -> CompositeFunctions {
+    let functions = CompositeFunctions::from_bits_truncate(bits);
+    if functions.is_empty() { CompositeFunctions::all() } else { functions }
+}
@@ -0,0 +1,516 @@
+//! A CDC-NCM (USB network control model) function, which gives the host an Ethernet interface to
+//! the device. The frames themselves are exchanged with the `net` server: the ones from the host
+//! are queued for the main loop to hand over, and `send()` takes the ones going the other way.
+//!
+//! Only the 16-bit NTB format is supported, and each NTB sent to the host carries one frame.
+//!
+//! Reference: "Universal Serial Bus Communications Class Subclass Specification for Network
+//! Control Model Devices", revision 1.0.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+use usb_device::Result;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+
+use crate::api::{NCM_MAX_FRAME_LEN, NcmCode, NcmMsgIpc};
+
+const USB_CLASS_CDC: u8 = 0x02;
+const CDC_SUBCLASS_NCM: u8 = 0x0D;
+const USB_CLASS_CDC_DATA: u8 = 0x0A;
+const CDC_DATA_PROTOCOL_NTB: u8 = 0x01;
+
+const CS_INTERFACE: u8 = 0x24;
+const CDC_TYPE_HEADER: u8 = 0x00;
+const CDC_TYPE_UNION: u8 = 0x06;
+const CDC_TYPE_ETHERNET: u8 = 0x0F;
+const CDC_TYPE_NCM: u8 = 0x1A;
+
+// class-specific requests
+const SET_ETHERNET_PACKET_FILTER: u8 = 0x43;
+const GET_NTB_PARAMETERS: u8 = 0x80;
+const GET_NTB_FORMAT: u8 = 0x83;
+const SET_NTB_FORMAT: u8 = 0x84;
+const GET_NTB_INPUT_SIZE: u8 = 0x85;
+const SET_NTB_INPUT_SIZE: u8 = 0x86;
+
+// notifications
+const NETWORK_CONNECTION: u8 = 0x00;
+const CONNECTION_SPEED_CHANGE: u8 = 0x2A;
+/// The speed reported to the host. It's what a full speed bulk pipe can carry, give or take.
+const LINK_SPEED_BPS: u32 = 12_000_000;
+
+const NTH16_SIGNATURE: u32 = 0x484D_434E; // "NCMH"
+const NDP16_SIGNATURE: u32 = 0x304D_434E; // "NCM0", without CRCs
+const NTH16_LEN: usize = 12;
+const NDP16_LEN: usize = 8;
+/// One datagram pointer and the null entry that ends the table.
+const NDP16_TX_LEN: usize = NDP16_LEN + 2 * 4;
+/// The largest NTB in either direction. This is also the smallest size Linux accepts.
+const NTB_MAX_SIZE: usize = 2048;
+const NTB_ALIGNMENT: usize = 4;
+
+const PACKET_SIZE: usize = 64;
+const NOTIFICATION_SIZE: usize = 16;
+/// Frames from the host that haven't been picked up yet. Beyond this, the oldest are dropped, as a
+/// busy network interface would.
+const MAX_RX_FRAMES: usize = 32;
+const MAX_TX_NTBS: usize = 8;
+
+pub struct NcmClass<'a, B: UsbBus> {
+    comm_interface: InterfaceNumber,
+    data_interface: InterfaceNumber,
+    notify: EndpointIn<'a, B>,
+    bulk_out: EndpointOut<'a, B>,
+    bulk_in: EndpointIn<'a, B>,
+    mac_string: StringIndex,
+    /// The MAC address of the host's end of the link, as twelve hex digits.
+    host_mac: String,
+    /// The host has selected the alternate setting with the data endpoints.
+    active: bool,
+    notifications: VecDeque<Vec<u8>>,
+    notify_busy: bool,
+    /// Reassembles an NTB that spans several packets.
+    rx: Vec<u8>,
+    frames: VecDeque<Vec<u8>>,
+    /// NTBs waiting to go to the host, and how much of the first one has been sent.
+    tx: VecDeque<Vec<u8>>,
+    tx_offset: usize,
+    tx_zlp: bool,
+    tx_sequence: u16,
+}
+
+impl<B: UsbBus> NcmClass<'_, B> {
+    pub fn new(alloc: &UsbBusAllocator<B>, host_mac: [u8; 6]) -> NcmClass<'_, B> {
+        NcmClass {
+            comm_interface: alloc.interface(),
+            data_interface: alloc.interface(),
+            notify: alloc.interrupt(NOTIFICATION_SIZE as u16, 32),
+            bulk_out: alloc.bulk(PACKET_SIZE as u16),
+            bulk_in: alloc.bulk(PACKET_SIZE as u16),
+            mac_string: alloc.string(),
+            host_mac: host_mac.iter().map(|b| format!("{:02X}", b)).collect(),
+            active: false,
+            notifications: VecDeque::new(),
+            notify_busy: false,
+            rx: Vec::new(),
+            frames: VecDeque::new(),
+            tx: VecDeque::new(),
+            tx_offset: 0,
+            tx_zlp: false,
+            tx_sequence: 0,
+        }
+    }
+
+    /// The link is up when the host has configured its end of it.
+    pub fn is_active(&self) -> bool { self.active }
+
+    /// The oldest Ethernet frame from the host, if any.
+    pub fn take_frame(&mut self) -> Option<Vec<u8>> { self.frames.pop_front() }
+
+    /// Queue an Ethernet frame for the host. Returns `false` if the link is down or backed up, in
+    /// which case the frame is dropped.
+    pub fn send(&mut self, frame: &[u8]) -> bool {
+        if !self.active || frame.is_empty() || frame.len() > NCM_MAX_FRAME_LEN || self.tx.len() >= MAX_TX_NTBS
+        {
+            return false;
+        }
+        let ntb = build_ntb(self.tx_sequence, frame);
+        self.tx_sequence = self.tx_sequence.wrapping_add(1);
+        let idle = self.tx.is_empty() && !self.tx_zlp;
+        self.tx.push_back(ntb);
+        if idle {
+            self.flush();
+        }
+        true
+    }
+
+    /// Forget all state, as if the cable had been pulled.
+    pub fn hangup(&mut self) {
+        self.active = false;
+        self.notifications.clear();
+        self.notify_busy = false;
+        self.rx.clear();
+        self.frames.clear();
+        self.tx.clear();
+        self.tx_offset = 0;
+        self.tx_zlp = false;
+    }
+
+    fn flush(&mut self) {
+        if self.tx_zlp {
+            if self.bulk_in.write(&[]).is_ok() {
+                self.tx_zlp = false;
+            }
+            return;
+        }
+        let ntb = match self.tx.front() {
+            Some(ntb) => ntb,
+            None => return,
+        };
+        let end = ntb.len().min(self.tx_offset + PACKET_SIZE);
+        match self.bulk_in.write(&ntb[self.tx_offset..end]) {
+            Ok(written) => {
+                self.tx_offset += written;
+                if self.tx_offset >= ntb.len() {
+                    // a transfer that ends on a packet boundary needs a zero-length packet to end it
+                    self.tx_zlp = ntb.len() % PACKET_SIZE == 0 && ntb.len() < NTB_MAX_SIZE;
+                    self.tx.pop_front();
+                    self.tx_offset = 0;
+                }
+            }
+            Err(UsbError::WouldBlock) => {}
+            Err(e) => log::warn!("NCM bulk in error: {:?}", e),
+        }
+    }
+
+    fn notify_next(&mut self) {
+        if self.notify_busy {
+            return;
+        }
+        if let Some(notification) = self.notifications.front() {
+            match self.notify.write(notification) {
+                Ok(_) => {
+                    self.notifications.pop_front();
+                    self.notify_busy = true;
+                }
+                Err(UsbError::WouldBlock) => {}
+                Err(e) => log::warn!("NCM notification error: {:?}", e),
+            }
+        }
+    }
+
+    fn notification(&self, code: u8, value: u16, data: &[u8]) -> Vec<u8> {
+        let mut notification = vec![0xA1, code];
+        notification.extend_from_slice(&value.to_le_bytes());
+        notification.extend_from_slice(&(u8::from(self.comm_interface) as u16).to_le_bytes());
+        notification.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        notification.extend_from_slice(data);
+        notification
+    }
+
+    fn set_active(&mut self, active: bool) {
+        self.hangup();
+        self.active = active;
+        if active {
+            let mut speeds = LINK_SPEED_BPS.to_le_bytes().to_vec();
+            speeds.extend_from_slice(&LINK_SPEED_BPS.to_le_bytes());
+            let speed = self.notification(CONNECTION_SPEED_CHANGE, 0, &speeds);
+            let connected = self.notification(NETWORK_CONNECTION, 1, &[]);
+            self.notifications.push_back(speed);
+            self.notifications.push_back(connected);
+            self.notify_next();
+        }
+    }
+
+    fn ntb_parameters() -> Vec<u8> {
+        let mut parameters = Vec::with_capacity(28);
+        parameters.extend_from_slice(&28u16.to_le_bytes()); // wLength
+        parameters.extend_from_slice(&1u16.to_le_bytes()); // bmNtbFormatsSupported: NTB16
+        parameters.extend_from_slice(&(NTB_MAX_SIZE as u32).to_le_bytes()); // dwNtbInMaxSize
+        parameters.extend_from_slice(&(NTB_ALIGNMENT as u16).to_le_bytes()); // wNdpInDivisor
+        parameters.extend_from_slice(&0u16.to_le_bytes()); // wNdpInPayloadRemainder
+        parameters.extend_from_slice(&(NTB_ALIGNMENT as u16).to_le_bytes()); // wNdpInAlignment
+        parameters.extend_from_slice(&0u16.to_le_bytes()); // reserved
+        parameters.extend_from_slice(&(NTB_MAX_SIZE as u32).to_le_bytes()); // dwNtbOutMaxSize
+        parameters.extend_from_slice(&(NTB_ALIGNMENT as u16).to_le_bytes()); // wNdpOutDivisor
+        parameters.extend_from_slice(&0u16.to_le_bytes()); // wNdpOutPayloadRemainder
+        parameters.extend_from_slice(&(NTB_ALIGNMENT as u16).to_le_bytes()); // wNdpOutAlignment
+        parameters.extend_from_slice(&0u16.to_le_bytes()); // wNtbOutMaxDatagrams: no limit
+        parameters
+    }
+
+    fn is_ours(&self, req: &usb_device::control::Request) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u8::from(self.comm_interface) as u16
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for NcmClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.interface(self.comm_interface, USB_CLASS_CDC, CDC_SUBCLASS_NCM, 0x00)?;
+        writer.write(CS_INTERFACE, &[CDC_TYPE_HEADER, 0x10, 0x01])?; // bcdCDC 1.10
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_UNION, u8::from(self.comm_interface), u8::from(self.data_interface)],
+        )?;
+        let max_segment = (NCM_MAX_FRAME_LEN as u16).to_le_bytes();
+        #[rustfmt::skip]
+        writer.write(CS_INTERFACE, &[
+            CDC_TYPE_ETHERNET,
+            u8::from(self.mac_string),      // iMACAddress
+            0x00, 0x00, 0x00, 0x00,         // bmEthernetStatistics: none
+            max_segment[0], max_segment[1], // wMaxSegmentSize
+            0x00, 0x00,                     // wNumberMCFilters: multicast is always let through
+            0x00,                           // bNumberPowerFilters
+        ])?;
+        writer.write(CS_INTERFACE, &[CDC_TYPE_NCM, 0x00, 0x01, 0x00])?; // bcdNcmVersion 1.00, no options
+        writer.endpoint(&self.notify)?;
+        // the data interface has no endpoints until the host selects the second alternate setting
+        writer.interface_alt(
+            self.data_interface,
+            0,
+            USB_CLASS_CDC_DATA,
+            0x00,
+            CDC_DATA_PROTOCOL_NTB,
+            None,
+        )?;
+        writer.interface_alt(
+            self.data_interface,
+            1,
+            USB_CLASS_CDC_DATA,
+            0x00,
+            CDC_DATA_PROTOCOL_NTB,
+            None,
+        )?;
+        writer.endpoint(&self.bulk_in)?;
+        writer.endpoint(&self.bulk_out)
+    }
+
+    fn get_string(&self, index: StringIndex, _lang_id: u16) -> Option<&str> {
+        if index == self.mac_string { Some(&self.host_mac) } else { None }
+    }
+
+    fn get_alt_setting(&mut self, interface: InterfaceNumber) -> Option<u8> {
+        if interface == self.data_interface { Some(self.active as u8) } else { None }
+    }
+
+    fn set_alt_setting(&mut self, interface: InterfaceNumber, alternative: u8) -> bool {
+        if interface != self.data_interface || alternative > 1 {
+            return false;
+        }
+        log::info!("NCM link {}", if alternative == 1 { "up" } else { "down" });
+        self.set_active(alternative == 1);
+        true
+    }
+
+    fn reset(&mut self) { self.hangup(); }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_ours(&req) {
+            return;
+        }
+        match req.request {
+            GET_NTB_PARAMETERS => {
+                xfer.accept_with(&Self::ntb_parameters()).ok();
+            }
+            GET_NTB_FORMAT => {
+                xfer.accept_with(&0u16.to_le_bytes()).ok();
+            }
+            GET_NTB_INPUT_SIZE => {
+                xfer.accept_with(&(NTB_MAX_SIZE as u32).to_le_bytes()).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_ours(&req) {
+            return;
+        }
+        match req.request {
+            SET_ETHERNET_PACKET_FILTER => {
+                // everything for us is wanted anyway, and smoltcp does its own filtering
+                xfer.accept().ok();
+            }
+            SET_NTB_FORMAT if req.value == 0 => {
+                xfer.accept().ok();
+            }
+            SET_NTB_INPUT_SIZE => {
+                // the host may only ask for NTBs smaller than the ones we offered, which each NTB
+                // carrying a single frame already are
+                let size = xfer.data().get(..4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+                match size {
+                    Some(size) if size as usize <= NTB_MAX_SIZE => xfer.accept().ok(),
+                    _ => xfer.reject().ok(),
+                };
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr != self.bulk_out.address() {
+            return;
+        }
+        let mut packet = [0u8; PACKET_SIZE];
+        let len = match self.bulk_out.read(&mut packet) {
+            Ok(len) => len,
+            Err(UsbError::WouldBlock) => return,
+            Err(e) => {
+                log::warn!("NCM bulk out error: {:?}", e);
+                return;
+            }
+        };
+        if !self.active {
+            return;
+        }
+        self.rx.extend_from_slice(&packet[..len]);
+        // an NTB ends with a short packet, or when it reaches the size we told the host about
+        if len < PACKET_SIZE || self.rx.len() >= NTB_MAX_SIZE {
+            let ntb = std::mem::take(&mut self.rx);
+            for frame in parse_ntb(&ntb) {
+                if self.frames.len() >= MAX_RX_FRAMES {
+                    self.frames.pop_front();
+                }
+                self.frames.push_back(frame.to_vec());
+            }
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.bulk_in.address() {
+            self.flush();
+        } else if addr == self.notify.address() {
+            self.notify_busy = false;
+            self.notify_next();
+        }
+    }
+}
+
+/// Wraps one frame in an NTB16.
+fn build_ntb(sequence: u16, frame: &[u8]) -> Vec<u8> {
+    let block_length = NTH16_LEN + NDP16_TX_LEN + frame.len();
+    let mut ntb = Vec::with_capacity(block_length);
+    ntb.extend_from_slice(&NTH16_SIGNATURE.to_le_bytes());
+    ntb.extend_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+    ntb.extend_from_slice(&sequence.to_le_bytes());
+    ntb.extend_from_slice(&(block_length as u16).to_le_bytes());
+    ntb.extend_from_slice(&(NTH16_LEN as u16).to_le_bytes()); // wNdpIndex
+    ntb.extend_from_slice(&NDP16_SIGNATURE.to_le_bytes());
+    ntb.extend_from_slice(&(NDP16_TX_LEN as u16).to_le_bytes());
+    ntb.extend_from_slice(&0u16.to_le_bytes()); // wNextNdpIndex
+    ntb.extend_from_slice(&((NTH16_LEN + NDP16_TX_LEN) as u16).to_le_bytes());
+    ntb.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+    ntb.extend_from_slice(&[0; 4]); // end of the datagram pointers
+    ntb.extend_from_slice(frame);
+    ntb
+}
+
+fn le16(buf: &[u8], offset: usize) -> Option<usize> {
+    buf.get(offset..offset + 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()) as usize)
+}
+
+/// The datagrams in an NTB16 from the host. Anything that doesn't fit in the NTB is dropped.
+fn parse_ntb(ntb: &[u8]) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    if ntb.len() < NTH16_LEN || ntb[..4] != NTH16_SIGNATURE.to_le_bytes() {
+        log::warn!("NCM: dropping a transfer that isn't an NTB16");
+        return frames;
+    }
+    let ntb = &ntb[..le16(ntb, 8).unwrap().min(ntb.len())];
+    let mut ndp_index = le16(ntb, 10).unwrap_or(0);
+    // each NDP has to come after the previous one, which also bounds the walk
+    let mut previous = 0;
+    while ndp_index > previous && ndp_index + NDP16_LEN <= ntb.len() {
+        if ntb[ndp_index..ndp_index + 4] != NDP16_SIGNATURE.to_le_bytes() {
+            log::warn!("NCM: unsupported NDP signature {:x?}", &ntb[ndp_index..ndp_index + 4]);
+            break;
+        }
+        let end = (ndp_index + le16(ntb, ndp_index + 4).unwrap()).min(ntb.len());
+        let mut entry = ndp_index + NDP16_LEN;
+        while entry + 4 <= end {
+            let (index, length) = (le16(ntb, entry).unwrap(), le16(ntb, entry + 2).unwrap());
+            if index == 0 || length == 0 {
+                break;
+            }
+            match ntb.get(index..index + length) {
+                Some(frame) if length <= NCM_MAX_FRAME_LEN => frames.push(frame),
+                _ => log::warn!("NCM: dropping a datagram at {} of {} bytes", index, length),
+            }
+            entry += 4;
+        }
+        previous = ndp_index;
+        ndp_index = le16(ntb, ndp_index + 6).unwrap();
+    }
+    frames
+}
+
+/// A MAC address for the host's end of the link. It has to be stable, so that the host keeps its
+/// settings for the interface from one connection to the next, and unique to the device, so it is
+/// derived from the serial number. The address is locally administered and unicast.
+pub(crate) fn host_mac(serial_number: &str) -> [u8; 6] {
+    // FNV-1a, which is plenty to spread a serial number over 46 bits
+    let hash = serial_number
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&hash.to_be_bytes()[2..]);
+    mac[0] = (mac[0] & 0xFC) | 0x02;
+    mac
+}
+
+/// Hand `frame` to the server waiting on `listener`.
+pub(crate) fn deliver(listener: &mut xous::MessageEnvelope, frame: Vec<u8>) {
+    let mut response =
+        unsafe { xous_ipc::Buffer::from_memory_message_mut(listener.body.memory_message_mut().unwrap()) };
+    let mut buf = response.to_original::<NcmMsgIpc, _>().unwrap();
+    assert_eq!(buf.code, NcmCode::RxWait, "Expected NcmCode::RxWait in wrapper");
+    let len = frame.len().min(NCM_MAX_FRAME_LEN);
+    buf.data[..len].copy_from_slice(&frame[..len]);
+    buf.len = len;
+    buf.code = NcmCode::Frame;
+    response.replace(buf).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntb_roundtrip() {
+        let frame: Vec<u8> = (0..60).collect();
+        let ntb = build_ntb(7, &frame);
+        assert_eq!(ntb.len(), NTH16_LEN + NDP16_TX_LEN + frame.len());
+        assert_eq!(le16(&ntb, 6), Some(7));
+        assert_eq!(parse_ntb(&ntb), vec![&frame[..]]);
+    }
+
+    #[test]
+    fn ntb_with_several_datagrams_and_ndps() {
+        // the layout Linux uses: header, datagrams, then the NDPs at the end
+        let mut ntb = vec![0u8; 64];
+        ntb[..4].copy_from_slice(&NTH16_SIGNATURE.to_le_bytes());
+        ntb[4..6].copy_from_slice(&12u16.to_le_bytes());
+        ntb[8..10].copy_from_slice(&64u16.to_le_bytes());
+        ntb[10..12].copy_from_slice(&24u16.to_le_bytes());
+        ntb[12..16].copy_from_slice(b"abcd");
+        ntb[16..22].copy_from_slice(b"efghij");
+        // first NDP: two datagrams, and a pointer to the second NDP
+        ntb[24..28].copy_from_slice(&NDP16_SIGNATURE.to_le_bytes());
+        ntb[28..30].copy_from_slice(&20u16.to_le_bytes());
+        ntb[30..32].copy_from_slice(&44u16.to_le_bytes());
+        ntb[32..36].copy_from_slice(&[12, 0, 4, 0]);
+        ntb[36..40].copy_from_slice(&[16, 0, 6, 0]);
+        // second NDP: one datagram that runs off the end of the NTB, which is dropped
+        ntb[44..48].copy_from_slice(&NDP16_SIGNATURE.to_le_bytes());
+        ntb[48..50].copy_from_slice(&16u16.to_le_bytes());
+        ntb[52..56].copy_from_slice(&[60, 0, 8, 0]);
+        assert_eq!(parse_ntb(&ntb), vec![&b"abcd"[..], &b"efghij"[..]]);
+    }
+
+    #[test]
+    fn ntb_that_loops_is_bounded() {
+        let mut ntb = build_ntb(0, b"frame");
+        // point the NDP back at itself
+        ntb[18..20].copy_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+        assert_eq!(parse_ntb(&ntb), vec![&b"frame"[..]]);
+        assert!(parse_ntb(&ntb[..8]).is_empty());
+    }
+
+    #[test]
+    fn host_mac_is_local_unicast() {
+        let mac = host_mac("1234abcd");
+        assert_eq!(mac[0] & 0x03, 0x02);
+        assert_eq!(mac, host_mac("1234abcd"));
+        assert_ne!(mac, host_mac("1234abce"));
+    }
+}
@@ -0,0 +1,408 @@
+//! The USB/IP wire protocol, as spoken by the Linux `usbip` tools and `vhci-hcd`. See
+//! Documentation/usb/usbip_protocol.rst in the kernel tree. Every field is big-endian.
+
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind, Read, Result};
+
+pub const USBIP_VERSION: u16 = 0x0111;
+
+pub const OP_REQ_DEVLIST: u16 = 0x8005;
+pub const OP_REP_DEVLIST: u16 = 0x0005;
+pub const OP_REQ_IMPORT: u16 = 0x8003;
+pub const OP_REP_IMPORT: u16 = 0x0003;
+
+pub const USBIP_CMD_SUBMIT: u32 = 0x0001;
+pub const USBIP_CMD_UNLINK: u32 = 0x0002;
+pub const USBIP_RET_SUBMIT: u32 = 0x0003;
+pub const USBIP_RET_UNLINK: u32 = 0x0004;
+
+pub const USBIP_DIR_IN: u32 = 1;
+
+/// URB status codes are negated Linux errno values.
+pub const EPIPE: i32 = -32;
+pub const ETIMEDOUT: i32 = -110;
+pub const ECONNRESET: i32 = -104;
+
+/// The largest transfer accepted on a bulk or interrupt endpoint. This is generous: the biggest transfers
+/// Linux class drivers submit are mass storage's, at 120 KiB.
+pub const MAX_TRANSFER_LENGTH: usize = 256 * 1024;
+
+/// `enum usb_device_speed` in Linux.
+const USB_SPEED_FULL: u32 = 2;
+const SYSFS_PATH_MAX: usize = 256;
+const SYSFS_BUS_ID_SIZE: usize = 32;
+
+/// A request on a fresh connection, before any device has been imported.
+#[derive(Debug, PartialEq, Eq)]
+pub enum OpRequest {
+    DevList,
+    Import { busid: String },
+}
+
+/// A request on a connection whose device has been imported.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UrbCommand {
+    Submit {
+        seqnum: u32,
+        ep: u8,
+        dir_in: bool,
+        /// Number of bytes the host expects for an IN transfer, or is sending for an OUT transfer.
+        length: usize,
+        /// Only meaningful for control transfers.
+        setup: [u8; 8],
+        /// The payload of an OUT transfer.
+        data: Vec<u8>,
+        /// Isochronous transfers are not supported, but their descriptors still have to be consumed.
+        iso_packets: usize,
+    },
+    Unlink {
+        seqnum: u32,
+        /// The sequence number of the submission to cancel.
+        victim: u32,
+    },
+}
+
+/// What `OP_REP_DEVLIST` and `OP_REP_IMPORT` say about an exported device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub path: String,
+    pub busid: String,
+    pub busnum: u32,
+    pub devnum: u32,
+    pub id_vendor: u16,
+    pub id_product: u16,
+    pub bcd_device: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub configuration_value: u8,
+    pub num_configurations: u8,
+    /// Class, subclass and protocol of each interface of the first configuration.
+    pub interfaces: Vec<(u8, u8, u8)>,
+}
+
+impl DeviceInfo {
+    /// Build the description of device `busnum-devnum` from its device and configuration descriptors.
+    pub fn from_descriptors(busnum: u32, devnum: u32, device: &[u8], config: &[u8]) -> Option<DeviceInfo> {
+        if device.len() < 18 || device[1] != 1 || config.len() < 9 || config[1] != 2 {
+            return None;
+        }
+        let mut interfaces = Vec::new();
+        let mut rest = config;
+        while rest.len() >= 2 && rest[0] >= 2 && rest[0] as usize <= rest.len() {
+            let (descriptor, tail) = rest.split_at(rest[0] as usize);
+            // only the default alternate setting of each interface is reported
+            if descriptor[1] == 4 && descriptor.len() >= 9 && descriptor[3] == 0 {
+                interfaces.push((descriptor[5], descriptor[6], descriptor[7]));
+            }
+            rest = tail;
+        }
+        let busid = format!("{}-{}", busnum, devnum);
+        Some(DeviceInfo {
+            path: format!("/sys/devices/platform/xous/usb{}/{}", busnum, busid),
+            busid,
+            busnum,
+            devnum,
+            id_vendor: u16::from_le_bytes([device[8], device[9]]),
+            id_product: u16::from_le_bytes([device[10], device[11]]),
+            bcd_device: u16::from_le_bytes([device[12], device[13]]),
+            class: device[4],
+            subclass: device[5],
+            protocol: device[6],
+            configuration_value: config[5],
+            num_configurations: device[17],
+            interfaces,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        write_str(out, &self.path, SYSFS_PATH_MAX);
+        write_str(out, &self.busid, SYSFS_BUS_ID_SIZE);
+        out.extend_from_slice(&self.busnum.to_be_bytes());
+        out.extend_from_slice(&self.devnum.to_be_bytes());
+        out.extend_from_slice(&USB_SPEED_FULL.to_be_bytes());
+        out.extend_from_slice(&self.id_vendor.to_be_bytes());
+        out.extend_from_slice(&self.id_product.to_be_bytes());
+        out.extend_from_slice(&self.bcd_device.to_be_bytes());
+        out.extend_from_slice(&[
+            self.class,
+            self.subclass,
+            self.protocol,
+            self.configuration_value,
+            self.num_configurations,
+            self.interfaces.len() as u8,
+        ]);
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str, len: usize) {
+    let bytes = &s.as_bytes()[..s.len().min(len - 1)];
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + len - bytes.len(), 0);
+}
+
+fn read_u16(r: &mut impl Read) -> Result<u16> {
+    let mut buf = [0u8; 2];
+    r.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub fn read_op_request(r: &mut impl Read) -> Result<OpRequest> {
+    let _version = read_u16(r)?;
+    let code = read_u16(r)?;
+    let _status = read_u32(r)?;
+    match code {
+        OP_REQ_DEVLIST => Ok(OpRequest::DevList),
+        OP_REQ_IMPORT => {
+            let mut busid = [0u8; SYSFS_BUS_ID_SIZE];
+            r.read_exact(&mut busid)?;
+            let len = busid.iter().position(|&b| b == 0).unwrap_or(busid.len());
+            Ok(OpRequest::Import { busid: String::from_utf8_lossy(&busid[..len]).into_owned() })
+        }
+        _ => Err(Error::new(ErrorKind::InvalidData, "unknown USB/IP operation")),
+    }
+}
+
+pub fn devlist_reply(devices: &[DeviceInfo]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&USBIP_VERSION.to_be_bytes());
+    out.extend_from_slice(&OP_REP_DEVLIST.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&(devices.len() as u32).to_be_bytes());
+    for device in devices {
+        device.write(&mut out);
+        for &(class, subclass, protocol) in device.interfaces.iter() {
+            out.extend_from_slice(&[class, subclass, protocol, 0]);
+        }
+    }
+    out
+}
+
+/// The reply to an import request; `None` refuses it.
+pub fn import_reply(device: Option<&DeviceInfo>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&USBIP_VERSION.to_be_bytes());
+    out.extend_from_slice(&OP_REP_IMPORT.to_be_bytes());
+    match device {
+        Some(device) => {
+            out.extend_from_slice(&0u32.to_be_bytes());
+            device.write(&mut out);
+        }
+        None => out.extend_from_slice(&1u32.to_be_bytes()),
+    }
+    out
+}
+
+pub fn read_urb_command(r: &mut impl Read) -> Result<UrbCommand> {
+    let mut header = [0u8; 48];
+    r.read_exact(&mut header)?;
+    let word = |index: usize| u32::from_be_bytes(header[index * 4..index * 4 + 4].try_into().unwrap());
+    let command = word(0);
+    let seqnum = word(1);
+    match command {
+        USBIP_CMD_SUBMIT => {
+            let dir_in = word(3) == USBIP_DIR_IN;
+            let length = word(6) as i32;
+            let iso_packets = word(8) as i32;
+            if length < 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "negative transfer length"));
+            }
+            let ep = (word(4) & 0xF) as u8;
+            if length as usize > max_transfer_length(ep) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "transfer length exceeds the endpoint's maximum",
+                ));
+            }
+            let mut data = Vec::new();
+            if !dir_in {
+                data.resize(length as usize, 0);
+                r.read_exact(&mut data)?;
+            }
+            // iso_packets is 0 or -1 for everything that isn't isochronous
+            let iso_packets = iso_packets.max(0) as usize;
+            let mut iso_descriptor = [0u8; 16];
+            for _ in 0..iso_packets {
+                r.read_exact(&mut iso_descriptor)?;
+            }
+            Ok(UrbCommand::Submit {
+                seqnum,
+                ep,
+                dir_in,
+                length: length as usize,
+                setup: header[40..48].try_into().unwrap(),
+                data,
+                iso_packets,
+            })
+        }
+        USBIP_CMD_UNLINK => Ok(UrbCommand::Unlink { seqnum, victim: word(5) }),
+        _ => Err(Error::new(ErrorKind::InvalidData, "unknown USB/IP command")),
+    }
+}
+
+/// The largest transfer a host may submit on endpoint `ep`. A control transfer is bounded by the 16-bit
+/// `wLength` of its setup packet.
+pub fn max_transfer_length(ep: u8) -> usize { if ep == 0 { u16::MAX as usize } else { MAX_TRANSFER_LENGTH } }
+
+/// Completes a submission. `data` is what an IN transfer returns, and is empty for an OUT transfer.
+pub fn ret_submit(seqnum: u32, status: i32, actual_length: usize, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(48 + data.len());
+    for word in [USBIP_RET_SUBMIT, seqnum, 0, 0, 0].iter() {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    for word in [status, actual_length as i32, 0, 0, 0].iter() {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    out.resize(48, 0);
+    out.extend_from_slice(data);
+    out
+}
+
+pub fn ret_unlink(seqnum: u32, status: i32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(48);
+    for word in [USBIP_RET_UNLINK, seqnum, 0, 0, 0].iter() {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    out.extend_from_slice(&status.to_be_bytes());
+    out.resize(48, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: [u8; 18] = [18, 1, 0x00, 0x02, 0, 0, 0, 64, 0x09, 0x12, 0x13, 0x36, 0x10, 0x01, 1, 2, 3, 1];
+    #[rustfmt::skip]
+    const CONFIG: [u8; 41] = [
+        9, 2, 41, 0, 1, 1, 0, 0x80, 50,
+        // interface 0, a HID
+        9, 4, 0, 0, 2, 3, 0, 0, 0,
+        9, 0x21, 0x11, 0x01, 0, 1, 0x22, 34, 0,
+        7, 5, 0x81, 3, 64, 0, 1,
+        // an alternate setting that must not be reported
+        7, 4, 0, 1, 0, 0xFF, 0,
+    ];
+
+    #[test]
+    fn test_device_info() {
+        let info = DeviceInfo::from_descriptors(1, 1, &DEVICE, &CONFIG).unwrap();
+        assert_eq!(info.busid, "1-1");
+        assert_eq!((info.id_vendor, info.id_product, info.bcd_device), (0x1209, 0x3613, 0x0110));
+        assert_eq!(info.configuration_value, 1);
+        assert_eq!(info.interfaces, vec![(3, 0, 0)]);
+        assert!(DeviceInfo::from_descriptors(1, 1, &CONFIG, &DEVICE).is_none());
+    }
+
+    #[test]
+    fn test_replies() {
+        let info = DeviceInfo::from_descriptors(1, 1, &DEVICE, &CONFIG).unwrap();
+        let devlist = devlist_reply(std::slice::from_ref(&info));
+        assert_eq!(devlist.len(), 12 + 312 + 4);
+        assert_eq!(&devlist[..8], &[0x01, 0x11, 0x00, 0x05, 0, 0, 0, 0]);
+        assert_eq!(&devlist[8..12], &[0, 0, 0, 1]);
+        assert_eq!(&devlist[12 + 256..12 + 260], b"1-1\0");
+        assert_eq!(&devlist[12 + 300..12 + 306], &[0x12, 0x09, 0x36, 0x13, 0x01, 0x10]);
+        assert_eq!(&devlist[12 + 312..], &[3, 0, 0, 0]);
+
+        let import = import_reply(Some(&info));
+        assert_eq!(import.len(), 8 + 312);
+        assert_eq!(&import[..8], &[0x01, 0x11, 0x00, 0x03, 0, 0, 0, 0]);
+        assert_eq!(&import[8..], &devlist[12..12 + 312]);
+        assert_eq!(import_reply(None), vec![0x01, 0x11, 0x00, 0x03, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_op_request() {
+        let mut devlist: &[u8] = &[0x01, 0x11, 0x80, 0x05, 0, 0, 0, 0];
+        assert_eq!(read_op_request(&mut devlist).unwrap(), OpRequest::DevList);
+        let mut import = vec![0x01, 0x11, 0x80, 0x03, 0, 0, 0, 0];
+        import.extend_from_slice(b"1-1");
+        import.resize(8 + 32, 0);
+        assert_eq!(
+            read_op_request(&mut &import[..]).unwrap(),
+            OpRequest::Import { busid: String::from("1-1") }
+        );
+        assert!(read_op_request(&mut &[0x01, 0x11, 0x80, 0x07, 0, 0, 0, 0][..]).is_err());
+    }
+
+    fn submit(seqnum: u32, dir_in: bool, ep: u32, length: u32, setup: [u8; 8]) -> Vec<u8> {
+        let mut cmd = Vec::new();
+        for word in [USBIP_CMD_SUBMIT, seqnum, 0x0001_0001, dir_in as u32, ep, 0, length, 0, 0, 0].iter() {
+            cmd.extend_from_slice(&word.to_be_bytes());
+        }
+        cmd.extend_from_slice(&setup);
+        cmd
+    }
+
+    #[test]
+    fn test_urb_commands() {
+        let setup = [0x80, 6, 0, 1, 0, 0, 18, 0];
+        let mut stream = submit(1, true, 0, 18, setup);
+        stream.extend_from_slice(&submit(2, false, 2, 3, [0; 8]));
+        stream.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+        for word in [USBIP_CMD_UNLINK, 3, 0x0001_0001, 0, 2, 2, 0, 0, 0, 0, 0, 0].iter() {
+            stream.extend_from_slice(&word.to_be_bytes());
+        }
+        let mut r = &stream[..];
+        assert_eq!(
+            read_urb_command(&mut r).unwrap(),
+            UrbCommand::Submit {
+                seqnum: 1,
+                ep: 0,
+                dir_in: true,
+                length: 18,
+                setup,
+                data: vec![],
+                iso_packets: 0
+            }
+        );
+        assert_eq!(
+            read_urb_command(&mut r).unwrap(),
+            UrbCommand::Submit {
+                seqnum: 2,
+                ep: 2,
+                dir_in: false,
+                length: 3,
+                setup: [0; 8],
+                data: vec![0xAA, 0xBB, 0xCC],
+                iso_packets: 0
+            }
+        );
+        assert_eq!(read_urb_command(&mut r).unwrap(), UrbCommand::Unlink { seqnum: 3, victim: 2 });
+        assert!(r.is_empty());
+        assert!(read_urb_command(&mut r).is_err());
+    }
+
+    #[test]
+    fn test_oversized_submit() {
+        // rejected from the header alone, before any payload is read
+        let mut r = &submit(1, false, 2, u32::MAX >> 1, [0; 8])[..];
+        assert!(read_urb_command(&mut r).is_err());
+        let mut r = &submit(1, true, 2, MAX_TRANSFER_LENGTH as u32 + 1, [0; 8])[..];
+        assert!(read_urb_command(&mut r).is_err());
+        let mut r = &submit(1, false, 0, 0x1_0000, [0; 8])[..];
+        assert!(read_urb_command(&mut r).is_err());
+        let mut r = &submit(1, true, 2, MAX_TRANSFER_LENGTH as u32, [0; 8])[..];
+        assert!(read_urb_command(&mut r).is_ok());
+    }
+
+    #[test]
+    fn test_rets() {
+        let ret = ret_submit(7, EPIPE, 2, &[1, 2]);
+        assert_eq!(ret.len(), 50);
+        assert_eq!(&ret[..8], &[0, 0, 0, 3, 0, 0, 0, 7]);
+        assert_eq!(&ret[20..28], &[0xFF, 0xFF, 0xFF, 0xE0, 0, 0, 0, 2]);
+        assert_eq!(&ret[48..], &[1, 2]);
+        let ret = ret_unlink(8, ECONNRESET);
+        assert_eq!(ret.len(), 48);
+        assert_eq!(&ret[..8], &[0, 0, 0, 4, 0, 0, 0, 8]);
+        assert_eq!(&ret[20..24], &(-104i32).to_be_bytes());
+    }
+}
@@ -0,0 +1,139 @@
+//! The levels of the processes that registered for runtime level control. Filtering happens in the
+//! processes themselves; this keeps the authoritative copy and hands changes to the listener thread each
+//! process leaves waiting here.
+
+use xous_api_log::api;
+
+struct Process {
+    pid: u8,
+    name: String,
+    modules: Vec<(String, u32)>,
+    /// The process' `ListenLevels`, held until its levels change
+    listener: Option<xous::MessageEnvelope>,
+    /// Levels changed while no listener was waiting
+    changed: bool,
+}
+
+pub struct Levels {
+    /// In the order they registered, which is the order `GetLevels` indexes them in
+    processes: Vec<Process>,
+    /// Lets `SetLevel` change the levels of processes other than the sender
+    token: [u32; 4],
+    /// The token is handed out once per boot, to the first process that claims it
+    claimed: bool,
+}
+
+impl Levels {
+    pub fn new() -> Self {
+        Levels {
+            processes: Vec::new(),
+            token: xous::create_server_id().expect("couldn't create level control token").to_array(),
+            claimed: false,
+        }
+    }
+
+    pub fn claim(&mut self) -> Option<[u32; 4]> {
+        if self.claimed {
+            None
+        } else {
+            self.claimed = true;
+            Some(self.token)
+        }
+    }
+
+    pub fn register(&mut self, pid: u8, levels: &api::ModuleLevels) {
+        // a PID can be reused by a new process, which replaces whatever the old one had
+        self.processes.retain(|p| p.pid != pid);
+        self.processes.push(Process {
+            pid,
+            name: levels.name().to_string(),
+            modules: levels.iter().map(|(module, level)| (module.to_string(), level)).collect(),
+            listener: None,
+            changed: false,
+        });
+    }
+
+    pub fn listen(&mut self, mut envelope: xous::MessageEnvelope) {
+        let pid = envelope.sender.pid().map(|p| p.get()).unwrap_or_default();
+        match self.processes.iter_mut().find(|p| p.pid == pid) {
+            Some(process) if process.changed => {
+                process.changed = false;
+                fill(process, levels_mut(&mut envelope));
+            }
+            Some(process) => {
+                // only one listener per process; one it displaces goes back with the current levels
+                if let Some(mut previous) = process.listener.replace(envelope) {
+                    fill(process, levels_mut(&mut previous));
+                }
+            }
+            // unregistered processes get an empty answer, and stop listening
+            None => {}
+        }
+    }
+
+    pub fn set(&mut self, mut envelope: xous::MessageEnvelope) {
+        let sender = envelope.sender.pid().map(|p| p.get()).unwrap_or_default();
+        let request = levels_mut(&mut envelope);
+        let pid = match request.pid {
+            0 => sender,
+            pid => pid as u8,
+        };
+        // a process can always change its own levels; anyone else's needs the level control token
+        if pid != sender && !(self.claimed && request.token == self.token) {
+            request.count = api::LEVELS_DENIED;
+            return;
+        }
+        let (module, level) = match request.iter().next() {
+            Some((module, level)) => (module.to_string(), level.min(log::LevelFilter::Trace as u32)),
+            None => return,
+        };
+        let mut changed = 0;
+        if let Some(process) = self.processes.iter_mut().find(|p| p.pid == pid) {
+            if module.is_empty() {
+                for entry in process.modules.iter_mut() {
+                    entry.1 = level;
+                    changed += 1;
+                }
+            } else if let Some(entry) = process.modules.iter_mut().find(|(m, _)| *m == module) {
+                entry.1 = level;
+                changed += 1;
+            } else if process.modules.len() < api::LEVELS_MAX_MODULES {
+                process.modules.push((module, level));
+                changed += 1;
+            }
+            if changed != 0 {
+                match process.listener.take() {
+                    Some(mut listener) => fill(process, levels_mut(&mut listener)),
+                    None => process.changed = true,
+                }
+            }
+        }
+        request.count = changed;
+    }
+
+    pub fn get(&self, mut envelope: xous::MessageEnvelope) {
+        let request = levels_mut(&mut envelope);
+        match self.processes.get(request.pid as usize) {
+            Some(process) => fill(process, request),
+            None => {
+                request.pid = 0;
+                request.count = 0;
+            }
+        }
+    }
+}
+
+fn levels_mut(envelope: &mut xous::MessageEnvelope) -> &mut api::ModuleLevels {
+    let mem = envelope.body.memory_message_mut().unwrap();
+    // safety: any bit pattern is a valid `ModuleLevels`, and the lend is a whole page
+    unsafe { &mut *(mem.buf.as_mut_ptr() as *mut api::ModuleLevels) }
+}
+
+fn fill(process: &Process, levels: &mut api::ModuleLevels) {
+    *levels = api::ModuleLevels::default();
+    levels.pid = process.pid as u32;
+    levels.set_name(&process.name);
+    for (module, level) in process.modules.iter() {
+        levels.push(module, *level);
+    }
+}
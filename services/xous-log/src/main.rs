@@ -3,8 +3,11 @@
 
 use xous_api_log::api;
 
+mod levels;
 #[macro_use]
 mod platform;
+#[cfg(feature = "store")]
+mod store;
 
 use core::fmt::Write;
 
@@ -49,6 +52,9 @@ fn reader_thread(arg: usize) {
     // use a stack-allocated string to ensure no heap thrashing results from String manipulations
     #[cfg(feature = "usb")]
     let mut usb_str = String::new();
+    #[cfg(feature = "store")]
+    let mut store = store::LogStore::new();
+    let mut levels = levels::Levels::new();
 
     println!("LOG: my PID is {}", xous::process::id());
     let mut counter: usize = 0;
@@ -60,6 +66,29 @@ fn reader_thread(arg: usize) {
         // writeln!(output, "LOG: Waiting for an event...").unwrap();
         let envelope = xous::syscall::receive_message(server_addr).expect("couldn't get address");
         let sender = envelope.sender;
+        // these write back to the message or hold on to it for later, so they take the whole envelope
+        if matches!(envelope.body, xous::Message::MutableBorrow(_)) {
+            match FromPrimitive::from_usize(envelope.body.id()) {
+                #[cfg(feature = "store")]
+                Some(api::Opcode::StoreRead) => {
+                    store.read(envelope);
+                    continue;
+                }
+                Some(api::Opcode::ListenLevels) => {
+                    levels.listen(envelope);
+                    continue;
+                }
+                Some(api::Opcode::SetLevel) => {
+                    levels.set(envelope);
+                    continue;
+                }
+                Some(api::Opcode::GetLevels) => {
+                    levels.get(envelope);
+                    continue;
+                }
+                _ => {}
+            }
+        }
         if let Some(opcode) = FromPrimitive::from_usize(envelope.body.id()) {
             if let Some(mem) = envelope.body.memory_message() {
                 match opcode {
@@ -140,6 +169,22 @@ fn reader_thread(arg: usize) {
                             writeln!(usb_str, ")").ok();
                             usb_send_str(conn, &usb_str);
                         }
+                        #[cfg(feature = "store")]
+                        store.log(
+                            sender.pid().map(|v| v.get()).unwrap_or_default(),
+                            lr.level,
+                            module_slice,
+                            args_slice,
+                            file_slice,
+                            lr.line.map(|l| l.get()),
+                        );
+                    }
+                    api::Opcode::RegisterLevels => {
+                        if mem.buf.len() >= core::mem::size_of::<api::ModuleLevels>() {
+                            // safety: any bit pattern is a valid `ModuleLevels`, and we checked the length
+                            let registration = unsafe { &*(mem.buf.as_ptr() as *const api::ModuleLevels) };
+                            levels.register(sender.pid().map(|v| v.get()).unwrap_or_default(), registration);
+                        }
                     }
                     api::Opcode::StandardOutput | api::Opcode::StandardError => {
                         // let mut buffer_start_offset = mem.offset.map(|o| o.get()).unwrap_or(0);
@@ -193,6 +238,8 @@ fn reader_thread(arg: usize) {
                         if let Some(conn) = usb_serial {
                             usb_send_str(conn, &format!("PANIC in PID {}:", sender_pid));
                         }
+                        #[cfg(feature="store")]
+                        store.panic_started(sender_pid.get());
                     },
                     1100 => (),
                     1101..=1132 => {
@@ -228,6 +275,8 @@ fn reader_thread(arg: usize) {
                         if let Some(conn) = usb_serial {
                             usb_send_str(conn, unsafe{std::str::from_utf8_unchecked(&output_bfr[..total_chars])});
                         }
+                        #[cfg(feature="store")]
+                        store.panic_message(sender_pid.get(), &output_bfr[..total_chars]);
                     }
                     1200 => {
                         writeln!(output, "Terminating process").unwrap();
@@ -235,6 +284,8 @@ fn reader_thread(arg: usize) {
                         if let Some(conn) = usb_serial {
                             usb_send_str(conn, "Terminating process");
                         }
+                        #[cfg(feature="store")]
+                        store.panic_finished(sender_pid.get());
                     },
                     2000 => {
                         #[cfg(any(feature="precursor", feature="renode"))]
@@ -288,6 +339,36 @@ fn reader_thread(arg: usize) {
                         usb_serial.take();
                         xous::return_scalar(envelope.sender, 1).ok();
                     },
+                    #[cfg(feature="store")]
+                    6 /* api::Opcode::StoreSetFilter */ => {
+                        store.set_filter(scalar.arg1 as u8, scalar.arg2);
+                        xous::return_scalar(envelope.sender, 1).ok();
+                    },
+                    #[cfg(feature="store")]
+                    7 /* api::Opcode::StoreSetCapacity */ => {
+                        let capacity = store.set_capacity(scalar.arg1);
+                        xous::return_scalar(envelope.sender, capacity).ok();
+                    },
+                    #[cfg(feature="store")]
+                    9 /* api::Opcode::StoreClear */ => {
+                        store.clear();
+                        xous::return_scalar(envelope.sender, 1).ok();
+                    },
+                    14 /* api::Opcode::ClaimLevelControl */ => {
+                        let (claimed, token) = match levels.claim() {
+                            Some(token) => (1, token),
+                            None => (0, [0u32; 4]),
+                        };
+                        xous::return_scalar5(
+                            envelope.sender,
+                            claimed,
+                            token[0] as usize,
+                            token[1] as usize,
+                            token[2] as usize,
+                            token[3] as usize,
+                        )
+                        .ok();
+                    },
                     _ => writeln!(
                         output,
                         "Unrecognized scalar message from {}: {:#?}",
@@ -0,0 +1,317 @@
+//! The log store: the most recent records, kept so they can be read back on the device after the fact.
+//! What gets kept is filtered per process and per level; panics are always kept, and aren't pushed out
+//! by ordinary records until there are more than `MAX_PANICS` of them.
+
+use std::collections::{HashMap, VecDeque};
+
+use xous_api_log::api;
+
+/// Panics beyond this many can be pushed out like any other record
+const MAX_PANICS: usize = 8;
+/// Ordinary records are kept at this level and above unless told otherwise; debug chatter would
+/// otherwise crowd out the records leading up to a crash.
+const DEFAULT_FILTER: u8 = log::LevelFilter::Warn as u8;
+
+struct Record {
+    seq: u32,
+    pid: u8,
+    level: u8,
+    text: Vec<u8>,
+}
+
+pub struct LogStore {
+    records: VecDeque<Record>,
+    capacity: usize,
+    next_seq: u32,
+    default_filter: u8,
+    filters: HashMap<u8, u8>,
+    /// Panic messages arrive in pieces; they're gathered here until the panic is finished
+    panicking: HashMap<u8, Vec<u8>>,
+    /// A `StoreRead` waiting for records
+    reader: Option<xous::MessageEnvelope>,
+}
+
+impl LogStore {
+    pub fn new() -> Self {
+        LogStore {
+            records: VecDeque::new(),
+            capacity: api::STORE_DEFAULT_CAPACITY,
+            next_seq: 0,
+            default_filter: DEFAULT_FILTER,
+            filters: HashMap::new(),
+            panicking: HashMap::new(),
+            reader: None,
+        }
+    }
+
+    pub fn wants(&self, pid: u8, level: u32) -> bool {
+        let filter = self.filters.get(&pid).copied().unwrap_or(self.default_filter);
+        level != 0 && level <= filter as u32
+    }
+
+    /// Keeps a `LogRecord`, formatted the same way as on the console.
+    pub fn log(&mut self, pid: u8, level: u32, module: &[u8], args: &[u8], file: &[u8], line: Option<u32>) {
+        if !self.wants(pid, level) {
+            return;
+        }
+        let mut text = Vec::with_capacity(module.len() + args.len() + file.len() + 16);
+        text.extend_from_slice(module);
+        text.extend_from_slice(b": ");
+        text.extend_from_slice(args);
+        text.extend_from_slice(b" (");
+        text.extend_from_slice(file);
+        if let Some(line) = line {
+            text.extend_from_slice(format!(":{}", line).as_bytes());
+        }
+        text.push(b')');
+        self.push(pid, level as u8, text);
+    }
+
+    pub fn panic_started(&mut self, pid: u8) { self.panicking.insert(pid, Vec::new()); }
+
+    pub fn panic_message(&mut self, pid: u8, message: &[u8]) {
+        let text = self.panicking.entry(pid).or_default();
+        if text.len() < api::STORE_MAX_TEXT_LEN {
+            text.extend_from_slice(message);
+        }
+    }
+
+    pub fn panic_finished(&mut self, pid: u8) {
+        if let Some(text) = self.panicking.remove(&pid) {
+            self.push(pid, api::STORE_LEVEL_PANIC, text);
+        }
+    }
+
+    pub fn set_filter(&mut self, pid: u8, filter: usize) {
+        match (pid, filter) {
+            (0, api::STORE_FILTER_NONE) => self.default_filter = DEFAULT_FILTER,
+            (0, filter) => self.default_filter = filter.min(log::LevelFilter::Trace as usize) as u8,
+            (pid, api::STORE_FILTER_NONE) => {
+                self.filters.remove(&pid);
+            }
+            (pid, filter) => {
+                self.filters.insert(pid, filter.min(log::LevelFilter::Trace as usize) as u8);
+            }
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) -> usize {
+        self.capacity = capacity.clamp(1, api::STORE_MAX_CAPACITY);
+        self.trim();
+        self.capacity
+    }
+
+    pub fn clear(&mut self) { self.records.clear(); }
+
+    /// Answers a `StoreRead`, or holds on to it until the records it waits for are in.
+    pub fn read(&mut self, mut envelope: xous::MessageEnvelope) {
+        if self.ready(&mut envelope) {
+            self.fill(&mut envelope);
+        } else if let Some(mut previous) = self.reader.replace(envelope) {
+            // only one reader can wait at a time; the one it displaces gets what there is
+            self.fill(&mut previous);
+        }
+    }
+
+    fn push(&mut self, pid: u8, level: u8, mut text: Vec<u8>) {
+        text.truncate(api::STORE_MAX_TEXT_LEN);
+        self.records.push_back(Record { seq: self.next_seq, pid, level, text });
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.trim();
+        if let Some(mut reader) = self.reader.take() {
+            if self.ready(&mut reader) {
+                self.fill(&mut reader);
+            } else {
+                self.reader = Some(reader);
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.records.len() > self.capacity {
+            let panics = self.records.iter().filter(|r| r.level == api::STORE_LEVEL_PANIC).count();
+            let victim = if panics > MAX_PANICS {
+                0
+            } else {
+                self.records.iter().position(|r| r.level != api::STORE_LEVEL_PANIC).unwrap_or(0)
+            };
+            self.records.remove(victim);
+        }
+    }
+
+    /// The first sequence number a `StoreRead` asks for, and how many records it waits for.
+    fn request(envelope: &mut xous::MessageEnvelope) -> (u32, usize) {
+        let mem = envelope.body.memory_message().unwrap();
+        let from = mem.offset.map(|o| o.get() - 1).unwrap_or(0) as u32;
+        (from, mem.valid.map(|v| v.get()).unwrap_or(0))
+    }
+
+    fn ready(&self, envelope: &mut xous::MessageEnvelope) -> bool {
+        let (from, wait_for) = Self::request(envelope);
+        let mut new = self.records.iter().filter(|r| r.seq >= from);
+        // a reader can't wait for more than the store holds
+        wait_for == 0
+            || new.clone().count() >= wait_for.min(self.capacity)
+            || new.any(|r| r.level == api::STORE_LEVEL_PANIC)
+    }
+
+    fn fill(&self, envelope: &mut xous::MessageEnvelope) {
+        let (from, _) = Self::request(envelope);
+        let mem = envelope.body.memory_message_mut().unwrap();
+        let page: &mut [u8] = unsafe { mem.buf.as_slice_mut() };
+        let mut used = 0;
+        let mut next = from;
+        for record in self.records.iter().filter(|r| r.seq >= from) {
+            match api::pack_stored_record(
+                &mut page[used..],
+                record.seq,
+                record.pid,
+                record.level,
+                &record.text,
+            ) {
+                Some(len) => {
+                    used += len;
+                    next = record.seq.wrapping_add(1);
+                }
+                None => break,
+            }
+        }
+        mem.offset = xous::MemoryAddress::new(next as usize + 1);
+        mem.valid = xous::MemorySize::new(used);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERROR: u32 = log::Level::Error as u32;
+    const WARN: u32 = log::Level::Warn as u32;
+    const INFO: u32 = log::Level::Info as u32;
+    const DEBUG: u32 = log::Level::Debug as u32;
+
+    fn warn(store: &mut LogStore, pid: u8, args: &str) {
+        store.log(pid, WARN, b"test", args.as_bytes(), b"test.rs", None);
+    }
+
+    fn panic(store: &mut LogStore, pid: u8, message: &str) {
+        store.panic_started(pid);
+        store.panic_message(pid, message.as_bytes());
+        store.panic_finished(pid);
+    }
+
+    fn texts(store: &LogStore) -> Vec<String> {
+        store.records.iter().map(|r| String::from_utf8(r.text.clone()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut store = LogStore::new();
+        assert!(store.wants(5, ERROR));
+        assert!(store.wants(5, WARN));
+        assert!(!store.wants(5, INFO));
+        // `Off` is never kept, whatever the filter
+        assert!(!store.wants(5, 0));
+
+        store.set_filter(5, log::LevelFilter::Debug as usize);
+        assert!(store.wants(5, DEBUG));
+        assert!(!store.wants(6, DEBUG));
+
+        store.set_filter(0, log::LevelFilter::Error as usize);
+        assert!(!store.wants(6, WARN));
+        assert!(store.wants(5, WARN));
+
+        store.set_filter(5, api::STORE_FILTER_NONE);
+        assert!(!store.wants(5, WARN));
+        store.set_filter(0, api::STORE_FILTER_NONE);
+        assert!(store.wants(5, WARN));
+
+        store.log(5, INFO, b"test", b"dropped", b"test.rs", Some(1));
+        assert!(store.records.is_empty());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut store = LogStore::new();
+        store.log(5, ERROR, b"gam", b"out of memory", b"src/main.rs", Some(42));
+        store.log(5, ERROR, b"gam", b"no line", b"src/main.rs", None);
+        assert_eq!(texts(&store), ["gam: out of memory (src/main.rs:42)", "gam: no line (src/main.rs)"]);
+        assert_eq!(store.records[0].pid, 5);
+        assert_eq!(store.records[0].level, ERROR as u8);
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut store = LogStore::new();
+        assert_eq!(store.set_capacity(3), 3);
+        for i in 0..5 {
+            warn(&mut store, 5, &i.to_string());
+        }
+        assert_eq!(store.records.iter().map(|r| r.seq).collect::<Vec<_>>(), [2, 3, 4]);
+
+        // shrinking drops the oldest records straight away
+        store.set_capacity(1);
+        assert_eq!(store.records.iter().map(|r| r.seq).collect::<Vec<_>>(), [4]);
+
+        assert_eq!(store.set_capacity(0), 1);
+        assert_eq!(store.set_capacity(usize::MAX), api::STORE_MAX_CAPACITY);
+
+        store.clear();
+        assert!(store.records.is_empty());
+        warn(&mut store, 5, "after clear");
+        assert_eq!(store.records[0].seq, 5);
+    }
+
+    #[test]
+    fn test_panics_kept() {
+        let mut store = LogStore::new();
+        store.set_capacity(3);
+        store.panic_started(7);
+        store.panic_message(7, b"panicked at ");
+        // records logged while the panic is being assembled don't get mixed into it
+        warn(&mut store, 5, "unrelated");
+        store.panic_message(7, b"src/main.rs:1");
+        store.panic_finished(7);
+        for i in 0..5 {
+            warn(&mut store, 5, &i.to_string());
+        }
+        let texts = texts(&store);
+        assert_eq!(texts.len(), 3);
+        assert_eq!(texts[0], "panicked at src/main.rs:1");
+        assert_eq!(store.records[0].level, api::STORE_LEVEL_PANIC);
+        assert_eq!(store.records[0].pid, 7);
+
+        // a panic that was never started isn't recorded
+        store.panic_finished(8);
+        assert_eq!(store.records.iter().filter(|r| r.pid == 8).count(), 0);
+    }
+
+    #[test]
+    fn test_panics_limit() {
+        let mut store = LogStore::new();
+        store.set_capacity(MAX_PANICS);
+        for i in 0..MAX_PANICS + 2 {
+            panic(&mut store, 7, &i.to_string());
+        }
+        // past the limit, panics go oldest first like everything else
+        let texts = texts(&store);
+        assert_eq!(texts.len(), MAX_PANICS);
+        assert_eq!(texts[0], "2");
+        assert_eq!(texts[MAX_PANICS - 1], (MAX_PANICS + 1).to_string());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut store = LogStore::new();
+        let long = "x".repeat(api::STORE_MAX_TEXT_LEN * 2);
+        warn(&mut store, 5, &long);
+        assert_eq!(store.records[0].text.len(), api::STORE_MAX_TEXT_LEN);
+
+        store.panic_started(7);
+        for _ in 0..4 {
+            store.panic_message(7, long.as_bytes());
+        }
+        store.panic_finished(7);
+        assert_eq!(store.records[1].text.len(), api::STORE_MAX_TEXT_LEN);
+    }
+}
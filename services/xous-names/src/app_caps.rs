@@ -0,0 +1,14 @@
+#![cfg_attr(rustfmt, rustfmt_skip)]
+// This file is auto-generated by xtask/main.rs generate_app_capabilities()
+
+/// (app name, PID assigned at boot if the app is in the image, servers the app may connect to)
+pub const APP_CAPABILITIES: &[(&str, Option<u32>, &[&str])] = &[
+    ("hello", None, &["_Graphical Abstraction Manager_", "_Text to speech front end_"]),
+];
+
+/// The only process that may register runtime-loaded apps
+pub const APP_LOADER_PID: Option<u32> = None;
+
+/// The highest PID assigned at boot; processes above it were started at runtime, and may not connect
+/// to anything until the app loader has registered them
+pub const LAST_BOOT_PID: Option<u32> = None;
@@ -10,6 +10,8 @@ use xous_api_names::api::*;
 use xous_api_names::*;
 use xous_ipc::Buffer;
 
+mod app_caps;
+
 #[derive(PartialEq)]
 #[repr(C)]
 enum ConnectError {
@@ -27,6 +29,9 @@ enum ConnectError {
 
     /// The server does not currently exist, and a blocking request was made
     ServerNotFound = 5,
+
+    /// The requesting app does not have the capability to connect to the server
+    AccessDenied = 6,
 }
 
 #[derive(PartialEq)]
@@ -259,6 +264,87 @@ impl CheckedHashMap {
     }
 }
 
+/// The servers an app process is allowed to connect to, as declared in `apps/manifest.json`.
+struct AppPolicy {
+    name: String,
+    servers: &'static [&'static str],
+}
+
+/// Per-PID connection policy for apps. Processes that were in the image at boot and have no entry are
+/// system services, and are not restricted. Processes started after boot have no entry until the app
+/// loader registers them, and may not connect to anything until it has.
+struct Capabilities {
+    apps: HashMap<u32, AppPolicy>,
+}
+impl Capabilities {
+    pub fn new() -> Self {
+        let mut apps = HashMap::new();
+        for (name, pid, servers) in app_caps::APP_CAPABILITIES.iter() {
+            if let Some(pid) = pid {
+                apps.insert(*pid, AppPolicy { name: name.to_string(), servers });
+            }
+        }
+        Capabilities { apps }
+    }
+
+    /// True for processes that were started after boot, which can only be apps loaded at runtime
+    fn started_at_runtime(pid: u32) -> bool {
+        app_caps::LAST_BOOT_PID.map(|last| pid > last).unwrap_or(false)
+    }
+
+    /// Record the policy for an app that was loaded at runtime. Apps that aren't in the manifest
+    /// may not connect to anything. Only processes started after boot can be registered, so that the
+    /// policy of a system service can't be replaced. An existing entry is overwritten: the kernel
+    /// reuses the PIDs of processes that have exited, and the app loader only registers a PID after it
+    /// has spawned a fresh process into it.
+    pub fn register(&mut self, pid: u32, name: &str) -> bool {
+        if !Self::started_at_runtime(pid) {
+            log::warn!("refusing to register app '{}' as PID {}: not a process loaded at runtime", name, pid);
+            return false;
+        }
+        let servers = app_caps::APP_CAPABILITIES
+            .iter()
+            .find(|(app, _, _)| *app == name)
+            .map(|(_, _, servers)| *servers)
+            .unwrap_or_else(|| {
+                log::warn!("app '{}' is not in the manifest; it will not be allowed any connections", name);
+                &[]
+            });
+        if let Some(old) = self.apps.insert(pid, AppPolicy { name: name.to_string(), servers }) {
+            log::info!(
+                "PID {} was reused: replacing the policy of '{}' with that of '{}'",
+                pid,
+                old.name,
+                name
+            );
+        }
+        true
+    }
+
+    pub fn permits(&self, pid: u32, server: &XousServerName) -> bool {
+        match self.apps.get(&pid) {
+            Some(policy) => {
+                if policy.servers.contains(&server.to_str()) {
+                    true
+                } else {
+                    log::warn!(
+                        "app '{}' (PID {}) denied a connection to '{}': not in its capabilities",
+                        policy.name,
+                        pid,
+                        server
+                    );
+                    false
+                }
+            }
+            None if Self::started_at_runtime(pid) => {
+                log::warn!("PID {} was started at runtime but never registered; denied '{}'", pid, server);
+                false
+            }
+            None => true,
+        }
+    }
+}
+
 fn name_from_msg(env: &MessageEnvelope) -> Result<XousServerName, ConnectError> {
     let msg = env.body.memory_message().ok_or(ConnectError::InvalidMessageType)?;
     let valid_bytes = msg.valid.map(|v| v.get()).unwrap_or_else(|| msg.buf.len());
@@ -280,11 +366,17 @@ fn name_from_msg(env: &MessageEnvelope) -> Result<XousServerName, ConnectError>
 fn blocking_connect(
     env: &mut MessageEnvelope,
     name_table: &mut CheckedHashMap,
+    capabilities: &Capabilities,
 ) -> Result<ConnectSuccess, ConnectError> {
     let name = name_from_msg(env)?;
     let sender_pid = env.sender.pid().expect("kernel provided us a PID of None");
     log::trace!("BlockingConnect request for '{}' for process {:?}", name, sender_pid);
 
+    // Refuse before waiting, so that an app can't park requests for servers it may never use.
+    if !capabilities.permits(sender_pid.get() as u32, &name) {
+        return Err(ConnectError::AccessDenied);
+    }
+
     // If the server already exists, attempt to make the connection. The connection can
     // only succeed if the server is in the name_table.
     if let (Some(server_sid), token) = name_table.connect(&name) {
@@ -340,6 +432,37 @@ fn respond_connect_success(mut msg: MessageEnvelope, cid: xous::CID, disc: Optio
     mem.offset = None;
 }
 
+/// Associate a runtime-loaded app's PID with its name. Only the app loader may do this. The first
+/// word of the buffer is overwritten with 0 on success.
+fn register_app_process(mut msg: MessageEnvelope, capabilities: &mut Capabilities) {
+    let Some(mem) = msg.body.memory_message_mut() else {
+        if msg.body.is_blocking() {
+            xous::return_scalar(msg.sender, 1).ok();
+        }
+        return;
+    };
+    let pid = mem.offset.map(|o| o.get() as u32);
+    let sender_pid = msg.sender.pid().map(|p| p.get() as u32);
+    let result = if sender_pid.is_none() || sender_pid != app_caps::APP_LOADER_PID {
+        log::warn!("PID {:?} attempted to register an app process, but is not the app loader", sender_pid);
+        1
+    } else if let (Some(pid), Ok(name)) = (pid, name_from_msg(&msg)) {
+        if capabilities.register(pid, name.to_str()) {
+            log::info!("app '{}' started as PID {}", name, pid);
+            0
+        } else {
+            1
+        }
+    } else {
+        1
+    };
+    let mem = msg.body.memory_message_mut().unwrap();
+    let s = unsafe { core::slice::from_raw_parts_mut(mem.buf.as_mut_ptr() as *mut u32, mem.buf.len() / 4) };
+    s[0] = result;
+    mem.valid = None;
+    mem.offset = None;
+}
+
 fn main() -> ! {
     use implementation::*;
     log_server::init_wait().unwrap();
@@ -358,6 +481,7 @@ fn main() -> ! {
     // this limits the number of available servers to be requested to 128...!
     //let mut name_table = FnvIndexMap::<XousServerName, xous::SID, 128>::new();
     let mut name_table = CheckedHashMap::new();
+    let mut capabilities = Capabilities::new();
 
     info!("started");
     loop {
@@ -404,7 +528,7 @@ fn main() -> ! {
                     while i >= 0 {
                         if name_from_msg(&waiting_connections[i as usize]) == Ok(name) {
                             let mut msg = waiting_connections.remove(i as usize);
-                            match blocking_connect(&mut msg, &mut name_table) {
+                            match blocking_connect(&mut msg, &mut name_table, &capabilities) {
                                 Err(e) => respond_connect_error(msg, e),
                                 Ok(ConnectSuccess::Connected(cid, disc)) => {
                                     respond_connect_success(msg, cid, disc)
@@ -440,7 +564,7 @@ fn main() -> ! {
                     continue;
                 }
 
-                match blocking_connect(&mut msg, &mut name_table) {
+                match blocking_connect(&mut msg, &mut name_table, &capabilities) {
                     Err(e) => respond_connect_error(msg, e),
                     Ok(ConnectSuccess::Connected(cid, disc)) => respond_connect_success(msg, cid, disc),
                     Ok(ConnectSuccess::Wait) => {
@@ -461,8 +585,10 @@ fn main() -> ! {
                 let name = XousServerName::from_str(name_string.as_str());
                 log::trace!("Lookup request for '{}'", name);
                 let response: api::Return;
-                if let (Some(server_sid), token) = name_table.connect(&name) {
-                    let sender_pid = msg.sender.pid().expect("can't extract sender PID on Lookup");
+                let sender_pid = msg.sender.pid().expect("can't extract sender PID on Lookup");
+                if !capabilities.permits(sender_pid.get() as u32, &name) {
+                    response = api::Return::Failure
+                } else if let (Some(server_sid), token) = name_table.connect(&name) {
                     match xous::connect_for_process(sender_pid, server_sid).expect("can't broker connection")
                     {
                         xous::Result::ConnectionID(connection_id) => {
@@ -523,6 +649,7 @@ fn main() -> ! {
                 };
                 buffer.replace(response).expect("Can't return buffer");
             }
+            Some(api::Opcode::RegisterAppProcess) => register_app_process(msg, &mut capabilities),
             None => {
                 error!("couldn't decode message: {:?}", msg);
                 break;
@@ -0,0 +1,46 @@
+//! Compares the page replacement policies on a recorded page access trace.
+//!
+//! Usage: `cargo run -p xous-swapper --example swap-sim -- <trace> <frames> [--resident <pid>]...`
+//!
+//! `<trace>` is a log captured from a kernel built with `debug-swap-verbose`, or a list of
+//! `<pid> <vaddr>` lines; `<frames>` is the number of pages of RAM to simulate. Processes passed with
+//! `--resident` get `ResidencyHint::KeepResident`, which only the working-set policy honors.
+
+use xous_swapper::policy::sim::{SimStats, parse_trace, simulate};
+use xous_swapper::policy::{Clock, Lru, ResidencyHint, ResidencyHints, WorkingSet};
+
+/// Pages evicted per OOM, matching `HARD_OOM_PAGE_TARGET` in the swapper
+const BATCH: usize = 24;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: {} <trace> <frames> [--resident <pid>]...", args[0]);
+        std::process::exit(1);
+    }
+    let text = std::fs::read_to_string(&args[1]).expect("couldn't read trace");
+    let frames: usize = args[2].parse().expect("<frames> must be a number");
+    let mut hints = ResidencyHints::default();
+    let mut rest = args[3..].iter();
+    while let Some(arg) = rest.next() {
+        match (arg.as_str(), rest.next().and_then(|pid| pid.parse::<u8>().ok())) {
+            ("--resident", Some(pid)) => hints.set(pid, ResidencyHint::KeepResident),
+            _ => {
+                eprintln!("Unrecognized argument {}", arg);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let trace = parse_trace(&text);
+    println!("{} accesses, {} frames of RAM, {} pages evicted per OOM", trace.len(), frames, BATCH);
+    let report = |name: &str, stats: SimStats| {
+        println!(
+            "{:<12} {:>8} first touches {:>8} refaults {:>8} evictions  refaults by PID: {:?}",
+            name, stats.first_touches, stats.refaults, stats.evictions, stats.refaults_by_pid
+        );
+    };
+    report("lru", simulate::<Lru>(&trace, frames, BATCH, &hints));
+    report("clock", simulate::<Clock>(&trace, frames, BATCH, &hints));
+    report("working-set", simulate::<WorkingSet>(&trace, frames, BATCH, &hints));
+}
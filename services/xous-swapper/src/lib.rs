@@ -1,9 +1,14 @@
+pub mod policy;
+pub mod zram;
+
 /// public userspace & swapper handler -> swapper userspace ABI
 #[derive(Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
 #[repr(usize)]
 pub enum Opcode {
     /// Userspace request to GC some physical pages
     GarbageCollect,
+    /// Sets the `ResidencyHint` of the sending process
+    SetResidencyHint,
     /// Test messages
     #[cfg(feature = "swap-userspace-testing")]
     Test0,
@@ -38,6 +43,16 @@ impl Swapper {
         }
         // no result is given, but the call blocks until the GC call has completed in the swapper.
     }
+
+    /// Tells the swapper how eager it should be to evict pages of the calling process. Only the
+    /// `swap-policy-working-set` page replacement policy takes hints into account.
+    pub fn set_residency_hint(&self, hint: policy::ResidencyHint) -> Result<(), xous::Error> {
+        xous::send_message(
+            self.conn,
+            xous::Message::new_scalar(Opcode::SetResidencyHint as usize, hint as usize, 0, 0, 0),
+        )
+        .map(|_| ())
+    }
 }
 
 use core::sync::atomic::{AtomicU32, Ordering};
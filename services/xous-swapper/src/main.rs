@@ -47,19 +47,43 @@
 //! the current `MEMORY_ALLOCATIONS` table into a pre-allocated BinaryHeap in the shared state structure,
 //! indexed by the timestamp. At this point, the blocking userspace handler can work through a sorted vector
 //! of allocations to pick the pages it wants to remove.
+//!
+//! == Page Replacement Policy ==
+//!
+//! Which pages get removed is up to the `ReplacementPolicy` selected at build time (see
+//! `xous_swapper::policy`). LRU is the default; pass `--feature swap-policy-clock` or
+//! `--feature swap-policy-working-set` to xtask to select another one. Processes can influence the
+//! working-set policy with `Swapper::set_residency_hint()`.
+//!
+//! == Compressed Swap Tier ==
+//!
+//! Evicted pages are not written to the swap device right away. They are first compressed into a pool of
+//! RAM owned by the swapper (see `xous_swapper::zram`), and only spill to the swap device when the pool is
+//! full. Pages in the pool are tracked in the same swap page tables as pages on the swap device: slot `n`
+//! of the pool shows up as swap offset `zram_base + n * PAGE_SIZE`, just past the end of the swap device.
 
 mod debug;
 mod platform;
 use core::fmt::Write;
-use std::collections::BinaryHeap;
 use std::fmt::Debug;
 
 use debug::*;
 use loader::swap::{SWAP_CFG_VADDR, SWAP_COUNT_VADDR, SWAP_PT_VADDR, SWAP_RPT_VADDR, SwapAlloc, SwapSpec};
 use num_traits::*;
-use platform::{PAGE_SIZE, SwapHal};
+use platform::{PAGE_SIZE, SwapHal, ZRAM_POOL_PAGES};
 use xous::{MemoryFlags, MemoryRange, PID, Result};
 use xous_swapper::Opcode;
+#[cfg(feature = "swap-policy-clock")]
+use xous_swapper::policy::Clock as Policy;
+#[cfg(not(any(feature = "swap-policy-clock", feature = "swap-policy-working-set")))]
+use xous_swapper::policy::Lru as Policy;
+#[cfg(feature = "swap-policy-working-set")]
+use xous_swapper::policy::WorkingSet as Policy;
+use xous_swapper::policy::{PageInfo, ReplacementPolicy, ResidencyHint, ResidencyHints};
+use xous_swapper::zram::{SwapBacking, ZramTier};
+
+#[cfg(all(feature = "swap-policy-clock", feature = "swap-policy-working-set"))]
+compile_error!("Only one swap-policy-* feature may be selected");
 
 /// Target of pages to free in case of a Hard OOM. Note that the PAGE_TARGET numbers
 /// are imprecise, in that there is a chance that one target is active during another
@@ -168,7 +192,10 @@ pub struct SwapperSharedState {
     /// starting from the free swap search origin. The unit of this variable is in pages, so it
     /// can be used to directly index the `sct` `SwapCountTracker`.
     pub free_swap_search_origin: usize,
-    pub hard_oom_alloc_heap: Option<BinaryHeap<SwapAlloc>>,
+    /// Picks the pages to evict. Allocated by `main()`, as it can't be allocated in the handler.
+    pub policy: Option<Policy>,
+    /// Per-process hints for the replacement policy, indexed by PID
+    pub hints: ResidencyHints,
     /// Reserve some memory to be freed by the hard OOM manager. These pages are needed to do things
     /// like create L1 page table entries for the swapper to track evicted pages.
     pub hard_oom_reserved_page: Option<MemoryRange>,
@@ -176,6 +203,12 @@ pub struct SwapperSharedState {
     /// number of pages to free in the OOM routine. Note that this value is imprecise: it can
     /// be mutated by the userspace soft-OOM handler at any time.
     pub pages_to_free: usize,
+    /// Compressed RAM tier in front of the swap device. Allocated by `main()`; until then, pages go
+    /// straight to the swap device.
+    pub zram: Option<ZramTier>,
+    /// Swap offset that maps to the first slot of the compressed RAM tier. Equal to the size of the swap
+    /// device, so the two ranges can't overlap.
+    pub zram_base: usize,
 }
 impl SwapperSharedState {
     pub fn pt_walk(&self, pid: u8, va: usize, mark_free: bool) -> Option<usize> {
@@ -206,6 +239,62 @@ impl SwapperSharedState {
         }
     }
 }
+impl SwapBacking for SwapperSharedState {
+    fn write_backing(&mut self, page: &mut [u8], pid: u8, vaddr: usize) {
+        // search the swap page tables for the next free page
+        let mut next_free_page: Option<usize> = None;
+        for slot in 0..self.sct.counts.len() {
+            let candidate = (self.free_swap_search_origin + slot) % self.sct.counts.len();
+            if (self.sct.counts[candidate] & loader::FLG_SWAP_USED) == 0 {
+                #[cfg(feature = "debug-verbose")]
+                writeln!(
+                    DebugUart {},
+                    "WTS found free page {:x} with contents {:x}",
+                    candidate,
+                    self.sct.counts[candidate]
+                )
+                .ok();
+                next_free_page = Some(candidate);
+                break;
+            }
+        }
+        if let Some(free_page_number) = next_free_page {
+            self.free_swap_search_origin = free_page_number + 1; // start search at next page beyond the one about to be used
+            // increment the swap counter by one, rolling over if full. Note that we only have 31
+            // bits; the MSB is the "swap used" status bit
+            let mut count = self.sct.counts[free_page_number] & !loader::FLG_SWAP_USED;
+            count = (count + 1) & !loader::FLG_SWAP_USED;
+            self.sct.counts[free_page_number] = count | loader::FLG_SWAP_USED;
+            #[cfg(feature = "debug-verbose")]
+            writeln!(
+                DebugUart {},
+                "WTS ss.sct.counts[{:x}] {:x}",
+                free_page_number,
+                self.sct.counts[free_page_number]
+            )
+            .ok();
+
+            // add a PT mapping for the swap entry
+            map_swap(self, free_page_number * PAGE_SIZE, vaddr, pid);
+
+            self.hal.encrypt_swap_to(page, count, free_page_number * PAGE_SIZE, vaddr, pid);
+        } else {
+            writeln!(DebugUart {}, "OOM detected, dumping all swap allocs:").ok();
+            for (i, &entry) in self.sct.counts.iter().enumerate() {
+                writeln!(DebugUart {}, "  {:04}:{:x}", i, entry).ok();
+            }
+            // OOS path
+            panic!("Ran out of swap space, hard OOM!");
+        }
+    }
+
+    fn map_zram(&mut self, slot: usize, pid: u8, vaddr: usize) {
+        map_swap(self, self.zram_base + slot * PAGE_SIZE, vaddr, pid);
+    }
+
+    fn unmap(&mut self, pid: u8, vaddr: usize) { self.pt_walk(pid, vaddr, true); }
+}
+
 struct SharedStateStorage {
     pub inner: Option<SwapperSharedState>,
 }
@@ -329,63 +418,18 @@ fn write_to_swap_inner(
         }
     };
 
-    // step 2: write the page to swap
+    // step 2: write the page to the compressed RAM tier, or to swap if the tier is unavailable
     #[cfg(feature = "debug-print-swapper")]
     writeln!(DebugUart {}, "WTS PID{} VA {:x}", candidate.raw_pid(), candidate.vaddr()).ok();
     // this is safe because the page is aligned and initialized as it comes from the kernel
     // remember that this page is overwritten with encrypted data
     let buf: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(vaddr_in_swap as *mut u8, PAGE_SIZE) };
 
-    // search the swap page tables for the next free page
-    let mut next_free_page: Option<usize> = None;
-    for slot in 0..ss.sct.counts.len() {
-        let candidate = (ss.free_swap_search_origin + slot) % ss.sct.counts.len();
-        if (ss.sct.counts[candidate] & loader::FLG_SWAP_USED) == 0 {
-            #[cfg(feature = "debug-verbose")]
-            writeln!(
-                DebugUart {},
-                "WTS found free page {:x} with contents {:x}",
-                candidate,
-                ss.sct.counts[candidate]
-            )
-            .ok();
-            next_free_page = Some(candidate);
-            break;
-        }
-    }
-    if let Some(free_page_number) = next_free_page {
-        ss.free_swap_search_origin = free_page_number + 1; // start search at next page beyond the one about to be used
-        // increment the swap counter by one, rolling over if full. Note that we only have 31
-        // bits; the MSB is the "swap used" status bit
-        let mut count = ss.sct.counts[free_page_number] & !loader::FLG_SWAP_USED;
-        count = (count + 1) & !loader::FLG_SWAP_USED;
-        ss.sct.counts[free_page_number] = count | loader::FLG_SWAP_USED;
-        #[cfg(feature = "debug-verbose")]
-        writeln!(
-            DebugUart {},
-            "WTS ss.sct.counts[{:x}] {:x}",
-            free_page_number,
-            ss.sct.counts[free_page_number]
-        )
-        .ok();
-
-        // add a PT mapping for the swap entry
-        map_swap(ss, free_page_number * PAGE_SIZE, candidate.vaddr(), candidate.raw_pid());
-
-        ss.hal.encrypt_swap_to(
-            buf,
-            count,
-            free_page_number * PAGE_SIZE,
-            candidate.vaddr(),
-            candidate.raw_pid(),
-        );
+    if let Some(mut zram) = ss.zram.take() {
+        zram.evict(ss, buf, candidate.raw_pid(), candidate.vaddr());
+        ss.zram = Some(zram);
     } else {
-        writeln!(DebugUart {}, "OOM detected, dumping all swap allocs:").ok();
-        for (i, &entry) in ss.sct.counts.iter().enumerate() {
-            writeln!(DebugUart {}, "  {:04}:{:x}", i, entry).ok();
-        }
-        // OOS path
-        panic!("Ran out of swap space, hard OOM!");
+        ss.write_backing(buf, candidate.raw_pid(), candidate.vaddr());
     }
 
     // step 3: release the page (currently mapped into the swapper's memory space). Need
@@ -480,10 +524,13 @@ fn swap_handler(
             sram_start: swap_spec.sram_start as usize,
             sram_size: swap_spec.sram_size as usize,
             free_swap_search_origin: 0,
-            hard_oom_alloc_heap: None,
+            policy: None,
+            hints: ResidencyHints::default(),
             report_full_rpt: true,
             hard_oom_reserved_page: Some(reserved),
             pages_to_free: HARD_OOM_PAGE_TARGET + HARD_OOM_RESERVED_PAGES,
+            zram: None,
+            zram_base: loader::swap::derive_usable_swap(swap_spec.swap_len as usize) / PAGE_SIZE * PAGE_SIZE,
         });
     }
     let ss = sss.inner.as_mut().expect("Shared state should be initialized");
@@ -505,6 +552,23 @@ fn swap_handler(
                     panic!("Couldn't resolve swapped data. Was the page actually swapped?")
                 }
             };
+            // safety: this is only safe because the pointer we're passed from the kernel is guaranteed to be
+            // a valid u8-page in memory
+            let buf = unsafe { core::slice::from_raw_parts_mut(vaddr_in_swap as *mut u8, PAGE_SIZE) };
+            if paddr_in_swap >= ss.zram_base {
+                let slot = (paddr_in_swap - ss.zram_base) / PAGE_SIZE;
+                #[cfg(feature = "debug-print-swapper")]
+                writeln!(DebugUart {}, "RFS PID{} VA {:x} zram slot {}", pid, vaddr_in_pid, slot).ok();
+                let zram = ss.zram.as_mut().expect("Page was mapped to zram, but zram is not initialized");
+                if let Err(e) = zram.retrieve(slot, buf) {
+                    writeln!(DebugUart {}, "Couldn't retrieve page from zram slot {}: {:?}", slot, e).ok();
+                    panic!("Couldn't retrieve page from zram slot {}: {:?}", slot, e);
+                }
+                return;
+            }
+            if let Some(zram) = ss.zram.as_mut() {
+                zram.note_backing_read();
+            }
             // clear the used bit in swap
             ss.sct.counts[paddr_in_swap / PAGE_SIZE] &= !loader::FLG_SWAP_USED;
             #[cfg(feature = "debug-print-swapper")]
@@ -518,9 +582,6 @@ fn swap_handler(
             )
             .ok();
 
-            // safety: this is only safe because the pointer we're passed from the kernel is guaranteed to be
-            // a valid u8-page in memory
-            let buf = unsafe { core::slice::from_raw_parts_mut(vaddr_in_swap as *mut u8, PAGE_SIZE) };
             // this is in a retry loop because the SPIM interface can timeout during high bus congestion
             // periods.
             const TIMEOUT_RETRIES: usize = 3;
@@ -577,23 +638,27 @@ fn swap_handler(
                 panic!("No space was reserved for the hard OOM manager to run!");
             }
             // recover the RPT from kernel
-            let mut alloc_heap =
-                ss.hard_oom_alloc_heap.take().expect("Hard OOM, but no pre-allocated storage for handler!");
-            alloc_heap.clear();
-            assert!(alloc_heap.len() == 0);
+            let mut policy = ss.policy.take().expect("Hard OOM, but no pre-allocated storage for handler!");
             let rpt = unsafe {
                 core::slice::from_raw_parts(SWAP_RPT_VADDR as *const SwapAlloc, ss.sram_size / PAGE_SIZE)
             };
-            for (_i, &entry) in rpt.iter().enumerate() {
-                // filter out invalid, wired, or kernel/swapper candidates
-                if (!entry.is_wired() && entry.is_valid() && entry.raw_pid() != 1 && entry.raw_pid() != 2)
-                // report_full_rpt is used to force the heap to reserve all the data we might need in a future oom
-                    || ss.report_full_rpt
-                {
-                    //  writeln!(DebugUart {}, "Pushing {:x?}", entry).ok();
-                    alloc_heap.push(entry);
-                }
-            }
+            let report_full_rpt = ss.report_full_rpt;
+            let mut candidates = rpt
+                .iter()
+                .enumerate()
+                .filter(|(_i, entry)| {
+                    // filter out invalid, wired, or kernel/swapper candidates
+                    (!entry.is_wired() && entry.is_valid() && entry.raw_pid() != 1 && entry.raw_pid() != 2)
+                    // report_full_rpt feeds the policy all the data it might see in a future oom
+                    || report_full_rpt
+                })
+                .map(|(frame, entry)| PageInfo {
+                    frame,
+                    pid: entry.raw_pid(),
+                    vaddr: entry.vaddr(),
+                    timestamp: entry.timestamp(),
+                });
+            policy.begin(&mut candidates, &ss.hints);
             // Inside the interrupt context, evict pages. No progress on any other process is made until this
             // loop is done. The loop is "inside-out" compared to the EvictPage call -- we can't make calls to
             // the kernel that would cause us to re-enter the swap context, because that would overwrite the
@@ -604,7 +669,7 @@ fn swap_handler(
             let mut wired: usize = 0;
 
             while pages_to_free > 0 {
-                if let Some(candidate) = alloc_heap.pop() {
+                if let Some(candidate) = policy.next_victim().map(|victim| rpt[victim.frame]) {
                     if candidate.is_wired()
                         || !candidate.is_valid()
                         || candidate.raw_pid() == 1
@@ -624,8 +689,8 @@ fn swap_handler(
                     break;
                 }
             }
-            // put the alloc heap back into the shared state
-            ss.hard_oom_alloc_heap = Some(alloc_heap);
+            // put the policy back into the shared state
+            ss.policy = Some(policy);
             writeln!(
                 DebugUart {},
                 "Exiting HARD OOM swap free loop: freed {} pages; {} requests rejected, {} wired",
@@ -634,6 +699,9 @@ fn swap_handler(
                 wired
             )
             .ok();
+            if let Some(zram) = ss.zram.as_ref() {
+                writeln!(DebugUart {}, "{}", zram.stats()).ok();
+            }
             //  Restore some reserved memory for the next hard OOM invocation.
             let mut reserved = xous::map_memory(
                 None,
@@ -674,8 +742,22 @@ fn main() {
     // measure memory at boot
     get_free_pages();
     let total_ram = sss.inner.as_ref().unwrap().sram_size;
-    // Binary heap for storing the view of the memory allocations.
-    sss.inner.as_mut().unwrap().hard_oom_alloc_heap = Some(BinaryHeap::with_capacity(total_ram / PAGE_SIZE));
+    // Replacement policy, which keeps its own view of the memory allocations.
+    sss.inner.as_mut().unwrap().policy = Some(Policy::new(total_ram / PAGE_SIZE));
+    // Pool for the compressed RAM tier.
+    let zram_pool = xous::map_memory(
+        None,
+        None,
+        PAGE_SIZE * ZRAM_POOL_PAGES,
+        MemoryFlags::R | MemoryFlags::W | MemoryFlags::RESERVE,
+    )
+    .expect("couldn't reserve space for the compressed swap tier");
+    // *touch* the memory -- otherwise it might not actually be demand-paged
+    // safety: this is safe because `u8` is fully representable, and the range is never unmapped
+    let zram_pool: &'static mut [u8] =
+        unsafe { core::slice::from_raw_parts_mut(zram_pool.as_mut_ptr(), zram_pool.len()) };
+    zram_pool.fill(0);
+    sss.inner.as_mut().unwrap().zram = Some(ZramTier::new(zram_pool));
 
     // Do a single invocation at boot with 0 pages to free, to ensure that the page maps are set up,
     // and sufficient heap has been allocated for the swapper to run in case of a hard OOM. Failure to
@@ -748,10 +830,21 @@ fn main() {
                     sss.inner.as_mut().unwrap().pages_to_free = HARD_OOM_PAGE_TARGET;
                     let free_pages = get_free_pages();
                     log::info!("Free pages after GC: {}", free_pages);
+                    if let Some(zram) = sss.inner.as_ref().unwrap().zram.as_ref() {
+                        log::info!("{}", zram.stats());
+                    }
                     // return the current free page count
                     scalar.arg1 = free_pages;
                 }
             }
+            Some(Opcode::SetResidencyHint) => {
+                if let Some(scalar) = msg.body.scalar_message() {
+                    let pid = msg.sender.pid().map(|p| p.get()).unwrap_or_default();
+                    let hint: ResidencyHint = FromPrimitive::from_usize(scalar.arg1).unwrap_or_default();
+                    log::info!("PID {} residency hint: {:?}", pid, hint);
+                    sss.inner.as_mut().unwrap().hints.set(pid, hint);
+                }
+            }
             #[cfg(feature = "swap-userspace-testing")]
             Some(Opcode::Test0) => {
                 log::info!("Free mem: {}kiB", get_free_pages() * PAGE_SIZE / 1024);
@@ -10,6 +10,8 @@ use loader::swap::{SWAP_HAL_VADDR, SwapSpec};
 use crate::debug::*;
 
 pub const PAGE_SIZE: usize = 4096;
+/// Pages of RAM set aside for the compressed swap tier. Kept small, as RAM is scarce on this platform.
+pub const ZRAM_POOL_PAGES: usize = 32;
 
 /// This is an implementation for SMTs that are accessible only through a SPI
 /// register interface. The base and bounds must be translated to SPI accesses
@@ -7,6 +7,8 @@ use loader::swap::{SWAP_HAL_VADDR, SwapSpec};
 use crate::debug::DebugUart;
 
 pub const PAGE_SIZE: usize = 4096;
+/// Pages of RAM set aside for the compressed swap tier
+pub const ZRAM_POOL_PAGES: usize = 256;
 
 /// This defines a set of functions to get and receive MACs (message
 /// authentication codes, also referred to as the tag in AES-GCM-SIV.
@@ -0,0 +1,241 @@
+//! Page replacement policies.
+//!
+//! On every OOM, the swapper hands the kernel's page tracker to the selected policy, and then asks it for
+//! victims until enough memory has been freed. The policy is selected at image build time:
+//!
+//!   - `Lru` (default): evicts the pages with the oldest epoch timestamps.
+//!   - `Clock` (`swap-policy-clock`): second-chance. A page whose timestamp changed since the clock hand last
+//!     passed it is skipped once.
+//!   - `WorkingSet` (`swap-policy-working-set`): evicts pages outside of their process' working set first,
+//!     and honors per-process `ResidencyHint`s.
+//!
+//! As with the rest of the swap handler, policies must not allocate once constructed. `sim` replays
+//! recorded page access traces against the policies on the host, to compare them.
+
+pub mod sim;
+
+/// A page the kernel is tracking
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct PageInfo {
+    /// Physical page number, relative to the start of RAM
+    pub frame: usize,
+    pub pid: u8,
+    pub vaddr: usize,
+    /// Epoch of the last page table operation on this page
+    pub timestamp: u32,
+}
+
+/// How eager the swapper should be to evict the pages of a process. Processes set this for themselves
+/// with `Swapper::set_residency_hint()`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, num_derive::FromPrimitive)]
+#[repr(usize)]
+pub enum ResidencyHint {
+    /// Evict these pages before those of any other process
+    Background = 0,
+    #[default]
+    Normal = 1,
+    /// Only evict these pages if nothing else can be evicted, e.g. for `graphics-server`
+    KeepResident = 2,
+}
+
+/// Residency hints of every process, indexed by PID
+pub struct ResidencyHints {
+    hints: [ResidencyHint; 256],
+}
+impl Default for ResidencyHints {
+    fn default() -> Self { ResidencyHints { hints: [ResidencyHint::Normal; 256] } }
+}
+impl ResidencyHints {
+    pub fn get(&self, pid: u8) -> ResidencyHint { self.hints[pid as usize] }
+
+    pub fn set(&mut self, pid: u8, hint: ResidencyHint) { self.hints[pid as usize] = hint; }
+}
+
+pub trait ReplacementPolicy {
+    /// Creates a policy for a machine with `frames` pages of RAM. This is the only place a policy may
+    /// allocate memory.
+    fn new(frames: usize) -> Self
+    where
+        Self: Sized;
+
+    /// Starts an eviction round. `pages` yields every page that may be evicted.
+    fn begin(&mut self, pages: &mut dyn Iterator<Item = PageInfo>, hints: &ResidencyHints);
+
+    /// Returns the next page to evict, or `None` once every candidate has been offered
+    fn next_victim(&mut self) -> Option<PageInfo>;
+}
+
+/// Pre-allocates a candidate list, writing to it so the memory is backed before a hard OOM
+fn candidate_list(frames: usize) -> Vec<PageInfo> {
+    let mut list = vec![PageInfo { frame: usize::MAX, ..Default::default() }; frames];
+    list.clear();
+    list
+}
+
+fn collect(list: &mut Vec<PageInfo>, pages: &mut dyn Iterator<Item = PageInfo>) {
+    list.clear();
+    for page in pages {
+        // the list is sized for all of RAM, so this never reallocates
+        if list.len() < list.capacity() {
+            list.push(page);
+        }
+    }
+}
+
+/// Least recently used, going by the epoch timestamps
+pub struct Lru {
+    candidates: Vec<PageInfo>,
+    next: usize,
+}
+impl ReplacementPolicy for Lru {
+    fn new(frames: usize) -> Self { Lru { candidates: candidate_list(frames), next: 0 } }
+
+    fn begin(&mut self, pages: &mut dyn Iterator<Item = PageInfo>, _hints: &ResidencyHints) {
+        collect(&mut self.candidates, pages);
+        self.candidates.sort_unstable_by_key(|p| p.timestamp);
+        self.next = 0;
+    }
+
+    fn next_victim(&mut self) -> Option<PageInfo> {
+        let victim = self.candidates.get(self.next).copied();
+        self.next += 1;
+        victim
+    }
+}
+
+/// CLOCK, or second-chance. The "referenced" bit of a page is derived from its timestamp changing since
+/// the hand last swept past it, as the swapper has no access to the hardware accessed bits.
+pub struct Clock {
+    /// candidates of the current round, indexed by frame
+    pages: Vec<Option<PageInfo>>,
+    /// timestamp of each frame when the hand last passed it
+    seen: Vec<u32>,
+    hand: usize,
+    /// frames the hand may still pass this round before giving up
+    budget: usize,
+}
+impl ReplacementPolicy for Clock {
+    fn new(frames: usize) -> Self {
+        Clock { pages: vec![None; frames], seen: vec![0; frames], hand: 0, budget: 0 }
+    }
+
+    fn begin(&mut self, pages: &mut dyn Iterator<Item = PageInfo>, _hints: &ResidencyHints) {
+        self.pages.fill(None);
+        for page in pages {
+            if let Some(slot) = self.pages.get_mut(page.frame) {
+                *slot = Some(page);
+            }
+        }
+        // two sweeps: one to clear the referenced bits, and one to collect the victims
+        self.budget = self.pages.len() * 2;
+    }
+
+    fn next_victim(&mut self) -> Option<PageInfo> {
+        while self.budget > 0 {
+            let frame = self.hand;
+            self.hand = (self.hand + 1) % self.pages.len();
+            self.budget -= 1;
+            if let Some(page) = self.pages[frame] {
+                if page.timestamp != self.seen[frame] {
+                    // referenced since the last pass: give it a second chance
+                    self.seen[frame] = page.timestamp;
+                } else {
+                    self.pages[frame] = None;
+                    return Some(page);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Epochs a page stays in its process' working set after its last use. Epochs advance with every page
+/// table operation in the system, so this is roughly "the last N page operations".
+pub const WORKING_SET_WINDOW: u32 = 4096;
+
+/// Evicts pages that fell out of the working set first, oldest first; then the working sets themselves.
+/// `ResidencyHint`s take priority over age: `Background` pages go before anything else, and
+/// `KeepResident` pages are only evicted when nothing else is left.
+pub struct WorkingSet {
+    candidates: Vec<PageInfo>,
+    next: usize,
+}
+impl WorkingSet {
+    fn class(page: &PageInfo, hints: &ResidencyHints, now: u32) -> u8 {
+        match hints.get(page.pid) {
+            ResidencyHint::Background => 0,
+            ResidencyHint::Normal if now.wrapping_sub(page.timestamp) > WORKING_SET_WINDOW => 1,
+            ResidencyHint::Normal => 2,
+            ResidencyHint::KeepResident => 3,
+        }
+    }
+}
+impl ReplacementPolicy for WorkingSet {
+    fn new(frames: usize) -> Self { WorkingSet { candidates: candidate_list(frames), next: 0 } }
+
+    fn begin(&mut self, pages: &mut dyn Iterator<Item = PageInfo>, hints: &ResidencyHints) {
+        collect(&mut self.candidates, pages);
+        let now = self.candidates.iter().map(|p| p.timestamp).max().unwrap_or(0);
+        self.candidates.sort_unstable_by_key(|p| (Self::class(p, hints, now), p.timestamp));
+        self.next = 0;
+    }
+
+    fn next_victim(&mut self) -> Option<PageInfo> {
+        let victim = self.candidates.get(self.next).copied();
+        self.next += 1;
+        victim
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(frame: usize, pid: u8, timestamp: u32) -> PageInfo {
+        PageInfo { frame, pid, vaddr: 0x2000_0000 + frame * 4096, timestamp }
+    }
+
+    fn victims<P: ReplacementPolicy>(
+        policy: &mut P,
+        pages: &[PageInfo],
+        hints: &ResidencyHints,
+    ) -> Vec<usize> {
+        policy.begin(&mut pages.iter().copied(), hints);
+        core::iter::from_fn(|| policy.next_victim()).map(|p| p.frame).collect()
+    }
+
+    #[test]
+    fn lru_evicts_oldest_first() {
+        let pages = [page(0, 5, 30), page(1, 5, 10), page(2, 6, 20)];
+        assert_eq!(victims(&mut Lru::new(4), &pages, &ResidencyHints::default()), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn clock_gives_referenced_pages_a_second_chance() {
+        let hints = ResidencyHints::default();
+        let mut clock = Clock::new(4);
+        // first round: everything looks referenced, so the hand clears every bit before evicting
+        let pages = [page(0, 5, 1), page(1, 5, 2), page(2, 5, 3)];
+        clock.begin(&mut pages.iter().copied(), &hints);
+        assert_eq!(clock.next_victim().map(|p| p.frame), Some(0));
+        // frame 1 was touched again since the hand passed it; frame 2 wasn't
+        let pages = [page(1, 5, 9), page(2, 5, 3)];
+        assert_eq!(victims(&mut clock, &pages, &hints), vec![2, 1]);
+    }
+
+    #[test]
+    fn working_set_respects_hints() {
+        let mut hints = ResidencyHints::default();
+        hints.set(7, ResidencyHint::KeepResident);
+        hints.set(8, ResidencyHint::Background);
+        let now = WORKING_SET_WINDOW * 2;
+        let pages = [
+            page(0, 7, 1),         // old, but resident
+            page(1, 5, now),       // in the working set
+            page(2, 5, 2),         // fell out of the working set
+            page(3, 8, now - 1),   // recent, but background
+            page(4, 6, now - 100), // in the working set, older than frame 1
+        ];
+        assert_eq!(victims(&mut WorkingSet::new(8), &pages, &hints), vec![3, 2, 4, 1, 0]);
+    }
+}
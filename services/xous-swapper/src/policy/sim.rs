@@ -0,0 +1,157 @@
+//! Host-side simulator that replays page access traces against a `ReplacementPolicy`.
+//!
+//! Traces are text, one access per line, either as `<pid> <vaddr in hex>`, or as the `-- update` lines
+//! the kernel prints with the `debug-swap-verbose` feature. Anything else is ignored, so a raw boot log can
+//! be used directly. See `examples/swap-sim.rs` for a command line front-end.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{PageInfo, ReplacementPolicy, ResidencyHints};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub pid: u8,
+    pub vaddr: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SimStats {
+    pub accesses: usize,
+    /// First use of a page. These cost the same regardless of policy.
+    pub first_touches: usize,
+    /// Accesses to pages that had been evicted. This is what a better policy reduces.
+    pub refaults: usize,
+    pub refaults_by_pid: BTreeMap<u8, usize>,
+    pub evictions: usize,
+}
+
+pub fn parse_trace(text: &str) -> Vec<Access> {
+    let mut trace = Vec::new();
+    for line in text.lines() {
+        let fields = match line.split_once("-- update ") {
+            // "-- update {pid}/{vaddr:x} <- ..."
+            Some((_, rest)) => rest.split_whitespace().next().and_then(|f| f.split_once('/')),
+            None => {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(pid), Some(vaddr), None) => Some((pid, vaddr)),
+                    _ => None,
+                }
+            }
+        };
+        if let Some((pid, vaddr)) = fields {
+            let vaddr = vaddr.trim_start_matches("0x");
+            if let (Ok(pid), Ok(vaddr)) = (pid.parse::<u8>(), usize::from_str_radix(vaddr, 16)) {
+                trace.push(Access { pid, vaddr: vaddr & !0xFFF });
+            }
+        }
+    }
+    trace
+}
+
+/// Replays `trace` on a machine with `frames` pages of RAM. When RAM is full, `batch` pages are evicted at
+/// once, as the swapper does on a hard OOM. Pages of the kernel and the swapper (PIDs 1 and 2) are wired.
+pub fn simulate<P: ReplacementPolicy>(
+    trace: &[Access],
+    frames: usize,
+    batch: usize,
+    hints: &ResidencyHints,
+) -> SimStats {
+    let mut policy = P::new(frames);
+    let mut ram: Vec<Option<PageInfo>> = vec![None; frames];
+    let mut resident: HashMap<(u8, usize), usize> = HashMap::new();
+    let mut evicted: HashSet<(u8, usize)> = HashSet::new();
+    let mut free: Vec<usize> = (0..frames).rev().collect();
+    let mut stats = SimStats::default();
+    let mut epoch = 0u32;
+
+    for access in trace {
+        epoch = epoch.wrapping_add(1);
+        stats.accesses += 1;
+        let key = (access.pid, access.vaddr);
+        if let Some(&frame) = resident.get(&key) {
+            ram[frame].as_mut().unwrap().timestamp = epoch;
+            continue;
+        }
+        if evicted.remove(&key) {
+            stats.refaults += 1;
+            *stats.refaults_by_pid.entry(access.pid).or_default() += 1;
+        } else {
+            stats.first_touches += 1;
+        }
+        if free.is_empty() {
+            let mut candidates = ram.iter().flatten().copied().filter(|p| p.pid > 2);
+            policy.begin(&mut candidates, hints);
+            for _ in 0..batch {
+                let Some(victim) = policy.next_victim() else { break };
+                ram[victim.frame] = None;
+                resident.remove(&(victim.pid, victim.vaddr));
+                evicted.insert((victim.pid, victim.vaddr));
+                free.push(victim.frame);
+                stats.evictions += 1;
+            }
+        }
+        let frame = free.pop().expect("RAM is full of wired pages");
+        ram[frame] = Some(PageInfo { frame, pid: access.pid, vaddr: access.vaddr, timestamp: epoch });
+        resident.insert(key, frame);
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Clock, Lru, ResidencyHint, WorkingSet};
+    use super::*;
+
+    #[test]
+    fn parses_both_trace_formats() {
+        let log = "INFO:kernel: boot\n\
+                   -- update 5/20001000 <- 20001005 @ 40000 (in pid1)\n\
+                   7 0x30002abc\n\
+                   -- release of pid5/20001000\n";
+        assert_eq!(
+            parse_trace(log),
+            vec![Access { pid: 5, vaddr: 0x2000_1000 }, Access { pid: 7, vaddr: 0x3000_2000 }]
+        );
+    }
+
+    /// A foreground process cycling through a working set slightly too large for RAM, interleaved with a
+    /// small process that's touched rarely but has to be responsive
+    fn trace() -> Vec<Access> {
+        let mut trace = Vec::new();
+        for round in 0..20 {
+            for page in 0..40 {
+                trace.push(Access { pid: 5, vaddr: 0x2000_0000 + page * 0x1000 });
+            }
+            if round % 4 == 0 {
+                for page in 0..4 {
+                    trace.push(Access { pid: 6, vaddr: 0x2000_0000 + page * 0x1000 });
+                }
+            }
+        }
+        trace
+    }
+
+    #[test]
+    fn policies_can_be_compared() {
+        let trace = trace();
+        let hints = ResidencyHints::default();
+        let lru = simulate::<Lru>(&trace, 36, 4, &hints);
+        let clock = simulate::<Clock>(&trace, 36, 4, &hints);
+        for stats in [&lru, &clock] {
+            assert_eq!(stats.accesses, trace.len());
+            assert_eq!(stats.first_touches, 44);
+            assert!(stats.refaults > 0);
+        }
+        // the rarely used process gets paged out between uses
+        assert!(lru.refaults_by_pid[&6] > 0);
+
+        // unless it's marked as resident
+        let mut hints = ResidencyHints::default();
+        hints.set(6, ResidencyHint::KeepResident);
+        let ws = simulate::<WorkingSet>(&trace, 36, 4, &hints);
+        assert_eq!(ws.first_touches, 44);
+        assert!(ws.refaults > 0);
+        assert_eq!(ws.refaults_by_pid.get(&6), None);
+    }
+}
@@ -0,0 +1,463 @@
+//! Compressed in-RAM swap tier.
+//!
+//! Pages picked for eviction are first compressed into a pool of RAM reserved by the swapper. Only when
+//! the pool fills up are pages spilled to the (encrypted) external swap device, oldest first. Pages that
+//! don't compress well are sent straight to the external device.
+//!
+//! The pool lives in the swapper's own memory, so pages in it get the same protection as any other
+//! process memory and are not encrypted.
+//!
+//! Everything in here is allocated up front by `ZramTier::new()`, as the eviction and retrieval paths
+//! run inside the blocking swap handler, where memory can't be allocated.
+
+use core::fmt;
+
+const PAGE_SIZE: usize = 4096;
+/// Allocation unit of the pool. Compressed pages are stored as a chain of chunks.
+pub const CHUNK_SIZE: usize = 64;
+/// Pages that don't compress to at least this size are not worth keeping in RAM
+const COMPRESS_LIMIT: usize = PAGE_SIZE * 3 / 4;
+const CHAIN_END: u16 = u16::MAX;
+const NO_ENTRY: u16 = u16::MAX;
+
+// compressed stream format: a control byte `c`, followed by either `c + 1` literal bytes if `c < 0x80`,
+// or a little-endian `u16` offset back into the output for a match of `(c & 0x7F) + MIN_MATCH` bytes.
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = 0x7F + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const HASH_BITS: u32 = 12;
+
+/// Where an evicted page ends up. Implemented by the swapper on top of the platform `SwapHal`.
+pub trait SwapBacking {
+    /// Writes `page` to the external swap device, and records it as the location of `vaddr` in `pid`.
+    /// The contents of `page` are clobbered.
+    fn write_backing(&mut self, page: &mut [u8], pid: u8, vaddr: usize);
+    /// Records pool slot `slot` as the location of `vaddr` in `pid`
+    fn map_zram(&mut self, slot: usize, pid: u8, vaddr: usize);
+    /// Forgets the location of `vaddr` in `pid`
+    fn unmap(&mut self, pid: u8, vaddr: usize);
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ZramStats {
+    /// Pages currently held in the pool
+    pub stored_pages: usize,
+    /// Compressed size of the pages currently held in the pool
+    pub stored_bytes: usize,
+    pub pool_bytes: usize,
+    /// Pages that were too incompressible to keep in the pool
+    pub rejected: usize,
+    /// Pages moved from the pool to the external swap device to make room
+    pub spilled: usize,
+    /// Page-ins served from the pool
+    pub hits: usize,
+    /// Page-ins served from the external swap device
+    pub misses: usize,
+}
+impl ZramStats {
+    /// Compression ratio of the pages in the pool, times 100
+    pub fn ratio_x100(&self) -> usize {
+        (self.stored_pages * PAGE_SIZE * 100).checked_div(self.stored_bytes).unwrap_or(0)
+    }
+
+    /// Percentage of page-ins that were served from the pool
+    pub fn hit_pct(&self) -> usize { (self.hits * 100).checked_div(self.hits + self.misses).unwrap_or(0) }
+}
+impl fmt::Display for ZramStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "zram: {} pages in {}/{} bytes, ratio {}.{:02}; {} hits {} misses ({}%); {} rejected, {} spilled",
+            self.stored_pages,
+            self.stored_bytes,
+            self.pool_bytes,
+            self.ratio_x100() / 100,
+            self.ratio_x100() % 100,
+            self.hits,
+            self.misses,
+            self.hit_pct(),
+            self.rejected,
+            self.spilled
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Slot {
+    pid: u8,
+    vaddr: usize,
+    first_chunk: u16,
+    len: u16,
+    /// eviction order, used to pick the page to spill when the pool is full
+    seq: u32,
+}
+
+pub struct ZramTier {
+    pool: &'static mut [u8],
+    /// next chunk in the chain of each chunk; free chunks are chained together starting at `free_head`
+    next: Vec<u16>,
+    free_head: u16,
+    free_chunks: usize,
+    /// every page uses at least one chunk, so there can't be more slots than chunks
+    slots: Vec<Option<Slot>>,
+    seq: u32,
+    hash: Vec<u16>,
+    scratch: Vec<u8>,
+    /// holds a spilled page while `scratch` is occupied by the page being evicted
+    bounce: Vec<u8>,
+    stats: ZramStats,
+}
+
+impl ZramTier {
+    /// Creates a tier that stores compressed pages in `pool`. The pool has to be backed by physical memory,
+    /// as it is written to while handling a hard OOM.
+    pub fn new(pool: &'static mut [u8]) -> Self {
+        let chunks = (pool.len() / CHUNK_SIZE).min(CHAIN_END as usize);
+        let mut next = Vec::with_capacity(chunks);
+        for i in 0..chunks {
+            next.push(if i + 1 < chunks { (i + 1) as u16 } else { CHAIN_END });
+        }
+        let mut slots = Vec::with_capacity(chunks);
+        slots.resize(chunks, None);
+        // filled with non-zero values, so the allocations are backed by memory before a hard OOM hits
+        let hash = vec![NO_ENTRY; 1 << HASH_BITS];
+        let scratch = vec![0xFF; PAGE_SIZE];
+        let bounce = vec![0xFF; COMPRESS_LIMIT];
+        let pool_bytes = chunks * CHUNK_SIZE;
+        ZramTier {
+            pool,
+            next,
+            free_head: if chunks > 0 { 0 } else { CHAIN_END },
+            free_chunks: chunks,
+            slots,
+            seq: 0,
+            hash,
+            scratch,
+            bounce,
+            stats: ZramStats { pool_bytes, ..Default::default() },
+        }
+    }
+
+    pub fn stats(&self) -> &ZramStats { &self.stats }
+
+    /// Takes a page out of RAM. It's kept compressed in the pool if possible, spilling the oldest pages of
+    /// the pool to `backing` if there isn't enough room. The contents of `page` are clobbered.
+    pub fn evict<B: SwapBacking>(&mut self, backing: &mut B, page: &mut [u8], pid: u8, vaddr: usize) {
+        assert!(page.len() == PAGE_SIZE);
+        let len = match self.compress(page) {
+            Some(len) if len.div_ceil(CHUNK_SIZE) <= self.next.len() => len,
+            _ => {
+                self.stats.rejected += 1;
+                backing.write_backing(page, pid, vaddr);
+                return;
+            }
+        };
+        let chunks_needed = len.div_ceil(CHUNK_SIZE);
+        while self.free_chunks < chunks_needed {
+            // `page` is free to stage the spilled page in, as its contents are already compressed into
+            // `scratch`
+            self.spill_oldest(backing, page);
+        }
+        let slot = self.slots.iter().position(|s| s.is_none()).expect("more chunks free than slots");
+
+        let first_chunk = self.free_head;
+        let mut chunk = first_chunk;
+        let mut last = chunk;
+        for data in self.scratch[..len].chunks(CHUNK_SIZE) {
+            let offset = chunk as usize * CHUNK_SIZE;
+            self.pool[offset..offset + data.len()].copy_from_slice(data);
+            last = chunk;
+            chunk = self.next[chunk as usize];
+        }
+        self.free_head = chunk;
+        self.next[last as usize] = CHAIN_END;
+        self.free_chunks -= chunks_needed;
+
+        self.slots[slot] = Some(Slot { pid, vaddr, first_chunk, len: len as u16, seq: self.seq });
+        self.seq = self.seq.wrapping_add(1);
+        self.stats.stored_pages += 1;
+        self.stats.stored_bytes += len;
+        backing.map_zram(slot, pid, vaddr);
+    }
+
+    /// Decompresses the page in `slot` into `page` and releases the slot.
+    pub fn retrieve(&mut self, slot: usize, page: &mut [u8]) -> Result<(), xous::Error> {
+        let entry = self.slots.get_mut(slot).and_then(|s| s.take()).ok_or(xous::Error::BadAddress)?;
+        self.take_chunks(&entry);
+        self.stats.hits += 1;
+        if decompress(&self.scratch[..entry.len as usize], page) {
+            Ok(())
+        } else {
+            Err(xous::Error::InternalError)
+        }
+    }
+
+    /// Records a page-in that had to be served from the external swap device
+    pub fn note_backing_read(&mut self) { self.stats.misses += 1; }
+
+    /// Moves the page that has been in the pool the longest to the external swap device
+    fn spill_oldest<B: SwapBacking>(&mut self, backing: &mut B, page: &mut [u8]) {
+        let seq = self.seq;
+        let slot = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.as_ref().map(|s| (i, seq.wrapping_sub(s.seq))))
+            .max_by_key(|&(_, age)| age)
+            .map(|(i, _)| i)
+            .expect("pool is full, but holds no pages");
+        let entry = self.slots[slot].take().unwrap();
+        let mut bounce = core::mem::take(&mut self.bounce);
+        let len = self.read_chunks(&entry, &mut bounce);
+        self.free_chain(&entry);
+        assert!(decompress(&bounce[..len], page), "zram pool corrupted");
+        self.bounce = bounce;
+        backing.unmap(entry.pid, entry.vaddr);
+        backing.write_backing(page, entry.pid, entry.vaddr);
+        self.stats.spilled += 1;
+    }
+
+    /// Copies the chunks of `entry` into `scratch` and returns them to the free list
+    fn take_chunks(&mut self, entry: &Slot) {
+        let mut scratch = core::mem::take(&mut self.scratch);
+        self.read_chunks(entry, &mut scratch);
+        self.scratch = scratch;
+        self.free_chain(entry);
+    }
+
+    fn read_chunks(&self, entry: &Slot, dst: &mut [u8]) -> usize {
+        let len = entry.len as usize;
+        let mut chunk = entry.first_chunk;
+        for data in dst[..len].chunks_mut(CHUNK_SIZE) {
+            let offset = chunk as usize * CHUNK_SIZE;
+            data.copy_from_slice(&self.pool[offset..offset + data.len()]);
+            chunk = self.next[chunk as usize];
+        }
+        len
+    }
+
+    fn free_chain(&mut self, entry: &Slot) {
+        let mut last = entry.first_chunk;
+        let mut count = 1;
+        while self.next[last as usize] != CHAIN_END {
+            last = self.next[last as usize];
+            count += 1;
+        }
+        self.next[last as usize] = self.free_head;
+        self.free_head = entry.first_chunk;
+        self.free_chunks += count;
+        self.stats.stored_pages -= 1;
+        self.stats.stored_bytes -= entry.len as usize;
+    }
+
+    /// Compresses `src` into `scratch`. Returns `None` if the result would be larger than `COMPRESS_LIMIT`.
+    fn compress(&mut self, src: &[u8]) -> Option<usize> {
+        self.hash.fill(NO_ENTRY);
+        let out = &mut self.scratch[..COMPRESS_LIMIT];
+        let mut o = 0;
+        let mut i = 0;
+        let mut literal_start = 0;
+        while i + MIN_MATCH <= src.len() {
+            let word = u32::from_le_bytes([src[i], src[i + 1], src[i + 2], src[i + 3]]);
+            let h = (word.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+            let candidate = self.hash[h] as usize;
+            self.hash[h] = i as u16;
+            if candidate == NO_ENTRY as usize
+                || src[candidate..candidate + MIN_MATCH] != src[i..i + MIN_MATCH]
+            {
+                i += 1;
+                continue;
+            }
+            let mut len = MIN_MATCH;
+            while i + len < src.len() && len < MAX_MATCH && src[candidate + len] == src[i + len] {
+                len += 1;
+            }
+            o = put_literals(out, o, &src[literal_start..i])?;
+            if o + 3 > out.len() {
+                return None;
+            }
+            let offset = (i - candidate) as u16;
+            out[o] = 0x80 | (len - MIN_MATCH) as u8;
+            out[o + 1..o + 3].copy_from_slice(&offset.to_le_bytes());
+            o += 3;
+            i += len;
+            literal_start = i;
+        }
+        put_literals(out, o, &src[literal_start..])
+    }
+}
+
+fn put_literals(out: &mut [u8], mut o: usize, literals: &[u8]) -> Option<usize> {
+    for run in literals.chunks(MAX_LITERALS) {
+        if o + 1 + run.len() > out.len() {
+            return None;
+        }
+        out[o] = (run.len() - 1) as u8;
+        out[o + 1..o + 1 + run.len()].copy_from_slice(run);
+        o += 1 + run.len();
+    }
+    Some(o)
+}
+
+/// Expands `src` into `dst`. Returns `false` if `src` is malformed or doesn't decompress to exactly
+/// `dst.len()` bytes.
+fn decompress(src: &[u8], dst: &mut [u8]) -> bool {
+    let mut i = 0;
+    let mut o = 0;
+    while i < src.len() {
+        let c = src[i] as usize;
+        i += 1;
+        if c < 0x80 {
+            let len = c + 1;
+            if i + len > src.len() || o + len > dst.len() {
+                return false;
+            }
+            dst[o..o + len].copy_from_slice(&src[i..i + len]);
+            i += len;
+            o += len;
+        } else {
+            let len = (c & 0x7F) + MIN_MATCH;
+            if i + 2 > src.len() {
+                return false;
+            }
+            let offset = u16::from_le_bytes([src[i], src[i + 1]]) as usize;
+            i += 2;
+            if offset == 0 || offset > o || o + len > dst.len() {
+                return false;
+            }
+            // matches may overlap their own output, so copy byte by byte
+            for k in 0..len {
+                dst[o + k] = dst[o + k - offset];
+            }
+            o += len;
+        }
+    }
+    o == dst.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Location {
+        Zram(usize),
+        Backing(usize),
+    }
+
+    /// Stands in for the platform `SwapHal` and the swap page tables
+    #[derive(Default)]
+    struct SimSwapHal {
+        pages: Vec<Vec<u8>>,
+        map: HashMap<(u8, usize), Location>,
+    }
+    impl SwapBacking for SimSwapHal {
+        fn write_backing(&mut self, page: &mut [u8], pid: u8, vaddr: usize) {
+            self.pages.push(page.to_vec());
+            // like the real HAL, the source buffer gets overwritten
+            page.fill(0xA5);
+            self.map.insert((pid, vaddr), Location::Backing(self.pages.len() - 1));
+        }
+
+        fn map_zram(&mut self, slot: usize, pid: u8, vaddr: usize) {
+            self.map.insert((pid, vaddr), Location::Zram(slot));
+        }
+
+        fn unmap(&mut self, pid: u8, vaddr: usize) { self.map.remove(&(pid, vaddr)).unwrap(); }
+    }
+    impl SimSwapHal {
+        fn page_in(&mut self, zram: &mut ZramTier, pid: u8, vaddr: usize) -> Vec<u8> {
+            let mut page = vec![0; PAGE_SIZE];
+            match self.map.remove(&(pid, vaddr)).unwrap() {
+                Location::Zram(slot) => zram.retrieve(slot, &mut page).unwrap(),
+                Location::Backing(index) => {
+                    page.copy_from_slice(&self.pages[index]);
+                    zram.note_backing_read();
+                }
+            }
+            page
+        }
+    }
+
+    fn tier(pool_bytes: usize) -> ZramTier {
+        ZramTier::new(Box::leak(vec![0u8; pool_bytes].into_boxed_slice()))
+    }
+
+    /// A page of text-like data that compresses reasonably well
+    fn text_page(seed: u8) -> Vec<u8> {
+        let words: [&[u8]; 5] = [b"swap ", b"page ", b"xous ", b"kernel ", b"memory "];
+        let mut page = Vec::with_capacity(PAGE_SIZE);
+        let mut i = seed as usize;
+        while page.len() < PAGE_SIZE {
+            page.extend_from_slice(words[i % words.len()]);
+            page.push(b'0' + seed % 10);
+            i = i.wrapping_mul(7).wrapping_add(3) % 11;
+        }
+        page.truncate(PAGE_SIZE);
+        page
+    }
+
+    /// A page of pseudo-random data that doesn't compress
+    fn noise_page(seed: u32) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..PAGE_SIZE)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compression_roundtrip() {
+        let mut zram = tier(64 * 1024);
+        for page in [vec![0u8; PAGE_SIZE], text_page(1), text_page(200)] {
+            let len = zram.compress(&page).unwrap();
+            assert!(len < PAGE_SIZE / 2);
+            let mut out = vec![0u8; PAGE_SIZE];
+            assert!(decompress(&zram.scratch[..len], &mut out));
+            assert_eq!(out, page);
+        }
+        assert!(zram.compress(&noise_page(1)).is_none());
+        assert!(!decompress(&[0x80, 1, 0], &mut [0u8; 8]), "match before any output must be rejected");
+    }
+
+    #[test]
+    fn pages_are_kept_in_ram_until_the_pool_fills() {
+        let mut hal = SimSwapHal::default();
+        let mut zram = tier(4 * 1024);
+        let mut expected = HashMap::new();
+        for i in 0..32u8 {
+            let page = text_page(i);
+            expected.insert(i as usize * PAGE_SIZE, page.clone());
+            let mut buf = page.clone();
+            zram.evict(&mut hal, &mut buf, 5, i as usize * PAGE_SIZE);
+        }
+        let mut noise = noise_page(9);
+        expected.insert(0x10_0000, noise.clone());
+        zram.evict(&mut hal, &mut noise, 5, 0x10_0000);
+
+        let stats = *zram.stats();
+        assert_eq!(stats.rejected, 1);
+        assert!(stats.spilled > 0, "a 4k pool can't hold 32 pages");
+        assert!(stats.stored_bytes <= stats.pool_bytes);
+        assert!(stats.ratio_x100() > 200);
+        // the oldest pages are the ones that got spilled
+        assert_eq!(hal.map[&(5, 0)], Location::Backing(0));
+        assert!(matches!(hal.map[&(5, 31 * PAGE_SIZE)], Location::Zram(_)));
+
+        for (vaddr, page) in expected {
+            assert_eq!(hal.page_in(&mut zram, 5, vaddr), page, "page {:x}", vaddr);
+        }
+        let stats = *zram.stats();
+        assert_eq!(stats.stored_pages, 0);
+        assert_eq!(stats.stored_bytes, 0);
+        assert_eq!(stats.hits + stats.misses, 33);
+        assert_eq!(stats.misses, stats.spilled + stats.rejected);
+        assert_eq!(zram.free_chunks, zram.next.len());
+    }
+}
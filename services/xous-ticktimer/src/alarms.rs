@@ -0,0 +1,375 @@
+//! Alarms: scalar messages the ticktimer sends to a server at a given time, once or periodically.
+//!
+//! The alarms are kept by the main loop, and fired by a thread of the ticktimer's own that sleeps until
+//! the next one is due, using the same sleep requests as everyone else. Every time it wakes up it sends
+//! `AlarmWait` again, which fires whatever is due and puts it back to sleep until the next alarm.
+//!
+//! Wall-clock alarms are turned into `elapsed_ms()` deadlines with the UTC time the thread reads from the
+//! time server before it sends `AlarmWait`. The main loop can't ask the time server itself: the time
+//! server calls the ticktimer, so blocking on it from here would deadlock.
+
+use std::collections::{BTreeMap, HashMap};
+
+use num_traits::ToPrimitive;
+use xous::MessageSender;
+use xous_api_ticktimer::api;
+
+use crate::platform::implementation::XousTickTimer;
+use crate::{TimeoutExpiry, TimerRequest};
+
+/// Answer to `AlarmWait` when there are no wall-clock alarms. A sleep that runs out answers with
+/// `RequestKind::Sleep`, which is 0 and means nothing changed.
+const WAKE_NO_WALL_CLOCK: usize = 1;
+/// Answer to `AlarmWait` when wall-clock alarms are set, so the thread has to read UTC before it waits
+const WAKE_WALL_CLOCK: usize = 2;
+
+/// `TimeOp::WallClockTimeInit` and `TimeOp::GetUtcTimeMs` of the time server in `dns`. We can't depend on
+/// it, since it depends on us.
+const TIME_WALL_CLOCK_INIT: usize = 6;
+const TIME_GET_UTC_MS: usize = 3;
+
+struct Alarm {
+    owner: Option<xous::PID>,
+    sid: (u32, u32, u32, u32),
+    cid: xous::CID,
+    opcode: usize,
+    arg: usize,
+    /// On the `elapsed_ms()` clock, or in ms since the UNIX epoch for wall-clock alarms
+    deadline: i64,
+    wall_clock: bool,
+    /// 0 for alarms that go off once
+    period: i64,
+}
+
+/// Where the alarm thread is
+enum Waiter {
+    /// Running, and on its way back with an `AlarmWait`
+    Running,
+    /// Waiting for alarms to be set
+    Parked(MessageSender),
+    /// In the sleep heap until the next alarm is due
+    Sleeping(MessageSender),
+}
+
+pub(crate) struct Alarms {
+    alarms: HashMap<u32, Alarm>,
+    next_id: u32,
+    /// Connections to the servers alarms are sent to, and how many alarms use each
+    connections: HashMap<(u32, u32, u32, u32), (xous::CID, usize)>,
+    /// UTC minus `elapsed_ms()`, as of the last `AlarmWait` that came with the time
+    utc_offset: Option<i64>,
+    waiter: Waiter,
+}
+
+impl Alarms {
+    pub(crate) fn new() -> Self {
+        Alarms {
+            alarms: HashMap::new(),
+            next_id: 1,
+            connections: HashMap::new(),
+            utc_offset: None,
+            waiter: Waiter::Running,
+        }
+    }
+
+    /// Sets the alarm in `request`, filling in its ID, which stays 0 if the server can't be reached.
+    pub(crate) fn set(&mut self, owner: Option<xous::PID>, now: i64, request: &mut api::AlarmRequest) {
+        let cid = match self.connections.get_mut(&request.sid) {
+            Some((cid, users)) => {
+                *users += 1;
+                *cid
+            }
+            None => {
+                let (s0, s1, s2, s3) = request.sid;
+                match xous::try_connect(xous::SID::from_u32(s0, s1, s2, s3)) {
+                    Ok(cid) => {
+                        self.connections.insert(request.sid, (cid, 1));
+                        cid
+                    }
+                    Err(e) => {
+                        log::warn!("couldn't connect to the server for an alarm: {:?}", e);
+                        return;
+                    }
+                }
+            }
+        };
+        let id = self.next_id;
+        // 0 is "no alarm", and IDs are not reused while an alarm is set
+        loop {
+            self.next_id = self.next_id.wrapping_add(1).max(1);
+            if !self.alarms.contains_key(&self.next_id) {
+                break;
+            }
+        }
+        let deadline = if request.wall_clock {
+            request.deadline_ms.min(i64::MAX as u64) as i64
+        } else {
+            now.saturating_add(request.deadline_ms.min(i64::MAX as u64) as i64)
+        };
+        self.alarms.insert(
+            id,
+            Alarm {
+                owner,
+                sid: request.sid,
+                cid,
+                opcode: request.opcode as usize,
+                arg: request.arg as usize,
+                deadline,
+                wall_clock: request.wall_clock,
+                period: request.period_ms.min(i64::MAX as u64) as i64,
+            },
+        );
+        request.id = id;
+    }
+
+    pub(crate) fn cancel(&mut self, owner: Option<xous::PID>, id: u32) -> bool {
+        match self.alarms.get(&id) {
+            Some(alarm) if alarm.owner == owner => {
+                self.remove(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn remove(&mut self, id: u32) {
+        if let Some(alarm) = self.alarms.remove(&id) {
+            if let Some((cid, users)) = self.connections.get_mut(&alarm.sid) {
+                *users -= 1;
+                if *users == 0 {
+                    // safety: only alarms use these connections, and none is left for this server
+                    unsafe { xous::disconnect(*cid).ok() };
+                    self.connections.remove(&alarm.sid);
+                }
+            }
+        }
+    }
+
+    /// When an alarm is next due on the `elapsed_ms()` clock. Wall-clock alarms aren't due until the
+    /// time is known.
+    fn due(&self, alarm: &Alarm) -> Option<i64> {
+        match alarm.wall_clock {
+            true => self.utc_offset.map(|offset| alarm.deadline - offset),
+            false => Some(alarm.deadline),
+        }
+    }
+
+    /// The alarms due at `now`, in the order they came due, with how many periods each missed. Periodic
+    /// alarms are moved on to their next deadline; the others are left for the caller to remove.
+    fn take_due(&mut self, now: i64) -> Vec<(u32, i64)> {
+        let mut due = Vec::new();
+        for (&id, alarm) in self.alarms.iter() {
+            if let Some(at) = self.due(alarm) {
+                if at <= now {
+                    due.push((at, id));
+                }
+            }
+        }
+        // alarms due at the same time go off in the order they were set
+        due.sort_unstable();
+        due.into_iter()
+            .map(|(at, id)| {
+                let alarm = self.alarms.get_mut(&id).unwrap();
+                if alarm.period > 0 {
+                    let periods = (now - at) / alarm.period + 1;
+                    alarm.deadline += periods * alarm.period;
+                    (id, periods)
+                } else {
+                    (id, 1)
+                }
+            })
+            .collect()
+    }
+
+    fn fire(&mut self, now: i64) {
+        let mut finished = Vec::new();
+        for (id, periods) in self.take_due(now) {
+            let alarm = &self.alarms[&id];
+            if alarm.period == 0 {
+                finished.push(id);
+            }
+            match xous::try_send_message(
+                alarm.cid,
+                xous::Message::new_scalar(alarm.opcode, id as usize, alarm.arg, periods as usize, 0),
+            ) {
+                Ok(_) => {}
+                Err(xous::Error::ServerQueueFull) => {
+                    log::warn!("alarm {} was missed, its server's queue is full", id)
+                }
+                Err(e) => {
+                    log::info!("cancelling alarm {}, its server can't be reached: {:?}", id, e);
+                    if alarm.period > 0 {
+                        finished.push(id);
+                    }
+                }
+            }
+        }
+        for id in finished {
+            self.remove(id);
+        }
+    }
+
+    /// When the next alarm is due on the `elapsed_ms()` clock
+    fn next_due(&self) -> Option<i64> { self.alarms.values().filter_map(|a| self.due(a)).min() }
+
+    fn wake_answer(&self) -> usize {
+        match self.alarms.values().any(|a| a.wall_clock) {
+            true => WAKE_WALL_CLOCK,
+            false => WAKE_NO_WALL_CLOCK,
+        }
+    }
+
+    /// Handles an `AlarmWait` from the alarm thread: fires what is due, and returns how long the
+    /// thread should sleep for, or `None` if there is nothing to wait for.
+    pub(crate) fn wait(&mut self, sender: MessageSender, now: i64, utc: Option<i64>) -> Option<i64> {
+        if let Some(utc) = utc {
+            self.utc_offset = Some(utc - now);
+        }
+        self.fire(now);
+        match self.next_due() {
+            Some(next) => {
+                self.waiter = Waiter::Sleeping(sender);
+                Some((next - now).max(1))
+            }
+            None => {
+                self.waiter = Waiter::Parked(sender);
+                None
+            }
+        }
+    }
+
+    /// Has the alarm thread come back early, after alarms were changed or the system resumed.
+    pub(crate) fn wake(
+        &mut self,
+        ticktimer: &mut XousTickTimer,
+        sleep_heap: &mut BTreeMap<TimeoutExpiry, TimerRequest>,
+    ) {
+        let answer = self.wake_answer();
+        match core::mem::replace(&mut self.waiter, Waiter::Running) {
+            Waiter::Running => {}
+            Waiter::Parked(sender) => {
+                xous::return_scalar(sender, answer).ok();
+            }
+            Waiter::Sleeping(sender) => {
+                ticktimer.stop_sleep(sleep_heap);
+                let len_before = sleep_heap.len();
+                sleep_heap.retain(|_, v| v.sender != sender);
+                // if it's not in the heap, the sleep already ran out and the thread is on its way back
+                if sleep_heap.len() != len_before {
+                    xous::return_scalar(sender, answer).ok();
+                }
+                ticktimer.start_sleep(sleep_heap);
+            }
+        }
+    }
+}
+
+/// The alarm thread. It waits in the ticktimer's own queue for as long as the main loop tells it to.
+pub(crate) fn alarm_thread() {
+    let ticktimer = xous::connect(xous::SID::from_bytes(b"ticktimer-server").unwrap())
+        .expect("couldn't connect to the ticktimer");
+    let mut time_server = None;
+    let mut wall_clock = false;
+    loop {
+        let utc = if wall_clock { utc_ms(&mut time_server) } else { None };
+        let (lower, upper) = match utc {
+            Some(utc) => (utc as u64 & 0xFFFF_FFFF, (utc as u64) >> 32),
+            None => (0, 0),
+        };
+        match xous::send_message(
+            ticktimer,
+            xous::Message::new_blocking_scalar(
+                api::Opcode::AlarmWait.to_usize().unwrap(),
+                lower as usize,
+                upper as usize,
+                utc.is_some() as usize,
+                0,
+            ),
+        ) {
+            Ok(xous::Result::Scalar1(WAKE_WALL_CLOCK)) => wall_clock = true,
+            Ok(xous::Result::Scalar1(WAKE_NO_WALL_CLOCK)) => wall_clock = false,
+            Ok(_) => {}
+            Err(e) => log::error!("alarm thread couldn't wait: {:?}", e),
+        }
+    }
+}
+
+/// UTC from the time server, if it is running and the time has been set
+fn utc_ms(time_server: &mut Option<xous::CID>) -> Option<i64> {
+    if time_server.is_none() {
+        *time_server = xous::try_connect(xous::SID::from_bytes(b"timeserverpublic").unwrap()).ok();
+    }
+    let cid = (*time_server)?;
+    match xous::send_message(cid, xous::Message::new_blocking_scalar(TIME_WALL_CLOCK_INIT, 0, 0, 0, 0)) {
+        Ok(xous::Result::Scalar1(1)) => {}
+        _ => return None,
+    }
+    match xous::send_message(cid, xous::Message::new_blocking_scalar(TIME_GET_UTC_MS, 0, 0, 0, 0)) {
+        Ok(xous::Result::Scalar2(lower, upper)) => Some((lower as u64 | ((upper as u64) << 32)) as i64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adds an alarm without connecting to its server, the way `set()` would
+    fn add(alarms: &mut Alarms, deadline: i64, period: i64, wall_clock: bool) -> u32 {
+        let id = alarms.next_id;
+        alarms.next_id += 1;
+        alarms.alarms.insert(
+            id,
+            Alarm { owner: None, sid: (0, 0, 0, 0), cid: 0, opcode: 0, arg: 0, deadline, wall_clock, period },
+        );
+        id
+    }
+
+    #[test]
+    fn test_order() {
+        let mut alarms = Alarms::new();
+        let late = add(&mut alarms, 300, 0, false);
+        let early = add(&mut alarms, 100, 0, false);
+        let tied = add(&mut alarms, 100, 0, false);
+        let future = add(&mut alarms, 1000, 0, false);
+        assert_eq!(alarms.next_due(), Some(100));
+        assert!(alarms.take_due(99).is_empty());
+        assert_eq!(alarms.take_due(500), vec![(early, 1), (tied, 1), (late, 1)]);
+        // one-shot alarms are left in place for `fire()` to remove
+        assert!(alarms.alarms.contains_key(&late));
+        assert!(alarms.alarms.contains_key(&future));
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let mut alarms = Alarms::new();
+        let periodic = add(&mut alarms, 100, 50, false);
+        let once = add(&mut alarms, 120, 0, false);
+        assert_eq!(alarms.take_due(100), vec![(periodic, 1)]);
+        assert_eq!(alarms.alarms[&periodic].deadline, 150);
+        assert_eq!(alarms.next_due(), Some(120));
+        // woken late: the missed periods are counted, and the next deadline stays on the period grid
+        assert_eq!(alarms.take_due(260), vec![(once, 1), (periodic, 3)]);
+        assert_eq!(alarms.alarms[&periodic].deadline, 300);
+        alarms.alarms.remove(&once);
+        assert_eq!(alarms.next_due(), Some(300));
+    }
+
+    #[test]
+    fn test_wall_clock() {
+        let mut alarms = Alarms::new();
+        let wall = add(&mut alarms, 1_000_500, 0, true);
+        let elapsed = add(&mut alarms, 800, 0, false);
+        assert_eq!(alarms.wake_answer(), WAKE_WALL_CLOCK);
+        // without the time, wall-clock alarms never come due
+        assert_eq!(alarms.next_due(), Some(800));
+        assert_eq!(alarms.take_due(10_000_000), vec![(elapsed, 1)]);
+        alarms.alarms.remove(&elapsed);
+        assert_eq!(alarms.next_due(), None);
+        // UTC 1_000_000 at elapsed 1000
+        alarms.utc_offset = Some(1_000_000 - 1000);
+        assert_eq!(alarms.next_due(), Some(1500));
+        assert!(alarms.take_due(1499).is_empty());
+        assert_eq!(alarms.take_due(1500), vec![(wall, 1)]);
+    }
+}
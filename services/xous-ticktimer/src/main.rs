@@ -10,6 +10,7 @@ use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 use log::{error, info};
 
+mod alarms;
 mod platform;
 use platform::implementation::*;
 use platform::*;
@@ -89,6 +90,11 @@ fn main() -> ! {
     let mut mutex_hash: HashMap<Option<xous::PID>, HashMap<usize, VecDeque<xous::MessageSender>>> =
         HashMap::new();
 
+    // Alarms, and the thread that fires them. The thread is started once the server is up, since it
+    // waits in our own queue.
+    let mut alarms = alarms::Alarms::new();
+    xous::create_thread_0(alarms::alarm_thread).expect("couldn't start the alarm thread");
+
     let mut msg_opt = None;
     let mut return_type = 0;
     loop {
@@ -114,6 +120,18 @@ fn main() -> ! {
                 }
             }
 
+            api::Opcode::ElapsedUs => {
+                if let Some(scalar) = msg.body.scalar_message_mut() {
+                    let time = ticktimer.elapsed_us();
+                    scalar.arg1 = (time & 0xFFFF_FFFF) as usize;
+                    scalar.arg2 = ((time >> 32) & 0xFFFF_FFFF) as usize;
+                    scalar.id = 0;
+
+                    // API calls expect a `Scalar2` value in response
+                    return_type = 2;
+                }
+            }
+
             api::Opcode::SleepMs => {
                 if let Some(scalar) = msg.body.scalar_message_mut() {
                     let ms = scalar.arg1 as i64;
@@ -196,8 +214,64 @@ fn main() -> ! {
                 #[cfg(not(any(target_arch = "arm", feature = "cramium-soc", feature = "cramium-fpga")))]
                 susres.suspend_until_resume(_token).expect("couldn't execute suspend/resume");
                 ticktimer.resume();
+                // wall-clock alarms went on through the suspend, so they have to be looked at again
+                alarms.wake(&mut ticktimer, &mut sleep_heap);
             }),
 
+            api::Opcode::SetAlarm => {
+                let Some(mem) = msg.body.memory_message_mut() else {
+                    log::error!("sender made SetAlarm request that wasn't a mutable lend");
+                    continue;
+                };
+                let mut buf = unsafe { xous_ipc::Buffer::from_memory_message_mut(mem) };
+                let Ok(mut request) = buf.to_original::<api::AlarmRequest, _>() else {
+                    log::error!("couldn't decode SetAlarm request");
+                    continue;
+                };
+                alarms.set(msg.sender.pid(), ticktimer.elapsed_ms() as i64, &mut request);
+                buf.replace(request).ok();
+                if request.id != 0 {
+                    alarms.wake(&mut ticktimer, &mut sleep_heap);
+                }
+            }
+
+            api::Opcode::CancelAlarm => {
+                let Some(scalar) = msg.body.scalar_message_mut() else {
+                    log::error!("sender made CancelAlarm request that wasn't a scalar");
+                    continue;
+                };
+                let cancelled = alarms.cancel(msg.sender.pid(), scalar.arg1 as u32);
+                scalar.id = cancelled as usize;
+                if cancelled {
+                    alarms.wake(&mut ticktimer, &mut sleep_heap);
+                }
+            }
+
+            api::Opcode::AlarmWait => {
+                if msg.sender.pid().map(|p| p.get()).unwrap_or_default() as u32 != xous::process::id() {
+                    log::error!("got an AlarmWait message from a process other than the ticktimer server");
+                    continue;
+                }
+                let Some(args) = msg.body.scalar_message() else {
+                    continue;
+                };
+                let utc = if args.arg3 != 0 {
+                    Some((args.arg1 as u64 | ((args.arg2 as u64) << 32)) as i64)
+                } else {
+                    None
+                };
+                let sender = msg.sender;
+                // The alarm thread is answered when it is time to fire the next alarm, or earlier
+                // if alarms change. Forget the message so it isn't answered now.
+                core::mem::forget(msg_opt.take());
+                if let Some(ms) = alarms.wait(sender, ticktimer.elapsed_ms() as i64, utc) {
+                    ticktimer.recalculate_sleep(
+                        &mut sleep_heap,
+                        Some(TimerRequest { msec: ms.into(), sender, kind: RequestKind::Sleep, data: 0 }),
+                    );
+                }
+            }
+
             api::Opcode::PingWdt => {
                 #[cfg(feature = "watchdog")]
                 ticktimer.reset_wdt();
@@ -109,6 +109,8 @@ impl XousTickTimer {
         elapsed_ms
     }
 
+    pub fn elapsed_us(&self) -> u64 { self.raw_ticktime() as u64 * 1000 / TICKS_PER_MS as u64 }
+
     pub fn stop_interrupt(&mut self) -> Option<TimerRequest> {
         // Disable the timer and interrupt
         self.timer.stop();
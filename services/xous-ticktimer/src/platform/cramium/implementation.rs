@@ -175,6 +175,9 @@ impl XousTickTimer {
 
     pub fn elapsed_ms(&self) -> u64 { self.raw_ticktime() / TICKS_PER_MS }
 
+    /// The timer counts milliseconds, so this is only as fine as `elapsed_ms()`
+    pub fn elapsed_us(&self) -> u64 { self.raw_ticktime() * 1000 / TICKS_PER_MS }
+
     pub fn stop_interrupt(&mut self) -> Option<TimerRequest> {
         // Disable the timer
         self.csr.wfo(utra::ticktimer::EV_ENABLE_ALARM, 0);
@@ -110,6 +110,8 @@ impl XousTickTimer {
 
     pub fn elapsed_ms(&self) -> u64 { self.start.elapsed().as_millis().try_into().unwrap() }
 
+    pub fn elapsed_us(&self) -> u64 { self.start.elapsed().as_micros().try_into().unwrap() }
+
     pub fn stop_interrupt(&mut self) -> Option<TimerRequest> {
         self.sleep_comms.send(SleepComms::InterruptSleep).unwrap();
         self.time_remaining_receiver.recv().ok().flatten()
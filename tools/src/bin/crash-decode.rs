@@ -0,0 +1,178 @@
+//! Turns a crash dump taken by the `crash-dump` library into a backtrace.
+//!
+//! The dump may either be the raw PDDB key contents, or the `CRD:` lines printed by the shellchat
+//! `crashdump show` command. Symbol names are printed as they appear in the ELF; pipe the output through
+//! `rustfilt` to demangle them.
+
+use std::fs;
+
+use clap::{App, Arg, crate_version};
+use crash_dump::{CrashDump, CrashKind};
+use xmas_elf::ElfFile;
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::{Entry, Type};
+
+const EXCEPTION_NAMES: [&str; 10] = [
+    "InstructionAddressMisaligned",
+    "InstructionAccessFault",
+    "IllegalInstruction",
+    "LoadAddressMisaligned",
+    "LoadAccessFault",
+    "StoreAddressMisaligned",
+    "StoreAccessFault",
+    "InstructionPageFault",
+    "LoadPageFault",
+    "StorePageFault",
+];
+
+struct Symbol {
+    addr: u32,
+    size: u32,
+    name: String,
+}
+
+struct Symbols {
+    symbols: Vec<Symbol>,
+}
+impl Symbols {
+    fn load(elf_data: &[u8]) -> Result<Symbols, String> {
+        let elf = ElfFile::new(elf_data)?;
+        let mut symbols = Vec::new();
+        for section in elf.section_iter() {
+            if let Ok(SectionData::SymbolTable32(entries)) = section.get_data(&elf) {
+                for entry in entries.iter() {
+                    if entry.get_type() != Ok(Type::Func) || entry.value() == 0 {
+                        continue;
+                    }
+                    symbols.push(Symbol {
+                        addr: entry.value() as u32,
+                        size: entry.size() as u32,
+                        name: entry.get_name(&elf).unwrap_or("<<error>>").to_owned(),
+                    });
+                }
+            }
+        }
+        if symbols.is_empty() {
+            return Err("ELF file has no function symbols; was it stripped?".to_owned());
+        }
+        symbols.sort_by_key(|s| s.addr);
+        Ok(Symbols { symbols })
+    }
+
+    fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+        let index = match self.symbols.binary_search_by_key(&addr, |s| s.addr) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let symbol = &self.symbols[index];
+        let offset = addr - symbol.addr;
+        if offset < symbol.size.max(1) { Some((&symbol.name, offset)) } else { None }
+    }
+
+    fn describe(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((name, offset)) => format!("{:08x} {}+0x{:x}", addr, name, offset),
+            None => format!("{:08x} ??", addr),
+        }
+    }
+}
+
+/// Accepts either a raw dump, or text containing `CRD:<hex>` lines
+fn parse_dump(data: &[u8]) -> Result<CrashDump, String> {
+    if data.starts_with(&crash_dump::MAGIC) {
+        return CrashDump::decode(data).map_err(|e| format!("couldn't decode dump: {:?}", e));
+    }
+    let text = String::from_utf8_lossy(data);
+    let mut raw = Vec::new();
+    for line in text.lines() {
+        if let Some((_, hex)) = line.split_once("CRD:") {
+            let hex = hex.trim();
+            if hex.len() % 2 != 0 {
+                return Err(format!("odd number of hex digits in line: {}", line));
+            }
+            for i in (0..hex.len()).step_by(2) {
+                raw.push(u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("{}: {}", e, line))?);
+            }
+        }
+    }
+    CrashDump::decode(&raw).map_err(|e| format!("couldn't decode dump: {:?}", e))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = App::new("crash-decode")
+        .version(crate_version!())
+        .about("Turn a Xous crash dump into a backtrace")
+        .arg(Arg::with_name("dump").help("crash dump, raw or as printed by shellchat").required(true))
+        .arg(Arg::with_name("elf").help("unstripped ELF of the crashed process").required(true))
+        .arg(
+            Arg::with_name("stack-scan")
+                .long("stack-scan")
+                .help("number of stack words to scan for return addresses")
+                .takes_value(true)
+                .default_value("256"),
+        )
+        .get_matches();
+
+    let dump = parse_dump(&fs::read(matches.value_of("dump").unwrap())?)?;
+    let symbols = Symbols::load(&fs::read(matches.value_of("elf").unwrap())?)?;
+    let scan_words: usize = matches.value_of("stack-scan").unwrap().parse()?;
+
+    match dump.kind {
+        CrashKind::Panic => println!("{} (PID {}) panicked in TID {}", dump.name, dump.pid, dump.tid),
+        CrashKind::Exception => println!(
+            "{} (PID {}) took a {} in TID {} at {}, fault address {:08x}",
+            dump.name,
+            dump.pid,
+            EXCEPTION_NAMES.get(dump.code as usize).unwrap_or(&"unknown exception"),
+            dump.tid,
+            symbols.describe(dump.pc),
+            dump.address
+        ),
+    }
+    if !dump.message.is_empty() {
+        println!("  {}", dump.message);
+    }
+
+    // RISC-V code built without frame pointers can't be unwound reliably, so the backtrace is the faulting
+    // pc, the return address register, and then every stack word that points into a known function.
+    println!("\nBacktrace (stack scan, may contain stale frames):");
+    let mut frame = 0;
+    if let Some(thread) = dump.crashed_thread() {
+        println!("  #{:<2} pc {}", frame, symbols.describe(thread.pc()));
+        frame += 1;
+        println!("  #{:<2} ra {}", frame, symbols.describe(thread.ra()));
+        frame += 1;
+    }
+    for (addr, word) in dump.stack_words().take(scan_words) {
+        if symbols.lookup(word).is_some() {
+            println!("  #{:<2} [{:08x}] {}", frame, addr, symbols.describe(word));
+            frame += 1;
+        }
+    }
+
+    println!("\nThreads:");
+    for thread in dump.threads.iter() {
+        println!(
+            "  TID {:2}{} pc {} ra {} sp {:08x}",
+            thread.tid,
+            if thread.tid == dump.tid { "*" } else { " " },
+            symbols.describe(thread.pc()),
+            symbols.describe(thread.ra()),
+            thread.sp()
+        );
+    }
+
+    println!("\nMemory map:");
+    for region in dump.regions.iter() {
+        println!(
+            "  {:08x}-{:08x} {}{}{}",
+            region.start,
+            region.start.wrapping_add(region.len),
+            if region.flags & 0b0010 != 0 { "r" } else { "-" },
+            if region.flags & 0b0100 != 0 { "w" } else { "-" },
+            if region.flags & 0b1000 != 0 { "x" } else { "-" },
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! Checks keyboard layouts, and the layout a host uses to type what a Precursor sends over USB.
+//!
+//! `kbd-layout check <file>...` parses layout descriptions in the format of the `keyboard-layout` crate
+//! before they are copied into the PDDB, and warns about printable ASCII characters that no key types.
+//!
+//! `kbd-layout kbdtest` checks the output of the shellchat `usb kbdtest` command, typed into this tool
+//! by the device acting as a USB keyboard. With `--generate`, it prints the `char_to_hid_code_*` mapping
+//! for the host layout that produced the output, for `usb-device-xous/src/mappings.rs`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::process::exit;
+
+use clap::{App, Arg, SubCommand, crate_version};
+use keyboard_layout::Layout;
+
+/// The first and last characters sent by `usb kbdtest`
+const START_ASCII: char = ' ';
+const END_ASCII: char = '~';
+
+/// HID usages that type each printable ASCII character on a US101 host layout
+#[rustfmt::skip]
+const ASCII_TO_KEYCODE: [(char, &[&str]); 95] = [
+    (' ', &["Space"]),
+    ('!', &["Keyboard1", "LeftShift"]),
+    ('"', &["Apostrophe", "LeftShift"]),
+    ('#', &["Keyboard3", "LeftShift"]),
+    ('$', &["Keyboard4", "LeftShift"]),
+    ('%', &["Keyboard5", "LeftShift"]),
+    ('&', &["Keyboard7", "LeftShift"]),
+    ('\'', &["Apostrophe"]),
+    ('(', &["Keyboard9", "LeftShift"]),
+    (')', &["Keyboard0", "LeftShift"]),
+    ('*', &["Keyboard8", "LeftShift"]),
+    ('+', &["Equal", "LeftShift"]),
+    (',', &["Comma"]),
+    ('-', &["Minus"]),
+    ('.', &["Dot"]),
+    ('/', &["ForwardSlash"]),
+    ('0', &["Keyboard0"]),
+    ('1', &["Keyboard1"]),
+    ('2', &["Keyboard2"]),
+    ('3', &["Keyboard3"]),
+    ('4', &["Keyboard4"]),
+    ('5', &["Keyboard5"]),
+    ('6', &["Keyboard6"]),
+    ('7', &["Keyboard7"]),
+    ('8', &["Keyboard8"]),
+    ('9', &["Keyboard9"]),
+    (':', &["Semicolon", "LeftShift"]),
+    (';', &["Semicolon"]),
+    ('<', &["Comma", "LeftShift"]),
+    ('=', &["Equal"]),
+    ('>', &["Dot", "LeftShift"]),
+    ('?', &["ForwardSlash", "LeftShift"]),
+    ('@', &["Keyboard2", "LeftShift"]),
+    ('A', &["A", "LeftShift"]),
+    ('B', &["B", "LeftShift"]),
+    ('C', &["C", "LeftShift"]),
+    ('D', &["D", "LeftShift"]),
+    ('E', &["E", "LeftShift"]),
+    ('F', &["F", "LeftShift"]),
+    ('G', &["G", "LeftShift"]),
+    ('H', &["H", "LeftShift"]),
+    ('I', &["I", "LeftShift"]),
+    ('J', &["J", "LeftShift"]),
+    ('K', &["K", "LeftShift"]),
+    ('L', &["L", "LeftShift"]),
+    ('M', &["M", "LeftShift"]),
+    ('N', &["N", "LeftShift"]),
+    ('O', &["O", "LeftShift"]),
+    ('P', &["P", "LeftShift"]),
+    ('Q', &["Q", "LeftShift"]),
+    ('R', &["R", "LeftShift"]),
+    ('S', &["S", "LeftShift"]),
+    ('T', &["T", "LeftShift"]),
+    ('U', &["U", "LeftShift"]),
+    ('V', &["V", "LeftShift"]),
+    ('W', &["W", "LeftShift"]),
+    ('X', &["X", "LeftShift"]),
+    ('Y', &["Y", "LeftShift"]),
+    ('Z', &["Z", "LeftShift"]),
+    ('[', &["LeftBrace"]),
+    ('\\', &["Backslash"]),
+    (']', &["RightBrace"]),
+    ('^', &["Keyboard6", "LeftShift"]),
+    ('_', &["Minus", "LeftShift"]),
+    ('`', &["Grave"]),
+    ('a', &["A"]),
+    ('b', &["B"]),
+    ('c', &["C"]),
+    ('d', &["D"]),
+    ('e', &["E"]),
+    ('f', &["F"]),
+    ('g', &["G"]),
+    ('h', &["H"]),
+    ('i', &["I"]),
+    ('j', &["J"]),
+    ('k', &["K"]),
+    ('l', &["L"]),
+    ('m', &["M"]),
+    ('n', &["N"]),
+    ('o', &["O"]),
+    ('p', &["P"]),
+    ('q', &["Q"]),
+    ('r', &["R"]),
+    ('s', &["S"]),
+    ('t', &["T"]),
+    ('u', &["U"]),
+    ('v', &["V"]),
+    ('w', &["W"]),
+    ('x', &["X"]),
+    ('y', &["Y"]),
+    ('z', &["Z"]),
+    ('{', &["LeftBrace", "LeftShift"]),
+    ('|', &["Backslash", "LeftShift"]),
+    ('}', &["RightBrace", "LeftShift"]),
+    ('~', &["Grave", "LeftShift"]),
+];
+
+fn check(files: Vec<&str>) -> bool {
+    let mut passing = true;
+    for file in files {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                println!("{}: {}", file, e);
+                passing = false;
+                continue;
+            }
+        };
+        match Layout::parse(&text) {
+            Ok(layout) => {
+                let warnings = layout.check();
+                for warning in warnings.iter() {
+                    println!("{}: warning: {}", file, warning);
+                }
+                println!("{}: layout {} parsed with {} warning(s)", file, layout.name, warnings.len());
+            }
+            Err(e) => {
+                println!("{}: {}", file, e);
+                passing = false;
+            }
+        }
+    }
+    passing
+}
+
+fn kbdtest(generate: bool) -> bool {
+    print!("Run `usb kbdtest` into the following prompt: ");
+    io::stdout().flush().ok();
+    let mut testval = String::new();
+    io::stdin().read_line(&mut testval).expect("couldn't read the test string");
+    let received: Vec<char> = testval.trim_end_matches(['\r', '\n']).chars().collect();
+    if received.len() != ASCII_TO_KEYCODE.len() {
+        println!(
+            "Check string length is incorrect. Expected {} chars, got {}",
+            ASCII_TO_KEYCODE.len(),
+            received.len()
+        );
+        return false;
+    }
+
+    if generate {
+        // the host typed `received[i]` for the usage that types `ASCII_TO_KEYCODE[i]` on US101, so that is
+        // the usage to send for `received[i]`
+        let mut codes: Vec<(char, &[&str])> =
+            received.iter().zip(ASCII_TO_KEYCODE.iter()).map(|(&c, &(_, codes))| (c, codes)).collect();
+        codes.sort_by_key(|&(c, _)| c);
+        println!("    pub fn char_to_hid_code_CUSTOM(key: char) -> Vec<UsbKeyCode> {{");
+        println!("        let mut code = vec![];");
+        println!("        match key {{");
+        for (c, codes) in codes {
+            print!("            {:?} => {{", c);
+            for code in codes {
+                print!("code.push(UsbKeyCode::{}); ", code);
+            }
+            println!("}},");
+        }
+        println!("            '\\u{{000d}}' => {{}}, // ignore CR");
+        println!(
+            "            '\\u{{000a}}' => code.push(UsbKeyCode::ReturnEnter), // turn LF ('\\n') into enter"
+        );
+        println!("            '\\u{{0008}}' => code.push(UsbKeyCode::DeleteBackspace),");
+        println!("            _ => log::warn!(\"Ignoring unhandled character: {{}}\", key),");
+        println!("        }};");
+        println!("        code");
+        println!("    }}");
+        true
+    } else {
+        let mut passing = true;
+        for (expected, &got) in (START_ASCII..=END_ASCII).zip(received.iter()) {
+            if expected != got {
+                println!("Expected {} but got 0x{:x}", expected, got as u32);
+                passing = false;
+            }
+        }
+        if passing {
+            println!("Test passed");
+        } else {
+            println!("Test did not pass");
+        }
+        passing
+    }
+}
+
+fn main() {
+    let matches = App::new("kbd-layout")
+        .version(crate_version!())
+        .about("Check Precursor keyboard layouts")
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check layout descriptions before they are copied to a device")
+                .arg(Arg::with_name("files").help("layout description").required(true).multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("kbdtest")
+                .about("Check the output of `usb kbdtest` typed into this tool")
+                .arg(
+                    Arg::with_name("generate")
+                        .long("generate")
+                        .help("print the USB HID mapping for the host layout instead"),
+                ),
+        )
+        .get_matches();
+
+    let passing = match matches.subcommand() {
+        ("check", Some(args)) => check(args.values_of("files").unwrap().collect()),
+        ("kbdtest", Some(args)) => kbdtest(args.is_present("generate")),
+        _ => {
+            println!("{}", matches.usage());
+            false
+        }
+    };
+    exit(if passing { 0 } else { 1 })
+}
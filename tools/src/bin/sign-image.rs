@@ -70,6 +70,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("v0.9.8-790")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("security-version")
+                .long("security-version")
+                .help("Anti-rollback security version to embed in the kernel image. Devices that have advanced their anti-rollback counter past this version refuse to boot it.")
+                .value_name("security version")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("defile")
                 .help("patch the resulting image, to create a test file to catch signature failure"),
@@ -91,6 +98,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             None
         };
 
+    let security_version = if let Some(sv_str) = matches.value_of("security-version") {
+        Some(
+            sv_str
+                .parse::<u32>()
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "Security version must be a number"))?,
+        )
+    } else {
+        None
+    };
+
     // Sign the loader, if an output file was specified
     if let Some(loader_output) = matches.value_of("loader-output") {
         let loader_key = matches.value_of("loader-key").expect("no loader key specified");
@@ -108,6 +125,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             &loader_pkey,
             matches.is_present("defile"),
             &minver,
+            None,
             false,
             matches.is_present("with-jump"),
         )?;
@@ -129,6 +147,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             &kernel_pkey,
             matches.is_present("defile"),
             &minver,
+            security_version,
             true,
             matches.is_present("with-jump"),
         )?;
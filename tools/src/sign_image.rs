@@ -9,6 +9,8 @@ use sha2::{Digest, Sha512};
 
 const LOADER_VERSION: u32 = 1;
 const LOADER_PREHASH_VERSION: u32 = 2;
+/// Pre-hash signature, with a security version for anti-rollback prepended to the version trailer
+const LOADER_PREHASH_SECVER_VERSION: u32 = 3;
 const RV_SKIP_I: u32 = 0x0000106f; // jal x0, 4096
 
 use xous_semver::SemVer;
@@ -99,16 +101,22 @@ pub fn sign_image(
     Ok(dest_file)
 }
 
+/// `security_version` is the anti-rollback version of the image. If specified, the loader refuses to boot
+/// the image if it is lower than the anti-rollback counter of the device. Images signed without a security
+/// version keep the v2 record format, so they still boot on loaders that predate anti-rollback.
 pub fn sign_image_prehash(
     source: &[u8],
     private_key: &pem::Pem,
     defile: bool,
     minver: &Option<SemVer>,
     semver: Option<[u8; 16]>,
+    security_version: Option<u32>,
     with_jump: bool,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let mut source = source.to_owned();
     let mut dest_file = vec![];
+    let sig_version =
+        if security_version.is_some() { LOADER_PREHASH_SECVER_VERSION } else { LOADER_PREHASH_VERSION };
 
     // Append version information to the binary. Appending it here means it is part
     // of the signed bundle.
@@ -128,9 +136,15 @@ pub fn sign_image_prehash(
     // the appended metadata is in the font region, so, this data has to be shuttled back.
     // The graphics server is also entirely naive to how much cryptographic data is in the font
     // region, and I think it's probably better for it to stay that way.
+    //
+    // The security version goes *in front of* the semvers, so that the semvers, version and length stay at
+    // the same offsets from the end of the image in both record formats.
+    if let Some(sv) = security_version {
+        source.extend_from_slice(&sv.to_le_bytes());
+    }
     source.append(&mut minver_bytes.to_vec());
     source.append(&mut semver.to_vec());
-    for &b in LOADER_PREHASH_VERSION.to_le_bytes().iter() {
+    for &b in sig_version.to_le_bytes().iter() {
         source.push(b);
     }
     for &b in (source.len() as u32).to_le_bytes().iter() {
@@ -158,7 +172,7 @@ pub fn sign_image_prehash(
         0
     };
 
-    dest_file.write_all(&LOADER_PREHASH_VERSION.to_le_bytes())?;
+    dest_file.write_all(&sig_version.to_le_bytes())?;
     dest_file.write_all(&(source.len() as u32).to_le_bytes())?;
 
     // Write the signature data
@@ -190,6 +204,7 @@ pub fn sign_file<S, T>(
     private_key: &pem::Pem,
     defile: bool,
     minver: &Option<SemVer>,
+    security_version: Option<u32>,
     use_prehash: bool,
     with_jump: bool,
 ) -> Result<(), Box<dyn std::error::Error>>
@@ -203,8 +218,11 @@ where
     source_file.read_to_end(&mut source)?;
 
     let result = if use_prehash {
-        sign_image_prehash(&source, private_key, defile, minver, None, with_jump)?
+        sign_image_prehash(&source, private_key, defile, minver, None, security_version, with_jump)?
     } else {
+        if security_version.is_some() {
+            return Err("a security version can only be embedded in pre-hash signed images".into());
+        }
         sign_image(&source, private_key, defile, minver, None, with_jump)?
     };
     dest_file.write_all(&result)?;
@@ -1,8 +1,7 @@
 use core::convert::{TryFrom, TryInto};
 
-/* https://github.com/betrusted-io/xous-core/issues/90
-use crate::Exception
-*/
+#[cfg(feature = "crash-dump")]
+use crate::Exception;
 // use num_derive::FromPrimitive;
 // use num_traits::FromPrimitive;
 #[cfg(feature = "processes-as-threads")]
@@ -561,6 +560,41 @@ pub enum SysCall {
     #[cfg(feature = "raw-trng")]
     RawTrng(usize, usize, usize, usize, usize, usize, usize),
 
+    /// Retrieve the saved register context of a thread in the current process. The
+    /// context is returned five registers at a time, starting at `bank * 5`. Register
+    /// indices follow the architecture's numbering, with the program counter appended
+    /// after the last general purpose register.
+    ///
+    /// This is intended for crash dumps, where it is called from within an exception
+    /// handler or panic hook. Other threads keep running unless the caller has stopped
+    /// them, so the context of a running thread is only as recent as its last switch
+    /// out of the CPU.
+    ///
+    /// ## Arguments
+    ///   * **tid**: The thread to inspect
+    ///   * **bank**: Which group of five registers to return
+    ///
+    /// ## Returns
+    /// Returns a Scalar5 containing up to five registers. Registers past the end of the
+    /// context are returned as 0.
+    ///
+    /// ## Errors
+    ///   * **ThreadNotAvailable**: The thread does not exist
+    ///   * **InvalidLimit**: The bank is past the end of the register context
+    #[cfg(feature = "crash-dump")]
+    GetThreadContext(TID, usize /* bank */),
+
+    /// Find the first mapped region of the current process' address space at or above
+    /// the given address. A region is a run of contiguous pages with identical flags.
+    ///
+    /// ## Returns
+    /// Returns a Scalar5 of (start, length, flags, 0, 0)
+    ///
+    /// ## Errors
+    ///   * **BadAddress**: There are no more mapped regions above the given address
+    #[cfg(feature = "crash-dump")]
+    GetMemoryRegion(usize /* start address */),
+
     /// This syscall does not exist. It captures all possible
     /// arguments so detailed analysis can be performed.
     Invalid(usize, usize, usize, usize, usize, usize, usize),
@@ -618,6 +652,10 @@ pub enum SysCallNumber {
     SwapOp = 44,
     #[cfg(feature = "raw-trng")]
     RawTrng = 45,
+    #[cfg(feature = "crash-dump")]
+    GetThreadContext = 46,
+    #[cfg(feature = "crash-dump")]
+    GetMemoryRegion = 47,
 }
 
 impl SysCallNumber {
@@ -673,6 +711,10 @@ impl SysCallNumber {
             44 => SwapOp,
             #[cfg(feature = "raw-trng")]
             45 => RawTrng,
+            #[cfg(feature = "crash-dump")]
+            46 => GetThreadContext,
+            #[cfg(feature = "crash-dump")]
+            47 => GetMemoryRegion,
             _ => Invalid,
         }
     }
@@ -926,6 +968,14 @@ impl SysCall {
             SysCall::RawTrng(a1, a2, a3, a4, a5, a6, a7) => {
                 [SysCallNumber::RawTrng as usize, *a1, *a2, *a3, *a4, *a5, *a6, *a7]
             }
+            #[cfg(feature = "crash-dump")]
+            SysCall::GetThreadContext(tid, bank) => {
+                [SysCallNumber::GetThreadContext as usize, *tid, *bank, 0, 0, 0, 0, 0]
+            }
+            #[cfg(feature = "crash-dump")]
+            SysCall::GetMemoryRegion(addr) => {
+                [SysCallNumber::GetMemoryRegion as usize, *addr, 0, 0, 0, 0, 0, 0]
+            }
             SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7) => {
                 [SysCallNumber::Invalid as usize, *a1, *a2, *a3, *a4, *a5, *a6, *a7]
             }
@@ -1080,6 +1130,10 @@ impl SysCall {
             SysCallNumber::SwapOp => SysCall::SwapOp(a1, a2, a3, a4, a5, a6, a7),
             #[cfg(feature = "raw-trng")]
             SysCallNumber::RawTrng => SysCall::RawTrng(a1, a2, a3, a4, a5, a6, a7),
+            #[cfg(feature = "crash-dump")]
+            SysCallNumber::GetThreadContext => SysCall::GetThreadContext(a1 as _, a2),
+            #[cfg(feature = "crash-dump")]
+            SysCallNumber::GetMemoryRegion => SysCall::GetMemoryRegion(a1),
             SysCallNumber::Invalid => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
         })
     }
@@ -1862,27 +1916,33 @@ pub fn reply_and_receive_next_legacy(
     }
 }
 
-/* https://github.com/betrusted-io/xous-core/issues/90
+#[cfg(feature = "crash-dump")]
 static EXCEPTION_HANDLER: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+#[cfg(feature = "crash-dump")]
 fn handle_exception(exception_type: usize, arg1: usize, arg2: usize) -> isize {
-    let exception = crate::exceptions::Exception::new(exception_type, arg1, arg2);
+    let exception = Exception::new(exception_type, arg1, arg2);
     let f = EXCEPTION_HANDLER.load(core::sync::atomic::Ordering::SeqCst);
     let f = unsafe { core::mem::transmute::<usize, fn(Exception) -> isize>(f) };
     f(exception)
 }
+
 /// Sets the given function as this process' Exception handler. This function
 /// will be called whenever an Exception occurs such as a memory fault,
 /// illegal instruction, or a child process terminating.
-pub fn set_exception_handler(
-    handler: fn(crate::Exception) -> isize,
-) -> core::result::Result<(), Error> {
+///
+/// The value returned by the handler is added to the program counter of the
+/// faulting thread when it resumes. Handlers that do not recover from the fault
+/// should terminate the process instead of returning.
+#[cfg(feature = "crash-dump")]
+pub fn set_exception_handler(handler: fn(Exception) -> isize) -> core::result::Result<(), Error> {
     let flags = crate::MemoryFlags::R | crate::MemoryFlags::W | crate::MemoryFlags::RESERVE;
 
     let stack = crate::map_memory(None, None, 131_072, flags)?;
     EXCEPTION_HANDLER.store(handler as usize, core::sync::atomic::Ordering::SeqCst);
+    // stacks grow down, so hand the kernel the top of the region
     rsyscall(SysCall::SetExceptionHandler(
-        handle_exception as usize,
-        stack.as_ptr() as usize,
+        handle_exception as *const () as usize,
+        stack.as_ptr() as usize + stack.len(),
     ))
     .and_then(|result| {
         if let Result::Ok = result {
@@ -1894,7 +1954,42 @@ pub fn set_exception_handler(
         }
     })
 }
-*/
+
+/// Number of registers returned by `thread_context()`: the general purpose registers (minus `zero` on
+/// RISC-V), followed by the program counter.
+#[cfg(feature = "crash-dump")]
+pub const THREAD_CONTEXT_REGS: usize = 32;
+
+/// Retrieve the saved registers of a thread in the current process. See `SysCall::GetThreadContext`.
+#[cfg(feature = "crash-dump")]
+pub fn thread_context(tid: TID) -> core::result::Result<[usize; THREAD_CONTEXT_REGS], Error> {
+    let mut regs = [0usize; THREAD_CONTEXT_REGS];
+    for (bank, chunk) in regs.chunks_mut(5).enumerate() {
+        match rsyscall(SysCall::GetThreadContext(tid, bank))? {
+            Result::Scalar5(a, b, c, d, e) => {
+                for (dst, src) in chunk.iter_mut().zip([a, b, c, d, e].iter()) {
+                    *dst = *src;
+                }
+            }
+            _ => return Err(Error::InternalError),
+        }
+    }
+    Ok(regs)
+}
+
+/// Find the first mapped region at or above `addr` in the current process. Returns `None` once the end of
+/// the address space is reached.
+#[cfg(feature = "crash-dump")]
+pub fn next_memory_region(addr: usize) -> core::result::Result<Option<(usize, usize, MemoryFlags)>, Error> {
+    match rsyscall(SysCall::GetMemoryRegion(addr)) {
+        Ok(Result::Scalar5(start, len, flags, _, _)) => {
+            Ok(Some((start, len, MemoryFlags::from_bits(flags).unwrap_or(MemoryFlags::empty()))))
+        }
+        Err(Error::BadAddress) => Ok(None),
+        Err(e) => Err(e),
+        _ => Err(Error::InternalError),
+    }
+}
 
 /// Translate a virtual address to a physical address
 #[cfg(feature = "v2p")]
@@ -15,19 +15,24 @@ struct AppManifest {
     context_name: String,
     menu_name: HashMap<String, HashMap<String, String>>,
     submenu: Option<u8>,
+    /// Names of the servers the app may connect to. Apps that don't specify this are unrestricted.
+    capabilities: Option<Vec<String>>,
 }
 #[derive(Deserialize, Serialize, Debug)]
 struct Locales {
     locales: HashMap<String, HashMap<String, String>>,
 }
 
-pub(crate) fn generate_app_menus(apps: &Vec<String>) {
+fn load_manifest() -> HashMap<String, AppManifest> {
     let file = File::open("apps/manifest.json").expect("Failed to open the manifest file");
     let mut reader = std::io::BufReader::new(file);
     let mut content = String::new();
     reader.read_to_string(&mut content).expect("Failed to read the file");
-    let manifest: HashMap<String, AppManifest> =
-        serde_json::from_str(&content).expect("Cannot parse manifest file");
+    serde_json::from_str(&content).expect("Cannot parse manifest file")
+}
+
+pub(crate) fn generate_app_menus(apps: &Vec<String>) {
+    let manifest = load_manifest();
 
     // localization file
     // inject all the localization strings into the i18n file, which in theory reduces the churn on other
@@ -155,7 +160,7 @@ pub(crate) fn app_index_to_name(index: usize) -> Result<&'static str, AppDispatc
     .unwrap();
     for (index, (_, _manifest)) in working_set.iter().enumerate() {
         for name in _manifest.menu_name.keys() {
-            writeln!(menu, "        {} => Ok(t!(\"{}\", locales::LANG)),", index, name,).unwrap();
+            writeln!(menu, "        {} => Ok(t!(\"{}\", locales::lang())),", index, name,).unwrap();
         }
     }
     writeln!(
@@ -172,7 +177,7 @@ pub(crate) fn app_menu_items(menu_items: &mut Vec::<MenuItem>, status_conn: u32)
         writeln!(menu, "    menu_items.push(MenuItem {{",).unwrap();
         assert_eq!(manifest.menu_name.len(), 1, "Improper menu name record entry");
         for name in manifest.menu_name.keys() {
-            writeln!(menu, "        name: String::from(t!(\"{}\", locales::LANG)),", name).unwrap();
+            writeln!(menu, "        name: String::from(t!(\"{}\", locales::lang())),", name).unwrap();
         }
         writeln!(menu, "        action_conn: Some(status_conn),",).unwrap();
         writeln!(menu, "        action_opcode: StatusOpcode::SwitchToApp.to_u32().unwrap(),",).unwrap();
@@ -185,6 +190,59 @@ pub(crate) fn app_menu_items(menu_items: &mut Vec::<MenuItem>, status_conn: u32)
     overwrite_if_changed(&menu, "services/cram-console/src/app_autogen.rs");
 }
 
+/// Bake the `capabilities` records of the app manifest into xous-names. `pids` maps the name of every
+/// app in the image to the PID it will be assigned at boot; apps that aren't in the image are still
+/// emitted so that xous-names can apply their policy if they are later started by the app loader.
+/// `last_boot_pid` is the highest PID in the image, or `None` when there is no image, as for the dummy
+/// template; processes above it were started at runtime.
+pub(crate) fn generate_app_capabilities(pids: &BTreeMap<String, u32>, last_boot_pid: Option<u32>) {
+    let manifest = load_manifest();
+    let mut working_set = BTreeMap::<&String, &Vec<String>>::new();
+    for (app, manifest) in manifest.iter() {
+        if let Some(capabilities) = &manifest.capabilities {
+            working_set.insert(app, capabilities);
+        }
+    }
+    for app in pids.keys() {
+        if manifest.get(app).map(|m| m.capabilities.is_none()).unwrap_or(false) {
+            println!("Note: app '{}' declares no capabilities and may connect to any server", app);
+        }
+    }
+
+    let mut caps = String::new();
+    writeln!(caps, "#![cfg_attr(rustfmt, rustfmt_skip)]").unwrap();
+    writeln!(caps, "// This file is auto-generated by xtask/main.rs generate_app_capabilities()").unwrap();
+    writeln!(
+        caps,
+        "\n/// (app name, PID assigned at boot if the app is in the image, servers the app may connect to)"
+    )
+    .unwrap();
+    writeln!(caps, "pub const APP_CAPABILITIES: &[(&str, Option<u32>, &[&str])] = &[").unwrap();
+    for (app_name, servers) in working_set.iter() {
+        let pid = match pids.get(*app_name) {
+            Some(pid) => format!("Some({})", pid),
+            None => "None".to_string(),
+        };
+        let servers: Vec<String> = servers.iter().map(|s| format!("{:?}", s)).collect();
+        writeln!(caps, "    ({:?}, {}, &[{}]),", app_name, pid, servers.join(", ")).unwrap();
+    }
+    writeln!(caps, "];").unwrap();
+    let loader_pid = match pids.get("app-loader") {
+        Some(pid) => format!("Some({})", pid),
+        None => "None".to_string(),
+    };
+    writeln!(caps, "\n/// The only process that may register runtime-loaded apps").unwrap();
+    writeln!(caps, "pub const APP_LOADER_PID: Option<u32> = {};", loader_pid).unwrap();
+    writeln!(
+        caps,
+        "\n/// The highest PID assigned at boot; processes above it were started at runtime, and may not connect\n\
+         /// to anything until the app loader has registered them"
+    )
+    .unwrap();
+    writeln!(caps, "pub const LAST_BOOT_PID: Option<u32> = {:?};", last_boot_pid).unwrap();
+    overwrite_if_changed(&caps, "services/xous-names/src/app_caps.rs");
+}
+
 fn overwrite_if_changed(new_string: &String, old_file: &str) {
     let original = match OpenOptions::new().read(true).open(old_file) {
         Ok(mut ref_file) => {
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::{
     env,
@@ -6,7 +7,10 @@ use std::{
     process::Command,
 };
 
-use crate::{DynError, app_manifest::generate_app_menus};
+use crate::{
+    DynError,
+    app_manifest::{generate_app_capabilities, generate_app_menus},
+};
 
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
@@ -133,6 +137,8 @@ pub(crate) struct Builder {
     global_flags: Vec<String>,
     stream: BuildStream,
     min_ver: String,
+    /// anti-rollback security version to embed in the kernel image
+    security_version: Option<u32>,
     target: Option<String>,
     /// The kernel might require a different target than the rest of the programs.
     target_kernel: Option<String>,
@@ -166,6 +172,7 @@ impl Builder {
             global_flags: Vec::new(),
             stream: BuildStream::Release,
             min_ver: crate::MIN_XOUS_VERSION.to_string(),
+            security_version: None,
             target: Some(crate::TARGET_TRIPLE_RISCV32.to_string()),
             target_kernel: Some(crate::TARGET_TRIPLE_RISCV32_KERNEL.to_string()),
             utra_target: format!("precursor-{}", crate::PRECURSOR_SOC_VERSION).to_string(),
@@ -195,6 +202,13 @@ impl Builder {
         self
     }
 
+    /// Embed an anti-rollback security version in the kernel image. Once a device advances its
+    /// anti-rollback counter to this version, it will no longer boot images with a lower version.
+    pub fn set_security_version(&mut self, version: u32) -> &mut Builder {
+        self.security_version = Some(version);
+        self
+    }
+
     pub fn set_swap<'a>(&'a mut self, offset: u32, size: u32) -> &'a mut Builder {
         self.swap = Some((offset, size));
         self
@@ -655,6 +669,74 @@ impl Builder {
         (inie, inif, inis)
     }
 
+    /// Predict the PID that each app will be assigned at boot, and the last PID assigned at boot. The
+    /// kernel is PID 1, and every other process is numbered from 2 in the order that it is handed to
+    /// `create-image` (or to the hosted-mode kernel), which is the order that `build()` assembles its
+    /// artifacts in. The predicted load order is returned as well, so that `build()` can check it
+    /// against the artifacts it actually loads with `check_load_order()`.
+    fn predicted_app_pids(&self) -> (BTreeMap<String, u32>, u32, Vec<String>) {
+        let packages = [&self.services[..], &self.apps[..]].concat();
+        let mut processes = Vec::<String>::new();
+        for pkg in packages.iter() {
+            if let CrateSpec::Local(name, _region) = pkg {
+                processes.push(name.to_string());
+            }
+        }
+        for pkg in packages.iter() {
+            if let CrateSpec::CratesIo(name, _version, _region) = pkg {
+                processes.push(name.to_string());
+            }
+        }
+        if self.target.is_some() {
+            for pkg in packages.iter() {
+                if let CrateSpec::Prebuilt(name, _url, _region) = pkg {
+                    processes.push(name.to_string());
+                }
+            }
+        }
+        for pkg in packages.iter() {
+            if let CrateSpec::BinaryFile(_name, path, _region) = pkg {
+                processes.push(path.to_string());
+            }
+        }
+        if self.target.is_some() {
+            let (inie, inif, inis) = self.split_region(processes);
+            processes = [inie, inif, inis].concat();
+        }
+
+        let mut pids = BTreeMap::<String, u32>::new();
+        for app in self.apps.iter() {
+            let (name, process) = match app {
+                CrateSpec::Local(name, _)
+                | CrateSpec::CratesIo(name, _, _)
+                | CrateSpec::Prebuilt(name, _, _) => (name, name),
+                CrateSpec::BinaryFile(Some(name), path, _) => (name, path),
+                _ => continue,
+            };
+            if let Some(index) = processes.iter().position(|p| p == process) {
+                pids.insert(name.to_string(), index as u32 + 2);
+            }
+        }
+        (pids, processes.len() as u32 + 1, processes)
+    }
+
+    /// The PIDs in xous-names' `app_caps.rs` are generated before anything is built, from the load
+    /// order predicted by `predicted_app_pids()`. Refuse to produce an image if the processes that are
+    /// actually loaded come out in a different order, as the apps would boot with the wrong policies.
+    fn check_load_order(predicted: &[String], loaded: &[String]) -> Result<(), DynError> {
+        let stem = |p: &String| Path::new(p).file_stem().map(|f| f.to_os_string());
+        if predicted.len() != loaded.len()
+            || predicted.iter().zip(loaded.iter()).any(|(p, l)| stem(p) != stem(l))
+        {
+            return Err(format!(
+                "Load order {:?} does not match the order {:?} that the app PIDs in xous-names were generated from",
+                loaded, predicted
+            )
+            .into());
+        }
+        Ok(())
+    }
+
     /// Consume the builder and execute the configured build task. This handles dispatching all
     /// configurations, including renode, hosted, and hardware targets.
     pub fn build(mut self) -> Result<(), DynError> {
@@ -718,6 +800,8 @@ impl Builder {
             }
         }
         generate_app_menus(&app_names);
+        let (app_pids, last_boot_pid, predicted_order) = self.predicted_app_pids();
+        generate_app_capabilities(&app_pids, Some(last_boot_pid));
         let mut services_path = self.builder(
             &[&self.services[..], &self.apps[..]].concat(),
             &self.features,
@@ -757,6 +841,10 @@ impl Builder {
             }
             // jam in any pre-built local binary files that were specified
             let binary_files_string = self.enumerate_binary_files()?;
+            Self::check_load_order(
+                &predicted_order,
+                &[&services_path[..], &binary_files_string[..]].concat(),
+            )?;
             let mut canonicalized_paths = Vec::new();
             let mut binary_files_storage = Vec::<String>::new();
             for f in binary_files_string {
@@ -886,6 +974,7 @@ impl Builder {
 
             // --------- package up and sign a binary image ----------
             let (inie, inif, inis) = self.split_region(services_path.clone());
+            Self::check_load_order(&predicted_order, &[&inie[..], &inif[..], &inis[..]].concat())?;
             let output_bundle = self.create_image(&kernel_path[0], &inie, &inif, &inis, svd_paths)?;
             println!();
             println!("Kernel+Init bundle is available at {}", output_bundle.display());
@@ -960,6 +1049,7 @@ impl Builder {
             let mut xous_img_path = output_bundle.parent().unwrap().to_owned();
             xous_img_path.push("xous.img");
 
+            let security_version = self.security_version.map(|v| v.to_string());
             let status = Command::new(cargo())
                 .current_dir(project_root())
                 .args([
@@ -979,6 +1069,7 @@ impl Builder {
                     &self.min_ver,
                     // "--defile",
                 ])
+                .args(security_version.iter().flat_map(|v| ["--security-version", v.as_str()]))
                 .status()?;
             if !status.success() {
                 return Err("kernel image sign failed".into());
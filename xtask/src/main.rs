@@ -1,6 +1,6 @@
 mod app_manifest;
 mod versioning;
-use app_manifest::generate_app_menus;
+use app_manifest::{generate_app_capabilities, generate_app_menus};
 use versioning::*;
 mod utils;
 use utils::*;
@@ -90,6 +90,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "ime-plugin-shell",
         "codec",
         "modals",
+        "clipboard",
         // security
         "root-keys",
         "trng",
@@ -154,6 +155,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         builder.set_swap(offset, size);
     }
 
+    let security_version = get_flag("--security-version")?;
+    if !security_version.is_empty() {
+        builder.set_security_version(
+            security_version[0].parse().map_err(|_| "Error: --security-version should be a number")?,
+        );
+    }
+
     let extra_apps = get_flag("--app")?;
     builder.add_apps(&extra_apps);
     let extra_services = get_flag("--service")?;
@@ -313,6 +321,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             builder.add_loader_feature("swap");
             builder.add_kernel_feature("swap");
             builder.add_feature("swap");
+            // has the graphics server ask to stay resident; the hint only has an effect with the
+            // `xous-swapper/swap-policy-working-set` policy
+            builder.add_feature("graphics-server/swap");
             builder.add_kernel_feature("debug-swap");
             // builder.add_kernel_feature("debug-swap-verbose");
             // builder.add_kernel_feature("debug-print");
@@ -648,7 +659,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // ---- other single-purpose commands ----
         Some("generate-locales") => generate_locales()?,
         Some("wycheproof-import") => wycheproof_import()?,
-        Some("dummy-template") => generate_app_menus(&Vec::new()),
+        Some("dummy-template") => {
+            generate_app_menus(&Vec::new());
+            generate_app_capabilities(&Default::default(), None);
+        }
         _ => print_help(),
     }
     builder.build()?;
@@ -742,6 +756,7 @@ fn print_help() {
     [--feature [feature name]]
     [--lkey [loader key]] [--kkey [kernel key]]
     [--swap [offset:size]]
+    [--security-version [version]]
     [--app [cratespec]]
     [--service [cratespec]]
     [--no-timestamp]
@@ -774,12 +789,16 @@ be merged in with explicit app/service treatment with the following flags:
 [--debug-loader]         Enable debug printing in the loader
 [--offline]              Avoid network traffic
 [--swap offset:size]     Specify a region for swap memory. The behavior of this depends on the target.
+[--security-version N]   Embed anti-rollback security version N in the kernel image. Devices whose anti-rollback
+                         counter has been advanced past N refuse to boot the image.
 [--change-target]        Used to clean the cached target/*/*/build/SVD_PATH when changing build targets.
                          This will also force a full rebuild every time the flag is specified.
 
 - An 'app' must be enumerated in apps/manifest.json.
    A pre-processor configures the launch menu based on the list of specified apps.
 - A 'service' is merged into the device image without any pre-processing.
+   Optional services that no target includes, such as `openpgp-card`, are added this way:
+   `cargo xtask app-image --service openpgp-card`
 
 [verb] options:
 Hardware images:
@@ -815,7 +834,7 @@ Renode emulation:
  renode-aes-test         Renode image for AES emulation development. Extremely minimal.
 
 Other commands:
- generate-locales        (re)generate the locales include for the language selected in locales/src/locale.rs
+ generate-locales        (re)generate the locales include with the strings of every language
  wycheproof-import       generate binary test vectors for engine-25519 from whycheproof-import/x25519.json
  install-toolkit         installs Xous toolkit with no prompt, useful in CI. Specify `--force` to remove existing toolchains
  compile-apps            Just compiles the apps specified in [cratespecs], for example in order to use app server